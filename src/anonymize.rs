@@ -0,0 +1,250 @@
+// ================================================================================================
+// SCRIPT ANONYMIZATION
+// ================================================================================================
+//
+// Turns a personal script into one that can be attached to a bug report: addresses, folder
+// names, free text and comments are replaced with placeholder tokens, while everything that
+// shapes how the script parses and validates stays as it is. That covers commands, tags,
+// numbers, capabilities, header names, comparators, variable references and wildcards. Equal
+// values map to equal placeholders, so a repeated address or folder is still recognizably the
+// same one.
+
+use crate::parser::{self, Argument, Script, StringLiteral};
+use crate::refactor;
+use crate::sieve::TAGS_WITH_VALUE;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::Range;
+
+/// Tags whose value is structural, such as `:comparator "i;ascii-numeric"` or `:zone "+0100"`
+const KEPT_TAG_VALUES: &[&str] = &[
+    ":comparator", ":value", ":count", ":index", ":param", ":zone", ":importance",
+];
+
+/// Other tags taking a value, which may hold personal content
+const ANONYMIZED_TAG_VALUES: &[&str] = &[
+    ":days", ":seconds", ":subject", ":from", ":addresses", ":handle", ":flags", ":options",
+    ":message", ":method", ":id", ":fcc",
+];
+
+lazy_static! {
+    static ref EMAIL: Regex =
+        Regex::new(r"^[^\s@<>()\[\],;:*?$]+@[^\s@<>()\[\],;:*?$]+$").unwrap();
+    static ref SYSTEM_FLAG: Regex = Regex::new(r"^\\[A-Za-z]+$").unwrap();
+}
+
+/// How the values of a string argument are replaced
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// Structural: left untouched
+    Kept,
+    /// A mailbox path; each hierarchy level becomes a folder token
+    Mailbox,
+    /// Anything else: addresses become user tokens, words become word tokens
+    Text,
+}
+
+/// Number of leading positional arguments that are structural for a command or test
+fn kept_positionals(name: &str) -> usize {
+    match name {
+        "require" | "global" | "include" => usize::MAX,
+        "header" | "exists" | "address" | "envelope" | "environment" | "currentdate" | "set" => 1,
+        "date" => 2,
+        _ => 0,
+    }
+}
+
+/// Placeholder tokens handed out so far, so equal values get equal tokens
+#[derive(Default)]
+struct Tokens {
+    users: HashMap<String, String>,
+    folders: HashMap<String, String>,
+    words: HashMap<String, String>,
+}
+
+impl Tokens {
+    fn user(&mut self, address: &str) -> String {
+        let next = self.users.len() + 1;
+        self.users
+            .entry(address.to_lowercase())
+            .or_insert_with(|| format!("user{}@example.com", next))
+            .clone()
+    }
+
+    fn folder(&mut self, name: &str) -> String {
+        if name.is_empty() || name.eq_ignore_ascii_case("INBOX") {
+            return name.to_string();
+        }
+        let next = self.folders.len() + 1;
+        self.folders
+            .entry(name.to_string())
+            .or_insert_with(|| format!("Folder{}", next))
+            .clone()
+    }
+
+    fn word(&mut self, word: &str) -> String {
+        if word.chars().all(|c| c.is_ascii_digit()) {
+            return word.to_string();
+        }
+        let next = self.words.len() + 1;
+        self.words
+            .entry(word.to_lowercase())
+            .or_insert_with(|| format!("word{}", next))
+            .clone()
+    }
+
+    /// Replace the words of a value, keeping punctuation, wildcards, escapes, line breaks and
+    /// `${...}` references
+    fn text(&mut self, value: &str) -> String {
+        if EMAIL.is_match(value) {
+            return self.user(value);
+        }
+        let mut result = String::new();
+        let mut word = String::new();
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                result.push_str(&self.word(&word));
+                word.clear();
+            }
+            result.push(c);
+            match c {
+                // Escaped characters in patterns, like `\d`, are syntax rather than content
+                '\\' => result.extend(chars.next()),
+                '$' if chars.peek() == Some(&'{') => {
+                    for c in chars.by_ref() {
+                        result.push(c);
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !word.is_empty() {
+            result.push_str(&self.word(&word));
+        }
+        result
+    }
+
+    /// Replace every level of a mailbox path, keeping `/` and `.` separators
+    fn mailbox(&mut self, path: &str) -> String {
+        if path.contains("${") {
+            return self.text(path);
+        }
+        path.split_inclusive(['/', '.'])
+            .map(|level| match level.strip_suffix(['/', '.']) {
+                Some(name) => format!("{}{}", self.folder(name), &level[name.len()..]),
+                None => self.folder(level),
+            })
+            .collect()
+    }
+
+    fn value(&mut self, value: &str, kind: Kind) -> String {
+        match kind {
+            _ if SYSTEM_FLAG.is_match(value) => value.to_string(),
+            Kind::Kept => value.to_string(),
+            Kind::Mailbox => self.mailbox(value),
+            Kind::Text => self.text(value),
+        }
+    }
+}
+
+/// Render a string literal with a new value, in the same quoted or `text:` form
+fn render(string: &StringLiteral, value: &str) -> String {
+    if !string.multiline {
+        return refactor::quote_string(value);
+    }
+    let mut rendered = String::from("text:\n");
+    for line in value.lines() {
+        if line.starts_with('.') {
+            rendered.push('.');
+        }
+        rendered.push_str(line);
+        rendered.push('\n');
+    }
+    rendered.push('.');
+    rendered
+}
+
+/// Classify the string arguments of a command or test
+fn classify<'a>(name: &str, arguments: &'a [Argument]) -> Vec<(&'a StringLiteral, Kind)> {
+    let kept = kept_positionals(name);
+    let mut strings = Vec::new();
+    let mut positional = 0;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if let Some(tag) = argument.tag() {
+            if ANONYMIZED_TAG_VALUES.contains(&tag) {
+                if let Some(value) = arguments.next() {
+                    strings.extend(value.strings().into_iter().map(|s| (s, Kind::Text)));
+                }
+            } else if KEPT_TAG_VALUES.contains(&tag) || TAGS_WITH_VALUE.contains(&tag) {
+                arguments.next();
+            }
+            continue;
+        }
+        let kind = if positional < kept {
+            Kind::Kept
+        } else if matches!(name, "fileinto" | "mailboxexists") {
+            Kind::Mailbox
+        } else {
+            Kind::Text
+        };
+        strings.extend(argument.strings().into_iter().map(|s| (s, kind)));
+        positional += 1;
+    }
+    strings
+}
+
+/// Replace a comment with a placeholder spanning the same number of lines
+fn comment_placeholder(text: &str, range: Range, bracket: bool) -> String {
+    if !bracket {
+        return "# comment".to_string();
+    }
+    let source = &text[parser::offset_at(text, range.start)..parser::offset_at(text, range.end)];
+    format!("/* comment{} */", "\n".repeat(source.matches('\n').count()))
+}
+
+/// Anonymize a script, keeping its structure and line layout
+pub fn anonymize(text: &str, script: &Script) -> String {
+    let mut tokens = Tokens::default();
+    let mut edits: Vec<(Range, String)> = Vec::new();
+
+    let mut strings: Vec<(&StringLiteral, Kind)> = script
+        .all_commands()
+        .into_iter()
+        .flat_map(|c| classify(&c.name, &c.arguments))
+        .chain(script.all_tests().into_iter().flat_map(|t| classify(&t.name, &t.arguments)))
+        .collect();
+    // Number placeholders in reading order
+    strings.sort_by_key(|(string, _)| string.range.start);
+    for (string, kind) in strings {
+        let value = tokens.value(&string.value, kind);
+        if value != string.value {
+            edits.push((string.range, render(string, &value)));
+        }
+    }
+    for comment in &script.comments {
+        edits.push((
+            comment.range,
+            comment_placeholder(text, comment.range, comment.bracket),
+        ));
+    }
+
+    // Apply back to front so earlier ranges stay valid
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut result = text.to_string();
+    for (range, replacement) in edits {
+        let start = parser::offset_at(&result, range.start);
+        let end = parser::offset_at(&result, range.end);
+        result.replace_range(start..end, &replacement);
+    }
+    result
+}
@@ -0,0 +1,243 @@
+// ================================================================================================
+// CONDITION BUILDER
+// ================================================================================================
+//
+// Backs form-based rule editors: a client describes a condition as field, operator and values,
+// and gets back the Sieve test together with the capabilities it needs. Served as the custom
+// `sieve/buildCondition` request. "Create filter" wizards go one step further with the
+// `sieve.newRule` command, which adds a whole rule with its action to a script and updates
+// its require statements.
+
+use crate::parser::{self, Script};
+use crate::refactor;
+use crate::requires;
+use crate::sieve;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use tower_lsp::lsp_types::{Position, Range, TextDocumentIdentifier, TextEdit};
+
+/// Method name of the custom request
+pub const BUILD_CONDITION_METHOD: &str = "sieve/buildCondition";
+
+/// A condition as entered in a rule builder form
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildConditionParams {
+    /// A header name such as "Subject", or one of "body", "size", "envelope-from", "envelope-to"
+    pub field: String,
+    /// "is", "contains", "matches", "regex", "exists", a numeric relation ("gt", "ge", "lt",
+    /// "le", "eq", "ne"), or "over"/"under" for size
+    pub operator: String,
+    /// Values compared against; any of them may match
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Invert the condition with `not`
+    #[serde(default)]
+    pub negate: bool,
+}
+
+/// The generated test
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildConditionResult {
+    /// Sieve source of the test, ready to be placed after `if`
+    pub source: String,
+    /// Capabilities the test needs, in alphabetical order
+    pub extensions: Vec<String>,
+}
+
+/// Turn a structured condition into a Sieve test
+/// Errors describe the invalid input and are meant to be shown next to the form.
+pub fn build_condition(params: &BuildConditionParams) -> Result<BuildConditionResult, String> {
+    let field = params.field.trim();
+    let operator = params.operator.trim().to_lowercase();
+    let mut extensions = BTreeSet::new();
+
+    let keys = || -> Result<String, String> {
+        match params.values.as_slice() {
+            [] => Err(format!("Operator '{}' needs at least one value", operator)),
+            [single] => Ok(refactor::quote_string(single)),
+            values => Ok(format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|v| refactor::quote_string(v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    };
+
+    let test = if field.eq_ignore_ascii_case("size") {
+        if operator != "over" && operator != "under" {
+            return Err(format!("Field 'size' supports 'over' and 'under', not '{}'", operator));
+        }
+        let [limit] = params.values.as_slice() else {
+            return Err("Field 'size' needs exactly one value".to_string());
+        };
+        let limit = limit.trim();
+        if parser::number_value(limit).is_none() {
+            return Err(format!("'{}' is not a size such as 100K or 10M", limit));
+        }
+        format!("size :{} {}", operator, limit)
+    } else {
+        let match_type = match operator.as_str() {
+            "is" | "contains" | "matches" => format!(":{}", operator),
+            "regex" => {
+                extensions.insert("regex");
+                ":regex".to_string()
+            }
+            relation if sieve::RELATIONAL_OPERATORS.contains_key(relation) => {
+                extensions.insert("relational");
+                extensions.insert("comparator-i;ascii-numeric");
+                format!(":value \"{}\" :comparator \"i;ascii-numeric\"", relation)
+            }
+            "exists" => String::new(),
+            other => return Err(format!("Unknown operator '{}'", other)),
+        };
+
+        match field.to_lowercase().as_str() {
+            "body" | "envelope-from" | "envelope-to" if match_type.is_empty() => {
+                return Err(format!("Field '{}' does not support 'exists'", field));
+            }
+            "body" => {
+                extensions.insert("body");
+                format!("body {} {}", match_type, keys()?)
+            }
+            part @ ("envelope-from" | "envelope-to") => {
+                extensions.insert("envelope");
+                format!(
+                    "envelope {} \"{}\" {}",
+                    match_type,
+                    part.trim_start_matches("envelope-"),
+                    keys()?
+                )
+            }
+            _ if !sieve::is_header_name(field) => {
+                return Err(format!("'{}' is not a valid header name", field));
+            }
+            _ if match_type.is_empty() => format!("exists {}", refactor::quote_string(field)),
+            // Custom X- headers may hold anything, so only well-known address headers are parsed
+            lower if sieve::ADDRESS_HEADERS.contains(&lower) => format!(
+                "address {} {} {}",
+                match_type,
+                refactor::quote_string(field),
+                keys()?
+            ),
+            _ => format!(
+                "header {} {} {}",
+                match_type,
+                refactor::quote_string(field),
+                keys()?
+            ),
+        }
+    };
+
+    Ok(BuildConditionResult {
+        source: if params.negate {
+            format!("not {}", test)
+        } else {
+            test
+        },
+        extensions: extensions.into_iter().map(str::to_string).collect(),
+    })
+}
+
+/// Arguments of the `sieve.newRule` command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NewRuleParams {
+    /// The open document the rule is appended to
+    pub text_document: TextDocumentIdentifier,
+    /// Field of the condition, as `field` of [`BuildConditionParams`]
+    pub header: String,
+    /// Operator of the condition, as `operator` of [`BuildConditionParams`]
+    #[serde(rename = "match")]
+    pub match_type: String,
+    /// Value compared against; not needed for `exists`
+    #[serde(default)]
+    pub value: Option<String>,
+    /// "fileinto", "redirect", "keep" or "discard"
+    pub action: String,
+    /// Mailbox of `fileinto`
+    #[serde(default)]
+    pub folder: Option<String>,
+    /// Address of `redirect`
+    #[serde(default)]
+    pub address: Option<String>,
+    /// End the script after the action
+    #[serde(default)]
+    pub stop: bool,
+}
+
+/// Source of a whole rule with the given indentation, and the capabilities it needs
+pub fn build_rule(params: &NewRuleParams, indent: &str) -> Result<BuildConditionResult, String> {
+    let condition = build_condition(&BuildConditionParams {
+        field: params.header.clone(),
+        operator: params.match_type.clone(),
+        values: params.value.iter().cloned().collect(),
+        negate: false,
+    })?;
+    let mut extensions: BTreeSet<String> = condition.extensions.into_iter().collect();
+
+    let argument = |value: &Option<String>, missing: &str| match value.as_deref().map(str::trim) {
+        Some(value) if !value.is_empty() => Ok(value.to_string()),
+        _ => Err(missing.to_string()),
+    };
+    let action = match params.action.trim().to_lowercase().as_str() {
+        "fileinto" => {
+            extensions.insert("fileinto".to_string());
+            let folder = argument(&params.folder, "Action 'fileinto' needs a folder")?;
+            format!("fileinto {};", refactor::quote_string(&folder))
+        }
+        "redirect" => {
+            let address = argument(&params.address, "Action 'redirect' needs an address")?;
+            sieve::check_address(&address).map_err(|error| error.message)?;
+            format!("redirect {};", refactor::quote_string(&address))
+        }
+        action @ ("keep" | "discard") => format!("{};", action),
+        other => return Err(format!("Unknown action '{}'", other)),
+    };
+
+    let mut source = format!("if {} {{\n{}{}\n", condition.source, indent, action);
+    if params.stop {
+        source.push_str(&format!("{}stop;\n", indent));
+    }
+    source.push('}');
+    Ok(BuildConditionResult {
+        source,
+        extensions: extensions.into_iter().collect(),
+    })
+}
+
+/// Edits appending a rule to a script and adding the capabilities it needs
+/// Positions count characters.
+pub fn new_rule_edits(
+    text: &str,
+    script: &Script,
+    params: &NewRuleParams,
+    indent: &str,
+) -> Result<Vec<TextEdit>, String> {
+    let rule = build_rule(params, indent)?;
+    let mut edits: Vec<TextEdit> = requires::add_requirements(script, &rule.extensions)
+        .into_iter()
+        .collect();
+
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    let end = Position {
+        line: text.matches('\n').count() as u32,
+        character: last_line.chars().count() as u32,
+    };
+    let separator = if text.trim().is_empty() || text.ends_with("\n\n") {
+        ""
+    } else if text.ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    edits.push(TextEdit {
+        range: Range { start: end, end },
+        new_text: format!("{}{}\n", separator, rule.source),
+    });
+    Ok(edits)
+}
@@ -0,0 +1,681 @@
+// ================================================================================================
+// COMMAND LINE MODES
+// ================================================================================================
+//
+// Besides serving LSP on stdin/stdout (see `logging` for its options) the binary offers
+// one-shot modes for editor plugins and scripts that cannot keep a language server running.
+// `check` and `fmt` apply the workspace configuration file of the current directory, as CI
+// runs them at the repository root:
+//
+//   sieve-lsp analyze --stdin [--format json]
+//   sieve-lsp check [--format text|json|sarif] [--deny-warnings] (--stdin | FILE...)
+//   sieve-lsp corpus [--format text|json] SCRIPT MBOX|MAILDIR
+//   sieve-lsp fmt (--check FILE... | --write FILE... | --stdin)
+//   sieve-lsp import-gmail FILE
+//   sieve-lsp test DIR
+//
+// The modes do not log unless given `--log-level LEVEL`, which they accept anywhere.
+
+use crate::config;
+use crate::fixtures::{self, Expectation, FixtureResult};
+use crate::datastructures::{
+    SieveDocument, SieveLanguageServer, SieveSettings, sort_document_symbols,
+};
+use crate::format;
+use crate::gmail;
+use crate::interpreter;
+use crate::message;
+use crate::metrics::{self, ScriptMetrics};
+use crate::outline;
+use crate::parser;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::io::AsyncReadExt;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+use std::path::Path;
+
+/// Usage text printed for unknown or malformed invocations
+pub const USAGE: &str = "usage: sieve-lsp [[--stdio] [--log-file PATH] [--log-level LEVEL] | \
+                         analyze --stdin [--format json] | \
+                         check [--format text|json|sarif] [--deny-warnings] \
+                         (--stdin | FILE...) | \
+                         corpus [--format text|json] SCRIPT MBOX|MAILDIR | \
+                         fmt (--check FILE... | --write FILE... | --stdin) | \
+                         import-gmail FILE | test DIR]";
+
+/// SARIF version written by `check --format sarif`, as accepted by GitHub code scanning
+pub const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Everything the server knows about one script, as written by `analyze`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisReport {
+    /// How the `character` of positions is counted
+    pub position_encoding: PositionEncodingKind,
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<DocumentSymbol>,
+    pub folding_ranges: Vec<FoldingRange>,
+    pub metrics: ScriptMetrics,
+}
+
+/// Run the full analysis of the language server over a single script
+/// Positions use UTF-16 columns, the LSP default
+pub async fn analyze_text(text: &str) -> AnalysisReport {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("stdin:///script.sieve").expect("static URI is valid");
+    let document = SieveDocument::new(uri.clone(), text.to_string(), 0);
+
+    let mut symbols: Vec<DocumentSymbol> = outline::document_symbols(text, document.script())
+        .into_iter()
+        .map(|symbol| document.to_client_symbol(symbol))
+        .collect();
+    sort_document_symbols(&mut symbols);
+    let folding_ranges = outline::folding_ranges(text, document.script());
+    let metrics = metrics::metrics(text, document.script(), &Default::default());
+    let position_encoding = document.encoding().kind();
+    server.document_map.insert(uri.clone(), document);
+
+    let mut diagnostics = server.validate_document(&uri).await.unwrap_or_default();
+    if let Some(document) = server.document_map.get(&uri) {
+        for diagnostic in &mut diagnostics {
+            document.to_client_diagnostic(diagnostic);
+        }
+    }
+
+    AnalysisReport {
+        position_encoding,
+        diagnostics,
+        symbols,
+        folding_ranges,
+        metrics,
+    }
+}
+
+/// Run the validation pipeline over several scripts with one server, as `check` does
+/// Positions count characters, as editors show columns. Scripts that cannot be read are
+/// reported as errors instead of diagnostics.
+pub async fn check_files(
+    files: &[(String, String)],
+    settings: &SieveSettings,
+) -> Vec<(String, Vec<Diagnostic>)> {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await = settings.clone();
+    let mut results = Vec::new();
+    for (name, text) in files {
+        let uri = std::fs::canonicalize(name)
+            .ok()
+            .and_then(|path| Url::from_file_path(path).ok())
+            .or_else(|| Url::parse(&format!("stdin:///{}", name)).ok())
+            .expect("stdin URI is valid");
+        server
+            .document_map
+            .insert(uri.clone(), SieveDocument::new(uri.clone(), text.clone(), 0));
+        let diagnostics = server.validate_document(&uri).await.unwrap_or_default();
+        server.document_map.remove(&uri);
+        results.push((name.clone(), diagnostics));
+    }
+    results
+}
+
+/// Settings of the workspace configuration file in a directory, defaults without one
+pub fn workspace_settings(root: &Path) -> Result<SieveSettings, String> {
+    let config = config::load(root)
+        .map_err(|(path, err)| format!("Invalid configuration in {}: {}", path.display(), err))?;
+    SieveSettings::layered(&json!({}), config.as_ref().map(|(_, config)| config))
+        .map_err(|err| format!("Invalid workspace configuration: {}", err))
+}
+
+/// A diagnostic as one line of compiler-style output, with 1-based line and column
+/// e.g. `filter.sieve:3:5: error[missing-require]: ...`
+pub fn format_diagnostic(name: &str, diagnostic: &Diagnostic) -> String {
+    let severity = match diagnostic.severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        _ => "hint",
+    };
+    let code = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => format!("[{}]", code),
+        Some(NumberOrString::Number(code)) => format!("[{}]", code),
+        None => String::new(),
+    };
+    format!(
+        "{}:{}:{}: {}{}: {}",
+        name,
+        diagnostic.range.start.line + 1,
+        diagnostic.range.start.character + 1,
+        severity,
+        code,
+        diagnostic.message
+    )
+}
+
+/// Findings of `check` for one script
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckedFile {
+    pub path: String,
+    /// Positions are 0-based and count characters
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A script `check` could not read
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadableFile {
+    pub path: String,
+    pub error: String,
+}
+
+/// The machine-readable result of `check --format json`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckReport {
+    pub files: Vec<CheckedFile>,
+    pub unreadable: Vec<UnreadableFile>,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl CheckReport {
+    pub fn new(results: Vec<(String, Vec<Diagnostic>)>, unreadable: Vec<UnreadableFile>) -> Self {
+        let count = |severity| {
+            results
+                .iter()
+                .flat_map(|(_, diagnostics)| diagnostics)
+                .filter(|d| d.severity == Some(severity))
+                .count()
+        };
+        Self {
+            errors: count(DiagnosticSeverity::ERROR) + unreadable.len(),
+            warnings: count(DiagnosticSeverity::WARNING),
+            files: results
+                .into_iter()
+                .map(|(path, diagnostics)| CheckedFile { path, diagnostics })
+                .collect(),
+            unreadable,
+        }
+    }
+
+    /// Compiler-style lines followed by a summary
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for file in &self.unreadable {
+            text.push_str(&format!("{}: error: cannot read file: {}\n", file.path, file.error));
+        }
+        for file in &self.files {
+            for diagnostic in &file.diagnostics {
+                text.push_str(&format_diagnostic(&file.path, diagnostic));
+                text.push('\n');
+            }
+        }
+        text.push_str(&format!(
+            "{} error(s), {} warning(s) in {} file(s)\n",
+            self.errors,
+            self.warnings,
+            self.files.len() + self.unreadable.len()
+        ));
+        text
+    }
+
+    /// A SARIF log with one run, for GitHub code scanning and other static analysis tooling
+    /// Columns count Unicode code points, which the run declares in `columnKind`.
+    pub fn to_sarif(&self) -> Value {
+        let mut rules: Vec<Value> = Vec::new();
+        let mut results = Vec::new();
+        for file in &self.files {
+            for diagnostic in &file.diagnostics {
+                let rule_id = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => code.clone(),
+                    Some(NumberOrString::Number(code)) => code.to_string(),
+                    None => "sieve".to_string(),
+                };
+                let rule_index = match rules.iter().position(|r| r["id"] == rule_id.as_str()) {
+                    Some(index) => index,
+                    None => {
+                        let mut rule = json!({ "id": rule_id });
+                        if let Some(description) = &diagnostic.code_description {
+                            rule["helpUri"] = json!(description.href);
+                        }
+                        rules.push(rule);
+                        rules.len() - 1
+                    }
+                };
+                let level = match diagnostic.severity {
+                    Some(DiagnosticSeverity::ERROR) => "error",
+                    Some(DiagnosticSeverity::WARNING) => "warning",
+                    _ => "note",
+                };
+                let range = diagnostic.range;
+                results.push(json!({
+                    "ruleId": rule_id,
+                    "ruleIndex": rule_index,
+                    "level": level,
+                    "message": { "text": diagnostic.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": sarif_uri(&file.path) },
+                            "region": {
+                                "startLine": range.start.line + 1,
+                                "startColumn": range.start.character + 1,
+                                "endLine": range.end.line + 1,
+                                "endColumn": range.end.character + 1,
+                            },
+                        },
+                    }],
+                }));
+            }
+        }
+        let notifications: Vec<Value> = self
+            .unreadable
+            .iter()
+            .map(|file| {
+                json!({
+                    "level": "error",
+                    "message": { "text": format!("cannot read file: {}", file.error) },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": sarif_uri(&file.path) },
+                        },
+                    }],
+                })
+            })
+            .collect();
+
+        json!({
+            "version": SARIF_VERSION,
+            "$schema": SARIF_SCHEMA,
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "sieve-lsp",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "columnKind": "unicodeCodePoints",
+                "invocations": [{
+                    "executionSuccessful": true,
+                    "toolExecutionNotifications": notifications,
+                }],
+                "results": results,
+            }],
+        })
+    }
+}
+
+/// Relative paths stay relative so code scanning resolves them against the checkout
+fn sarif_uri(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Dispatch a command line mode; `args` excludes the program name
+/// Returns the process exit code
+pub async fn run(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("analyze") => analyze(&args[1..]).await,
+        Some("check") => check(&args[1..]).await,
+        Some("corpus") => corpus(&args[1..]),
+        Some("fmt") => fmt(&args[1..]).await,
+        Some("import-gmail") => import_gmail(&args[1..]),
+        Some("test") => test(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+async fn analyze(args: &[String]) -> i32 {
+    let mut stdin = false;
+    let mut format = "json".to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => stdin = true,
+            "--format" => match args.next() {
+                Some(value) => format = value.clone(),
+                None => {
+                    eprintln!("--format expects a value");
+                    return 2;
+                }
+            },
+            other => {
+                eprintln!("Unknown argument: {}\n{}", other, USAGE);
+                return 2;
+            }
+        }
+    }
+    if !stdin {
+        eprintln!("analyze currently only reads scripts from --stdin\n{}", USAGE);
+        return 2;
+    }
+    if format != "json" {
+        eprintln!("Unsupported format: {} (expected json)", format);
+        return 2;
+    }
+
+    let mut text = String::new();
+    if let Err(err) = tokio::io::stdin().read_to_string(&mut text).await {
+        eprintln!("Cannot read stdin: {}", err);
+        return 1;
+    }
+
+    let report = analyze_text(&text).await;
+    match serde_json::to_string(&report) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(err) => {
+            eprintln!("Cannot serialize analysis: {}", err);
+            1
+        }
+    }
+}
+
+/// Lint scripts for CI: exit code 1 when errors were found, or warnings with `--deny-warnings`
+async fn check(args: &[String]) -> i32 {
+    let mut stdin = false;
+    let mut deny_warnings = false;
+    let mut format = "text".to_string();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => stdin = true,
+            "--deny-warnings" => deny_warnings = true,
+            "--format" => match args.next() {
+                Some(value) => format = value.clone(),
+                None => {
+                    eprintln!("--format expects a value");
+                    return 2;
+                }
+            },
+            other if other.starts_with("--") => {
+                eprintln!("Unknown argument: {}\n{}", other, USAGE);
+                return 2;
+            }
+            path => paths.push(path.to_string()),
+        }
+    }
+    // Exactly one source of scripts
+    if stdin != paths.is_empty() {
+        eprintln!("check expects either --stdin or script files\n{}", USAGE);
+        return 2;
+    }
+    if !matches!(format.as_str(), "text" | "json" | "sarif") {
+        eprintln!("Unsupported format: {} (expected text, json or sarif)", format);
+        return 2;
+    }
+
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    if stdin {
+        let mut text = String::new();
+        if let Err(err) = tokio::io::stdin().read_to_string(&mut text).await {
+            eprintln!("Cannot read stdin: {}", err);
+            return 1;
+        }
+        files.push(("<stdin>".to_string(), text));
+    }
+    for path in paths {
+        match std::fs::read(Path::new(&path)) {
+            Ok(bytes) => files.push((path, String::from_utf8_lossy(&bytes).into_owned())),
+            Err(err) => unreadable.push(UnreadableFile {
+                path,
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    let settings = match workspace_settings(Path::new(".")) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    let report = CheckReport::new(check_files(&files, &settings).await, unreadable);
+    let output = match format.as_str() {
+        "json" => serde_json::to_string(&report),
+        "sarif" => serde_json::to_string_pretty(&report.to_sarif()),
+        _ => Ok(report.to_text()),
+    };
+    match output {
+        Ok(output) => println!("{}", output.trim_end()),
+        Err(err) => {
+            eprintln!("Cannot serialize diagnostics: {}", err);
+            return 1;
+        }
+    }
+    if report.errors > 0 || (deny_warnings && report.warnings > 0) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Format scripts with the engine behind `textDocument/formatting`
+/// `--check` lists the files that would change and exits with 1 if there are any, `--write`
+/// rewrites them in place and `--stdin` prints the formatted script.
+async fn fmt(args: &[String]) -> i32 {
+    let mut mode = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" | "--write" | "--stdin" => {
+                if mode.replace(arg.as_str()).is_some_and(|mode| mode != arg) {
+                    eprintln!("fmt expects only one of --check, --write and --stdin\n{}", USAGE);
+                    return 2;
+                }
+            }
+            other if other.starts_with("--") => {
+                eprintln!("Unknown argument: {}\n{}", other, USAGE);
+                return 2;
+            }
+            path => paths.push(path.to_string()),
+        }
+    }
+    let options = match workspace_settings(Path::new(".")) {
+        Ok(settings) => settings.format().options(None),
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+
+    match mode {
+        Some("--stdin") if paths.is_empty() => {
+            let mut text = String::new();
+            if let Err(err) = tokio::io::stdin().read_to_string(&mut text).await {
+                eprintln!("Cannot read stdin: {}", err);
+                return 1;
+            }
+            print!("{}", format::format(&text, &options));
+            0
+        }
+        Some(mode @ ("--check" | "--write")) if !paths.is_empty() => {
+            let mut failed = false;
+            let mut changed = 0;
+            for path in &paths {
+                let text = match std::fs::read_to_string(path) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        eprintln!("Cannot read {}: {}", path, err);
+                        failed = true;
+                        continue;
+                    }
+                };
+                let formatted = format::format(&text, &options);
+                if formatted == text {
+                    continue;
+                }
+                changed += 1;
+                if mode == "--check" {
+                    println!("Would reformat: {}", path);
+                } else if let Err(err) = std::fs::write(path, formatted) {
+                    eprintln!("Cannot write {}: {}", path, err);
+                    failed = true;
+                } else {
+                    println!("Reformatted: {}", path);
+                }
+            }
+            if failed || (mode == "--check" && changed > 0) {
+                1
+            } else {
+                0
+            }
+        }
+        _ => {
+            eprintln!("fmt expects --check or --write with script files, or --stdin\n{}", USAGE);
+            2
+        }
+    }
+}
+
+/// Convert an exported Gmail filter file: the script goes to stdout, warnings to stderr
+fn import_gmail(args: &[String]) -> i32 {
+    let [path] = args else {
+        eprintln!("import-gmail expects the exported filter file\n{}", USAGE);
+        return 2;
+    };
+    let xml = match std::fs::read_to_string(path) {
+        Ok(xml) => xml,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", path, err);
+            return 1;
+        }
+    };
+    match gmail::import(&xml) {
+        Ok(import) => {
+            for warning in &import.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            print!("{}", import.text);
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Run a script against every message of an mbox file or Maildir and count the matches of
+/// each rule, with the mailboxes it files them into
+fn corpus(args: &[String]) -> i32 {
+    let mut format = "text".to_string();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next() {
+                Some(value) => format = value.clone(),
+                None => {
+                    eprintln!("--format expects a value");
+                    return 2;
+                }
+            },
+            other if other.starts_with("--") => {
+                eprintln!("Unknown argument: {}\n{}", other, USAGE);
+                return 2;
+            }
+            path => paths.push(path.to_string()),
+        }
+    }
+    let [script_path, corpus_path] = paths.as_slice() else {
+        eprintln!("corpus expects a script and an mbox file or Maildir\n{}", USAGE);
+        return 2;
+    };
+    if !matches!(format.as_str(), "text" | "json") {
+        eprintln!("Unsupported format: {} (expected text or json)", format);
+        return 2;
+    }
+    let text = match std::fs::read(script_path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", script_path, err);
+            return 1;
+        }
+    };
+    let messages = match message::load_corpus(Path::new(corpus_path)) {
+        Ok(messages) => messages,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", corpus_path, err);
+            return 1;
+        }
+    };
+
+    let report = interpreter::run_corpus(&parser::parse(&text), &messages);
+    if format == "json" {
+        println!("{}", json!(report));
+        return 0;
+    }
+    println!("{} messages, {} kept in the inbox", report.messages, report.kept);
+    for rule in &report.rules {
+        println!(
+            "line {}: {} {} matched {}",
+            rule.range.start.line + 1,
+            rule.command,
+            rule.test,
+            rule.matched
+        );
+        for (mailbox, count) in &rule.destinations {
+            println!("  fileinto {:?}: {}", mailbox, count);
+        }
+    }
+    0
+}
+
+/// Run the script fixtures below a directory, see `fixtures`
+/// Exits with 1 when a fixture fails or there are none, as a CI job should not pass silently.
+fn test(args: &[String]) -> i32 {
+    let [dir] = args else {
+        eprintln!("test expects a directory\n{}", USAGE);
+        return 2;
+    };
+    if !Path::new(dir).is_dir() {
+        eprintln!("Not a directory: {}", dir);
+        return 2;
+    }
+    let fixtures = fixtures::discover(Path::new(dir));
+    if fixtures.is_empty() {
+        eprintln!("No scripts with .eml fixtures found in {}", dir);
+        return 1;
+    }
+
+    let mut failed = 0;
+    for fixture in &fixtures {
+        let name = format!(
+            "{} with {}",
+            fixture.script.display(),
+            fixture.message.file_name().unwrap_or_default().to_string_lossy()
+        );
+        match fixtures::run(fixture) {
+            FixtureResult::Passed => println!("ok      {}", name),
+            FixtureResult::Failed { expected, actual } => {
+                failed += 1;
+                println!("FAILED  {}", name);
+                println!("  expected:\n{}", indented_yaml(&expected));
+                println!("  actual:\n{}", indented_yaml(&actual));
+            }
+            FixtureResult::Error(err) => {
+                failed += 1;
+                println!("ERROR   {}: {}", name, err);
+            }
+        }
+    }
+    println!("{} passed, {} failed", fixtures.len() - failed, failed);
+    if failed > 0 { 1 } else { 0 }
+}
+
+/// An expectation as YAML, indented below the fixture it belongs to
+fn indented_yaml(expectation: &Expectation) -> String {
+    let yaml = serde_yaml::to_string(expectation).unwrap_or_default();
+    yaml.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n")
+}
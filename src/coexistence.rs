@@ -0,0 +1,166 @@
+// ================================================================================================
+// COEXISTENCE WITH OTHER SIEVE TOOLS
+// ================================================================================================
+//
+// Some editors ship their own Sieve checker, and mail providers offer linters of their own.
+// Users running one of them next to this server would see every problem twice. Clients name the
+// tools they run in `initializationOptions.activeSieveTools`; the `coexistence` settings then
+// leave whole categories of diagnostics to those tools. Every diagnostic of this server carries
+// the source `sieve-lsp` and a code from `DIAGNOSTIC_CODES`, so clients can also filter
+// overlaps themselves.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+/// Source of all built-in diagnostics
+pub const DIAGNOSTIC_SOURCE: &str = "sieve-lsp";
+
+/// Code of diagnostics converted from the external linter's output
+pub const EXTERNAL_LINTER_CODE: &str = "external-linter";
+
+/// Source and code of problems the ManageSieve server found in a checked script
+pub const REMOTE_CHECK_SOURCE: &str = "managesieve";
+pub const REMOTE_CHECK_CODE: &str = "remote-check";
+
+/// Broad groups of diagnostics, as other checkers tend to cover them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticCategory {
+    /// Lexical and grammar errors
+    Syntax,
+    /// Missing requires and extension variants
+    Extensions,
+    /// Invalid values of arguments such as header names, sizes or patterns
+    Arguments,
+    /// Suspicious but valid logic, such as unreachable code
+    Logic,
+    /// Relations between the scripts of a workspace
+    Workspace,
+}
+
+/// Every code the server reports, with its category
+pub const DIAGNOSTIC_CODES: &[(&str, DiagnosticCategory)] = &[
+    ("invalid-escape", DiagnosticCategory::Syntax),
+    ("invalid-number", DiagnosticCategory::Syntax),
+    ("invalid-syntax", DiagnosticCategory::Syntax),
+    ("malformed-string-list", DiagnosticCategory::Syntax),
+    ("missing-semicolon", DiagnosticCategory::Syntax),
+    ("unknown-command", DiagnosticCategory::Syntax),
+    ("unmatched-bracket", DiagnosticCategory::Syntax),
+    ("unterminated-comment", DiagnosticCategory::Syntax),
+    ("unterminated-multiline", DiagnosticCategory::Syntax),
+    ("unterminated-string", DiagnosticCategory::Syntax),
+    ("dialect-mismatch", DiagnosticCategory::Extensions),
+    ("duplicate-require", DiagnosticCategory::Extensions),
+    ("missing-require", DiagnosticCategory::Extensions),
+    ("prefer-ereject", DiagnosticCategory::Extensions),
+    ("proton-extension-disabled", DiagnosticCategory::Extensions),
+    ("unsupported-feature", DiagnosticCategory::Extensions),
+    ("unknown-extension", DiagnosticCategory::Extensions),
+    ("unused-require", DiagnosticCategory::Extensions),
+    ("address-value", DiagnosticCategory::Arguments),
+    ("argument-order", DiagnosticCategory::Arguments),
+    ("case-sensitive-key", DiagnosticCategory::Arguments),
+    ("invalid-address", DiagnosticCategory::Arguments),
+    ("invalid-address-part", DiagnosticCategory::Arguments),
+    ("invalid-break", DiagnosticCategory::Arguments),
+    ("invalid-editheader", DiagnosticCategory::Arguments),
+    ("invalid-encoding", DiagnosticCategory::Arguments),
+    ("invalid-header-name", DiagnosticCategory::Arguments),
+    ("invalid-ihave", DiagnosticCategory::Arguments),
+    ("invalid-metadata", DiagnosticCategory::Arguments),
+    ("invalid-mime", DiagnosticCategory::Arguments),
+    ("invalid-notify", DiagnosticCategory::Arguments),
+    ("invalid-regex", DiagnosticCategory::Arguments),
+    ("invalid-relation", DiagnosticCategory::Arguments),
+    ("invalid-size", DiagnosticCategory::Arguments),
+    ("invalid-spamtest", DiagnosticCategory::Arguments),
+    ("invalid-vacation", DiagnosticCategory::Arguments),
+    ("invalid-variable", DiagnosticCategory::Arguments),
+    ("invalid-zone", DiagnosticCategory::Arguments),
+    ("mailbox-path", DiagnosticCategory::Arguments),
+    ("non-address-header", DiagnosticCategory::Arguments),
+    ("octet-case-mismatch", DiagnosticCategory::Arguments),
+    ("spamtest-comparison", DiagnosticCategory::Arguments),
+    ("unknown-comparator", DiagnosticCategory::Arguments),
+    ("unknown-envelope-part", DiagnosticCategory::Arguments),
+    ("unknown-tag", DiagnosticCategory::Arguments),
+    ("conflicting-actions", DiagnosticCategory::Logic),
+    ("empty-destination", DiagnosticCategory::Logic),
+    ("inbox-fileinto", DiagnosticCategory::Logic),
+    ("match-type", DiagnosticCategory::Logic),
+    ("match-variable", DiagnosticCategory::Logic),
+    ("redirect-loop", DiagnosticCategory::Logic),
+    ("redirect-without-copy", DiagnosticCategory::Logic),
+    ("redundant-keep", DiagnosticCategory::Logic),
+    ("redundant-negation", DiagnosticCategory::Logic),
+    ("shadowed-rule", DiagnosticCategory::Logic),
+    ("unmatched-rule", DiagnosticCategory::Logic),
+    ("unreachable-code", DiagnosticCategory::Logic),
+    ("unset-variable", DiagnosticCategory::Logic),
+    ("vacation-loop", DiagnosticCategory::Logic),
+    ("invalid-directive", DiagnosticCategory::Workspace),
+    ("unused-script", DiagnosticCategory::Workspace),
+];
+
+/// Category of a diagnostic code, `None` for codes of other tools
+pub fn category_of(code: &str) -> Option<DiagnosticCategory> {
+    DIAGNOSTIC_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, category)| *category)
+}
+
+/// Settings under `coexistence`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoexistenceSettings {
+    /// Categories left to other tools while the client reports one as active
+    #[serde(default)]
+    pub suppress: Vec<DiagnosticCategory>,
+    /// Only defer to these tools; empty defers to any reported tool
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+impl CoexistenceSettings {
+    /// Categories to suppress given the tools the client reported
+    pub fn suppressed(&self, active_tools: &[String]) -> BTreeSet<DiagnosticCategory> {
+        let deferred = active_tools.iter().any(|tool| {
+            self.tools.is_empty() || self.tools.iter().any(|t| t.eq_ignore_ascii_case(tool))
+        });
+        if deferred {
+            self.suppress.iter().copied().collect()
+        } else {
+            BTreeSet::new()
+        }
+    }
+}
+
+/// Whether a built-in diagnostic falls into one of the suppressed categories
+pub fn is_suppressed(diagnostic: &Diagnostic, suppressed: &BTreeSet<DiagnosticCategory>) -> bool {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => {
+            category_of(code).is_some_and(|category| suppressed.contains(&category))
+        }
+        _ => false,
+    }
+}
+
+/// Names of the other Sieve tools in the client's `initializationOptions`
+/// e.g. `{"activeSieveTools": ["proton-sieve-checker"]}`
+pub fn active_tools(initialization_options: Option<&Value>) -> Vec<String> {
+    initialization_options
+        .and_then(|options| options.get("activeSieveTools"))
+        .and_then(Value::as_array)
+        .map(|tools| {
+            tools
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,105 @@
+// ================================================================================================
+// WORKSPACE CONFIGURATION
+// ================================================================================================
+//
+// Settings committed with the scripts instead of kept per editor. The root of a workspace may
+// hold `sieve-lsp.toml` or `.sieverc` (JSON) with the same keys as the client settings, e.g.
+//
+//   strict_mode = true
+//   server_dialect = "dovecot"
+//   capabilities = ["fileinto", "envelope"]
+//
+//   [format]
+//   tabSize = 2
+//
+// Keys set in the file take precedence over the editor's settings, so everyone working on the
+// scripts validates and formats them the same way.
+//
+// Keys of earlier versions are migrated when settings are loaded, see `migration`.
+//
+// Editors send their settings nested under the `sieve` section, both with
+// `workspace/didChangeConfiguration` and in answer to `workspace/configuration`. Each update
+// only needs to carry the keys that changed.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Section of the editor's configuration holding the server's settings
+pub const SETTINGS_SECTION: &str = "sieve";
+
+/// Configuration files looked up at the workspace root, in order of preference
+pub const CONFIG_FILES: [&str; 2] = ["sieve-lsp.toml", ".sieverc"];
+
+/// Whether a path names a workspace configuration file
+pub fn is_config_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| CONFIG_FILES.contains(&name))
+}
+
+/// Settings of a configuration file as JSON
+/// TOML is used for `.toml` files, JSON otherwise.
+pub fn parse(path: &Path, content: &str) -> Result<Value, String> {
+    let value = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str::<Value>(content).map_err(|err| err.to_string())?
+    } else {
+        serde_json::from_str::<Value>(content).map_err(|err| err.to_string())?
+    };
+    if !value.is_object() {
+        return Err("expected a table of settings".to_string());
+    }
+    Ok(value)
+}
+
+/// The configuration file of a workspace, if it has one
+pub fn find(root: &Path) -> Option<PathBuf> {
+    CONFIG_FILES
+        .iter()
+        .map(|name| root.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Read the configuration file of a workspace
+/// Returns `Ok(None)` when the workspace has none, and the file with the reason when it cannot
+/// be read or parsed.
+pub fn load(root: &Path) -> Result<Option<(PathBuf, Value)>, (PathBuf, String)> {
+    let Some(path) = find(root) else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(&path).map_err(|err| (path.clone(), err.to_string()))?;
+    match parse(&path, &content) {
+        Ok(value) => Ok(Some((path, value))),
+        Err(err) => Err((path, err)),
+    }
+}
+
+/// The server's settings in a configuration sent by the editor
+/// Settings nested under `sieve` are taken from there; a configuration without that section
+/// holds the settings themselves.
+pub fn settings_section(settings: Value) -> Value {
+    let nested = settings.get(SETTINGS_SECTION).is_some_and(Value::is_object);
+    match settings {
+        Value::Object(mut settings) if nested => {
+            settings.remove(SETTINGS_SECTION).unwrap_or_default()
+        }
+        settings => settings,
+    }
+}
+
+/// Lay settings over others, e.g. those of a configuration file over the editor's
+/// Tables are merged key by key and `null` resets a key to its default; any other value
+/// replaces the previous one.
+pub fn overlay(base: &mut Value, settings: &Value) {
+    match (base, settings) {
+        (Value::Object(base), Value::Object(settings)) => {
+            for (key, value) in settings {
+                if value.is_null() {
+                    base.remove(key);
+                } else {
+                    overlay(base.entry(key.clone()).or_insert(Value::Null), value);
+                }
+            }
+        }
+        (base, settings) => *base = settings.clone(),
+    }
+}
@@ -1,16 +1,70 @@
-use crate::sieve::{SIEVE_ACTIONS, SIEVE_EXTENSIONS, SIEVE_TAGS, SIEVE_TESTS};
+use crate::coexistence::{self, CoexistenceSettings};
+use crate::config;
+use crate::deep;
+use crate::dialect;
+use crate::directives::{self, Directives};
+use crate::documentation;
+use crate::encoding::PositionEncoding;
+use crate::errors::{self, ErrorKind, ErrorLog, InternalError};
+use crate::external::{self, ExternalLinterSettings};
+use crate::format::{self, FormatSettings};
+use crate::i18n::Localizer;
+use crate::history::DiagnosticsHistory;
+use crate::incremental::{self, LineEdit};
+use crate::interpreter::{self, CorpusReport, Envelope, Outcome, RuleTestResult};
+use crate::logging::TraceLevel;
+use crate::mailbox::{self, MailboxConvention};
+use crate::message::{self, Message};
+use crate::migration;
+use crate::outline::{self, FlatSymbol};
+use crate::managesieve::{
+    self, CheckReport, ManageSieveError, ManageSieveResult, ManageSieveSettings, Operation,
+};
+use crate::manifest::{DeploymentManifest, MANIFEST_FILE};
+use crate::parser::{self, Argument, Script};
+use crate::profile;
+use crate::refactor;
+use crate::requires::{self, RequireLayout};
+use crate::rules::{self, RuleContext};
+use crate::status::{self, ServerStatus, StatusNotification, ValidationTiming};
+use crate::snapshot::{
+    self, DocumentState, FrozenState, IndexCache, SessionCapabilities, WorkspaceIndex, STATE_FORMAT,
+};
+use crate::sieve::{
+    self, RELATIONAL_OPERATORS, SIEVE_ACTIONS, SIEVE_COMPARATORS, SIEVE_EXTENSIONS, SIEVE_TAGS,
+    SIEVE_TESTS, TIME_ZONES,
+};
 use dashmap::DashMap;
+use futures::FutureExt;
 use lazy_static::lazy_static;
 use regex::Regex;
 use ropey::Rope;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
 use tower_lsp::lsp_types::*;
 use tower_lsp::Client;
-use tracing::{trace, error, info, warn};
+use tracing::{debug, trace, error, info, warn};
 use url::Url;
 
+/// Scans parsing fewer scripts than this finish too quickly to be worth a progress indicator
+pub const INDEX_PROGRESS_THRESHOLD: usize = 50;
+
+/// Documents with at least this many lines show a progress indicator while validated
+pub const VALIDATION_PROGRESS_THRESHOLD: usize = 5_000;
+
+/// How often a disabled `status_interval` is checked for being switched on
+const STATUS_SETTING_POLL: Duration = Duration::from_secs(5);
+
 // ================================================================================================
 // DATA STRUCTURES
 // ================================================================================================
@@ -38,6 +92,128 @@ pub struct SieveSettings {
     /// Includes checking for undefined extensions, unreachable code, etc.
     #[serde(default = "default_true")]
     semantic_analysis: bool,
+
+    /// Delay in milliseconds between the last keystroke and re-validation
+    /// Rapid typing only triggers one validation once the user pauses; 0 validates immediately
+    #[serde(default = "default_debounce_ms")]
+    debounce_ms: u64,
+
+    /// Optional external checker run on save, e.g. `sievec -c`
+    /// Its findings are merged with the built-in diagnostics under their own source name
+    #[serde(default)]
+    external_linter: Option<ExternalLinterSettings>,
+
+    /// Specification variant the server implements per extension family
+    /// e.g. `{"notify": "draft", "imapflags": "rfc5232"}`; unset families use the RFC
+    #[serde(default)]
+    dialects: BTreeMap<String, String>,
+
+    /// Handling of `require` statements
+    #[serde(default)]
+    requires: RequireSettings,
+
+    /// Folder separator and namespace prefix of the user's IMAP server
+    #[serde(default)]
+    mailbox: MailboxConvention,
+
+    /// Diagnostic categories left to other Sieve tools the client runs
+    #[serde(default)]
+    coexistence: CoexistenceSettings,
+
+    /// Sieve implementation scripts are validated against: "generic", "proton", "dovecot",
+    /// "cyrus", "fastmail" or "gmail-forwarding"
+    /// The generic dialect accepts every known extension, including vendor ones
+    #[serde(default = "default_server_dialect")]
+    server_dialect: String,
+
+    /// Extensions the server advertises, e.g. the SIEVE capability of its ManageSieve greeting
+    /// When set, requires and extension completions are limited to exactly these
+    #[serde(default)]
+    capabilities: Option<Vec<String>>,
+
+    /// Longest line, in characters, before rewrites splitting long string lists are offered
+    /// 0 disables them
+    #[serde(default = "default_max_line_length")]
+    max_line_length: usize,
+
+    /// ManageSieve server holding the user's scripts; its capabilities apply unless
+    /// `capabilities` is set
+    #[serde(default)]
+    managesieve: Option<ManageSieveSettings>,
+
+    /// Formatting preferences taking precedence over the editor's options
+    #[serde(default)]
+    format: FormatSettings,
+
+    /// Lint rules switched on or off by id, e.g. `{"argument-order": false}`
+    /// Rules not listed are enabled
+    #[serde(default)]
+    rules: BTreeMap<String, bool>,
+
+    /// Severity of individual diagnostic codes, e.g. `{"missing-require": "error"}`
+    /// `off` drops a code entirely
+    #[serde(default)]
+    severity: BTreeMap<String, RuleSeverity>,
+
+    /// Message file (`.eml`), mbox or Maildir the "Test rule" code lens runs rules against
+    /// Relative paths start at the workspace root; unset uses the `.eml` file next to the script
+    #[serde(default)]
+    sample_messages: Option<String>,
+
+    /// IMAP keywords offered besides the system flags when completing flags, e.g. `$Label1`
+    #[serde(default)]
+    keyword_flags: Vec<String>,
+
+    /// Addresses that deliver to the user's own mailbox, e.g. aliases
+    /// Redirecting to one of them is flagged as a likely mail loop
+    #[serde(default)]
+    own_addresses: Vec<String>,
+
+    /// Run the script against the sample messages whenever it is saved
+    /// Reports rules no sample matches and destinations that expand to nothing
+    #[serde(default = "default_true")]
+    deep_analysis: bool,
+
+    /// Seconds between `sieve/status` notifications; 0 only answers `sieve/statistics`
+    #[serde(default)]
+    status_interval: u64,
+}
+
+/// Severity a diagnostic code is reported with, from `severity`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSeverity {
+    Error,
+    Warning,
+    #[serde(alias = "information")]
+    Info,
+    Hint,
+    Off,
+}
+
+impl RuleSeverity {
+    /// The LSP severity, `None` for `off`
+    pub fn lsp(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Info => Some(DiagnosticSeverity::INFORMATION),
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::Off => None,
+        }
+    }
+}
+
+/// Settings under `requires`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RequireSettings {
+    /// Add missing and remove unused requires whenever a document is saved
+    #[serde(default)]
+    pub auto_manage: bool,
+    /// Merge, sort and deduplicate the requires whenever a document is saved
+    #[serde(default)]
+    pub organize_on_save: bool,
 }
 
 // Helper functions for default values in serde
@@ -50,6 +226,15 @@ fn default_false() -> bool {
 fn default_max_errors() -> usize {
     100
 }
+fn default_debounce_ms() -> u64 {
+    300
+}
+fn default_max_line_length() -> usize {
+    100
+}
+fn default_server_dialect() -> String {
+    profile::GENERIC.to_string()
+}
 
 impl Default for SieveSettings {
     fn default() -> Self {
@@ -58,7 +243,53 @@ impl Default for SieveSettings {
             strict_mode: false,
             max_errors: 100,
             semantic_analysis: true,
+            debounce_ms: 300,
+            external_linter: None,
+            dialects: BTreeMap::new(),
+            requires: RequireSettings::default(),
+            mailbox: MailboxConvention::default(),
+            coexistence: CoexistenceSettings::default(),
+            server_dialect: default_server_dialect(),
+            capabilities: None,
+            max_line_length: default_max_line_length(),
+            managesieve: None,
+            format: FormatSettings::default(),
+            rules: BTreeMap::new(),
+            severity: BTreeMap::new(),
+            sample_messages: None,
+            keyword_flags: Vec::new(),
+            own_addresses: Vec::new(),
+            deep_analysis: true,
+            status_interval: 0,
+        }
+    }
+}
+
+impl SieveSettings {
+    /// Settings from the editor's, with those of a workspace configuration file laid over them
+    /// Legacy keys of both are migrated first.
+    pub fn layered(client: &Value, config: Option<&Value>) -> serde_json::Result<Self> {
+        let mut merged = migration::migrate(client).settings;
+        if let Some(config) = config {
+            config::overlay(&mut merged, &migration::migrate(config).settings);
         }
+        serde_json::from_value(merged)
+    }
+
+    /// These settings with some replaced, e.g. by the magic comments of a script
+    /// Overrides that do not fit the settings are ignored.
+    pub fn with_overrides(&self, overrides: &serde_json::Map<String, Value>) -> Self {
+        if overrides.is_empty() {
+            return self.clone();
+        }
+        let mut settings = serde_json::to_value(self).unwrap_or_default();
+        config::overlay(&mut settings, &Value::Object(overrides.clone()));
+        serde_json::from_value(settings).unwrap_or_else(|_| self.clone())
+    }
+
+    /// Settings under `format`
+    pub fn format(&self) -> &FormatSettings {
+        &self.format
     }
 }
 
@@ -74,6 +305,28 @@ pub struct SieveDocument {
     /// Document version number for synchronization with client
     /// Incremented each time the document is modified
     pub version: i32,
+    /// Parsed syntax tree, kept in sync with the text incrementally
+    script: Script,
+    /// How the client counts columns; positions are stored as character offsets
+    encoding: PositionEncoding,
+    /// Results of the line-based checks per line, `None` where a line has not been checked
+    line_checks: Vec<Option<LineCheck>>,
+}
+
+/// Results of the line-based checks for one line
+/// Ranges are relative to the line, which is line 0, so the results move with their line when
+/// an edit above it inserts or removes lines.
+#[derive(Debug, Clone, Default)]
+struct LineCheck {
+    /// Hash of everything the checks saw: the code of the line, how the syntax tree treats it
+    /// and the settings
+    input: u64,
+    diagnostics: Vec<Diagnostic>,
+    /// Extensions named by a `require` on the line, and the range of the statement
+    requires: Vec<String>,
+    require_range: Option<Range>,
+    /// The first usage on the line of each extension that needs a `require`
+    usages: Vec<(String, Range)>,
 }
 
 impl SieveDocument {
@@ -81,8 +334,48 @@ impl SieveDocument {
     pub fn new(uri: Url, text: String, version: i32) -> Self {
         Self {
             uri,
+            script: parser::parse(&text),
             text: Rope::from_str(&text),
             version,
+            encoding: PositionEncoding::default(),
+            line_checks: Vec::new(),
+        }
+    }
+
+    /// Use the position encoding negotiated with the client instead of UTF-16
+    pub fn with_encoding(mut self, encoding: PositionEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Position encoding of positions exchanged with the client
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Convert a position sent by the client into a character position
+    pub fn to_char_position(&self, position: Position) -> Position {
+        let line = self.get_line(position.line as usize).unwrap_or_default();
+        Position {
+            line: position.line,
+            character: self.encoding.to_char_column(&line, position.character),
+        }
+    }
+
+    /// Convert a character position into a position for the client
+    pub fn to_client_position(&self, position: Position) -> Position {
+        let line = self.get_line(position.line as usize).unwrap_or_default();
+        Position {
+            line: position.line,
+            character: self.encoding.from_char_column(&line, position.character),
+        }
+    }
+
+    /// Convert a character range into a range for the client
+    pub fn to_client_range(&self, range: Range) -> Range {
+        Range {
+            start: self.to_client_position(range.start),
+            end: self.to_client_position(range.end),
         }
     }
 
@@ -90,22 +383,101 @@ impl SieveDocument {
     /// LSP sends incremental changes as ranges to avoid sending entire document
     pub fn apply_change(&mut self, change: &TextDocumentContentChangeEvent) {
         match change.range {
-            Some(range) => {
+            Some(mut range) => {
                 // Incremental change - replace text in specific range
-                let start_idx = self.text.line_to_char(range.start.line as usize)
-                    + range.start.character as usize;
-                let end_idx =
-                    self.text.line_to_char(range.end.line as usize) + range.end.character as usize;
+                // A reversed range from the client covers the same text
+                if range.end < range.start {
+                    std::mem::swap(&mut range.start, &mut range.end);
+                }
+                // Columns arrive in the client's encoding and are converted to character offsets
+                let start_idx = self.char_index(range.start);
+                let end_idx = self.char_index(range.end).max(start_idx);
 
                 // Remove old text and insert new text atomically
                 self.text.remove(start_idx..end_idx);
                 self.text.insert(start_idx, &change.text);
+
+                // Only re-parse the statements touched by the edit
+                let removed_lines = (range.end.line - range.start.line) as i64;
+                let inserted_lines = change.text.matches('\n').count() as i64;
+
+                // Line checks of the replaced lines are redone; those below move with their lines
+                let end = (range.end.line as usize + 1).min(self.line_checks.len());
+                let start = (range.start.line as usize).min(end);
+                let replaced = std::iter::repeat_n(None, inserted_lines as usize + 1);
+                self.line_checks.splice(start..end, replaced);
+
+                self.script = incremental::reparse(
+                    &self.script,
+                    &self.text.to_string(),
+                    LineEdit {
+                        first_line: range.start.line,
+                        last_line: range.end.line,
+                        line_delta: inserted_lines - removed_lines,
+                    },
+                );
             }
             None => {
                 // Full document replacement
                 self.text = Rope::from_str(&change.text);
+                self.script = parser::parse(&change.text);
+                self.line_checks.clear();
+            }
+        }
+    }
+
+    /// Convert the ranges of a diagnostic about this document into client positions
+    /// This includes the range a quick fix replaces when it differs from the diagnostic's, and
+    /// those of its further edits.
+    pub fn to_client_diagnostic(&self, diagnostic: &mut Diagnostic) {
+        diagnostic.range = self.to_client_range(diagnostic.range);
+        if let Some(Value::Object(data)) = &mut diagnostic.data {
+            if let Some(range) = data.get_mut("range")
+                && let Ok(fix_range) = serde_json::from_value::<Range>(range.clone())
+            {
+                *range = serde_json::json!(self.to_client_range(fix_range));
             }
+            for edit in data.get_mut("edits").and_then(Value::as_array_mut).into_iter().flatten() {
+                if let Some(range) = edit.get_mut("range")
+                    && let Ok(edit_range) = serde_json::from_value::<Range>(range.clone())
+                {
+                    *range = serde_json::json!(self.to_client_range(edit_range));
+                }
+            }
+        }
+        for related in diagnostic.related_information.iter_mut().flatten() {
+            if related.location.uri == self.uri {
+                related.location.range = self.to_client_range(related.location.range);
+            }
+        }
+    }
+
+    /// Convert the ranges of a symbol tree of this document into client positions
+    pub fn to_client_symbol(&self, mut symbol: DocumentSymbol) -> DocumentSymbol {
+        symbol.range = self.to_client_range(symbol.range);
+        symbol.selection_range = self.to_client_range(symbol.selection_range);
+        symbol.children = symbol.children.map(|children| {
+            children
+                .into_iter()
+                .map(|child| self.to_client_symbol(child))
+                .collect()
+        });
+        symbol
+    }
+
+    /// Rope index of a client position
+    /// Positions past the end of a line or of the document are clamped instead of panicking
+    fn char_index(&self, position: Position) -> usize {
+        if position.line as usize >= self.text.len_lines() {
+            return self.text.len_chars();
         }
+        let position = self.to_char_position(position);
+        self.text.line_to_char(position.line as usize) + position.character as usize
+    }
+
+    /// The parsed syntax tree of the current text
+    pub fn script(&self) -> &Script {
+        &self.script
     }
 
     /// Get the full text content of the document as a String
@@ -113,6 +485,63 @@ impl SieveDocument {
         self.text.to_string()
     }
 
+    /// The full text, borrowed from the rope when it holds the text in one piece
+    pub fn text(&self) -> Cow<'_, str> {
+        Cow::from(&self.text)
+    }
+
+    /// Number of lines, not counting the empty line after a final line break
+    pub fn line_count(&self) -> usize {
+        let lines = self.text.len_lines();
+        if self.text.line(lines - 1).len_chars() == 0 {
+            lines - 1
+        } else {
+            lines
+        }
+    }
+
+    /// Number of lines whose line checks have not run yet
+    /// After a validation these are the lines edited since.
+    pub fn unchecked_lines(&self) -> usize {
+        (0..self.line_count())
+            .filter(|line| !matches!(self.line_checks.get(*line), Some(Some(_))))
+            .count()
+    }
+
+    /// Results of the line-based checks for a line
+    /// `check` runs on the code of the line, with comments blanked out, and whether the
+    /// statement continues in a `text:` block below, unless the cached results are for the
+    /// same input.
+    fn line_check(
+        &mut self,
+        line: usize,
+        settings_key: u64,
+        check: impl FnOnce(&str, bool) -> LineCheck,
+    ) -> &LineCheck {
+        let text = Cow::from(self.text.line(line));
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let code = parser::blank_line_comments(text, line as u32, &self.script.comments);
+        let continues = self.script.opens_multiline_string(line as u32);
+        // Content of text: blocks is free-form message text, not Sieve statements
+        let body = self.script.is_multiline_body_line(line as u32);
+
+        let mut hasher = DefaultHasher::new();
+        (settings_key, &code, continues, body).hash(&mut hasher);
+        let input = hasher.finish();
+
+        if self.line_checks.len() <= line {
+            self.line_checks.resize(line + 1, None);
+        }
+        let entry = &mut self.line_checks[line];
+        if entry.as_ref().is_none_or(|cached| cached.input != input) {
+            let mut result = if body { LineCheck::default() } else { check(&code, continues) };
+            result.input = input;
+            *entry = Some(result);
+        }
+        entry.get_or_insert_with(LineCheck::default)
+    }
+
     /// Get a specific line of text (0-indexed)
     /// Returns None if line number is out of bounds
     pub fn get_line(&self, line: usize) -> Option<String> {
@@ -126,7 +555,8 @@ impl SieveDocument {
 
 /// The main Language Server structure
 /// Handles all LSP protocol interactions and maintains server state
-#[derive(Debug)]
+/// Cloning is cheap: all state is shared, so background tasks can hold their own handle
+#[derive(Debug, Clone)]
 pub struct SieveLanguageServer {
     /// LSP client handle for sending notifications and requests back to editor
     pub client: Client,
@@ -138,6 +568,72 @@ pub struct SieveLanguageServer {
     /// Global settings that apply to all documents
     /// RwLock allows multiple readers or single writer access
     pub settings: Arc<RwLock<SieveSettings>>,
+
+    /// Root directory of the workspace, if the client opened one
+    pub workspace_root: Arc<RwLock<Option<PathBuf>>>,
+
+    /// Rolling record of diagnostics per file, persisted in the workspace on save
+    pub history: Arc<RwLock<DiagnosticsHistory>>,
+
+    /// Diagnostics produced by the external linter at the last save of each document
+    pub external_diagnostics: Arc<DashMap<Url, Vec<Diagnostic>>>,
+
+    /// Debounced validation waiting to run (or running) for each document
+    pub pending_validations: Arc<DashMap<Url, JoinHandle<()>>>,
+
+    /// Position encoding negotiated with the client at initialization
+    pub position_encoding: Arc<RwLock<PositionEncoding>>,
+
+    /// Translates user-facing strings into the client's locale
+    pub localizer: Arc<RwLock<Localizer>>,
+
+    /// Workspace scripts not used by any entry point of the deployment manifest (canonical paths)
+    pub orphaned_scripts: Arc<RwLock<BTreeSet<PathBuf>>>,
+
+    /// Include structure of the workspace scripts, refreshed incrementally
+    pub workspace_index: Arc<RwLock<WorkspaceIndex>>,
+
+    /// State frozen by the previous session, consulted as its documents are reopened
+    pub thawed_state: Arc<RwLock<Option<FrozenState>>>,
+
+    /// Other Sieve tools the client reported as active in its initialization options
+    pub active_tools: Arc<RwLock<Vec<String>>>,
+
+    /// Recent internal errors, for `sieve/lastError` and error reports
+    pub errors: Arc<RwLock<ErrorLog>>,
+
+    /// Sieve extensions the configured ManageSieve server advertised at the last connection
+    pub remote_capabilities: Arc<RwLock<Option<Vec<String>>>>,
+
+    /// Problems the ManageSieve server found in each document, with the version it checked
+    pub remote_diagnostics: Arc<DashMap<Url, (i32, Vec<Diagnostic>)>>,
+
+    /// Findings of the deep analysis run when each document was saved, with the version saved
+    pub deep_diagnostics: Arc<DashMap<Url, (i32, Vec<Diagnostic>)>>,
+
+    /// Settings last sent by the editor, before the workspace configuration is applied
+    pub client_settings: Arc<RwLock<Value>>,
+
+    /// Settings of the configuration file at the workspace root, if there is one
+    pub workspace_config: Arc<RwLock<Option<Value>>>,
+
+    /// Whether the client accepts a dynamic registration to watch the configuration file
+    pub watch_config_file: Arc<RwLock<bool>>,
+
+    /// Whether the client answers `workspace/configuration` requests
+    pub pull_configuration: Arc<RwLock<bool>>,
+
+    /// Whether the client shows progress the server starts with `window/workDoneProgress/create`
+    pub work_done_progress: Arc<RwLock<bool>>,
+
+    /// Level of the `$/logTrace` notifications the client asked for
+    pub trace: TraceLevel,
+
+    /// How long the last validation of each open document took
+    pub validation_times: Arc<DashMap<Url, Duration>>,
+
+    /// When the server was created, for its uptime
+    pub started: Instant,
 }
 
 impl SieveLanguageServer {
@@ -148,7 +644,997 @@ impl SieveLanguageServer {
             client,
             document_map: Arc::new(DashMap::new()),
             settings: Arc::new(RwLock::new(SieveSettings::default())),
+            workspace_root: Arc::new(RwLock::new(None)),
+            history: Arc::new(RwLock::new(DiagnosticsHistory::default())),
+            external_diagnostics: Arc::new(DashMap::new()),
+            pending_validations: Arc::new(DashMap::new()),
+            position_encoding: Arc::new(RwLock::new(PositionEncoding::default())),
+            localizer: Arc::new(RwLock::new(Localizer::default())),
+            orphaned_scripts: Arc::new(RwLock::new(BTreeSet::new())),
+            workspace_index: Arc::new(RwLock::new(WorkspaceIndex::default())),
+            thawed_state: Arc::new(RwLock::new(None)),
+            active_tools: Arc::new(RwLock::new(Vec::new())),
+            errors: Arc::new(RwLock::new(ErrorLog::default())),
+            remote_capabilities: Arc::new(RwLock::new(None)),
+            remote_diagnostics: Arc::new(DashMap::new()),
+            deep_diagnostics: Arc::new(DashMap::new()),
+            client_settings: Arc::new(RwLock::new(Value::Object(Default::default()))),
+            workspace_config: Arc::new(RwLock::new(None)),
+            watch_config_file: Arc::new(RwLock::new(false)),
+            pull_configuration: Arc::new(RwLock::new(false)),
+            work_done_progress: Arc::new(RwLock::new(false)),
+            trace: TraceLevel::default(),
+            validation_times: Arc::new(DashMap::new()),
+            started: Instant::now(),
+        }
+    }
+
+    /// Send what a [`TraceRelay`](crate::logging::TraceRelay) lets through as `$/logTrace`
+    /// The relay has to share [`Self::trace`], which decides what it lets through.
+    pub fn relay_traces(&self, mut receiver: mpsc::UnboundedReceiver<LogTraceParams>) {
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            while let Some(params) = receiver.recv().await {
+                client.send_notification::<notification::LogTrace>(params).await;
+            }
+        });
+    }
+
+    /// Publish diagnostics for a document, merged with the external linter's findings
+    pub async fn publish_diagnostics(
+        &self,
+        uri: Url,
+        mut diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    ) {
+        // Analysis works in character columns and English; the client expects its own
+        // encoding and language
+        let localizer = self.localizer.read().await.clone();
+        for diagnostic in &mut diagnostics {
+            diagnostic.message = localizer.translate(&diagnostic.message);
+            for related in diagnostic.related_information.iter_mut().flatten() {
+                related.message = localizer.translate(&related.message);
+            }
+        }
+        if let Some(document) = self.document_map.get(&uri) {
+            for diagnostic in &mut diagnostics {
+                document.to_client_diagnostic(diagnostic);
+            }
+        }
+        if let Some(external) = self.external_diagnostics.get(&uri) {
+            diagnostics.extend(external.iter().cloned());
+            sort_diagnostics(&mut diagnostics);
+        }
+        // Remote checks and the deep analysis only apply to the version they saw
+        for checked in [&self.remote_diagnostics, &self.deep_diagnostics] {
+            if let Some(entry) = checked.get(&uri)
+                && Some(entry.0) == self.document_version(&uri)
+            {
+                diagnostics.extend(entry.1.iter().cloned());
+                sort_diagnostics(&mut diagnostics);
+            }
+        }
+        self.client
+            .publish_diagnostics(uri, diagnostics, version)
+            .await;
+    }
+
+    /// Current version of an open document
+    pub fn document_version(&self, uri: &Url) -> Option<i32> {
+        self.document_map.get(uri).map(|doc| doc.version)
+    }
+
+    /// Validate a document after the configured debounce delay
+    /// A newer change cancels the pending (or in-flight) validation of the previous one
+    pub async fn schedule_validation(&self, uri: Url) {
+        let debounce = self.settings.read().await.debounce_ms;
+        let Some(version) = self.document_version(&uri) else {
+            return;
+        };
+
+        if let Some((_, previous)) = self.pending_validations.remove(&uri) {
+            previous.abort();
+        }
+
+        if debounce == 0 {
+            self.validate_if_current(uri, version).await;
+            return;
+        }
+
+        let server = self.clone();
+        let task_uri = uri.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(debounce)).await;
+            server.validate_if_current(task_uri, version).await;
+        });
+        self.pending_validations.insert(uri, handle);
+    }
+
+    /// Validate and publish, unless the document moved past `version` in the meantime
+    async fn validate_if_current(&self, uri: Url, version: i32) {
+        if self.document_version(&uri) != Some(version) {
+            trace!("Skipping stale validation of {} v{}", uri, version);
+            return;
+        }
+        let Some(diagnostics) = self.validate_guarded(&uri).await else {
+            return;
+        };
+        if self.document_version(&uri) != Some(version) {
+            debug!("Discarding stale diagnostics for {} v{}", uri, version);
+            return;
+        }
+        self.publish_diagnostics(uri, diagnostics, Some(version))
+            .await;
+    }
+
+    /// Validate a document, recording a panic of the analysis as an internal error
+    /// The document is then published without diagnostics instead of taking the server down.
+    /// `None` when the validation was abandoned, see `validate_document`.
+    pub async fn validate_guarded(&self, uri: &Url) -> Option<Vec<Diagnostic>> {
+        let started = Instant::now();
+        let result = AssertUnwindSafe(self.validate_document(uri)).catch_unwind().await;
+        if self.document_map.contains_key(uri) {
+            self.validation_times.insert(uri.clone(), started.elapsed());
+        }
+        match result {
+            Ok(diagnostics) => diagnostics,
+            Err(payload) => {
+                let message = errors::panic_message(payload.as_ref());
+                error!("Analysis of {} panicked: {}", uri, message);
+                let mut error = InternalError::new(ErrorKind::Panic, message).with_uri(uri);
+                if let Some(document) = self.document_map.get(uri) {
+                    error = error.with_input_hash(snapshot::content_hash(&document.get_text()));
+                }
+                self.record_error(error).await;
+                Some(Vec::new())
+            }
+        }
+    }
+
+    /// Statistics for `sieve/statistics` and `sieve/status`
+    pub async fn server_status(&self) -> ServerStatus {
+        let mut validations: Vec<ValidationTiming> = self
+            .validation_times
+            .iter()
+            .map(|entry| ValidationTiming {
+                uri: entry.key().clone(),
+                duration_ms: entry.value().as_secs_f64() * 1000.0,
+            })
+            .collect();
+        validations.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+        ServerStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            open_documents: self.document_map.len(),
+            indexed_scripts: self.workspace_index.read().await.scripts.len(),
+            validations,
+            resident_memory: status::resident_memory(),
+            internal_errors: self.errors.read().await.len(),
+        }
+    }
+
+    /// Send `sieve/status` every `status_interval` seconds for as long as the server runs
+    /// The interval is read again after each wait, so changing it takes effect without a
+    /// restart.
+    pub fn start_status_reports(&self) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let interval = server.settings.read().await.status_interval;
+                if interval == 0 {
+                    tokio::time::sleep(STATUS_SETTING_POLL).await;
+                    continue;
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+                let status = server.server_status().await;
+                server.client.send_notification::<StatusNotification>(status).await;
+            }
+        });
+    }
+
+    /// Remember an internal error for `sieve/lastError` and error reports
+    pub async fn record_error(&self, error: InternalError) {
+        self.errors.write().await.record(error);
+    }
+
+    /// Run the configured external linter on a saved document and remember its results
+    pub async fn run_external_linter(&self, uri: &Url) {
+        let Some(linter) = self.settings.read().await.external_linter.clone() else {
+            return;
+        };
+        let Ok(path) = uri.to_file_path() else {
+            debug!("Skipping external linter for non-file URI {}", uri);
+            return;
+        };
+
+        match external::run_external_linter(&linter, &path).await {
+            Ok(diagnostics) => {
+                info!("External linter reported {} diagnostics", diagnostics.len());
+                self.external_diagnostics.insert(uri.clone(), diagnostics);
+            }
+            Err(err) => {
+                warn!("External linter '{}' failed: {}", linter.command, err);
+                self.record_error(
+                    InternalError::new(
+                        ErrorKind::External,
+                        format!("External linter '{}' failed: {}", linter.command, err),
+                    )
+                    .with_uri(uri),
+                )
+                .await;
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("External linter '{}' failed: {}", linter.command, err),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Write the diagnostics history into the workspace, if there is one
+    pub async fn persist_history(&self, history: &DiagnosticsHistory) {
+        if let Some(root) = self.workspace_root.read().await.as_ref()
+            && let Err(err) = history.save(root)
+        {
+            warn!("Failed to persist diagnostics history: {}", err);
+            self.record_error(InternalError::new(
+                ErrorKind::Io,
+                format!("Failed to persist diagnostics history: {}", err),
+            ))
+            .await;
+        }
+    }
+
+    /// Restore the state the previous session froze in the workspace
+    /// Documents are only restored when the position encoding is unchanged, since cached
+    /// diagnostics are stored in client positions. Returns whether a state was found.
+    pub async fn restore_state(&self) -> bool {
+        let Some(root) = self.workspace_root.read().await.clone() else {
+            return false;
+        };
+        let Some(mut state) = FrozenState::load(&root) else {
+            return false;
+        };
+        info!(
+            "Restoring frozen state: {} indexed script(s), {} document(s)",
+            state.index.scripts.len(),
+            state.documents.len()
+        );
+        *self.workspace_index.write().await = std::mem::take(&mut state.index);
+        if state.capabilities.position_encoding != self.position_encoding.read().await.kind() {
+            state.documents.clear();
+        }
+        *self.thawed_state.write().await = Some(state);
+        true
+    }
+
+    /// Reuse what the previous session knew about a reopened, unchanged document
+    pub async fn restore_document(&self, uri: &Url, text: &str) {
+        let thawed = self.thawed_state.read().await;
+        let Some(document) = thawed.as_ref().and_then(|state| state.document(uri, text)) else {
+            return;
+        };
+        if !document.external_diagnostics.is_empty() {
+            debug!("Restored external diagnostics of {}", uri);
+            self.external_diagnostics
+                .insert(uri.clone(), document.external_diagnostics.clone());
+        }
+    }
+
+    /// Freeze the workspace index and open documents into the workspace, if there is one
+    pub async fn freeze_state(&self) {
+        let Some(root) = self.workspace_root.read().await.clone() else {
+            return;
+        };
+        let documents = self
+            .document_map
+            .iter()
+            .map(|entry| DocumentState {
+                uri: entry.key().clone(),
+                version: entry.version,
+                content_hash: snapshot::content_hash(&entry.get_text()),
+                external_diagnostics: self
+                    .external_diagnostics
+                    .get(entry.key())
+                    .map(|d| d.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        let state = FrozenState {
+            format: STATE_FORMAT,
+            root,
+            capabilities: SessionCapabilities {
+                position_encoding: self.position_encoding.read().await.kind(),
+            },
+            documents,
+            index: self.workspace_index.read().await.clone(),
+        };
+        if let Err(err) = state.save() {
+            warn!("Failed to freeze server state: {}", err);
+            self.record_error(InternalError::new(
+                ErrorKind::Io,
+                format!("Failed to freeze server state: {}", err),
+            ))
+            .await;
+        }
+    }
+
+    /// Dialect variants configured in the settings, keyed by extension family
+    pub async fn dialects(&self) -> BTreeMap<String, String> {
+        self.settings.read().await.dialects.clone()
+    }
+
+    /// Edits applied to a document as it is saved: syncing its requires with their usage
+    /// (`requires.autoManage`), organizing them (`requires.organizeOnSave`) and formatting
+    /// (`format.onSave`)
+    /// Each step works on the result of the previous one, so they come back as a single edit of
+    /// the changed lines, in the client's position encoding.
+    pub async fn will_save_edits(&self, uri: &Url) -> Vec<TextEdit> {
+        let settings = self.settings.read().await.clone();
+        let Some(document) = self.document_map.get(uri) else {
+            return Vec::new();
+        };
+        let original = document.get_text();
+        let mut text = original.clone();
+        if settings.requires.auto_manage {
+            let edits = requires::sync_requires(&text, &parser::parse(&text), &settings.dialects);
+            text = refactor::apply_edits(&text, &edits);
+        }
+        if settings.requires.organize_on_save {
+            let edits = requires::tidy_requires(&text, &parser::parse(&text), &settings.dialects);
+            text = refactor::apply_edits(&text, &edits);
+        }
+        if settings.format.on_save {
+            text = format::format(&text, &settings.format.options(None));
+        }
+        refactor::changed_lines(&original, &text)
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: document.to_client_range(edit.range),
+                new_text: edit.new_text,
+            })
+            .collect()
+    }
+
+    /// Edits laying out the require statements of a document as one list or one per capability
+    /// Ranges are in the client's position encoding.
+    pub fn organize_requires_edits(&self, uri: &Url, layout: RequireLayout) -> Vec<TextEdit> {
+        let Some(document) = self.document_map.get(uri) else {
+            return Vec::new();
+        };
+        requires::organize_requires(&document.get_text(), document.script(), layout)
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: document.to_client_range(edit.range),
+                new_text: edit.new_text,
+            })
+            .collect()
+    }
+
+    /// Edits organizing the require statements of a document, see `requires::tidy_requires`
+    /// Ranges are in the client's position encoding.
+    pub async fn tidy_requires_edits(&self, uri: &Url) -> Vec<TextEdit> {
+        let dialects = self.dialects().await;
+        let Some(document) = self.document_map.get(uri) else {
+            return Vec::new();
+        };
+        requires::tidy_requires(&document.get_text(), document.script(), &dialects)
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: document.to_client_range(edit.range),
+                new_text: edit.new_text,
+            })
+            .collect()
+    }
+
+    /// Rewrites shortening the line at a position if it exceeds `max_line_length`
+    /// The position and the edits' ranges are in the client's position encoding.
+    pub async fn line_length_edits(
+        &self,
+        uri: &Url,
+        position: Position,
+    ) -> Vec<(&'static str, TextEdit)> {
+        let max_length = self.settings.read().await.max_line_length;
+        let Some(document) = self.document_map.get(uri) else {
+            return Vec::new();
+        };
+        let line = document.to_char_position(position).line;
+        refactor::shorten_line(&document.get_text(), document.script(), line, max_length)
+            .into_iter()
+            .map(|(title, mut edit)| {
+                edit.range = document.to_client_range(edit.range);
+                (title, edit)
+            })
+            .collect()
+    }
+
+    /// Run an operation on the configured ManageSieve server
+    /// The capabilities the server advertises replace the ones fetched before; connection and
+    /// protocol failures are recorded as internal errors.
+    pub async fn managesieve(&self, operation: Operation) -> ManageSieveResult<Value> {
+        let Some(settings) = self.settings.read().await.managesieve.clone() else {
+            return Err(ManageSieveError::NotConfigured);
+        };
+        match managesieve::execute(&settings, &operation).await {
+            Ok((capabilities, result)) => {
+                self.set_remote_capabilities(Some(capabilities.sieve)).await;
+                Ok(result)
+            }
+            Err(err) => {
+                warn!("ManageSieve {} failed: {}", operation.command(), err);
+                if matches!(err, ManageSieveError::Io(_) | ManageSieveError::Protocol(_)) {
+                    self.record_error(InternalError::new(
+                        ErrorKind::External,
+                        format!("ManageSieve server {}: {}", settings.host, err),
+                    ))
+                    .await;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Run an open document against a sample message
+    /// Without a message the sidecar file next to the script is used, e.g. `filter.eml` for
+    /// `filter.sieve`. The envelope defaults to one derived from the message headers. Ranges in
+    /// the outcome are in client positions.
+    pub fn test_message(
+        &self,
+        uri: &Url,
+        raw: Option<String>,
+        envelope: Option<Envelope>,
+    ) -> std::result::Result<Outcome, String> {
+        let raw = match raw {
+            Some(raw) => raw,
+            None => Self::sidecar_message(uri)?,
+        };
+        let document = self
+            .document_map
+            .get(uri)
+            .ok_or_else(|| "Document is not open".to_string())?;
+        let message = Message::parse(&raw);
+        let envelope = envelope.unwrap_or_else(|| Envelope::from_message(&message));
+        let mut outcome = interpreter::run(document.script(), &message, &envelope);
+        outcome.map_ranges(|range| document.to_client_range(range));
+        info!(
+            "Simulated {}: {} rule(s) evaluated, {} action(s)",
+            uri,
+            outcome.rules.len(),
+            outcome.actions.len()
+        );
+        Ok(outcome)
+    }
+
+    /// Run an open document against the sample messages and report how its top-level `if` on
+    /// the given line fared with each
+    pub async fn test_rule(
+        &self,
+        uri: &Url,
+        line: u32,
+    ) -> std::result::Result<Vec<RuleTestResult>, String> {
+        let messages = self.sample_messages(uri).await?;
+        let document = self
+            .document_map
+            .get(uri)
+            .ok_or_else(|| "Document is not open".to_string())?;
+        let script = document.script();
+        let rule = script
+            .commands
+            .iter()
+            .find(|command| command.name == "if" && command.name_range.start.line == line)
+            .ok_or_else(|| format!("No rule starts on line {}", line + 1))?;
+        let results = interpreter::test_rule(script, rule, &messages);
+        info!(
+            "Tested the rule on line {} of {} against {} message(s)",
+            line + 1,
+            uri,
+            results.len()
+        );
+        Ok(results)
+    }
+
+    /// Run an open document against every sample message and count the matches of each rule
+    pub async fn run_corpus(&self, uri: &Url) -> std::result::Result<CorpusReport, String> {
+        let messages = self.sample_messages(uri).await?;
+        let document = self
+            .document_map
+            .get(uri)
+            .ok_or_else(|| "Document is not open".to_string())?;
+        let mut report = interpreter::run_corpus(document.script(), &messages);
+        for rule in &mut report.rules {
+            rule.range = document.to_client_range(rule.range);
+        }
+        info!(
+            "Ran {} against {} message(s), {} kept in the inbox",
+            uri, report.messages, report.kept
+        );
+        Ok(report)
+    }
+
+    /// Messages rules are tested against: those of `sample_messages`, else the sidecar message
+    pub async fn sample_messages(&self, uri: &Url) -> std::result::Result<Vec<Message>, String> {
+        let configured = self.settings.read().await.sample_messages.clone();
+        let Some(configured) = configured else {
+            let mut message = Message::parse(&Self::sidecar_message(uri)?);
+            if let Ok(path) = uri.to_file_path() {
+                message.origin = path.with_extension("eml").display().to_string();
+            }
+            return Ok(vec![message]);
+        };
+        let mut path = PathBuf::from(configured);
+        if path.is_relative()
+            && let Some(root) = self.workspace_root.read().await.as_ref()
+        {
+            path = root.join(path);
+        }
+        let loaded = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("eml")) {
+            std::fs::read(&path).map(|bytes| {
+                let mut message = Message::parse(&String::from_utf8_lossy(&bytes));
+                message.origin = path.display().to_string();
+                vec![message]
+            })
+        } else {
+            message::load_corpus(&path)
+        };
+        loaded.map_err(|err| format!("Cannot read {}: {}", path.display(), err))
+    }
+
+    /// The sample message stored next to a script, e.g. `filter.eml` for `filter.sieve`
+    pub fn sidecar_message(uri: &Url) -> std::result::Result<String, String> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| "No message given and the script is not a file".to_string())?
+            .with_extension("eml");
+        std::fs::read(&path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))
+    }
+
+    /// Let the ManageSieve server check an open document and publish what it finds
+    /// Returns the server's problems in client positions; they are shown until the next edit.
+    pub async fn check_remote(&self, uri: &Url) -> ManageSieveResult<Vec<Diagnostic>> {
+        let Some((text, version)) = self
+            .document_map
+            .get(uri)
+            .map(|document| (document.get_text(), document.version))
+        else {
+            return Ok(Vec::new());
+        };
+        let result = self.managesieve(Operation::CheckScript(text.clone())).await?;
+        let report: CheckReport = serde_json::from_value(result)
+            .map_err(|err| ManageSieveError::Protocol(err.to_string()))?;
+        let mut diagnostics = managesieve::check_diagnostics(&report, &text);
+        if let Some(document) = self.document_map.get(uri) {
+            for diagnostic in &mut diagnostics {
+                document.to_client_diagnostic(diagnostic);
+            }
+        }
+        info!("ManageSieve check of {} found {} problem(s)", uri, diagnostics.len());
+        self.remote_diagnostics
+            .insert(uri.clone(), (version, diagnostics.clone()));
+
+        if let Some(local) = self.validate_guarded(uri).await {
+            self.publish_diagnostics(uri.clone(), local, Some(version))
+                .await;
+        }
+        Ok(diagnostics)
+    }
+
+    /// Run the deep analysis of a saved document against the configured sample messages
+    /// Its findings are kept for the saved version; without `sample_messages` or with
+    /// `deep_analysis` off there are none.
+    pub async fn analyze_deeply(&self, uri: &Url) {
+        let settings = self.settings.read().await.clone();
+        if !settings.deep_analysis || settings.sample_messages.is_none() {
+            self.deep_diagnostics.remove(uri);
+            return;
+        }
+        let messages = match self.sample_messages(uri).await {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("Skipping the deep analysis of {}: {}", uri, err);
+                self.deep_diagnostics.remove(uri);
+                return;
+            }
+        };
+        let localizer = self.localizer.read().await.clone();
+        let Some(document) = self.document_map.get(uri) else {
+            return;
+        };
+        let mut diagnostics = deep::analyze(document.script(), &messages);
+        apply_severity(&mut diagnostics, &settings.severity);
+        for diagnostic in &mut diagnostics {
+            diagnostic.message = localizer.translate(&diagnostic.message);
+            document.to_client_diagnostic(diagnostic);
+        }
+        info!(
+            "Deep analysis of {} against {} message(s) found {} problem(s)",
+            uri,
+            messages.len(),
+            diagnostics.len()
+        );
+        self.deep_diagnostics
+            .insert(uri.clone(), (document.version, diagnostics));
+    }
+
+    /// Fetch the capabilities of the configured ManageSieve server in the background, or forget
+    /// them when none is configured
+    pub async fn refresh_remote_capabilities(&self) {
+        if self.settings.read().await.managesieve.is_none() {
+            self.set_remote_capabilities(None).await;
+            return;
+        }
+        let server = self.clone();
+        tokio::spawn(async move {
+            // Failures are logged and recorded by `managesieve`
+            let _ = server.managesieve(Operation::Capabilities).await;
+        });
+    }
+
+    /// Replace the remote capabilities, revalidating open documents when they changed
+    async fn set_remote_capabilities(&self, capabilities: Option<Vec<String>>) {
+        {
+            let mut remote = self.remote_capabilities.write().await;
+            if *remote == capabilities {
+                return;
+            }
+            *remote = capabilities;
+        }
+        self.revalidate_open_documents().await;
+    }
+
+    /// Validate all open documents again, e.g. after the settings changed
+    pub async fn revalidate_open_documents(&self) {
+        let uris: Vec<Url> = self.document_map.iter().map(|item| item.key().clone()).collect();
+        for uri in uris {
+            if let Some(diagnostics) = self.validate_guarded(&uri).await {
+                let version = self.document_version(&uri);
+                self.publish_diagnostics(uri, diagnostics, version).await;
+            }
+        }
+    }
+
+    /// Apply settings changed in the editor
+    /// Keys missing from the update keep their current values and unknown keys are ignored.
+    /// Invalid settings keep the previous ones; the failure is kept for error reports.
+    /// Returns whether the settings were applied.
+    pub async fn set_client_settings(&self, update: Value) -> bool {
+        let input_hash = snapshot::content_hash(&update.to_string());
+        let mut client = self.client_settings.read().await.clone();
+        config::overlay(&mut client, &update);
+        for change in migration::migrate(&client).changes {
+            warn!("Legacy setting of the editor: {}", change);
+        }
+        let config = self.workspace_config.read().await.clone();
+        match SieveSettings::layered(&client, config.as_ref()) {
+            Ok(settings) => {
+                info!("Updated settings: {:?}", settings);
+                *self.settings.write().await = settings;
+                *self.client_settings.write().await = client;
+                true
+            }
+            Err(err) => {
+                warn!("Ignoring invalid settings: {}", err);
+                self.record_error(
+                    InternalError::new(ErrorKind::Parse, format!("Invalid settings: {}", err))
+                        .with_input_hash(input_hash),
+                )
+                .await;
+                false
+            }
+        }
+    }
+
+    /// Ask the editor for its settings and apply them
+    /// Only done when the client announced support for `workspace/configuration`.
+    /// Returns whether the settings were applied.
+    pub async fn request_client_settings(&self) -> bool {
+        if !*self.pull_configuration.read().await {
+            return false;
+        }
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some(config::SETTINGS_SECTION.to_string()),
+        };
+        match self.client.configuration(vec![item]).await {
+            Ok(mut values) if !values.is_empty() && values[0].is_object() => {
+                self.set_client_settings(values.swap_remove(0)).await
+            }
+            Ok(_) => false,
+            Err(err) => {
+                warn!("Cannot request the editor's settings: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Read the configuration file at the workspace root again and apply it
+    /// A file that cannot be read or parsed keeps the previous settings; the failure is kept
+    /// for error reports. Returns whether the settings were applied.
+    pub async fn reload_workspace_config(&self) -> bool {
+        let Some(root) = self.workspace_root.read().await.clone() else {
+            return false;
+        };
+        let config = match config::load(&root) {
+            Ok(Some((path, config))) => {
+                info!("Using workspace configuration {}", path.display());
+                for change in migration::migrate(&config).changes {
+                    warn!("Legacy setting in {}: {}", path.display(), change);
+                }
+                Some(config)
+            }
+            Ok(None) => None,
+            Err((path, err)) => {
+                let message = format!("Invalid configuration in {}: {}", path.display(), err);
+                warn!("{}", message);
+                self.record_error(InternalError::new(ErrorKind::Parse, message)).await;
+                return false;
+            }
+        };
+        let client = self.client_settings.read().await.clone();
+        match SieveSettings::layered(&client, config.as_ref()) {
+            Ok(settings) => {
+                info!("Updated settings: {:?}", settings);
+                *self.settings.write().await = settings;
+                *self.workspace_config.write().await = config;
+                true
+            }
+            Err(err) => {
+                let message = format!("Invalid workspace configuration: {}", err);
+                warn!("{}", message);
+                self.record_error(InternalError::new(ErrorKind::Parse, message)).await;
+                false
+            }
+        }
+    }
+
+    /// Rewrite the workspace configuration file to the current settings keys and apply it
+    /// Returns the file and what changed, `None` when it was up to date.
+    pub async fn migrate_workspace_config(
+        &self,
+    ) -> std::result::Result<Option<(PathBuf, Vec<String>)>, String> {
+        let root = self.workspace_root.read().await.clone();
+        let path = root
+            .as_deref()
+            .and_then(config::find)
+            .ok_or_else(|| "The workspace has no configuration file".to_string())?;
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        let Some((content, changes)) = migration::rewrite(&path, &content)
+            .map_err(|err| format!("Invalid configuration in {}: {}", path.display(), err))?
+        else {
+            return Ok(None);
+        };
+        std::fs::write(&path, content)
+            .map_err(|err| format!("Cannot write {}: {}", path.display(), err))?;
+        info!("Migrated {} setting(s) in {}", changes.len(), path.display());
+        self.reload_workspace_config().await;
+        Ok(Some((path, changes)))
+    }
+
+    /// Point the user to `sieve.migrateSettings` when the workspace configuration is outdated
+    pub async fn notify_legacy_settings(&self) {
+        let Some(config) = self.workspace_config.read().await.clone() else {
+            return;
+        };
+        if migration::migrate(&config).changes.is_empty() {
+            return;
+        }
+        let message = "The workspace configuration uses settings of an earlier version; \
+                       the \"Migrate settings\" command updates them";
+        let message = self.localizer.read().await.translate(message);
+        self.client.show_message(MessageType::INFO, message).await;
+    }
+
+    /// Capabilities validation is limited to: the configured ones, else the remote server's
+    async fn advertised_capabilities(&self, settings: &SieveSettings) -> Option<Vec<String>> {
+        let remote = self.remote_capabilities.read().await;
+        advertised_capabilities(settings, remote.as_deref())
+    }
+
+    /// Refresh the workspace index and recompute which scripts no entry point uses
+    /// Without a deployment manifest nothing is considered orphaned
+    /// Returns whether the set changed
+    pub async fn refresh_orphaned_scripts(&self) -> bool {
+        let orphans: BTreeSet<PathBuf> = match self.workspace_root.read().await.as_ref() {
+            Some(root) => {
+                self.refresh_index(root).await;
+                match DeploymentManifest::load(root) {
+                    Some(manifest) => self
+                        .workspace_index
+                        .read()
+                        .await
+                        .orphans(&manifest.entry_paths(root))
+                        .into_iter()
+                        .map(|path| path.canonicalize().unwrap_or(path))
+                        .collect(),
+                    None => BTreeSet::new(),
+                }
+            }
+            None => BTreeSet::new(),
+        };
+        let mut current = self.orphaned_scripts.write().await;
+        if *current == orphans {
+            return false;
+        }
+        debug!("Orphaned scripts: {:?}", orphans);
+        *current = orphans;
+        true
+    }
+
+    /// Bring the workspace index up to date, parsing changed scripts in parallel
+    /// Scans of many scripts report their progress to clients that show it. Returns the
+    /// number of scripts that had to be parsed.
+    pub async fn refresh_index(&self, root: &Path) -> usize {
+        // Readers wait for the scan rather than see a partial index
+        let mut guard = self.workspace_index.write().await;
+        // A failed scan keeps the previous index instead of leaving none
+        let mut index = guard.clone();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let root = root.to_path_buf();
+        let scan = tokio::task::spawn_blocking(move || {
+            let report = |done, total| {
+                let _ = sender.send((done, total));
+            };
+            let parsed = index.refresh_cached(&root, &IndexCache::new(&root), &report);
+            (index, parsed)
+        });
+
+        let mut token = None;
+        let mut reported = 0;
+        while let Some((done, total)) = receiver.recv().await {
+            if total < INDEX_PROGRESS_THRESHOLD {
+                continue;
+            }
+            if token.is_none() {
+                token = self.begin_progress("Indexing Sieve scripts").await;
+            }
+            let percentage = (done * 100 / total) as u32;
+            if let Some(token) = &token
+                && percentage > reported
+            {
+                reported = percentage;
+                let message = format!("{}/{} scripts", done, total);
+                self.report_progress(token, percentage, message).await;
+            }
+        }
+        let parsed = match scan.await {
+            Ok((index, parsed)) => {
+                *guard = index;
+                parsed
+            }
+            Err(err) => {
+                error!("Workspace scan failed, keeping the previous index: {}", err);
+                0
+            }
+        };
+        if let Some(token) = token {
+            self.end_progress(token).await;
+        }
+        debug!("Workspace index refreshed, {} script(s) parsed", parsed);
+        parsed
+    }
+
+    /// Start a progress indicator in the client, if it shows them
+    /// The title and the messages of later reports are translated into the client's locale.
+    pub async fn begin_progress(&self, title: &str) -> Option<ProgressToken> {
+        static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+        if !*self.work_done_progress.read().await {
+            return None;
         }
+        let id = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("sieve-lsp/progress/{}", id));
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        let created = self.client.send_request::<request::WorkDoneProgressCreate>(params).await;
+        if let Err(err) = created {
+            debug!("Client declined progress: {}", err);
+            return None;
+        }
+        let begin = WorkDoneProgressBegin {
+            title: self.localizer.read().await.translate(title),
+            cancellable: Some(false),
+            message: None,
+            percentage: Some(0),
+        };
+        self.send_progress(&token, WorkDoneProgress::Begin(begin)).await;
+        Some(token)
+    }
+
+    /// Update a progress indicator started with [`Self::begin_progress`]
+    pub async fn report_progress(&self, token: &ProgressToken, percentage: u32, message: String) {
+        let report = WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(self.localizer.read().await.translate(&message)),
+            percentage: Some(percentage),
+        };
+        self.send_progress(token, WorkDoneProgress::Report(report)).await;
+    }
+
+    /// Close a progress indicator started with [`Self::begin_progress`]
+    pub async fn end_progress(&self, token: ProgressToken) {
+        let end = WorkDoneProgress::End(WorkDoneProgressEnd { message: None });
+        self.send_progress(&token, end).await;
+    }
+
+    async fn send_progress(&self, token: &ProgressToken, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    /// Symbols of all workspace scripts whose name contains the query, ignoring case
+    /// Open documents are outlined from their current text, other scripts come from the
+    /// workspace index.
+    pub async fn workspace_symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        let mut found: Vec<(Url, FlatSymbol)> = Vec::new();
+        let mut open = BTreeSet::new();
+        for document in self.document_map.iter() {
+            if let Ok(path) = document.uri.to_file_path() {
+                open.insert(path.canonicalize().unwrap_or(path));
+            }
+            let text = document.get_text();
+            let symbols = outline::document_symbols(&text, document.script())
+                .into_iter()
+                .map(|symbol| document.to_client_symbol(symbol))
+                .collect();
+            found.extend(outline::flatten(symbols).into_iter().map(|s| (document.uri.clone(), s)));
+        }
+
+        let encoding = *self.position_encoding.read().await;
+        let index = self.workspace_index.read().await;
+        for (path, script) in &index.scripts {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            let Ok(uri) = Url::from_file_path(path) else {
+                continue;
+            };
+            if open.contains(&canonical)
+                || !script.symbols.iter().any(|s| s.name.to_lowercase().contains(&query))
+            {
+                continue;
+            }
+            // Character columns only differ from client columns on lines with other characters
+            let lines: Vec<String> = match script.ascii {
+                true => Vec::new(),
+                false => std::fs::read_to_string(path)
+                    .map(|text| text.lines().map(str::to_string).collect())
+                    .unwrap_or_default(),
+            };
+            let client = |position: Position| match lines.get(position.line as usize) {
+                Some(line) => Position {
+                    line: position.line,
+                    character: encoding.from_char_column(line, position.character),
+                },
+                None => position,
+            };
+            found.extend(script.symbols.iter().map(|symbol| {
+                let range = Range::new(client(symbol.range.start), client(symbol.range.end));
+                (uri.clone(), FlatSymbol { range, ..symbol.clone() })
+            }));
+        }
+
+        #[allow(deprecated)]
+        let mut symbols: Vec<SymbolInformation> = found
+            .into_iter()
+            .filter(|(_, symbol)| symbol.name.to_lowercase().contains(&query))
+            .map(|(uri, symbol)| SymbolInformation {
+                name: symbol.name,
+                kind: symbol.kind,
+                tags: None,
+                deprecated: None,
+                location: Location::new(uri, symbol.range),
+                container_name: symbol.container,
+            })
+            .collect();
+        // Open documents come in the map's order; report every query the same way
+        sort_symbols(&mut symbols);
+        symbols
     }
 
     /// Extract word at specific character position in a line
@@ -188,92 +1674,272 @@ impl SieveLanguageServer {
 
     /// Generate diagnostics (errors, warnings) for a Sieve document
     /// This is the core validation logic that checks syntax and semantics
-    pub async fn validate_document(&self, uri: &Url) -> Vec<Diagnostic> {
+    /// The line-based checks only rerun for lines that changed since the last validation. The
+    /// document is read in place under short locks, never held across an await.
+    /// `None` when the document changed or closed before validation finished.
+    pub async fn validate_document(&self, uri: &Url) -> Option<Vec<Diagnostic>> {
         trace!("Validating document {}", uri);
         let mut diagnostics = Vec::new();
 
+        // Get current settings
+        let settings = self.settings.read().await.clone();
+        let remote_capabilities = self.remote_capabilities.read().await.clone();
+
         // Get document from cache
-        let document = match self.document_map.get(uri) {
-            Some(doc) => doc.clone(),
+        let (version, unchecked) = match self.document_map.get(uri) {
+            Some(doc) => (doc.version, doc.unchecked_lines()),
             None => {
                 error!("Document not found in cache: {}", uri);
-                return diagnostics;
+                return None;
             }
         };
 
-        // Get current settings
-        let settings = self.settings.read().await.clone();
+        // Generated scripts can be huge; their users are shown how far validation got
+        let progress = if unchecked >= VALIDATION_PROGRESS_THRESHOLD {
+            self.begin_progress("Validating filters").await
+        } else {
+            None
+        };
+
+        let (directives, settings, guards, line_count) = {
+            let Some(document) = self.document_at(uri, version) else {
+                return self.abandon_validation(uri, progress).await;
+            };
+            let text = document.text();
+            let script = document.script();
+
+            // The syntax tree is maintained incrementally; its errors are reported directly
+            for error in &script.errors {
+                diagnostics.push(Diagnostic {
+                    range: error.range,
+                    severity: Some(error.severity),
+                    code: Some(NumberOrString::String(error.code.to_string())),
+                    code_description: Some(CodeDescription {
+                        href: Url::parse(
+                            "https://datatracker.ietf.org/doc/html/rfc5228#section-2.4",
+                        )
+                        .unwrap(),
+                    }),
+                    source: Some("sieve-lsp".to_string()),
+                    message: error.message.clone(),
+                    related_information: (!error.related.is_empty()).then(|| {
+                        error
+                            .related
+                            .iter()
+                            .map(|(range, message)| DiagnosticRelatedInformation {
+                                location: Location {
+                                    uri: uri.clone(),
+                                    range: *range,
+                                },
+                                message: message.clone(),
+                            })
+                            .collect()
+                    }),
+                    tags: None,
+                    data: None,
+                });
+            }
 
-        let text = document.get_text();
-        let lines: Vec<&str> = text.lines().collect();
+            // Magic comments may override settings for this script and suppress diagnostics
+            let directives = directives::parse(&text, script);
+            let settings = settings.with_overrides(&directives.settings);
+            self.check_directives(&mut diagnostics, &directives);
+            (directives, settings, requires::ihave_guards(script), document.line_count())
+        };
 
-        info!("Validating document with {} lines", lines.len());
+        info!("Validating document with {} lines", line_count);
 
         // Track required extensions to validate 'require' statements
         let mut required_extensions = Vec::new();
-        let mut used_extensions = Vec::new();
-
-        // Analyze each line for syntax and semantic errors
-        for (line_idx, line) in lines.iter().enumerate() {
-            trace!("Analyzing line {}", line_idx);
-            let trimmed = line.trim();
-
-            // Skip empty lines and comments
-            if trimmed.is_empty() || trimmed.starts_with('#') {
-                continue;
-            }
-
-            // Check for basic syntax errors
-            self.check_line_syntax(&mut diagnostics, line_idx, line, &settings)
-                .await;
+        let mut require_ranges = Vec::new();
+        let mut used_extensions: Vec<(String, Range)> = Vec::new();
+
+        // Cached line checks are only valid for the settings they ran with
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(&settings).unwrap_or_default().hash(&mut hasher);
+        let settings_key = hasher.finish();
+
+        // Lines are checked in chunks between progress reports, each chunk under its own lock
+        let chunk = if progress.is_some() { line_count.div_ceil(90) } else { line_count };
+        let mut line_idx = 0;
+        'lines: while line_idx < line_count {
+            let chunk_end = (line_idx + chunk).min(line_count);
+            {
+                let Some(mut document) = self.document_at(uri, version) else {
+                    return self.abandon_validation(uri, progress).await;
+                };
+                for idx in line_idx..chunk_end {
+                    trace!("Analyzing line {}", idx);
+                    let check = document.line_check(idx, settings_key, |line, continues| {
+                        self.check_line(line, continues, &settings)
+                    });
+                    let line = idx as u32;
+                    diagnostics.extend(check.diagnostics.iter().map(|diagnostic| Diagnostic {
+                        range: on_line(diagnostic.range, line),
+                        ..diagnostic.clone()
+                    }));
+
+                    // Track extension usage for semantic analysis
+                    required_extensions.extend(check.requires.iter().cloned());
+                    require_ranges.extend(check.require_range.map(|range| on_line(range, line)));
+                    // Remember the first usage outside of `ihave` guards for each extension
+                    for (extension, range) in &check.usages {
+                        let range = on_line(*range, line);
+                        if !used_extensions.iter().any(|(used, _)| used == extension)
+                            && !requires::is_guarded(&guards, extension, range.start)
+                        {
+                            used_extensions.push((extension.clone(), range));
+                        }
+                    }
 
-            // Track extension usage for semantic analysis
-            if settings.semantic_analysis {
-                self.analyze_extensions(
-                    &mut diagnostics,
-                    line_idx,
-                    line,
-                    &mut required_extensions,
-                    &mut used_extensions,
-                    &settings,
-                )
-                .await;
+                    // Stop if we've hit the error limit to avoid overwhelming the editor
+                    if diagnostics.len() >= settings.max_errors {
+                        warn!("Reached maximum error limit of {}", settings.max_errors);
+                        break 'lines;
+                    }
+                }
             }
+            line_idx = chunk_end;
 
-            // Stop if we've hit the error limit to avoid overwhelming the editor
-            if diagnostics.len() >= settings.max_errors {
-                warn!("Reached maximum error limit of {}", settings.max_errors);
-                break;
+            // The line checks take most of the time, the rules get the last tenth
+            if let Some(token) = &progress {
+                let percentage = (line_idx * 90 / line_count) as u32;
+                let message = format!("{}/{} lines", line_idx, line_count);
+                self.report_progress(token, percentage, message).await;
             }
         }
 
         // Perform global semantic analysis
+        if let Some(token) = &progress {
+            self.report_progress(token, 90, "Running lint rules".to_string()).await;
+        }
         if settings.semantic_analysis {
             self.check_extension_consistency(
                 &mut diagnostics,
+                uri,
                 &required_extensions,
+                &require_ranges,
                 &used_extensions,
             )
             .await;
+
+            // Checks of the syntax tree are registered as lint rules
+            let found = {
+                let Some(document) = self.document_at(uri, version) else {
+                    return self.abandon_validation(uri, progress).await;
+                };
+                let text = document.text();
+                let remote = remote_capabilities.as_deref();
+                let advertised = advertised_capabilities(&settings, remote);
+                let context = RuleContext {
+                    uri,
+                    text: &text,
+                    script: document.script(),
+                    strict: settings.strict_mode,
+                    mailbox: &settings.mailbox,
+                    dialects: &settings.dialects,
+                    profile: profile::find(&settings.server_dialect),
+                    advertised: advertised.as_deref(),
+                    own_addresses: &settings.own_addresses,
+                };
+                rules::run(&context, |id| settings.rules.get(id).copied().unwrap_or(true))
+            };
+            diagnostics.extend(found);
+            self.check_orphaned_script(&mut diagnostics, uri).await;
+        }
+
+        apply_severity(&mut diagnostics, &settings.severity);
+        diagnostics.retain(|d| !directives.suppresses(d));
+
+        // Leave overlapping categories to the other Sieve tools the client runs
+        let suppressed = settings
+            .coexistence
+            .suppressed(&self.active_tools.read().await);
+        if !suppressed.is_empty() {
+            diagnostics.retain(|d| !coexistence::is_suppressed(d, &suppressed));
+        }
+
+        // Report diagnostics in document order so editors and snapshots see a stable list
+        sort_diagnostics(&mut diagnostics);
+        if let Some(token) = progress {
+            self.end_progress(token).await;
         }
 
         info!("Generated {} diagnostics for {}", diagnostics.len(), uri);
-        diagnostics
+        Some(diagnostics)
+    }
+
+    /// The document being validated, unless it moved past `version` in the meantime
+    fn document_at(
+        &self,
+        uri: &Url,
+        version: i32,
+    ) -> Option<dashmap::mapref::one::RefMut<'_, Url, SieveDocument>> {
+        self.document_map.get_mut(uri).filter(|document| document.version == version)
+    }
+
+    /// Give up on a document that changed or closed during validation
+    /// A newer version schedules a validation of its own, so nothing is reported for this one.
+    async fn abandon_validation(
+        &self,
+        uri: &Url,
+        progress: Option<ProgressToken>,
+    ) -> Option<Vec<Diagnostic>> {
+        debug!("Abandoning validation of {}, which changed meanwhile", uri);
+        if let Some(token) = progress {
+            self.end_progress(token).await;
+        }
+        None
+    }
+
+    /// Report malformed magic comments
+    fn check_directives(&self, diagnostics: &mut Vec<Diagnostic>, directives: &Directives) {
+        for (range, message) in &directives.problems {
+            warn!("{}", message);
+            diagnostics.push(Diagnostic {
+                range: *range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("invalid-directive".to_string())),
+                code_description: None,
+                source: Some("sieve-lsp".to_string()),
+                message: message.clone(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+
+    /// Run the line-based checks on the code of one line
+    /// Ranges of the results are relative to the line, see [`LineCheck`].
+    fn check_line(&self, line: &str, continues: bool, settings: &SieveSettings) -> LineCheck {
+        let mut check = LineCheck::default();
+        // Skip empty lines, including those that only held comments
+        if line.trim().is_empty() {
+            return check;
+        }
+        self.check_line_syntax(&mut check.diagnostics, 0, line, continues, settings);
+        if settings.semantic_analysis {
+            self.analyze_extensions(0, line, &mut check);
+        }
+        check
     }
 
     /// Check syntax errors for a single line
-    async fn check_line_syntax(
+    /// `continues` is set when the statement carries on in a `text:` block below
+    fn check_line_syntax(
         &self,
         diagnostics: &mut Vec<Diagnostic>,
         line_idx: usize,
         line: &str,
+        continues: bool,
         settings: &SieveSettings,
     ) {
         trace!("Checking syntax for line {}", line_idx);
         let trimmed = line.trim();
 
         // Check for missing semicolons on action statements
-        if self.is_action_line(trimmed) && !trimmed.ends_with(';') {
+        if self.is_action_line(trimmed) && !trimmed.ends_with(';') && !continues {
             error!("Missing semicolon after action statement");
             diagnostics.push(Diagnostic {
                 range: Range {
@@ -332,54 +1998,56 @@ impl SieveLanguageServer {
         if !settings.proton_extensions {
             let proton_commands = ["expire", "currentdate"];
             for cmd in &proton_commands {
-                if trimmed.contains(cmd) {
-                    if let Some(pos) = trimmed.find(cmd) {
-                        warn!("Invalid Proton extension syntax");
-                        diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position {
-                                    line: line_idx as u32,
-                                    character: pos as u32,
-                                },
-                                end: Position {
-                                    line: line_idx as u32,
-                                    character: (pos + cmd.len()) as u32,
-                                },
+                if let Some(pos) = trimmed.find(cmd) {
+                    warn!("Invalid Proton extension syntax");
+                    diagnostics.push(Diagnostic {
+                        range: Range {
+                            start: Position {
+                                line: line_idx as u32,
+                                character: pos as u32,
                             },
-                            severity: Some(DiagnosticSeverity::WARNING),
-                            code: Some(NumberOrString::String(
-                                "proton-extension-disabled".to_string(),
-                            )),
-                            code_description: Some(CodeDescription {
-                                href: Url::parse(
-                                    "https://proton.me/support/sieve-advanced-custom-filters",
-                                )
-                                .unwrap(),
-                            }),
-                            source: Some("sieve-lsp".to_string()),
-                            message: format!("Proton extension '{}' is disabled in settings", cmd),
-                            related_information: None,
-                            tags: None,
-                            data: None,
-                        });
-                    }
+                            end: Position {
+                                line: line_idx as u32,
+                                character: (pos + cmd.len()) as u32,
+                            },
+                        },
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String(
+                            "proton-extension-disabled".to_string(),
+                        )),
+                        code_description: Some(CodeDescription {
+                            href: Url::parse(
+                                "https://proton.me/support/sieve-advanced-custom-filters",
+                            )
+                            .unwrap(),
+                        }),
+                        source: Some("sieve-lsp".to_string()),
+                        message: format!("Proton extension '{}' is disabled in settings", cmd),
+                        related_information: None,
+                        tags: None,
+                        data: None,
+                    });
                 }
             }
         }
     }
 
     /// Analyze extension usage and requirements
-    async fn analyze_extensions(
-        &self,
-        _diagnostics: &mut Vec<Diagnostic>,
-        _line_idx: usize,
-        line: &str,
-        required_extensions: &mut Vec<String>,
-        used_extensions: &mut Vec<String>,
-        _settings: &SieveSettings,
-    ) {
+    fn analyze_extensions(&self, line_idx: usize, line: &str, check: &mut LineCheck) {
         trace!("Analyzing extension");
         let trimmed = line.trim();
+        // Columns of the trimmed line within the line
+        let indent = line.chars().count() - line.trim_start().chars().count();
+        let range_of = |bytes: std::ops::Range<usize>| Range {
+            start: Position::new(
+                line_idx as u32,
+                (indent + trimmed[..bytes.start].chars().count()) as u32,
+            ),
+            end: Position::new(
+                line_idx as u32,
+                (indent + trimmed[..bytes.end].chars().count()) as u32,
+            ),
+        };
 
         // Parse 'require' statements to track required extensions
         if trimmed.starts_with("require") {
@@ -387,47 +2055,56 @@ impl SieveLanguageServer {
             // Extract extensions from require statement
             // Examples: require "fileinto"; or require ["body", "regex"];
             if let Some(extensions) = self.parse_require_statement(trimmed) {
-                required_extensions.extend(extensions);
+                check.requires.extend(extensions);
+                check.require_range = Some(range_of(0..trimmed.len()));
             }
         }
 
         // Check if line uses extensions that should be required
         for (ext_name, _) in SIEVE_EXTENSIONS.iter() {
             trace!("Checking extension usage : {}", ext_name);
-            if self.line_uses_extension(trimmed, ext_name) {
-                if !used_extensions.contains(&ext_name.to_string()) {
-                    used_extensions.push(ext_name.to_string());
-                }
+            if let Some(usage) = self.extension_usage(trimmed, ext_name) {
+                check.usages.push((ext_name.to_string(), range_of(usage)));
             }
         }
     }
 
     /// Check that all used extensions are properly required
+    /// Missing requires are reported where the extension is first used, pointing to the
+    /// existing require statements.
     async fn check_extension_consistency(
         &self,
         diagnostics: &mut Vec<Diagnostic>,
+        uri: &Url,
         required_extensions: &[String],
-        used_extensions: &[String],
+        require_ranges: &[Range],
+        used_extensions: &[(String, Range)],
     ) {
         trace!("Checking extension consistency");
         // Find extensions that are used but not required
-        for used_ext in used_extensions {
+        for (used_ext, usage) in used_extensions {
             trace!("Checking extension usage : {}", used_ext);
-            if !required_extensions.contains(used_ext) {
-                // This would need line-specific information for proper positioning
-                // For now, we'll add a general diagnostic
+            // Any variant of a dialect family satisfies the requirement; mismatches are
+            // reported by the dialect check
+            let satisfied = required_extensions
+                .iter()
+                .any(|required| requires::satisfies(required, used_ext));
+            if !satisfied {
                 warn!("Extension {} is used but not required", used_ext);
+                let related_information = (!require_ranges.is_empty()).then(|| {
+                    require_ranges
+                        .iter()
+                        .map(|range| DiagnosticRelatedInformation {
+                            location: Location {
+                                uri: uri.clone(),
+                                range: *range,
+                            },
+                            message: "Extensions are required here".to_string(),
+                        })
+                        .collect()
+                });
                 diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                    },
+                    range: *usage,
                     severity: Some(DiagnosticSeverity::WARNING),
                     code: Some(NumberOrString::String("missing-require".to_string())),
                     code_description: Some(CodeDescription {
@@ -438,7 +2115,7 @@ impl SieveLanguageServer {
                     }),
                     source: Some("sieve-lsp".to_string()),
                     message: format!("Extension '{}' is used but not required", used_ext),
-                    related_information: None,
+                    related_information,
                     tags: None,
                     data: None,
                 });
@@ -446,6 +2123,37 @@ impl SieveLanguageServer {
         }
     }
 
+    /// Flag a script that is neither a deployed entry point nor included by one
+    async fn check_orphaned_script(&self, diagnostics: &mut Vec<Diagnostic>, uri: &Url) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let path = path.canonicalize().unwrap_or(path);
+        if !self.orphaned_scripts.read().await.contains(&path) {
+            return;
+        }
+
+        let message = format!(
+            "Script is not an entry point in {} and no entry script includes it",
+            MANIFEST_FILE
+        );
+        warn!("{}", message);
+        diagnostics.push(Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String("unused-script".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc6609#section-3.2")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
     /// Check if a line contains an action statement
     fn is_action_line(&self, line: &str) -> bool {
         SIEVE_ACTIONS
@@ -463,7 +2171,10 @@ impl SieveLanguageServer {
         }
 
         // Check for known Sieve constructs
-        let valid_starts = ["require", "if", "elsif", "else", "stop", "{", "}"];
+        // A leading ';' terminates a statement started on an earlier line (e.g. after text:)
+        let valid_starts = [
+            "require", "if", "elsif", "else", "stop", "foreverypart", "{", "}", ";",
+        ];
 
         // Check if line starts with valid keyword
         if valid_starts.iter().any(|start| trimmed.starts_with(start)) {
@@ -514,38 +2225,361 @@ impl SieveLanguageServer {
                     .map(|s| s.trim().trim_matches('"').to_string())
                     .collect();
                 Some(extensions)
-            } else if let Some(single_match) = captures.get(2) {
-                // Handle single string format: require "ext";
-                Some(vec![single_match.as_str().to_string()])
             } else {
-                None
+                // Handle single string format: require "ext";
+                captures
+                    .get(2)
+                    .map(|single_match| vec![single_match.as_str().to_string()])
             }
         } else {
             None
         }
     }
 
-    /// Check if a line uses a specific extension
-    fn line_uses_extension(&self, line: &str, extension: &str) -> bool {
+    /// Where a line uses a specific extension, as byte offsets into the line
+    fn extension_usage(&self, line: &str, extension: &str) -> Option<std::ops::Range<usize>> {
+        let find = |pattern: &str, length: usize| line.find(pattern).map(|at| at..at + length);
+        let prefix = |word: &str| line.starts_with(word).then_some(0..word.len());
         match extension {
-            "body" => line.contains("body "),
-            "regex" => line.contains(":regex"),
-            "fileinto" => line.contains("fileinto"),
-            "vacation" => line.contains("vacation"),
-            "copy" => line.contains(":copy"),
-            "date" => line.contains("date ") || line.contains("currentdate"),
-            "relational" => line.contains(":value") || line.contains(":count"),
-            "imap4flags" => {
-                line.contains("addflag") || line.contains("setflag") || line.contains("removeflag")
-            }
-            _ => false,
+            "body" => find("body ", 4),
+            "regex" => find(":regex", 6),
+            "fileinto" => find("fileinto", 8),
+            "vacation" => find("vacation", 8),
+            "copy" => find(":copy", 5),
+            "variables" => line
+                .starts_with("set ")
+                .then_some(0..3)
+                .or_else(|| find("string :", 6)),
+            "editheader" => prefix("addheader").or_else(|| prefix("deleteheader")),
+            "foreverypart" => prefix("foreverypart").or_else(|| prefix("break")),
+            "enotify" => prefix("notify")
+                .or_else(|| find("valid_notify_method", 19))
+                .or_else(|| find("notify_method_capability", 24)),
+            // vacation has a `:mime` of its own
+            "mime" => find(":mime", 5).filter(|_| !line.starts_with("vacation")),
+            "date" => find("date ", 4).or_else(|| find("currentdate", 11)),
+            "imap4flags" => find("addflag", 7)
+                .or_else(|| find("setflag", 7))
+                .or_else(|| find("removeflag", 10)),
+            _ => None,
+        }
+    }
+
+    /// Whether a position lies inside the body of a `text:` multi-line string
+    pub fn is_in_multiline_string(&self, uri: &Url, position: Position) -> bool {
+        self.document_map
+            .get(uri)
+            .is_some_and(|document| document.script().is_multiline_body_line(position.line))
+    }
+
+    /// Whether a position lies inside a `#` or `/* */` comment
+    pub fn is_in_comment(&self, uri: &Url, position: Position) -> bool {
+        self.document_map
+            .get(uri)
+            .is_some_and(|document| document.script().comment_at(position).is_some())
+    }
+
+    /// Folders `fileinto` completes: those of `mailbox.folders` and of the workspace's
+    /// `folders.txt`, with the levels of their hierarchy
+    pub async fn folder_names(&self) -> BTreeSet<String> {
+        let convention = self.settings.read().await.mailbox.clone();
+        let mut folders = convention.folders.clone();
+        if let Some(root) = self.workspace_root.read().await.as_ref()
+            && let Ok(content) = std::fs::read_to_string(root.join(mailbox::FOLDERS_FILE))
+        {
+            folders.extend(mailbox::parse_folder_list(&content));
         }
+        convention.folder_hierarchy(folders.iter().map(String::as_str))
     }
 
     /// Generate completion items for the current cursor position
-    pub async fn get_completions(&self, _uri: &Url, _position: Position) -> Vec<CompletionItem> {
+    pub async fn get_completions(&self, uri: &Url, position: Position) -> Vec<CompletionItem> {
         let mut completions = Vec::new();
+
+        // Message text inside text: blocks and comments get no Sieve completions
+        if self.is_in_multiline_string(uri, position) || self.is_in_comment(uri, position) {
+            return completions;
+        }
+
+        // Inside the quoted argument of a tag only the values of that tag make sense
+        let prefix = self.document_map.get(uri).and_then(|document| {
+            let line = document.get_line(position.line as usize)?;
+            Some(line.chars().take(position.character as usize).collect::<String>())
+        });
+        let values = match prefix.as_deref().and_then(quoted_argument_tag) {
+            Some(":comparator") => Some(("Sieve comparator", &*SIEVE_COMPARATORS)),
+            Some(":value") | Some(":count") => {
+                Some(("Relational operator", &*RELATIONAL_OPERATORS))
+            }
+            Some(":zone") => Some(("Time zone", &*TIME_ZONES)),
+            _ => None,
+        };
+        if let Some((kind, values)) = values {
+            for name in values.keys() {
+                completions.push(CompletionItem {
+                    label: name.to_string(),
+                    sort_text: Some(format!("1_{}", name)),
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    detail: Some(format!("{}: {}", kind, name)),
+                    data: Some(completion_data(kind, name)),
+                    insert_text: Some(name.to_string()),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                });
+            }
+            // Zone names are offered too but insert the offset, which is all :zone accepts
+            if kind == "Time zone" {
+                for (zone, offset) in sieve::NAMED_ZONES {
+                    completions.push(CompletionItem {
+                        label: zone.to_string(),
+                        sort_text: Some(format!("2_{}", zone)),
+                        kind: Some(CompletionItemKind::ENUM_MEMBER),
+                        detail: Some(format!("{}: {}", kind, offset)),
+                        data: Some(completion_data(kind, offset)),
+                        insert_text: Some(offset.to_string()),
+                        insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                        ..Default::default()
+                    });
+                }
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // After spamtest or virustest, offer the numeric comparisons RFC 5235 intends
+        if let Some((test, percent)) = prefix.as_deref().and_then(spam_test_arguments) {
+            let numeric = ":comparator \"i;ascii-numeric\"";
+            let patterns: &[(&str, &str, &str)] = match (test, percent) {
+                ("spamtest", false) => &[
+                    ("ge", "5", "Likely spam: a score of 5 or more"),
+                    ("eq", "0", "Not tested for spam"),
+                ],
+                ("spamtest", true) => &[
+                    ("ge", "50", "Likely spam: a score of 50% or more"),
+                    ("eq", "0", "Definitely not spam, or not tested"),
+                ],
+                _ => &[
+                    ("ge", "4", "Possibly or definitely infected"),
+                    ("eq", "0", "Not tested for viruses"),
+                ],
+            };
+            for (relation, score, description) in patterns {
+                let text = format!(":value \"{}\" {} \"{}\"", relation, numeric, score);
+                completions.push(CompletionItem {
+                    label: text.clone(),
+                    sort_text: Some(format!("1_{}_{}", relation, score)),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    detail: Some(description.to_string()),
+                    insert_text: Some(text),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                });
+            }
+            if test == "spamtest" && !percent {
+                let text = format!(":percent :value \"ge\" {} \"50\"", numeric);
+                completions.push(CompletionItem {
+                    label: text.clone(),
+                    sort_text: Some("2_percent".to_string()),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    detail: Some("Likely spam as a percentage (spamtestplus)".to_string()),
+                    insert_text: Some(text),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Inside the value of spamtest or virustest, offer the results the test reports
+        if let Some((test, percent)) = prefix.as_deref().and_then(spam_score_argument) {
+            let (kind, scores) = if test == "spamtest" {
+                ("Spam score", sieve::SPAMTEST_SCORES)
+            } else {
+                ("Virus score", sieve::VIRUSTEST_SCORES)
+            };
+            let scores: Vec<(String, Option<&str>)> = if percent {
+                (0..=100).step_by(10).map(|score| (score.to_string(), None)).collect()
+            } else {
+                scores.iter().map(|(score, d)| (score.to_string(), Some(*d))).collect()
+            };
+            for (score, description) in scores {
+                completions.push(CompletionItem {
+                    label: score.clone(),
+                    sort_text: Some(format!("1_{:03}", score.parse::<u32>().unwrap_or(0))),
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    detail: Some(match description {
+                        Some(description) => format!("{}: {}", kind, description),
+                        None => format!("{}: {}%", kind, score),
+                    }),
+                    data: description.map(|_| completion_data(kind, &score)),
+                    insert_text: Some(score),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Inside the envelope part list of an envelope test, offer the parts the dialect accepts
+        if prefix.as_deref().is_some_and(is_envelope_part_argument) {
+            let settings = self.settings.read().await;
+            let profile = profile::find(&settings.server_dialect);
+            for part in sieve::ENVELOPE_PARTS {
+                if part
+                    .capability
+                    .is_some_and(|c| profile.is_some_and(|p| !p.supports_extension(c)))
+                {
+                    continue;
+                }
+                completions.push(CompletionItem {
+                    label: part.name.to_string(),
+                    sort_text: Some(format!("1_{}", part.name)),
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    detail: Some(format!("Envelope part: {}", part.name)),
+                    data: Some(completion_data("Envelope part", part.name)),
+                    insert_text: Some(part.name.to_string()),
+                    insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Inside the flags of setflag, addflag, removeflag or :flags, offer the system flags
+        // and the configured keywords, escaped for the quoted string
+        if let Some(typed) = prefix.as_deref().and_then(flag_argument) {
+            let start = position.character - typed.chars().count() as u32;
+            let range = Range::new(Position::new(position.line, start), position);
+            let keywords = self.settings.read().await.keyword_flags.clone();
+            let flags = sieve::SYSTEM_FLAGS
+                .iter()
+                .map(|(flag, _)| (flag.to_string(), "IMAP system flag"))
+                .chain(keywords.into_iter().map(|flag| (flag, "IMAP keyword")));
+            for (flag, kind) in flags {
+                let quoted = refactor::quote_string(&flag);
+                let escaped = &quoted[1..quoted.len() - 1];
+                completions.push(CompletionItem {
+                    label: flag.clone(),
+                    sort_text: Some(format!("1_{}", flag)),
+                    kind: Some(CompletionItemKind::CONSTANT),
+                    detail: Some(format!("{}: {}", kind, flag)),
+                    filter_text: Some(escaped.to_string()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                        range,
+                        escaped.to_string(),
+                    ))),
+                    data: Some(completion_data(kind, &flag)),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Inside the header names of a header, exists or address test, offer common headers;
+        // address only parses headers holding addresses
+        if let Some((test, typed)) = prefix.as_deref().and_then(header_name_argument) {
+            let start = position.character - typed.chars().count() as u32;
+            let range = Range::new(Position::new(position.line, start), position);
+            for (name, _) in sieve::COMMON_HEADERS {
+                if test == "address"
+                    && !sieve::ADDRESS_HEADERS.contains(&name.to_lowercase().as_str())
+                {
+                    continue;
+                }
+                completions.push(CompletionItem {
+                    label: name.to_string(),
+                    sort_text: Some(format!("1_{}", name)),
+                    kind: Some(CompletionItemKind::FIELD),
+                    detail: Some(format!("Header field: {}", name)),
+                    filter_text: Some(name.to_string()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                        range,
+                        name.to_string(),
+                    ))),
+                    data: Some(completion_data("Header field", name)),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Inside the mailbox of a fileinto, offer the folders that exist on the server; the
+        // edit replaces what was typed so far, separators included
+        if let Some(typed) = prefix.as_deref().and_then(fileinto_mailbox_argument) {
+            let start = position.character - typed.chars().count() as u32;
+            let range = Range::new(Position::new(position.line, start), position);
+            for folder in self.folder_names().await {
+                completions.push(CompletionItem {
+                    label: folder.clone(),
+                    sort_text: Some(format!("1_{}", folder)),
+                    kind: Some(CompletionItemKind::FOLDER),
+                    detail: Some("Mailbox folder".to_string()),
+                    filter_text: Some(folder.clone()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, folder))),
+                    ..Default::default()
+                });
+            }
+            sort_completions(&mut completions);
+            return completions;
+        }
+
+        // Some commands only fit after an if/elsif block or inside a foreverypart loop
+        let (after_if, in_loop) = self
+            .document_map
+            .get(uri)
+            .zip(prefix.as_deref())
+            .map(|(document, prefix)| {
+                let typed = prefix
+                    .chars()
+                    .rev()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .count();
+                let context = document.script().context_at(Position {
+                    line: position.line,
+                    character: position.character - typed as u32,
+                });
+                (
+                    context.previous.is_some_and(|c| {
+                        matches!(c.name.as_str(), "if" | "elsif") && c.block.is_some()
+                    }),
+                    context.enclosing.iter().any(|c| c.name == "foreverypart"),
+                )
+            })
+            .unwrap_or_default();
+
         let settings = self.settings.read().await;
+        let profile = profile::find(&settings.server_dialect);
+        let unsupported = |feature: &str| {
+            profile.is_some_and(|p| {
+                p.is_unsupported(feature)
+                    || profile::vendor_of(feature).is_some_and(|(vendor, _)| vendor != p)
+            })
+        };
+
+        // Add control keyword completions
+        for keyword in documentation::CONTROL_KEYWORDS {
+            if SIEVE_ACTIONS.contains(&keyword.keyword)
+                || (matches!(keyword.keyword, "elsif" | "else") && !after_if)
+            {
+                continue;
+            }
+
+            completions.push(CompletionItem {
+                label: keyword.keyword.to_string(),
+                sort_text: Some(format!("2_{}", keyword.keyword)),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(format!("Sieve control: {}", keyword.keyword)),
+                data: Some(completion_data("Sieve control", keyword.keyword)),
+                insert_text: Some(keyword.keyword.to_string()),
+                insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+                ..Default::default()
+            });
+        }
 
         // Add test command completions
         for test in SIEVE_TESTS.iter() {
@@ -553,12 +2587,16 @@ impl SieveLanguageServer {
             if !settings.proton_extensions && ["currentdate"].contains(test) {
                 continue;
             }
+            if unsupported(test) {
+                continue;
+            }
 
             completions.push(CompletionItem {
                 label: test.to_string(),
+                sort_text: Some(format!("1_{}", test)),
                 kind: Some(CompletionItemKind::FUNCTION),
                 detail: Some(format!("Sieve test: {}", test)),
-                documentation: Some(Documentation::String(self.get_test_documentation(test))),
+                data: Some(completion_data("Sieve test", test)),
                 insert_text: Some(test.to_string()),
                 insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
                 ..Default::default()
@@ -571,12 +2609,16 @@ impl SieveLanguageServer {
             if !settings.proton_extensions && ["expire"].contains(action) {
                 continue;
             }
+            if unsupported(action) || (*action == "break" && !in_loop) {
+                continue;
+            }
 
             completions.push(CompletionItem {
                 label: action.to_string(),
+                sort_text: Some(format!("2_{}", action)),
                 kind: Some(CompletionItemKind::METHOD),
                 detail: Some(format!("Sieve action: {}", action)),
-                documentation: Some(Documentation::String(self.get_action_documentation(action))),
+                data: Some(completion_data("Sieve action", action)),
                 insert_text: Some(format!("{};", action)), // Auto-add semicolon for actions
                 insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
                 ..Default::default()
@@ -587,32 +2629,166 @@ impl SieveLanguageServer {
         for tag in SIEVE_TAGS.iter() {
             completions.push(CompletionItem {
                 label: tag.to_string(),
+                sort_text: Some(format!("3_{}", tag)),
                 kind: Some(CompletionItemKind::PROPERTY),
                 detail: Some(format!("Sieve tag: {}", tag)),
-                documentation: Some(Documentation::String(self.get_tag_documentation(tag))),
+                data: Some(completion_data("Sieve tag", tag)),
                 insert_text: Some(tag.to_string()),
                 insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
                 ..Default::default()
             });
         }
 
-        // Add extension completions for require statements
-        for (ext_name, ext_desc) in SIEVE_EXTENSIONS.iter() {
+        // Add extension completions for require statements, exactly those the server
+        // advertises when the client sent them
+        let advertised = self.advertised_capabilities(&settings).await;
+        let extensions: Vec<String> = match advertised {
+            Some(advertised) => advertised,
+            None => SIEVE_EXTENSIONS
+                .keys()
+                .filter(|name| profile.is_none_or(|p| p.supports_extension(name)))
+                .map(|name| name.to_string())
+                .collect(),
+        };
+        for ext_name in &extensions {
             completions.push(CompletionItem {
                 label: format!("\"{}\"", ext_name),
+                sort_text: Some(format!("4_{}", ext_name)),
                 kind: Some(CompletionItemKind::MODULE),
                 detail: Some(format!("Sieve extension: {}", ext_name)),
-                documentation: Some(Documentation::String(ext_desc.to_string())),
+                data: Some(completion_data("Sieve extension", ext_name)),
                 insert_text: Some(format!("\"{}\"", ext_name)),
                 insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
                 ..Default::default()
             });
         }
 
+        sort_completions(&mut completions);
+
         info!("Generated {} completion items", completions.len());
         completions
     }
 
+    /// Fill in the Markdown documentation of an item from `get_completions`
+    /// Only the item the user selects is resolved, so examples and links cost nothing while
+    /// the list is built. Items without our data are returned unchanged.
+    pub async fn resolve_completion(&self, mut item: CompletionItem) -> CompletionItem {
+        let Some((kind, name)) = item.data.as_ref().and_then(|data| {
+            Some((data.get("kind")?.as_str()?.to_string(), data.get("name")?.as_str()?.to_string()))
+        }) else {
+            return item;
+        };
+        let summary = match kind.as_str() {
+            "Sieve test" => Some(self.get_test_documentation(&name)),
+            "Sieve action" => Some(self.get_action_documentation(&name)),
+            "Sieve tag" => Some(self.get_tag_documentation(&name)),
+            "Sieve comparator" => SIEVE_COMPARATORS.get(name.as_str()).map(|d| d.to_string()),
+            "Relational operator" => RELATIONAL_OPERATORS.get(name.as_str()).map(|d| d.to_string()),
+            "Time zone" => TIME_ZONES.get(name.as_str()).map(|d| d.to_string()),
+            "Spam score" | "Virus score" => {
+                let scores = if kind == "Spam score" {
+                    sieve::SPAMTEST_SCORES
+                } else {
+                    sieve::VIRUSTEST_SCORES
+                };
+                scores
+                    .iter()
+                    .find(|(score, _)| *score == name)
+                    .map(|(_, description)| description.to_string())
+            }
+            "Envelope part" => sieve::envelope_part(&name).map(|part| part.description.to_string()),
+            "Header field" => sieve::common_header(&name).map(str::to_string),
+            "IMAP system flag" => sieve::SYSTEM_FLAGS
+                .iter()
+                .find(|(flag, _)| *flag == name)
+                .map(|(_, description)| description.to_string()),
+            _ => None,
+        };
+        let markdown = match kind.as_str() {
+            "Sieve control" => documentation::keyword(&name).map(|doc| doc.markdown()),
+            "Sieve extension" => {
+                let description = SIEVE_EXTENSIONS
+                    .get(name.as_str())
+                    .copied()
+                    .unwrap_or("Extension advertised by the server");
+                let mut doc = documentation::extension_markdown(&name, description);
+                // Extensions with draft and RFC variants name the one validation assumes
+                if let Some(note) = dialect::hover_note(&name, &self.dialects().await) {
+                    doc.push_str(&format!("\n\n{}", note));
+                }
+                Some(doc)
+            }
+            _ => summary.map(|summary| documentation::completion_markdown(&name, &kind, &summary)),
+        };
+        item.documentation = markdown.map(|value| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            })
+        });
+        item
+    }
+
+    /// Hover text for a number literal: its value with the quantifier applied
+    /// Returns the text and the range of the literal
+    pub fn get_number_documentation(
+        &self,
+        script: &Script,
+        position: Position,
+    ) -> Option<(String, Range)> {
+        let argument_lists = script
+            .all_commands()
+            .into_iter()
+            .map(|c| (c.name.as_str(), &c.arguments))
+            .chain(script.all_tests().into_iter().map(|t| (t.name.as_str(), &t.arguments)));
+        for (name, arguments) in argument_lists {
+            for argument in arguments {
+                let Argument::Number { raw, range } = argument else {
+                    continue;
+                };
+                if !parser::range_contains(range, position) {
+                    continue;
+                }
+                let unit = if name == "size" { " bytes" } else { "" };
+                let text = match parser::number_value(raw) {
+                    Some(value) => format!("{} = {}{}", raw, group_thousands(value), unit),
+                    None => format!("{} is not a valid number", raw),
+                };
+                return Some((text, *range));
+            }
+        }
+        None
+    }
+
+    /// Hover text for an envelope part named in an `envelope` test
+    /// Returns the text and the range of the string literal
+    pub fn get_envelope_part_documentation(
+        &self,
+        script: &Script,
+        position: Position,
+    ) -> Option<(String, Range)> {
+        script
+            .all_tests()
+            .into_iter()
+            .filter(|test| test.name == "envelope")
+            .filter_map(|test| test.positional_arguments().first().map(|a| a.strings()))
+            .flatten()
+            .find(|part| parser::range_contains(&part.range, position))
+            .and_then(|part| {
+                let info = sieve::envelope_part(&part.value)?;
+                let mut text = format!(
+                    "**{}**\n\n{}\n\nDefined in {}",
+                    info.name,
+                    info.description,
+                    info.capability.map_or("RFC 5228", |_| "RFC 6009")
+                );
+                if let Some(capability) = info.capability {
+                    text.push_str(&format!("\n\nRequires the \"{}\" extension", capability));
+                }
+                Some((text, part.range))
+            })
+    }
+
     /// Get documentation for a test command
     pub fn get_test_documentation(&self, test: &str) -> String {
         match test {
@@ -629,9 +2805,49 @@ impl SieveLanguageServer {
             "currentdate" => {
                 "Tests the current date/time on the server (Proton extension)".to_string()
             }
+            "hasexpiration" => {
+                "Tests whether the message has an expiration time (requires 'vnd.proton.expire' extension)"
+                    .to_string()
+            }
+            "expiration" => {
+                "Compares the remaining time until the message expires, in the given unit (requires 'vnd.proton.expire' extension)"
+                    .to_string()
+            }
             "regex" => {
                 "Provides regular expression matching (requires 'regex' extension)".to_string()
             }
+            "string" => {
+                "Compares strings after variable expansion (requires 'variables' extension)"
+                    .to_string()
+            }
+            "metadata" => {
+                "Tests a metadata entry of a mailbox (requires 'mboxmetadata' extension)"
+                    .to_string()
+            }
+            "metadataexists" => {
+                "Tests whether a mailbox has all the given metadata entries (requires 'mboxmetadata' extension)"
+                    .to_string()
+            }
+            "servermetadata" => {
+                "Tests a metadata entry of the server (requires 'servermetadata' extension)"
+                    .to_string()
+            }
+            "servermetadataexists" => {
+                "Tests whether the server has all the given metadata entries (requires 'servermetadata' extension)"
+                    .to_string()
+            }
+            "ihave" => {
+                "Tests whether the server supports all the given extensions; inside its block they need no 'require' (requires 'ihave' extension)"
+                    .to_string()
+            }
+            "valid_notify_method" => {
+                "Tests whether the server supports the given notification URIs (requires 'enotify' extension)"
+                    .to_string()
+            }
+            "notify_method_capability" => {
+                "Tests a capability of a notification method, such as whether the recipient is \"online\" (requires 'enotify' extension)"
+                    .to_string()
+            }
             _ => format!("Sieve test command: {}", test),
         }
     }
@@ -645,8 +2861,49 @@ impl SieveLanguageServer {
             "discard" => "Silently discards the message (no error sent)".to_string(),
             "keep" => "Keeps the message in the default location (usually INBOX)".to_string(),
             "stop" => "Stops processing the current script".to_string(),
+            "error" => {
+                "Fails the script with the given message, so the implicit keep takes place (requires 'ihave' extension)"
+                    .to_string()
+            }
+            "notify" => {
+                "Sends a notification to a URI such as \"mailto:user@example.com\" (requires 'enotify' extension)"
+                    .to_string()
+            }
+            "break" => {
+                "Leaves the enclosing 'foreverypart' loop, or the one named with ':name' (requires 'foreverypart' extension)"
+                    .to_string()
+            }
             "vacation" => "Sends an auto-reply message (requires 'vacation' extension)".to_string(),
             "expire" => "Sets message expiration time (Proton extension)".to_string(),
+            "unexpire" => {
+                "Removes the expiration time set by 'expire' (requires 'vnd.proton.expire' extension)"
+                    .to_string()
+            }
+            "set" => "Assigns a value to a variable (requires 'variables' extension)".to_string(),
+            "debug_log" => {
+                "Writes a message to the Sieve log for debugging (requires 'vnd.dovecot.debug' extension)"
+                    .to_string()
+            }
+            "pipe" => {
+                "Passes the message to a program configured by the administrator (requires 'vnd.dovecot.pipe' extension)"
+                    .to_string()
+            }
+            "filter" => {
+                "Replaces the message with the output of a program; as a test, whether that succeeded (requires 'vnd.dovecot.filter' extension)"
+                    .to_string()
+            }
+            "execute" => {
+                "Runs a program, optionally storing its output in a variable; as a test, whether it succeeded (requires 'vnd.dovecot.execute' extension)"
+                    .to_string()
+            }
+            "addheader" => {
+                "Adds a header field at the top, or with ':last' at the bottom (requires 'editheader' extension)"
+                    .to_string()
+            }
+            "deleteheader" => {
+                "Deletes header fields, optionally only those matching values (requires 'editheader' extension)"
+                    .to_string()
+            }
             _ => format!("Sieve action command: {}", action),
         }
     }
@@ -664,9 +2921,308 @@ impl SieveLanguageServer {
                 "Size comparison - tests if size is greater than specified value".to_string()
             }
             ":under" => "Size comparison - tests if size is less than specified value".to_string(),
+            ":percent" => {
+                "Reports the spam score as a percentage from 0 to 100 (requires 'spamtestplus')"
+                    .to_string()
+            }
+            ":user" => {
+                "Address part: the local part before the '+' separator, e.g. \"ken\" for ken+sieve@example.com (requires 'subaddress' extension)"
+                    .to_string()
+            }
+            ":detail" => {
+                "Address part: the local part after the '+' separator, e.g. \"sieve\" for ken+sieve@example.com (requires 'subaddress' extension)"
+                    .to_string()
+            }
             ":copy" => "Copy the message instead of moving it (preserves original)".to_string(),
             ":zone" => "Specifies timezone for date operations".to_string(),
+            ":comparator" => {
+                "Selects the comparator used for matching, e.g. \"i;ascii-numeric\"".to_string()
+            }
+            ":value" => {
+                "Relational comparison of values (requires 'relational' extension)".to_string()
+            }
+            ":count" => {
+                "Relational comparison of the number of values (requires 'relational' extension)"
+                    .to_string()
+            }
+            ":lower" => "Converts the value to lower case before assigning it".to_string(),
+            ":upper" => "Converts the value to upper case before assigning it".to_string(),
+            ":lowerfirst" => "Converts the first character of the value to lower case".to_string(),
+            ":upperfirst" => "Converts the first character of the value to upper case".to_string(),
+            ":quotewildcard" => {
+                "Escapes '*', '?' and '\\' so the value matches literally in ':matches'".to_string()
+            }
+            ":length" => "Assigns the length of the value in characters".to_string(),
+            ":index" => "Selects the n-th occurrence of a header field, counting from 1".to_string(),
+            ":last" => {
+                "Counts ':index' from the last occurrence, or adds a header at the end".to_string()
+            }
+            ":mime" => {
+                "Tests the MIME headers of the current body part (requires 'mime' extension)"
+                    .to_string()
+            }
+            ":anychild" => {
+                "Tests the children of the current body part instead of the part itself"
+                    .to_string()
+            }
+            ":type" => "Tests the type of a Content-Type header, e.g. \"text\"".to_string(),
+            ":subtype" => "Tests the subtype of a Content-Type header, e.g. \"plain\"".to_string(),
+            ":contenttype" => {
+                "Tests the type and subtype of a Content-Type header, e.g. \"text/plain\""
+                    .to_string()
+            }
+            ":param" => {
+                "Tests the named parameters of a MIME header, e.g. [\"filename\"]".to_string()
+            }
+            ":name" => "Names a 'foreverypart' loop so 'break' can leave it".to_string(),
+            ":try" => "Ignores failures of the program instead of failing the script".to_string(),
+            ":input" => "Feeds the given string to the program instead of the message".to_string(),
+            ":pipe" => "Feeds the message to the program".to_string(),
+            ":output" => "Stores the output of the program in the named variable".to_string(),
+            ":eval" => {
+                "Evaluates the value as an arithmetic expression before assigning it (requires 'vnd.proton.eval' extension)"
+                    .to_string()
+            }
+            ":importance" => {
+                "Importance of a notification: \"1\" (high), \"2\" (normal) or \"3\" (low)"
+                    .to_string()
+            }
+            ":from" => "Sender address of a notification or vacation response".to_string(),
+            ":options" => "Options specific to the notification method".to_string(),
+            ":message" => "Text of the notification; defaults to a summary of the message".to_string(),
             _ => format!("Sieve tag parameter: {}", tag),
         }
     }
 }
+
+/// Format a number with thousands separators, e.g. 10,485,760
+fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::new();
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// The tag whose quoted argument is being typed at the end of `prefix`
+/// e.g. `:comparator` for `if header :comparator "i;a`
+fn quoted_argument_tag(prefix: &str) -> Option<&str> {
+    lazy_static! {
+        static ref QUOTED_ARGUMENT: Regex = Regex::new(r#"(:[A-Za-z]+)\s+"[^"\\]*$"#).unwrap();
+    }
+    QUOTED_ARGUMENT
+        .captures(prefix)
+        .and_then(|captures| captures.get(1))
+        .map(|tag| tag.as_str())
+}
+
+/// The test and whether `:percent` was typed, when `prefix` ends right after the name of a
+/// `spamtest` or `virustest`, e.g. `if spamtest :percent `
+fn spam_test_arguments(prefix: &str) -> Option<(&str, bool)> {
+    lazy_static! {
+        static ref SPAM_TEST: Regex =
+            Regex::new(r"\b(spamtest|virustest)\s+(:percent\s+)?$").unwrap();
+    }
+    let captures = SPAM_TEST.captures(prefix)?;
+    Some((captures.get(1)?.as_str(), captures.get(2).is_some()))
+}
+
+/// The test and whether it has `:percent`, when `prefix` ends inside the quoted value of a
+/// `spamtest` or `virustest`, e.g. `if spamtest :value "ge" :comparator "i;ascii-numeric" "`
+fn spam_score_argument(prefix: &str) -> Option<(&str, bool)> {
+    lazy_static! {
+        static ref SPAM_SCORE: Regex = Regex::new(
+            r#"\b(spamtest|virustest)\s+((?::\w+\s+(?:"[^"]*"\s+)?)*)"[^"\\]*$"#
+        )
+        .unwrap();
+    }
+    let captures = SPAM_SCORE.captures(prefix)?;
+    Some((captures.get(1)?.as_str(), captures.get(2)?.as_str().contains(":percent")))
+}
+
+/// Whether the end of `prefix` is inside the quoted envelope part of an `envelope` test
+/// e.g. `if envelope :all ["to", "no`
+fn is_envelope_part_argument(prefix: &str) -> bool {
+    lazy_static! {
+        static ref ENVELOPE_PART: Regex = Regex::new(
+            r#"\benvelope\s+(?::(?:comparator|value|count)\s+"[^"]*"\s+|:\w+\s+)*\[?(?:\s*"[^"]*"\s*,)*\s*"[^"\\]*$"#
+        )
+        .unwrap();
+    }
+    ENVELOPE_PART.is_match(prefix)
+}
+
+/// What was typed of the flag under the cursor, when `prefix` ends inside the quoted flags of
+/// `setflag`, `addflag`, `removeflag` or a `:flags` tag, e.g. `\\Se` for `addflag "\\Seen \\Se`
+/// Flags in one string are separated by spaces (RFC 5232 section 3).
+fn flag_argument(prefix: &str) -> Option<&str> {
+    lazy_static! {
+        static ref FLAGS: Regex = Regex::new(
+            r#"(?:\b(?:setflag|addflag|removeflag)\s+(?:"(?:[^"\\]|\\.)*"\s+)?|:flags\s+)\[?(?:\s*"(?:[^"\\]|\\.)*"\s*,)*\s*"((?:[^"\\]|\\.)*\\?)$"#
+        )
+        .unwrap();
+    }
+    let typed = FLAGS.captures(prefix)?.get(1)?.as_str();
+    typed.rsplit(' ').next()
+}
+
+/// The test and what was typed of its header name, when `prefix` ends inside the quoted header
+/// names of a `header`, `exists` or `address` test, e.g. `("header", "X-Sp")` for
+/// `if header :contains ["From", "X-Sp`
+fn header_name_argument(prefix: &str) -> Option<(&str, &str)> {
+    lazy_static! {
+        static ref HEADER_NAME: Regex = Regex::new(
+            r#"\b(header|exists|address)\s+(?::(?:comparator|value|count|index|param)\s+(?:"[^"]*"|\[[^\]]*\]|\d+)\s+|:\w+\s+)*\[?(?:\s*"[^"]*"\s*,)*\s*"([^"\\]*)$"#
+        )
+        .unwrap();
+    }
+    let captures = HEADER_NAME.captures(prefix)?;
+    Some((captures.get(1)?.as_str(), captures.get(2)?.as_str()))
+}
+
+/// What was typed of the quoted mailbox of a `fileinto`, when `prefix` ends inside it
+/// e.g. `Lists/Ru` for `fileinto :copy "Lists/Ru`
+fn fileinto_mailbox_argument(prefix: &str) -> Option<&str> {
+    lazy_static! {
+        static ref MAILBOX: Regex = Regex::new(
+            r#"\bfileinto\s+(?::\w+\s+(?:"[^"]*"\s+|\[[^\]]*\]\s+)?)*"([^"\\]*)$"#
+        )
+        .unwrap();
+    }
+    MAILBOX
+        .captures(prefix)
+        .and_then(|captures| captures.get(1))
+        .map(|typed| typed.as_str())
+}
+
+/// Capabilities validation is limited to: the configured ones, else those `remote` advertises
+fn advertised_capabilities(
+    settings: &SieveSettings,
+    remote: Option<&[String]>,
+) -> Option<Vec<String>> {
+    match &settings.capabilities {
+        Some(configured) => Some(profile::parse_capabilities(configured)),
+        None => remote.map(profile::parse_capabilities),
+    }
+}
+
+/// A range of a [`LineCheck`], relative to its line, placed on `line`
+fn on_line(range: Range, line: u32) -> Range {
+    Range {
+        start: Position::new(range.start.line + line, range.start.character),
+        end: Position::new(range.end.line + line, range.end.character),
+    }
+}
+
+// ================================================================================================
+// SEVERITY OVERRIDES
+// ================================================================================================
+
+/// Report diagnostics with the severity configured for their code, dropping those turned off
+pub fn apply_severity(
+    diagnostics: &mut Vec<Diagnostic>,
+    severity: &BTreeMap<String, RuleSeverity>,
+) {
+    if severity.is_empty() {
+        return;
+    }
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        match severity.get(code).map(|rule| rule.lsp()) {
+            Some(Some(level)) => {
+                diagnostic.severity = Some(level);
+                true
+            }
+            Some(None) => false,
+            None => true,
+        }
+    });
+}
+
+// ================================================================================================
+// DETERMINISTIC ORDERING
+// ================================================================================================
+
+/// Sort diagnostics by position, then by code and message
+/// Every list returned to the client is ordered by a stable key so results don't jitter
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| {
+        let key = |d: &Diagnostic| {
+            (
+                d.range.start.line,
+                d.range.start.character,
+                d.range.end.line,
+                d.range.end.character,
+            )
+        };
+        let code = |d: &Diagnostic| match &d.code {
+            Some(NumberOrString::String(code)) => code.clone(),
+            Some(NumberOrString::Number(code)) => code.to_string(),
+            None => String::new(),
+        };
+        key(a)
+            .cmp(&key(b))
+            .then_with(|| code(a).cmp(&code(b)))
+            .then_with(|| a.message.cmp(&b.message))
+    });
+}
+
+/// Data of a completion item naming what it completes, so its documentation can be computed
+/// when the item is resolved
+fn completion_data(kind: &str, name: &str) -> Value {
+    serde_json::json!({ "kind": kind, "name": name })
+}
+
+/// Sort completion items by their sort text, falling back to the label
+pub fn sort_completions(completions: &mut [CompletionItem]) {
+    completions.sort_by(|a, b| {
+        let key = |c: &CompletionItem| c.sort_text.clone().unwrap_or_else(|| c.label.clone());
+        key(a).cmp(&key(b)).then_with(|| a.label.cmp(&b.label))
+    });
+}
+
+/// Sort the symbols of a document outline by position and name, at every level
+pub fn sort_document_symbols(symbols: &mut [DocumentSymbol]) {
+    symbols.sort_by(|a, b| {
+        (a.range.start, a.range.end, &a.name).cmp(&(b.range.start, b.range.end, &b.name))
+    });
+    for symbol in symbols {
+        if let Some(children) = &mut symbol.children {
+            sort_document_symbols(children);
+        }
+    }
+}
+
+/// Sort code actions by kind, then by the position of the diagnostic they fix and by title
+/// Quick fixes come first, in document order, whatever order the client sent diagnostics in.
+pub fn sort_code_actions(actions: &mut [CodeActionOrCommand]) {
+    fn key(action: &CodeActionOrCommand) -> (&str, Option<(Position, Position)>, &str) {
+        match action {
+            CodeActionOrCommand::CodeAction(action) => (
+                action.kind.as_ref().map_or("", |kind| kind.as_str()),
+                action
+                    .diagnostics
+                    .as_ref()
+                    .and_then(|diagnostics| diagnostics.first())
+                    .map(|diagnostic| (diagnostic.range.start, diagnostic.range.end)),
+                &action.title,
+            ),
+            CodeActionOrCommand::Command(command) => ("", None, &command.title),
+        }
+    }
+    actions.sort_by(|a, b| key(a).cmp(&key(b)));
+}
+
+/// Sort symbols by document, position and name
+pub fn sort_symbols(symbols: &mut [SymbolInformation]) {
+    fn key(s: &SymbolInformation) -> (&str, Position, &str) {
+        (s.location.uri.as_str(), s.location.range.start, &s.name)
+    }
+    symbols.sort_by(|a, b| key(a).cmp(&key(b)));
+}
@@ -0,0 +1,123 @@
+// ================================================================================================
+// DEEP ANALYSIS
+// ================================================================================================
+//
+// Checks that run a script through the simulator against the sample messages, too slow to
+// repeat on every keystroke. The server runs them when a document is saved (`deep_analysis`)
+// and shows their findings until the next edit. Tests the simulator cannot evaluate make a
+// rule's result partial, so such rules are never reported.
+
+use crate::interpreter::{self, Envelope, Outcome};
+use crate::message::Message;
+use crate::parser::{self, Script};
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+use tracing::debug;
+
+/// Findings of running a script against the sample messages
+pub fn analyze(script: &Script, messages: &[Message]) -> Vec<Diagnostic> {
+    let outcomes: Vec<Outcome> = messages
+        .iter()
+        .map(|message| interpreter::run(script, message, &Envelope::from_message(message)))
+        .collect();
+    let mut diagnostics = unmatched_rules(script, &outcomes);
+    diagnostics.extend(empty_destinations(script, &outcomes));
+    diagnostics
+}
+
+/// Rules reached by some sample message without any of them matching
+fn unmatched_rules(script: &Script, outcomes: &[Outcome]) -> Vec<Diagnostic> {
+    // Whether the test at a range matched for any message that evaluated it
+    let mut results: BTreeMap<(Position, Position), bool> = BTreeMap::new();
+    for outcome in outcomes {
+        for rule in &outcome.rules {
+            *results.entry((rule.range.start, rule.range.end)).or_default() |= rule.matched;
+        }
+    }
+    let partial = |range: &Range| {
+        outcomes.iter().any(|outcome| {
+            outcome
+                .unsupported
+                .iter()
+                .any(|u| parser::range_contains(range, u.range.start))
+        })
+    };
+
+    script
+        .all_commands()
+        .into_iter()
+        .filter(|command| matches!(command.name.as_str(), "if" | "elsif"))
+        .filter_map(|command| command.tests.first())
+        .filter(|test| results.get(&(test.range.start, test.range.end)) == Some(&false))
+        .filter(|test| !partial(&test.range))
+        .map(|test| {
+            let message = "No sample message matches this rule".to_string();
+            debug!("{}", message);
+            Diagnostic {
+                range: test.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("unmatched-rule".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.1")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            }
+        })
+        .collect()
+}
+
+/// `fileinto` and `redirect` whose destination expands to nothing for some sample messages
+fn empty_destinations(script: &Script, outcomes: &[Outcome]) -> Vec<Diagnostic> {
+    script
+        .all_commands()
+        .into_iter()
+        .filter(|command| matches!(command.name.as_str(), "fileinto" | "redirect"))
+        .filter_map(|command| {
+            let empty = outcomes
+                .iter()
+                .filter(|outcome| {
+                    outcome.actions.iter().any(|action| {
+                        action.range == command.range
+                            && action.arguments.last().is_some_and(|a| a.trim().is_empty())
+                    })
+                })
+                .count();
+            if empty == 0 {
+                return None;
+            }
+            let (message, href) = if command.name == "fileinto" {
+                (
+                    format!("Mailbox name expands to nothing for {} sample message(s)", empty),
+                    "https://datatracker.ietf.org/doc/html/rfc5228#section-4.1",
+                )
+            } else {
+                (
+                    format!(
+                        "Redirect address expands to nothing for {} sample message(s)",
+                        empty
+                    ),
+                    "https://datatracker.ietf.org/doc/html/rfc5228#section-4.2",
+                )
+            };
+            debug!("{}", message);
+            Some(Diagnostic {
+                range: command.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("empty-destination".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(href).unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,144 @@
+// ================================================================================================
+// EXTENSION DIALECTS
+// ================================================================================================
+//
+// Some extensions were deployed from drafts before they became RFCs and servers still implement
+// either variant: draft "notify" versus RFC 5435 "enotify", draft "imapflags" versus RFC 5232
+// "imap4flags", and two generations of the never-finished regex draft. The `dialects` setting
+// pins the variant of each family so validation matches what the server actually implements.
+
+use std::collections::BTreeMap;
+
+/// One implementation of an extension family
+#[derive(Debug, PartialEq, Eq)]
+pub struct Variant {
+    /// Value selecting this variant in the `dialects` setting
+    pub name: &'static str,
+    /// Capability string that has to be required
+    pub capability: &'static str,
+    /// Human readable name of the specification
+    pub description: &'static str,
+    /// Commands, tests and `command :tag` pairs that only this variant defines
+    pub features: &'static [&'static str],
+}
+
+/// An extension whose semantics depend on the specification the server follows
+#[derive(Debug, PartialEq, Eq)]
+pub struct Family {
+    /// Key in the `dialects` setting
+    pub name: &'static str,
+    /// Commands and tests belonging to the family, used to annotate hovers
+    pub members: &'static [&'static str],
+    /// Known variants; the first one is the default
+    pub variants: &'static [Variant],
+}
+
+/// Extension families with more than one specification in use
+pub const FAMILIES: &[Family] = &[
+    Family {
+        name: "notify",
+        members: &["notify", "denotify", "valid_notify_method", "notify_method_capability"],
+        variants: &[
+            Variant {
+                name: "rfc5435",
+                capability: "enotify",
+                description: "RFC 5435 (enotify)",
+                features: &[
+                    "valid_notify_method",
+                    "notify_method_capability",
+                    "notify :from",
+                    "notify :importance",
+                ],
+            },
+            Variant {
+                name: "draft",
+                capability: "notify",
+                description: "draft-martin-sieve-notify-01 (notify)",
+                features: &[
+                    "denotify",
+                    "notify :method",
+                    "notify :id",
+                    "notify :low",
+                    "notify :normal",
+                    "notify :high",
+                ],
+            },
+        ],
+    },
+    Family {
+        name: "imapflags",
+        members: &["setflag", "addflag", "removeflag", "hasflag", "mark", "unmark"],
+        variants: &[
+            Variant {
+                name: "rfc5232",
+                capability: "imap4flags",
+                description: "RFC 5232 (imap4flags)",
+                features: &["hasflag", "fileinto :flags", "keep :flags"],
+            },
+            Variant {
+                name: "draft",
+                capability: "imapflags",
+                description: "draft-melnikov-sieve-imapflags-03 (imapflags)",
+                features: &["mark", "unmark"],
+            },
+        ],
+    },
+    Family {
+        name: "regex",
+        members: &["regex", ":regex", ":quoteregex"],
+        variants: &[
+            Variant {
+                name: "ietf",
+                capability: "regex",
+                description: "draft-ietf-sieve-regex-01",
+                features: &["set :quoteregex"],
+            },
+            Variant {
+                name: "murchison",
+                capability: "regex",
+                description: "draft-murchison-sieve-regex-08",
+                features: &[],
+            },
+        ],
+    },
+];
+
+impl Family {
+    /// The variant selected in the settings, or the default for unknown or missing values
+    pub fn active(&self, dialects: &BTreeMap<String, String>) -> &'static Variant {
+        let variants: &'static [Variant] = self.variants;
+        dialects
+            .get(self.name)
+            .and_then(|selected| {
+                variants
+                    .iter()
+                    .find(|variant| variant.name.eq_ignore_ascii_case(selected))
+            })
+            .unwrap_or(&variants[0])
+    }
+}
+
+/// The family a capability, command or test belongs to
+pub fn family_of(word: &str) -> Option<&'static Family> {
+    FAMILIES.iter().find(|family| {
+        family.members.contains(&word)
+            || family.variants.iter().any(|variant| variant.capability == word)
+    })
+}
+
+/// The variant a feature such as `denotify` or `notify :method` is exclusive to
+pub fn variant_of_feature(feature: &str) -> Option<(&'static Family, &'static Variant)> {
+    FAMILIES.iter().find_map(|family| {
+        family
+            .variants
+            .iter()
+            .find(|variant| variant.features.contains(&feature))
+            .map(|variant| (family, variant))
+    })
+}
+
+/// Hover line naming the active variant of the family a word belongs to
+pub fn hover_note(word: &str, dialects: &BTreeMap<String, String>) -> Option<String> {
+    let family = family_of(word)?;
+    Some(format!("Active dialect: {}", family.active(dialects).description))
+}
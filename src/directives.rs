@@ -0,0 +1,228 @@
+// ================================================================================================
+// MAGIC COMMENTS
+// ================================================================================================
+//
+// Comments addressed to the server let a single script deviate from the settings:
+//
+//   # sieve-lsp: dialect=proton strict=true        settings for this script, before any statement
+//   # sieve-lsp-disable missing-require            suppress codes in the whole script
+//   # sieve-lsp-disable-next-line unreachable-code suppress codes on the following line
+//   keep; # sieve-lsp-disable-line                 suppress everything on this line
+//
+// Codes are separated by commas or spaces; without codes every diagnostic is suppressed.
+
+use crate::coexistence;
+use crate::parser::Script;
+use crate::profile;
+use serde_json::{Map, Value};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+
+/// Prefix shared by all directives
+pub const DIRECTIVE_PREFIX: &str = "sieve-lsp";
+
+/// Names accepted in `# sieve-lsp:` comments, with the setting each one overrides
+pub const DIRECTIVE_SETTINGS: &[(&str, &str)] = &[
+    ("dialect", "server_dialect"),
+    ("strict", "strict_mode"),
+    ("proton-extensions", "proton_extensions"),
+    ("semantic-analysis", "semantic_analysis"),
+    ("max-errors", "max_errors"),
+    ("max-line-length", "max_line_length"),
+    ("capabilities", "capabilities"),
+];
+
+/// Lines a suppression applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    File,
+    Line(u32),
+}
+
+/// Diagnostics suppressed by a `sieve-lsp-disable` comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suppression {
+    pub scope: Scope,
+    /// Suppressed codes; empty suppresses every diagnostic
+    pub codes: Vec<String>,
+}
+
+/// Everything the magic comments of a script ask for
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Directives {
+    /// Settings overridden for this script, by setting name
+    pub settings: Map<String, Value>,
+    pub suppressions: Vec<Suppression>,
+    /// Malformed directives, with the range of the offending word
+    pub problems: Vec<(Range, String)>,
+}
+
+impl Directives {
+    /// Whether a diagnostic is suppressed by a directive
+    pub fn suppresses(&self, diagnostic: &Diagnostic) -> bool {
+        let code = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => Some(code.as_str()),
+            _ => None,
+        };
+        self.suppressions.iter().any(|suppression| {
+            let in_scope = match suppression.scope {
+                Scope::File => true,
+                Scope::Line(line) => diagnostic.range.start.line == line,
+            };
+            in_scope
+                && (suppression.codes.is_empty()
+                    || code.is_some_and(|code| suppression.codes.iter().any(|c| c == code)))
+        })
+    }
+}
+
+/// Words of a comment with their character columns
+/// Commas separate words too when `commas` is set.
+fn words(text: &str, column: u32, commas: bool) -> Vec<(u32, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, (offset, c)) in text.char_indices().enumerate() {
+        let separator = c.is_whitespace() || (commas && c == ',');
+        match start {
+            Some((first, begin)) if separator => {
+                words.push((column + first, &text[begin..offset]));
+                start = None;
+            }
+            None if !separator => start = Some((index as u32, offset)),
+            _ => {}
+        }
+    }
+    if let Some((first, begin)) = start {
+        words.push((column + first, &text[begin..]));
+    }
+    words
+}
+
+/// The setting a directive value stands for, `None` when it is not valid for the setting
+fn setting_value(setting: &str, value: &str) -> Option<Value> {
+    match setting {
+        "server_dialect" => (value.eq_ignore_ascii_case(profile::GENERIC)
+            || profile::find(value).is_some())
+        .then(|| Value::String(value.to_ascii_lowercase())),
+        "strict_mode" | "proton_extensions" | "semantic_analysis" => {
+            value.parse::<bool>().ok().map(Value::Bool)
+        }
+        "max_errors" | "max_line_length" => value.parse::<u64>().ok().map(Value::from),
+        "capabilities" => Some(Value::Array(
+            value
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(|c| Value::String(c.to_string()))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Read the directives in the comments of a script
+pub fn parse(text: &str, script: &Script) -> Directives {
+    let lines: Vec<&str> = text.lines().collect();
+    let first_statement = script.commands.first().map(|c| c.range.start.line);
+    let mut directives = Directives::default();
+
+    for comment in script.comments.iter().filter(|c| !c.bracket) {
+        let line = comment.range.start.line;
+        let Some(content) = lines.get(line as usize).map(|l| {
+            let start = comment.range.start.character as usize;
+            l.char_indices().nth(start).map_or("", |(offset, _)| &l[offset..])
+        }) else {
+            continue;
+        };
+        let body = content.trim_start_matches('#');
+        let column = comment.range.start.character
+            + (content.len() - body.len()) as u32
+            + (body.chars().count() - body.trim_start().chars().count()) as u32;
+        let body = body.trim_start();
+        let Some(rest) = body.strip_prefix(DIRECTIVE_PREFIX) else {
+            continue;
+        };
+        let range_of = |start: u32, word: &str| Range {
+            start: Position::new(line, start),
+            end: Position::new(line, start + word.chars().count() as u32),
+        };
+
+        if let Some(settings) = rest.strip_prefix(':') {
+            let offset = column + DIRECTIVE_PREFIX.len() as u32 + 1;
+            let words = words(settings, offset, false);
+            if first_statement.is_some_and(|first| line >= first) {
+                directives.problems.push((
+                    comment.range,
+                    "Settings directives only apply before the first statement".to_string(),
+                ));
+                continue;
+            }
+            for (start, word) in words {
+                let range = range_of(start, word);
+                let Some((name, value)) = word.split_once('=') else {
+                    let message = format!("Expected 'name=value' in directive, found '{}'", word);
+                    directives.problems.push((range, message));
+                    continue;
+                };
+                let setting = DIRECTIVE_SETTINGS.iter().find(|(n, _)| *n == name);
+                let Some((_, setting)) = setting else {
+                    directives
+                        .problems
+                        .push((range, format!("Unknown setting '{}' in directive", name)));
+                    continue;
+                };
+                match setting_value(setting, value) {
+                    Some(value) => {
+                        directives.settings.insert(setting.to_string(), value);
+                    }
+                    None => directives.problems.push((
+                        range,
+                        format!("Invalid value '{}' for setting '{}'", value, name),
+                    )),
+                }
+            }
+            continue;
+        }
+
+        let Some(rest) = rest.strip_prefix('-') else {
+            continue;
+        };
+        let name_length = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (name, codes) = rest.split_at(name_length);
+        let scope = match name {
+            "disable" => Scope::File,
+            "disable-line" => Scope::Line(line),
+            "disable-next-line" => Scope::Line(line + 1),
+            _ => {
+                let directive = format!("{}-{}", DIRECTIVE_PREFIX, name);
+                let range = range_of(column, &directive);
+                directives
+                    .problems
+                    .push((range, format!("Unknown directive '{}'", directive)));
+                continue;
+            }
+        };
+        let offset = column + (DIRECTIVE_PREFIX.len() + 1 + name.chars().count()) as u32;
+        let mut suppression = Suppression {
+            scope,
+            codes: Vec::new(),
+        };
+        let words = words(codes, offset, true);
+        for &(start, code) in &words {
+            if coexistence::category_of(code).is_none()
+                && code != coexistence::EXTERNAL_LINTER_CODE
+                && code != coexistence::REMOTE_CHECK_CODE
+            {
+                directives.problems.push((
+                    range_of(start, code),
+                    format!("Unknown diagnostic code '{}'", code),
+                ));
+                continue;
+            }
+            suppression.codes.push(code.to_string());
+        }
+        // Only unknown codes must not turn into suppressing everything
+        if words.is_empty() || !suppression.codes.is_empty() {
+            directives.suppressions.push(suppression);
+        }
+    }
+    directives
+}
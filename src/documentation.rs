@@ -0,0 +1,516 @@
+// ================================================================================================
+// KEYWORD DOCUMENTATION
+// ================================================================================================
+//
+// Structured hover documentation for the words that shape a script rather than act on a
+// message: control commands and blocks. Besides what each one does, the entries explain the
+// order in which a script is evaluated, which is where most surprises in Sieve come from.
+// Every line is rendered on its own so hovers can be translated line by line.
+//
+// Tests, actions and tags get an example and a link to their specification, which completions
+// only render for the item the user selects.
+
+use crate::parser::{Command, Script};
+use tower_lsp::lsp_types::Position;
+
+/// Documentation of one control keyword
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeywordDoc {
+    pub keyword: &'static str,
+    /// Grammar of the construct, shown as a code block
+    pub syntax: &'static str,
+    pub summary: &'static str,
+    /// How the construct affects evaluation order, one paragraph each
+    pub notes: &'static [&'static str],
+    /// Section of RFC 5228 defining the construct
+    pub section: &'static str,
+}
+
+/// Control commands of RFC 5228 section 3
+pub const CONTROL_KEYWORDS: &[KeywordDoc] = &[
+    KeywordDoc {
+        keyword: "if",
+        syntax: "if <test> <block>",
+        summary: "Runs the block when the test is true.",
+        notes: &[
+            "Only the first branch of an 'if'/'elsif'/'else' chain whose test is true runs; the other branches are skipped.",
+            "'allof' stops evaluating at the first false test and 'anyof' at the first true one.",
+        ],
+        section: "3.1",
+    },
+    KeywordDoc {
+        keyword: "elsif",
+        syntax: "elsif <test> <block>",
+        summary: "Runs the block when the test is true and no earlier branch of the chain ran.",
+        notes: &[
+            "Its test is only evaluated when every earlier test of the chain was false.",
+        ],
+        section: "3.1",
+    },
+    KeywordDoc {
+        keyword: "else",
+        syntax: "else <block>",
+        summary: "Runs the block when no earlier branch of the chain ran.",
+        notes: &["'else' ends the chain and must directly follow an 'if' or 'elsif' block."],
+        section: "3.1",
+    },
+    KeywordDoc {
+        keyword: "require",
+        syntax: "require <capabilities: string-list>;",
+        summary: "Declares the extensions the script uses.",
+        notes: &[
+            "Requires must come before any other command; the whole script is rejected when the server lacks one of the extensions.",
+        ],
+        section: "3.2",
+    },
+    KeywordDoc {
+        keyword: "stop",
+        syntax: "stop;",
+        summary: "Ends the script.",
+        notes: &[
+            "Actions taken before 'stop' are still carried out.",
+            "Unless an action such as 'fileinto' or 'discard' cancelled it, the implicit keep files the message into the inbox.",
+        ],
+        section: "3.3",
+    },
+];
+
+/// Blocks delimited by braces
+pub const BLOCK: KeywordDoc = KeywordDoc {
+    keyword: "{ }",
+    syntax: "{ <commands> }",
+    summary: "Groups the commands run by 'if', 'elsif', 'else' or 'foreverypart'.",
+    notes: &[
+        "Commands in a block run in order, just like the top level of the script.",
+    ],
+    section: "8.2",
+};
+
+/// Documentation of a control keyword
+pub fn keyword(word: &str) -> Option<&'static KeywordDoc> {
+    CONTROL_KEYWORDS.iter().find(|doc| doc.keyword == word)
+}
+
+impl KeywordDoc {
+    /// Markdown shown on hover
+    pub fn markdown(&self) -> String {
+        let mut doc = format!(
+            "**{}** - control structure (RFC 5228)\n\n```sieve\n{}\n```\n\n{}\n",
+            self.keyword, self.syntax, self.summary
+        );
+        for note in self.notes {
+            doc.push('\n');
+            doc.push_str(note);
+            doc.push('\n');
+        }
+        doc.push_str(&format!("\n{}", rfc_link(5228, Some(self.section))));
+        doc
+    }
+}
+
+/// The command whose block opens or closes with the brace at a position
+pub fn block_owner(script: &Script, position: Position) -> Option<&Command> {
+    script.all_commands().into_iter().find(|command| {
+        command.block.as_ref().is_some_and(|block| {
+            let closing = Position {
+                line: block.range.end.line,
+                character: block.range.end.character.saturating_sub(1),
+            };
+            block.range.start == position || (block.range.end.character > 0 && closing == position)
+        })
+    })
+}
+
+/// Hover Markdown for the brace at a position, naming the command it belongs to
+pub fn block_markdown(script: &Script, position: Position) -> Option<String> {
+    let owner = block_owner(script, position)?;
+    Some(format!("Block of '{}'\n\n{}", owner.name, BLOCK.markdown()))
+}
+
+/// Example and specification of a test, action or tag, shown when a completion is resolved
+#[derive(Debug, PartialEq, Eq)]
+pub struct FeatureDoc {
+    pub name: &'static str,
+    /// A statement using the feature
+    pub example: &'static str,
+    /// RFC number and section defining the feature; vendor extensions have none
+    pub rfc: Option<(u32, &'static str)>,
+}
+
+/// Features with an example and a reference, tests and actions first, then tags
+pub const FEATURES: &[FeatureDoc] = &[
+    FeatureDoc {
+        name: "address",
+        example: "if address :domain \"from\" \"example.com\" { keep; }",
+        rfc: Some((5228, "5.1")),
+    },
+    FeatureDoc {
+        name: "allof",
+        example: "if allof (exists \"list-id\", header :contains \"subject\" \"[dev]\") { stop; }",
+        rfc: Some((5228, "5.2")),
+    },
+    FeatureDoc {
+        name: "anyof",
+        example: "if anyof (size :over 10M, exists \"x-spam-flag\") { discard; }",
+        rfc: Some((5228, "5.3")),
+    },
+    FeatureDoc {
+        name: "envelope",
+        example: "if envelope :all :is \"from\" \"bounce@example.com\" { discard; }",
+        rfc: Some((5228, "5.4")),
+    },
+    FeatureDoc {
+        name: "exists",
+        example: "if exists [\"list-id\", \"list-unsubscribe\"] { fileinto \"Lists\"; }",
+        rfc: Some((5228, "5.5")),
+    },
+    FeatureDoc {
+        name: "false",
+        example: "if false { discard; }",
+        rfc: Some((5228, "5.6")),
+    },
+    FeatureDoc {
+        name: "header",
+        example: "if header :contains \"subject\" \"invoice\" { fileinto \"Bills\"; }",
+        rfc: Some((5228, "5.7")),
+    },
+    FeatureDoc {
+        name: "not",
+        example: "if not exists \"date\" { discard; }",
+        rfc: Some((5228, "5.8")),
+    },
+    FeatureDoc {
+        name: "size",
+        example: "if size :over 5M { fileinto \"Large\"; }",
+        rfc: Some((5228, "5.9")),
+    },
+    FeatureDoc {
+        name: "true",
+        example: "if true { keep; }",
+        rfc: Some((5228, "5.10")),
+    },
+    FeatureDoc {
+        name: "body",
+        example: "if body :text :contains \"unsubscribe\" { fileinto \"Newsletters\"; }",
+        rfc: Some((5173, "5")),
+    },
+    FeatureDoc {
+        name: "date",
+        example: "if date :value \"ge\" \"date\" \"hour\" \"18\" { fileinto \"Evening\"; }",
+        rfc: Some((5260, "4")),
+    },
+    FeatureDoc {
+        name: "currentdate",
+        example: "if currentdate :value \"ge\" \"date\" \"2024-12-24\" { vacation \"Away\"; }",
+        rfc: Some((5260, "5")),
+    },
+    FeatureDoc {
+        name: "environment",
+        example: "if environment :is \"location\" \"MS\" { keep; }",
+        rfc: Some((5183, "4")),
+    },
+    FeatureDoc {
+        name: "mailboxexists",
+        example: "if mailboxexists \"Archive\" { fileinto \"Archive\"; }",
+        rfc: Some((5490, "3.1")),
+    },
+    FeatureDoc {
+        name: "metadata",
+        example: "if metadata \"INBOX\" \"/private/comment\" \"important\" { keep; }",
+        rfc: Some((5490, "4.1")),
+    },
+    FeatureDoc {
+        name: "metadataexists",
+        example: "if metadataexists \"Lists\" \"/shared/comment\" { fileinto \"Lists\"; }",
+        rfc: Some((5490, "4.2")),
+    },
+    FeatureDoc {
+        name: "servermetadata",
+        example: "if servermetadata :matches \"/shared/admin\" \"mailto:*\" { keep; }",
+        rfc: Some((5490, "5.1")),
+    },
+    FeatureDoc {
+        name: "servermetadataexists",
+        example: "if servermetadataexists \"/shared/admin\" { keep; }",
+        rfc: Some((5490, "5.2")),
+    },
+    FeatureDoc {
+        name: "ihave",
+        example: "if ihave \"regex\" { if header :regex \"subject\" \"^[0-9]+$\" { discard; } }",
+        rfc: Some((5463, "4")),
+    },
+    FeatureDoc {
+        name: "spamtest",
+        example: "if spamtest :value \"ge\" :comparator \"i;ascii-numeric\" \"6\" { discard; }",
+        rfc: Some((5235, "3.2")),
+    },
+    FeatureDoc {
+        name: "virustest",
+        example: "if virustest :value \"eq\" :comparator \"i;ascii-numeric\" \"5\" { discard; }",
+        rfc: Some((5235, "3.3")),
+    },
+    FeatureDoc {
+        name: "string",
+        example: "if string :is \"${folder}\" \"\" { keep; }",
+        rfc: Some((5229, "5")),
+    },
+    FeatureDoc {
+        name: "valid_notify_method",
+        example: "if valid_notify_method \"mailto:me@example.com\" { keep; }",
+        rfc: Some((5435, "4")),
+    },
+    FeatureDoc {
+        name: "notify_method_capability",
+        example: "if notify_method_capability \"xmpp:me@example.com\" \"online\" \"yes\" { keep; }",
+        rfc: Some((5435, "5")),
+    },
+    FeatureDoc {
+        name: "regex",
+        example: "if header :regex \"subject\" \"^\\\\[[a-z]+\\\\]\" { fileinto \"Lists\"; }",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: "hasexpiration",
+        example: "if hasexpiration { unexpire; }",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: "expiration",
+        example: "if expiration :comparator \"i;ascii-numeric\" \"lt\" \"day\" \"1\" { keep; }",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: "fileinto",
+        example: "fileinto \"Archive\";",
+        rfc: Some((5228, "4.1")),
+    },
+    FeatureDoc {
+        name: "redirect",
+        example: "redirect \"assistant@example.com\";",
+        rfc: Some((5228, "4.2")),
+    },
+    FeatureDoc {
+        name: "keep",
+        example: "keep;",
+        rfc: Some((5228, "4.3")),
+    },
+    FeatureDoc {
+        name: "discard",
+        example: "discard;",
+        rfc: Some((5228, "4.4")),
+    },
+    FeatureDoc {
+        name: "reject",
+        example: "reject \"This address no longer accepts mail.\";",
+        rfc: Some((5429, "2.2")),
+    },
+    FeatureDoc {
+        name: "break",
+        example: "foreverypart { if header :mime :type \"content-type\" \"image\" { break; } }",
+        rfc: Some((5703, "3")),
+    },
+    FeatureDoc {
+        name: "setflag",
+        example: "setflag \"\\\\Seen\";",
+        rfc: Some((5232, "3.1")),
+    },
+    FeatureDoc {
+        name: "addflag",
+        example: "addflag \"\\\\Flagged\";",
+        rfc: Some((5232, "3.2")),
+    },
+    FeatureDoc {
+        name: "removeflag",
+        example: "removeflag \"\\\\Seen\";",
+        rfc: Some((5232, "3.3")),
+    },
+    FeatureDoc {
+        name: "set",
+        example: "set :lower \"folder\" \"${1}\";",
+        rfc: Some((5229, "4")),
+    },
+    FeatureDoc {
+        name: "addheader",
+        example: "addheader \"X-Filtered\" \"yes\";",
+        rfc: Some((5293, "4")),
+    },
+    FeatureDoc {
+        name: "deleteheader",
+        example: "deleteheader \"X-Spam-Status\";",
+        rfc: Some((5293, "5")),
+    },
+    FeatureDoc {
+        name: "vacation",
+        example: "vacation :days 7 :subject \"Out of office\" \"I am away until Monday.\";",
+        rfc: Some((5230, "4")),
+    },
+    FeatureDoc {
+        name: "notify",
+        example: "notify :message \"New mail\" \"mailto:me@example.com\";",
+        rfc: Some((5435, "3")),
+    },
+    FeatureDoc {
+        name: "error",
+        example: "if not ihave \"vacation\" { error \"This script needs vacation\"; }",
+        rfc: Some((5463, "5")),
+    },
+    FeatureDoc {
+        name: "expire",
+        example: "expire \"day\" \"30\";",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: "unexpire",
+        example: "unexpire;",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: "debug_log",
+        example: "debug_log \"Filed into ${folder}\";",
+        rfc: None,
+    },
+    FeatureDoc {
+        name: ":is",
+        example: "if header :is \"x-priority\" \"1\" { keep; }",
+        rfc: Some((5228, "2.7.1")),
+    },
+    FeatureDoc {
+        name: ":contains",
+        example: "if header :contains \"subject\" \"invoice\" { keep; }",
+        rfc: Some((5228, "2.7.1")),
+    },
+    FeatureDoc {
+        name: ":matches",
+        example: "if header :matches \"subject\" \"[*] *\" { keep; }",
+        rfc: Some((5228, "2.7.1")),
+    },
+    FeatureDoc {
+        name: ":comparator",
+        example: "if header :comparator \"i;octet\" :is \"subject\" \"URGENT\" { keep; }",
+        rfc: Some((5228, "2.7.3")),
+    },
+    FeatureDoc {
+        name: ":localpart",
+        example: "if address :localpart \"to\" \"sales\" { keep; }",
+        rfc: Some((5228, "2.7.4")),
+    },
+    FeatureDoc {
+        name: ":domain",
+        example: "if address :domain \"from\" \"example.com\" { keep; }",
+        rfc: Some((5228, "2.7.4")),
+    },
+    FeatureDoc {
+        name: ":user",
+        example: "if envelope :user \"to\" \"ken\" { fileinto \"Personal\"; }",
+        rfc: Some((5233, "4")),
+    },
+    FeatureDoc {
+        name: ":detail",
+        example: "if address :detail \"to\" \"lists\" { fileinto \"Lists\"; }",
+        rfc: Some((5233, "4")),
+    },
+    FeatureDoc {
+        name: ":over",
+        example: "if size :over 1M { discard; }",
+        rfc: Some((5228, "5.9")),
+    },
+    FeatureDoc {
+        name: ":under",
+        example: "if size :under 100K { keep; }",
+        rfc: Some((5228, "5.9")),
+    },
+    FeatureDoc {
+        name: ":percent",
+        example: "if spamtest :percent :value \"ge\" :comparator \"i;ascii-numeric\" \"50\" { discard; }",
+        rfc: Some((5235, "3.2")),
+    },
+    FeatureDoc {
+        name: ":copy",
+        example: "redirect :copy \"backup@example.com\";",
+        rfc: Some((3894, "3")),
+    },
+    FeatureDoc {
+        name: ":create",
+        example: "fileinto :create \"Lists/New\";",
+        rfc: Some((5490, "3.2")),
+    },
+    FeatureDoc {
+        name: ":value",
+        example: "if header :value \"ge\" :comparator \"i;ascii-numeric\" \"x-score\" \"5\" { stop; }",
+        rfc: Some((5231, "4")),
+    },
+    FeatureDoc {
+        name: ":count",
+        example: "if header :count \"gt\" :comparator \"i;ascii-numeric\" \"received\" \"20\" { stop; }",
+        rfc: Some((5231, "4")),
+    },
+    FeatureDoc {
+        name: ":flags",
+        example: "fileinto :flags \"\\\\Seen\" \"Archive\";",
+        rfc: Some((5232, "5")),
+    },
+    FeatureDoc {
+        name: ":index",
+        example: "if header :index 1 :is \"received\" \"x\" { keep; }",
+        rfc: Some((5260, "6")),
+    },
+    FeatureDoc {
+        name: ":lower",
+        example: "set :lower \"name\" \"${1}\";",
+        rfc: Some((5229, "4.1")),
+    },
+    FeatureDoc {
+        name: ":upper",
+        example: "set :upper \"name\" \"${1}\";",
+        rfc: Some((5229, "4.1")),
+    },
+];
+
+/// Example and reference of a test, action or tag
+pub fn feature(name: &str) -> Option<&'static FeatureDoc> {
+    FEATURES.iter().find(|doc| doc.name == name)
+}
+
+/// Link to an RFC, or to one of its sections
+pub fn rfc_link(rfc: u32, section: Option<&str>) -> String {
+    match section {
+        Some(section) => format!(
+            "[RFC {} section {}](https://datatracker.ietf.org/doc/html/rfc{}#section-{})",
+            rfc, section, rfc, section
+        ),
+        None => format!("[RFC {}](https://datatracker.ietf.org/doc/html/rfc{})", rfc, rfc),
+    }
+}
+
+/// Markdown shown for a resolved completion: what the item is, its summary, and an example and
+/// specification link when there are any
+/// `kind` names what the item is, e.g. "Sieve test".
+pub fn completion_markdown(name: &str, kind: &str, summary: &str) -> String {
+    let mut doc = format!("**{}** - {}\n\n{}\n", name, kind, summary);
+    if let Some(feature) = feature(name) {
+        doc.push_str(&format!("\n```sieve\n{}\n```\n", feature.example));
+        if let Some((rfc, section)) = feature.rfc {
+            doc.push_str(&format!("\n{}", rfc_link(rfc, Some(section))));
+        }
+    }
+    doc
+}
+
+/// Markdown shown for a resolved extension completion, with the require statement loading it
+pub fn extension_markdown(name: &str, description: &str) -> String {
+    let mut doc = format!(
+        "**{}** - Sieve extension\n\n{}\n\n```sieve\nrequire \"{}\";\n```\n",
+        name, description, name
+    );
+    if let Some(rfc) = rfc_number(description) {
+        doc.push_str(&format!("\n{}", rfc_link(rfc, None)));
+    }
+    doc
+}
+
+/// Number of the RFC a description cites, e.g. 5173 for "Message body testing (RFC 5173)"
+fn rfc_number(description: &str) -> Option<u32> {
+    let start = description.find("RFC ")? + "RFC ".len();
+    let digits: String = description[start..].chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
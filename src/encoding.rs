@@ -0,0 +1,85 @@
+// ================================================================================================
+// POSITION ENCODING
+// ================================================================================================
+//
+// The syntax tree and all analysis work with character (Unicode scalar value) columns. Clients
+// count columns in the negotiated position encoding - UTF-16 code units unless both sides agree
+// on something else - so every position crossing the protocol boundary is converted here.
+
+use tower_lsp::lsp_types::{ClientCapabilities, PositionEncodingKind};
+
+/// How the client counts the `character` offset of a position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Bytes of the UTF-8 representation
+    Utf8,
+    /// UTF-16 code units, the protocol default
+    #[default]
+    Utf16,
+    /// Unicode scalar values, identical to the internal representation
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Pick the encoding announced in `general.positionEncodings`
+    /// UTF-8 is preferred when offered, then UTF-32, otherwise the mandatory UTF-16 is used
+    pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+        let offered = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref())
+            .unwrap_or_default();
+        if offered.contains(&PositionEncodingKind::UTF8) {
+            Self::Utf8
+        } else if offered.contains(&PositionEncodingKind::UTF32) {
+            Self::Utf32
+        } else {
+            Self::Utf16
+        }
+    }
+
+    /// The protocol value announced in the server capabilities
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    fn width(self, c: char) -> u32 {
+        match self {
+            Self::Utf8 => c.len_utf8() as u32,
+            Self::Utf16 => c.len_utf16() as u32,
+            Self::Utf32 => 1,
+        }
+    }
+
+    /// Convert a client column on `line` into a character column
+    /// Columns inside a multi-unit character snap to its start; columns past the end clamp
+    pub fn to_char_column(self, line: &str, column: u32) -> u32 {
+        let mut units = 0;
+        for (idx, c) in line_content(line).chars().enumerate() {
+            units += self.width(c);
+            if units > column {
+                return idx as u32;
+            }
+        }
+        line_content(line).chars().count() as u32
+    }
+
+    /// Convert a character column on `line` into a client column
+    /// Columns past the end of the line clamp to its length
+    pub fn from_char_column(self, line: &str, column: u32) -> u32 {
+        line_content(line)
+            .chars()
+            .take(column as usize)
+            .map(|c| self.width(c))
+            .sum()
+    }
+}
+
+/// A line without its line terminator
+fn line_content(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
@@ -0,0 +1,163 @@
+// ================================================================================================
+// INTERNAL ERROR REPORTING
+// ================================================================================================
+//
+// Failures inside the server rarely reach the user in a useful form: a panic during validation
+// just leaves stale diagnostics, and a broken configuration is silently ignored. The server keeps
+// the most recent ones in a ring buffer, which clients can query with `sieve/lastError`, and
+// `sieve.copyErrorReport` turns them into a Markdown report ready to paste into a GitHub issue.
+// Inputs are identified by their hash, so reports never carry a user's script unless it is
+// anonymized first.
+
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_lsp::lsp_types::Url;
+
+/// Custom request returning the most recent internal error, or `null`
+pub const LAST_ERROR_METHOD: &str = "sieve/lastError";
+
+/// Number of errors retained before the oldest ones are dropped
+pub const MAX_ERRORS: usize = 20;
+
+/// Where an internal error came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// A panic caught while analyzing a document
+    Panic,
+    /// Input the server could not parse, such as the client's configuration
+    Parse,
+    /// The external linter could not be run
+    External,
+    /// Reading or writing the server's files in the workspace failed
+    Io,
+}
+
+/// One recorded failure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Document being processed, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<Url>,
+    /// Hash of the failing input, see [`crate::snapshot::content_hash`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_hash: Option<u64>,
+}
+
+impl InternalError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            uri: None,
+            input_hash: None,
+        }
+    }
+
+    pub fn with_uri(mut self, uri: &Url) -> Self {
+        self.uri = Some(uri.clone());
+        self
+    }
+
+    pub fn with_input_hash(mut self, hash: u64) -> Self {
+        self.input_hash = Some(hash);
+        self
+    }
+}
+
+/// The most recent internal errors, oldest first
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    errors: VecDeque<InternalError>,
+}
+
+impl ErrorLog {
+    pub fn record(&mut self, error: InternalError) {
+        if self.errors.len() == MAX_ERRORS {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(error);
+    }
+
+    pub fn last(&self) -> Option<&InternalError> {
+        self.errors.back()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &InternalError> {
+        self.errors.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// The message of a caught panic
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Everything that goes into an error report
+pub struct ReportInput<'a> {
+    pub version: &'a str,
+    pub error: &'a InternalError,
+    /// Number of errors in the log, including the reported one
+    pub recorded: usize,
+    /// Current settings as JSON
+    pub settings: &'a str,
+    /// Anonymized text of the failing document, and whether it still matches the failing input
+    pub snippet: Option<(String, bool)>,
+}
+
+/// Markdown report for a GitHub issue
+pub fn report(input: &ReportInput) -> String {
+    let error = input.error;
+    let mut report = format!(
+        "### Internal error\n\n\
+         - Server version: {}\n\
+         - Kind: {}\n\
+         - Message: {}\n\
+         - Errors recorded this session: {}\n",
+        input.version,
+        serde_json::to_value(error.kind)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+        error.message,
+        input.recorded
+    );
+    if let Some(hash) = error.input_hash {
+        report.push_str(&format!("- Input hash: {:016x}\n", hash));
+    }
+    report.push_str(&format!(
+        "\n<details><summary>Settings</summary>\n\n```json\n{}\n```\n\n</details>\n",
+        input.settings
+    ));
+    if let Some((snippet, matches)) = &input.snippet {
+        report.push_str("\n#### Anonymized script\n\n");
+        if !matches {
+            report.push_str("The document changed after the error; it may no longer reproduce it.\n\n");
+        }
+        report.push_str(&format!("```sieve\n{}\n```\n", snippet.trim_end()));
+    }
+    report
+}
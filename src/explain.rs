@@ -0,0 +1,347 @@
+// ================================================================================================
+// SCRIPT EXPLANATION
+// ================================================================================================
+//
+// A machine-readable summary of what a script does, for generating documentation of a filter
+// set and for audit tooling. Served as the `sieve.explainScript` command.
+//
+// Rules are listed in the order they are evaluated. Nested rules are flattened, and the
+// condition of each rule is the full condition under which its actions run: an `elsif` or
+// `else` includes the negation of the branches before it, a nested rule the condition of its
+// enclosing block. Conditions are normalized so that equivalent tests read the same: default
+// match types, comparators and address parts are spelled out, header names are lowercase,
+// numbers have their quantifier applied, and nested `allof`/`anyof` lists, double negations
+// and constant tests are folded.
+
+use crate::parser::{self, Argument, Command, Script, Test};
+use crate::sieve::TAGS_WITH_VALUE;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tower_lsp::lsp_types::Range;
+
+/// Tests that compare values and so have a match type and a comparator
+const COMPARING_TESTS: &[&str] = &[
+    "address",
+    "body",
+    "currentdate",
+    "date",
+    "envelope",
+    "environment",
+    "hasflag",
+    "header",
+    "metadata",
+    "notify_method_capability",
+    "servermetadata",
+    "spamtest",
+    "string",
+    "virustest",
+];
+
+/// Tests whose first positional argument lists header names
+const HEADER_TESTS: &[&str] = &["address", "exists", "header"];
+
+/// Match types, with `:value` and `:count` taking a relation
+const MATCH_TYPES: &[&str] = &[":is", ":contains", ":matches", ":regex", ":value", ":count"];
+
+const ADDRESS_PARTS: &[&str] = &[":all", ":localpart", ":domain", ":user", ":detail"];
+
+/// What a script does, as returned by `sieve.explainScript`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptExplanation {
+    /// Capabilities the script requires, in the order they are required
+    pub requires: Vec<String>,
+    /// Rules in evaluation order
+    pub rules: Vec<ExplainedRule>,
+}
+
+impl ScriptExplanation {
+    /// Apply a function to every range, e.g. to convert them to the client's position encoding
+    pub fn map_ranges(&mut self, convert: impl Fn(Range) -> Range) {
+        for rule in &mut self.rules {
+            rule.range = convert(rule.range);
+            for action in &mut rule.actions {
+                action.range = convert(action.range);
+            }
+        }
+    }
+}
+
+/// Actions that run together under one condition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainedRule {
+    /// The `if`, `elsif` or `else` of the rule, or its actions outside of any conditional
+    pub range: Range,
+    pub condition: Condition,
+    /// Commands in execution order, `stop` included
+    pub actions: Vec<ExplainedAction>,
+}
+
+/// A command of a rule
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainedAction {
+    pub command: String,
+    /// Tags with their values, `true` for tags without one
+    pub tags: Map<String, Value>,
+    pub arguments: Vec<Value>,
+    pub range: Range,
+}
+
+/// A condition in normalized form
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Condition {
+    /// `true` and `false`, and conditions that fold into them
+    Constant(bool),
+    AllOf(Vec<Condition>),
+    AnyOf(Vec<Condition>),
+    Not(Box<Condition>),
+    Test(TestCondition),
+}
+
+/// A single test with its defaults spelled out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCondition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_type: Option<String>,
+    /// Relation of `:value` and `:count`, e.g. `gt`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_part: Option<String>,
+    /// Other tags with their values, `true` for tags without one
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub tags: Map<String, Value>,
+    pub arguments: Vec<Value>,
+}
+
+/// Summarize a script
+pub fn explain(script: &Script) -> ScriptExplanation {
+    let requires = script
+        .commands
+        .iter()
+        .filter(|command| command.name == "require")
+        .flat_map(|command| command.positional_arguments())
+        .flat_map(|argument| argument.strings())
+        .map(|capability| capability.decoded().into_owned())
+        .collect();
+    let mut rules = Vec::new();
+    explain_block(&script.commands, &Condition::Constant(true), &mut rules);
+    ScriptExplanation { requires, rules }
+}
+
+/// Add the rules of a block that runs under `condition`
+fn explain_block(commands: &[Command], condition: &Condition, rules: &mut Vec<ExplainedRule>) {
+    // Earlier branches of the current `if` chain
+    let mut previous: Vec<Condition> = Vec::new();
+    // Commands outside of conditionals, collected until the next conditional
+    let mut actions: Vec<ExplainedAction> = Vec::new();
+    let flush = |actions: &mut Vec<ExplainedAction>, rules: &mut Vec<ExplainedRule>| {
+        if let (Some(first), Some(last)) = (actions.first(), actions.last()) {
+            rules.push(ExplainedRule {
+                range: Range::new(first.range.start, last.range.end),
+                condition: condition.clone(),
+                actions: std::mem::take(actions),
+            });
+        }
+    };
+
+    for command in commands {
+        match command.name.as_str() {
+            "require" => {}
+            "if" | "elsif" | "else" => {
+                flush(&mut actions, rules);
+                if command.name == "if" {
+                    previous.clear();
+                }
+                let own = match command.tests.first() {
+                    Some(test) => normalize(test),
+                    None if command.name == "else" => Condition::Constant(true),
+                    None => continue,
+                };
+                let mut parts = vec![condition.clone()];
+                parts.extend(previous.iter().map(|p| Condition::Not(Box::new(p.clone()))));
+                parts.push(own.clone());
+                let branch = fold(Condition::AllOf(parts));
+                previous.push(own);
+
+                let Some(block) = &command.block else {
+                    continue;
+                };
+                let start = rules.len();
+                explain_block(&block.commands, &branch, rules);
+                // The first rule of the block stands for the branch itself
+                if let Some(rule) = rules.get_mut(start)
+                    && rule.condition == branch
+                {
+                    rule.range.start = command.range.start;
+                }
+            }
+            _ => actions.push(ExplainedAction {
+                command: command.name.clone(),
+                tags: tags(&command.arguments),
+                arguments: command
+                    .positional_arguments()
+                    .into_iter()
+                    .map(argument_value)
+                    .collect(),
+                range: command.range,
+            }),
+        }
+    }
+    flush(&mut actions, rules);
+}
+
+/// A test in normalized form
+pub fn normalize(test: &Test) -> Condition {
+    let condition = match test.name.as_str() {
+        "true" => Condition::Constant(true),
+        "false" => Condition::Constant(false),
+        "allof" => Condition::AllOf(test.tests.iter().map(normalize).collect()),
+        "anyof" => Condition::AnyOf(test.tests.iter().map(normalize).collect()),
+        "not" => match test.tests.first() {
+            Some(inner) => Condition::Not(Box::new(normalize(inner))),
+            None => Condition::Constant(true),
+        },
+        name => Condition::Test(normalize_test(name, test)),
+    };
+    fold(condition)
+}
+
+fn normalize_test(name: &str, test: &Test) -> TestCondition {
+    let mut tags = tags(&test.arguments);
+    let mut arguments: Vec<Value> =
+        test.positional_arguments().into_iter().map(argument_value).collect();
+    let mut take = |candidates: &[&str]| {
+        let tag = candidates.iter().find(|tag| tags.contains_key(**tag))?;
+        let value = tags.remove(*tag)?;
+        Some((tag.to_string(), value))
+    };
+
+    let comparing = COMPARING_TESTS.contains(&name);
+    let (match_type, relation) = match take(MATCH_TYPES) {
+        Some((tag, Value::String(relation))) => (Some(tag), Some(relation.to_lowercase())),
+        Some((tag, _)) => (Some(tag), None),
+        None => (comparing.then(|| ":is".to_string()), None),
+    };
+    let comparator = match take(&[":comparator"]) {
+        Some((_, Value::String(comparator))) => Some(comparator),
+        _ => comparing.then(|| "i;ascii-casemap".to_string()),
+    };
+    let address_part = match take(ADDRESS_PARTS) {
+        Some((tag, _)) => Some(tag),
+        None => matches!(name, "address" | "envelope").then(|| ":all".to_string()),
+    };
+
+    if (HEADER_TESTS.contains(&name) || name == "envelope")
+        && let Some(names) = arguments.first_mut()
+    {
+        lowercase(names);
+    }
+    TestCondition {
+        name: name.to_string(),
+        match_type,
+        relation,
+        comparator,
+        address_part,
+        tags,
+        arguments,
+    }
+}
+
+/// Lowercase a string or the strings of a list
+fn lowercase(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = s.to_lowercase(),
+        Value::Array(items) => items.iter_mut().for_each(lowercase),
+        _ => {}
+    }
+}
+
+/// Flatten nested lists of the same kind and fold negations and constants
+fn fold(condition: Condition) -> Condition {
+    match condition {
+        Condition::AllOf(parts) => {
+            let mut flat = Vec::new();
+            for part in parts.into_iter().map(fold) {
+                match part {
+                    Condition::Constant(true) => {}
+                    Condition::Constant(false) => return Condition::Constant(false),
+                    Condition::AllOf(inner) => flat.extend(inner),
+                    other => flat.push(other),
+                }
+            }
+            single_or(flat, Condition::AllOf, true)
+        }
+        Condition::AnyOf(parts) => {
+            let mut flat = Vec::new();
+            for part in parts.into_iter().map(fold) {
+                match part {
+                    Condition::Constant(false) => {}
+                    Condition::Constant(true) => return Condition::Constant(true),
+                    Condition::AnyOf(inner) => flat.extend(inner),
+                    other => flat.push(other),
+                }
+            }
+            single_or(flat, Condition::AnyOf, false)
+        }
+        Condition::Not(inner) => match fold(*inner) {
+            Condition::Constant(value) => Condition::Constant(!value),
+            Condition::Not(inner) => *inner,
+            other => Condition::Not(Box::new(other)),
+        },
+        other => other,
+    }
+}
+
+/// The only part of a list, the constant of an empty one, or the list itself
+fn single_or(
+    mut parts: Vec<Condition>,
+    list: fn(Vec<Condition>) -> Condition,
+    empty: bool,
+) -> Condition {
+    match parts.len() {
+        0 => Condition::Constant(empty),
+        1 => parts.remove(0),
+        _ => list(parts),
+    }
+}
+
+/// Tags of an argument list with their values, `true` for tags without one
+fn tags(arguments: &[Argument]) -> Map<String, Value> {
+    let mut tags = Map::new();
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        let Some(tag) = argument.tag() else {
+            continue;
+        };
+        let value = if TAGS_WITH_VALUE.contains(&tag) {
+            arguments.next().map(argument_value).unwrap_or(Value::Null)
+        } else {
+            Value::Bool(true)
+        };
+        tags.insert(tag.to_string(), value);
+    }
+    tags
+}
+
+/// An argument as JSON: strings decoded, numbers with their quantifier applied
+fn argument_value(argument: &Argument) -> Value {
+    match argument {
+        Argument::String(string) => Value::from(string.decoded().into_owned()),
+        Argument::StringList { items, .. } => {
+            items.iter().map(|item| Value::from(item.decoded().into_owned())).collect()
+        }
+        Argument::Number { raw, .. } => match parser::number_value(raw) {
+            Some(value) => Value::from(value),
+            None => Value::from(raw.clone()),
+        },
+        Argument::Tag { name, .. } => Value::from(name.clone()),
+    }
+}
@@ -0,0 +1,158 @@
+// ================================================================================================
+// EXTERNAL LINTER INTEGRATION
+// ================================================================================================
+//
+// Runs a third-party checker (e.g. Dovecot's `sievec -c` or a provider CLI) on saved files and
+// converts its output into LSP diagnostics using configurable regular expressions.
+
+use crate::coexistence::EXTERNAL_LINTER_CODE;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tower_lsp::lsp_types::*;
+use tracing::{debug, warn};
+
+/// Placeholder in linter arguments that is replaced by the path of the checked file
+pub const FILE_PLACEHOLDER: &str = "${file}";
+
+/// Configuration of an external checker
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ExternalLinterSettings {
+    /// Executable to run, e.g. "sievec"
+    pub command: String,
+
+    /// Arguments passed to the executable; `${file}` is replaced by the file path
+    #[serde(default = "default_args")]
+    pub args: Vec<String>,
+
+    /// Regular expressions applied to each output line
+    /// Named groups: `line` (required), `column`, `severity` and `message`
+    #[serde(default = "default_patterns")]
+    pub patterns: Vec<String>,
+
+    /// Source name attached to the produced diagnostics
+    #[serde(default = "default_source")]
+    pub source: String,
+
+    /// Maximum run time in milliseconds before the checker is abandoned
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_args() -> Vec<String> {
+    vec!["-c".to_string(), FILE_PLACEHOLDER.to_string()]
+}
+
+fn default_patterns() -> Vec<String> {
+    // Dovecot Pigeonhole: "script.sieve: line 3: error: unknown command 'fileinot'."
+    vec![r"^[^:]*: line (?P<line>\d+): (?P<severity>error|warning): (?P<message>.*)$".to_string()]
+}
+
+fn default_source() -> String {
+    "sieve-external".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Convert checker output into diagnostics
+/// Lines are 1-based in checker output and converted to 0-based LSP positions
+pub fn parse_linter_output(output: &str, settings: &ExternalLinterSettings) -> Vec<Diagnostic> {
+    let patterns: Vec<Regex> = settings
+        .patterns
+        .iter()
+        .filter_map(|p| match Regex::new(p) {
+            Ok(regex) => Some(regex),
+            Err(err) => {
+                warn!("Invalid external linter pattern {:?}: {}", p, err);
+                None
+            }
+        })
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    for line in output.lines() {
+        let Some(captures) = patterns.iter().find_map(|p| p.captures(line)) else {
+            continue;
+        };
+        let Some(line_number) = captures
+            .name("line")
+            .and_then(|m| m.as_str().parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let column = captures
+            .name("column")
+            .and_then(|m| m.as_str().parse::<u32>().ok())
+            .unwrap_or(1);
+        let severity = match captures
+            .name("severity")
+            .map(|m| m.as_str().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("warning") | Some("warn") => DiagnosticSeverity::WARNING,
+            Some("info") | Some("note") => DiagnosticSeverity::INFORMATION,
+            Some("hint") => DiagnosticSeverity::HINT,
+            _ => DiagnosticSeverity::ERROR,
+        };
+        let message = captures
+            .name("message")
+            .map(|m| m.as_str().trim().to_string())
+            .unwrap_or_else(|| line.trim().to_string());
+
+        let position = Position {
+            line: line_number.saturating_sub(1),
+            character: column.saturating_sub(1),
+        };
+        diagnostics.push(Diagnostic {
+            range: Range {
+                start: position,
+                end: Position {
+                    line: position.line,
+                    character: u32::MAX,
+                },
+            },
+            severity: Some(severity),
+            code: Some(NumberOrString::String(EXTERNAL_LINTER_CODE.to_string())),
+            code_description: None,
+            source: Some(settings.source.clone()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+    diagnostics
+}
+
+/// Run the external checker on a file and parse its stdout and stderr
+pub async fn run_external_linter(
+    settings: &ExternalLinterSettings,
+    path: &Path,
+) -> std::io::Result<Vec<Diagnostic>> {
+    let file = path.display().to_string();
+    let args: Vec<String> = settings
+        .args
+        .iter()
+        .map(|arg| arg.replace(FILE_PLACEHOLDER, &file))
+        .collect();
+    debug!("Running external linter: {} {:?}", settings.command, args);
+
+    let output = tokio::time::timeout(
+        Duration::from_millis(settings.timeout_ms),
+        Command::new(&settings.command)
+            .args(&args)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "external linter timed out"))??;
+
+    let mut combined = String::from_utf8_lossy(&output.stderr).into_owned();
+    combined.push('\n');
+    combined.push_str(&String::from_utf8_lossy(&output.stdout));
+    Ok(parse_linter_output(&combined, settings))
+}
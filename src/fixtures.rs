@@ -0,0 +1,165 @@
+// ================================================================================================
+// SCRIPT FIXTURES
+// ================================================================================================
+//
+// Regression tests for filter sets, run in CI with `sieve-lsp test DIR`. Every script below the
+// directory runs through the simulator against the sample messages stored next to it, and the
+// actions it takes are compared with the expected ones. As with the "Test rule" code lens,
+// `filter.eml` belongs to `filter.sieve`; further fixtures of the script are named
+// `filter.NAME.eml`. Each message has a YAML `.expected` file beside it:
+//
+//   # filter.newsletter.expected
+//   actions:
+//     - fileinto "Newsletters"
+//   implicit_keep: false
+//
+// Actions are written as the trace of `sieve/traceMessage` shows them. `implicit_keep` is only
+// checked when present.
+
+use crate::include;
+use crate::interpreter::{self, Envelope, Outcome};
+use crate::message::Message;
+use crate::parser;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+/// Expected result of running a script against one message, the content of a `.expected` file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    /// Actions in execution order, e.g. `fileinto :copy "Lists"`
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// Whether the implicit keep files the message into the inbox
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub implicit_keep: Option<bool>,
+}
+
+impl Expectation {
+    /// What a run did, in the form of an expectation
+    pub fn from_outcome(outcome: &Outcome) -> Self {
+        Self {
+            actions: outcome.actions.iter().map(|action| action.describe()).collect(),
+            implicit_keep: Some(outcome.implicit_keep),
+        }
+    }
+
+    /// Whether the actual result of a run meets the expectation
+    pub fn is_met_by(&self, actual: &Expectation) -> bool {
+        self.actions == actual.actions
+            && self.implicit_keep.is_none_or(|keep| actual.implicit_keep == Some(keep))
+    }
+}
+
+/// A script and one of its sample messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub script: PathBuf,
+    pub message: PathBuf,
+    /// The `.expected` file beside the message
+    pub expected: PathBuf,
+}
+
+/// How a script fared against one fixture
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixtureResult {
+    Passed,
+    Failed {
+        expected: Expectation,
+        actual: Expectation,
+    },
+    /// The fixture could not be run, e.g. without a `.expected` file
+    Error(String),
+}
+
+/// All scripts below a directory paired with their messages, sorted by path
+/// `a.b.eml` belongs to `a.b.sieve` when there is one, and to `a.sieve` otherwise.
+pub fn discover(root: &Path) -> Vec<Fixture> {
+    let mut fixtures = Vec::new();
+    for script in include::workspace_scripts(root) {
+        let (Some(dir), Some(stem)) = (script.parent(), script.file_stem()) else {
+            continue;
+        };
+        let stem = stem.to_string_lossy();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut messages: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+            .filter(|path| {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                owning_script(dir, &name).is_some_and(|owner| owner == stem)
+            })
+            .collect();
+        messages.sort();
+        fixtures.extend(messages.into_iter().map(|message| Fixture {
+            script: script.clone(),
+            expected: message.with_extension("expected"),
+            message,
+        }));
+    }
+    fixtures
+}
+
+/// The script a message belongs to: the longest part of its name up to a dot that names a
+/// script in the same directory
+fn owning_script<'a>(dir: &Path, message: &'a str) -> Option<&'a str> {
+    let mut name = message;
+    loop {
+        if dir.join(format!("{}.sieve", name)).is_file() {
+            return Some(name);
+        }
+        name = &name[..name.rfind('.')?];
+    }
+}
+
+/// Run the script of a fixture against its message and compare the result
+pub fn run(fixture: &Fixture) -> FixtureResult {
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))
+    };
+    let (text, raw, expected) = match (
+        read(&fixture.script),
+        read(&fixture.message),
+        read(&fixture.expected),
+    ) {
+        (Ok(text), Ok(raw), Ok(expected)) => (text, raw, expected),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+            return FixtureResult::Error(err);
+        }
+    };
+    let expected: Expectation = match serde_yaml::from_str(&expected) {
+        Ok(expected) => expected,
+        Err(err) => {
+            return FixtureResult::Error(format!(
+                "Invalid expectations in {}: {}",
+                fixture.expected.display(),
+                err
+            ));
+        }
+    };
+
+    let script = parser::parse(&text);
+    let first_error = script.errors.iter().find(|e| e.severity == DiagnosticSeverity::ERROR);
+    if let Some(error) = first_error {
+        return FixtureResult::Error(format!(
+            "{}:{}: {}",
+            fixture.script.display(),
+            error.range.start.line + 1,
+            error.message
+        ));
+    }
+    let message = Message::parse(&raw);
+    let outcome = interpreter::run(&script, &message, &Envelope::from_message(&message));
+    let actual = Expectation::from_outcome(&outcome);
+    if expected.is_met_by(&actual) {
+        FixtureResult::Passed
+    } else {
+        FixtureResult::Failed { expected, actual }
+    }
+}
@@ -0,0 +1,263 @@
+// ================================================================================================
+// FORMATTER
+// ================================================================================================
+//
+// Layout-only formatting shared by `textDocument/formatting` and `sieve-lsp fmt`. Lines are
+// re-indented by their nesting in blocks, test lists and string lists, with wrapped arguments
+// one level deeper than their statement. Trailing whitespace is trimmed, runs of blank lines
+// are collapsed and the file ends with exactly one newline. Nothing inside a line is rewritten,
+// and the bodies of `text:` strings and bracket comments are left exactly as they are, so
+// formatting never changes what a script does.
+
+use crate::parser::{self, TokenKind};
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{FormattingOptions, Position, Range, TextEdit};
+
+/// Layout preferences
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    /// Text of one indentation level
+    pub indent: String,
+    /// Most consecutive blank lines kept
+    pub max_blank_lines: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent: crate::refactor::INDENT.to_string(),
+            max_blank_lines: 1,
+        }
+    }
+}
+
+/// Settings under `format`
+/// Unset values follow the options the editor sends with each formatting request.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatSettings {
+    /// Spaces per indentation level
+    #[serde(default)]
+    pub tab_size: Option<u32>,
+    /// Indent with spaces rather than tabs
+    #[serde(default)]
+    pub insert_spaces: Option<bool>,
+    /// Most consecutive blank lines kept
+    #[serde(default)]
+    pub max_blank_lines: Option<usize>,
+    /// Format documents whenever they are saved
+    #[serde(default)]
+    pub on_save: bool,
+}
+
+impl FormatSettings {
+    /// Layout preferences for a request with the editor's options, or for the CLI without
+    pub fn options(&self, client: Option<&FormattingOptions>) -> FormatOptions {
+        let defaults = FormatOptions::default();
+        let tab_size = self
+            .tab_size
+            .or(client.map(|options| options.tab_size))
+            .unwrap_or(defaults.indent.len() as u32);
+        let insert_spaces = self
+            .insert_spaces
+            .or(client.map(|options| options.insert_spaces))
+            .unwrap_or(true);
+        FormatOptions {
+            indent: if insert_spaces {
+                " ".repeat(tab_size as usize)
+            } else {
+                "\t".to_string()
+            },
+            max_blank_lines: self.max_blank_lines.unwrap_or(defaults.max_blank_lines),
+        }
+    }
+}
+
+/// How a line is laid out
+#[derive(Debug, Clone, Copy, Default)]
+struct LineLayout {
+    /// Indentation levels
+    depth: usize,
+    /// Inside a `text:` body, a quoted string or a bracket comment: kept as it is
+    verbatim: bool,
+    /// A quoted string continues on the next line, so trailing whitespace is content
+    keep_trailing: bool,
+}
+
+/// Indentation and protected regions of every line
+fn layout(text: &str, line_count: usize) -> Vec<LineLayout> {
+    let (tokens, comments, _) = parser::tokenize(text);
+    let mut lines = vec![LineLayout::default(); line_count];
+
+    // Tokens and comments spanning lines protect everything after their first line
+    let spans = tokens
+        .iter()
+        .map(|t| (t.range, matches!(t.kind, TokenKind::String { multiline: false, .. })))
+        .chain(comments.iter().filter(|c| c.bracket).map(|c| (c.range, true)));
+    for (range, quoted) in spans {
+        let (start, end) = (range.start.line as usize, range.end.line as usize);
+        if end <= start {
+            continue;
+        }
+        if quoted && let Some(line) = lines.get_mut(start) {
+            line.keep_trailing = true;
+        }
+        for line in lines.iter_mut().take(end + 1).skip(start + 1) {
+            line.verbatim = true;
+        }
+    }
+
+    // Nesting at the start of each line, from the tokens before it
+    let mut braces = 0usize;
+    let mut lists = 0usize;
+    let mut open_statement = false;
+    let mut tokens = tokens.iter().peekable();
+    for (index, line) in lines.iter_mut().enumerate() {
+        let (depth_braces, depth_lists, continued) = (braces, lists, open_statement);
+        // Closers at the start of a line sit at the depth of their openers
+        let mut leading_closers = 0;
+        let mut leading = true;
+        let mut starts_statement_part = false;
+        let mut first = true;
+        while let Some(token) = tokens.next_if(|t| t.range.start.line as usize <= index) {
+            if first && token.range.start.line as usize == index {
+                starts_statement_part = matches!(
+                    token.kind,
+                    TokenKind::LeftBrace | TokenKind::RightBrace | TokenKind::Semicolon
+                );
+            }
+            first = false;
+            match token.kind {
+                TokenKind::LeftBrace => braces += 1,
+                TokenKind::RightBrace => braces = braces.saturating_sub(1),
+                TokenKind::LeftParen | TokenKind::LeftBracket => lists += 1,
+                TokenKind::RightParen | TokenKind::RightBracket => lists = lists.saturating_sub(1),
+                _ => {}
+            }
+            let closer = matches!(
+                token.kind,
+                TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket
+            );
+            if leading && closer && token.range.start.line as usize == index {
+                leading_closers += 1;
+            } else {
+                leading = false;
+            }
+            open_statement = !matches!(
+                token.kind,
+                TokenKind::Semicolon | TokenKind::LeftBrace | TokenKind::RightBrace
+            );
+        }
+        // Wrapped arguments of a statement sit one level deeper, unless a list already does so
+        let continuation = usize::from(continued && depth_lists == 0 && !starts_statement_part);
+        line.depth = (depth_braces + depth_lists + continuation).saturating_sub(leading_closers);
+    }
+    lines
+}
+
+/// The formatted form of each line, `None` for blank lines that are dropped
+fn formatted_lines(text: &str, options: &FormatOptions) -> Vec<Option<String>> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let layouts = layout(text, lines.len());
+    let mut formatted = Vec::with_capacity(lines.len());
+    let mut blank_run = 0;
+    for (line, layout) in lines.iter().zip(layouts) {
+        let (content, cr) = match line.strip_suffix('\r') {
+            Some(content) => (content, "\r"),
+            None => (*line, ""),
+        };
+        if layout.verbatim {
+            blank_run = 0;
+            formatted.push(Some(line.to_string()));
+            continue;
+        }
+        let trimmed = if layout.keep_trailing {
+            content.trim_start()
+        } else {
+            content.trim()
+        };
+        if trimmed.is_empty() {
+            blank_run += 1;
+            let leading = formatted.iter().all(Option::is_none);
+            if leading || blank_run > options.max_blank_lines {
+                formatted.push(None);
+            } else {
+                formatted.push(Some(cr.to_string()));
+            }
+            continue;
+        }
+        blank_run = 0;
+        formatted.push(Some(format!(
+            "{}{}{}",
+            options.indent.repeat(layout.depth),
+            trimmed,
+            cr
+        )));
+    }
+
+    // Exactly one newline at the end: the empty piece after the last one is kept, blank lines
+    // before it are dropped
+    let last = formatted.len() - 1;
+    for line in formatted[..last].iter_mut().rev() {
+        match line {
+            Some(content) if content.trim().is_empty() => *line = None,
+            None => {}
+            Some(_) => break,
+        }
+    }
+    if lines[last].trim().is_empty() {
+        formatted[last] = Some(String::new());
+    }
+    formatted
+}
+
+/// The formatted text of a script
+pub fn format(text: &str, options: &FormatOptions) -> String {
+    let formatted = formatted_lines(text, options);
+    let mut result: Vec<&str> = formatted.iter().flatten().map(String::as_str).collect();
+    if result.last().is_some_and(|last| !last.is_empty()) {
+        result.push("");
+    }
+    let result = result.join("\n");
+    if result.trim().is_empty() {
+        return String::new();
+    }
+    result
+}
+
+/// Edits turning a script into its formatted form, one per changed line
+/// Positions count characters.
+pub fn edits(text: &str, options: &FormatOptions) -> Vec<TextEdit> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let formatted = formatted_lines(text, options);
+    let mut edits = Vec::new();
+    for (index, (line, new)) in lines.iter().zip(&formatted).enumerate() {
+        let index = index as u32;
+        match new {
+            Some(new) if new == line => {}
+            Some(new) => edits.push(TextEdit {
+                range: Range {
+                    start: Position::new(index, 0),
+                    end: Position::new(index, line.chars().count() as u32),
+                },
+                new_text: new.clone(),
+            }),
+            None => edits.push(TextEdit {
+                range: Range {
+                    start: Position::new(index, 0),
+                    end: Position::new(index + 1, 0),
+                },
+                new_text: String::new(),
+            }),
+        }
+    }
+    if lines.last().is_some_and(|last| !last.trim().is_empty()) {
+        let index = (lines.len() - 1) as u32;
+        let end = Position::new(index, lines[lines.len() - 1].chars().count() as u32);
+        edits.push(TextEdit {
+            range: Range { start: end, end },
+            new_text: "\n".to_string(),
+        });
+    }
+    edits
+}
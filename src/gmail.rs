@@ -0,0 +1,321 @@
+// ================================================================================================
+// GMAIL FILTER IMPORT
+// ================================================================================================
+//
+// Converts the XML file Gmail exports its filters to ("Settings > Filters > Export") into a
+// Sieve script, one rule per filter. Served as the `sieve.importGmailFilters` command and as
+// `sieve-lsp import-gmail FILE`.
+//
+// Gmail criteria are search queries. Plain words, quoted phrases, `OR` and `{...}` alternatives
+// and `list:` translate; other search operators do not, and a filter with such a criterion is
+// left out as a comment instead of being converted with a broader condition. Labels become
+// `fileinto :copy`, or a plain `fileinto` when the filter also archives. Actions without a
+// Sieve equivalent, like "Never send it to Spam", are dropped. Everything that did not convert
+// faithfully is reported as a warning and noted in a comment above its rule.
+
+use crate::refactor::{INDENT, quote_string};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Namespace of the `apps:property` elements of a filter
+const APPS_NAMESPACE: &str = "http://schemas.google.com/apps/2006";
+
+/// Actions Gmail offers that Sieve cannot express, with the text of the Gmail UI
+const UNSUPPORTED_ACTIONS: &[(&str, &str)] = &[
+    ("shouldNeverSpam", "Never send it to Spam"),
+    ("shouldAlwaysMarkAsImportant", "Always mark it as important"),
+    ("shouldNeverMarkAsImportant", "Never mark it as important"),
+    ("smartLabelToApply", "Categorize as"),
+    ("cannedResponse", "Send template"),
+];
+
+/// A converted filter file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailImport {
+    /// The Sieve script
+    pub text: String,
+    /// Filters and parts of filters that were not converted faithfully
+    pub warnings: Vec<String>,
+}
+
+/// One filter of the export: its properties in document order
+struct Filter {
+    properties: Vec<(String, String)>,
+}
+
+impl Filter {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        self.get(name) == Some("true")
+    }
+}
+
+/// A filter converted to a rule
+struct Rule {
+    tests: Vec<String>,
+    actions: Vec<String>,
+    requires: BTreeSet<&'static str>,
+    notes: Vec<String>,
+}
+
+/// Convert an exported Gmail filter file to Sieve
+pub fn import(xml: &str) -> Result<GmailImport, String> {
+    let document =
+        roxmltree::Document::parse(xml).map_err(|err| format!("Invalid filter XML: {}", err))?;
+    let filters: Vec<Filter> = document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "entry")
+        .map(|entry| Filter {
+            properties: entry
+                .children()
+                .filter(|node| {
+                    node.tag_name().name() == "property"
+                        && node.tag_name().namespace() == Some(APPS_NAMESPACE)
+                })
+                .filter_map(|node| Some((node.attribute("name")?, node.attribute("value")?)))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        })
+        .collect();
+    if filters.is_empty() {
+        return Err("The file contains no Gmail filters".to_string());
+    }
+
+    let mut requires = BTreeSet::new();
+    let mut warnings = Vec::new();
+    let mut body = String::new();
+    for (index, filter) in filters.iter().enumerate() {
+        let number = index + 1;
+        body.push_str(&format!("\n# Gmail filter {}\n", number));
+        match convert(filter) {
+            Ok(rule) => {
+                for note in &rule.notes {
+                    body.push_str(&format!("# {}\n", note));
+                    warnings.push(format!("Filter {}: {}", number, note));
+                }
+                if rule.actions.is_empty() {
+                    let note = "No action of the filter can be expressed in Sieve";
+                    body.push_str(&format!("# {}\n", note));
+                    warnings.push(format!("Filter {}: {}", number, note));
+                    continue;
+                }
+                requires.extend(rule.requires);
+                let test = match rule.tests.as_slice() {
+                    [single] => single.clone(),
+                    tests => format!("allof ({})", tests.join(", ")),
+                };
+                body.push_str(&format!("if {} {{\n", test));
+                for action in &rule.actions {
+                    body.push_str(&format!("{}{}\n", INDENT, action));
+                }
+                body.push_str("}\n");
+            }
+            Err(reason) => {
+                body.push_str(&format!("# Not converted: {}\n", reason));
+                warnings.push(format!("Filter {} was not converted: {}", number, reason));
+            }
+        }
+    }
+
+    let mut text = String::new();
+    if !requires.is_empty() {
+        let quoted: Vec<String> = requires.iter().map(|r: &&str| quote_string(r)).collect();
+        text.push_str(&format!("require [{}];\n", quoted.join(", ")));
+        text.push_str(&body);
+    } else {
+        text.push_str(body.trim_start_matches('\n'));
+    }
+    Ok(GmailImport { text, warnings })
+}
+
+/// Translate the criteria and actions of one filter
+/// Fails when a criterion cannot be expressed, as dropping it would match more messages.
+fn convert(filter: &Filter) -> Result<Rule, String> {
+    let mut requires = BTreeSet::new();
+    let mut tests = Vec::new();
+    let mut notes = Vec::new();
+
+    if let Some(from) = filter.get("from") {
+        tests.push(format!("address :contains \"from\" {}", keys(&alternatives(from)?)));
+    }
+    if let Some(to) = filter.get("to") {
+        tests.push(format!("address :contains [\"to\", \"cc\"] {}", keys(&alternatives(to)?)));
+    }
+    if let Some(subject) = filter.get("subject") {
+        tests.push(format!("header :contains \"subject\" {}", keys(&alternatives(subject)?)));
+    }
+    if let Some(query) = filter.get("hasTheWord") {
+        tests.push(search(query, &mut requires)?);
+    }
+    if let Some(query) = filter.get("doesNotHaveTheWord") {
+        tests.push(format!("not {}", search(query, &mut requires)?));
+    }
+    if filter.is_set("hasAttachment") {
+        tests.push("header :contains \"content-type\" \"multipart/mixed\"".to_string());
+        notes.push(
+            "'Has attachment' is approximated by a multipart/mixed Content-Type".to_string(),
+        );
+    }
+    if let Some(size) = filter.get("size") {
+        tests.push(size_test(filter, size)?);
+    }
+    if tests.is_empty() {
+        return Err("the filter has no criteria Sieve can test".to_string());
+    }
+
+    let mut actions = Vec::new();
+    for (property, flag) in [("shouldMarkAsRead", "\\\\Seen"), ("shouldStar", "\\\\Flagged")] {
+        if filter.is_set(property) {
+            requires.insert("imap4flags");
+            actions.push(format!("addflag \"{}\";", flag));
+        }
+    }
+    if let Some(address) = filter.get("forwardTo") {
+        requires.insert("copy");
+        actions.push(format!("redirect :copy {};", quote_string(address)));
+    }
+    let archive = filter.is_set("shouldArchive");
+    if filter.is_set("shouldTrash") {
+        requires.insert("fileinto");
+        actions.push("fileinto \"Trash\";".to_string());
+    } else if let Some(label) = filter.get("label") {
+        requires.insert("fileinto");
+        if archive {
+            actions.push(format!("fileinto {};", quote_string(label)));
+        } else {
+            requires.insert("copy");
+            actions.push(format!("fileinto :copy {};", quote_string(label)));
+        }
+    } else if archive {
+        requires.insert("fileinto");
+        actions.push("fileinto \"Archive\";".to_string());
+        notes.push("Archiving without a label files the message into \"Archive\"".to_string());
+    }
+    for (property, description) in UNSUPPORTED_ACTIONS {
+        if filter.get(property).is_some_and(|value| value != "false") {
+            notes.push(format!("'{}' has no Sieve equivalent", description));
+        }
+    }
+
+    Ok(Rule {
+        tests,
+        actions,
+        requires,
+        notes,
+    })
+}
+
+/// The alternatives of a criterion such as `a@x.com OR b@y.com` or `{a@x.com b@y.com}`
+fn alternatives(value: &str) -> Result<Vec<String>, String> {
+    let value = value.trim();
+    let terms = if let Some(inner) = value.strip_prefix('{').and_then(|v| v.strip_suffix('}')) {
+        words(inner)
+    } else {
+        value
+            .split(" OR ")
+            .flat_map(|part| part.split('|'))
+            .map(|part| unquote(part.trim()))
+            .collect()
+    };
+    let unsupported = |term: &String| {
+        term.is_empty() || term.starts_with('-') || term.contains(['(', ')', '{', '}'])
+    };
+    if terms.iter().any(unsupported) {
+        return Err(format!("the search \"{}\" uses operators Sieve cannot express", value));
+    }
+    Ok(terms)
+}
+
+/// A "Has the words" query as a test of the message body or its list header
+fn search(query: &str, requires: &mut BTreeSet<&'static str>) -> Result<String, String> {
+    let query = query.trim();
+    if let Some(list) = query.strip_prefix("list:").filter(|list| !list.contains(' ')) {
+        let list = list.trim_matches(['<', '>']);
+        return Ok(format!("header :contains \"list-id\" {}", quote_string(list)));
+    }
+    if query.contains(':') {
+        return Err(format!("the search \"{}\" uses operators Sieve cannot express", query));
+    }
+    requires.insert("body");
+    if query.contains(" OR ") || query.contains('|') || query.starts_with('{') {
+        return Ok(format!("body :text :contains {}", keys(&alternatives(query)?)));
+    }
+    // Gmail requires all words of a query
+    let tests: Vec<String> = alternatives(query)?
+        .iter()
+        .flat_map(|term| words(term))
+        .map(|word| format!("body :text :contains {}", quote_string(&word)))
+        .collect();
+    Ok(match tests.as_slice() {
+        [single] => single.clone(),
+        tests => format!("allof ({})", tests.join(", ")),
+    })
+}
+
+/// The size criterion as a `size` test
+fn size_test(filter: &Filter, size: &str) -> Result<String, String> {
+    let comparison = match filter.get("sizeOperator") {
+        Some("s_ss") => ":under",
+        Some("s_sl") | None => ":over",
+        Some(other) => return Err(format!("unknown size comparison '{}'", other)),
+    };
+    let unit = match filter.get("sizeUnit") {
+        Some("s_sb") | None => "",
+        Some("s_skb") => "K",
+        Some("s_smb") => "M",
+        Some(other) => return Err(format!("unknown size unit '{}'", other)),
+    };
+    let size = size.trim();
+    if size.is_empty() || !size.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("'{}' is not a size", size));
+    }
+    Ok(format!("size {} {}{}", comparison, size, unit))
+}
+
+/// Words of a query, keeping quoted phrases together
+fn words(query: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn unquote(term: &str) -> String {
+    term.strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(term)
+        .to_string()
+}
+
+/// Keys of a test: a single string or a list
+fn keys(values: &[String]) -> String {
+    match values {
+        [single] => quote_string(single),
+        values => format!(
+            "[{}]",
+            values.iter().map(|v| quote_string(v)).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
@@ -0,0 +1,177 @@
+// ================================================================================================
+// DOCUMENT HIGHLIGHTS
+// ================================================================================================
+//
+// Occurrences related to the element under the cursor: the keywords and braces of an
+// `if`/`elsif`/`else` chain, the two braces of a block, or every use of a variable. Like the
+// outline they are derived from the syntax tree alone.
+
+use crate::parser::{Block, Command, Script, StringLiteral, range_contains};
+use crate::variables;
+use tower_lsp::lsp_types::*;
+
+/// Highlights for the element at a position, empty when there is nothing to relate it to
+pub fn highlights(text: &str, script: &Script, position: Position) -> Vec<DocumentHighlight> {
+    variable_highlights(text, script, position)
+        .or_else(|| chain_highlights(script, position))
+        .or_else(|| brace_highlights(script, position))
+        .unwrap_or_default()
+}
+
+fn highlight(range: Range, kind: DocumentHighlightKind) -> DocumentHighlight {
+    DocumentHighlight {
+        range,
+        kind: Some(kind),
+    }
+}
+
+/// Every list of sibling commands: the script's and those of all blocks
+fn command_lists(script: &Script) -> Vec<&[Command]> {
+    fn walk<'a>(commands: &'a [Command], out: &mut Vec<&'a [Command]>) {
+        out.push(commands);
+        for command in commands {
+            if let Some(block) = &command.block {
+                walk(&block.commands, out);
+            }
+        }
+    }
+    let mut out = Vec::new();
+    walk(&script.commands, &mut out);
+    out
+}
+
+/// The `{` of a block and its `}`, when it is closed
+fn braces(block: &Block) -> Vec<Range> {
+    let character = |position: Position, offset: i64| {
+        Position::new(position.line, (position.character as i64 + offset).max(0) as u32)
+    };
+    let mut ranges = vec![Range::new(block.range.start, character(block.range.start, 1))];
+    if block.closed {
+        ranges.push(Range::new(character(block.range.end, -1), block.range.end));
+    }
+    ranges
+}
+
+fn is_named(command: &Command, names: &[&str]) -> bool {
+    names.iter().any(|name| command.name.eq_ignore_ascii_case(name))
+}
+
+/// Keywords and braces of the `if`/`elsif`/`else` chain whose keyword is at the position
+fn chain_highlights(script: &Script, position: Position) -> Option<Vec<DocumentHighlight>> {
+    for commands in command_lists(script) {
+        let Some(index) = commands.iter().position(|command| {
+            is_named(command, &["if", "elsif", "else"])
+                && range_contains(&command.name_range, position)
+        }) else {
+            continue;
+        };
+        let mut start = index;
+        while start > 0
+            && is_named(&commands[start], &["elsif", "else"])
+            && is_named(&commands[start - 1], &["if", "elsif"])
+        {
+            start -= 1;
+        }
+        let mut found = Vec::new();
+        for (offset, command) in commands[start..].iter().enumerate() {
+            if offset > 0 && !is_named(command, &["elsif", "else"]) {
+                break;
+            }
+            found.push(highlight(command.name_range, DocumentHighlightKind::TEXT));
+            for range in command.block.iter().flat_map(braces) {
+                found.push(highlight(range, DocumentHighlightKind::TEXT));
+            }
+            if is_named(command, &["else"]) {
+                break;
+            }
+        }
+        return Some(found);
+    }
+    None
+}
+
+/// Both braces of the block whose brace is at the position
+fn brace_highlights(script: &Script, position: Position) -> Option<Vec<DocumentHighlight>> {
+    command_lists(script)
+        .into_iter()
+        .flatten()
+        .filter_map(|command| command.block.as_ref())
+        .map(braces)
+        .find(|ranges| ranges.iter().any(|range| range_contains(range, position)))
+        .map(|ranges| {
+            ranges
+                .into_iter()
+                .map(|range| highlight(range, DocumentHighlightKind::TEXT))
+                .collect()
+        })
+}
+
+/// Every string of the script, with the `set` command it names the variable of
+fn strings(script: &Script) -> Vec<(&StringLiteral, bool)> {
+    let commands = script.all_commands();
+    let tests = script.all_tests();
+    let mut found = Vec::new();
+    for command in commands {
+        let assigned = command
+            .name
+            .eq_ignore_ascii_case("set")
+            .then(|| variables::set_variable(command))
+            .flatten();
+        for string in command.arguments.iter().flat_map(|a| a.strings()) {
+            found.push((string, assigned.is_some_and(|a| std::ptr::eq(a, string))));
+        }
+    }
+    for test in tests {
+        found.extend(test.arguments.iter().flat_map(|a| a.strings()).map(|s| (s, false)));
+    }
+    found
+}
+
+/// Assignments and references of the variable at the position
+/// Variable names are case-insensitive (RFC 5229 section 3).
+fn variable_highlights(
+    text: &str,
+    script: &Script,
+    position: Position,
+) -> Option<Vec<DocumentHighlight>> {
+    let strings = strings(script);
+    let name = strings
+        .iter()
+        .filter(|(string, _)| range_contains(&string.range, position))
+        .find_map(|(string, assigned)| {
+            if *assigned {
+                return Some(string.value.clone());
+            }
+            variables::references(&string.value)
+                .into_iter()
+                .find(|reference| {
+                    let range = string.value_range(text, reference.start, reference.end);
+                    range_contains(&range, position)
+                })
+                .map(|reference| reference.name)
+        })?;
+
+    let mut found = Vec::new();
+    for (string, assigned) in strings {
+        if assigned {
+            if string.value.eq_ignore_ascii_case(&name) {
+                let length = string.value.chars().count();
+                found.push(highlight(
+                    string.value_range(text, 0, length),
+                    DocumentHighlightKind::WRITE,
+                ));
+            }
+            continue;
+        }
+        for reference in variables::references(&string.value) {
+            if reference.name.eq_ignore_ascii_case(&name) {
+                found.push(highlight(
+                    string.value_range(text, reference.start, reference.end),
+                    DocumentHighlightKind::READ,
+                ));
+            }
+        }
+    }
+    found.sort_by_key(|h| h.range.start);
+    Some(found)
+}
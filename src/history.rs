@@ -0,0 +1,204 @@
+// ================================================================================================
+// DIAGNOSTICS HISTORY
+// ================================================================================================
+//
+// Keeps a rolling record of the diagnostics reported for each file every time it is saved.
+// The record lives in the workspace (`.sieve-lsp/history.json`) so trends survive restarts and
+// can be compared against the state at the last deployment.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+/// Number of snapshots retained per file before the oldest ones are dropped
+pub const MAX_SNAPSHOTS_PER_FILE: usize = 50;
+
+/// Location of the history file relative to the workspace root
+pub const HISTORY_FILE: &str = ".sieve-lsp/history.json";
+
+/// A single issue as remembered in the history
+/// Line numbers are stored for reference but are not part of an issue's identity,
+/// since unrelated edits shift them around
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub code: String,
+    pub message: String,
+    pub line: u32,
+}
+
+impl HistoryEntry {
+    fn key(&self) -> (&str, &str) {
+        (&self.code, &self.message)
+    }
+}
+
+/// Diagnostics of one file at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Rolling diagnostics history for all files of a workspace
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiagnosticsHistory {
+    /// Snapshots per document URI, oldest first
+    #[serde(default)]
+    pub files: BTreeMap<String, Vec<Snapshot>>,
+    /// Diagnostics per document URI at the time of the last deployment
+    #[serde(default)]
+    pub deployed: BTreeMap<String, Snapshot>,
+}
+
+/// Trend summary for a single file
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    pub uri: String,
+    pub current_count: usize,
+    /// Issue counts of every retained snapshot, oldest first
+    pub trend: Vec<usize>,
+    /// Issues present now but not at the baseline
+    pub new_issues: Vec<HistoryEntry>,
+    /// Issues present at the baseline but not anymore
+    pub fixed_issues: Vec<HistoryEntry>,
+}
+
+/// Result of the `sieve.diagnosticsReport` command
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsReport {
+    /// "deploy" when compared against the last deployment, otherwise "first-snapshot"
+    pub baseline: String,
+    pub files: Vec<FileReport>,
+    pub summary: String,
+}
+
+impl DiagnosticsHistory {
+    /// Load the history stored in the given workspace, or start an empty one
+    pub fn load(root: &Path) -> Self {
+        std::fs::read_to_string(root.join(HISTORY_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the history into the given workspace
+    pub fn save(&self, root: &Path) -> std::io::Result<()> {
+        let path: PathBuf = root.join(HISTORY_FILE);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)
+    }
+
+    /// Append a snapshot of the given diagnostics for a file
+    pub fn record(&mut self, uri: &str, diagnostics: &[Diagnostic]) {
+        let entries = diagnostics
+            .iter()
+            .map(|d| HistoryEntry {
+                code: match &d.code {
+                    Some(NumberOrString::String(code)) => code.clone(),
+                    Some(NumberOrString::Number(code)) => code.to_string(),
+                    None => String::new(),
+                },
+                message: d.message.clone(),
+                line: d.range.start.line,
+            })
+            .collect();
+
+        let snapshots = self.files.entry(uri.to_string()).or_default();
+        snapshots.push(Snapshot {
+            timestamp: now(),
+            entries,
+        });
+        if snapshots.len() > MAX_SNAPSHOTS_PER_FILE {
+            let excess = snapshots.len() - MAX_SNAPSHOTS_PER_FILE;
+            snapshots.drain(..excess);
+        }
+    }
+
+    /// Remember the latest snapshot of every file as the deployed state
+    pub fn mark_deployed(&mut self) {
+        self.deployed = self
+            .files
+            .iter()
+            .filter_map(|(uri, snapshots)| Some((uri.clone(), snapshots.last()?.clone())))
+            .collect();
+    }
+
+    /// Summarize new and fixed issues per file
+    /// Compares against the last deployment when one was marked, otherwise against the
+    /// oldest retained snapshot
+    pub fn report(&self) -> DiagnosticsReport {
+        let use_deploy = !self.deployed.is_empty();
+        let mut files = Vec::new();
+
+        for (uri, snapshots) in &self.files {
+            let Some(latest) = snapshots.last() else {
+                continue;
+            };
+            let baseline = if use_deploy {
+                self.deployed.get(uri)
+            } else {
+                snapshots.first()
+            };
+            let empty = Vec::new();
+            let baseline_entries = baseline.map(|s| &s.entries).unwrap_or(&empty);
+
+            files.push(FileReport {
+                uri: uri.clone(),
+                current_count: latest.entries.len(),
+                trend: snapshots.iter().map(|s| s.entries.len()).collect(),
+                new_issues: difference(&latest.entries, baseline_entries),
+                fixed_issues: difference(baseline_entries, &latest.entries),
+            });
+        }
+
+        let new_total: usize = files.iter().map(|f| f.new_issues.len()).sum();
+        let fixed_total: usize = files.iter().map(|f| f.fixed_issues.len()).sum();
+        let baseline = if use_deploy { "deploy" } else { "first-snapshot" };
+        let summary = format!(
+            "{} file(s) tracked: {} new issue(s), {} fixed issue(s) since {}",
+            files.len(),
+            new_total,
+            fixed_total,
+            if use_deploy {
+                "last deploy"
+            } else {
+                "first recorded save"
+            }
+        );
+
+        DiagnosticsReport {
+            baseline: baseline.to_string(),
+            files,
+            summary,
+        }
+    }
+}
+
+/// Entries of `a` that are not matched by an entry of `b` (multiset difference)
+fn difference(a: &[HistoryEntry], b: &[HistoryEntry]) -> Vec<HistoryEntry> {
+    let mut remaining: Vec<&HistoryEntry> = b.iter().collect();
+    let mut result = Vec::new();
+    for entry in a {
+        if let Some(idx) = remaining.iter().position(|e| e.key() == entry.key()) {
+            remaining.swap_remove(idx);
+        } else {
+            result.push(entry.clone());
+        }
+    }
+    result
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,159 @@
+// ================================================================================================
+// LOCALIZATION
+// ================================================================================================
+//
+// User-facing strings are written in English throughout the code base. Message catalogs in
+// `locales/` map each English template to its translation, with `{0}`, `{1}`, ... standing for
+// interpolated values, and finished messages are translated when they are sent to the client.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+
+/// Catalogs bundled with the server, keyed by lowercase language tag
+const BUNDLED_CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+];
+
+/// Translations of one language
+#[derive(Debug)]
+pub struct Catalog {
+    /// Templates without placeholders, looked up directly
+    exact: HashMap<String, String>,
+    /// Templates with placeholders, matched against the finished message
+    patterns: Vec<(String, Regex, String)>,
+}
+
+impl Catalog {
+    /// Build a catalog from a JSON object of `"English template": "translation"` pairs
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let entries: BTreeMap<String, String> = serde_json::from_str(json)?;
+        let mut catalog = Catalog {
+            exact: HashMap::new(),
+            patterns: Vec::new(),
+        };
+        for (template, translation) in entries {
+            if PLACEHOLDER.is_match(&template) {
+                let regex = template_regex(&template);
+                catalog.patterns.push((template, regex, translation));
+            } else {
+                catalog.exact.insert(template, translation);
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Translate a finished message, or `None` if the catalog has no matching template
+    pub fn translate(&self, message: &str) -> Option<String> {
+        if let Some(translation) = self.exact.get(message) {
+            return Some(translation.clone());
+        }
+        self.patterns.iter().find_map(|(_, regex, translation)| {
+            let captures = regex.captures(message)?;
+            Some(
+                PLACEHOLDER
+                    .replace_all(translation, |placeholder: &regex::Captures| {
+                        captures
+                            .name(&format!("p{}", &placeholder[1]))
+                            .map(|value| value.as_str().to_string())
+                            .unwrap_or_default()
+                    })
+                    .into_owned(),
+            )
+        })
+    }
+
+    /// English templates covered by this catalog
+    pub fn templates(&self) -> Vec<String> {
+        let mut templates: Vec<String> = self.exact.keys().cloned().collect();
+        templates.extend(self.patterns.iter().map(|(template, _, _)| template.clone()));
+        templates.sort();
+        templates
+    }
+}
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{(\d+)\}").unwrap();
+    static ref CATALOGS: BTreeMap<&'static str, Catalog> = BUNDLED_CATALOGS
+        .iter()
+        .map(|(tag, json)| {
+            let catalog = Catalog::from_json(json)
+                .unwrap_or_else(|err| panic!("Invalid bundled catalog '{}': {}", tag, err));
+            (*tag, catalog)
+        })
+        .collect();
+}
+
+/// Anchored regex for a template, with a named group per placeholder
+fn template_regex(template: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    for placeholder in PLACEHOLDER.captures_iter(template) {
+        let whole = placeholder.get(0).unwrap();
+        pattern.push_str(&regex::escape(&template[last..whole.start()]));
+        pattern.push_str(&format!("(?P<p{}>.+?)", &placeholder[1]));
+        last = whole.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+    Regex::new(&pattern).expect("escaped template is a valid regex")
+}
+
+/// The bundled catalog of a language, if there is one
+pub fn catalog(tag: &str) -> Option<&'static Catalog> {
+    CATALOGS.get(tag)
+}
+
+/// Translates messages for the locale the client asked for
+#[derive(Debug, Clone)]
+pub struct Localizer {
+    /// Catalogs tried in order, most specific first
+    chain: Vec<&'static Catalog>,
+}
+
+impl Default for Localizer {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Localizer {
+    /// Build the fallback chain for a locale such as "de-AT": `de-at`, `de`, then English
+    pub fn new(locale: Option<&str>) -> Self {
+        let mut chain = Vec::new();
+        if let Some(locale) = locale {
+            let tag = locale.trim().replace('_', "-").to_lowercase();
+            let mut candidate = tag.as_str();
+            loop {
+                if let Some(catalog) = catalog(candidate) {
+                    chain.push(catalog);
+                }
+                match candidate.rfind('-') {
+                    Some(idx) => candidate = &candidate[..idx],
+                    None => break,
+                }
+            }
+        }
+        Self { chain }
+    }
+
+    /// Translate a message, returning it unchanged when no catalog covers it
+    pub fn translate(&self, message: &str) -> String {
+        self.chain
+            .iter()
+            .find_map(|catalog| catalog.translate(message))
+            .unwrap_or_else(|| message.to_string())
+    }
+
+    /// Translate a multi-line text such as hover Markdown line by line
+    pub fn translate_lines(&self, text: &str) -> String {
+        if self.chain.is_empty() {
+            return text.to_string();
+        }
+        text.split('\n')
+            .map(|line| self.translate(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,191 @@
+// ================================================================================================
+// TEST IMPLICATION
+// ================================================================================================
+//
+// Decides whether one test can only be true when another one is, e.g. `header :is "from"
+// "a@corp.com"` implies `header :contains "from" "corp.com"`. Used to find rules that an
+// earlier rule always catches first. The analysis is conservative: `false` means "not known
+// to imply", so comparators other than the two basic ones, variables, `:regex` and tags of
+// extensions make a test opaque.
+
+use crate::interpreter::wildcard_regex;
+use crate::parser::{self, Argument, Test};
+use regex::Regex;
+
+/// Whether `specific` can only be true when `general` is true as well
+pub fn implies(specific: &Test, general: &Test) -> bool {
+    match (specific.name.as_str(), general.name.as_str()) {
+        (_, "true") => true,
+        (_, "anyof") => general.tests.iter().any(|g| implies(specific, g)),
+        (_, "allof") => general.tests.iter().all(|g| implies(specific, g)),
+        ("allof", _) => specific.tests.iter().any(|s| implies(s, general)),
+        ("anyof", _) => {
+            !specific.tests.is_empty() && specific.tests.iter().all(|s| implies(s, general))
+        }
+        ("size", "size") => match (size_limit(specific), size_limit(general)) {
+            (Some((true, s)), Some((true, g))) => s >= g,
+            (Some((false, s)), Some((false, g))) => s <= g,
+            _ => false,
+        },
+        ("exists", "exists") => match (header_names(specific), header_names(general)) {
+            (Some(s), Some(g)) => g.iter().all(|name| s.contains(name)),
+            _ => false,
+        },
+        (s, g) if s == g && matches!(s, "header" | "address" | "envelope") => {
+            match (Comparison::of(specific), Comparison::of(general)) {
+                (Some(s), Some(g)) => s.implies(&g),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// A key of a comparison, normalized so that `:matches "*x*"` reads as `:contains "x"`
+#[derive(Debug, PartialEq)]
+enum Key {
+    Is(String),
+    Contains(String),
+    Pattern(String),
+}
+
+impl Key {
+    fn new(match_type: &str, value: String) -> Option<Key> {
+        match match_type {
+            ":is" => Some(Key::Is(value)),
+            ":contains" => Some(Key::Contains(value)),
+            ":matches" => {
+                let wildcard = |s: &str| s.contains(['*', '?', '\\']);
+                if !wildcard(&value) {
+                    Some(Key::Is(value))
+                } else if let Some(inner) = value
+                    .strip_prefix('*')
+                    .and_then(|rest| rest.strip_suffix('*'))
+                    .filter(|inner| !wildcard(inner))
+                {
+                    Some(Key::Contains(inner.to_string()))
+                } else if value.chars().all(|c| c == '*') {
+                    Some(Key::Contains(String::new()))
+                } else {
+                    Some(Key::Pattern(value))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether every value matching `self` also matches `general`
+    fn implies(&self, general: &Key) -> bool {
+        match (self, general) {
+            (Key::Is(s), Key::Is(g)) => s == g,
+            (Key::Is(s) | Key::Contains(s), Key::Contains(g)) => s.contains(g.as_str()),
+            (Key::Pattern(_), Key::Contains(g)) => g.is_empty(),
+            (Key::Is(s), Key::Pattern(g)) => Regex::new(&format!("(?s){}", wildcard_regex(g)))
+                .is_ok_and(|regex| regex.is_match(s)),
+            (Key::Pattern(s), Key::Pattern(g)) => s == g,
+            _ => false,
+        }
+    }
+}
+
+/// A `header`, `address` or `envelope` test broken into its parts
+#[derive(Debug)]
+struct Comparison {
+    /// Header names or envelope parts, lowercase
+    fields: Vec<String>,
+    address_part: Option<String>,
+    comparator: String,
+    keys: Vec<Key>,
+}
+
+impl Comparison {
+    fn of(test: &Test) -> Option<Comparison> {
+        let mut match_type = ":is";
+        let mut comparator = "i;ascii-casemap".to_string();
+        let mut address_part = None;
+        let mut arguments = test.arguments.iter();
+        let mut positional: Vec<&Argument> = Vec::new();
+        while let Some(argument) = arguments.next() {
+            match argument.tag() {
+                Some(tag @ (":is" | ":contains" | ":matches")) => match_type = tag,
+                Some(":comparator") => {
+                    comparator = arguments.next()?.strings().first()?.value.to_lowercase();
+                }
+                Some(tag @ (":all" | ":localpart" | ":domain" | ":user" | ":detail")) => {
+                    address_part = Some(tag.to_string());
+                }
+                Some(_) => return None,
+                None => positional.push(argument),
+            }
+        }
+        if test.name != "header" && address_part.is_none() {
+            address_part = Some(":all".to_string());
+        }
+        let fold = match comparator.as_str() {
+            "i;ascii-casemap" => true,
+            "i;octet" => false,
+            _ => return None,
+        };
+
+        let [fields, keys] = positional.as_slice() else {
+            return None;
+        };
+        let strings = |argument: &Argument| -> Option<Vec<String>> {
+            argument
+                .strings()
+                .into_iter()
+                .map(|s| s.decoded().into_owned())
+                .map(|value| (!value.contains("${")).then_some(value))
+                .collect()
+        };
+        let fields = strings(fields)?.iter().map(|f| f.to_lowercase()).collect();
+        let keys = strings(keys)?
+            .into_iter()
+            .map(|key| if fold { key.to_lowercase() } else { key })
+            .map(|key| Key::new(match_type, key))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Comparison {
+            fields,
+            address_part,
+            comparator,
+            keys,
+        })
+    }
+
+    fn implies(&self, general: &Comparison) -> bool {
+        self.comparator == general.comparator
+            && self.address_part == general.address_part
+            && !self.keys.is_empty()
+            && self.fields.iter().all(|f| general.fields.contains(f))
+            && self
+                .keys
+                .iter()
+                .all(|key| general.keys.iter().any(|g| key.implies(g)))
+    }
+}
+
+/// `(true, n)` for `size :over n`, `(false, n)` for `size :under n`
+fn size_limit(test: &Test) -> Option<(bool, u64)> {
+    let over = match test.arguments.first()?.tag()? {
+        ":over" => true,
+        ":under" => false,
+        _ => return None,
+    };
+    match test.arguments.get(1)? {
+        Argument::Number { raw, .. } => Some((over, parser::number_value(raw)?)),
+        _ => None,
+    }
+}
+
+/// Header names of an `exists` test, lowercase
+fn header_names(test: &Test) -> Option<Vec<String>> {
+    if test.arguments.iter().any(|a| a.tag().is_some()) {
+        return None;
+    }
+    let names = test.positional_arguments().first()?.strings();
+    names
+        .into_iter()
+        .map(|s| s.decoded().into_owned())
+        .map(|name| (!name.contains("${")).then(|| name.to_lowercase()))
+        .collect()
+}
@@ -0,0 +1,372 @@
+// ================================================================================================
+// INCLUDE GRAPH (RFC 6609)
+// ================================================================================================
+//
+// Resolves `include` commands between scripts on disk and can flatten an entry script with all
+// of its includes into a single standalone script for servers without the include extension.
+
+use crate::parser::{self, Command, Script};
+use crate::variables::set_variable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::Range;
+
+/// An `include` command found in a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludeDirective {
+    /// Script name as written in the include command
+    pub name: String,
+    /// `:global` instead of the default `:personal` location
+    pub global: bool,
+    /// `:once` - only include the script the first time it is encountered
+    pub once: bool,
+    /// `:optional` - a missing script is not an error
+    pub optional: bool,
+    pub range: Range,
+}
+
+/// Extract all include directives of a parsed script
+pub fn includes_of(script: &Script) -> Vec<IncludeDirective> {
+    script
+        .all_commands()
+        .into_iter()
+        .filter_map(include_directive)
+        .collect()
+}
+
+/// Interpret a single command as an include directive
+pub fn include_directive(command: &Command) -> Option<IncludeDirective> {
+    if command.name != "include" {
+        return None;
+    }
+    let name = command
+        .arguments
+        .iter()
+        .find_map(|a| a.strings().first().map(|s| s.value.clone()))?;
+    let has = |tag: &str| command.arguments.iter().any(|a| a.tag() == Some(tag));
+    Some(IncludeDirective {
+        name,
+        global: has(":global"),
+        once: has(":once"),
+        optional: has(":optional"),
+        range: command.range,
+    })
+}
+
+/// Find the file an include refers to, relative to the including script's directory
+/// Both the bare name and the name with a `.sieve` suffix are tried
+pub fn resolve_include(from: &Path, name: &str) -> Option<PathBuf> {
+    let dir = from.parent().unwrap_or_else(|| Path::new("."));
+    [dir.join(name), dir.join(format!("{}.sieve", name))]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+/// All Sieve scripts below a directory, skipping hidden directories such as `.git`
+pub fn workspace_scripts(root: &Path) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "sieve") {
+                scripts.push(path);
+            }
+        }
+    }
+    scripts.sort();
+    scripts
+}
+
+/// Scripts reachable from the entry points through includes, entry points included
+pub fn reachable_scripts(entry_points: &[PathBuf]) -> BTreeSet<PathBuf> {
+    let mut reached = BTreeSet::new();
+    let mut pending: Vec<PathBuf> = entry_points.to_vec();
+    while let Some(path) = pending.pop() {
+        let path = path.canonicalize().unwrap_or(path);
+        if !reached.insert(path.clone()) {
+            continue;
+        }
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        for include in includes_of(&parser::parse(&text)) {
+            if let Some(target) = resolve_include(&path, &include.name) {
+                pending.push(target);
+            }
+        }
+    }
+    reached
+}
+
+/// Workspace scripts that no entry point uses, directly or through includes
+pub fn orphaned_scripts(root: &Path, entry_points: &[PathBuf]) -> Vec<PathBuf> {
+    let reached = reachable_scripts(entry_points);
+    workspace_scripts(root)
+        .into_iter()
+        .filter(|path| {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            !reached.contains(&canonical)
+        })
+        .collect()
+}
+
+/// Result of flattening an include graph
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FlattenedScript {
+    pub text: String,
+    /// Merged require list of all scripts
+    pub requires: Vec<String>,
+    /// Problems that prevented a faithful translation
+    pub warnings: Vec<String>,
+}
+
+/// A loaded script of the include graph
+struct LoadedScript {
+    text: String,
+    script: Script,
+}
+
+/// Inline every include of `entry` and merge all requires into one standalone script
+///
+/// Local variables that are set in more than one script would collide once the scripts share
+/// a namespace, so they are renamed with a per-script suffix in the included scripts.
+pub fn flatten(entry: &Path) -> Result<FlattenedScript, String> {
+    let mut scripts: BTreeMap<PathBuf, LoadedScript> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    load_graph(entry, &mut scripts, &mut warnings)?;
+
+    // Variables declared with `global` are shared on purpose and keep their names
+    let mut globals = BTreeSet::new();
+    let mut locals_by_script: BTreeMap<&PathBuf, BTreeSet<String>> = BTreeMap::new();
+    for (path, loaded) in &scripts {
+        for command in loaded.script.all_commands() {
+            match command.name.as_str() {
+                "global" => {
+                    for arg in &command.arguments {
+                        globals.extend(arg.strings().iter().map(|s| s.value.to_lowercase()));
+                    }
+                }
+                "set" => {
+                    if let Some(name) = set_variable(command) {
+                        locals_by_script
+                            .entry(path)
+                            .or_default()
+                            .insert(name.value.to_lowercase());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut owners: BTreeMap<&String, usize> = BTreeMap::new();
+    for names in locals_by_script.values() {
+        for name in names {
+            *owners.entry(name).or_default() += 1;
+        }
+    }
+    let colliding: BTreeSet<String> = owners
+        .into_iter()
+        .filter(|(name, count)| *count > 1 && !globals.contains(*name))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let mut requires = BTreeSet::new();
+    for loaded in scripts.values() {
+        for command in loaded.script.all_commands() {
+            if command.name == "require" {
+                for arg in &command.arguments {
+                    requires.extend(arg.strings().iter().map(|s| s.value.clone()));
+                }
+            }
+        }
+    }
+    requires.remove("include");
+
+    let mut included_once = BTreeSet::new();
+    let body = inline(
+        entry,
+        true,
+        &scripts,
+        &colliding,
+        &mut Vec::new(),
+        &mut included_once,
+        &mut warnings,
+    )?;
+
+    let requires: Vec<String> = requires.into_iter().collect();
+    let mut text = String::new();
+    if !requires.is_empty() {
+        let list: Vec<String> = requires.iter().map(|r| format!("\"{}\"", r)).collect();
+        text.push_str(&format!("require [{}];\n", list.join(", ")));
+    }
+    text.push_str(body.trim_start_matches('\n'));
+
+    Ok(FlattenedScript {
+        text,
+        requires,
+        warnings,
+    })
+}
+
+fn load_graph(
+    path: &Path,
+    scripts: &mut BTreeMap<PathBuf, LoadedScript>,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    if scripts.contains_key(path) {
+        return Ok(());
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    let script = parser::parse(&text);
+    let includes = includes_of(&script);
+    scripts.insert(path.to_path_buf(), LoadedScript { text, script });
+
+    for include in includes {
+        match resolve_include(path, &include.name) {
+            Some(target) => load_graph(&target, scripts, warnings)?,
+            None if include.optional => {}
+            None => warnings.push(format!(
+                "{}: included script '{}' not found",
+                path.display(),
+                include.name
+            )),
+        }
+    }
+    Ok(())
+}
+
+/// Render one script with its includes replaced by their (recursively inlined) content
+fn inline(
+    path: &Path,
+    is_entry: bool,
+    scripts: &BTreeMap<PathBuf, LoadedScript>,
+    colliding: &BTreeSet<String>,
+    stack: &mut Vec<PathBuf>,
+    included_once: &mut BTreeSet<PathBuf>,
+    warnings: &mut Vec<String>,
+) -> Result<String, String> {
+    if stack.iter().any(|p| p == path) {
+        return Err(format!("Include cycle detected at {}", path.display()));
+    }
+    let Some(loaded) = scripts.get(path) else {
+        return Ok(String::new());
+    };
+    stack.push(path.to_path_buf());
+
+    let suffix = path
+        .file_stem()
+        .map(|s| {
+            s.to_string_lossy()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+    let rename = |name: &str| -> Option<String> {
+        (!is_entry && colliding.contains(&name.to_lowercase()))
+            .then(|| format!("{}_{}", name, suffix))
+    };
+
+    let mut edits: Vec<(Range, String)> = Vec::new();
+    for command in loaded.script.all_commands() {
+        match command.name.as_str() {
+            "require" | "global" => edits.push((command.range, String::new())),
+            "return" if !is_entry => {
+                warnings.push(format!(
+                    "{}: 'return' cannot be inlined faithfully and was commented out",
+                    path.display()
+                ));
+                edits.push((command.range, "# return;".to_string()));
+            }
+            "include" => {
+                let Some(include) = include_directive(command) else {
+                    continue;
+                };
+                let replacement = match resolve_include(path, &include.name) {
+                    Some(target) if include.once && included_once.contains(&target) => {
+                        String::new()
+                    }
+                    Some(target) => {
+                        included_once.insert(target.clone());
+                        let content = inline(
+                            &target,
+                            false,
+                            scripts,
+                            colliding,
+                            stack,
+                            included_once,
+                            warnings,
+                        )?;
+                        format!(
+                            "# --- begin include \"{}\" ---\n{}\n# --- end include \"{}\" ---",
+                            include.name,
+                            content.trim(),
+                            include.name
+                        )
+                    }
+                    None => format!("# missing include \"{}\"", include.name),
+                };
+                edits.push((command.range, replacement));
+            }
+            _ => {}
+        }
+
+        // Rename colliding local variables where they are set ...
+        if command.name == "set"
+            && let Some(name) = set_variable(command)
+            && let Some(new_name) = rename(&name.value)
+        {
+            edits.push((name.range, format!("\"{}\"", new_name)));
+        }
+    }
+
+    // ... and wherever they are referenced inside strings
+    let mut text = apply_edits(&loaded.text, edits);
+    if !is_entry {
+        for name in colliding {
+            let pattern = regex::RegexBuilder::new(&format!(r"\$\{{{}\}}", regex::escape(name)))
+                .case_insensitive(true)
+                .build()
+                .expect("escaped variable name is a valid regex");
+            let replacement = format!("${{{}_{}}}", name, suffix);
+            text = pattern
+                .replace_all(&text, regex::NoExpand(&replacement))
+                .into_owned();
+        }
+    }
+
+    stack.pop();
+    Ok(text)
+}
+
+/// Apply non-overlapping range replacements to a text
+/// Removed statements take their now-empty line with them
+fn apply_edits(text: &str, mut edits: Vec<(Range, String)>) -> String {
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse((range.start.line, range.start.character)));
+    let mut result = text.to_string();
+    for (range, replacement) in edits {
+        let start = parser::offset_at(&result, range.start);
+        let end = parser::offset_at(&result, range.end);
+        if replacement.is_empty() {
+            let line_start = result[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let rest = &result[end..];
+            let line_end = rest.find('\n').map(|i| end + i + 1).unwrap_or(result.len());
+            if result[line_start..start].trim().is_empty()
+                && result[end..line_end].trim().is_empty()
+            {
+                result.replace_range(line_start..line_end, "");
+                continue;
+            }
+        }
+        result.replace_range(start..end, &replacement);
+    }
+    result
+}
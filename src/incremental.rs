@@ -0,0 +1,228 @@
+// ================================================================================================
+// INCREMENTAL REPARSING
+// ================================================================================================
+//
+// Large generated scripts are edited a few lines at a time. Instead of re-parsing the whole
+// document on every keystroke, only the top-level statements touching the edited lines are
+// parsed again and spliced into the cached syntax tree; everything below is shifted.
+
+use crate::parser::{self, Argument, Command, Script, StringLiteral, Test};
+use tower_lsp::lsp_types::Range;
+
+/// Lines affected by an edit, in the coordinates of the document before the edit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineEdit {
+    /// First line touched by the edit
+    pub first_line: u32,
+    /// Last line touched by the edit (inclusive)
+    pub last_line: u32,
+    /// How many lines the document grew (positive) or shrank (negative) by
+    pub line_delta: i64,
+}
+
+/// Update a parsed script after an edit, re-parsing only the dirty top-level statements
+/// Falls back to a full parse whenever the edit may change how the rest of the file lexes
+pub fn reparse(old: &Script, new_text: &str, edit: LineEdit) -> Script {
+    // Bracket and unterminated-token problems may span the whole file - start from scratch
+    if old.errors.iter().any(|e| is_structural(e.code)) {
+        return parser::parse(new_text);
+    }
+
+    // Grow the dirty line range until no statement or comment straddles its borders
+    let (mut first, mut last) = (edit.first_line, edit.last_line);
+    loop {
+        let (mut new_first, mut new_last) = (first, last);
+        let spans = old
+            .commands
+            .iter()
+            .map(|c| c.range)
+            .chain(old.comments.iter().map(|c| c.range));
+        for range in spans {
+            if range.start.line <= last && range.end.line >= first {
+                new_first = new_first.min(range.start.line);
+                new_last = new_last.max(range.end.line);
+            }
+        }
+        if (new_first, new_last) == (first, last) {
+            break;
+        }
+        (first, last) = (new_first, new_last);
+    }
+
+    let new_last = last as i64 + edit.line_delta;
+    if new_last < first as i64 {
+        return parser::parse(new_text);
+    }
+    let new_last = new_last as u32;
+
+    // Parse the dirty lines on their own
+    let region: String = new_text
+        .split_inclusive('\n')
+        .skip(first as usize)
+        .take((new_last - first + 1) as usize)
+        .collect();
+    let mut partial = parser::parse(&region);
+
+    // An unterminated string or comment swallows what follows, a bracket imbalance may pair
+    // with braces elsewhere and a statement without terminator continues into the next one.
+    // In all these cases the region cannot be treated in isolation.
+    let unbalanced = partial.errors.iter().any(|e| is_structural(e.code));
+    let open_statement = partial.commands.last().is_some_and(|c| !c.terminated)
+        || old
+            .commands
+            .iter()
+            .rev()
+            .find(|c| c.range.end.line < first)
+            .is_some_and(|c| !c.terminated);
+    if unbalanced || open_statement {
+        return parser::parse(new_text);
+    }
+    shift_script(&mut partial, first as i64);
+
+    let before = |r: &Range| r.end.line < first;
+    let after = |r: &Range| r.start.line > last;
+
+    let mut script = Script::default();
+    script
+        .commands
+        .extend(old.commands.iter().filter(|c| before(&c.range)).cloned());
+    script.commands.extend(partial.commands);
+    script.commands.extend(
+        old.commands
+            .iter()
+            .filter(|c| after(&c.range))
+            .cloned()
+            .map(|mut c| {
+                shift_command(&mut c, edit.line_delta);
+                c
+            }),
+    );
+
+    script
+        .comments
+        .extend(old.comments.iter().filter(|c| before(&c.range)).cloned());
+    script.comments.extend(partial.comments);
+    script.comments.extend(
+        old.comments
+            .iter()
+            .filter(|c| after(&c.range))
+            .cloned()
+            .map(|mut c| {
+                shift_range(&mut c.range, edit.line_delta);
+                c
+            }),
+    );
+
+    script
+        .errors
+        .extend(old.errors.iter().filter(|e| before(&e.range)).cloned());
+    script.errors.extend(partial.errors);
+    script.errors.extend(
+        old.errors
+            .iter()
+            .filter(|e| after(&e.range))
+            .cloned()
+            .map(|mut e| {
+                shift_range(&mut e.range, edit.line_delta);
+                for (range, _) in &mut e.related {
+                    shift_range(range, edit.line_delta);
+                }
+                e
+            }),
+    );
+
+    script
+        .multiline_strings
+        .extend(old.multiline_strings.iter().filter(|r| before(r)).copied());
+    script.multiline_strings.extend(partial.multiline_strings);
+    script.multiline_strings.extend(
+        old.multiline_strings
+            .iter()
+            .filter(|r| after(r))
+            .map(|r| {
+                let mut r = *r;
+                shift_range(&mut r, edit.line_delta);
+                r
+            }),
+    );
+
+    script
+}
+
+/// Errors whose effect is not confined to the statement they occur in
+fn is_structural(code: &str) -> bool {
+    matches!(
+        code,
+        "unterminated-string"
+            | "unterminated-multiline"
+            | "unterminated-comment"
+            | "unmatched-bracket"
+    )
+}
+
+// ================================================================================================
+// POSITION SHIFTING
+// ================================================================================================
+
+fn shift_range(range: &mut Range, delta: i64) {
+    range.start.line = (range.start.line as i64 + delta) as u32;
+    range.end.line = (range.end.line as i64 + delta) as u32;
+}
+
+fn shift_script(script: &mut Script, delta: i64) {
+    for command in &mut script.commands {
+        shift_command(command, delta);
+    }
+    for comment in &mut script.comments {
+        shift_range(&mut comment.range, delta);
+    }
+    for error in &mut script.errors {
+        shift_range(&mut error.range, delta);
+        for (range, _) in &mut error.related {
+            shift_range(range, delta);
+        }
+    }
+    for range in &mut script.multiline_strings {
+        shift_range(range, delta);
+    }
+}
+
+fn shift_command(command: &mut Command, delta: i64) {
+    shift_range(&mut command.range, delta);
+    shift_range(&mut command.name_range, delta);
+    shift_arguments(&mut command.arguments, delta);
+    for test in &mut command.tests {
+        shift_test(test, delta);
+    }
+    if let Some(block) = &mut command.block {
+        shift_range(&mut block.range, delta);
+        for command in &mut block.commands {
+            shift_command(command, delta);
+        }
+    }
+}
+
+fn shift_test(test: &mut Test, delta: i64) {
+    shift_range(&mut test.range, delta);
+    shift_range(&mut test.name_range, delta);
+    shift_arguments(&mut test.arguments, delta);
+    for test in &mut test.tests {
+        shift_test(test, delta);
+    }
+}
+
+fn shift_arguments(arguments: &mut [Argument], delta: i64) {
+    let shift_string = |s: &mut StringLiteral| shift_range(&mut s.range, delta);
+    for argument in arguments {
+        match argument {
+            Argument::String(s) => shift_string(s),
+            Argument::StringList { items, range } => {
+                shift_range(range, delta);
+                items.iter_mut().for_each(shift_string);
+            }
+            Argument::Number { range, .. } | Argument::Tag { range, .. } => {
+                shift_range(range, delta)
+            }
+        }
+    }
+}
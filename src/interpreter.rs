@@ -0,0 +1,1025 @@
+// ================================================================================================
+// SCRIPT SIMULATOR
+// ================================================================================================
+//
+// Runs a script against a sample message so filters can be debugged without sending real mail.
+// The outcome lists the result of every `if`/`elsif` test that was evaluated and the actions the
+// script would take, including whether the implicit keep still files the message into the inbox
+// (RFC 5228 section 2.10.2). Tests that depend on the delivery itself, like `date` or
+// `currentdate`, cannot be simulated: they evaluate to false and are listed as unsupported, so a
+// partial result is never mistaken for a complete one.
+//
+// Every run also records a trace of each test evaluated and each command executed, which
+// `trace_text` renders as a read-only document for `sieve/traceMessage`.
+
+use crate::message::Message;
+use crate::parser::{Argument, Command, Script, Test, range_contains};
+use crate::sieve::TAGS_WITH_VALUE;
+use crate::variables;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier, Url};
+
+/// Method name of the custom request returning the execution trace of a sample message
+pub const TRACE_MESSAGE_METHOD: &str = "sieve/traceMessage";
+
+/// Commands that end the implicit keep unless they carry `:copy` (RFC 3894)
+const CANCELS_IMPLICIT_KEEP: &[&str] =
+    &["keep", "discard", "fileinto", "redirect", "reject", "ereject"];
+
+/// SMTP envelope of the simulated delivery
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    /// Return path (SMTP MAIL FROM)
+    pub from: Option<String>,
+    /// Recipient (SMTP RCPT TO)
+    pub to: Option<String>,
+}
+
+impl Envelope {
+    /// Best guess of the envelope a message was delivered with
+    /// The sender comes from the mbox separator or `Return-Path`, the recipient from
+    /// `Delivered-To`, `X-Original-To` or the first `To` address.
+    pub fn from_message(message: &Message) -> Self {
+        let first_address = |name: &str| {
+            message
+                .header(name)
+                .first()
+                .and_then(|value| addresses(value).into_iter().next())
+        };
+        Self {
+            from: message
+                .envelope_from
+                .clone()
+                .or_else(|| first_address("return-path")),
+            to: first_address("delivered-to")
+                .or_else(|| first_address("x-original-to"))
+                .or_else(|| first_address("to")),
+        }
+    }
+}
+
+/// An action the script would take
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutedAction {
+    /// Command name such as `fileinto`
+    pub command: String,
+    /// Tags of the command, e.g. `:copy`
+    pub tags: Vec<String>,
+    /// String arguments with variables expanded
+    pub arguments: Vec<String>,
+    /// IMAP flags stored with the message by `keep` and `fileinto`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    pub range: Range,
+}
+
+impl ExecutedAction {
+    /// The action as written in a script with its flags, e.g. `fileinto :copy "Lists"`
+    pub fn describe(&self) -> String {
+        let mut text = std::iter::once(self.command.clone())
+            .chain(self.tags.iter().cloned())
+            .chain(self.arguments.iter().map(|a| format!("{:?}", a)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !self.flags.is_empty() {
+            text.push_str(&format!(" [flags: {}]", self.flags.join(" ")));
+        }
+        text
+    }
+}
+
+/// The result of the test of an `if` or `elsif`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleResult {
+    /// Name of the outermost test, e.g. `anyof`
+    pub test: String,
+    pub matched: bool,
+    pub range: Range,
+}
+
+/// A test the simulator cannot evaluate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Unsupported {
+    pub name: String,
+    pub range: Range,
+}
+
+/// One step of the execution trace
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceEntry {
+    /// Nesting of blocks and tests, 0 for the top level
+    pub depth: usize,
+    /// The test with its result, or the command as executed
+    pub event: String,
+    pub range: Range,
+}
+
+/// Everything a simulated delivery did
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Outcome {
+    /// Evaluated rules in execution order
+    pub rules: Vec<RuleResult>,
+    /// Actions in execution order
+    pub actions: Vec<ExecutedAction>,
+    /// Whether the message still ends up in the inbox through the implicit keep
+    pub implicit_keep: bool,
+    /// Whether `stop` ended the script early
+    pub stopped: bool,
+    /// Tests that evaluated to false because they cannot be simulated
+    pub unsupported: Vec<Unsupported>,
+    /// Values of the variables when the script ended
+    pub variables: BTreeMap<String, String>,
+    /// Tests and commands in execution order
+    pub trace: Vec<TraceEntry>,
+}
+
+impl Outcome {
+    /// Apply a function to every range, e.g. to convert them to the client's position encoding
+    pub fn map_ranges(&mut self, convert: impl Fn(Range) -> Range) {
+        let ranges = self
+            .rules
+            .iter_mut()
+            .map(|r| &mut r.range)
+            .chain(self.actions.iter_mut().map(|a| &mut a.range))
+            .chain(self.unsupported.iter_mut().map(|u| &mut u.range))
+            .chain(self.trace.iter_mut().map(|t| &mut t.range));
+        for range in ranges {
+            *range = convert(*range);
+        }
+    }
+}
+
+/// Run a script against a message
+pub fn run(script: &Script, message: &Message, envelope: &Envelope) -> Outcome {
+    let mut interpreter = Interpreter {
+        message: message.clone(),
+        envelope,
+        variables: BTreeMap::new(),
+        match_values: Vec::new(),
+        flags: Vec::new(),
+        outcome: Outcome::default(),
+        keep_cancelled: false,
+        depth: 0,
+    };
+    let flow = interpreter.execute(&script.commands);
+    let mut outcome = interpreter.outcome;
+    outcome.stopped = flow == Flow::Stop;
+    outcome.implicit_keep = !interpreter.keep_cancelled;
+    outcome.variables = interpreter.variables;
+    outcome
+}
+
+/// How a rule fared against one sample message
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleTestResult {
+    /// Where the message was loaded from
+    pub origin: String,
+    /// Whether the test matched, `None` when the script ended before reaching it
+    pub matched: Option<bool>,
+}
+
+/// Run a script against each message and report the result of one of its rules
+/// The whole script runs, so variables set and `stop` executed before the rule count.
+pub fn test_rule(script: &Script, rule: &Command, messages: &[Message]) -> Vec<RuleTestResult> {
+    let Some(test) = rule.tests.first() else {
+        return Vec::new();
+    };
+    messages
+        .iter()
+        .map(|message| {
+            let outcome = run(script, message, &Envelope::from_message(message));
+            RuleTestResult {
+                origin: message.origin.clone(),
+                matched: outcome
+                    .rules
+                    .iter()
+                    .find(|result| result.range == test.range)
+                    .map(|result| result.matched),
+            }
+        })
+        .collect()
+}
+
+/// One-line summary of a rule test, shown to the user
+pub fn rule_test_summary(results: &[RuleTestResult]) -> String {
+    let matched = results.iter().filter(|r| r.matched == Some(true)).count();
+    let unreached = results.iter().filter(|r| r.matched.is_none()).count();
+    match results {
+        [] => "No sample messages found".to_string(),
+        [result] => match result.matched {
+            Some(true) => "Rule matches the sample message".to_string(),
+            Some(false) => "Rule does not match the sample message".to_string(),
+            None => "The script ends before reaching the rule".to_string(),
+        },
+        _ if unreached > 0 => format!(
+            "Rule matches {} of {} sample messages, {} end before reaching it",
+            matched,
+            results.len(),
+            unreached
+        ),
+        _ => format!("Rule matches {} of {} sample messages", matched, results.len()),
+    }
+}
+
+/// The results of running a script against every message of a corpus
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorpusReport {
+    /// Number of messages the script ran against
+    pub messages: usize,
+    /// Every `if` and `elsif` of the script in document order, nested ones included
+    pub rules: Vec<CorpusRule>,
+    /// Messages the implicit keep leaves in the inbox
+    pub kept: usize,
+}
+
+/// How often one rule matched the messages of a corpus
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorpusRule {
+    /// `if` or `elsif`
+    pub command: String,
+    /// Name of the outermost test, e.g. `anyof`
+    pub test: String,
+    pub range: Range,
+    /// Messages the test matched
+    pub matched: usize,
+    /// Messages filed by a `fileinto` in the block of the rule, by mailbox
+    pub destinations: BTreeMap<String, usize>,
+}
+
+/// Run a script against each message of a corpus and count the matches of each rule
+pub fn run_corpus(script: &Script, messages: &[Message]) -> CorpusReport {
+    let mut rules = Vec::new();
+    collect_rules(&script.commands, &mut rules);
+    let mut report = CorpusReport {
+        messages: messages.len(),
+        rules: rules
+            .iter()
+            .map(|(command, test)| CorpusRule {
+                command: command.name.clone(),
+                test: test.name.clone(),
+                range: command.header_range(),
+                matched: 0,
+                destinations: BTreeMap::new(),
+            })
+            .collect(),
+        kept: 0,
+    };
+    for message in messages {
+        let outcome = run(script, message, &Envelope::from_message(message));
+        report.kept += outcome.implicit_keep as usize;
+        for ((command, test), result) in rules.iter().zip(&mut report.rules) {
+            let matched = outcome.rules.iter().any(|r| r.range == test.range && r.matched);
+            if !matched {
+                continue;
+            }
+            result.matched += 1;
+            let Some(block) = &command.block else {
+                continue;
+            };
+            // A message counts once per mailbox, however often the rule files it there
+            let mailboxes: BTreeSet<&String> = outcome
+                .actions
+                .iter()
+                .filter(|action| {
+                    action.command == "fileinto" && range_contains(&block.range, action.range.start)
+                })
+                .filter_map(|action| action.arguments.last())
+                .collect();
+            for mailbox in mailboxes {
+                *result.destinations.entry(mailbox.clone()).or_default() += 1;
+            }
+        }
+    }
+    report
+}
+
+/// The `if` and `elsif` commands below `commands` with their test, in document order
+fn collect_rules<'a>(commands: &'a [Command], rules: &mut Vec<(&'a Command, &'a Test)>) {
+    for command in commands {
+        if matches!(command.name.as_str(), "if" | "elsif")
+            && let Some(test) = command.tests.first()
+        {
+            rules.push((command, test));
+        }
+        if let Some(block) = &command.block {
+            collect_rules(&block.commands, rules);
+        }
+    }
+}
+
+/// How execution continues after a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    Next,
+    /// `break` leaves the enclosing `foreverypart`
+    Break,
+    Stop,
+}
+
+/// Tags and positional arguments of a command or test
+struct Arguments<'a> {
+    tags: Vec<(&'a str, Option<&'a Argument>)>,
+    positional: Vec<&'a Argument>,
+}
+
+impl<'a> Arguments<'a> {
+    fn new(arguments: &'a [Argument]) -> Self {
+        let mut tags = Vec::new();
+        let mut positional = Vec::new();
+        let mut iter = arguments.iter();
+        while let Some(argument) = iter.next() {
+            match argument.tag() {
+                Some(tag) if TAGS_WITH_VALUE.contains(&tag) => tags.push((tag, iter.next())),
+                Some(tag) => tags.push((tag, None)),
+                None => positional.push(argument),
+            }
+        }
+        Self { tags, positional }
+    }
+
+    fn has(&self, tag: &str) -> bool {
+        self.tags.iter().any(|(t, _)| *t == tag)
+    }
+
+    fn value(&self, tag: &str) -> Option<&'a Argument> {
+        self.tags.iter().find(|(t, _)| *t == tag).and_then(|(_, v)| *v)
+    }
+
+    /// The first of several alternative tags that is present
+    fn first_of(&self, candidates: &[&'static str]) -> Option<&'static str> {
+        candidates.iter().copied().find(|c| self.has(c))
+    }
+}
+
+/// How values are compared with keys
+struct Matcher {
+    match_type: &'static str,
+    comparator: String,
+    /// Operator of `:value` and `:count`
+    relation: String,
+}
+
+struct Interpreter<'a> {
+    message: Message,
+    envelope: &'a Envelope,
+    /// Variables by lowercase name
+    variables: BTreeMap<String, String>,
+    /// `${0}` to `${9}` from the last successful `:matches` or `:regex`
+    match_values: Vec<String>,
+    flags: Vec<String>,
+    outcome: Outcome,
+    keep_cancelled: bool,
+    /// Depth of the next trace entry
+    depth: usize,
+}
+
+impl Interpreter<'_> {
+    fn execute(&mut self, commands: &[Command]) -> Flow {
+        // Whether a branch of the current if/elsif/else chain has already run
+        let mut branch_taken = true;
+        for command in commands {
+            let run_block = match command.name.as_str() {
+                "if" => {
+                    branch_taken = self.rule(command);
+                    branch_taken
+                }
+                "elsif" if !branch_taken => {
+                    branch_taken = self.rule(command);
+                    branch_taken
+                }
+                "elsif" => false,
+                "else" => !std::mem::replace(&mut branch_taken, true),
+                "foreverypart" => true,
+                "require" => continue,
+                _ => match self.command(command) {
+                    Flow::Next => continue,
+                    flow => return flow,
+                },
+            };
+            if run_block && let Some(block) = &command.block {
+                if matches!(command.name.as_str(), "else" | "foreverypart") {
+                    self.trace(command.name.clone(), command.header_range());
+                }
+                self.depth += 1;
+                let flow = self.execute(&block.commands);
+                self.depth -= 1;
+                match flow {
+                    Flow::Next => {}
+                    Flow::Break if command.name == "foreverypart" => {}
+                    flow => return flow,
+                }
+            }
+        }
+        Flow::Next
+    }
+
+    /// Evaluate the test of an `if` or `elsif` and record the result
+    fn rule(&mut self, command: &Command) -> bool {
+        let Some(test) = command.tests.first() else {
+            return false;
+        };
+        let matched = self.traced_test(&command.name, test);
+        self.outcome.rules.push(RuleResult {
+            test: test.name.clone(),
+            matched,
+            range: test.range,
+        });
+        matched
+    }
+
+    fn command(&mut self, command: &Command) -> Flow {
+        let arguments = Arguments::new(&command.arguments);
+        match command.name.as_str() {
+            "stop" | "break" => {
+                self.trace(command.name.clone(), command.range);
+                return if command.name == "stop" {
+                    Flow::Stop
+                } else {
+                    Flow::Break
+                };
+            }
+            "set" => {
+                self.set(&arguments);
+                if let Some(name) = self.positional_strings(&arguments).first() {
+                    let event = format!("set {} = {:?}", name, self.variable(name));
+                    self.trace(event, command.range);
+                }
+            }
+            "addflag" | "setflag" | "removeflag" => {
+                self.change_flags(&command.name, &arguments);
+                let event = match self.positional_strings(&arguments).as_slice() {
+                    [variable, _] => {
+                        format!("{} {} -> {:?}", command.name, variable, self.variable(variable))
+                    }
+                    _ => format!("{} -> flags: {}", command.name, self.flags.join(" ")),
+                };
+                self.trace(event, command.range);
+            }
+            "addheader" => {
+                let values = self.positional_strings(&arguments);
+                if let [name, value] = values.as_slice() {
+                    let field = (name.clone(), value.clone());
+                    if arguments.has(":last") {
+                        self.message.headers.push(field);
+                    } else {
+                        self.message.headers.insert(0, field);
+                    }
+                }
+                self.action(command, &arguments);
+            }
+            "deleteheader" => {
+                let name = self.strings(arguments.positional.first().copied());
+                let patterns = self.strings(arguments.positional.get(1).copied());
+                let matcher = self.matcher(&arguments);
+                let mut headers = std::mem::take(&mut self.message.headers);
+                headers.retain(|(field, value)| {
+                    !name.iter().any(|n| n.eq_ignore_ascii_case(field))
+                        || !(patterns.is_empty()
+                            || patterns.iter().any(|p| self.compare(&matcher, value, p)))
+                });
+                self.message.headers = headers;
+                self.action(command, &arguments);
+            }
+            _ => self.action(command, &arguments),
+        }
+        Flow::Next
+    }
+
+    /// Record an action and its effect on the implicit keep
+    fn action(&mut self, command: &Command, arguments: &Arguments) {
+        if CANCELS_IMPLICIT_KEEP.contains(&command.name.as_str()) && !arguments.has(":copy") {
+            self.keep_cancelled = true;
+        }
+        let flags = if matches!(command.name.as_str(), "keep" | "fileinto") {
+            match arguments.value(":flags") {
+                Some(flags) => split_flags(&self.strings(Some(flags))),
+                None => self.flags.clone(),
+            }
+        } else {
+            Vec::new()
+        };
+        let action = ExecutedAction {
+            command: command.name.clone(),
+            tags: arguments.tags.iter().map(|(t, _)| t.to_string()).collect(),
+            arguments: self.positional_strings(arguments),
+            flags,
+            range: command.range,
+        };
+        self.trace(action.describe(), command.range);
+        self.outcome.actions.push(action);
+    }
+
+    fn trace(&mut self, event: String, range: Range) {
+        self.outcome.trace.push(TraceEntry {
+            depth: self.depth,
+            event,
+            range,
+        });
+    }
+
+    /// Evaluate a test, tracing it with its result before the tests nested in it
+    fn traced_test(&mut self, keyword: &str, test: &Test) -> bool {
+        let index = self.outcome.trace.len();
+        let description = describe_test(test);
+        let event = if keyword.is_empty() {
+            description
+        } else {
+            format!("{} {}", keyword, description)
+        };
+        self.trace(event, test.range);
+        self.depth += 1;
+        let unsupported = self.outcome.unsupported.len();
+        let matched = self.test(test);
+        self.depth -= 1;
+        let note = if self.outcome.unsupported.len() > unsupported && test.tests.is_empty() {
+            " (not simulated)"
+        } else {
+            ""
+        };
+        self.outcome.trace[index]
+            .event
+            .push_str(&format!(" -> {}{}", matched, note));
+        matched
+    }
+
+    /// `set [modifiers] name value` (RFC 5229 section 4)
+    fn set(&mut self, arguments: &Arguments) {
+        let values = self.positional_strings(arguments);
+        let [name, value] = values.as_slice() else {
+            return;
+        };
+        let mut value = value.clone();
+        for (modifier, _) in variables::SET_MODIFIERS.iter().rev() {
+            if !arguments.has(modifier) {
+                continue;
+            }
+            value = match *modifier {
+                ":lower" => value.to_lowercase(),
+                ":upper" => value.to_uppercase(),
+                ":lowerfirst" => change_first(&value, |c| c.to_lowercase().collect()),
+                ":upperfirst" => change_first(&value, |c| c.to_uppercase().collect()),
+                ":quotewildcard" => value
+                    .chars()
+                    .flat_map(|c| match c {
+                        '*' | '?' | '\\' => vec!['\\', c],
+                        _ => vec![c],
+                    })
+                    .collect(),
+                ":quoteregex" => regex::escape(&value),
+                ":length" => value.chars().count().to_string(),
+                _ => value,
+            };
+        }
+        self.variables.insert(name.to_lowercase(), value);
+    }
+
+    /// `addflag`, `setflag` and `removeflag` on the internal flags or a variable (RFC 5232)
+    fn change_flags(&mut self, command: &str, arguments: &Arguments) {
+        let (variable, list) = match arguments.positional.as_slice() {
+            [variable, list] => (self.strings(Some(variable)).first().cloned(), list),
+            [list] => (None, list),
+            _ => return,
+        };
+        let changed = split_flags(&self.strings(Some(list)));
+        let mut flags = match &variable {
+            Some(name) => split_flags(&[self.variable(name)]),
+            None => self.flags.clone(),
+        };
+        match command {
+            "setflag" => flags = changed,
+            "addflag" => {
+                for flag in changed {
+                    if !flags.iter().any(|f| f.eq_ignore_ascii_case(&flag)) {
+                        flags.push(flag);
+                    }
+                }
+            }
+            _ => flags.retain(|f| !changed.iter().any(|c| c.eq_ignore_ascii_case(f))),
+        }
+        match variable {
+            Some(name) => {
+                self.variables.insert(name.to_lowercase(), flags.join(" "));
+            }
+            None => self.flags = flags,
+        }
+    }
+
+    fn test(&mut self, test: &Test) -> bool {
+        let arguments = Arguments::new(&test.arguments);
+        match test.name.as_str() {
+            "true" => true,
+            "false" => false,
+            "not" => test.tests.first().is_some_and(|t| !self.traced_test("", t)),
+            "allof" => test.tests.iter().all(|t| self.traced_test("", t)),
+            "anyof" => test.tests.iter().any(|t| self.traced_test("", t)),
+            "exists" => self
+                .strings(arguments.positional.first().copied())
+                .iter()
+                .all(|name| !self.message.header(name).is_empty()),
+            "size" => {
+                let limit = arguments.positional.first().and_then(|a| match a {
+                    Argument::Number { raw, .. } => crate::parser::number_value(raw),
+                    _ => None,
+                });
+                let size = self.message.size as u64;
+                match (limit, arguments.first_of(&[":over", ":under"])) {
+                    (Some(limit), Some(":over")) => size > limit,
+                    (Some(limit), Some(_)) => size < limit,
+                    _ => false,
+                }
+            }
+            "header" | "address" | "envelope" => {
+                let names = self.strings(arguments.positional.first().copied());
+                let mut values = Vec::new();
+                for name in &names {
+                    let fields: Vec<String> = if test.name == "envelope" {
+                        match name.to_lowercase().as_str() {
+                            "from" => self.envelope.from.iter().cloned().collect(),
+                            "to" => self.envelope.to.iter().cloned().collect(),
+                            _ => Vec::new(),
+                        }
+                    } else {
+                        self.message.header(name).into_iter().map(str::to_string).collect()
+                    };
+                    if test.name == "header" {
+                        values.extend(fields);
+                    } else {
+                        values.extend(
+                            fields
+                                .iter()
+                                .flat_map(|field| addresses(field))
+                                .filter_map(|address| address_part(&arguments, &address)),
+                        );
+                    }
+                }
+                let keys = self.strings(arguments.positional.get(1).copied());
+                self.matches(&arguments, &values, &keys)
+            }
+            "body" => {
+                let values = vec![self.message.body.clone()];
+                let keys = self.strings(arguments.positional.first().copied());
+                self.matches(&arguments, &values, &keys)
+            }
+            "string" => {
+                let values = self.strings(arguments.positional.first().copied());
+                let keys = self.strings(arguments.positional.get(1).copied());
+                self.matches(&arguments, &values, &keys)
+            }
+            "hasflag" => {
+                let (values, keys) = match arguments.positional.as_slice() {
+                    [variables, keys] => (
+                        split_flags(
+                            &self
+                                .strings(Some(variables))
+                                .iter()
+                                .map(|name| self.variable(name))
+                                .collect::<Vec<_>>(),
+                        ),
+                        keys,
+                    ),
+                    [keys] => (self.flags.clone(), keys),
+                    _ => return false,
+                };
+                let keys = self.strings(Some(keys));
+                self.matches(&arguments, &values, &keys)
+            }
+            _ => {
+                self.outcome.unsupported.push(Unsupported {
+                    name: test.name.clone(),
+                    range: test.range,
+                });
+                false
+            }
+        }
+    }
+
+    /// Whether any value matches any key, setting the match variables on success
+    fn matches(&mut self, arguments: &Arguments, values: &[String], keys: &[String]) -> bool {
+        let matcher = self.matcher(arguments);
+        if matcher.match_type == ":count" {
+            let count = values.iter().filter(|v| !v.is_empty()).count().to_string();
+            let numeric = Matcher {
+                match_type: ":value",
+                comparator: "i;ascii-numeric".to_string(),
+                relation: matcher.relation,
+            };
+            return keys.iter().any(|key| self.compare(&numeric, &count, key));
+        }
+        for value in values {
+            for key in keys {
+                if let Some(captures) = self.captures(&matcher, value, key) {
+                    if !captures.is_empty() {
+                        self.match_values = captures;
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn matcher(&self, arguments: &Arguments) -> Matcher {
+        let string = |tag: &str| {
+            arguments
+                .value(tag)
+                .and_then(|a| a.strings().first().map(|s| s.value.to_lowercase()))
+        };
+        let match_type =
+            arguments.first_of(&[":is", ":contains", ":matches", ":regex", ":value", ":count"]);
+        Matcher {
+            match_type: match_type.unwrap_or(":is"),
+            comparator: string(":comparator").unwrap_or_else(|| "i;ascii-casemap".to_string()),
+            relation: match_type.and_then(string).unwrap_or_else(|| "eq".to_string()),
+        }
+    }
+
+    fn compare(&self, matcher: &Matcher, value: &str, key: &str) -> bool {
+        self.captures(matcher, value, key).is_some()
+    }
+
+    /// Match a value against a key; the match variables are returned for `:matches`/`:regex`
+    fn captures(&self, matcher: &Matcher, value: &str, key: &str) -> Option<Vec<String>> {
+        let casemap = matcher.comparator != "i;octet";
+        let fold = |s: &str| if casemap { s.to_lowercase() } else { s.to_string() };
+        match matcher.match_type {
+            ":contains" => fold(value).contains(&fold(key)).then(Vec::new),
+            ":matches" | ":regex" => {
+                let pattern = if matcher.match_type == ":matches" {
+                    wildcard_regex(key)
+                } else {
+                    key.to_string()
+                };
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(casemap)
+                    .dot_matches_new_line(true)
+                    .build()
+                    .ok()?;
+                let captures = regex.captures(value)?;
+                Some(
+                    captures
+                        .iter()
+                        .map(|group| group.map_or(String::new(), |g| g.as_str().to_string()))
+                        .collect(),
+                )
+            }
+            ":value" => {
+                let ordering = if matcher.comparator == "i;ascii-numeric" {
+                    numeric(value).cmp(&numeric(key))
+                } else {
+                    fold(value).cmp(&fold(key))
+                };
+                use std::cmp::Ordering::*;
+                let holds = match matcher.relation.as_str() {
+                    "gt" => ordering == Greater,
+                    "ge" => ordering != Less,
+                    "lt" => ordering == Less,
+                    "le" => ordering != Greater,
+                    "ne" => ordering != Equal,
+                    _ => ordering == Equal,
+                };
+                holds.then(Vec::new)
+            }
+            _ if matcher.comparator == "i;ascii-numeric" => {
+                (numeric(value) == numeric(key)).then(Vec::new)
+            }
+            _ => (fold(value) == fold(key)).then(Vec::new),
+        }
+    }
+
+    /// String values of an argument with variables expanded
+    fn strings(&self, argument: Option<&Argument>) -> Vec<String> {
+        argument
+            .map(|a| a.strings().iter().map(|s| self.expand(&s.value)).collect())
+            .unwrap_or_default()
+    }
+
+    /// String values of all positional arguments
+    fn positional_strings(&self, arguments: &Arguments) -> Vec<String> {
+        arguments
+            .positional
+            .iter()
+            .flat_map(|a| self.strings(Some(a)))
+            .collect()
+    }
+
+    fn variable(&self, name: &str) -> String {
+        self.variables
+            .get(&name.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace `${...}` references; unknown variables expand to the empty string
+    fn expand(&self, value: &str) -> String {
+        let references = variables::references(value);
+        if references.is_empty() {
+            return value.to_string();
+        }
+        let chars: Vec<char> = value.chars().collect();
+        let mut expanded = String::new();
+        let mut idx = 0;
+        for reference in references {
+            expanded.extend(&chars[idx..reference.start]);
+            match reference.match_index() {
+                Some(index) => {
+                    expanded.push_str(self.match_values.get(index).map_or("", String::as_str))
+                }
+                None => expanded.push_str(&self.variable(&reference.name)),
+            }
+            idx = reference.end;
+        }
+        expanded.extend(&chars[idx..]);
+        expanded
+    }
+}
+
+/// A test as written in the script; nested tests are traced on their own
+fn describe_test(test: &Test) -> String {
+    if !test.tests.is_empty() {
+        return test.name.clone();
+    }
+    let mut parts = vec![test.name.clone()];
+    for argument in &test.arguments {
+        parts.push(match argument {
+            Argument::String(s) => format!("{:?}", s.value),
+            Argument::StringList { items, .. } => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|s| format!("{:?}", s.value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Argument::Number { raw, .. } => raw.clone(),
+            Argument::Tag { name, .. } => name.clone(),
+        });
+    }
+    parts.join(" ")
+}
+
+/// Human readable trace of a run: one line per step with its line number, then a summary
+pub fn trace_text(outcome: &Outcome, title: &str) -> String {
+    let mut text = format!("Trace of {}\n\n", title);
+    for entry in &outcome.trace {
+        text.push_str(&format!(
+            "{:>4}  {}{}\n",
+            entry.range.start.line + 1,
+            "  ".repeat(entry.depth),
+            entry.event
+        ));
+    }
+    if outcome.trace.is_empty() {
+        text.push_str("No tests or actions were executed\n");
+    }
+    text.push('\n');
+    if outcome.stopped {
+        text.push_str("Execution ended at 'stop'\n");
+    }
+    if outcome.implicit_keep {
+        text.push_str("The message is kept in the inbox (implicit keep)\n");
+    } else {
+        text.push_str("The implicit keep was cancelled\n");
+    }
+    if !outcome.unsupported.is_empty() {
+        text.push_str(&format!(
+            "{} test(s) could not be simulated and count as false\n",
+            outcome.unsupported.len()
+        ));
+    }
+    text
+}
+
+/// Parameters of `sieve/traceMessage`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceMessageParams {
+    /// An open document
+    pub text_document: TextDocumentIdentifier,
+    /// The raw message; the sidecar `.eml` file of the script when missing
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The envelope; derived from the message headers when missing
+    #[serde(default)]
+    pub envelope: Option<Envelope>,
+}
+
+/// Result of `sieve/traceMessage`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceMessageResult {
+    /// URI for the virtual document, distinct for every script and message
+    pub uri: Url,
+    /// Content of the virtual document
+    pub text: String,
+    pub outcome: Outcome,
+}
+
+/// Translate a `:matches` pattern into an anchored regex with a group per wildcard
+pub fn wildcard_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str("(.*?)"),
+            '?' => regex.push_str("(.)"),
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Sort key of `i;ascii-numeric`: the leading digits, with strings without any sorting last
+/// (RFC 4790 section 9.1)
+fn numeric(value: &str) -> (bool, u128) {
+    let digits: String = value.chars().take_while(char::is_ascii_digit).collect();
+    match digits.parse() {
+        Ok(number) => (false, number),
+        Err(_) => (true, 0),
+    }
+}
+
+/// Split flag lists on whitespace, dropping duplicates (RFC 5232 section 3)
+fn split_flags(lists: &[String]) -> Vec<String> {
+    let mut flags: Vec<String> = Vec::new();
+    for flag in lists.iter().flat_map(|list| list.split_whitespace()) {
+        if !flags.iter().any(|f| f.eq_ignore_ascii_case(flag)) {
+            flags.push(flag.to_string());
+        }
+    }
+    flags
+}
+
+fn change_first(value: &str, change: impl Fn(char) -> String) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => change(first) + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Mail addresses in a header value such as `"Doe, Jane" <jane@example.com>, bob@example.com`
+pub fn addresses(value: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut angle = false;
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => angle = true,
+            '>' if !quoted => angle = false,
+            ',' if !quoted && !angle => {
+                entries.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    entries.push(current);
+
+    lazy_static::lazy_static! {
+        static ref ANGLE: Regex = Regex::new(r"<([^>]*)>").unwrap();
+    }
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let address = match ANGLE.captures(entry) {
+                Some(captures) => captures[1].trim().to_string(),
+                None => entry.trim().to_string(),
+            };
+            (!address.is_empty()).then_some(address)
+        })
+        .collect()
+}
+
+/// The part of an address selected by `:localpart`, `:domain`, `:user` or `:detail`
+/// `None` when the part does not exist, e.g. `:detail` without a `+` (RFC 5233 section 4)
+fn address_part(arguments: &Arguments, address: &str) -> Option<String> {
+    let (local, domain) = address.rsplit_once('@').unwrap_or((address, ""));
+    let part = arguments.first_of(&[":localpart", ":domain", ":user", ":detail"]);
+    Some(match part {
+        Some(":localpart") => local.to_string(),
+        Some(":domain") => domain.to_string(),
+        Some(":user") => local.split_once('+').map_or(local, |(user, _)| user).to_string(),
+        Some(_) => local.split_once('+')?.1.to_string(),
+        None => address.to_string(),
+    })
+}
@@ -1,3 +1,45 @@
+pub mod anonymize;
+pub mod builder;
+pub mod cli;
+pub mod coexistence;
+pub mod config;
 pub mod datastructures;
+pub mod deep;
+pub mod dialect;
+pub mod directives;
+pub mod documentation;
+pub mod encoding;
+pub mod errors;
+pub mod explain;
+pub mod external;
+pub mod fixtures;
+pub mod format;
+pub mod gmail;
+pub mod highlight;
+pub mod history;
+pub mod i18n;
+pub mod implication;
+pub mod include;
+pub mod incremental;
+pub mod interpreter;
+pub mod logging;
 pub mod lsp;
+pub mod mailbox;
+pub mod managesieve;
+pub mod manifest;
+pub mod message;
+pub mod metrics;
+pub mod migration;
+pub mod notify;
+pub mod outline;
+pub mod parser;
+pub mod posix;
+pub mod profile;
+pub mod refactor;
+pub mod requires;
+pub mod roles;
+pub mod rules;
 pub mod sieve;
+pub mod snapshot;
+pub mod status;
+pub mod variables;
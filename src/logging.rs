@@ -0,0 +1,203 @@
+// ================================================================================================
+// SERVER LOGGING
+// ================================================================================================
+//
+// stdout carries the LSP stream, so the server logs to stderr, or to the file given with
+// `--log-file`. Its own events are also relayed to the client as `$/logTrace` notifications
+// at the trace level the client asked for in `initialize` or with `$/setTrace`: `messages`
+// relays info and above, `verbose` debug and above with the event's origin and fields.
+//
+//   sieve-lsp [--stdio] [--log-file PATH] [--log-level error|warn|info|debug|trace]
+//
+// stdio is the only transport, so `--stdio`, which editor clients pass, changes nothing. Other
+// flags clients add, like `--clientProcessId=1234`, are ignored with a warning.
+//
+// Command line modes do not log unless they are given `--log-level` as well.
+
+use std::fmt::{self, Write as _};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tower_lsp::lsp_types::{LogTraceParams, TraceValue};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Target prefix of the events that are relayed to the client
+const RELAYED_TARGET: &str = "sieve_language_server";
+
+const LOG_LEVEL_EXPECTED: &str = "--log-level expects one of error, warn, info, debug, trace";
+
+/// Logging options of the server mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogOptions {
+    /// File the log is appended to instead of stderr
+    pub file: Option<PathBuf>,
+    pub level: Level,
+    /// Unknown flags, logged as a warning once logging is set up
+    pub ignored: Vec<String>,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions {
+            file: None,
+            level: Level::INFO,
+            ignored: Vec::new(),
+        }
+    }
+}
+
+/// Whether the arguments start the language server rather than a command line mode
+/// Command line modes start with their name, the server with flags or nothing.
+pub fn is_server_invocation(args: &[String]) -> bool {
+    args.first().is_none_or(|arg| arg.starts_with("--"))
+}
+
+/// Parse the arguments of the server mode
+pub fn parse_options(args: &[String]) -> Result<LogOptions, String> {
+    let mut options = LogOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-file" => match args.next() {
+                Some(path) => options.file = Some(PathBuf::from(path)),
+                None => return Err("--log-file expects a path".to_string()),
+            },
+            "--log-level" => match args.next().map(|level| level.parse::<Level>()) {
+                Some(Ok(level)) => options.level = level,
+                Some(Err(_)) | None => return Err(LOG_LEVEL_EXPECTED.to_string()),
+            },
+            "--stdio" => {}
+            flag if flag.starts_with("--") => options.ignored.push(flag.to_string()),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Remove `--log-level LEVEL` from the arguments of a command line mode and return the level
+pub fn take_log_level(args: &mut Vec<String>) -> Result<Option<Level>, String> {
+    let Some(index) = args.iter().position(|arg| arg == "--log-level") else {
+        return Ok(None);
+    };
+    let level = args.get(index + 1).and_then(|level| level.parse::<Level>().ok());
+    match level {
+        Some(level) => {
+            args.drain(index..index + 2);
+            Ok(Some(level))
+        }
+        None => Err(LOG_LEVEL_EXPECTED.to_string()),
+    }
+}
+
+/// Install the subscriber of the server mode
+/// Events go to the log file or stderr, and through the relay if there is one.
+pub fn init(options: &LogOptions, relay: Option<TraceRelay>) -> std::io::Result<()> {
+    let writer = match &options.file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    let output = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(options.file.is_none())
+        .with_filter(LevelFilter::from_level(options.level));
+    tracing_subscriber::registry().with(output).with(relay).init();
+    Ok(())
+}
+
+/// Trace level requested by the client, shared between the server and its relay
+#[derive(Debug, Clone, Default)]
+pub struct TraceLevel(Arc<AtomicU8>);
+
+impl TraceLevel {
+    pub fn get(&self) -> TraceValue {
+        match self.0.load(Ordering::Relaxed) {
+            1 => TraceValue::Messages,
+            2 => TraceValue::Verbose,
+            _ => TraceValue::Off,
+        }
+    }
+
+    pub fn set(&self, value: TraceValue) {
+        let level = match value {
+            TraceValue::Off => 0,
+            TraceValue::Messages => 1,
+            TraceValue::Verbose => 2,
+        };
+        self.0.store(level, Ordering::Relaxed);
+    }
+}
+
+/// Layer turning server events into `$/logTrace` parameters at the requested trace level
+/// The parameters are sent to a channel, since events are recorded outside of any request.
+pub struct TraceRelay {
+    level: TraceLevel,
+    sender: mpsc::UnboundedSender<LogTraceParams>,
+}
+
+impl TraceRelay {
+    /// A relay and the receiving end the server forwards to the client
+    pub fn new(level: TraceLevel) -> (Self, mpsc::UnboundedReceiver<LogTraceParams>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (TraceRelay { level, sender }, receiver)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for TraceRelay {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let metadata = event.metadata();
+        let (relayed, verbose) = match self.level.get() {
+            TraceValue::Off => return,
+            TraceValue::Messages => (Level::INFO, false),
+            TraceValue::Verbose => (Level::DEBUG, true),
+        };
+        if *metadata.level() > relayed || !metadata.target().starts_with(RELAYED_TARGET) {
+            return;
+        }
+        let mut fields = FieldText::default();
+        event.record(&mut fields);
+        let details = verbose.then(|| {
+            let mut details = format!("{} {}", metadata.level(), metadata.target());
+            if !fields.others.is_empty() {
+                let _ = write!(details, " {}", fields.others);
+            }
+            details
+        });
+        let _ = self.sender.send(LogTraceParams {
+            message: fields.message,
+            verbose: details,
+        });
+    }
+}
+
+/// The message of an event and its other fields as `name=value` pairs
+#[derive(Default)]
+struct FieldText {
+    message: String,
+    others: String,
+}
+
+impl Visit for FieldText {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            if !self.others.is_empty() {
+                self.others.push(' ');
+            }
+            let _ = write!(self.others, "{}={:?}", field.name(), value);
+        }
+    }
+}
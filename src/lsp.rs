@@ -2,13 +2,138 @@
 // IMPORTS AND DEPENDENCIES
 // ================================================================================================
 
+use crate::anonymize;
+use crate::builder::{self, BuildConditionParams, BuildConditionResult, NewRuleParams};
+use crate::interpreter::{self, TraceMessageParams, TraceMessageResult};
+use crate::coexistence;
+use crate::config;
 use crate::datastructures::*;
+use crate::dialect;
+use crate::documentation;
+use crate::encoding::PositionEncoding;
+use crate::format;
+use crate::gmail;
+use crate::highlight;
+use crate::errors::{self, InternalError};
+use crate::explain;
+use crate::i18n::Localizer;
+use crate::history::DiagnosticsHistory;
+use crate::include;
+use crate::managesieve::{ManageSieveError, Operation};
+use crate::manifest::MANIFEST_FILE;
+use crate::metrics::{self, MetricsParams, ScriptMetrics};
+use crate::outline;
+use crate::parser;
+use crate::refactor;
+use crate::requires::RequireLayout;
+use crate::roles;
+use crate::snapshot;
+use crate::status::ServerStatus;
+use crate::variables;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use crate::sieve::*;
-use tower_lsp::jsonrpc::Result;
+use serde_json::Value;
+use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{LanguageServer};
 use tracing::{debug, info, warn};
 
+/// Code action kind of merging require statements into one list
+/// Organizing imports merges them as well, but also sorts and prunes the list.
+pub const SOURCE_MERGE_REQUIRES: &str = "source.mergeRequires";
+
+/// Code action kind of splitting require lists into one statement per extension
+pub const SOURCE_SPLIT_REQUIRES: &str = "source.splitRequires";
+
+/// Command that summarizes the diagnostics history of the workspace
+pub const COMMAND_DIAGNOSTICS_REPORT: &str = "sieve.diagnosticsReport";
+
+/// Command that inlines all includes of an entry script into one standalone script
+pub const COMMAND_EXPORT_STANDALONE: &str = "sieve.exportStandalone";
+
+/// Command that joins a statement wrapped over several lines onto one line
+pub const COMMAND_JOIN_LINES: &str = "sieve.joinLines";
+
+/// Command that splits a long test across lines at argument boundaries
+pub const COMMAND_SPLIT_STATEMENT: &str = "sieve.splitStatement";
+
+/// Command that lists workspace scripts no deployed entry point uses
+pub const COMMAND_ORPHANED_SCRIPTS: &str = "sieve.orphanedScripts";
+
+/// Command that returns a copy of a script with personal content replaced by placeholders
+pub const COMMAND_ANONYMIZE_SCRIPT: &str = "sieve.anonymizeScript";
+
+/// Command that assembles a Markdown report of the last internal error for a bug report
+pub const COMMAND_COPY_ERROR_REPORT: &str = "sieve.copyErrorReport";
+
+/// Command that reads the capabilities of the configured ManageSieve server
+pub const COMMAND_REMOTE_CAPABILITIES: &str = "sieve.remoteCapabilities";
+
+/// Command that lists the scripts stored on the ManageSieve server
+pub const COMMAND_LIST_REMOTE_SCRIPTS: &str = "sieve.listRemoteScripts";
+
+/// Command that returns the content of a script stored on the ManageSieve server
+pub const COMMAND_DOWNLOAD_SCRIPT: &str = "sieve.downloadScript";
+
+/// Command that stores a document on the ManageSieve server
+pub const COMMAND_UPLOAD_SCRIPT: &str = "sieve.uploadScript";
+
+/// Command that makes a script on the ManageSieve server the active one
+pub const COMMAND_ACTIVATE_SCRIPT: &str = "sieve.activateScript";
+
+/// Command that deletes a script from the ManageSieve server
+pub const COMMAND_DELETE_SCRIPT: &str = "sieve.deleteScript";
+
+/// Command that lets the ManageSieve server check a document without storing it
+pub const COMMAND_CHECK_REMOTE: &str = "sieve.checkRemote";
+
+/// Command that runs a document against a sample message and reports what the script would do
+pub const COMMAND_TEST_MESSAGE: &str = "sieve.testMessage";
+
+/// Command behind the "Test rule" code lens: runs one top-level rule against the sample messages
+pub const COMMAND_TEST_RULE: &str = "sieve.testRule";
+
+/// Command that runs a document against every sample message and counts the matches per rule
+pub const COMMAND_RUN_CORPUS: &str = "sieve.runCorpus";
+
+/// Command that returns a JSON summary of a script: its rules, their conditions and actions
+pub const COMMAND_EXPLAIN_SCRIPT: &str = "sieve.explainScript";
+
+/// Command that returns the edit adding a rule built from a field, operator, value and action
+pub const COMMAND_NEW_RULE: &str = "sieve.newRule";
+
+/// Command that converts an exported Gmail filter file into the text of a new Sieve script
+pub const COMMAND_IMPORT_GMAIL_FILTERS: &str = "sieve.importGmailFilters";
+
+/// Command that rewrites legacy keys of the workspace configuration file and reports them
+pub const COMMAND_MIGRATE_SETTINGS: &str = "sieve.migrateSettings";
+
+/// All commands the server handles through workspace/executeCommand
+pub const SUPPORTED_COMMANDS: &[&str] = &[
+    COMMAND_DIAGNOSTICS_REPORT,
+    COMMAND_EXPORT_STANDALONE,
+    COMMAND_JOIN_LINES,
+    COMMAND_SPLIT_STATEMENT,
+    COMMAND_ORPHANED_SCRIPTS,
+    COMMAND_ANONYMIZE_SCRIPT,
+    COMMAND_COPY_ERROR_REPORT,
+    COMMAND_REMOTE_CAPABILITIES,
+    COMMAND_LIST_REMOTE_SCRIPTS,
+    COMMAND_DOWNLOAD_SCRIPT,
+    COMMAND_UPLOAD_SCRIPT,
+    COMMAND_ACTIVATE_SCRIPT,
+    COMMAND_DELETE_SCRIPT,
+    COMMAND_CHECK_REMOTE,
+    COMMAND_TEST_MESSAGE,
+    COMMAND_TEST_RULE,
+    COMMAND_RUN_CORPUS,
+    COMMAND_EXPLAIN_SCRIPT,
+    COMMAND_NEW_RULE,
+    COMMAND_IMPORT_GMAIL_FILTERS,
+    COMMAND_MIGRATE_SETTINGS,
+];
+
 // ================================================================================================
 // LSP PROTOCOL IMPLEMENTATION
 // ================================================================================================
@@ -24,17 +149,82 @@ impl LanguageServer for SieveLanguageServer {
         info!("Client: {:?}", params.client_info);
         info!("Root URI: {:?}", params.root_uri);
 
+        // Remember the workspace root and restore state persisted there
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| folder.uri.clone())
+            .or(params.root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+        if let Some(root) = &root {
+            *self.history.write().await = DiagnosticsHistory::load(root);
+        }
+        *self.workspace_root.write().await = root;
+        self.reload_workspace_config().await;
+        *self.watch_config_file.write().await = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        *self.pull_configuration.write().await = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+
+        let encoding = PositionEncoding::negotiate(&params.capabilities);
+        info!("Position encoding: {:?}", encoding);
+        *self.position_encoding.write().await = encoding;
+
+        self.trace.set(params.trace.unwrap_or_default());
+        *self.work_done_progress.write().await = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+
+        // The thawed index and the index cache spare parsing every unchanged script again;
+        // the rest is scanned once initialized, where the progress can be shown
+        self.restore_state().await;
+
+        let active_tools = coexistence::active_tools(params.initialization_options.as_ref());
+        if !active_tools.is_empty() {
+            info!("Other Sieve tools active: {:?}", active_tools);
+        }
+        *self.active_tools.write().await = active_tools;
+
+        info!("Locale: {:?}", params.locale);
+        *self.localizer.write().await = Localizer::new(params.locale.as_deref());
+
         // Return server capabilities - tells the editor what features we support
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                // We support incremental text synchronization
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::INCREMENTAL,
+                // Columns are counted in the negotiated encoding (UTF-16 unless agreed otherwise)
+                position_encoding: Some(encoding.kind()),
+
+                // We support incremental text synchronization and want to hear about saves
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(false),
+                        })),
+                        // Lets `requires.autoManage`, `requires.organizeOnSave` and
+                        // `format.onSave` rewrite documents before saving
+                        will_save_wait_until: Some(true),
+                        ..Default::default()
+                    },
                 )),
 
-                // We provide completion suggestions
+                // We provide completion suggestions, documented once an item is selected
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
+                    resolve_provider: Some(true),
                     trigger_characters: Some(vec![":".to_string()]), // Trigger on colon for tags
                     work_done_progress_options: WorkDoneProgressOptions::default(),
                     all_commit_characters: None,
@@ -54,10 +244,44 @@ impl LanguageServer for SieveLanguageServer {
                     },
                 )),
 
+                // Outline, folding and highlights derived from the syntax tree
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+
+                // "Test rule" above every top-level rule
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+
+                // Layout-only formatting, shared with `sieve-lsp fmt`
+                document_formatting_provider: Some(OneOf::Left(true)),
+
+                // Quick fixes for diagnostics that carry a replacement
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::SOURCE_FIX_ALL,
+                            CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                            CodeActionKind::new(SOURCE_MERGE_REQUIRES),
+                            CodeActionKind::new(SOURCE_SPLIT_REQUIRES),
+                        ]),
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                        resolve_provider: Some(false),
+                    },
+                )),
+
+                // Custom commands (reports, refactorings, ...)
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: SUPPORTED_COMMANDS.iter().map(|c| c.to_string()).collect(),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+
                 // Future capabilities we might add:
                 // definition_provider: Some(OneOf::Left(true)), // Go to definition
-                // document_formatting_provider: Some(OneOf::Left(true)), // Code formatting
-                // document_symbol_provider: Some(OneOf::Left(true)), // Document outline
                 ..Default::default()
             },
             server_info: Some(ServerInfo {
@@ -71,6 +295,54 @@ impl LanguageServer for SieveLanguageServer {
     /// Server is now ready to handle requests
     async fn initialized(&self, _: InitializedParams) {
         info!("Sieve Language Server initialized successfully");
+        self.notify_legacy_settings().await;
+
+        // Settings committed to the workspace apply as soon as they are edited, and scripts
+        // changed outside the editor, e.g. by a checkout, update the workspace diagnostics
+        if *self.watch_config_file.read().await {
+            let watchers = |globs: Vec<String>| {
+                let watchers = globs
+                    .into_iter()
+                    .map(|glob| FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(glob),
+                        kind: None,
+                    })
+                    .collect();
+                serde_json::to_value(DidChangeWatchedFilesRegistrationOptions { watchers }).ok()
+            };
+            let registrations = vec![
+                Registration {
+                    id: "sieve-config-watcher".to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: watchers(
+                        config::CONFIG_FILES.iter().map(|name| format!("**/{}", name)).collect(),
+                    ),
+                },
+                Registration {
+                    id: "sieve-script-watcher".to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: watchers(vec![
+                        "**/*.sieve".to_string(),
+                        format!("**/{}", MANIFEST_FILE),
+                    ]),
+                },
+            ];
+            if let Err(err) = self.client.register_capability(registrations).await {
+                warn!("Cannot watch the workspace files: {}", err);
+            }
+        }
+
+        // Settings are pulled as well as pushed, as not every client sends them unasked
+        let mut revalidate = false;
+        if self.request_client_settings().await {
+            self.refresh_remote_capabilities().await;
+            revalidate = true;
+        }
+        revalidate |= self.refresh_orphaned_scripts().await;
+        if revalidate {
+            self.revalidate_open_documents().await;
+        }
+        self.start_status_reports();
 
         // Log server capabilities for debugging
         self.client
@@ -82,6 +354,7 @@ impl LanguageServer for SieveLanguageServer {
     /// Cleanup and prepare for exit
     async fn shutdown(&self) -> Result<()> {
         info!("Shutting down Sieve Language Server");
+        self.freeze_state().await;
         Ok(())
     }
 
@@ -91,21 +364,30 @@ impl LanguageServer for SieveLanguageServer {
         info!("Document opened: {}", params.text_document.uri);
 
         // Create and store document in our cache
+        let encoding = *self.position_encoding.read().await;
         let document = SieveDocument::new(
             params.text_document.uri.clone(),
-            params.text_document.text,
+            params.text_document.text.clone(),
             params.text_document.version,
-        );
+        )
+        .with_encoding(encoding);
 
         self.document_map
             .insert(params.text_document.uri.clone(), document);
+        self.restore_document(&params.text_document.uri, &params.text_document.text)
+            .await;
 
         // Validate the document and send diagnostics
-        let diagnostics = self.validate_document(&params.text_document.uri).await;
+        let Some(diagnostics) = self.validate_guarded(&params.text_document.uri).await else {
+            return;
+        };
 
-        self.client
-            .publish_diagnostics(params.text_document.uri, diagnostics, None)
-            .await;
+        self.publish_diagnostics(
+            params.text_document.uri,
+            diagnostics,
+            Some(params.text_document.version),
+        )
+        .await;
     }
 
     /// Called when a document is modified in the editor
@@ -129,11 +411,59 @@ impl LanguageServer for SieveLanguageServer {
             return;
         }
 
-        // Re-validate the document and send updated diagnostics
-        let diagnostics = self.validate_document(&params.text_document.uri).await;
+        // Re-validate once typing pauses; stale results are dropped by version
+        self.schedule_validation(params.text_document.uri).await;
+    }
 
-        self.client
-            .publish_diagnostics(params.text_document.uri, diagnostics, None)
+    /// Called before a document is saved; the returned edits are applied to the saved text
+    async fn will_save_wait_until(
+        &self,
+        params: WillSaveTextDocumentParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let edits = self.will_save_edits(&params.text_document.uri).await;
+        debug!("Save-time edits: {:?}", edits);
+        Ok((!edits.is_empty()).then_some(edits))
+    }
+
+    /// Called when a document is saved in the editor
+    /// Saves are the checkpoints recorded in the diagnostics history
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        info!("Document saved: {}", params.text_document.uri);
+
+        // A saved script may have gained or lost includes, which changes what is orphaned
+        if self.refresh_orphaned_scripts().await {
+            let others: Vec<Url> = self
+                .document_map
+                .iter()
+                .map(|item| item.key().clone())
+                .filter(|uri| *uri != params.text_document.uri)
+                .collect();
+            for uri in others {
+                if let Some(diagnostics) = self.validate_guarded(&uri).await {
+                    let version = self.document_version(&uri);
+                    self.publish_diagnostics(uri, diagnostics, version).await;
+                }
+            }
+        }
+
+        // A validation abandoned for a newer version records nothing; that version is checked
+        // on its own
+        let Some(diagnostics) = self.validate_guarded(&params.text_document.uri).await else {
+            return;
+        };
+
+        {
+            let mut history = self.history.write().await;
+            history.record(params.text_document.uri.as_str(), &diagnostics);
+            self.persist_history(&history).await;
+        }
+
+        // The external checker reads the file from disk and the deep analysis is too slow for
+        // every keystroke, so they only run on save
+        self.run_external_linter(&params.text_document.uri).await;
+        self.analyze_deeply(&params.text_document.uri).await;
+        let version = self.document_version(&params.text_document.uri);
+        self.publish_diagnostics(params.text_document.uri, diagnostics, version)
             .await;
     }
 
@@ -144,6 +474,12 @@ impl LanguageServer for SieveLanguageServer {
 
         // Remove from cache
         self.document_map.remove(&params.text_document.uri);
+        self.external_diagnostics.remove(&params.text_document.uri);
+        self.deep_diagnostics.remove(&params.text_document.uri);
+        self.validation_times.remove(&params.text_document.uri);
+        if let Some((_, pending)) = self.pending_validations.remove(&params.text_document.uri) {
+            pending.abort();
+        }
 
         // Clear diagnostics for this document
         self.client
@@ -159,16 +495,40 @@ impl LanguageServer for SieveLanguageServer {
             params.text_document_position
         );
 
-        let completions = self
-            .get_completions(
-                &params.text_document_position.text_document.uri,
-                params.text_document_position.position,
-            )
-            .await;
+        let uri = &params.text_document_position.text_document.uri;
+        let Some(position) = self
+            .document_map
+            .get(uri)
+            .map(|doc| doc.to_char_position(params.text_document_position.position))
+        else {
+            return Ok(Some(CompletionResponse::Array(Vec::new())));
+        };
+        let mut completions = self.get_completions(uri, position).await;
+
+        // Documentation is left to completionItem/resolve
+        let localizer = self.localizer.read().await;
+        let document = self.document_map.get(uri);
+        for item in &mut completions {
+            item.detail = item.detail.as_deref().map(|d| localizer.translate(d));
+            if let (Some(CompletionTextEdit::Edit(edit)), Some(document)) =
+                (&mut item.text_edit, &document)
+            {
+                edit.range = document.to_client_range(edit.range);
+            }
+        }
 
         Ok(Some(CompletionResponse::Array(completions)))
     }
 
+    /// Add the documentation of the completion item the user selected
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        let mut item = self.resolve_completion(item).await;
+        if let Some(Documentation::MarkupContent(doc)) = &mut item.documentation {
+            doc.value = self.localizer.read().await.translate_lines(&doc.value);
+        }
+        Ok(item)
+    }
+
     /// Handle hover requests
     /// Called when user hovers over text to get information
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -178,13 +538,20 @@ impl LanguageServer for SieveLanguageServer {
         );
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let position = params.text_document_position_params.position;
+        let localizer = self.localizer.read().await.clone();
+        let dialects = self.dialects().await;
 
         // Get the document
         let document = match self.document_map.get(uri) {
             Some(doc) => doc,
             None => return Ok(None),
         };
+        let position = document.to_char_position(params.text_document_position_params.position);
+
+        // Words inside text: blocks and comments are prose, not Sieve keywords
+        if self.is_in_multiline_string(uri, position) || self.is_in_comment(uri, position) {
+            return Ok(None);
+        }
 
         // Get the line at cursor position
         let line = match document.get_line(position.line as usize) {
@@ -192,9 +559,106 @@ impl LanguageServer for SieveLanguageServer {
             None => return Ok(None),
         };
 
+        // Numbered match variables point back at the pattern that fills them, named variables
+        // at the `set` statement that assigns them
+        if let Some((name, start, end)) =
+            variables::variable_reference_at(&line, position.character as usize)
+            && let Some(reference) = variables::references(&format!("${{{}}}", name)).pop()
+            && reference.match_index().is_none_or(|index| index <= 9)
+        {
+            let doc = match reference.match_index() {
+                Some(index) => variables::match_variable_hover(document.script(), position, index),
+                None => {
+                    let text = document.get_text();
+                    variables::named_variable_hover(&text, document.script(), position, &name)
+                }
+            };
+            let doc = localizer.translate_lines(&doc);
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: doc,
+                }),
+                range: Some(document.to_client_range(Range {
+                    start: Position {
+                        line: position.line,
+                        character: start as u32,
+                    },
+                    end: Position {
+                        line: position.line,
+                        character: end as u32,
+                    },
+                })),
+            }));
+        }
+
+        // Envelope parts explain which part of the SMTP transaction they test
+        if let Some((doc, range)) =
+            self.get_envelope_part_documentation(document.script(), position)
+        {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: localizer.translate_lines(&doc),
+                }),
+                range: Some(document.to_client_range(range)),
+            }));
+        }
+
+        // Number literals show their value with the quantifier applied
+        if let Some((doc, range)) = self.get_number_documentation(document.script(), position) {
+            return Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(
+                    localizer.translate_lines(&doc),
+                )),
+                range: Some(document.to_client_range(range)),
+            }));
+        }
+
+        // Strings explain what they mean to their command, e.g. an extension or a match key
+        if let Some((string, role)) = roles::string_at(document.script(), position) {
+            let mut doc = roles::role_markdown(&string.decoded(), &role);
+            if role == roles::StringRole::Extension
+                && let Some(note) = dialect::hover_note(&string.value, &dialects)
+            {
+                doc.push_str(&format!("\n{}\n", note));
+            }
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: localizer.translate_lines(&doc),
+                }),
+                range: Some(document.to_client_range(string.range)),
+            }));
+        }
+
+        // Braces document the block and the command it belongs to
+        if matches!(line.chars().nth(position.character as usize), Some('{' | '}'))
+            && let Some(doc) = documentation::block_markdown(document.script(), position)
+        {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: localizer.translate_lines(&doc),
+                }),
+                range: None,
+            }));
+        }
+
         // Find the word at cursor position
         let word = self.get_word_at_position(&line, position.character as usize);
 
+        // Control keywords have structured documentation of their own
+        if let Some(doc) = word.as_deref().and_then(documentation::keyword) {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: localizer.translate_lines(&doc.markdown()),
+                }),
+                range: None,
+            }));
+        }
+
         if let Some(word) = word {
             // Generate hover information based on the word
             let documentation = if SIEVE_TESTS.contains(&word.as_str()) {
@@ -209,7 +673,14 @@ impl LanguageServer for SieveLanguageServer {
                 None
             };
 
+            // Extensions with draft and RFC variants name the one validation assumes
+            let documentation = match (documentation, dialect::hover_note(&word, &dialects)) {
+                (Some(doc), Some(note)) => Some(format!("{}\n\n{}", doc, note)),
+                (doc, note) => doc.or(note),
+            };
+
             if let Some(doc) = documentation {
+                let doc = localizer.translate_lines(&doc);
                 return Ok(Some(Hover {
                     contents: HoverContents::Scalar(MarkedString::String(doc)),
                     range: None,
@@ -220,25 +691,861 @@ impl LanguageServer for SieveLanguageServer {
         Ok(None)
     }
 
+    /// Provide the outline of a document
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let Some(document) = self.document_map.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let text = document.get_text();
+        let mut symbols: Vec<DocumentSymbol> = outline::document_symbols(&text, document.script())
+            .into_iter()
+            .map(|symbol| document.to_client_symbol(symbol))
+            .collect();
+        sort_document_symbols(&mut symbols);
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    /// Find symbols across the workspace, served from the index for scripts that are not open
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        Ok(Some(self.workspace_symbols(&params.query).await))
+    }
+
+    /// Provide the foldable regions of a document
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let Some(document) = self.document_map.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let text = document.get_text();
+        Ok(Some(outline::folding_ranges(&text, document.script())))
+    }
+
+    /// Highlight what belongs to the element at the cursor, e.g. the branches of an `if`
+    /// chain or the uses of a variable
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let Some(document) = self.document_map.get(uri) else {
+            return Ok(None);
+        };
+        let position = document.to_char_position(params.text_document_position_params.position);
+        let text = document.get_text();
+        let highlights = highlight::highlights(&text, document.script(), position)
+            .into_iter()
+            .map(|mut highlight| {
+                highlight.range = document.to_client_range(highlight.range);
+                highlight
+            })
+            .collect();
+        Ok(Some(highlights))
+    }
+
+    /// Put a "Test rule" lens above each top-level `if`, running it against the sample messages
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+        let Some(document) = self.document_map.get(&uri) else {
+            return Ok(None);
+        };
+        let title = self.localizer.read().await.translate("▶ Test rule");
+        let lenses = document
+            .script()
+            .commands
+            .iter()
+            .filter(|command| command.name == "if")
+            .map(|command| CodeLens {
+                range: document.to_client_range(command.name_range),
+                command: Some(Command {
+                    title: title.clone(),
+                    command: COMMAND_TEST_RULE.to_string(),
+                    arguments: Some(vec![
+                        Value::from(uri.to_string()),
+                        Value::from(command.name_range.start.line),
+                    ]),
+                }),
+                data: None,
+            })
+            .collect();
+        Ok(Some(lenses))
+    }
+
+    /// Re-indent a document and tidy its whitespace
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let Some(document) = self.document_map.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let text = document.get_text();
+        let options = self.settings.read().await.format().options(Some(&params.options));
+        let edits = format::edits(&text, &options)
+            .into_iter()
+            .map(|mut edit| {
+                edit.range = document.to_client_range(edit.range);
+                edit
+            })
+            .collect();
+        Ok(Some(edits))
+    }
+
+    /// Offer quick fixes for the diagnostics in the requested range
+    /// Diagnostics that can be fixed carry `{ "title", "replacement" }` in their data field,
+    /// plus the `range` to replace when it is not the diagnostic's own
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        debug!("Code actions requested for {:?}", params.range);
+
+        let uri = params.text_document.uri;
+        let localizer = self.localizer.read().await.clone();
+        let mut actions = Vec::new();
+        for diagnostic in params.context.diagnostics {
+            let Some((title, edits)) = quick_fix(&diagnostic) else {
+                continue;
+            };
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: localizer.translate(title),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+
+        // Lines over the configured length can be shortened by splitting their string lists
+        for (title, edit) in self.line_length_edits(&uri, params.range.start).await {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: localizer.translate(title),
+                kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+        }
+
+        // Source actions are only offered when asked for, e.g. from a source action menu or on
+        // save
+        let requested = |source: &CodeActionKind| {
+            params.context.only.as_ref().is_some_and(|only| {
+                only.iter()
+                    .any(|kind| source.as_str().starts_with(kind.as_str()))
+            })
+        };
+
+        // Fix-all applies every quick fix of the document at once
+        if requested(&CodeActionKind::SOURCE_FIX_ALL)
+            && let Some(action) = self.fix_all(&uri).await
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        // Organizing merges, sorts and prunes the requires; editors may run it on save
+        if requested(&CodeActionKind::SOURCE_ORGANIZE_IMPORTS) {
+            let edits = self.tidy_requires_edits(&uri).await;
+            if !edits.is_empty() {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: localizer.translate("Organize requires"),
+                    kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        // Require statements can be merged into one list, or split into one per extension
+        let layouts = [
+            (
+                CodeActionKind::new(SOURCE_MERGE_REQUIRES),
+                RequireLayout::Merged,
+                "Merge require statements into one list",
+            ),
+            (
+                CodeActionKind::new(SOURCE_SPLIT_REQUIRES),
+                RequireLayout::Split,
+                "Split require lists into one statement per extension",
+            ),
+        ];
+        for (kind, layout, title) in layouts {
+            if !requested(&kind) {
+                continue;
+            }
+            let edits = self.organize_requires_edits(&uri, layout);
+            if edits.is_empty() {
+                continue;
+            }
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: localizer.translate(title),
+                kind: Some(kind),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+        }
+
+        sort_code_actions(&mut actions);
+        Ok(Some(actions))
+    }
+
+    /// Handle custom commands invoked by the editor
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        info!("Executing command: {}", params.command);
+
+        match params.command.as_str() {
+            COMMAND_DIAGNOSTICS_REPORT => {
+                // Optional argument: { "markDeployed": true } to set a new baseline
+                let mark_deployed = params
+                    .arguments
+                    .first()
+                    .and_then(|arg| arg.get("markDeployed"))
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+
+                let mut history = self.history.write().await;
+                let report = history.report();
+                if mark_deployed {
+                    history.mark_deployed();
+                    self.persist_history(&history).await;
+                }
+
+                self.client
+                    .log_message(MessageType::INFO, &report.summary)
+                    .await;
+                Ok(Some(serde_json::to_value(report).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_EXPORT_STANDALONE => {
+                // Argument: the URI of the entry script
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the entry script URI"))?;
+                let path = uri
+                    .to_file_path()
+                    .map_err(|_| Error::invalid_params("Entry script must be a file URI"))?;
+
+                let flattened = include::flatten(&path).map_err(Error::invalid_params)?;
+                for warning in &flattened.warnings {
+                    self.client.log_message(MessageType::WARNING, warning).await;
+                }
+                Ok(Some(serde_json::to_value(flattened).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_ORPHANED_SCRIPTS => {
+                self.refresh_orphaned_scripts().await;
+                let orphans: Vec<String> = self
+                    .orphaned_scripts
+                    .read()
+                    .await
+                    .iter()
+                    .filter_map(|path| Url::from_file_path(path).ok())
+                    .map(|uri| uri.to_string())
+                    .collect();
+                Ok(Some(Value::from(orphans)))
+            }
+            COMMAND_ANONYMIZE_SCRIPT => {
+                // Argument: the URI of the script, which does not have to be open
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                let text = match self.document_map.get(&uri) {
+                    Some(document) => document.get_text(),
+                    None => uri
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| std::fs::read_to_string(path).ok())
+                        .ok_or_else(|| Error::invalid_params("Script is neither open nor readable"))?,
+                };
+                let script = parser::parse(&text);
+                Ok(Some(Value::from(anonymize::anonymize(&text, &script))))
+            }
+            COMMAND_EXPLAIN_SCRIPT => {
+                // Argument: the URI of the script, which does not have to be open
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                let explanation = match self.document_map.get(&uri) {
+                    Some(document) => {
+                        let mut explanation = explain::explain(document.script());
+                        explanation.map_ranges(|range| document.to_client_range(range));
+                        explanation
+                    }
+                    None => {
+                        let text = uri
+                            .to_file_path()
+                            .ok()
+                            .and_then(|path| std::fs::read_to_string(path).ok())
+                            .ok_or_else(|| {
+                                Error::invalid_params("Script is neither open nor readable")
+                            })?;
+                        explain::explain(&parser::parse(&text))
+                    }
+                };
+                Ok(Some(serde_json::to_value(explanation).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_NEW_RULE => {
+                // Argument: { "textDocument": { "uri": ... }, "header": ..., "match": ...,
+                // "value": ..., "action": ..., "folder": ... }
+                let rule: NewRuleParams = params
+                    .arguments
+                    .first()
+                    .cloned()
+                    .and_then(|arg| serde_json::from_value(arg).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected a rule description"))?;
+                let indent = self.settings.read().await.format().options(None).indent;
+                let uri = rule.text_document.uri.clone();
+                let edits = {
+                    let document = self
+                        .document_map
+                        .get(&uri)
+                        .ok_or_else(|| Error::invalid_params("Document is not open"))?;
+                    let text = document.get_text();
+                    builder::new_rule_edits(&text, document.script(), &rule, &indent).map(
+                        |edits| {
+                            edits
+                                .into_iter()
+                                .map(|mut edit| {
+                                    edit.range = document.to_client_range(edit.range);
+                                    edit
+                                })
+                                .collect::<Vec<_>>()
+                        },
+                    )
+                };
+                let edits = match edits {
+                    Ok(edits) => edits,
+                    Err(message) => {
+                        let message = self.localizer.read().await.translate(&message);
+                        return Err(Error::invalid_params(message));
+                    }
+                };
+                let edit = WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri, edits)])),
+                    ..Default::default()
+                };
+                Ok(Some(serde_json::to_value(edit).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_IMPORT_GMAIL_FILTERS => {
+                // Argument: the URI of the exported mailFilters.xml
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the filter file URI"))?;
+                let xml = uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| std::fs::read_to_string(path).ok())
+                    .ok_or_else(|| Error::invalid_params("Filter file is not readable"))?;
+                let import = match gmail::import(&xml) {
+                    Ok(import) => import,
+                    Err(message) => {
+                        let message = self.localizer.read().await.translate(&message);
+                        return Err(Error::invalid_params(message));
+                    }
+                };
+                for warning in &import.warnings {
+                    self.client.log_message(MessageType::WARNING, warning).await;
+                }
+                Ok(Some(serde_json::to_value(import).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_COPY_ERROR_REPORT => Ok(self.error_report().await.map(Value::from)),
+            COMMAND_CHECK_REMOTE => {
+                // Argument: the URI of an open document
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                if !self.document_map.contains_key(&uri) {
+                    return Err(Error::invalid_params("Document is not open"));
+                }
+                match self.check_remote(&uri).await {
+                    Ok(diagnostics) => Ok(Some(
+                        serde_json::to_value(diagnostics).map_err(|_| Error::internal_error())?,
+                    )),
+                    Err(err) => Err(self.remote_error(&err).await),
+                }
+            }
+            COMMAND_TEST_MESSAGE => {
+                // Arguments: the URI of an open document, optionally the raw message and an
+                // envelope { "from": ..., "to": ... }
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                let message = params
+                    .arguments
+                    .get(1)
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                let envelope = match params.arguments.get(2) {
+                    Some(value) => Some(
+                        serde_json::from_value(value.clone())
+                            .map_err(|_| Error::invalid_params("Expected an envelope object"))?,
+                    ),
+                    None => None,
+                };
+                let outcome = self
+                    .test_message(&uri, message, envelope)
+                    .map_err(Error::invalid_params)?;
+                Ok(Some(serde_json::to_value(outcome).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_TEST_RULE => {
+                // Arguments: the URI of an open document and the line its rule starts on
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                let line = params
+                    .arguments
+                    .get(1)
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| Error::invalid_params("Expected the line of the rule"))?;
+                let results = match self.test_rule(&uri, line as u32).await {
+                    Ok(results) => results,
+                    Err(message) => {
+                        let message = self.localizer.read().await.translate(&message);
+                        return Err(Error::invalid_params(message));
+                    }
+                };
+                // Lenses have no result view, so the outcome is shown as a message
+                let summary = interpreter::rule_test_summary(&results);
+                let message = self.localizer.read().await.translate(&summary);
+                self.client
+                    .send_notification::<notification::ShowMessage>(ShowMessageParams {
+                        typ: MessageType::INFO,
+                        message,
+                    })
+                    .await;
+                Ok(Some(serde_json::to_value(results).map_err(|_| Error::internal_error())?))
+            }
+            COMMAND_MIGRATE_SETTINGS => {
+                let localizer = self.localizer.read().await.clone();
+                let (path, changes) = match self.migrate_workspace_config().await {
+                    Ok(Some((path, changes))) => (Some(path), changes),
+                    Ok(None) => (None, Vec::new()),
+                    Err(message) => {
+                        return Err(Error::invalid_params(localizer.translate(&message)));
+                    }
+                };
+                let changes: Vec<String> =
+                    changes.iter().map(|change| localizer.translate(change)).collect();
+                let summary = match &path {
+                    Some(path) => format!(
+                        "Migrated {} setting(s) in {}: {}",
+                        changes.len(),
+                        path.display(),
+                        changes.join("; ")
+                    ),
+                    None => "The workspace configuration is up to date".to_string(),
+                };
+                self.client
+                    .send_notification::<notification::ShowMessage>(ShowMessageParams {
+                        typ: MessageType::INFO,
+                        message: localizer.translate(&summary),
+                    })
+                    .await;
+                Ok(Some(serde_json::json!({ "path": path, "changes": changes })))
+            }
+            COMMAND_RUN_CORPUS => {
+                // Argument: the URI of an open document
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                match self.run_corpus(&uri).await {
+                    Ok(report) => {
+                        Ok(Some(serde_json::to_value(report).map_err(|_| Error::internal_error())?))
+                    }
+                    Err(message) => {
+                        let message = self.localizer.read().await.translate(&message);
+                        Err(Error::invalid_params(message))
+                    }
+                }
+            }
+            COMMAND_REMOTE_CAPABILITIES
+            | COMMAND_LIST_REMOTE_SCRIPTS
+            | COMMAND_DOWNLOAD_SCRIPT
+            | COMMAND_UPLOAD_SCRIPT
+            | COMMAND_ACTIVATE_SCRIPT
+            | COMMAND_DELETE_SCRIPT => {
+                let operation = self.remote_operation(&params)?;
+                match self.managesieve(operation).await {
+                    Ok(result) => Ok(Some(result)),
+                    Err(err) => Err(self.remote_error(&err).await),
+                }
+            }
+            COMMAND_JOIN_LINES | COMMAND_SPLIT_STATEMENT => {
+                // Argument: { "textDocument": { "uri": ... }, "position": { ... } }
+                let target: TextDocumentPositionParams = params
+                    .arguments
+                    .first()
+                    .cloned()
+                    .and_then(|arg| serde_json::from_value(arg).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected a text document position"))?;
+                let uri = target.text_document.uri;
+
+                let edit = {
+                    let document = self
+                        .document_map
+                        .get(&uri)
+                        .ok_or_else(|| Error::invalid_params("Document is not open"))?;
+                    let text = document.get_text();
+                    let position = document.to_char_position(target.position);
+                    let edit = if params.command == COMMAND_JOIN_LINES {
+                        refactor::join_statement(&text, document.script(), position)
+                    } else {
+                        refactor::split_statement(&text, document.script(), position)
+                    };
+                    edit.map(|mut edit| {
+                        edit.range = document.to_client_range(edit.range);
+                        edit
+                    })
+                };
+
+                if let Some(edit) = edit {
+                    let changes = HashMap::from([(uri, vec![edit])]);
+                    self.client
+                        .apply_edit(WorkspaceEdit {
+                            changes: Some(changes),
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+                Ok(None)
+            }
+            _ => {
+                warn!("Unknown command: {}", params.command);
+                Err(Error::invalid_params(format!(
+                    "Unknown command: {}",
+                    params.command
+                )))
+            }
+        }
+    }
+
     /// Handle configuration changes from the editor
     /// Called when user updates settings
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         info!("Configuration changed: {:?}", params.settings);
+        // Clients using the pull model only notify that something changed
+        let applied = if params.settings.is_null() {
+            self.request_client_settings().await
+        } else {
+            self.set_client_settings(config::settings_section(params.settings)).await
+        };
+        if !applied {
+            return;
+        }
+        self.refresh_remote_capabilities().await;
 
-        // Parse new settings (this is simplified - real implementation would be more robust)
-        if let Ok(new_settings) = serde_json::from_value::<SieveSettings>(params.settings) {
-            let mut settings = self.settings.write().await;
-            *settings = new_settings;
-            info!("Updated settings: {:?}", *settings);
+        // Re-validate all open documents with new settings
+        self.revalidate_open_documents().await;
+    }
 
-            // Re-validate all open documents with new settings
-            for item in self.document_map.iter() {
-                let uri = item.key().clone();
-                let diagnostics = self.validate_document(&uri).await;
-                self.client
-                    .publish_diagnostics(uri, diagnostics, None)
-                    .await;
+    /// Apply changes made outside the editor
+    /// Edits to the configuration file at the workspace root reload the settings. Scripts and
+    /// the deployment manifest changed on disk leave the include index and may change which
+    /// scripts are orphaned.
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let root = self.workspace_root.read().await.clone();
+        let paths: Vec<PathBuf> = params
+            .changes
+            .iter()
+            .filter_map(|change| change.uri.to_file_path().ok())
+            .collect();
+        let config_changed = paths
+            .iter()
+            .any(|path| config::is_config_file(path) && path.parent() == root.as_deref());
+        let scripts: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "sieve"))
+            .collect();
+        let manifest_changed = root
+            .as_ref()
+            .is_some_and(|root| paths.contains(&root.join(MANIFEST_FILE)));
+
+        let mut revalidate = false;
+        if config_changed && self.reload_workspace_config().await {
+            self.refresh_remote_capabilities().await;
+            revalidate = true;
+        }
+        if !scripts.is_empty() || manifest_changed {
+            debug!("{} script(s) changed on disk", scripts.len());
+            {
+                let mut index = self.workspace_index.write().await;
+                for path in scripts {
+                    index.invalidate(path);
+                }
             }
+            revalidate |= self.refresh_orphaned_scripts().await;
         }
+        if revalidate {
+            self.revalidate_open_documents().await;
+        }
+    }
+}
+
+// ================================================================================================
+// CUSTOM REQUESTS
+// ================================================================================================
+
+impl SieveLanguageServer {
+    /// Handle `$/setTrace`: change which server events are sent as `$/logTrace`
+    pub async fn set_trace(&self, params: SetTraceParams) {
+        info!("Trace level: {:?}", params.value);
+        self.trace.set(params.value);
+    }
+
+    /// Handle `sieve/statistics`: report the figures of the server's status
+    pub async fn statistics(&self) -> Result<ServerStatus> {
+        Ok(self.server_status().await)
+    }
+
+    /// Handle `sieve/buildCondition`: turn a rule builder form into a Sieve test
+    /// Invalid input is answered with an `InvalidParams` error carrying a translated message
+    pub async fn build_condition(
+        &self,
+        params: BuildConditionParams,
+    ) -> Result<BuildConditionResult> {
+        debug!("Building condition from {:?}", params);
+        match builder::build_condition(&params) {
+            Ok(result) => Ok(result),
+            Err(message) => {
+                warn!("Cannot build condition: {}", message);
+                let message = self.localizer.read().await.translate(&message);
+                Err(Error::invalid_params(message))
+            }
+        }
+    }
+
+    /// Handle `sieve/metrics`: size and shape of an open document
+    pub async fn metrics(&self, params: MetricsParams) -> Result<ScriptMetrics> {
+        let dialects = self.dialects().await;
+        let document = self
+            .document_map
+            .get(&params.text_document.uri)
+            .ok_or_else(|| Error::invalid_params("Document is not open"))?;
+        Ok(metrics::metrics(&document.get_text(), document.script(), &dialects))
+    }
+
+    /// Handle `sieve/traceMessage`: run a document against a sample message and render the
+    /// execution trace as the content of a read-only virtual document
+    pub async fn trace_message(&self, params: TraceMessageParams) -> Result<TraceMessageResult> {
+        let uri = params.text_document.uri;
+        let raw = match params.message {
+            Some(raw) => raw,
+            None => Self::sidecar_message(&uri).map_err(Error::invalid_params)?,
+        };
+        let outcome = self
+            .test_message(&uri, Some(raw.clone()), params.envelope)
+            .map_err(Error::invalid_params)?;
+
+        let name = uri
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or("script")
+            .to_string();
+        let trace_uri = Url::parse(&format!(
+            "sieve-trace:{}.trace?message={:016x}",
+            uri.path(),
+            snapshot::content_hash(&raw)
+        ))
+        .map_err(|_| Error::internal_error())?;
+        let text = self
+            .localizer
+            .read()
+            .await
+            .translate_lines(&interpreter::trace_text(&outcome, &name));
+        Ok(TraceMessageResult {
+            uri: trace_uri,
+            text,
+            outcome,
+        })
+    }
+
+    /// Translated JSON-RPC error for a failed ManageSieve operation
+    async fn remote_error(&self, err: &ManageSieveError) -> Error {
+        let message = self.localizer.read().await.translate(&err.to_string());
+        Error {
+            message: message.into(),
+            ..Error::internal_error()
+        }
+    }
+
+    /// The ManageSieve operation of a command
+    /// Arguments: the script name, and for uploads the URI of the script to store, which does
+    /// not have to be open. Activating an empty name deactivates all scripts.
+    fn remote_operation(&self, params: &ExecuteCommandParams) -> Result<Operation> {
+        let name = || {
+            params
+                .arguments
+                .first()
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| Error::invalid_params("Expected the script name"))
+        };
+        Ok(match params.command.as_str() {
+            COMMAND_REMOTE_CAPABILITIES => Operation::Capabilities,
+            COMMAND_LIST_REMOTE_SCRIPTS => Operation::ListScripts,
+            COMMAND_DOWNLOAD_SCRIPT => Operation::GetScript(name()?),
+            COMMAND_ACTIVATE_SCRIPT => Operation::SetActive(name()?),
+            COMMAND_DELETE_SCRIPT => Operation::DeleteScript(name()?),
+            _ => {
+                let uri = params
+                    .arguments
+                    .get(1)
+                    .and_then(Value::as_str)
+                    .and_then(|s| Url::parse(s).ok())
+                    .ok_or_else(|| Error::invalid_params("Expected the script URI"))?;
+                let content = match self.document_map.get(&uri) {
+                    Some(document) => document.get_text(),
+                    None => uri
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| std::fs::read_to_string(path).ok())
+                        .ok_or_else(|| Error::invalid_params("Script is neither open nor readable"))?,
+                };
+                Operation::PutScript {
+                    name: name()?,
+                    content,
+                }
+            }
+        })
+    }
+
+    /// Handle `sieve/lastError`: the most recent internal error, if any
+    pub async fn last_error(&self) -> Result<Option<InternalError>> {
+        Ok(self.errors.read().await.last().cloned())
+    }
+
+    /// Markdown report of the last internal error, `None` while there is none
+    /// The failing document is only included anonymized, and only while it is still open.
+    pub async fn error_report(&self) -> Option<String> {
+        let (error, recorded) = {
+            let errors = self.errors.read().await;
+            (errors.last()?.clone(), errors.len())
+        };
+        let settings = serde_json::to_string_pretty(&*self.settings.read().await)
+            .unwrap_or_default();
+        let snippet = error
+            .uri
+            .as_ref()
+            .and_then(|uri| self.document_map.get(uri))
+            .map(|document| {
+                let text = document.get_text();
+                let unchanged = error
+                    .input_hash
+                    .is_none_or(|hash| hash == snapshot::content_hash(&text));
+                (anonymize::anonymize(&text, document.script()), unchanged)
+            });
+        Some(errors::report(&errors::ReportInput {
+            version: env!("CARGO_PKG_VERSION"),
+            error: &error,
+            recorded,
+            settings: &settings,
+            snippet,
+        }))
+    }
+}
+
+// ================================================================================================
+// CODE ACTIONS
+// ================================================================================================
+
+/// Title and edits of a quick fix, from `{ "title", "replacement" }` in a diagnostic's data
+/// An optional `range` in the data replaces another range than the diagnostic's, and optional
+/// `edits` are further edits the fix makes, e.g. to the require statements.
+fn quick_fix(diagnostic: &Diagnostic) -> Option<(&str, Vec<TextEdit>)> {
+    if diagnostic.source.as_deref() != Some("sieve-lsp") {
+        return None;
+    }
+    let data = diagnostic.data.as_ref()?;
+    let title = data.get("title").and_then(Value::as_str)?;
+    let replacement = data.get("replacement").and_then(Value::as_str)?;
+    let range = data
+        .get("range")
+        .and_then(|range| serde_json::from_value(range.clone()).ok())
+        .unwrap_or(diagnostic.range);
+    let mut edits = vec![TextEdit {
+        range,
+        new_text: replacement.to_string(),
+    }];
+    if let Some(more) = data.get("edits") {
+        edits.extend(serde_json::from_value::<Vec<TextEdit>>(more.clone()).ok()?);
+    }
+    Some((title, edits))
+}
+
+impl SieveLanguageServer {
+    /// A `source.fixAll` action applying every quick fix of a document
+    /// Fixes overlapping an earlier one are left for the next run.
+    async fn fix_all(&self, uri: &Url) -> Option<CodeAction> {
+        let mut diagnostics = self.validate_document(uri).await?;
+        {
+            let document = self.document_map.get(uri)?;
+            for diagnostic in &mut diagnostics {
+                document.to_client_diagnostic(diagnostic);
+            }
+        }
+        sort_diagnostics(&mut diagnostics);
+
+        let mut edits: Vec<TextEdit> = Vec::new();
+        let mut fixed = Vec::new();
+        for diagnostic in diagnostics {
+            let Some((_, fix)) = quick_fix(&diagnostic) else {
+                continue;
+            };
+            let overlaps = |a: &TextEdit, b: &TextEdit| {
+                a.range.start < b.range.end && b.range.start < a.range.end
+            };
+            if fix.iter().any(|edit| edits.iter().any(|done| overlaps(done, edit))) {
+                continue;
+            }
+            edits.extend(fix);
+            fixed.push(diagnostic);
+        }
+        if edits.is_empty() {
+            return None;
+        }
+
+        let localizer = self.localizer.read().await.clone();
+        Some(CodeAction {
+            title: localizer.translate("Fix all auto-fixable problems"),
+            kind: Some(CodeActionKind::SOURCE_FIX_ALL),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), edits)])),
+                ..Default::default()
+            }),
+            diagnostics: Some(fixed),
+            ..Default::default()
+        })
     }
 }
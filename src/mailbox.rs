@@ -0,0 +1,132 @@
+// ================================================================================================
+// MAILBOX NAMING
+// ================================================================================================
+//
+// IMAP servers differ in how folder hierarchies are spelled: some separate levels with `/`,
+// others with `.`, and some place personal folders below an `INBOX.` namespace prefix. Scripts
+// moved between providers keep the old spelling and silently file into the wrong folders.
+//
+// The folders that exist on the server can be listed in the settings or in a `folders.txt` at
+// the workspace root, so `fileinto` completes real folder names instead of creating new ones
+// from typos.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// File at the workspace root listing the user's folders, one per line
+pub const FOLDERS_FILE: &str = "folders.txt";
+
+/// The hierarchy conventions of the user's server, from the `mailbox` settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MailboxConvention {
+    /// Hierarchy separator, `/` or `.`; paths are not checked when unset
+    #[serde(default)]
+    pub separator: Option<char>,
+    /// Prefix of personal folders such as `INBOX.`; empty or unset when there is none
+    #[serde(default)]
+    pub namespace_prefix: Option<String>,
+    /// Folders that exist on the server, offered when completing `fileinto`
+    #[serde(default)]
+    pub folders: Vec<String>,
+}
+
+/// A mailbox path that does not follow the configured conventions
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathProblem {
+    pub message: String,
+    /// The path rewritten to the configured conventions
+    pub converted: String,
+}
+
+impl MailboxConvention {
+    fn prefix(&self) -> &str {
+        self.namespace_prefix.as_deref().unwrap_or_default()
+    }
+
+    /// Check a mailbox path, returning the first convention it breaks
+    pub fn check(&self, path: &str) -> Option<PathProblem> {
+        let separator = self.separator?;
+        let other = match separator {
+            '/' => '.',
+            '.' => '/',
+            _ => return None,
+        };
+        // INBOX itself never carries a prefix
+        if path.eq_ignore_ascii_case("INBOX") || path.is_empty() {
+            return None;
+        }
+
+        let message = if path.contains(other) && !path.contains(separator) {
+            format!(
+                "Mailbox '{}' uses '{}' as separator; the server separates folders with '{}'",
+                path, other, separator
+            )
+        } else if !self.prefix().is_empty() && !starts_with_ignore_case(path, self.prefix()) {
+            format!(
+                "Mailbox '{}' is missing the namespace prefix '{}'",
+                path,
+                self.prefix()
+            )
+        } else {
+            return None;
+        };
+        let converted = self.convert(path);
+        (converted != path).then_some(PathProblem { message, converted })
+    }
+
+    /// Rewrite a path to the configured separator and namespace prefix
+    pub fn convert(&self, path: &str) -> String {
+        let Some(separator) = self.separator else {
+            return path.to_string();
+        };
+        let other = if separator == '/' { '.' } else { '/' };
+
+        let inbox_child = ["INBOX.", "INBOX/"]
+            .iter()
+            .find(|prefix| starts_with_ignore_case(path, prefix));
+        let rest = inbox_child.map_or(path, |prefix| &path[prefix.len()..]);
+        let rest = rest.replace(other, &separator.to_string());
+
+        if !self.prefix().is_empty() {
+            format!("{}{}", self.prefix(), rest)
+        } else if inbox_child.is_some() {
+            format!("INBOX{}{}", separator, rest)
+        } else {
+            rest
+        }
+    }
+
+    /// The folders with every level of their hierarchy, so `Lists/Rust` also offers `Lists`
+    /// Without a configured separator the names are taken as they are.
+    pub fn folder_hierarchy<'a>(
+        &self,
+        folders: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        for folder in folders {
+            if let Some(separator) = self.separator {
+                for (at, _) in folder.match_indices(separator).filter(|(at, _)| *at > 0) {
+                    found.insert(folder[..at].to_string());
+                }
+            }
+            found.insert(folder.to_string());
+        }
+        found
+    }
+}
+
+/// Folder names of a `folders.txt`: one per line, blank lines and `#` comments skipped
+pub fn parse_folder_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
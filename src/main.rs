@@ -10,9 +10,17 @@
 // ================================================================================================
 // IMPORTS AND DEPENDENCIES
 // ================================================================================================
+use sieve_language_server::builder::BUILD_CONDITION_METHOD;
+use sieve_language_server::cli;
 use sieve_language_server::datastructures::*;
+use sieve_language_server::errors::LAST_ERROR_METHOD;
+use sieve_language_server::interpreter::TRACE_MESSAGE_METHOD;
+use sieve_language_server::logging::{self, TraceLevel, TraceRelay};
+use sieve_language_server::metrics::METRICS_METHOD;
+use sieve_language_server::status::STATISTICS_METHOD;
+use tower_lsp::lsp_types::notification::{Notification, SetTrace};
 use tower_lsp::{LspService, Server};
-use tracing::info;
+use tracing::{info, warn};
 
 // ================================================================================================
 // MAIN FUNCTION - ENTRY POINT
@@ -20,22 +28,64 @@ use tracing::info;
 
 #[tokio::main]
 async fn main() {
-    // Initialize logging for debugging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    // One-shot command line modes write their results to stdout. They only log, to stderr,
+    // when given `--log-level`, as the server logs the findings that are their output.
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if !logging::is_server_invocation(&args) {
+        match logging::take_log_level(&mut args) {
+            Ok(Some(level)) => tracing_subscriber::fmt()
+                .with_max_level(level)
+                .with_writer(std::io::stderr)
+                .init(),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{}\n{}", err, cli::USAGE);
+                std::process::exit(2);
+            }
+        }
+        std::process::exit(cli::run(&args).await);
+    }
+
+    // stdout carries the LSP stream, so logs go to stderr or a file, and to the client as
+    // `$/logTrace` when it asks for them
+    let options = match logging::parse_options(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let trace = TraceLevel::default();
+    let (relay, traces) = TraceRelay::new(trace.clone());
+    if let Err(err) = logging::init(&options, Some(relay)) {
+        eprintln!("Cannot open the log file: {}", err);
+        std::process::exit(2);
+    }
 
     info!("Starting Sieve Language Server");
+    for flag in &options.ignored {
+        warn!("Ignoring unknown argument {}", flag);
+    }
 
     // Create stdin/stdout for LSP communication
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
     // Create the language server service
-    let (service, socket) = LspService::new(|client| {
+    let (service, socket) = LspService::build(|client| {
         info!("Creating new language server instance");
-        SieveLanguageServer::new(client)
-    });
+        let mut server = SieveLanguageServer::new(client);
+        server.trace = trace;
+        server.relay_traces(traces);
+        server
+    })
+    .custom_method(SetTrace::METHOD, SieveLanguageServer::set_trace)
+    .custom_method(BUILD_CONDITION_METHOD, SieveLanguageServer::build_condition)
+    .custom_method(LAST_ERROR_METHOD, SieveLanguageServer::last_error)
+    .custom_method(TRACE_MESSAGE_METHOD, SieveLanguageServer::trace_message)
+    .custom_method(METRICS_METHOD, SieveLanguageServer::metrics)
+    .custom_method(STATISTICS_METHOD, SieveLanguageServer::statistics)
+    .finish();
 
     // Start the server
     info!("Sieve Language Server listening on stdin/stdout");
@@ -0,0 +1,708 @@
+// ================================================================================================
+// MANAGESIEVE CLIENT
+// ================================================================================================
+//
+// A minimal client for ManageSieve (RFC 5804), the protocol mail servers offer for uploading and
+// activating Sieve scripts. It is only used when the `managesieve` settings name a server: the
+// capability list of the server then drives validation, and editor commands list, download,
+// upload, activate and delete the scripts stored there.
+//
+// Connections are not encrypted, as the server does not ship a TLS stack. Credentials are only
+// sent to loopback addresses (e.g. an SSH tunnel) unless `allowPlaintext` is set.
+
+use crate::coexistence::{REMOTE_CHECK_CODE, REMOTE_CHECK_SOURCE};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+use tracing::{debug, trace};
+
+/// Port assigned to ManageSieve by IANA
+pub const DEFAULT_PORT: u16 = 4190;
+
+/// Settings under `managesieve`
+/// The password is neither serialized nor shown in debug output, so it stays out of logs and
+/// error reports.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManageSieveSettings {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Login name; without one the session stays unauthenticated and only capabilities are read
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    /// Send credentials to hosts other than loopback over the unencrypted connection
+    #[serde(default)]
+    pub allow_plaintext: bool,
+    /// Maximum time in milliseconds for connecting and for each command
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl fmt::Debug for ManageSieveSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManageSieveSettings")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<hidden>"))
+            .field("allow_plaintext", &self.allow_plaintext)
+            .field("timeout_ms", &self.timeout_ms)
+            .finish()
+    }
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Failure of a ManageSieve operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManageSieveError {
+    /// The connection failed or timed out
+    Io(String),
+    /// The server sent something that is not ManageSieve
+    Protocol(String),
+    /// The server refused the command (`NO`) or closed the session (`BYE`)
+    Refused {
+        code: Option<String>,
+        message: String,
+    },
+    /// Credentials would have been sent unencrypted to a remote host
+    Insecure(String),
+    /// No server is configured under `managesieve`
+    NotConfigured,
+    /// A script the check would store under its temporary name already exists
+    NameTaken(String),
+}
+
+impl fmt::Display for ManageSieveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(message) => write!(f, "Connection failed: {}", message),
+            Self::Protocol(message) => write!(f, "Unexpected server response: {}", message),
+            Self::Refused {
+                code: Some(code),
+                message,
+            } => write!(f, "Server refused: {} ({})", message, code),
+            Self::Refused { code: None, message } => write!(f, "Server refused: {}", message),
+            Self::Insecure(host) => write!(
+                f,
+                "Refusing to send credentials unencrypted to {}; use an SSH tunnel or set \
+                 managesieve.allowPlaintext",
+                host
+            ),
+            Self::NotConfigured => write!(f, "No ManageSieve server is configured"),
+            Self::NameTaken(name) => {
+                write!(f, "Refusing to check the script: '{}' already exists on the server", name)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ManageSieveError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err.to_string())
+    }
+}
+
+pub type ManageSieveResult<T> = std::result::Result<T, ManageSieveError>;
+
+/// What the server announced in its greeting
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SieveCapabilities {
+    pub implementation: Option<String>,
+    /// Sieve extensions the server supports
+    pub sieve: Vec<String>,
+    /// SASL mechanisms offered for authentication
+    pub sasl: Vec<String>,
+    pub starttls: bool,
+    pub version: Option<String>,
+}
+
+/// A script stored on the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteScript {
+    pub name: String,
+    pub active: bool,
+}
+
+/// Prefix of the names under which scripts are stored while servers without CHECKSCRIPT
+/// validate them
+pub const CHECK_SCRIPT_NAME: &str = "sieve-lsp-check";
+
+/// Largest literal accepted from a server, far above any script size limit servers enforce
+pub const MAX_LITERAL_SIZE: usize = 16 * 1024 * 1024;
+
+/// The server's verdict on a script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub valid: bool,
+    /// Errors, or warnings of a valid script; one problem per line
+    pub message: String,
+}
+
+/// One element of a server response line
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),
+    /// Quoted string or literal
+    String(String),
+    /// Parenthesized response code, without the parentheses
+    Code(String),
+}
+
+/// Final response to a command
+#[derive(Debug)]
+struct Status {
+    ok: bool,
+    code: Option<String>,
+    message: String,
+}
+
+/// An open ManageSieve session
+pub struct ManageSieveClient<S> {
+    stream: BufStream<S>,
+    capabilities: SieveCapabilities,
+    timeout: Duration,
+}
+
+/// Connect to the configured server, read its capabilities and log in if credentials are set
+pub async fn connect(
+    settings: &ManageSieveSettings,
+) -> ManageSieveResult<ManageSieveClient<TcpStream>> {
+    let timeout = Duration::from_millis(settings.timeout_ms);
+    debug!("Connecting to ManageSieve server {}:{}", settings.host, settings.port);
+    let stream = tokio::time::timeout(
+        timeout,
+        TcpStream::connect((settings.host.as_str(), settings.port)),
+    )
+    .await
+    .map_err(|_| ManageSieveError::Io("connection timed out".to_string()))??;
+    let loopback = stream.peer_addr().is_ok_and(|addr| addr.ip().is_loopback())
+        || settings.host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+
+    let mut client = ManageSieveClient::new(stream, timeout).await?;
+    if let Some(username) = &settings.username {
+        if !loopback && !settings.allow_plaintext {
+            return Err(ManageSieveError::Insecure(settings.host.clone()));
+        }
+        let password = settings.password.as_deref().unwrap_or_default();
+        client.authenticate(username, password).await?;
+    }
+    Ok(client)
+}
+
+/// A request made on behalf of an editor command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// Only read the capabilities of the greeting
+    Capabilities,
+    ListScripts,
+    GetScript(String),
+    PutScript { name: String, content: String },
+    SetActive(String),
+    DeleteScript(String),
+    /// Validate a script without storing it
+    CheckScript(String),
+}
+
+impl Operation {
+    /// Name of the protocol command, for logs that must not show script content
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Capabilities => "CAPABILITY",
+            Self::ListScripts => "LISTSCRIPTS",
+            Self::GetScript(_) => "GETSCRIPT",
+            Self::PutScript { .. } => "PUTSCRIPT",
+            Self::SetActive(_) => "SETACTIVE",
+            Self::DeleteScript(_) => "DELETESCRIPT",
+            Self::CheckScript(_) => "CHECKSCRIPT",
+        }
+    }
+}
+
+/// Connect, perform an operation and log out
+/// Returns the server's capabilities with the result of the operation as JSON.
+pub async fn execute(
+    settings: &ManageSieveSettings,
+    operation: &Operation,
+) -> ManageSieveResult<(SieveCapabilities, Value)> {
+    let mut client = connect(settings).await?;
+    let result = client.perform(operation).await?;
+    let capabilities = client.capabilities().clone();
+    // The operation succeeded; a failing LOGOUT only loses the goodbye
+    if let Err(err) = client.logout().await {
+        debug!("ManageSieve logout failed: {}", err);
+    }
+    Ok((capabilities, result))
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ManageSieveClient<S> {
+    /// Perform an operation, with its result as JSON
+    pub async fn perform(&mut self, operation: &Operation) -> ManageSieveResult<Value> {
+        Ok(match operation {
+            Operation::Capabilities => json!(self.capabilities),
+            Operation::ListScripts => json!(self.list_scripts().await?),
+            Operation::GetScript(name) => Value::from(self.get_script(name).await?),
+            Operation::PutScript { name, content } => {
+                self.put_script(name, content).await?;
+                Value::Null
+            }
+            Operation::SetActive(name) => {
+                self.set_active(name).await?;
+                Value::Null
+            }
+            Operation::DeleteScript(name) => {
+                self.delete_script(name).await?;
+                Value::Null
+            }
+            Operation::CheckScript(content) => json!(self.check_script(content).await?),
+        })
+    }
+
+    /// Start a session on an established connection by reading the server's greeting
+    pub async fn new(stream: S, timeout: Duration) -> ManageSieveResult<Self> {
+        let mut client = Self {
+            stream: BufStream::new(stream),
+            capabilities: SieveCapabilities::default(),
+            timeout,
+        };
+        let (lines, status) = client.read_response().await?;
+        status.into_result()?;
+        client.capabilities = parse_capabilities(&lines);
+        Ok(client)
+    }
+
+    /// Capabilities from the server's greeting
+    pub fn capabilities(&self) -> &SieveCapabilities {
+        &self.capabilities
+    }
+
+    /// Log in with SASL PLAIN
+    pub async fn authenticate(&mut self, username: &str, password: &str) -> ManageSieveResult<()> {
+        let credentials = base64(format!("\0{}\0{}", username, password).as_bytes());
+        self.command(&format!("AUTHENTICATE \"PLAIN\" \"{}\"", credentials), None)
+            .await
+            .map(|_| ())
+    }
+
+    /// Scripts stored for the user
+    pub async fn list_scripts(&mut self) -> ManageSieveResult<Vec<RemoteScript>> {
+        let lines = self.command("LISTSCRIPTS", None).await?;
+        lines
+            .iter()
+            .map(|tokens| match tokens.as_slice() {
+                [Token::String(name)] => Ok(RemoteScript {
+                    name: name.clone(),
+                    active: false,
+                }),
+                [Token::String(name), Token::Atom(flag)] if flag.eq_ignore_ascii_case("ACTIVE") => {
+                    Ok(RemoteScript {
+                        name: name.clone(),
+                        active: true,
+                    })
+                }
+                _ => Err(ManageSieveError::Protocol(format!("{:?}", tokens))),
+            })
+            .collect()
+    }
+
+    /// Content of a stored script
+    pub async fn get_script(&mut self, name: &str) -> ManageSieveResult<String> {
+        let lines = self
+            .command(&format!("GETSCRIPT {}", quote(name)), None)
+            .await?;
+        match lines.first().map(Vec::as_slice) {
+            Some([Token::String(content)]) => Ok(content.clone()),
+            _ => Err(ManageSieveError::Protocol("GETSCRIPT returned no script".to_string())),
+        }
+    }
+
+    /// Store a script, replacing one of the same name
+    pub async fn put_script(&mut self, name: &str, content: &str) -> ManageSieveResult<()> {
+        self.command(&format!("PUTSCRIPT {}", quote(name)), Some(content))
+            .await
+            .map(|_| ())
+    }
+
+    /// Make a script the active one; an empty name deactivates all scripts
+    pub async fn set_active(&mut self, name: &str) -> ManageSieveResult<()> {
+        self.command(&format!("SETACTIVE {}", quote(name)), None)
+            .await
+            .map(|_| ())
+    }
+
+    /// Delete a stored script; servers refuse to delete the active one
+    pub async fn delete_script(&mut self, name: &str) -> ManageSieveResult<()> {
+        self.command(&format!("DELETESCRIPT {}", quote(name)), None)
+            .await
+            .map(|_| ())
+    }
+
+    /// Let the server validate a script without storing it
+    /// Servers predating RFC 5804 lack CHECKSCRIPT; the script is then stored under a temporary
+    /// name and deleted again. The name is unique to the check, and the check is refused when a
+    /// script of that name exists, so no script of the user is replaced.
+    pub async fn check_script(&mut self, content: &str) -> ManageSieveResult<CheckReport> {
+        let result = if self.capabilities.version.is_some() {
+            self.command_status("CHECKSCRIPT", Some(content)).await
+        } else {
+            let name = check_script_name();
+            if self.list_scripts().await?.iter().any(|script| script.name == name) {
+                return Err(ManageSieveError::NameTaken(name));
+            }
+            let put = format!("PUTSCRIPT {}", quote(&name));
+            let result = self.command_status(&put, Some(content)).await;
+            if matches!(result, Ok((_, Status { ok: true, .. }))) {
+                self.delete_script(&name).await?;
+            }
+            result
+        };
+        let (_, status) = result?;
+        // Quota refusals of the fallback say nothing about the script
+        if status.code.as_deref().is_some_and(|code| code.starts_with("QUOTA")) {
+            return Err(ManageSieveError::Refused {
+                code: status.code,
+                message: status.message,
+            });
+        }
+        Ok(CheckReport {
+            valid: status.ok,
+            message: status.message,
+        })
+    }
+
+    /// End the session
+    pub async fn logout(mut self) -> ManageSieveResult<()> {
+        self.command("LOGOUT", None).await.map(|_| ())
+    }
+
+    /// Send a command, optionally followed by a literal, and collect the lines of its response
+    async fn command(
+        &mut self,
+        command: &str,
+        literal: Option<&str>,
+    ) -> ManageSieveResult<Vec<Vec<Token>>> {
+        let (lines, status) = self.command_status(command, literal).await?;
+        status.into_result()?;
+        Ok(lines)
+    }
+
+    /// Send a command and return its response, even when the server refused it
+    async fn command_status(
+        &mut self,
+        command: &str,
+        literal: Option<&str>,
+    ) -> ManageSieveResult<(Vec<Vec<Token>>, Status)> {
+        trace!("ManageSieve > {}", command.split(' ').next().unwrap_or_default());
+        let mut request = command.to_string();
+        if let Some(literal) = literal {
+            request.push_str(&format!(" {{{}+}}\r\n{}", literal.len(), literal));
+        }
+        request.push_str("\r\n");
+        self.stream.write_all(request.as_bytes()).await?;
+        self.stream.flush().await?;
+        self.read_response().await
+    }
+
+    async fn read_response(&mut self) -> ManageSieveResult<(Vec<Vec<Token>>, Status)> {
+        tokio::time::timeout(self.timeout, read_response(&mut self.stream))
+            .await
+            .map_err(|_| ManageSieveError::Io("server did not answer in time".to_string()))?
+    }
+}
+
+impl Status {
+    fn into_result(self) -> ManageSieveResult<()> {
+        if self.ok {
+            Ok(())
+        } else {
+            Err(ManageSieveError::Refused {
+                code: self.code,
+                message: self.message,
+            })
+        }
+    }
+}
+
+/// Read response lines up to the final `OK`, `NO` or `BYE`
+async fn read_response<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut BufStream<S>,
+) -> ManageSieveResult<(Vec<Vec<Token>>, Status)> {
+    let mut lines = Vec::new();
+    loop {
+        let tokens = read_tokens(stream).await?;
+        if let Some(Token::Atom(word)) = tokens.first() {
+            let word = word.to_ascii_uppercase();
+            if matches!(word.as_str(), "OK" | "NO" | "BYE") {
+                let mut code = None;
+                let mut message = String::new();
+                for token in &tokens[1..] {
+                    match token {
+                        Token::Code(c) => code = Some(c.clone()),
+                        Token::String(s) => message = s.clone(),
+                        Token::Atom(_) => {}
+                    }
+                }
+                return Ok((
+                    lines,
+                    Status {
+                        ok: word == "OK",
+                        code,
+                        message,
+                    },
+                ));
+            }
+        }
+        lines.push(tokens);
+    }
+}
+
+/// Read one logical response line, including literals embedded in it
+async fn read_tokens<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut BufStream<S>,
+) -> ManageSieveResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(ManageSieveError::Io("connection closed by server".to_string()));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let literal = tokenize_line(line, &mut tokens)?;
+        let Some(length) = literal else {
+            return Ok(tokens);
+        };
+        if length > MAX_LITERAL_SIZE {
+            return Err(ManageSieveError::Protocol(format!(
+                "literal of {} bytes exceeds the limit of {} bytes",
+                length, MAX_LITERAL_SIZE
+            )));
+        }
+        let mut buffer = vec![0; length];
+        stream.read_exact(&mut buffer).await?;
+        tokens.push(Token::String(String::from_utf8_lossy(&buffer).into_owned()));
+        // The rest of the line follows the literal
+    }
+}
+
+/// Split a response line into tokens
+/// Returns the length of a literal announced at the end of the line, e.g. `{42}`
+fn tokenize_line(line: &str, tokens: &mut Vec<Token>) -> ManageSieveResult<Option<usize>> {
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            ' ' => {}
+            '"' => {
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                if !closed {
+                    return Err(ManageSieveError::Protocol(line.to_string()));
+                }
+                tokens.push(Token::String(value));
+            }
+            '(' => {
+                let mut depth = 1;
+                let mut in_string = false;
+                let mut end = line.len();
+                for (idx, c) in chars.by_ref() {
+                    match c {
+                        '"' => in_string = !in_string,
+                        '(' if !in_string => depth += 1,
+                        ')' if !in_string => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = idx;
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                tokens.push(Token::Code(line[start + 1..end].to_string()));
+            }
+            '{' => {
+                let digits: String = line[start + 1..]
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect();
+                return digits
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| ManageSieveError::Protocol(line.to_string()));
+            }
+            _ => {
+                let mut end = line.len();
+                while let Some(&(idx, c)) = chars.peek() {
+                    if c == ' ' {
+                        end = idx;
+                        break;
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Atom(line[start..end].to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Convert the problems in a check report into diagnostics
+/// Servers name positions like Dovecot's "line 3: error: ..." or Cyrus' "line 3 column 7: ...";
+/// lines and columns are 1-based there and character based in the result. Messages without a
+/// position are reported at the start of the script, unless another one has a position.
+pub fn check_diagnostics(report: &CheckReport, text: &str) -> Vec<Diagnostic> {
+    let severity = if report.valid {
+        DiagnosticSeverity::WARNING
+    } else {
+        DiagnosticSeverity::ERROR
+    };
+    let problems: Vec<(Option<(u32, u32)>, String)> = report
+        .message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match CHECK_POSITION.captures(line) {
+            Some(captures) => {
+                let number = |name| {
+                    captures
+                        .name(name)
+                        .and_then(|m| m.as_str().parse::<u32>().ok())
+                        .unwrap_or(1)
+                };
+                let rest = line[captures.get(0).map_or(0, |m| m.end())..].trim_start();
+                let rest = CHECK_SEVERITY.replace(rest, "");
+                (
+                    Some((number("line").saturating_sub(1), number("column").saturating_sub(1))),
+                    rest.trim().to_string(),
+                )
+            }
+            None => (None, line.to_string()),
+        })
+        .collect();
+    let located = problems.iter().any(|(position, _)| position.is_some());
+
+    problems
+        .into_iter()
+        .filter(|(position, _)| position.is_some() || !located)
+        .map(|(position, message)| {
+            let (line, character) = position.unwrap_or((0, 0));
+            let length = text.lines().nth(line as usize).map_or(0, |l| l.chars().count()) as u32;
+            Diagnostic {
+                range: Range {
+                    start: Position {
+                        line,
+                        character: character.min(length),
+                    },
+                    end: Position {
+                        line,
+                        character: length,
+                    },
+                },
+                severity: Some(severity),
+                code: Some(NumberOrString::String(REMOTE_CHECK_CODE.to_string())),
+                code_description: None,
+                source: Some(REMOTE_CHECK_SOURCE.to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            }
+        })
+        .collect()
+}
+
+lazy_static! {
+    static ref CHECK_POSITION: Regex =
+        Regex::new(r"(?i)^(?:.*?:\s*)?line (?P<line>\d+)(?:,? ?col(?:umn)?\.? (?P<column>\d+))?:?")
+            .unwrap();
+    static ref CHECK_SEVERITY: Regex = Regex::new(r"(?i)^(?:error|warning):\s*").unwrap();
+}
+
+/// Interpret the capability lines of a greeting
+fn parse_capabilities(lines: &[Vec<Token>]) -> SieveCapabilities {
+    let mut capabilities = SieveCapabilities::default();
+    for tokens in lines {
+        let (Some(Token::String(name)), value) = (tokens.first(), tokens.get(1)) else {
+            continue;
+        };
+        let value = match value {
+            Some(Token::String(value)) => Some(value.clone()),
+            _ => None,
+        };
+        let words = || {
+            value
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        };
+        match name.to_ascii_uppercase().as_str() {
+            "IMPLEMENTATION" => capabilities.implementation = value,
+            "SIEVE" => capabilities.sieve = words(),
+            "SASL" => capabilities.sasl = words(),
+            "STARTTLS" => capabilities.starttls = true,
+            "VERSION" => capabilities.version = value,
+            _ => {}
+        }
+    }
+    capabilities
+}
+
+/// Temporary name for a script checked with PUTSCRIPT, unique to the process and the moment
+fn check_script_name() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    format!("{}-{}-{:x}", CHECK_SCRIPT_NAME, std::process::id(), nanos)
+}
+
+/// Quote a script name
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Standard base64 as used by SASL
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::new();
+    for chunk in input.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,36 @@
+// ================================================================================================
+// DEPLOYMENT MANIFEST
+// ================================================================================================
+//
+// Describes how the scripts of a workspace are deployed. The manifest lives in the workspace
+// (`.sieve-lsp/manifest.json`) and lists the entry scripts that are activated on the server;
+// all other scripts are only used through `include`.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Location of the manifest relative to the workspace root
+pub const MANIFEST_FILE: &str = ".sieve-lsp/manifest.json";
+
+/// Deployment description of a workspace
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentManifest {
+    /// Entry scripts, relative to the workspace root
+    #[serde(default)]
+    pub entry_points: Vec<String>,
+}
+
+impl DeploymentManifest {
+    /// Read the manifest of a workspace
+    /// Returns `None` when the workspace has no (readable) manifest
+    pub fn load(root: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(root.join(MANIFEST_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Absolute paths of the entry scripts
+    pub fn entry_paths(&self, root: &Path) -> Vec<PathBuf> {
+        self.entry_points.iter().map(|p| root.join(p)).collect()
+    }
+}
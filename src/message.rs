@@ -0,0 +1,145 @@
+// ================================================================================================
+// EMAIL MESSAGES AND CORPORA
+// ================================================================================================
+//
+// Minimal RFC 5322 message model used to run scripts against real mail, plus loaders for
+// mbox files and Maildir directories so whole mail archives can be replayed.
+
+use std::io;
+use std::path::Path;
+
+/// A parsed email message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    /// Header fields in their original order, with folded lines joined
+    pub headers: Vec<(String, String)>,
+    /// Everything after the blank line separating headers from the body
+    pub body: String,
+    /// Size of the raw message in bytes
+    pub size: usize,
+    /// Envelope sender taken from an mbox `From ` separator line, if any
+    pub envelope_from: Option<String>,
+    /// Where the message was loaded from (file path or mbox position)
+    pub origin: String,
+}
+
+impl Message {
+    /// Parse a raw RFC 5322 message
+    pub fn parse(raw: &str) -> Self {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut body = String::new();
+        let mut lines = raw.split_inclusive('\n');
+
+        for line in lines.by_ref() {
+            let content = line.trim_end_matches(['\r', '\n']);
+            if content.is_empty() {
+                break;
+            }
+            if content.starts_with([' ', '\t']) {
+                // Folded continuation of the previous header
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(content.trim());
+                }
+            } else if let Some((name, value)) = content.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        for line in lines {
+            body.push_str(line);
+        }
+
+        Self {
+            headers,
+            body,
+            size: raw.len(),
+            envelope_from: None,
+            origin: String::new(),
+        }
+    }
+
+    /// All values of a header field (names are compared case-insensitively)
+    pub fn header(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+/// Split an mbox file into messages
+/// Messages start at lines beginning with `From `; `>From ` quoting is undone (mboxrd)
+pub fn parse_mbox(content: &str, origin: &str) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut current: Option<(Option<String>, String)> = None;
+    let mut index = 0;
+
+    let mut finish = |current: Option<(Option<String>, String)>, messages: &mut Vec<Message>| {
+        if let Some((from, raw)) = current {
+            let mut message = Message::parse(&raw);
+            message.envelope_from = from;
+            message.origin = format!("{}#{}", origin, index);
+            index += 1;
+            messages.push(message);
+        }
+    };
+
+    for line in content.split_inclusive('\n') {
+        if let Some(separator) = line.strip_prefix("From ") {
+            finish(current.take(), &mut messages);
+            let sender = separator.split_whitespace().next().map(str::to_string);
+            current = Some((sender, String::new()));
+        } else if let Some((_, raw)) = current.as_mut() {
+            let unquoted = match line.strip_prefix('>') {
+                Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+                _ => line,
+            };
+            raw.push_str(unquoted);
+        }
+    }
+    finish(current, &mut messages);
+    messages
+}
+
+/// Load all messages of an mbox file
+pub fn load_mbox(path: &Path) -> io::Result<Vec<Message>> {
+    let content = String::from_utf8_lossy(&std::fs::read(path)?).into_owned();
+    Ok(parse_mbox(&content, &path.display().to_string()))
+}
+
+/// Load all messages of a Maildir (its `cur` and `new` subdirectories), sorted by file name
+pub fn load_maildir(path: &Path) -> io::Result<Vec<Message>> {
+    let mut files = Vec::new();
+    for sub in ["cur", "new"] {
+        let dir = path.join(sub);
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+
+    let mut messages = Vec::new();
+    for file in files {
+        let raw = String::from_utf8_lossy(&std::fs::read(&file)?).into_owned();
+        let mut message = Message::parse(&raw);
+        message.origin = file.display().to_string();
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+/// Load a corpus: directories are read as Maildir, files as mbox
+pub fn load_corpus(path: &Path) -> io::Result<Vec<Message>> {
+    if path.is_dir() {
+        load_maildir(path)
+    } else {
+        load_mbox(path)
+    }
+}
@@ -0,0 +1,125 @@
+// ================================================================================================
+// SCRIPT METRICS
+// ================================================================================================
+//
+// Figures about the size and shape of a script, for summary panels in editors and for auditing
+// large filter sets: how many rules and tests it has, how deeply it nests, which extensions it
+// uses and where it files or forwards mail. Served as the custom `sieve/metrics` request and
+// included in the report of `sieve-lsp analyze`.
+
+use crate::parser::{Command, Script, Test};
+use crate::requires;
+use crate::sieve::SIEVE_ACTIONS;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tower_lsp::lsp_types::TextDocumentIdentifier;
+
+/// Method name of the custom request
+pub const METRICS_METHOD: &str = "sieve/metrics";
+
+/// Parameters of `sieve/metrics`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsParams {
+    /// An open document
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Metrics of one script
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptMetrics {
+    pub lines: usize,
+    pub comments: usize,
+    /// Top-level `if` chains, the rules a user sees in a filter list
+    pub rules: usize,
+    /// Tests that look at the message, not counting `allof`, `anyof` and `not`
+    pub tests: usize,
+    pub actions: usize,
+    /// Deepest nesting of blocks; 0 when the script has none
+    pub max_nesting_depth: usize,
+    /// Cyclomatic complexity: 1 plus one for every `if`, `elsif` and extra `allof`/`anyof`
+    /// operand
+    pub complexity: usize,
+    /// Capabilities loaded with `require`
+    pub required_extensions: Vec<String>,
+    /// Capabilities the script is detected to use
+    pub used_extensions: Vec<String>,
+    /// Distinct variables assigned with `set`, lowercase
+    pub variables: Vec<String>,
+    /// How often each mailbox is the target of `fileinto`
+    pub fileinto_targets: BTreeMap<String, usize>,
+    /// How often each address is the target of `redirect`
+    pub redirect_targets: BTreeMap<String, usize>,
+}
+
+/// Compute the metrics of a script
+pub fn metrics(text: &str, script: &Script, dialects: &BTreeMap<String, String>) -> ScriptMetrics {
+    let commands = script.all_commands();
+    let tests = script.all_tests();
+
+    let mut required: Vec<String> = script.required_capabilities();
+    required.sort();
+    required.dedup();
+
+    let targets = |name: &str| {
+        let mut counts = BTreeMap::new();
+        for command in commands.iter().filter(|c| c.name == name) {
+            if let Some(target) = command.positional_arguments().first() {
+                for string in target.strings() {
+                    *counts.entry(string.decoded().into_owned()).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    };
+
+    let branches = commands
+        .iter()
+        .filter(|c| matches!(c.name.as_str(), "if" | "elsif"))
+        .count();
+    let operators: usize = tests
+        .iter()
+        .filter(|t| matches!(t.name.as_str(), "allof" | "anyof"))
+        .map(|t| t.tests.len().saturating_sub(1))
+        .sum();
+
+    ScriptMetrics {
+        lines: text.lines().count(),
+        comments: script.comments.len(),
+        rules: script.commands.iter().filter(|c| c.name == "if").count(),
+        tests: tests.iter().filter(|t| !is_combinator(t)).count(),
+        actions: commands
+            .iter()
+            .filter(|c| SIEVE_ACTIONS.contains(&c.name.as_str()))
+            .count(),
+        max_nesting_depth: nesting_depth(&script.commands),
+        complexity: 1 + branches + operators,
+        required_extensions: required,
+        used_extensions: requires::used_capabilities(script, dialects).into_iter().collect(),
+        variables: commands
+            .iter()
+            .filter(|c| c.name == "set")
+            .filter_map(|c| c.positional_arguments().first()?.strings().first().copied())
+            .map(|name| name.value.to_lowercase())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        fileinto_targets: targets("fileinto"),
+        redirect_targets: targets("redirect"),
+    }
+}
+
+fn is_combinator(test: &Test) -> bool {
+    matches!(test.name.as_str(), "allof" | "anyof" | "not")
+}
+
+/// Depth of the most deeply nested block among `commands`
+fn nesting_depth(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .filter_map(|command| command.block.as_ref())
+        .map(|block| 1 + nesting_depth(&block.commands))
+        .max()
+        .unwrap_or(0)
+}
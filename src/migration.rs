@@ -0,0 +1,234 @@
+// ================================================================================================
+// SETTINGS MIGRATION
+// ================================================================================================
+//
+// Settings written for earlier versions of the server keep working: they are migrated whenever
+// settings are loaded. Those versions took the settings of the `requires` and `mailbox` sections
+// as flat keys, and were often sent camelCase keys by editors, which the current settings would
+// ignore:
+//
+//   strictMode = true           ->  strict_mode = true
+//   mailbox_separator = "."     ->  [mailbox] separator = "."
+//
+// Only keys the current settings no longer read are migrated; keys they still read, such as
+// `proton_extensions`, keep their meaning.
+//
+// The `sieve.migrateSettings` command rewrites the workspace configuration file the same way,
+// keeping the comments and layout of TOML files, and reports what it changed.
+
+use crate::config;
+use crate::datastructures::SieveSettings;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Flat keys of settings that moved into a section, with the section and their key there
+const MOVED_KEYS: &[(&str, &str, &str)] = &[
+    ("auto_manage_requires", "requires", "autoManage"),
+    ("organize_requires_on_save", "requires", "organizeOnSave"),
+    ("mailbox_separator", "mailbox", "separator"),
+    ("namespace_prefix", "mailbox", "namespacePrefix"),
+    ("mailbox_folders", "mailbox", "folders"),
+];
+
+lazy_static! {
+    /// Top-level keys of the current settings
+    static ref CURRENT_KEYS: BTreeSet<String> = match serde_json::to_value(SieveSettings::default())
+    {
+        Ok(Value::Object(settings)) => settings.into_iter().map(|(key, _)| key).collect(),
+        _ => BTreeSet::new(),
+    };
+}
+
+/// Settings rewritten to the current keys
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Migration {
+    pub settings: Value,
+    /// What changed, one sentence per legacy key
+    pub changes: Vec<String>,
+}
+
+/// One legacy key and what becomes of it
+struct Step {
+    legacy: String,
+    /// Key path and value it moves to, `None` when it is dropped
+    target: Option<(Vec<String>, Value)>,
+    description: String,
+}
+
+/// Migrate settings of the editor or of a configuration file
+pub fn migrate(settings: &Value) -> Migration {
+    let steps = steps(settings);
+    let mut migrated = settings.clone();
+    if let Value::Object(migrated) = &mut migrated {
+        for step in &steps {
+            apply(migrated, step);
+        }
+    }
+    Migration {
+        settings: migrated,
+        changes: steps.into_iter().map(|step| step.description).collect(),
+    }
+}
+
+/// Rewrite a workspace configuration file to the current keys
+/// Returns the new content with the changes, `None` when the file is up to date.
+pub fn rewrite(path: &Path, content: &str) -> Result<Option<(String, Vec<String>)>, String> {
+    let mut settings = config::parse(path, content)?;
+    let steps = steps(&settings);
+    if steps.is_empty() {
+        return Ok(None);
+    }
+    let content = if path.extension().is_some_and(|ext| ext == "toml") {
+        rewrite_toml(content, &steps)?
+    } else {
+        if let Value::Object(settings) = &mut settings {
+            for step in &steps {
+                apply(settings, step);
+            }
+        }
+        let json = serde_json::to_string_pretty(&settings).map_err(|err| err.to_string())?;
+        format!("{}\n", json)
+    };
+    Ok(Some((content, steps.into_iter().map(|step| step.description).collect())))
+}
+
+/// The legacy keys of settings in the order they appear
+fn steps(settings: &Value) -> Vec<Step> {
+    let Value::Object(settings) = settings else {
+        return Vec::new();
+    };
+    let is_set = |path: &[String]| {
+        let mut value = settings.get(&path[0]);
+        for key in &path[1..] {
+            value = value.and_then(|value| value.get(key));
+        }
+        value.is_some()
+    };
+
+    let mut steps = Vec::new();
+    for (key, value) in settings {
+        let snake = snake_case(key);
+        if *key == snake && CURRENT_KEYS.contains(key) {
+            continue;
+        }
+        let target = if CURRENT_KEYS.contains(&snake) {
+            vec![snake]
+        } else if let Some((_, section, field)) = MOVED_KEYS.iter().find(|(f, ..)| *f == snake) {
+            vec![section.to_string(), field.to_string()]
+        } else {
+            // Unknown keys are left alone, they may belong to a newer version
+            continue;
+        };
+        let name = target.join(".");
+        steps.push(if is_set(&target) {
+            Step {
+                legacy: key.clone(),
+                target: None,
+                description: format!("`{}` is dropped, `{}` is already set", key, name),
+            }
+        } else {
+            Step {
+                legacy: key.clone(),
+                target: Some((target, value.clone())),
+                description: format!("`{}` is now `{}`", key, name),
+            }
+        });
+    }
+    steps
+}
+
+fn apply(settings: &mut Map<String, Value>, step: &Step) {
+    settings.remove(&step.legacy);
+    let Some((path, value)) = &step.target else {
+        return;
+    };
+    let Some((key, sections)) = path.split_last() else {
+        return;
+    };
+    let mut table = settings;
+    for section in sections {
+        let entry = table.entry(section.clone()).or_insert_with(|| Value::Object(Map::new()));
+        let Some(next) = entry.as_object_mut() else {
+            return;
+        };
+        table = next;
+    }
+    table.insert(key.clone(), value.clone());
+}
+
+/// Apply the steps to a TOML document, leaving everything else as written
+fn rewrite_toml(content: &str, steps: &[Step]) -> Result<String, String> {
+    let mut document: toml_edit::DocumentMut = content.parse().map_err(|err| format!("{}", err))?;
+    for step in steps {
+        let removed = document.as_table_mut().remove_entry(&step.legacy);
+        let Some((path, value)) = &step.target else {
+            continue;
+        };
+        let Some((key, sections)) = path.split_last() else {
+            continue;
+        };
+        let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+        for section in sections {
+            table = table
+                .entry(section)
+                .or_insert(toml_edit::table())
+                .as_table_like_mut()
+                .ok_or_else(|| format!("`{}` is not a table", section))?;
+        }
+        // Comments around the legacy key stay with the setting
+        let mut value = toml_value(value)?;
+        if let Some((_, legacy)) = &removed
+            && let Some(legacy) = legacy.as_value()
+        {
+            *value.decor_mut() = legacy.decor().clone();
+        }
+        table.insert(key, toml_edit::Item::Value(value));
+        if let Some((legacy, _)) = &removed
+            && let Some(mut key) = table.key_mut(key)
+        {
+            *key.leaf_decor_mut() = legacy.leaf_decor().clone();
+        }
+    }
+    Ok(document.to_string())
+}
+
+/// A setting as a TOML value
+fn toml_value(value: &Value) -> Result<toml_edit::Value, String> {
+    Ok(match value {
+        Value::Bool(value) => (*value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(number) => number.into(),
+            None => number.as_f64().unwrap_or_default().into(),
+        },
+        Value::String(value) => value.as_str().into(),
+        Value::Array(values) => {
+            let values = values.iter().map(toml_value).collect::<Result<Vec<_>, _>>()?;
+            toml_edit::Value::Array(values.into_iter().collect())
+        }
+        Value::Object(entries) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in entries {
+                table.insert(key, toml_value(value)?);
+            }
+            toml_edit::Value::InlineTable(table)
+        }
+        Value::Null => return Err("TOML has no null values".to_string()),
+    })
+}
+
+/// `strictMode` as `strict_mode`; snake_case keys stay as they are
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
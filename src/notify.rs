@@ -0,0 +1,92 @@
+// ================================================================================================
+// NOTIFICATION METHODS (RFC 5435, RFC 5436)
+// ================================================================================================
+//
+// The enotify extension names the notification method with a URI. Any scheme can be supported
+// by a server, but `mailto:` (RFC 5436) is the one every implementation offers and the one
+// users get wrong: its recipients and header fields are percent-encoded (RFC 6068).
+
+use crate::sieve;
+
+/// The capability RFC 5435 defines for `notify_method_capability`
+pub const ONLINE_CAPABILITY: &str = "online";
+
+/// Values accepted by `:importance`: high, normal and low
+pub const IMPORTANCE_LEVELS: &[&str] = &["1", "2", "3"];
+
+/// Check a notification method URI, returning what is wrong with it
+pub fn check_method(uri: &str) -> Result<(), String> {
+    let Some((scheme, rest)) = uri.split_once(':').filter(|(scheme, _)| is_scheme(scheme)) else {
+        return Err(format!(
+            "'{}' is not a notification URI: it needs a scheme such as 'mailto:'",
+            uri
+        ));
+    };
+    if rest.is_empty() {
+        return Err(format!("The notification URI '{}' is empty after its scheme", uri));
+    }
+    if scheme.eq_ignore_ascii_case("mailto") {
+        check_mailto(rest)
+    } else {
+        Ok(())
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Check the part of a `mailto:` URI after the scheme
+fn check_mailto(rest: &str) -> Result<(), String> {
+    let (to, fields) = rest.split_once('?').unwrap_or((rest, ""));
+    let mut recipients = Vec::new();
+    if !to.is_empty() {
+        recipients.extend(to.split(',').map(str::to_string));
+    }
+
+    for field in fields.split('&').filter(|f| !f.is_empty()) {
+        let Some((name, value)) = field.split_once('=') else {
+            return Err(format!("Header field '{}' of the mailto URI has no value", field));
+        };
+        if name.eq_ignore_ascii_case("to") {
+            recipients.extend(value.split(',').map(str::to_string));
+        } else {
+            percent_decode(value)?;
+        }
+    }
+
+    if recipients.is_empty() {
+        return Err("The mailto URI has no recipient".to_string());
+    }
+    for recipient in recipients {
+        let address = percent_decode(&recipient)?;
+        if !sieve::is_email_address(&address) {
+            return Err(format!("'{}' is not a valid email address", address));
+        }
+    }
+    Ok(())
+}
+
+/// Decode `%XX` escapes, rejecting incomplete ones
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = value
+                .get(idx + 1..idx + 3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("Invalid percent-encoding in '{}'", value))?;
+            decoded.push(hex);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    Ok(String::from_utf8_lossy(&decoded).into_owned())
+}
@@ -0,0 +1,206 @@
+// ================================================================================================
+// DOCUMENT OUTLINE
+// ================================================================================================
+//
+// Structure of a script for editors: a symbol tree (requires, rules, actions, variables) and
+// the regions that can be folded. Both are derived from the syntax tree alone. The workspace
+// index keeps the tree flattened, which is the shape of workspace symbol results.
+
+use crate::parser::{Command, Script};
+use crate::refactor;
+use crate::sieve::SIEVE_ACTIONS;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::*;
+
+/// Longest rule condition shown as a symbol name before it is abbreviated
+const MAX_SYMBOL_NAME: usize = 60;
+
+/// Hierarchical symbols of a script
+/// Control structures contain the symbols of their blocks
+pub fn document_symbols(text: &str, script: &Script) -> Vec<DocumentSymbol> {
+    symbols_of(text, &script.commands)
+}
+
+fn symbols_of(text: &str, commands: &[Command]) -> Vec<DocumentSymbol> {
+    commands
+        .iter()
+        .filter_map(|command| symbol_of(text, command))
+        .collect()
+}
+
+fn symbol_of(text: &str, command: &Command) -> Option<DocumentSymbol> {
+    let first_string = || {
+        command
+            .arguments
+            .iter()
+            .find_map(|a| a.strings().first().map(|s| s.value.clone()))
+    };
+
+    let (name, kind, detail) = match command.name.as_str() {
+        "require" => {
+            let capabilities: Vec<String> = command
+                .arguments
+                .iter()
+                .flat_map(|a| a.strings())
+                .map(|s| s.value.clone())
+                .collect();
+            ("require".to_string(), SymbolKind::PACKAGE, Some(capabilities.join(", ")))
+        }
+        "if" | "elsif" | "else" | "foreverypart" => {
+            let header = refactor::slice(text, command.header_range());
+            (abbreviate(header), SymbolKind::NAMESPACE, None)
+        }
+        "set" => (first_string()?, SymbolKind::VARIABLE, Some("set".to_string())),
+        name if SIEVE_ACTIONS.contains(&name) => {
+            (name.to_string(), SymbolKind::METHOD, first_string())
+        }
+        _ => return None,
+    };
+
+    let children = command
+        .block
+        .as_ref()
+        .map(|block| symbols_of(text, &block.commands))
+        .filter(|children| !children.is_empty());
+
+    #[allow(deprecated)]
+    Some(DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: command.range,
+        selection_range: command.name_range,
+        children,
+    })
+}
+
+/// A symbol taken out of its tree, named by the symbol that contained it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
+
+/// Flatten a symbol tree in document order
+/// Requires and actions carry their detail in the name, so `fileinto "Spam"` can be found by
+/// its mailbox.
+pub fn flatten(symbols: Vec<DocumentSymbol>) -> Vec<FlatSymbol> {
+    let mut flat = Vec::new();
+    flatten_into(symbols, None, &mut flat);
+    flat
+}
+
+fn flatten_into(symbols: Vec<DocumentSymbol>, container: Option<&str>, flat: &mut Vec<FlatSymbol>) {
+    for symbol in symbols {
+        let name = match (symbol.kind, &symbol.detail) {
+            (SymbolKind::PACKAGE | SymbolKind::METHOD, Some(detail)) => {
+                format!("{} {}", symbol.name, detail)
+            }
+            _ => symbol.name,
+        };
+        flat.push(FlatSymbol {
+            name: name.clone(),
+            kind: symbol.kind,
+            range: symbol.range,
+            container: container.map(str::to_string),
+        });
+        if let Some(children) = symbol.children {
+            flatten_into(children, Some(&name), flat);
+        }
+    }
+}
+
+/// Collapse whitespace and shorten long rule headers
+fn abbreviate(source: &str) -> String {
+    let collapsed = source.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() <= MAX_SYMBOL_NAME {
+        return collapsed;
+    }
+    let mut short: String = collapsed.chars().take(MAX_SYMBOL_NAME - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Foldable regions: blocks, `text:` strings, comments and runs of `require` statements
+/// Block folds stop before the line of the closing brace so it stays visible
+pub fn folding_ranges(text: &str, script: &Script) -> Vec<FoldingRange> {
+    let fold = |start: u32, end: u32, kind: Option<FoldingRangeKind>| FoldingRange {
+        start_line: start,
+        start_character: None,
+        end_line: end,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    };
+    let mut ranges = Vec::new();
+
+    for command in script.all_commands() {
+        if let Some(block) = &command.block
+            && block.range.end.line > block.range.start.line + 1
+        {
+            ranges.push(fold(block.range.start.line, block.range.end.line - 1, None));
+        }
+    }
+
+    for string in &script.multiline_strings {
+        if string.end.line > string.start.line {
+            ranges.push(fold(string.start.line, string.end.line, None));
+        }
+    }
+
+    // Adjacent hash comments fold together, bracket comments on their own
+    let mut run: Option<(u32, u32)> = None;
+    let lines: Vec<&str> = text.lines().collect();
+    for comment in &script.comments {
+        let (start, end) = (comment.range.start.line, comment.range.end.line);
+        if comment.bracket {
+            if end > start {
+                ranges.push(fold(start, end, Some(FoldingRangeKind::Comment)));
+            }
+            continue;
+        }
+        let own_line = lines
+            .get(start as usize)
+            .is_some_and(|line| line.trim_start().starts_with('#'));
+        match run {
+            Some((first, last)) if own_line && start == last + 1 => run = Some((first, start)),
+            _ => {
+                if let Some((first, last)) = run.take()
+                    && last > first
+                {
+                    ranges.push(fold(first, last, Some(FoldingRangeKind::Comment)));
+                }
+                run = own_line.then_some((start, start));
+            }
+        }
+    }
+    if let Some((first, last)) = run
+        && last > first
+    {
+        ranges.push(fold(first, last, Some(FoldingRangeKind::Comment)));
+    }
+
+    let requires: Vec<&Command> = script
+        .commands
+        .iter()
+        .filter(|c| c.name == "require")
+        .collect();
+    if let (Some(first), Some(last)) = (requires.first(), requires.last())
+        && last.range.end.line > first.range.start.line
+    {
+        ranges.push(fold(
+            first.range.start.line,
+            last.range.end.line,
+            Some(FoldingRangeKind::Imports),
+        ));
+    }
+
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
@@ -0,0 +1,1347 @@
+// ================================================================================================
+// SIEVE LEXER AND PARSER
+// ================================================================================================
+//
+// A small, error-tolerant recursive descent parser for the Sieve grammar (RFC 5228 section 8).
+// It turns a script into a tree of commands, tests and arguments that semantic passes can walk.
+// Positions are reported as LSP ranges (0-indexed line, character offset within the line).
+
+use crate::sieve::TAGS_WITH_VALUE;
+use std::borrow::Cow;
+use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+// ================================================================================================
+// TOKENS
+// ================================================================================================
+
+/// The different kinds of tokens produced by the lexer
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// Command or test name, e.g. `fileinto`, `header`
+    Identifier(String),
+    /// Tagged argument including the leading colon, e.g. `:contains`
+    Tag(String),
+    /// Number literal as written, including an optional K/M/G quantifier
+    Number(String),
+    /// Quoted or multi-line string with its decoded value
+    String { value: String, multiline: bool },
+    LeftBracket,
+    RightBracket,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Semicolon,
+    /// Any character that cannot start a valid token
+    Unknown(char),
+}
+
+/// A single token together with its location in the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range,
+}
+
+/// A hash (`#`) or bracket (`/* */`) comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub range: Range,
+    pub bracket: bool,
+}
+
+/// A syntax problem found while lexing or parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    /// Stable diagnostic code, e.g. `unterminated-string`
+    pub code: &'static str,
+    pub message: String,
+    pub range: Range,
+    pub severity: DiagnosticSeverity,
+    /// Other locations involved in the error, e.g. the opener of an unmatched bracket
+    pub related: Vec<(Range, String)>,
+}
+
+impl SyntaxError {
+    fn error(code: &'static str, message: impl Into<String>, range: Range) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            range,
+            severity: DiagnosticSeverity::ERROR,
+            related: Vec::new(),
+        }
+    }
+
+    fn warning(code: &'static str, message: impl Into<String>, range: Range) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            range,
+            severity: DiagnosticSeverity::WARNING,
+            related: Vec::new(),
+        }
+    }
+
+    fn with_related(mut self, range: Range, message: impl Into<String>) -> Self {
+        self.related.push((range, message.into()));
+        self
+    }
+}
+
+/// Character based lexer that keeps track of line and column positions
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: u32,
+    character: u32,
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    errors: Vec<SyntaxError>,
+}
+
+impl Lexer {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 0,
+            character: 0,
+            tokens: Vec::new(),
+            comments: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            character: self.character,
+        }
+    }
+
+    /// Consume one character, updating the line/column counters
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.character = 0;
+        } else {
+            self.character += 1;
+        }
+        Some(c)
+    }
+
+    fn push(&mut self, kind: TokenKind, start: Position) {
+        let end = self.position();
+        self.tokens.push(Token {
+            kind,
+            range: Range { start, end },
+        });
+    }
+
+    fn run(mut self) -> (Vec<Token>, Vec<Comment>, Vec<SyntaxError>) {
+        while let Some(c) = self.peek() {
+            let start = self.position();
+            match c {
+                c if c.is_whitespace() => {
+                    self.bump();
+                }
+                '#' => {
+                    while let Some(c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                    self.comments.push(Comment {
+                        range: Range {
+                            start,
+                            end: self.position(),
+                        },
+                        bracket: false,
+                    });
+                }
+                '/' if self.peek_at(1) == Some('*') => {
+                    self.bump();
+                    self.bump();
+                    let mut terminated = false;
+                    while self.peek().is_some() {
+                        if self.peek() == Some('*') && self.peek_at(1) == Some('/') {
+                            self.bump();
+                            self.bump();
+                            terminated = true;
+                            break;
+                        }
+                        self.bump();
+                    }
+                    if !terminated {
+                        self.errors.push(SyntaxError::error(
+                            "unterminated-comment",
+                            "Unterminated comment: missing closing '*/'",
+                            Range {
+                                start,
+                                end: Position {
+                                    line: start.line,
+                                    character: start.character + 2,
+                                },
+                            },
+                        ));
+                    }
+                    self.comments.push(Comment {
+                        range: Range {
+                            start,
+                            end: self.position(),
+                        },
+                        bracket: true,
+                    });
+                }
+                '"' => self.lex_quoted_string(start),
+                ':' if self.peek_at(1).is_some_and(is_identifier_start) => {
+                    self.bump();
+                    let name = self.take_identifier();
+                    self.push(TokenKind::Tag(format!(":{}", name)), start);
+                }
+                c if c.is_ascii_digit() => {
+                    let mut raw = String::new();
+                    while let Some(c) = self.peek() {
+                        if !c.is_ascii_digit() {
+                            break;
+                        }
+                        raw.push(c);
+                        self.bump();
+                    }
+                    if let Some(q @ ('K' | 'M' | 'G' | 'k' | 'm' | 'g')) = self.peek() {
+                        raw.push(q);
+                        self.bump();
+                    }
+                    // Anything glued to the number ("10MB", "1.5M") belongs to the same token
+                    let mut malformed = false;
+                    while let Some(c) = self.peek() {
+                        if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                            break;
+                        }
+                        malformed = true;
+                        raw.push(c);
+                        self.bump();
+                    }
+                    let range = Range {
+                        start,
+                        end: self.position(),
+                    };
+                    if malformed {
+                        self.errors.push(SyntaxError::error(
+                            "invalid-number",
+                            format!(
+                                "Invalid number '{}': expected digits with an optional K, M or G quantifier",
+                                raw
+                            ),
+                            range,
+                        ));
+                    } else if number_value(&raw).is_none() {
+                        self.errors.push(SyntaxError::error(
+                            "invalid-number",
+                            format!("Number '{}' is too large", raw),
+                            range,
+                        ));
+                    }
+                    self.push(TokenKind::Number(raw), start);
+                }
+                c if is_identifier_start(c) => {
+                    let name = self.take_identifier();
+                    if name.eq_ignore_ascii_case("text") && self.peek() == Some(':') {
+                        self.bump();
+                        self.lex_multiline_string(start);
+                    } else {
+                        self.push(TokenKind::Identifier(name), start);
+                    }
+                }
+                _ => {
+                    self.bump();
+                    let kind = match c {
+                        '[' => TokenKind::LeftBracket,
+                        ']' => TokenKind::RightBracket,
+                        '(' => TokenKind::LeftParen,
+                        ')' => TokenKind::RightParen,
+                        '{' => TokenKind::LeftBrace,
+                        '}' => TokenKind::RightBrace,
+                        ',' => TokenKind::Comma,
+                        ';' => TokenKind::Semicolon,
+                        other => TokenKind::Unknown(other),
+                    };
+                    self.push(kind, start);
+                }
+            }
+        }
+        (self.tokens, self.comments, self.errors)
+    }
+
+    fn take_identifier(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            name.push(c);
+            self.bump();
+        }
+        name
+    }
+
+    /// Lex a `"..."` string, resolving `\"` and `\\` escapes
+    fn lex_quoted_string(&mut self, start: Position) {
+        self.bump(); // opening quote
+        let mut value = String::new();
+        let mut terminated = false;
+        loop {
+            let escape_start = self.position();
+            let Some(c) = self.bump() else {
+                break;
+            };
+            match c {
+                '"' => {
+                    terminated = true;
+                    break;
+                }
+                '\\' => {
+                    if let Some(escaped) = self.bump() {
+                        // Only \" and \\ are defined; anything else is reserved (RFC 5228 2.4.2)
+                        if escaped != '"' && escaped != '\\' {
+                            self.errors.push(SyntaxError::warning(
+                                "invalid-escape",
+                                format!(
+                                    "Undefined escape sequence '\\{}' is read as '{}'",
+                                    escaped, escaped
+                                ),
+                                Range {
+                                    start: escape_start,
+                                    end: self.position(),
+                                },
+                            ));
+                        }
+                        value.push(escaped);
+                    }
+                }
+                other => value.push(other),
+            }
+        }
+        if !terminated {
+            self.errors.push(SyntaxError::error(
+                "unterminated-string",
+                "Unterminated string: missing closing '\"'",
+                Range {
+                    start,
+                    end: Position {
+                        line: start.line,
+                        character: start.character + 1,
+                    },
+                },
+            ));
+        }
+        self.push(
+            TokenKind::String {
+                value,
+                multiline: false,
+            },
+            start,
+        );
+    }
+
+    /// Lex a `text:` multi-line string terminated by a line containing a single dot
+    fn lex_multiline_string(&mut self, start: Position) {
+        // Skip the remainder of the `text:` line (whitespace and an optional comment)
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+
+        let mut value = String::new();
+        let mut terminated = false;
+        while self.peek().is_some() {
+            let mut line = String::new();
+            while let Some(c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                line.push(c);
+                self.bump();
+            }
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if line == "." {
+                // The token ends at the dot; the line break belongs to what follows
+                terminated = true;
+                break;
+            }
+            self.bump(); // line break
+            // Dot-stuffing: a leading ".." stands for a single "."
+            let line = line.strip_prefix('.').filter(|l| l.starts_with('.')).unwrap_or(line);
+            value.push_str(line);
+            value.push('\n');
+        }
+        if !terminated {
+            self.errors.push(SyntaxError::error(
+                "unterminated-multiline",
+                "Unterminated multi-line string: 'text:' must end with a line containing only '.'",
+                Range {
+                    start,
+                    end: Position {
+                        line: start.line,
+                        character: start.character + 5,
+                    },
+                },
+            ));
+        }
+        self.push(
+            TokenKind::String {
+                value,
+                multiline: true,
+            },
+            start,
+        );
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+/// Split a script into tokens, comments and lexical errors
+pub fn tokenize(text: &str) -> (Vec<Token>, Vec<Comment>, Vec<SyntaxError>) {
+    Lexer::new(text).run()
+}
+
+// ================================================================================================
+// BRACKET MATCHING
+// ================================================================================================
+
+/// Check that `{}` and `()` pairs are balanced across the whole document
+///
+/// When a closing brace starts a line, its indentation is compared with the lines of the open
+/// braces; if it lines up with an outer brace, the inner ones are the ones reported as unclosed.
+/// This points at the block that is actually missing its `}` in nested `if` statements.
+pub fn check_brackets(tokens: &[Token], text: &str) -> Vec<SyntaxError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let indent = |line: u32| {
+        lines
+            .get(line as usize)
+            .map(|l| l.len() - l.trim_start().len())
+            .unwrap_or(0)
+    };
+    let starts_line = |range: Range| {
+        lines
+            .get(range.start.line as usize)
+            .is_some_and(|l| l.chars().take(range.start.character as usize).all(char::is_whitespace))
+    };
+
+    let mut errors = Vec::new();
+    // Open brackets with their expected closer
+    let mut stack: Vec<(char, Range)> = Vec::new();
+
+    let unclosed = |open: char, range: Range| {
+        SyntaxError::error(
+            "unmatched-bracket",
+            format!("Unclosed '{}': missing '{}'", open, closer_for(open)),
+            range,
+        )
+    };
+
+    for token in tokens {
+        let (open, close) = match token.kind {
+            TokenKind::LeftBrace => ('{', None),
+            TokenKind::LeftParen => ('(', None),
+            TokenKind::RightBrace => ('{', Some('}')),
+            TokenKind::RightParen => ('(', Some(')')),
+            _ => continue,
+        };
+        let Some(close) = close else {
+            stack.push((open, token.range));
+            continue;
+        };
+
+        // Find the opener this closer belongs to
+        let matching = if close == '}' && starts_line(token.range) {
+            let own_indent = indent(token.range.start.line);
+            stack
+                .iter()
+                .rposition(|(o, r)| *o == '{' && indent(r.start.line) == own_indent)
+                .or_else(|| stack.iter().rposition(|(o, _)| *o == open))
+        } else {
+            stack.iter().rposition(|(o, _)| *o == open)
+        };
+
+        match matching {
+            Some(idx) => {
+                // Everything opened after the match was never closed
+                for (inner, inner_range) in stack.drain(idx + 1..) {
+                    errors.push(unclosed(inner, inner_range).with_related(
+                        token.range,
+                        format!("'{}' here closes an outer '{}' instead", close, open),
+                    ));
+                }
+                stack.pop();
+            }
+            None => {
+                let mut error = SyntaxError::error(
+                    "unmatched-bracket",
+                    format!("Unmatched '{}': no corresponding '{}'", close, open),
+                    token.range,
+                );
+                if let Some((o, r)) = stack.last() {
+                    error = error.with_related(*r, format!("Innermost open bracket is '{}'", o));
+                }
+                errors.push(error);
+            }
+        }
+    }
+
+    for (open, range) in stack {
+        errors.push(unclosed(open, range));
+    }
+    errors
+}
+
+fn closer_for(open: char) -> char {
+    match open {
+        '{' => '}',
+        '(' => ')',
+        '[' => ']',
+        other => other,
+    }
+}
+
+// ================================================================================================
+// SYNTAX TREE
+// ================================================================================================
+
+/// A string literal argument
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral {
+    pub value: String,
+    pub range: Range,
+    pub multiline: bool,
+}
+
+/// A single argument of a command or test
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    String(StringLiteral),
+    StringList {
+        items: Vec<StringLiteral>,
+        range: Range,
+    },
+    Number {
+        raw: String,
+        range: Range,
+    },
+    Tag {
+        name: String,
+        range: Range,
+    },
+}
+
+impl Argument {
+    /// The full source range covered by this argument
+    pub fn range(&self) -> Range {
+        match self {
+            Argument::String(s) => s.range,
+            Argument::StringList { range, .. }
+            | Argument::Number { range, .. }
+            | Argument::Tag { range, .. } => *range,
+        }
+    }
+
+    /// All string literals contained in this argument (a single string or a string list)
+    pub fn strings(&self) -> Vec<&StringLiteral> {
+        match self {
+            Argument::String(s) => vec![s],
+            Argument::StringList { items, .. } => items.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The tag name if this argument is a tag
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Argument::Tag { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// A test such as `header :contains "subject" "x"` or `anyof (...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Test {
+    pub name: String,
+    pub name_range: Range,
+    pub arguments: Vec<Argument>,
+    /// Nested tests for `not`, `allof` and `anyof`
+    pub tests: Vec<Test>,
+    pub range: Range,
+}
+
+impl Test {
+    /// Arguments that are neither tags nor the value of a tag like `:comparator`
+    pub fn positional_arguments(&self) -> Vec<&Argument> {
+        positional_arguments(&self.arguments)
+    }
+}
+
+/// Arguments that are neither tags nor the value of a tag like `:flags`
+fn positional_arguments(all: &[Argument]) -> Vec<&Argument> {
+    let mut positional = Vec::new();
+    let mut arguments = all.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.tag() {
+            Some(tag) if TAGS_WITH_VALUE.contains(&tag) => {
+                arguments.next();
+            }
+            Some(_) => {}
+            None => positional.push(argument),
+        }
+    }
+    positional
+}
+
+/// A `{ ... }` block of commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub commands: Vec<Command>,
+    pub range: Range,
+    /// Whether the block ends with `}`; unclosed blocks extend to the end of the script
+    pub closed: bool,
+}
+
+/// A command such as `fileinto "Spam";` or `if <test> { ... }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub name_range: Range,
+    pub arguments: Vec<Argument>,
+    /// The test of control commands like `if` and `elsif`
+    pub tests: Vec<Test>,
+    pub block: Option<Block>,
+    /// Whether the command ends with `;` or a block
+    pub terminated: bool,
+    pub range: Range,
+}
+
+impl Command {
+    /// Arguments that are neither tags nor the value of a tag, e.g. the mailbox of `fileinto`
+    pub fn positional_arguments(&self) -> Vec<&Argument> {
+        positional_arguments(&self.arguments)
+    }
+
+    /// Range of the command without its block: name, arguments and tests
+    pub fn header_range(&self) -> Range {
+        let end = self
+            .tests
+            .last()
+            .map(|t| t.range.end)
+            .into_iter()
+            .chain(self.arguments.last().map(|a| a.range().end))
+            .chain(std::iter::once(self.name_range.end))
+            .max()
+            .unwrap_or(self.name_range.end);
+        Range {
+            start: self.range.start,
+            end,
+        }
+    }
+}
+
+/// Whether a position lies within a range (both ends inclusive)
+pub fn range_contains(range: &Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+impl StringLiteral {
+    /// The value with its encoded characters decoded, see `decode_encoded_characters`
+    pub fn decoded(&self) -> Cow<'_, str> {
+        decode_encoded_characters(&self.value)
+    }
+
+    /// Source range of the characters `start..end` of the decoded value
+    /// Accounts for escapes in quoted strings and dot-stuffing in `text:` strings.
+    pub fn value_range(&self, text: &str, start: usize, end: usize) -> Range {
+        let mut source = text[offset_at(text, self.range.start)..].chars().peekable();
+        let mut position = self.range.start;
+        let advance = |position: &mut Position, c: char| {
+            if c == '\n' {
+                position.line += 1;
+                position.character = 0;
+            } else {
+                position.character += 1;
+            }
+        };
+
+        // Skip the opening quote, or the rest of the `text:` line
+        for c in source.by_ref() {
+            advance(&mut position, c);
+            if !self.multiline || c == '\n' {
+                break;
+            }
+        }
+
+        let mut at_line_start = true;
+        let (mut range_start, mut range_end) = (position, position);
+        for index in 0..=end {
+            if index == start {
+                range_start = position;
+            }
+            if index == end {
+                range_end = position;
+                break;
+            }
+            let Some(c) = source.next() else {
+                break;
+            };
+            advance(&mut position, c);
+            if self.multiline {
+                if c == '\r' && source.peek() == Some(&'\n') {
+                    advance(&mut position, source.next().unwrap_or('\n'));
+                } else if at_line_start && c == '.' && source.peek() == Some(&'.') {
+                    advance(&mut position, source.next().unwrap_or('.'));
+                }
+                at_line_start = c == '\n' || c == '\r';
+            } else if c == '\\'
+                && let Some(escaped) = source.next()
+            {
+                advance(&mut position, escaped);
+            }
+        }
+        Range {
+            start: range_start,
+            end: range_end,
+        }
+    }
+}
+
+/// A `${hex:...}` or `${unicode:...}` sequence in a string value (RFC 5228 section 2.4.2.4)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedCharacter {
+    /// Character offsets of the whole sequence within the value, end exclusive
+    pub start: usize,
+    pub end: usize,
+    pub value: EncodedValue,
+}
+
+/// What an encoded character sequence stands for
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodedValue {
+    Decoded(String),
+    /// Not valid encoded-character syntax, so the sequence is kept verbatim; holds the reason
+    Malformed(String),
+    /// Valid syntax for octets or code points that are not characters, which is an error
+    Invalid(String),
+}
+
+/// All encoded character sequences in a string value
+/// The `hex:` and `unicode:` prefixes are case-insensitive and values are separated by blanks.
+pub fn encoded_characters(value: &str) -> Vec<EncodedCharacter> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut found = Vec::new();
+    let mut idx = 0;
+    while idx + 1 < chars.len() {
+        if chars[idx] != '$' || chars[idx + 1] != '{' {
+            idx += 1;
+            continue;
+        }
+        let Some(close) = (idx + 2..chars.len()).find(|&i| chars[i] == '}') else {
+            break;
+        };
+        let inner: String = chars[idx + 2..close].iter().collect();
+        let Some((prefix, body)) = inner.split_once(':') else {
+            idx += 2;
+            continue;
+        };
+        let unicode = match prefix.to_ascii_lowercase().as_str() {
+            "hex" => false,
+            "unicode" => true,
+            _ => {
+                idx += 2;
+                continue;
+            }
+        };
+        found.push(EncodedCharacter {
+            start: idx,
+            end: close + 1,
+            value: decode_sequence(body, unicode),
+        });
+        idx = close + 1;
+    }
+    found
+}
+
+/// Decode the blank-separated hex values of one sequence
+fn decode_sequence(body: &str, unicode: bool) -> EncodedValue {
+    let values: Vec<&str> = body.split_whitespace().collect();
+    if values.is_empty() {
+        return EncodedValue::Malformed("no value is encoded".to_string());
+    }
+    let max_digits = if unicode { usize::MAX } else { 2 };
+    if let Some(bad) = values
+        .iter()
+        .find(|v| v.len() > max_digits || !v.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        let expected = if unicode { "a hexadecimal code point" } else { "a hexadecimal octet" };
+        return EncodedValue::Malformed(format!("'{}' is not {}", bad, expected));
+    }
+
+    if unicode {
+        let mut decoded = String::new();
+        for value in values {
+            // Leading zeros are allowed, so only the significant digits can overflow
+            let digits = value.trim_start_matches('0');
+            let code = match digits {
+                "" => Some(0),
+                _ if digits.len() > 8 => None,
+                _ => u32::from_str_radix(digits, 16).ok(),
+            };
+            match code.and_then(char::from_u32) {
+                Some(c) => decoded.push(c),
+                None => {
+                    return EncodedValue::Invalid(format!(
+                        "U+{} is not a Unicode character",
+                        value.to_uppercase()
+                    ));
+                }
+            }
+        }
+        EncodedValue::Decoded(decoded)
+    } else {
+        let octets = values
+            .iter()
+            .map(|value| u8::from_str_radix(value, 16).unwrap_or_default())
+            .collect();
+        match String::from_utf8(octets) {
+            Ok(decoded) => EncodedValue::Decoded(decoded),
+            Err(_) => EncodedValue::Invalid("the octets are not valid UTF-8".to_string()),
+        }
+    }
+}
+
+/// A string value with its encoded characters replaced by what they stand for
+/// Malformed and invalid sequences are left as they are.
+pub fn decode_encoded_characters(value: &str) -> Cow<'_, str> {
+    let sequences = encoded_characters(value);
+    if sequences.is_empty() {
+        return Cow::Borrowed(value);
+    }
+    let chars: Vec<char> = value.chars().collect();
+    let mut decoded = String::with_capacity(value.len());
+    let mut last = 0;
+    for sequence in sequences {
+        if let EncodedValue::Decoded(text) = &sequence.value {
+            decoded.extend(&chars[last..sequence.start]);
+            decoded.push_str(text);
+            last = sequence.end;
+        }
+    }
+    decoded.extend(&chars[last..]);
+    Cow::Owned(decoded)
+}
+
+/// Value of a number literal with its K/M/G quantifier applied (powers of 1024)
+/// Returns `None` for malformed literals and values that do not fit in 64 bits
+pub fn number_value(raw: &str) -> Option<u64> {
+    let (digits, multiplier) = match raw.chars().last()? {
+        'K' | 'k' => (&raw[..raw.len() - 1], 1u64 << 10),
+        'M' | 'm' => (&raw[..raw.len() - 1], 1 << 20),
+        'G' | 'g' => (&raw[..raw.len() - 1], 1 << 30),
+        _ => (raw, 1),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// A parsed Sieve script
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub commands: Vec<Command>,
+    pub comments: Vec<Comment>,
+    pub errors: Vec<SyntaxError>,
+    /// Ranges of all `text:` multi-line strings, from `text:` to the terminating dot
+    pub multiline_strings: Vec<Range>,
+}
+
+/// Where a position sits in the command structure of a script
+#[derive(Debug, Default)]
+pub struct CommandContext<'a> {
+    /// The command right before the position in the same block
+    pub previous: Option<&'a Command>,
+    /// Commands whose blocks enclose the position, outermost first
+    pub enclosing: Vec<&'a Command>,
+}
+
+impl Script {
+    /// Whether a line lies inside the body of a multi-line string
+    /// The line holding `text:` itself is not part of the body
+    pub fn is_multiline_body_line(&self, line: u32) -> bool {
+        self.multiline_strings
+            .iter()
+            .any(|r| line > r.start.line && line <= r.end.line)
+    }
+
+    /// Whether a line opens a multi-line string, so its statement continues below
+    pub fn opens_multiline_string(&self, line: u32) -> bool {
+        self.multiline_strings.iter().any(|r| r.start.line == line)
+    }
+
+    /// The comment containing a position, if any
+    /// The end of a hash comment still belongs to it, the position after `*/` does not.
+    pub fn comment_at(&self, position: Position) -> Option<&Comment> {
+        self.comments.iter().find(|comment| {
+            comment.range.start < position
+                && (position < comment.range.end
+                    || (!comment.bracket && position == comment.range.end))
+        })
+    }
+
+    /// The innermost command whose source range contains the position
+    pub fn command_at(&self, position: Position) -> Option<&Command> {
+        let mut commands = &self.commands;
+        let mut found = None;
+        while let Some(command) = commands
+            .iter()
+            .find(|c| range_contains(&c.range, position))
+        {
+            found = Some(command);
+            match &command.block {
+                Some(block) if range_contains(&block.range, position) => {
+                    commands = &block.commands
+                }
+                _ => break,
+            }
+        }
+        found
+    }
+
+    /// The block structure around a position where a new command could start
+    pub fn context_at(&self, position: Position) -> CommandContext<'_> {
+        let inside = |block: &Block| {
+            block.range.start < position && (!block.closed || position < block.range.end)
+        };
+        let mut commands = &self.commands;
+        let mut enclosing = Vec::new();
+        while let Some((command, block)) = commands.iter().find_map(|c| {
+            c.block
+                .as_ref()
+                .filter(|block| inside(block))
+                .map(|block| (c, block))
+        }) {
+            enclosing.push(command);
+            commands = &block.commands;
+        }
+        CommandContext {
+            previous: commands.iter().take_while(|c| c.range.end <= position).last(),
+            enclosing,
+        }
+    }
+
+    /// All tests of the script including nested ones (`not`, `allof`, `anyof`), in document order
+    pub fn all_tests(&self) -> Vec<&Test> {
+        fn walk<'a>(tests: &'a [Test], out: &mut Vec<&'a Test>) {
+            for test in tests {
+                out.push(test);
+                walk(&test.tests, out);
+            }
+        }
+        let mut out = Vec::new();
+        for command in self.all_commands() {
+            walk(&command.tests, &mut out);
+        }
+        out
+    }
+
+    /// Capabilities loaded with `require` anywhere in the script, lowercased
+    pub fn required_capabilities(&self) -> Vec<String> {
+        self.all_commands()
+            .into_iter()
+            .filter(|c| c.name == "require")
+            .flat_map(|c| c.arguments.iter().flat_map(|a| a.strings()))
+            .map(|s| s.value.to_lowercase())
+            .collect()
+    }
+
+    /// All commands of the script including those nested in blocks, in document order
+    pub fn all_commands(&self) -> Vec<&Command> {
+        fn walk<'a>(commands: &'a [Command], out: &mut Vec<&'a Command>) {
+            for command in commands {
+                out.push(command);
+                if let Some(block) = &command.block {
+                    walk(&block.commands, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.commands, &mut out);
+        out
+    }
+}
+
+/// Convert an LSP position into a byte offset into `text`
+/// Positions past the end of a line or of the document are clamped
+pub fn offset_at(text: &str, position: Position) -> usize {
+    let mut offset = 0;
+    for (idx, line) in text.split_inclusive('\n').enumerate() {
+        if idx == position.line as usize {
+            let content = line.trim_end_matches('\n');
+            return offset
+                + content
+                    .char_indices()
+                    .nth(position.character as usize)
+                    .map(|(i, _)| i)
+                    .unwrap_or(content.len());
+        }
+        offset += line.len();
+    }
+    text.len()
+}
+
+/// `text` with every comment replaced by spaces, keeping line breaks and columns
+/// Line-based checks see code alone, so a rule commented out with `/* */` is not validated.
+pub fn blank_comments(text: &str, comments: &[Comment]) -> String {
+    let mut blanked = String::with_capacity(text.len());
+    let mut comments = comments.iter().peekable();
+    let mut position = Position::new(0, 0);
+    for c in text.chars() {
+        while comments.peek().is_some_and(|comment| comment.range.end <= position) {
+            comments.next();
+        }
+        let commented = comments.peek().is_some_and(|comment| comment.range.start <= position);
+        blanked.push(if commented && c != '\n' { ' ' } else { c });
+        if c == '\n' {
+            position = Position::new(position.line + 1, 0);
+        } else {
+            position.character += 1;
+        }
+    }
+    blanked
+}
+
+/// One line of [`blank_comments`], given without its line break
+pub fn blank_line_comments(line: &str, line_number: u32, comments: &[Comment]) -> String {
+    let first = comments.partition_point(|comment| comment.range.end.line < line_number);
+    let on_line: Vec<&Comment> = comments[first..]
+        .iter()
+        .take_while(|comment| comment.range.start.line <= line_number)
+        .collect();
+    if on_line.is_empty() {
+        return line.to_string();
+    }
+    line.chars()
+        .enumerate()
+        .map(|(column, c)| {
+            let position = Position::new(line_number, column as u32);
+            let commented = on_line
+                .iter()
+                .any(|comment| comment.range.start <= position && position < comment.range.end);
+            if commented { ' ' } else { c }
+        })
+        .collect()
+}
+
+// ================================================================================================
+// PARSER
+// ================================================================================================
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    errors: Vec<SyntaxError>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// End position of the most recently consumed token
+    fn last_end(&self, fallback: Position) -> Position {
+        if self.pos == 0 {
+            fallback
+        } else {
+            self.tokens[self.pos - 1].range.end
+        }
+    }
+
+    fn parse_commands(&mut self, in_block: bool) -> Vec<Command> {
+        let mut commands = Vec::new();
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::RightBrace if in_block => break,
+                TokenKind::Identifier(_) => commands.push(self.parse_command()),
+                _ => {
+                    // Stray token outside of any command - skip it
+                    self.next();
+                }
+            }
+        }
+        commands
+    }
+
+    fn parse_command(&mut self) -> Command {
+        let name_token = self.next().expect("parse_command called without a token");
+        let name = match name_token.kind {
+            TokenKind::Identifier(name) => name,
+            _ => unreachable!("parse_command called on a non-identifier"),
+        };
+        let start = name_token.range.start;
+
+        let arguments = self.parse_arguments();
+        let tests = self.parse_test_or_list();
+
+        let mut block = None;
+        let mut terminated = true;
+        match self.peek_kind() {
+            Some(TokenKind::Semicolon) => {
+                self.next();
+            }
+            Some(TokenKind::LeftBrace) => block = Some(self.parse_block()),
+            _ => terminated = false,
+        }
+
+        Command {
+            name,
+            name_range: name_token.range,
+            arguments,
+            tests,
+            block,
+            terminated,
+            range: Range {
+                start,
+                end: self.last_end(start),
+            },
+        }
+    }
+
+    fn parse_block(&mut self) -> Block {
+        let open = self.next().expect("parse_block called without a token");
+        let commands = self.parse_commands(true);
+        let closed = matches!(self.peek_kind(), Some(TokenKind::RightBrace));
+        if closed {
+            self.next();
+        }
+        Block {
+            commands,
+            range: Range {
+                start: open.range.start,
+                end: self.last_end(open.range.start),
+            },
+            closed,
+        }
+    }
+
+    fn parse_arguments(&mut self) -> Vec<Argument> {
+        let mut arguments = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            match token.kind {
+                TokenKind::String { value, multiline } => {
+                    self.next();
+                    arguments.push(Argument::String(StringLiteral {
+                        value,
+                        range: token.range,
+                        multiline,
+                    }));
+                }
+                TokenKind::Tag(name) => {
+                    self.next();
+                    arguments.push(Argument::Tag {
+                        name,
+                        range: token.range,
+                    });
+                }
+                TokenKind::Number(raw) => {
+                    self.next();
+                    arguments.push(Argument::Number {
+                        raw,
+                        range: token.range,
+                    });
+                }
+                TokenKind::LeftBracket => arguments.push(self.parse_string_list()),
+                _ => break,
+            }
+        }
+        arguments
+    }
+
+    fn parse_string_list(&mut self) -> Argument {
+        let open = self.next().expect("parse_string_list called without a token");
+        let mut items = Vec::new();
+        // Whether the previous element was a string (a comma or `]` must follow)
+        let mut after_string = false;
+        let mut last_comma: Option<Range> = None;
+        let mut closed = false;
+
+        while let Some(token) = self.peek().cloned() {
+            match token.kind {
+                TokenKind::String { value, multiline } => {
+                    self.next();
+                    if after_string {
+                        self.errors.push(SyntaxError::error(
+                            "malformed-string-list",
+                            "Missing ',' between strings in string list",
+                            token.range,
+                        ));
+                    }
+                    items.push(StringLiteral {
+                        value,
+                        range: token.range,
+                        multiline,
+                    });
+                    after_string = true;
+                    last_comma = None;
+                }
+                TokenKind::Comma => {
+                    self.next();
+                    if !after_string {
+                        self.errors.push(SyntaxError::error(
+                            "malformed-string-list",
+                            "Unexpected ',' in string list: expected a string",
+                            token.range,
+                        ));
+                    }
+                    after_string = false;
+                    last_comma = Some(token.range);
+                }
+                TokenKind::RightBracket => {
+                    self.next();
+                    closed = true;
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(comma) = last_comma {
+            self.errors.push(SyntaxError::error(
+                "malformed-string-list",
+                "Trailing ',' in string list",
+                comma,
+            ));
+        }
+        let range = Range {
+            start: open.range.start,
+            end: self.last_end(open.range.start),
+        };
+        if !closed {
+            self.errors.push(SyntaxError::error(
+                "malformed-string-list",
+                "Unterminated string list: missing ']'",
+                open.range,
+            ));
+        } else if items.is_empty() {
+            self.errors.push(SyntaxError::error(
+                "malformed-string-list",
+                "String list must contain at least one string",
+                range,
+            ));
+        }
+
+        Argument::StringList { items, range }
+    }
+
+    /// Parse either a single test or a parenthesised test list, if one follows
+    fn parse_test_or_list(&mut self) -> Vec<Test> {
+        match self.peek_kind() {
+            Some(TokenKind::Identifier(_)) => vec![self.parse_test()],
+            Some(TokenKind::LeftParen) => {
+                self.next();
+                let mut tests = Vec::new();
+                while let Some(kind) = self.peek_kind() {
+                    match kind {
+                        TokenKind::Identifier(_) => tests.push(self.parse_test()),
+                        TokenKind::Comma => {
+                            self.next();
+                        }
+                        TokenKind::RightParen => {
+                            self.next();
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+                tests
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_test(&mut self) -> Test {
+        let name_token = self.next().expect("parse_test called without a token");
+        let name = match name_token.kind {
+            TokenKind::Identifier(name) => name,
+            _ => unreachable!("parse_test called on a non-identifier"),
+        };
+        let start = name_token.range.start;
+        let arguments = self.parse_arguments();
+        let tests = self.parse_test_or_list();
+        Test {
+            name,
+            name_range: name_token.range,
+            arguments,
+            tests,
+            range: Range {
+                start,
+                end: self.last_end(start),
+            },
+        }
+    }
+}
+
+/// Parse a complete Sieve script
+pub fn parse(text: &str) -> Script {
+    let (tokens, comments, mut errors) = tokenize(text);
+    errors.extend(check_brackets(&tokens, text));
+    let multiline_strings = tokens
+        .iter()
+        .filter(|t| {
+            matches!(
+                t.kind,
+                TokenKind::String {
+                    multiline: true,
+                    ..
+                }
+            )
+        })
+        .map(|t| t.range)
+        .collect();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        errors: Vec::new(),
+    };
+    let commands = parser.parse_commands(false);
+    errors.extend(parser.errors);
+    Script {
+        commands,
+        comments,
+        errors,
+        multiline_strings,
+    }
+}
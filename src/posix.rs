@@ -0,0 +1,239 @@
+// ================================================================================================
+// POSIX EXTENDED REGULAR EXPRESSIONS
+// ================================================================================================
+//
+// The regex extension matches with POSIX extended regular expressions (IEEE Std 1003.1, 9.4),
+// not the Perl-style syntax of the `regex` crate. This is a syntax checker for that dialect: it
+// finds the first construct a POSIX implementation rejects and reports where it is.
+
+/// Upper limit for `{m,n}` repetition bounds (`RE_DUP_MAX`)
+pub const MAX_REPETITION: u32 = 255;
+
+/// Character classes usable as `[:name:]` in bracket expressions
+const CHARACTER_CLASSES: &[&str] = &[
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+/// A syntax error in a pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError {
+    pub message: String,
+    /// Character offsets into the pattern, end exclusive
+    pub start: usize,
+    pub end: usize,
+}
+
+impl PatternError {
+    fn new(message: impl Into<String>, start: usize, end: usize) -> Self {
+        Self {
+            message: message.into(),
+            start,
+            end,
+        }
+    }
+}
+
+/// Check a pattern against the POSIX ERE grammar, returning the first error
+pub fn check(pattern: &str) -> Result<(), PatternError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut open_groups = Vec::new();
+    // Whether the previous element can take a repetition operator
+    let mut repeatable = false;
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        let c = chars[idx];
+        match c {
+            '\\' => {
+                if idx + 1 == chars.len() {
+                    return Err(PatternError::new(
+                        "Pattern ends with an incomplete escape '\\'",
+                        idx,
+                        idx + 1,
+                    ));
+                }
+                idx += 2;
+                repeatable = true;
+                continue;
+            }
+            '(' => {
+                open_groups.push(idx);
+                repeatable = false;
+            }
+            ')' => {
+                if open_groups.pop().is_none() {
+                    return Err(PatternError::new(
+                        "Unmatched ')' in regular expression",
+                        idx,
+                        idx + 1,
+                    ));
+                }
+                repeatable = true;
+            }
+            '|' | '^' => repeatable = false,
+            '*' | '+' | '?' => {
+                if !repeatable {
+                    return Err(PatternError::new(
+                        format!("Nothing to repeat before '{}'", c),
+                        idx,
+                        idx + 1,
+                    ));
+                }
+            }
+            '{' if chars.get(idx + 1).is_some_and(|d| d.is_ascii_digit()) => {
+                if !repeatable {
+                    return Err(PatternError::new("Nothing to repeat before '{'", idx, idx + 1));
+                }
+                idx = check_bound(&chars, idx)?;
+                continue;
+            }
+            '[' => {
+                idx = check_bracket(&chars, idx)?;
+                repeatable = true;
+                continue;
+            }
+            _ => repeatable = true,
+        }
+        idx += 1;
+    }
+
+    match open_groups.pop() {
+        Some(open) => Err(PatternError::new(
+            "Unmatched '(' in regular expression",
+            open,
+            open + 1,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check a `{m}`, `{m,}` or `{m,n}` bound starting at `start`, returning the index after it
+fn check_bound(chars: &[char], start: usize) -> Result<usize, PatternError> {
+    let Some(close) = chars[start..].iter().position(|&c| c == '}').map(|p| start + p) else {
+        let text: String = chars[start..].iter().collect();
+        return Err(PatternError::new(
+            format!("Invalid repetition bound '{}'", text),
+            start,
+            chars.len(),
+        ));
+    };
+    let text: String = chars[start..=close].iter().collect();
+    let invalid = || {
+        PatternError::new(
+            format!("Invalid repetition bound '{}'", text),
+            start,
+            close + 1,
+        )
+    };
+
+    let inner = &text[1..text.len() - 1];
+    let (min, max) = match inner.split_once(',') {
+        Some((min, "")) => (min, None),
+        Some((min, max)) => (min, Some(max)),
+        None => (inner, Some(inner)),
+    };
+    let parse = |n: &str| {
+        if n.is_empty() || !n.chars().all(|c| c.is_ascii_digit()) {
+            None
+        } else {
+            Some(n.parse::<u32>().unwrap_or(u32::MAX))
+        }
+    };
+    let min = parse(min).ok_or_else(invalid)?;
+    let max = match max {
+        Some(max) => Some(parse(max).ok_or_else(invalid)?),
+        None => None,
+    };
+
+    if min.max(max.unwrap_or(0)) > MAX_REPETITION {
+        return Err(PatternError::new(
+            format!("Repetition bound exceeds the limit of {}", MAX_REPETITION),
+            start,
+            close + 1,
+        ));
+    }
+    if max.is_some_and(|max| max < min) {
+        return Err(invalid());
+    }
+    Ok(close + 1)
+}
+
+/// Check a bracket expression starting at `start`, returning the index after its `]`
+fn check_bracket(chars: &[char], start: usize) -> Result<usize, PatternError> {
+    let unterminated = || {
+        PatternError::new(
+            "Unterminated bracket expression: missing ']'",
+            start,
+            start + 1,
+        )
+    };
+
+    let mut idx = start + 1;
+    if chars.get(idx) == Some(&'^') {
+        idx += 1;
+    }
+    // A leading ']' is a literal member
+    let mut first = true;
+    // Start of the previous single-character member, for ranges
+    let mut previous: Option<(char, usize)> = None;
+
+    while idx < chars.len() {
+        let c = chars[idx];
+        if c == ']' && !first {
+            return Ok(idx + 1);
+        }
+        first = false;
+
+        if c == '['
+            && let Some(&kind @ (':' | '.' | '=')) = chars.get(idx + 1)
+        {
+            let body_start = idx + 2;
+            let end = (body_start..chars.len().saturating_sub(1))
+                .find(|&j| chars[j] == kind && chars[j + 1] == ']')
+                .ok_or_else(|| {
+                    PatternError::new(
+                        "Unterminated collating element or character class",
+                        idx,
+                        idx + 2,
+                    )
+                })?;
+            if kind == ':' {
+                let name: String = chars[body_start..end].iter().collect();
+                if !CHARACTER_CLASSES.contains(&name.as_str()) {
+                    return Err(PatternError::new(
+                        format!("Unknown character class '[:{}:]'", name),
+                        idx,
+                        end + 2,
+                    ));
+                }
+                previous = None;
+            } else {
+                previous = (end == body_start + 1).then(|| (chars[body_start], idx));
+            }
+            idx = end + 2;
+            continue;
+        }
+
+        if c == '-'
+            && let Some((low, low_start)) = previous
+            && let Some(&high) = chars.get(idx + 1)
+            && high != ']'
+        {
+            if high < low {
+                return Err(PatternError::new(
+                    format!("Invalid range '{}-{}': the end sorts before the start", low, high),
+                    low_start,
+                    idx + 2,
+                ));
+            }
+            previous = None;
+            idx += 2;
+            continue;
+        }
+
+        previous = Some((c, idx));
+        idx += 1;
+    }
+    Err(unterminated())
+}
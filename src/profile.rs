@@ -0,0 +1,258 @@
+// ================================================================================================
+// SERVER PROFILES
+// ================================================================================================
+//
+// Mail servers and providers implement their own selection of Sieve: a subset of the standard
+// extensions, sometimes without parts of the base language, plus vendor extensions of their own.
+// Selecting one with the `server_dialect` setting validates scripts against what it actually
+// accepts; the default "generic" dialect accepts every known extension. Profiles are plain data,
+// so supporting another server means adding an entry to `PROFILES`.
+
+/// Value of the `server_dialect` setting that selects no profile
+pub const GENERIC: &str = "generic";
+
+/// The Sieve implementation of a mail server or provider
+#[derive(Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// Value selecting this profile in the `server_dialect` setting
+    pub name: &'static str,
+    /// Human readable name of the provider
+    pub description: &'static str,
+    /// Reference documentation of the provider's Sieve support
+    pub documentation: &'static str,
+    /// Every capability the provider accepts in `require`
+    pub extensions: &'static [&'static str],
+    /// Standard commands, tests and `command :tag` pairs the provider rejects where they are used
+    pub unsupported: &'static [&'static str],
+    /// Vendor commands, tests and tags with the capability they belong to
+    pub vendor_features: &'static [(&'static str, &'static str)],
+}
+
+/// Proton Mail
+pub const PROTON: Profile = Profile {
+    name: "proton",
+    description: "Proton Mail",
+    documentation: "https://proton.me/support/sieve-advanced-custom-filters",
+    extensions: &[
+        "comparator-i;ascii-numeric",
+        "date",
+        "environment",
+        "extlists",
+        "fileinto",
+        "imap4flags",
+        "include",
+        "index",
+        "relational",
+        "spamtest",
+        "vacation",
+        "variables",
+        "vnd.proton.eval",
+        "vnd.proton.expire",
+    ],
+    unsupported: &["redirect", "reject", "ereject"],
+    vendor_features: &[
+        ("expire", "vnd.proton.expire"),
+        ("unexpire", "vnd.proton.expire"),
+        ("hasexpiration", "vnd.proton.expire"),
+        ("expiration", "vnd.proton.expire"),
+        (":eval", "vnd.proton.eval"),
+    ],
+};
+
+/// Dovecot with the Pigeonhole Sieve plugin
+pub const DOVECOT: Profile = Profile {
+    name: "dovecot",
+    description: "Dovecot Pigeonhole",
+    documentation: "https://doc.dovecot.org/configuration_manual/sieve/extensions/",
+    extensions: &[
+        "body",
+        "comparator-i;ascii-numeric",
+        "copy",
+        "date",
+        "editheader",
+        "encoded-character",
+        "enotify",
+        "envelope",
+        "environment",
+        "ereject",
+        "fileinto",
+        "foreverypart",
+        "ihave",
+        "imap4flags",
+        "imapflags",
+        "include",
+        "index",
+        "mailbox",
+        "mboxmetadata",
+        "mime",
+        "regex",
+        "reject",
+        "relational",
+        "servermetadata",
+        "spamtest",
+        "spamtestplus",
+        "subaddress",
+        "vacation",
+        "variables",
+        "virustest",
+        "vnd.dovecot.debug",
+        "vnd.dovecot.execute",
+        "vnd.dovecot.filter",
+        "vnd.dovecot.pipe",
+    ],
+    unsupported: &[],
+    vendor_features: &[
+        ("debug_log", "vnd.dovecot.debug"),
+        ("pipe", "vnd.dovecot.pipe"),
+        ("filter", "vnd.dovecot.filter"),
+        ("execute", "vnd.dovecot.execute"),
+        (":input", "vnd.dovecot.execute"),
+        (":pipe", "vnd.dovecot.execute"),
+        (":output", "vnd.dovecot.execute"),
+    ],
+};
+
+/// Cyrus IMAP
+pub const CYRUS: Profile = Profile {
+    name: "cyrus",
+    description: "Cyrus IMAP",
+    documentation: "https://www.cyrusimap.org/imap/reference/admin/sieve.html",
+    extensions: &[
+        "body",
+        "comparator-i;ascii-numeric",
+        "comparator-i;unicode-casemap",
+        "copy",
+        "date",
+        "editheader",
+        "encoded-character",
+        "enotify",
+        "envelope",
+        "environment",
+        "ereject",
+        "fileinto",
+        "ihave",
+        "imap4flags",
+        "imapflags",
+        "include",
+        "index",
+        "mailbox",
+        "mboxmetadata",
+        "regex",
+        "reject",
+        "relational",
+        "servermetadata",
+        "spamtest",
+        "subaddress",
+        "vacation",
+        "variables",
+        "virustest",
+    ],
+    unsupported: &[],
+    vendor_features: &[],
+};
+
+/// Fastmail, a hosted Cyrus without the extensions reserved for administrators
+pub const FASTMAIL: Profile = Profile {
+    name: "fastmail",
+    description: "Fastmail",
+    documentation: "https://www.fastmail.help/hc/en-us/articles/1500000280481",
+    extensions: &[
+        "body",
+        "comparator-i;ascii-numeric",
+        "copy",
+        "date",
+        "envelope",
+        "environment",
+        "fileinto",
+        "imap4flags",
+        "index",
+        "mailbox",
+        "regex",
+        "reject",
+        "relational",
+        "spamtest",
+        "subaddress",
+        "vacation",
+        "variables",
+    ],
+    unsupported: &[],
+    vendor_features: &[],
+};
+
+/// Scripts translated into Gmail filters, which can only label, flag, forward and delete
+pub const GMAIL_FORWARDING: Profile = Profile {
+    name: "gmail-forwarding",
+    description: "Gmail filters",
+    documentation: "https://support.google.com/mail/answer/6579",
+    extensions: &["body", "copy", "fileinto", "imap4flags"],
+    unsupported: &["reject", "ereject", "vacation", "stop"],
+    vendor_features: &[],
+};
+
+/// Every known profile
+pub const PROFILES: &[Profile] = &[PROTON, DOVECOT, CYRUS, FASTMAIL, GMAIL_FORWARDING];
+
+/// The profile selected by name, `None` for "generic" and unknown names
+pub fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES
+        .iter()
+        .find(|profile| profile.name.eq_ignore_ascii_case(name))
+}
+
+/// The vendor capability defining a command, test or tag, in any profile
+pub fn vendor_capability(feature: &str) -> Option<&'static str> {
+    vendor_of(feature).map(|(_, capability)| capability)
+}
+
+/// The profile whose vendor extension defines a command, test or tag, with its capability
+pub fn vendor_of(feature: &str) -> Option<(&'static Profile, &'static str)> {
+    PROFILES.iter().find_map(|profile| {
+        profile
+            .vendor_capability(feature)
+            .map(|capability| (profile, capability))
+    })
+}
+
+/// Capability names from a client-provided list, without duplicates
+/// Entries may also be whole space-separated lists, such as the SIEVE line of a ManageSieve
+/// greeting (RFC 5804 section 1.7).
+pub fn parse_capabilities(list: &[String]) -> Vec<String> {
+    let mut capabilities: Vec<String> = Vec::new();
+    for name in list.iter().flat_map(|entry| entry.split_whitespace()) {
+        let name = name.trim_matches('"');
+        if !name.is_empty() && !capabilities.iter().any(|c| c.eq_ignore_ascii_case(name)) {
+            capabilities.push(name.to_string());
+        }
+    }
+    capabilities
+}
+
+/// Whether a capability is a vendor extension of some profile
+pub fn is_vendor_capability(capability: &str) -> bool {
+    PROFILES.iter().any(|profile| {
+        profile
+            .vendor_features
+            .iter()
+            .any(|(_, c)| *c == capability)
+    })
+}
+
+impl Profile {
+    /// Whether the provider accepts a capability in `require`
+    pub fn supports_extension(&self, capability: &str) -> bool {
+        self.extensions.contains(&capability)
+    }
+
+    /// Whether the provider rejects a command, test or `command :tag` pair
+    pub fn is_unsupported(&self, feature: &str) -> bool {
+        self.unsupported.contains(&feature)
+    }
+
+    /// The vendor capability defining a feature, if it is one of the provider's own
+    pub fn vendor_capability(&self, feature: &str) -> Option<&'static str> {
+        self.vendor_features
+            .iter()
+            .find(|(f, _)| *f == feature)
+            .map(|(_, capability)| *capability)
+    }
+}
@@ -0,0 +1,422 @@
+// ================================================================================================
+// SYNTAX-AWARE EDITS
+// ================================================================================================
+//
+// Text transformations that understand Sieve structure, used by custom editor commands and
+// code actions.
+// Every function computes a `TextEdit` from the current text and its syntax tree; applying it
+// is left to the caller.
+
+use crate::parser::{self, Argument, Script, Test, TokenKind};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+/// Width of one indentation level in generated code
+pub const INDENT: &str = "    ";
+
+/// Source text covered by a range
+pub fn slice(text: &str, range: Range) -> &str {
+    &text[parser::offset_at(text, range.start)..parser::offset_at(text, range.end)]
+}
+
+/// Render a value as a quoted Sieve string, escaping quotes and backslashes
+pub fn quote_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Leading whitespace of a line
+pub fn line_indent(text: &str, line: u32) -> String {
+    text.lines()
+        .nth(line as usize)
+        .map(|l| l.chars().take_while(|c| c.is_whitespace()).collect())
+        .unwrap_or_default()
+}
+
+/// Apply non-overlapping edits to a text, back to front
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+    let mut result = text.to_string();
+    for edit in edits {
+        let start = parser::offset_at(&result, edit.range.start);
+        let end = parser::offset_at(&result, edit.range.end);
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+/// One edit turning a text into another by replacing the lines between their common start
+/// and end, `None` when they are equal
+pub fn changed_lines(old: &str, new: &str) -> Option<TextEdit> {
+    if old == new {
+        return None;
+    }
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let end_line = old_lines.len() - suffix;
+    // A last line without a newline ends within the line rather than at the next one
+    let end = match end_line.checked_sub(1).and_then(|last| old_lines.get(last)) {
+        Some(last) if end_line > prefix && !last.ends_with('\n') => {
+            Position::new((end_line - 1) as u32, last.chars().count() as u32)
+        }
+        _ => Position::new(end_line as u32, 0),
+    };
+    Some(TextEdit {
+        range: Range {
+            start: Position::new(prefix as u32, 0),
+            end,
+        },
+        new_text: new_lines[prefix..new_lines.len() - suffix].concat(),
+    })
+}
+
+/// Join a statement that is wrapped over several lines onto a single line
+///
+/// For block commands only the header up to the opening brace is joined. Statements containing
+/// comments or `text:` strings are left alone, since joining would change their meaning.
+pub fn join_statement(text: &str, script: &Script, position: Position) -> Option<TextEdit> {
+    let command = script.command_at(position)?;
+    let range = match &command.block {
+        Some(block) => Range {
+            start: command.range.start,
+            end: Position {
+                line: block.range.start.line,
+                character: block.range.start.character + 1,
+            },
+        },
+        None => command.range,
+    };
+    if range.start.line == range.end.line {
+        return None;
+    }
+
+    let (tokens, comments, _) = parser::tokenize(text);
+    if comments
+        .iter()
+        .any(|c| c.range.start >= range.start && c.range.end <= range.end)
+    {
+        return None;
+    }
+    let tokens: Vec<_> = tokens
+        .into_iter()
+        .filter(|t| t.range.start >= range.start && t.range.end <= range.end)
+        .collect();
+    if tokens.iter().any(|t| {
+        matches!(
+            t.kind,
+            TokenKind::String {
+                multiline: true,
+                ..
+            }
+        )
+    }) {
+        return None;
+    }
+
+    let mut joined = String::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        joined.push_str(slice(text, token.range));
+        let Some(next) = tokens.get(idx + 1) else {
+            break;
+        };
+        let gap = slice(
+            text,
+            Range {
+                start: token.range.end,
+                end: next.range.start,
+            },
+        );
+        if gap.contains('\n') {
+            let tight = matches!(token.kind, TokenKind::LeftParen | TokenKind::LeftBracket)
+                || matches!(
+                    next.kind,
+                    TokenKind::Comma
+                        | TokenKind::RightParen
+                        | TokenKind::RightBracket
+                        | TokenKind::Semicolon
+                );
+            if !tight {
+                joined.push(' ');
+            }
+        } else {
+            joined.push_str(gap);
+        }
+    }
+
+    Some(TextEdit {
+        range,
+        new_text: joined,
+    })
+}
+
+/// Split the test of a control statement across lines at argument boundaries
+///
+/// `allof`/`anyof` test lists get one test per line; other tests keep their tags and first
+/// positional argument on the first line and put each further argument on its own line.
+pub fn split_statement(text: &str, script: &Script, position: Position) -> Option<TextEdit> {
+    let command = script.command_at(position)?;
+    let mut test = command.tests.first()?;
+    while test.name == "not" && test.tests.len() == 1 {
+        test = &test.tests[0];
+    }
+
+    let base = line_indent(text, command.range.start.line);
+    let inner = format!("{}{}", base, INDENT);
+
+    let new_text = if test.tests.len() > 1 {
+        let prefix = slice(
+            text,
+            Range {
+                start: test.range.start,
+                end: test.tests[0].range.start,
+            },
+        )
+        .trim_end();
+        let members: Vec<String> = test
+            .tests
+            .iter()
+            .map(|t| format!("{}{}", inner, slice(text, t.range)))
+            .collect();
+        format!("{}\n{}\n{})", prefix, members.join(",\n"), base)
+    } else {
+        split_arguments(text, test, &inner)?
+    };
+
+    if new_text == slice(text, test.range) {
+        return None;
+    }
+    Some(TextEdit {
+        range: test.range,
+        new_text,
+    })
+}
+
+fn split_arguments(text: &str, test: &Test, inner: &str) -> Option<String> {
+    let mut first_line = slice(text, test.name_range).to_string();
+    let mut rest = Vec::new();
+    let mut seen_positional = false;
+    for argument in &test.arguments {
+        let source = slice(text, argument.range());
+        if !seen_positional {
+            first_line.push(' ');
+            first_line.push_str(source);
+            seen_positional |= argument.tag().is_none();
+        } else {
+            rest.push(format!("{}{}", inner, source));
+        }
+    }
+    if rest.is_empty() {
+        return None;
+    }
+    Some(format!("{}\n{}", first_line, rest.join("\n")))
+}
+
+/// Tests that match when any key of their key list matches, so the list can be split into an
+/// `anyof` of the same test with fewer keys
+const ANY_KEY_TESTS: &[&str] = &[
+    "address",
+    "body",
+    "currentdate",
+    "date",
+    "envelope",
+    "environment",
+    "hasflag",
+    "header",
+    "metadata",
+    "notify_method_capability",
+    "servermetadata",
+    "string",
+];
+
+/// Rewrites shortening a line longer than `max_length` characters because of a string list
+///
+/// The longest single-line string list starting on the line is split with one string per line.
+/// When it is the key list of a test like `header`, the test can also become an `anyof` of the
+/// same test with one key each. Both keep the meaning of the script.
+pub fn shorten_line(
+    text: &str,
+    script: &Script,
+    line: u32,
+    max_length: usize,
+) -> Vec<(&'static str, TextEdit)> {
+    let length = text.lines().nth(line as usize).map_or(0, |l| l.chars().count());
+    if max_length == 0 || length <= max_length {
+        return Vec::new();
+    }
+
+    let lists = script
+        .all_commands()
+        .into_iter()
+        .flat_map(|command| command.arguments.iter())
+        .chain(script.all_tests().into_iter().flat_map(|test| test.arguments.iter()));
+    let Some(Argument::StringList { items, range }) = lists
+        .filter(|argument| match argument {
+            Argument::StringList { items, range } => {
+                range.start.line == line
+                    && range.end.line == line
+                    && items.len() > 1
+                    && items.iter().all(|item| !item.multiline)
+            }
+            _ => false,
+        })
+        .max_by_key(|argument| slice(text, argument.range()).chars().count())
+    else {
+        return Vec::new();
+    };
+
+    let base = line_indent(text, line);
+    let inner = format!("{}{}", base, INDENT);
+    let strings: Vec<&str> = items.iter().map(|item| slice(text, item.range)).collect();
+
+    let mut edits = vec![(
+        "Split string list across lines",
+        TextEdit {
+            range: *range,
+            new_text: format!(
+                "[\n{}\n{}]",
+                strings
+                    .iter()
+                    .map(|s| format!("{}{}", inner, s))
+                    .collect::<Vec<_>>()
+                    .join(",\n"),
+                base
+            ),
+        },
+    )];
+
+    let key_test = script.all_tests().into_iter().find(|test| {
+        ANY_KEY_TESTS.contains(&test.name.as_str())
+            && test
+                .positional_arguments()
+                .last()
+                .is_some_and(|keys| keys.range() == *range)
+    });
+    if let Some(test) = key_test {
+        let prefix = slice(
+            text,
+            Range {
+                start: test.range.start,
+                end: range.start,
+            },
+        );
+        let suffix = slice(
+            text,
+            Range {
+                start: range.end,
+                end: test.range.end,
+            },
+        );
+        let members: Vec<String> = strings
+            .iter()
+            .map(|s| format!("{}{}{}{}", inner, prefix, s, suffix))
+            .collect();
+        edits.push((
+            "Convert to 'anyof' with one key per test",
+            TextEdit {
+                range: test.range,
+                new_text: format!("anyof (\n{}\n{})", members.join(",\n"), base),
+            },
+        ));
+    }
+    edits
+}
+
+/// A negation that can be written more simply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Negation {
+    /// `not not <test>`, possibly repeated
+    Double,
+    /// `not true` or `not false`, with the constant it amounts to
+    Constant(bool),
+    /// `anyof`/`allof` of negated tests only, with the combinator of the De Morgan form
+    DeMorgan(&'static str),
+}
+
+/// Detect a redundant negation at the top of a test
+/// Only the outermost `not` of a chain is reported, so callers should skip tests whose parent is
+/// a `not`.
+pub fn redundant_negation(test: &Test) -> Option<Negation> {
+    match (test.name.as_str(), test.tests.as_slice()) {
+        ("not", [inner]) if inner.name == "not" && inner.tests.len() == 1 => Some(Negation::Double),
+        ("not", [inner]) if inner.tests.is_empty() && inner.arguments.is_empty() => {
+            match inner.name.as_str() {
+                "true" => Some(Negation::Constant(false)),
+                "false" => Some(Negation::Constant(true)),
+                _ => None,
+            }
+        }
+        ("anyof" | "allof", tests)
+            if tests.len() > 1 && tests.iter().all(|t| t.name == "not" && t.tests.len() == 1) =>
+        {
+            Some(Negation::DeMorgan(if test.name == "anyof" {
+                "allof"
+            } else {
+                "anyof"
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Rewrite a redundant negation found by [`redundant_negation`]
+///
+/// The negated tests are kept as written, including the whitespace and comments between them;
+/// only the `not` keywords are removed. Nothing is offered when a comment sits between a `not`
+/// and its test, since it would be lost.
+pub fn simplify_negation(text: &str, test: &Test) -> Option<TextEdit> {
+    // The text from a `not` up to its test, if it is nothing but the keyword
+    let keyword = |not: &Test| {
+        let range = Range {
+            start: not.range.start,
+            end: not.tests.first()?.range.start,
+        };
+        (slice(text, range).trim_end() == "not").then_some(range)
+    };
+
+    let new_text = match redundant_negation(test)? {
+        Negation::Double => {
+            let mut inner = test;
+            let mut count = 0;
+            while inner.name == "not" && inner.tests.len() == 1 {
+                keyword(inner)?;
+                inner = &inner.tests[0];
+                count += 1;
+            }
+            let source = slice(text, inner.range);
+            if count % 2 == 0 {
+                source.to_string()
+            } else {
+                format!("not {}", source)
+            }
+        }
+        Negation::Constant(value) => {
+            keyword(test)?;
+            value.to_string()
+        }
+        Negation::DeMorgan(combinator) => {
+            let mut rewritten = format!("not {}", combinator);
+            let mut position = test.name_range.end;
+            for not in &test.tests {
+                let removed = keyword(not)?;
+                rewritten.push_str(slice(text, Range { start: position, end: removed.start }));
+                position = removed.end;
+            }
+            rewritten.push_str(slice(text, Range { start: position, end: test.range.end }));
+            rewritten
+        }
+    };
+    Some(TextEdit {
+        range: test.range,
+        new_text,
+    })
+}
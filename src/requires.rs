@@ -0,0 +1,638 @@
+// ================================================================================================
+// REQUIRE MANAGEMENT
+// ================================================================================================
+//
+// Works out which capabilities a script uses from its syntax tree and computes the edits that
+// bring its `require` statements in line with that usage. Only capabilities whose usage can be
+// detected reliably are ever removed; anything else a script requires is left untouched.
+
+use crate::dialect;
+use crate::parser::{self, Argument, Command, Script, StringLiteral};
+use crate::profile;
+use crate::refactor;
+use crate::sieve;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range, TextEdit};
+
+/// Commands, tests and tags that imply a capability
+/// Tags shared by several extensions (such as `:from`) are deliberately absent.
+const CAPABILITY_FEATURES: &[(&str, &str)] = &[
+    ("fileinto", "fileinto"),
+    ("reject", "reject"),
+    ("ereject", "ereject"),
+    ("vacation", "vacation"),
+    ("envelope", "envelope"),
+    ("body", "body"),
+    ("date", "date"),
+    ("currentdate", "date"),
+    ("environment", "environment"),
+    ("mailboxexists", "mailbox"),
+    ("metadata", "mboxmetadata"),
+    ("metadataexists", "mboxmetadata"),
+    ("servermetadata", "servermetadata"),
+    ("servermetadataexists", "servermetadata"),
+    ("spamtest", "spamtest"),
+    ("virustest", "virustest"),
+    (":percent", "spamtestplus"),
+    ("set", "variables"),
+    ("string", "variables"),
+    ("include", "include"),
+    ("return", "include"),
+    ("addheader", "editheader"),
+    ("deleteheader", "editheader"),
+    ("foreverypart", "foreverypart"),
+    ("break", "foreverypart"),
+    (":copy", "copy"),
+    (":regex", "regex"),
+    (":value", "relational"),
+    (":count", "relational"),
+    (":user", "subaddress"),
+    (":detail", "subaddress"),
+    (":index", "index"),
+    (":last", "index"),
+    (":mime", "mime"),
+    (":anychild", "mime"),
+    (":type", "mime"),
+    (":subtype", "mime"),
+    (":contenttype", "mime"),
+    (":param", "mime"),
+    (":create", "mailbox"),
+    ("ihave", "ihave"),
+    ("error", "ihave"),
+];
+
+lazy_static! {
+    static ref VARIABLE_REFERENCE: Regex = Regex::new(r"\$\{[A-Za-z0-9_.]+\}").unwrap();
+    static ref MISSING_CAPABILITY: Regex =
+        Regex::new(r#"(?:requires the|required with) "([^"]+)""#).unwrap();
+}
+
+/// A part of a script that only runs when `ihave` found its capabilities (RFC 5463 section 4)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+    /// The `if` or `elsif` command with its test and block
+    pub range: Range,
+    pub capabilities: Vec<String>,
+}
+
+/// Parts of a script guarded by `if ihave [...]`, also as part of an `allof` test
+/// Capabilities named with variables are left out, since they are only known at runtime.
+pub fn ihave_guards(script: &Script) -> Vec<Guard> {
+    script
+        .all_commands()
+        .into_iter()
+        .filter(|c| matches!(c.name.as_str(), "if" | "elsif"))
+        .filter_map(|command| {
+            let test = command.tests.first()?;
+            let tests: Vec<&parser::Test> = match test.name.as_str() {
+                "ihave" => vec![test],
+                "allof" => test.tests.iter().filter(|t| t.name == "ihave").collect(),
+                _ => return None,
+            };
+            let capabilities: Vec<String> = tests
+                .iter()
+                .flat_map(|t| t.positional_arguments())
+                .flat_map(|a| a.strings())
+                .filter(|s| !s.value.contains("${"))
+                .map(|s| s.value.clone())
+                .collect();
+            (!capabilities.is_empty()).then_some(Guard {
+                range: command.range,
+                capabilities,
+            })
+        })
+        .collect()
+}
+
+/// Whether a capability used at a position is guarded by an `ihave` test
+pub fn is_guarded(guards: &[Guard], capability: &str, position: Position) -> bool {
+    guards.iter().any(|guard| {
+        parser::range_contains(&guard.range, position)
+            && guard.capabilities.iter().any(|c| satisfies(c, capability))
+    })
+}
+
+/// The capability a missing-require message of a lint rule asks for
+pub fn missing_capability(message: &str) -> Option<&str> {
+    MISSING_CAPABILITY
+        .captures(message)
+        .and_then(|captures| captures.get(1))
+        .map(|m| m.as_str())
+}
+
+/// Capabilities the script uses, with dialect families resolved to the configured variant
+/// Usage inside an `ihave` guard for the capability does not count, as it needs no require.
+pub fn used_capabilities(script: &Script, dialects: &BTreeMap<String, String>) -> BTreeSet<String> {
+    let mut used = BTreeSet::new();
+    let guards = ihave_guards(script);
+    let feature_at = |word: &str, range: &Range| {
+        CAPABILITY_FEATURES
+            .iter()
+            .find(|(f, _)| *f == word)
+            .map(|(_, capability)| capability.to_string())
+            .or_else(|| profile::vendor_capability(word).map(str::to_string))
+            .or_else(|| {
+                dialect::family_of(word)
+                    .map(|family| family.active(dialects).capability.to_string())
+            })
+            .filter(|capability| !is_guarded(&guards, capability, range.start))
+    };
+    let unguarded = |capability: &str, range: &Range| !is_guarded(&guards, capability, range.start);
+
+    let argument_lists: Vec<(&str, Range, &Vec<Argument>)> = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (c.name.as_str(), c.range, &c.arguments))
+        .chain(
+            script
+                .all_tests()
+                .into_iter()
+                .map(|t| (t.name.as_str(), t.range, &t.arguments)),
+        )
+        .collect();
+    for (name, range, arguments) in &argument_lists {
+        if *name == "require" {
+            continue;
+        }
+        let feature = |word: &str| feature_at(word, range);
+        used.extend(feature(name));
+        for (idx, argument) in arguments.iter().enumerate() {
+            let Some(tag) = argument.tag() else {
+                continue;
+            };
+            match tag {
+                // editheader defines its own :index and :last
+                ":index" | ":last" if matches!(*name, "addheader" | "deleteheader") => {}
+                // vacation defines its own :mime
+                ":mime" if *name == "vacation" => {}
+                ":flags" => used.extend(feature("setflag")),
+                ":comparator" => {
+                    if let Some(Argument::String(comparator)) = arguments.get(idx + 1)
+                        && let Some(capability) = sieve::comparator_requirement(&comparator.value)
+                        && unguarded(&capability, range)
+                    {
+                        used.insert(capability);
+                    }
+                }
+                _ => used.extend(feature(tag)),
+            }
+        }
+    }
+
+    for test in script.all_tests() {
+        if test.name != "envelope" {
+            continue;
+        }
+        if let Some(parts) = test.positional_arguments().first() {
+            used.extend(
+                parts
+                    .strings()
+                    .into_iter()
+                    .filter_map(|part| sieve::envelope_part(&part.value)?.capability)
+                    .filter(|capability| unguarded(capability, &test.range))
+                    .map(str::to_string),
+            );
+        }
+    }
+
+    for (name, range, arguments) in &argument_lists {
+        if *name == "require" {
+            continue;
+        }
+        for string in arguments.iter().flat_map(|a| a.strings()) {
+            if VARIABLE_REFERENCE.is_match(&string.value) && unguarded("variables", range) {
+                used.insert("variables".to_string());
+            }
+            if !parser::encoded_characters(&string.value).is_empty()
+                && unguarded("encoded-character", range)
+            {
+                used.insert("encoded-character".to_string());
+            }
+        }
+    }
+    used
+}
+
+/// Whether unused requires of a capability may be removed, i.e. its usage is detectable
+fn is_managed(capability: &str) -> bool {
+    CAPABILITY_FEATURES.iter().any(|(_, c)| *c == capability)
+        || capability.starts_with("comparator-")
+        || dialect::family_of(capability).is_some()
+        || profile::is_vendor_capability(capability)
+        || sieve::ENVELOPE_PARTS.iter().any(|part| part.capability == Some(capability))
+        || capability == "encoded-character"
+}
+
+/// Capabilities that provide everything another one does, so requiring both is redundant
+/// "spamtestplus" extends `spamtest` with `:percent` (RFC 5235 section 3.2).
+pub const IMPLIED_CAPABILITIES: &[(&str, &str)] = &[("spamtestplus", "spamtest")];
+
+/// Whether requiring one capability already provides another
+pub fn implies(required: &str, other: &str) -> bool {
+    IMPLIED_CAPABILITIES
+        .iter()
+        .any(|(r, o)| r.eq_ignore_ascii_case(required) && o.eq_ignore_ascii_case(other))
+}
+
+/// Whether a required capability satisfies a used one
+/// Any variant of a dialect family counts; the mismatch itself is a diagnostic. So does a
+/// capability implying the used one.
+pub fn satisfies(required: &str, used: &str) -> bool {
+    required == used
+        || implies(required, used)
+        || dialect::family_of(required)
+            .is_some_and(|family| dialect::family_of(used) == Some(family))
+}
+
+/// Render a require statement for a list of capabilities
+fn require_statement(capabilities: &[String]) -> String {
+    let quoted: Vec<String> = capabilities.iter().map(|c| refactor::quote_string(c)).collect();
+    match quoted.as_slice() {
+        [single] => format!("require {};", single),
+        _ => format!("require [{}];", quoted.join(", ")),
+    }
+}
+
+/// Whether the usage of capabilities can be trusted, i.e. the script has no syntax errors
+fn usage_is_reliable(script: &Script) -> bool {
+    !script
+        .errors
+        .iter()
+        .any(|e| e.severity == DiagnosticSeverity::ERROR)
+}
+
+/// The require statements at the top of a script
+fn require_statements(script: &Script) -> Vec<&Command> {
+    script
+        .commands
+        .iter()
+        .take_while(|c| c.name == "require")
+        .collect()
+}
+
+/// Required capabilities the script never uses, with their require statement
+/// Returns nothing for scripts with syntax errors, where usage cannot be trusted.
+pub fn unused_requires<'a>(
+    script: &'a Script,
+    dialects: &BTreeMap<String, String>,
+) -> Vec<(&'a Command, &'a StringLiteral)> {
+    if !usage_is_reliable(script) {
+        return Vec::new();
+    }
+    let used = used_capabilities(script, dialects);
+    require_statements(script)
+        .into_iter()
+        .flat_map(|command| {
+            command
+                .arguments
+                .iter()
+                .flat_map(|a| a.strings())
+                .map(move |capability| (command, capability))
+        })
+        .filter(|(_, capability)| {
+            is_managed(&capability.value) && !used.iter().any(|u| satisfies(&capability.value, u))
+        })
+        .collect()
+}
+
+/// Edit that removes one capability from a require statement
+/// The remaining capabilities are written as a single string or a list; a statement left
+/// without any is removed.
+pub fn remove_requirement(text: &str, command: &Command, capability: &StringLiteral) -> TextEdit {
+    let kept: Vec<String> = command
+        .arguments
+        .iter()
+        .flat_map(|a| a.strings())
+        .filter(|c| c.range != capability.range)
+        .map(|c| c.value.clone())
+        .collect();
+    if kept.is_empty() {
+        let lines: Vec<&str> = text.split('\n').collect();
+        TextEdit {
+            range: removal_range(&lines, command.range),
+            new_text: String::new(),
+        }
+    } else {
+        TextEdit {
+            range: command.range,
+            new_text: require_statement(&kept),
+        }
+    }
+}
+
+/// Edit to the require statements when a script switches from one capability to another
+/// `to` takes the place of `from` in its statement, or joins the last one; `from` stays when
+/// `keep` is set because other parts of the script still use it. Returns `None` when the
+/// requires need no change.
+pub fn switch_requirement(
+    text: &str,
+    script: &Script,
+    from: &str,
+    to: &str,
+    keep: bool,
+) -> Option<TextEdit> {
+    let requires = require_statements(script);
+    let has_to = script.required_capabilities().iter().any(|c| c == to);
+    let capabilities_of = |command: &Command| -> Vec<String> {
+        command
+            .arguments
+            .iter()
+            .flat_map(|a| a.strings())
+            .map(|s| s.value.clone())
+            .collect()
+    };
+    let Some(command) = requires
+        .iter()
+        .find(|c| capabilities_of(c).iter().any(|capability| capability == from))
+        .or(requires.last())
+    else {
+        let line = script.commands.first().map_or(0, |c| c.range.start.line);
+        let position = Position { line, character: 0 };
+        return Some(TextEdit {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            new_text: format!("{}\n", require_statement(&[to.to_string()])),
+        });
+    };
+
+    let capabilities = capabilities_of(command);
+    let mut kept = Vec::new();
+    for capability in &capabilities {
+        if capability == from && !has_to {
+            if keep {
+                kept.push(capability.clone());
+            }
+            kept.push(to.to_string());
+        } else if capability != from || keep {
+            kept.push(capability.clone());
+        }
+    }
+    if !has_to && !kept.iter().any(|c| c == to) {
+        kept.push(to.to_string());
+    }
+    if kept == capabilities {
+        return None;
+    }
+    if kept.is_empty() {
+        let lines: Vec<&str> = text.split('\n').collect();
+        return Some(TextEdit {
+            range: removal_range(&lines, command.range),
+            new_text: String::new(),
+        });
+    }
+    Some(TextEdit {
+        range: command.range,
+        new_text: require_statement(&kept),
+    })
+}
+
+/// Edit adding capabilities to the require statements of a script
+/// They join the last statement, or a new one at the top. Returns `None` when the script
+/// already requires all of them.
+pub fn add_requirements(script: &Script, capabilities: &[String]) -> Option<TextEdit> {
+    let required = script.required_capabilities();
+    let missing: Vec<String> = capabilities
+        .iter()
+        .filter(|capability| !required.iter().any(|r| satisfies(r, capability)))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    let Some(command) = require_statements(script).pop() else {
+        let line = script.commands.first().map_or(0, |c| c.range.start.line);
+        let position = Position { line, character: 0 };
+        return Some(TextEdit {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            new_text: format!("{}\n", require_statement(&missing)),
+        });
+    };
+    let mut kept: Vec<String> = command
+        .arguments
+        .iter()
+        .flat_map(|a| a.strings())
+        .map(|s| s.value.clone())
+        .collect();
+    kept.extend(missing);
+    Some(TextEdit {
+        range: command.range,
+        new_text: require_statement(&kept),
+    })
+}
+
+/// How the capabilities of a script's require statements are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequireLayout {
+    /// A single sorted `require [...]` list
+    Merged,
+    /// One `require "...";` statement per capability
+    Split,
+}
+
+/// Edits rewriting the require statements at the top of a script into a layout
+/// Returns no edits when the script already has that layout.
+pub fn organize_requires(text: &str, script: &Script, layout: RequireLayout) -> Vec<TextEdit> {
+    let requires = require_statements(script);
+    let capabilities_of = |command: &Command| -> Vec<String> {
+        command
+            .arguments
+            .iter()
+            .flat_map(|a| a.strings())
+            .map(|s| s.value.clone())
+            .collect()
+    };
+    let lines: Vec<&str> = text.split('\n').collect();
+    match layout {
+        RequireLayout::Merged => {
+            let [first, rest @ ..] = requires.as_slice() else {
+                return Vec::new();
+            };
+            if rest.is_empty() {
+                return Vec::new();
+            }
+            let capabilities: BTreeSet<String> =
+                requires.iter().flat_map(|c| capabilities_of(c)).collect();
+            let capabilities: Vec<String> = capabilities.into_iter().collect();
+            let mut edits = vec![TextEdit {
+                range: first.range,
+                new_text: require_statement(&capabilities),
+            }];
+            edits.extend(rest.iter().map(|command| TextEdit {
+                range: removal_range(&lines, command.range),
+                new_text: String::new(),
+            }));
+            edits
+        }
+        RequireLayout::Split => requires
+            .iter()
+            .filter_map(|command| {
+                let capabilities = capabilities_of(command);
+                if capabilities.len() < 2 {
+                    return None;
+                }
+                let indent: String = lines
+                    .get(command.range.start.line as usize)
+                    .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+                    .unwrap_or_default();
+                let statements: Vec<String> = capabilities
+                    .iter()
+                    .map(|capability| require_statement(std::slice::from_ref(capability)))
+                    .collect();
+                Some(TextEdit {
+                    range: command.range,
+                    new_text: statements.join(&format!("\n{}", indent)),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Edits organizing the require statements at the top of a script like imports
+/// They are merged into one sorted list without duplicates, leaving out unused capabilities
+/// unless the script has syntax errors. Returns no edits when the requires are organized.
+pub fn tidy_requires(
+    text: &str,
+    script: &Script,
+    dialects: &BTreeMap<String, String>,
+) -> Vec<TextEdit> {
+    let requires = require_statements(script);
+    let Some(first) = requires.first() else {
+        return Vec::new();
+    };
+    let mut capabilities: BTreeSet<String> = requires
+        .iter()
+        .flat_map(|c| c.arguments.iter().flat_map(|a| a.strings()))
+        .map(|s| s.value.clone())
+        .collect();
+    if usage_is_reliable(script) {
+        let used = used_capabilities(script, dialects);
+        capabilities.retain(|c| !is_managed(c) || used.iter().any(|u| satisfies(c, u)));
+    }
+    let capabilities: Vec<String> = capabilities.into_iter().collect();
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    let removal = |command: &&Command| TextEdit {
+        range: removal_range(&lines, command.range),
+        new_text: String::new(),
+    };
+    if capabilities.is_empty() {
+        return requires.iter().map(removal).collect();
+    }
+    let statement = require_statement(&capabilities);
+    let current =
+        &text[parser::offset_at(text, first.range.start)..parser::offset_at(text, first.range.end)];
+    if requires.len() == 1 && current == statement {
+        return Vec::new();
+    }
+    let mut edits = vec![TextEdit {
+        range: first.range,
+        new_text: statement,
+    }];
+    edits.extend(requires[1..].iter().map(removal));
+    edits
+}
+
+/// Edits that add missing and remove unused requires
+/// Returns no edits for scripts with syntax errors, where usage cannot be trusted.
+pub fn sync_requires(
+    text: &str,
+    script: &Script,
+    dialects: &BTreeMap<String, String>,
+) -> Vec<TextEdit> {
+    if !usage_is_reliable(script) {
+        return Vec::new();
+    }
+
+    let used = used_capabilities(script, dialects);
+    let requires = require_statements(script);
+    let required: Vec<String> = requires
+        .iter()
+        .flat_map(|c| c.arguments.iter().flat_map(|a| a.strings()))
+        .map(|s| s.value.clone())
+        .collect();
+    let missing: Vec<String> = used
+        .iter()
+        .filter(|u| !required.iter().any(|r| satisfies(r, u)))
+        .cloned()
+        .collect();
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut edits = Vec::new();
+    for (idx, command) in requires.iter().enumerate() {
+        let capabilities: Vec<String> = command
+            .arguments
+            .iter()
+            .flat_map(|a| a.strings())
+            .map(|s| s.value.clone())
+            .collect();
+        let mut kept: Vec<String> = capabilities
+            .iter()
+            .filter(|c| !is_managed(c) || used.iter().any(|u| satisfies(c, u)))
+            .cloned()
+            .collect();
+        // Missing capabilities join the last require statement
+        if idx + 1 == requires.len() {
+            kept.extend(missing.iter().cloned());
+        }
+        if kept == capabilities {
+            continue;
+        }
+        if kept.is_empty() {
+            edits.push(TextEdit {
+                range: removal_range(&lines, command.range),
+                new_text: String::new(),
+            });
+        } else {
+            edits.push(TextEdit {
+                range: command.range,
+                new_text: require_statement(&kept),
+            });
+        }
+    }
+
+    if requires.is_empty() && !missing.is_empty() {
+        let line = script.commands.first().map_or(0, |c| c.range.start.line);
+        let position = Position { line, character: 0 };
+        edits.push(TextEdit {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            new_text: format!("{}\n", require_statement(&missing)),
+        });
+    }
+    edits
+}
+
+/// Range to delete for a statement: its whole lines when nothing else shares them
+fn removal_range(lines: &[&str], range: Range) -> Range {
+    let before = lines
+        .get(range.start.line as usize)
+        .map(|l| l.chars().take(range.start.character as usize).collect::<String>())
+        .unwrap_or_default();
+    let after = lines
+        .get(range.end.line as usize)
+        .map(|l| l.chars().skip(range.end.character as usize).collect::<String>())
+        .unwrap_or_default();
+    if before.trim().is_empty() && after.trim().is_empty() {
+        Range {
+            start: Position {
+                line: range.start.line,
+                character: 0,
+            },
+            end: Position {
+                line: range.end.line + 1,
+                character: 0,
+            },
+        }
+    } else {
+        range
+    }
+}
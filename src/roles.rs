@@ -0,0 +1,251 @@
+// ================================================================================================
+// STRING ROLES
+// ================================================================================================
+//
+// Sieve passes almost everything as a string, so what a string means depends on where it sits:
+// the first argument of `header` names header fields, the last one holds keys compared against
+// the message, and the argument of `require` names extensions. Roles are derived from the
+// syntax tree alone and explain strings on hover.
+
+use crate::documentation;
+use crate::parser::{Argument, Script, StringLiteral, range_contains};
+use crate::sieve::{self, RELATIONAL_OPERATORS, SIEVE_COMPARATORS, SIEVE_EXTENSIONS, TIME_ZONES};
+use tower_lsp::lsp_types::Position;
+
+/// What a string argument means to the command or test it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringRole {
+    Extension,
+    HeaderName,
+    EnvelopePart,
+    DatePart,
+    /// A key compared against the message with the given match type and comparator
+    MatchKey {
+        match_type: String,
+        comparator: String,
+    },
+    Mailbox,
+    Address,
+    /// One or more space-separated IMAP flags
+    Flag,
+    VariableName,
+    Comparator,
+    Relation,
+    TimeZone,
+    /// Name of a mailbox or server metadata entry
+    MetadataEntry,
+}
+
+/// The string literal at `position` and its role, if the role is known
+pub fn string_at(script: &Script, position: Position) -> Option<(&StringLiteral, StringRole)> {
+    let commands = script.all_commands().into_iter().map(|c| (&c.name, &c.arguments));
+    let tests = script.all_tests().into_iter().map(|t| (&t.name, &t.arguments));
+    commands.chain(tests).find_map(|(name, arguments)| {
+        argument_roles(name, arguments)
+            .into_iter()
+            .flat_map(|(argument, role)| {
+                argument.strings().into_iter().map(move |string| (string, role.clone()))
+            })
+            .find(|(string, _)| range_contains(&string.range, position))
+    })
+}
+
+/// Roles of the string arguments of a command or test
+/// Tag values are classified by their tag, positional arguments by their place.
+fn argument_roles<'a>(name: &str, arguments: &'a [Argument]) -> Vec<(&'a Argument, StringRole)> {
+    let mut roles = Vec::new();
+    let mut positional = Vec::new();
+    let mut match_type = ":is";
+    let mut comparator = "i;ascii-casemap".to_string();
+
+    let mut iter = arguments.iter();
+    while let Some(argument) = iter.next() {
+        let Some(tag) = argument.tag() else {
+            positional.push(argument);
+            continue;
+        };
+        if matches!(tag, ":is" | ":contains" | ":matches" | ":regex") {
+            match_type = tag;
+        }
+        if !sieve::TAGS_WITH_VALUE.contains(&tag) {
+            continue;
+        }
+        let Some(value) = iter.next() else {
+            break;
+        };
+        let role = match tag {
+            ":comparator" => {
+                if let Some(name) = value.strings().first() {
+                    comparator = name.value.to_lowercase();
+                }
+                StringRole::Comparator
+            }
+            ":value" | ":count" => {
+                match_type = tag;
+                StringRole::Relation
+            }
+            ":zone" => StringRole::TimeZone,
+            ":flags" => StringRole::Flag,
+            ":from" | ":addresses" => StringRole::Address,
+            _ => continue,
+        };
+        roles.push((value, role));
+    }
+
+    let key = StringRole::MatchKey {
+        match_type: match_type.to_string(),
+        comparator,
+    };
+    // Places whose meaning is not a role of its own, like the source of `string`, are `None`
+    let places = match (name, positional.len()) {
+        ("require" | "ihave", _) => vec![Some(StringRole::Extension)],
+        ("header" | "address", _) => vec![Some(StringRole::HeaderName), Some(key)],
+        ("envelope", _) => vec![Some(StringRole::EnvelopePart), Some(key)],
+        ("exists", _) => vec![Some(StringRole::HeaderName)],
+        ("date", _) => vec![
+            Some(StringRole::HeaderName),
+            Some(StringRole::DatePart),
+            Some(key),
+        ],
+        ("currentdate", _) => vec![Some(StringRole::DatePart), Some(key)],
+        ("body" | "spamtest" | "virustest", _) => vec![Some(key)],
+        ("string" | "environment", _) => vec![None, Some(key)],
+        ("fileinto" | "mailboxexists", _) => vec![Some(StringRole::Mailbox)],
+        ("metadata", _) => vec![
+            Some(StringRole::Mailbox),
+            Some(StringRole::MetadataEntry),
+            Some(key),
+        ],
+        ("metadataexists", _) => vec![Some(StringRole::Mailbox), Some(StringRole::MetadataEntry)],
+        ("servermetadata", _) => vec![Some(StringRole::MetadataEntry), Some(key)],
+        ("servermetadataexists", _) => vec![Some(StringRole::MetadataEntry)],
+        ("redirect", _) => vec![Some(StringRole::Address)],
+        ("setflag" | "addflag" | "removeflag" | "hasflag", 2) => {
+            vec![Some(StringRole::VariableName), Some(StringRole::Flag)]
+        }
+        ("setflag" | "addflag" | "removeflag" | "hasflag", _) => vec![Some(StringRole::Flag)],
+        ("set" | "global", _) => vec![Some(StringRole::VariableName)],
+        ("addheader" | "deleteheader", _) => vec![Some(StringRole::HeaderName)],
+        _ => Vec::new(),
+    };
+    roles.extend(
+        positional
+            .into_iter()
+            .zip(places)
+            .filter_map(|(argument, role)| Some((argument, role?))),
+    );
+    roles
+}
+
+/// Markdown explaining a string with the given role
+pub fn role_markdown(value: &str, role: &StringRole) -> String {
+    let (kind, details) = match role {
+        StringRole::Extension => {
+            return match SIEVE_EXTENSIONS.get(value.to_lowercase().as_str()) {
+                Some(description) => documentation::extension_markdown(value, description),
+                None => format!("**{}** - Sieve extension\n\nUnknown extension\n", value),
+            };
+        }
+        StringRole::HeaderName => (
+            "header name",
+            sieve::common_header(value).unwrap_or("Header field of the message").to_string(),
+        ),
+        StringRole::EnvelopePart => (
+            "envelope part",
+            sieve::envelope_part(value)
+                .map_or("Unknown envelope part", |part| part.description)
+                .to_string(),
+        ),
+        StringRole::DatePart => (
+            "date part",
+            describe(sieve::DATE_PARTS, value).unwrap_or("Unknown date part").to_string(),
+        ),
+        StringRole::MatchKey {
+            match_type,
+            comparator,
+        } => {
+            let comparison = match match_type.as_str() {
+                ":contains" => "The tested value must contain this key",
+                ":matches" => "Wildcard pattern: `*` matches any text and `?` a single character",
+                ":regex" => "Regular expression the tested value must match",
+                ":value" => "Compared with the tested value using the relation",
+                ":count" => "Compared with the number of tested values using the relation",
+                _ => "The tested value must equal this key",
+            };
+            (
+                "match key",
+                format!(
+                    "{}\n\nMatch type `{}`, comparator `{}`",
+                    comparison, match_type, comparator
+                ),
+            )
+        }
+        StringRole::Mailbox => ("mailbox", "Name of a mailbox folder".to_string()),
+        StringRole::Address => {
+            let details = match value.rsplit_once('@') {
+                Some((local, domain)) if sieve::is_email_address(value) => {
+                    format!("Local part `{}`, domain `{}`", local, domain)
+                }
+                _ if value.contains("${") => "Address built from variables".to_string(),
+                _ => "Not a plain email address".to_string(),
+            };
+            ("email address", details)
+        }
+        StringRole::Flag => {
+            let flags = value
+                .split_whitespace()
+                .map(|flag| match describe(sieve::SYSTEM_FLAGS, flag) {
+                    Some(description) => format!("- `{}`: {}", flag, description),
+                    None => format!("- `{}`: IMAP keyword", flag),
+                })
+                .collect::<Vec<_>>();
+            ("IMAP flags", flags.join("\n"))
+        }
+        StringRole::VariableName => {
+            ("variable name", format!("Referenced as `${{{}}}`", value))
+        }
+        StringRole::Comparator => (
+            "comparator",
+            SIEVE_COMPARATORS
+                .get(value.to_lowercase().as_str())
+                .copied()
+                .unwrap_or("Unknown comparator")
+                .to_string(),
+        ),
+        StringRole::Relation => (
+            "relation",
+            RELATIONAL_OPERATORS
+                .get(value.to_lowercase().as_str())
+                .copied()
+                .unwrap_or("Unknown relation")
+                .to_string(),
+        ),
+        StringRole::TimeZone => (
+            "time zone",
+            TIME_ZONES.get(value).copied().unwrap_or("Not a known time zone offset").to_string(),
+        ),
+        StringRole::MetadataEntry => {
+            let scope = if value.to_lowercase().starts_with("/private/") {
+                "Private entry, only visible to the user"
+            } else {
+                "Shared entry, visible to every user"
+            };
+            let known = describe(sieve::METADATA_ENTRIES, value);
+            let details = match (known, sieve::metadata_entry_problem(value)) {
+                (Some(description), _) => format!("{}\n\n{}", description, scope),
+                (None, None) => scope.to_string(),
+                (None, Some(problem)) => format!("Not a valid entry name: {}", problem),
+            };
+            ("metadata entry", details)
+        }
+    };
+    format!("**\"{}\"** - {}\n\n{}\n", value, kind, details)
+}
+
+/// Description of `name` in a table of names and descriptions, ignoring case
+fn describe(table: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(entry, _)| entry.eq_ignore_ascii_case(name))
+        .map(|(_, description)| *description)
+}
@@ -0,0 +1,3474 @@
+// ================================================================================================
+// LINT RULES
+// ================================================================================================
+//
+// Semantic checks on the syntax tree of a script. Each check is a `LintRule` registered in
+// `RULES`: it has an id and reports into a `Sink` without access to the server, so rules can
+// be added and tested on their own. Each diagnostic carries its own severity, as one rule may
+// report problems of different weight; the `severity` setting overrides them per code. The
+// `rules` setting switches rules off by id, e.g. `{"argument-order": false}`. Line-based syntax
+// checks and checks that need workspace state stay in the server.
+
+use crate::dialect;
+use crate::implication;
+use crate::mailbox::MailboxConvention;
+use crate::notify;
+use crate::parser::{self, Argument, Command, Script};
+use crate::posix;
+use crate::profile::{self, Profile};
+use crate::refactor::{self, Negation};
+use crate::requires;
+use crate::variables;
+use crate::sieve::{self, EnvelopePart, RELATIONAL_OPERATORS, SIEVE_ACTIONS, SIEVE_COMPARATORS};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use tower_lsp::lsp_types::*;
+use tracing::{debug, trace, warn};
+
+/// A check run over the syntax tree of a script
+pub trait LintRule: Sync {
+    /// Identifier of the rule in the `rules` setting
+    fn id(&self) -> &'static str;
+
+    /// Report the problems of a script
+    fn check(&self, context: &RuleContext, sink: &mut Sink);
+}
+
+/// Everything a rule may look at
+pub struct RuleContext<'a> {
+    pub uri: &'a Url,
+    pub text: &'a str,
+    pub script: &'a Script,
+    /// Strict RFC compliance (`strict_mode`)
+    pub strict: bool,
+    pub mailbox: &'a MailboxConvention,
+    /// Specification variants per extension family (`dialects`)
+    pub dialects: &'a BTreeMap<String, String>,
+    /// Implementation the script is validated against, `None` for the generic dialect
+    pub profile: Option<&'static Profile>,
+    /// Capabilities the server advertises, when known
+    pub advertised: Option<&'a [String]>,
+    /// Addresses that deliver to the user's own mailbox (`own_addresses`)
+    pub own_addresses: &'a [String],
+}
+
+impl<'a> RuleContext<'a> {
+    /// Context with default settings, e.g. to run a rule on its own
+    pub fn new(uri: &'a Url, text: &'a str, script: &'a Script) -> Self {
+        lazy_static! {
+            static ref NO_MAILBOX: MailboxConvention = MailboxConvention::default();
+            static ref NO_DIALECTS: BTreeMap<String, String> = BTreeMap::new();
+        }
+        Self {
+            uri,
+            text,
+            script,
+            strict: false,
+            mailbox: &NO_MAILBOX,
+            dialects: &NO_DIALECTS,
+            profile: None,
+            advertised: None,
+            own_addresses: &[],
+        }
+    }
+}
+
+/// Collects the diagnostics reported by rules
+#[derive(Debug, Default)]
+pub struct Sink {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Sink {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+/// A rule made of a single check function
+pub struct FnRule {
+    pub id: &'static str,
+    pub check: fn(&RuleContext, &mut Sink),
+}
+
+impl LintRule for FnRule {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn check(&self, context: &RuleContext, sink: &mut Sink) {
+        (self.check)(context, sink)
+    }
+}
+
+/// Every rule, run in this order
+pub static RULES: &[&dyn LintRule] = &[
+    &FnRule {
+        id: "unreachable-code",
+        check: |cx, sink| check_unreachable_code(sink, &cx.script.commands),
+    },
+    &FnRule {
+        id: "conflicting-actions",
+        check: |cx, sink| check_conflicting_actions(sink, cx.uri, &cx.script.commands),
+    },
+    &FnRule {
+        id: "shadowed-rule",
+        check: |cx, sink| check_shadowed_rules(sink, cx.uri, &cx.script.commands),
+    },
+    &FnRule {
+        id: "address-value",
+        check: |cx, sink| check_address_values(sink, cx.script),
+    },
+    &FnRule {
+        id: "ihave",
+        check: |cx, sink| check_ihave(sink, cx.script),
+    },
+    &FnRule {
+        id: "subaddress",
+        check: |cx, sink| check_subaddress_parts(sink, cx.script),
+    },
+    &FnRule {
+        id: "comparator",
+        check: |cx, sink| check_comparators(sink, cx.script),
+    },
+    &FnRule {
+        id: "comparator-case",
+        check: |cx, sink| check_comparator_case(sink, cx.script),
+    },
+    &FnRule {
+        id: "relational-match",
+        check: |cx, sink| check_relational_matches(sink, cx.script),
+    },
+    &FnRule {
+        id: "size-test",
+        check: |cx, sink| check_size_tests(sink, cx.script),
+    },
+    &FnRule {
+        id: "spamtest",
+        check: |cx, sink| check_spam_tests(sink, cx.script),
+    },
+    &FnRule {
+        id: "metadata",
+        check: |cx, sink| check_metadata_tests(sink, cx.script),
+    },
+    &FnRule {
+        id: "encoded-character",
+        check: |cx, sink| check_encoded_characters(sink, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "time-zone",
+        check: |cx, sink| check_time_zones(sink, cx.script),
+    },
+    &FnRule {
+        id: "header-name",
+        check: |cx, sink| check_header_names(sink, cx.script),
+    },
+    &FnRule {
+        id: "regex-pattern",
+        check: |cx, sink| check_regex_patterns(sink, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "mailbox-path",
+        check: |cx, sink| check_mailbox_paths(sink, cx.script, cx.mailbox),
+    },
+    &FnRule {
+        id: "match-type",
+        check: |cx, sink| check_wildcards(sink, cx.script),
+    },
+    &FnRule {
+        id: "vacation",
+        check: |cx, sink| check_vacation(sink, cx.uri, cx.script),
+    },
+    &FnRule {
+        id: "redirect",
+        check: |cx, sink| check_redirects(sink, cx.uri, cx.script, cx.own_addresses),
+    },
+    &FnRule {
+        id: "address-syntax",
+        check: |cx, sink| check_address_syntax(sink, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "prefer-ereject",
+        check: |cx, sink| check_prefer_ereject(sink, cx.text, cx.script, cx.profile, cx.advertised),
+    },
+    &FnRule {
+        id: "variables",
+        check: |cx, sink| check_variables(sink, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "editheader",
+        check: |cx, sink| check_editheader(sink, cx.script),
+    },
+    &FnRule {
+        id: "mime",
+        check: |cx, sink| check_mime(sink, cx.script),
+    },
+    &FnRule {
+        id: "notify",
+        check: |cx, sink| check_notify(sink, cx.script, cx.dialects),
+    },
+    &FnRule {
+        id: "dialect-mismatch",
+        check: |cx, sink| check_dialects(sink, cx.script, cx.dialects),
+    },
+    &FnRule {
+        id: "unknown-extension",
+        check: |cx, sink| check_unknown_extensions(sink, cx.script, cx.profile, cx.advertised),
+    },
+    &FnRule {
+        id: "unknown-identifier",
+        check: |cx, sink| check_unknown_identifiers(sink, cx.script),
+    },
+    &FnRule {
+        id: "unused-require",
+        check: |cx, sink| check_unused_requires(sink, cx.text, cx.script, cx.dialects),
+    },
+    &FnRule {
+        id: "duplicate-require",
+        check: |cx, sink| check_duplicate_requires(sink, cx.uri, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "advertised-capabilities",
+        check: |cx, sink| {
+            if let Some(advertised) = cx.advertised {
+                check_advertised_capabilities(sink, cx.script, advertised);
+            }
+        },
+    },
+    &FnRule {
+        id: "server-dialect",
+        check: |cx, sink| {
+            // Requires are left to the advertised capabilities when those are known
+            if let Some(profile) = cx.profile {
+                check_profile(sink, cx.script, profile, cx.advertised.is_none());
+            }
+        },
+    },
+    &FnRule {
+        id: "envelope-part",
+        check: |cx, sink| check_envelope_parts(sink, cx.script, cx.profile),
+    },
+    &FnRule {
+        id: "keep-idiom",
+        check: |cx, sink| check_keep_idioms(sink, cx.script, cx.profile),
+    },
+    &FnRule {
+        id: "redundant-negation",
+        check: |cx, sink| check_negations(sink, cx.text, cx.script),
+    },
+    &FnRule {
+        id: "argument-order",
+        check: |cx, sink| check_argument_order(sink, cx.text, cx.script, cx.strict),
+    },
+];
+
+/// The rule with an id
+pub fn find(id: &str) -> Option<&'static dyn LintRule> {
+    RULES.iter().copied().find(|rule| rule.id() == id)
+}
+
+/// Run the rules `enabled` accepts over a script
+pub fn run(context: &RuleContext, enabled: impl Fn(&str) -> bool) -> Vec<Diagnostic> {
+    let mut sink = Sink::default();
+    for rule in RULES.iter().filter(|rule| enabled(rule.id())) {
+        trace!("Running rule {}", rule.id());
+        rule.check(context, &mut sink);
+    }
+    // Capabilities used where `ihave` found them need no require (RFC 5463 section 4)
+    let guards = requires::ihave_guards(context.script);
+    let mut diagnostics = sink.into_diagnostics();
+    diagnostics.retain(|d| {
+        d.code != Some(NumberOrString::String("missing-require".to_string()))
+            || !requires::missing_capability(&d.message)
+                .is_some_and(|capability| requires::is_guarded(&guards, capability, d.range.start))
+    });
+    diagnostics
+}
+
+/// Flag statements that can never take effect within a block of commands
+/// Covers code after `stop`, actions after `discard`/`reject` and branches after `if true`
+fn check_unreachable_code(sink: &mut Sink, commands: &[Command]) {
+    trace!("Checking for unreachable code");
+    let mut stopped: Option<&str> = None;
+    let mut cancelled_by: Option<&str> = None;
+    let mut chain_always_true = false;
+
+    for command in commands {
+        if let Some(cause) = stopped {
+            let message = match cause {
+                "break" => "Unreachable code: statement follows 'break'",
+                "error" => "Unreachable code: statement follows 'error'",
+                _ => "Unreachable code: statement follows 'stop'",
+            };
+            sink.push(unreachable_diagnostic(command, message.to_string()));
+            continue;
+        }
+
+        match command.name.as_str() {
+            "elsif" | "else" if chain_always_true => {
+                sink.push(unreachable_diagnostic(
+                    command,
+                    format!(
+                        "Unreachable code: '{}' branch follows a test that is always true",
+                        command.name
+                    ),
+                ));
+                continue;
+            }
+            "if" | "elsif" => {
+                chain_always_true = command.tests.first().is_some_and(|t| t.name == "true");
+            }
+            _ => chain_always_true = false,
+        }
+
+        if let Some(cause) = cancelled_by
+            && !matches!(command.name.as_str(), "stop" | "break" | "error")
+            && SIEVE_ACTIONS.contains(&command.name.as_str())
+        {
+            sink.push(unreachable_diagnostic(
+                command,
+                format!("Unreachable code: action follows an unconditional '{}'", cause),
+            ));
+            continue;
+        }
+
+        match command.name.as_str() {
+            "stop" | "break" | "error" => stopped = Some(command.name.as_str()),
+            "discard" => cancelled_by = Some("discard"),
+            "reject" => cancelled_by = Some("reject"),
+            _ => {}
+        }
+
+        if let Some(block) = &command.block {
+            check_unreachable_code(sink, &block.commands);
+        }
+    }
+}
+
+/// Flag action combinations within one block that contradict each other
+/// e.g. `reject` together with `fileinto`, repeated `vacation`, or `keep` after `discard`
+fn check_conflicting_actions(
+    sink: &mut Sink,
+    uri: &Url,
+    commands: &[Command],
+) {
+    trace!("Checking for conflicting actions");
+    let mut seen: Vec<&Command> = Vec::new();
+
+    for command in commands {
+        let find = |names: &[&str]| {
+            seen.iter()
+                .find(|c| names.contains(&c.name.as_str()))
+                .copied()
+        };
+
+        let conflict = match command.name.as_str() {
+            "reject" | "ereject" => find(&["fileinto"]).map(|other| {
+                (other, "'reject' cannot be combined with 'fileinto' (RFC 5429)")
+            }),
+            "fileinto" => find(&["reject", "ereject"]).map(|other| {
+                (other, "'fileinto' cannot be combined with 'reject' (RFC 5429)")
+            }),
+            "vacation" => find(&["vacation"])
+                .map(|other| (other, "Only one 'vacation' action may be executed (RFC 5230)")),
+            "keep" => find(&["discard"])
+                .map(|other| (other, "'keep' contradicts the preceding 'discard'")),
+            _ => None,
+        };
+
+        if let Some((other, message)) = conflict {
+            warn!("Conflicting actions: {}", message);
+            sink.push(Diagnostic {
+                range: command.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("conflicting-actions".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5429#section-2")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message: message.to_string(),
+                related_information: Some(vec![
+                    DiagnosticRelatedInformation {
+                        location: Location {
+                            uri: uri.clone(),
+                            range: other.range,
+                        },
+                        message: format!("Conflicting '{}' action", other.name),
+                    },
+                    DiagnosticRelatedInformation {
+                        location: Location {
+                            uri: uri.clone(),
+                            range: command.range,
+                        },
+                        message: format!("Conflicting '{}' action", command.name),
+                    },
+                ]),
+                tags: None,
+                data: None,
+            });
+        }
+
+        seen.push(command);
+
+        if let Some(block) = &command.block {
+            check_conflicting_actions(sink, uri, &block.commands);
+        }
+    }
+}
+
+/// Flag rules that can never match within a block of commands
+/// A rule is shadowed when an earlier `if` whose block ends in `stop` matches whenever it does,
+/// and an `elsif` branch when an earlier branch of its chain does. See `implication`.
+fn check_shadowed_rules(sink: &mut Sink, uri: &Url, commands: &[Command]) {
+    trace!("Checking for shadowed rules");
+    let mut stoppers: Vec<&Command> = Vec::new();
+    let mut chain: Vec<&Command> = Vec::new();
+
+    for command in commands {
+        if command.name != "elsif" {
+            chain.clear();
+        }
+        let Some(test) = command.tests.first() else {
+            continue;
+        };
+        if !matches!(command.name.as_str(), "if" | "elsif") {
+            continue;
+        }
+
+        let branch = chain
+            .iter()
+            .find(|earlier| earlier.tests.first().is_some_and(|t| implication::implies(test, t)));
+        let stopper = stoppers
+            .iter()
+            .find(|earlier| earlier.tests.first().is_some_and(|t| implication::implies(test, t)));
+        let shadowed = match (branch, stopper) {
+            (Some(earlier), _) => Some((
+                *earlier,
+                format!(
+                    "Branch is never taken: the branch on line {} matches whenever this one does",
+                    earlier.range.start.line + 1
+                ),
+                "Branch taken instead",
+            )),
+            (None, Some(earlier)) => Some((
+                *earlier,
+                format!(
+                    "Rule never matches: the rule on line {} matches whenever this one does \
+                     and stops",
+                    earlier.range.start.line + 1
+                ),
+                "Rule that stops first",
+            )),
+            (None, None) => None,
+        };
+
+        if let Some((earlier, message, related)) = shadowed {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: command.header_range(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("shadowed-rule".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.3")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: earlier.header_range(),
+                    },
+                    message: related.to_string(),
+                }]),
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                data: None,
+            });
+        } else if command.name == "if"
+            && command
+                .block
+                .as_ref()
+                .is_some_and(|block| block.commands.iter().any(|c| c.name == "stop"))
+        {
+            stoppers.push(command);
+        }
+        chain.push(command);
+
+        if let Some(block) = &command.block {
+            check_shadowed_rules(sink, uri, &block.commands);
+        }
+    }
+}
+
+/// Flag `address` test keys that can never match the parsed address
+/// Display names and angle brackets are never part of the compared value, and `:domain` or
+/// `:localpart` comparisons only see one half of the address. A quick fix replacement is
+/// attached to each diagnostic in its `data` field.
+fn check_address_values(sink: &mut Sink, script: &Script) {
+    trace!("Checking address test values");
+    lazy_static! {
+        static ref ADDRESS_IN_BRACKETS: Regex = Regex::new(r"<([^<>]*@[^<>]*)>").unwrap();
+    }
+
+    for test in script.all_tests() {
+        if test.name != "address" {
+            continue;
+        }
+        let part = test.arguments.iter().find_map(|a| match a.tag() {
+            Some(tag @ (":domain" | ":localpart" | ":all")) => Some(tag),
+            _ => None,
+        });
+        let Some(keys) = test.arguments.last() else {
+            continue;
+        };
+        for key in keys.strings() {
+            // Variables are expanded at runtime, so the final value is unknown
+            if key.value.contains("${") {
+                continue;
+            }
+            let (stripped, message) = match ADDRESS_IN_BRACKETS.captures(&key.value) {
+                Some(captures) => (
+                    captures[1].trim().to_string(),
+                    "Address tests compare the bare address; display names and angle brackets never match",
+                ),
+                None => (key.value.clone(), ""),
+            };
+            let (replacement, message) = match (part, stripped.split_once('@')) {
+                (Some(":domain"), Some((_, domain))) if !domain.is_empty() => (
+                    domain.to_string(),
+                    "':domain' compares only the part after '@'; the full address never matches",
+                ),
+                (Some(":localpart"), Some((local, _))) if !local.is_empty() => (
+                    local.to_string(),
+                    "':localpart' compares only the part before '@'; the full address never matches",
+                ),
+                _ => (stripped, message),
+            };
+            if message.is_empty() || replacement == key.value {
+                continue;
+            }
+
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: key.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("address-value".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-5.1")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message: message.to_string(),
+                related_information: None,
+                tags: None,
+                data: Some(serde_json::json!({
+                    "title": format!("Replace with \"{}\"", replacement),
+                    "replacement": refactor::quote_string(&replacement),
+                })),
+            });
+        }
+    }
+}
+
+/// Validate the `ihave` test and the `error` action (RFC 5463)
+/// `ihave` takes a list of capabilities and `error` a message; both need "ihave" to be
+/// required.
+fn check_ihave(sink: &mut Sink, script: &Script) {
+    trace!("Checking ihave and error");
+    let required = script.required_capabilities().iter().any(|c| c == "ihave");
+
+    let items = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (c.name.as_str(), c.name_range, &c.arguments))
+        .chain(
+            script
+                .all_tests()
+                .into_iter()
+                .map(|t| (t.name.as_str(), t.name_range, &t.arguments)),
+        );
+    for (name, name_range, arguments) in items {
+        let (expected, href) = match name {
+            "ihave" => (
+                "'ihave' expects a list of capabilities",
+                "https://datatracker.ietf.org/doc/html/rfc5463#section-4",
+            ),
+            "error" => (
+                "'error' expects a message",
+                "https://datatracker.ietf.org/doc/html/rfc5463#section-5",
+            ),
+            _ => continue,
+        };
+        let mut problems = Vec::new();
+        if !required {
+            problems.push((
+                name_range,
+                "missing-require",
+                DiagnosticSeverity::WARNING,
+                format!("'{}' requires the \"ihave\" extension", name),
+            ));
+        }
+        let valid = match arguments.as_slice() {
+            [Argument::String(_)] => true,
+            [Argument::StringList { .. }] => name == "ihave",
+            _ => false,
+        };
+        if !valid {
+            let range = match (arguments.first(), arguments.last()) {
+                (Some(first), Some(last)) => Range {
+                    start: first.range().start,
+                    end: last.range().end,
+                },
+                _ => name_range,
+            };
+            problems.push((
+                range,
+                "invalid-ihave",
+                DiagnosticSeverity::ERROR,
+                expected.to_string(),
+            ));
+        }
+
+        for (range, code, severity, message) in problems {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(href).unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Validate the `:user` and `:detail` address parts of the subaddress extension (RFC 5233)
+/// They only select a part of an address, so they belong to the `address` and `envelope`
+/// tests, and they need "subaddress" to be required.
+fn check_subaddress_parts(sink: &mut Sink, script: &Script) {
+    trace!("Checking subaddress parts");
+    let required = script.required_capabilities().iter().any(|c| c == "subaddress");
+
+    let items = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (c.name.as_str(), &c.arguments))
+        .chain(script.all_tests().into_iter().map(|t| (t.name.as_str(), &t.arguments)));
+    for (name, arguments) in items {
+        for argument in arguments {
+            let Some(tag @ (":user" | ":detail")) = argument.tag() else {
+                continue;
+            };
+            let (code, severity, message) = if !matches!(name, "address" | "envelope") {
+                (
+                    "invalid-address-part",
+                    DiagnosticSeverity::ERROR,
+                    format!("'{}' only applies to the 'address' and 'envelope' tests", tag),
+                )
+            } else if !required {
+                (
+                    "missing-require",
+                    DiagnosticSeverity::WARNING,
+                    format!("'{}' requires the \"subaddress\" extension", tag),
+                )
+            } else {
+                continue;
+            };
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: argument.range(),
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5233#section-4")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Validate `:comparator` arguments against the comparator registry
+/// Comparators other than i;octet and i;ascii-casemap must be required as "comparator-<name>"
+fn check_comparators(sink: &mut Sink, script: &Script) {
+    trace!("Checking comparators");
+    let required = script.required_capabilities();
+
+    let argument_lists = script
+        .all_commands()
+        .into_iter()
+        .map(|c| &c.arguments)
+        .chain(script.all_tests().into_iter().map(|t| &t.arguments));
+    for arguments in argument_lists {
+        for (idx, argument) in arguments.iter().enumerate() {
+            if argument.tag() != Some(":comparator") {
+                continue;
+            }
+            let (range, problem) = match arguments.get(idx + 1) {
+                Some(Argument::String(name)) => {
+                    let lower = name.value.to_lowercase();
+                    if !SIEVE_COMPARATORS.contains_key(lower.as_str()) {
+                        (
+                            name.range,
+                            Some((
+                                "unknown-comparator",
+                                DiagnosticSeverity::ERROR,
+                                format!("Unknown comparator '{}'", name.value),
+                            )),
+                        )
+                    } else {
+                        let missing = sieve::comparator_requirement(&lower)
+                            .filter(|capability| !required.contains(capability));
+                        (
+                            name.range,
+                            missing.map(|capability| {
+                                (
+                                    "missing-require",
+                                    DiagnosticSeverity::WARNING,
+                                    format!(
+                                        "Comparator '{}' must be required with \"{}\"",
+                                        name.value, capability
+                                    ),
+                                )
+                            }),
+                        )
+                    }
+                }
+                _ => (
+                    argument.range(),
+                    Some((
+                        "unknown-comparator",
+                        DiagnosticSeverity::ERROR,
+                        "':comparator' expects a comparator name string".to_string(),
+                    )),
+                ),
+            };
+
+            if let Some((code, severity, message)) = problem {
+                warn!("{}", message);
+                sink.push(Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: Some(CodeDescription {
+                        href: Url::parse(
+                            "https://datatracker.ietf.org/doc/html/rfc5228#section-2.7.3",
+                        )
+                        .unwrap(),
+                    }),
+                    source: Some("sieve-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+    }
+}
+
+/// Validate the relation of `:value` and `:count` match types (RFC 5231)
+/// The relation must be one of the six operators and the extension must be required
+fn check_relational_matches(sink: &mut Sink, script: &Script) {
+    trace!("Checking relational match types");
+    let required = script.required_capabilities().contains(&"relational".to_string());
+
+    let argument_lists = script
+        .all_commands()
+        .into_iter()
+        .map(|c| &c.arguments)
+        .chain(script.all_tests().into_iter().map(|t| &t.arguments));
+    for arguments in argument_lists {
+        for (idx, argument) in arguments.iter().enumerate() {
+            let Some(tag @ (":value" | ":count")) = argument.tag() else {
+                continue;
+            };
+
+            let mut problems = Vec::new();
+            if !required {
+                problems.push((
+                    argument.range(),
+                    "missing-require",
+                    DiagnosticSeverity::WARNING,
+                    format!("'{}' requires the \"relational\" extension", tag),
+                ));
+            }
+            match arguments.get(idx + 1) {
+                Some(Argument::String(relation))
+                    if RELATIONAL_OPERATORS
+                        .contains_key(relation.value.to_lowercase().as_str()) => {}
+                Some(Argument::String(relation)) => problems.push((
+                    relation.range,
+                    "invalid-relation",
+                    DiagnosticSeverity::ERROR,
+                    format!(
+                        "Unknown relation '{}': expected gt, ge, lt, le, eq or ne",
+                        relation.value
+                    ),
+                )),
+                _ => problems.push((
+                    argument.range(),
+                    "invalid-relation",
+                    DiagnosticSeverity::ERROR,
+                    format!("'{}' expects a relation string such as \"gt\"", tag),
+                )),
+            }
+
+            for (range, code, severity, message) in problems {
+                warn!("{}", message);
+                sink.push(Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: Some(CodeDescription {
+                        href: Url::parse(
+                            "https://datatracker.ietf.org/doc/html/rfc5231#section-4",
+                        )
+                        .unwrap(),
+                    }),
+                    source: Some("sieve-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+    }
+}
+
+/// Validate the offset given to `:zone` in `date` and `currentdate` tests (RFC 5260 section 4.1)
+/// Zone names such as "EST" are rejected by servers, so they get a quick fix to the offset.
+fn check_time_zones(sink: &mut Sink, script: &Script) {
+    trace!("Checking time zones");
+    for test in script.all_tests() {
+        for (idx, argument) in test.arguments.iter().enumerate() {
+            if argument.tag() != Some(":zone") {
+                continue;
+            }
+
+            let (range, message, data) = match test.arguments.get(idx + 1) {
+                Some(Argument::String(zone))
+                    if zone.decoded().contains("${") || sieve::is_zone_offset(&zone.decoded()) =>
+                {
+                    continue;
+                }
+                Some(Argument::String(zone)) => match sieve::named_zone_offset(&zone.value) {
+                    Some(offset) => (
+                        zone.range,
+                        format!(
+                            "Time zone '{}' must be given as an offset such as \"{}\"",
+                            zone.value, offset
+                        ),
+                        Some(serde_json::json!({
+                            "title": format!("Replace with \"{}\"", offset),
+                            "replacement": refactor::quote_string(offset),
+                        })),
+                    ),
+                    None => (
+                        zone.range,
+                        format!(
+                            "Invalid time zone '{}': expected an offset such as \"+0100\" or \"-0500\"",
+                            zone.value
+                        ),
+                        None,
+                    ),
+                },
+                _ => (
+                    argument.range(),
+                    "':zone' expects an offset string such as \"+0100\"".to_string(),
+                    None,
+                ),
+            };
+
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid-zone".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5260#section-4.1")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data,
+            });
+        }
+    }
+}
+
+/// Validate `${hex:...}` and `${unicode:...}` sequences (RFC 5228 section 2.4.2.4)
+/// They are only decoded when "encoded-character" is required; malformed sequences are kept
+/// verbatim by the server, while code points and octets that are not characters are errors.
+fn check_encoded_characters(sink: &mut Sink, text: &str, script: &Script) {
+    trace!("Checking encoded characters");
+    let required = script
+        .required_capabilities()
+        .contains(&"encoded-character".to_string());
+    let argument_lists = script
+        .all_commands()
+        .into_iter()
+        .map(|c| &c.arguments)
+        .chain(script.all_tests().into_iter().map(|t| &t.arguments));
+    for string in argument_lists.flatten().flat_map(|a| a.strings()) {
+        for sequence in parser::encoded_characters(&string.value) {
+            let source: String = string
+                .value
+                .chars()
+                .skip(sequence.start)
+                .take(sequence.end - sequence.start)
+                .collect();
+            let mut problems = Vec::new();
+            if !required {
+                problems.push((
+                    "missing-require",
+                    DiagnosticSeverity::WARNING,
+                    format!(
+                        "Encoded character '{}' requires the \"encoded-character\" extension",
+                        source
+                    ),
+                ));
+            }
+            match sequence.value {
+                parser::EncodedValue::Decoded(_) => {}
+                parser::EncodedValue::Malformed(reason) => problems.push((
+                    "invalid-encoding",
+                    DiagnosticSeverity::WARNING,
+                    format!("Malformed encoded character '{}' is kept as is: {}", source, reason),
+                )),
+                parser::EncodedValue::Invalid(reason) => problems.push((
+                    "invalid-encoding",
+                    DiagnosticSeverity::ERROR,
+                    format!("Invalid encoded character '{}': {}", source, reason),
+                )),
+            }
+
+            let range = string.value_range(text, sequence.start, sequence.end);
+            for (code, severity, message) in problems {
+                warn!("{}", message);
+                sink.push(Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: Some(CodeDescription {
+                        href: Url::parse(
+                            "https://datatracker.ietf.org/doc/html/rfc5228#section-2.4.2.4",
+                        )
+                        .unwrap(),
+                    }),
+                    source: Some("sieve-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+    }
+}
+
+/// Flag keys whose case works against the comparator
+/// With "i;octet" a key only matches the usual value of a header if the case is the same, e.g.
+/// "yes" never matches `X-Spam-Flag: YES`. Conversely, base64-like tokens compared with the
+/// default "i;ascii-casemap" also match tokens that differ only by case.
+fn check_comparator_case(sink: &mut Sink, script: &Script) {
+    trace!("Checking the case of comparator keys");
+    const OCTET: &str = "i;octet";
+    let href = "https://datatracker.ietf.org/doc/html/rfc4790#section-9.3";
+
+    for test in script.all_tests() {
+        if !matches!(test.name.as_str(), "header" | "string") {
+            continue;
+        }
+        let mut comparator = None;
+        let mut match_type = ":is";
+        let mut arguments = test.arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.tag() {
+                Some(":comparator") => {
+                    comparator = arguments.next().and_then(|a| a.strings().first().copied());
+                }
+                Some(tag @ (":is" | ":contains" | ":matches")) => match_type = tag,
+                Some(":regex" | ":value" | ":count") => match_type = "",
+                _ => {}
+            }
+        }
+        let positional = test.positional_arguments();
+        let [fields, keys] = positional.as_slice() else {
+            continue;
+        };
+        if match_type.is_empty() {
+            continue;
+        }
+        let keys = keys.strings();
+        let keys = keys.iter().filter(|key| !key.value.contains("${"));
+
+        match comparator {
+            Some(name) if name.value.eq_ignore_ascii_case(OCTET) => {
+                if test.name != "header" {
+                    continue;
+                }
+                let values: Vec<(&str, &str)> = fields
+                    .strings()
+                    .iter()
+                    .flat_map(|field| {
+                        sieve::typical_header_values(&field.value)
+                            .iter()
+                            .map(|value| (field.value.as_str(), *value))
+                    })
+                    .collect();
+                for key in keys {
+                    let cased = values.iter().find_map(|(header, value)| {
+                        let wildcard = key.value.contains(['*', '?']);
+                        let start = match match_type {
+                            ":contains" if !key.value.is_empty() => value
+                                .to_ascii_lowercase()
+                                .find(&key.value.to_ascii_lowercase()),
+                            ":contains" => None,
+                            ":matches" if wildcard => None,
+                            _ => value.eq_ignore_ascii_case(&key.value).then_some(0),
+                        }?;
+                        let cased = value.get(start..start + key.value.len())?;
+                        (cased != key.value).then_some((*header, *value, cased))
+                    });
+                    let Some((header, value, cased)) = cased else {
+                        continue;
+                    };
+                    let message = format!(
+                        "\"{}\" never matches the usual \"{}\" of '{}': \"{}\" is case-sensitive",
+                        key.value, value, header, OCTET
+                    );
+                    warn!("{}", message);
+                    sink.push(Diagnostic {
+                        range: key.range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("octet-case-mismatch".to_string())),
+                        code_description: Some(CodeDescription {
+                            href: Url::parse(href).unwrap(),
+                        }),
+                        source: Some("sieve-lsp".to_string()),
+                        message,
+                        related_information: None,
+                        tags: None,
+                        data: Some(serde_json::json!({
+                            "title": format!("Replace with \"{}\"", cased),
+                            "replacement": refactor::quote_string(cased),
+                        })),
+                    });
+                }
+            }
+            Some(name) if !name.value.eq_ignore_ascii_case("i;ascii-casemap") => {}
+            _ => {
+                let Some(key) = keys.into_iter().find(|key| looks_case_sensitive(&key.value))
+                else {
+                    continue;
+                };
+                let message = format!(
+                    "\"{}\" looks like a token where case matters, but the comparison ignores case",
+                    key.value
+                );
+                debug!("{}", message);
+                let fix = match comparator {
+                    Some(name) => serde_json::json!({
+                        "title": format!("Compare with \"{}\"", OCTET),
+                        "replacement": format!("\"{}\"", OCTET),
+                        "range": name.range,
+                    }),
+                    None => serde_json::json!({
+                        "title": format!("Compare with \"{}\"", OCTET),
+                        "replacement": format!(" :comparator \"{}\"", OCTET),
+                        "range": Range::new(test.name_range.end, test.name_range.end),
+                    }),
+                };
+                sink.push(Diagnostic {
+                    range: key.range,
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("case-sensitive-key".to_string())),
+                    code_description: Some(CodeDescription {
+                        href: Url::parse(href).unwrap(),
+                    }),
+                    source: Some("sieve-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: Some(fix),
+                });
+            }
+        }
+    }
+}
+
+/// Whether a key looks like a base64 or base64url token, where case is significant
+/// Such tokens are long, mix digits into letters whose case varies far more than in
+/// camel-cased words, and contain nothing else.
+fn looks_case_sensitive(key: &str) -> bool {
+    const MIN_LENGTH: usize = 16;
+    let token = key.trim_end_matches('=');
+    if token.len() < MIN_LENGTH
+        || !token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_'))
+    {
+        return false;
+    }
+    let digits = token.chars().filter(char::is_ascii_digit).count();
+    let letters = token.chars().filter(char::is_ascii_alphabetic).count();
+    let upper = token.chars().filter(char::is_ascii_uppercase).count();
+    digits >= 1 && letters > 0 && (0.3..=0.7).contains(&(upper as f64 / letters as f64))
+}
+
+/// Validate `spamtest` and `virustest` (RFC 5235)
+/// Both report a number, so they are compared with `:value` or `:count` and the
+/// "i;ascii-numeric" comparator; values outside the range of the test never match.
+fn check_spam_tests(sink: &mut Sink, script: &Script) {
+    trace!("Checking spamtest and virustest");
+    const NUMERIC: &str = "i;ascii-numeric";
+    let required = script.required_capabilities();
+
+    for test in script.all_tests() {
+        let name = test.name.as_str();
+        let Some(max) = sieve::max_score(name, false) else {
+            continue;
+        };
+        let find = |tags: &[&str]| {
+            test.arguments
+                .iter()
+                .position(|a| a.tag().is_some_and(|t| tags.contains(&t)))
+        };
+        let percent = find(&[":percent"]).map(|idx| &test.arguments[idx]);
+        let relational = find(&[":value", ":count"]);
+        let match_type = find(&[":is", ":contains", ":matches", ":regex"]);
+        let comparator = find(&[":comparator"]).map(|idx| test.arguments.get(idx + 1));
+        let mut problems = Vec::new();
+
+        if let Some(percent) = percent {
+            if name == "virustest" {
+                problems.push((
+                    percent.range(),
+                    "invalid-spamtest",
+                    DiagnosticSeverity::ERROR,
+                    "':percent' only applies to 'spamtest'".to_string(),
+                    None,
+                ));
+            } else if !required.iter().any(|c| c == "spamtestplus") {
+                problems.push((
+                    percent.range(),
+                    "missing-require",
+                    DiagnosticSeverity::WARNING,
+                    "':percent' requires the \"spamtestplus\" extension".to_string(),
+                    None,
+                ));
+            }
+        }
+
+        match (relational, comparator) {
+            (None, _) => {
+                // Without a match type, `:is` compares the same value as `:value "eq"`
+                let fix = match (match_type.map(|idx| &test.arguments[idx]), comparator) {
+                    (None, None) => Some((
+                        test.name_range,
+                        format!("{} :value \"eq\" :comparator \"{}\"", name, NUMERIC),
+                    )),
+                    (Some(is), None) if is.tag() == Some(":is") => {
+                        Some((is.range(), format!(":value \"eq\" :comparator \"{}\"", NUMERIC)))
+                    }
+                    _ => None,
+                };
+                problems.push((
+                    test.name_range,
+                    "spamtest-comparison",
+                    DiagnosticSeverity::WARNING,
+                    format!(
+                        "'{}' compares its result as text; use ':value' with the \"{}\" comparator",
+                        name, NUMERIC
+                    ),
+                    fix.map(|(range, replacement)| {
+                        serde_json::json!({
+                            "title": "Compare numerically with ':value \"eq\"'",
+                            "replacement": replacement,
+                            "range": range,
+                        })
+                    }),
+                ));
+            }
+            (Some(idx), None) => {
+                let relation_end = match test.arguments.get(idx + 1) {
+                    Some(Argument::String(relation)) => relation.range.end,
+                    _ => test.arguments[idx].range().end,
+                };
+                problems.push((
+                    test.arguments[idx].range(),
+                    "spamtest-comparison",
+                    DiagnosticSeverity::WARNING,
+                    format!(
+                        "'{}' compares the result of '{}' as text unless the \"{}\" comparator \
+                         is given",
+                        test.arguments[idx].tag().unwrap_or_default(),
+                        name,
+                        NUMERIC
+                    ),
+                    Some(serde_json::json!({
+                        "title": format!("Compare with \"{}\"", NUMERIC),
+                        "replacement": format!(" :comparator \"{}\"", NUMERIC),
+                        "range": Range::new(relation_end, relation_end),
+                    })),
+                ));
+            }
+            (Some(_), Some(Some(Argument::String(other))))
+                if !other.value.eq_ignore_ascii_case(NUMERIC) =>
+            {
+                problems.push((
+                    other.range,
+                    "spamtest-comparison",
+                    DiagnosticSeverity::WARNING,
+                    format!(
+                        "Comparator '{}' compares the result of '{}' as text",
+                        other.value, name
+                    ),
+                    Some(serde_json::json!({
+                        "title": format!("Compare with \"{}\"", NUMERIC),
+                        "replacement": format!("\"{}\"", NUMERIC),
+                    })),
+                ));
+            }
+            _ => {}
+        }
+
+        // Counts and patterns are not scores, so only plain and `:value` comparisons are checked
+        let counted = relational.is_some_and(|idx| test.arguments[idx].tag() == Some(":count"));
+        let patterned = match_type.is_some_and(|idx| test.arguments[idx].tag() != Some(":is"));
+        if !counted && !patterned {
+            let max = sieve::max_score(name, percent.is_some()).unwrap_or(max);
+            let keys = test
+                .positional_arguments()
+                .last()
+                .map(|a| a.strings())
+                .unwrap_or_default();
+            for key in keys {
+                let value = key.decoded();
+                if value.contains("${") || value.parse::<u32>().is_ok_and(|score| score <= max) {
+                    continue;
+                }
+                problems.push((
+                    key.range,
+                    "invalid-spamtest",
+                    DiagnosticSeverity::ERROR,
+                    format!(
+                        "'{}' is never a result of '{}', which ranges from \"0\" to \"{}\"",
+                        key.value, name, max
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        let href = if name == "spamtest" {
+            "https://datatracker.ietf.org/doc/html/rfc5235#section-3.2"
+        } else {
+            "https://datatracker.ietf.org/doc/html/rfc5235#section-3.3"
+        };
+        for (range, code, severity, message, data) in problems {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(href).unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data,
+            });
+        }
+    }
+}
+
+/// Validate the tests of the mboxmetadata and servermetadata extensions (RFC 5490)
+/// Checks the number and kind of arguments, the entry names and that the extension is required.
+fn check_metadata_tests(sink: &mut Sink, script: &Script) {
+    trace!("Checking metadata tests");
+    // Test, capability, expected arguments, whether a mailbox comes first, whether keys follow
+    const SIGNATURES: &[(&str, &str, &str, bool, bool)] = &[
+        ("metadata", "mboxmetadata", "a mailbox, an entry name and keys", true, true),
+        ("metadataexists", "mboxmetadata", "a mailbox and entry names", true, false),
+        ("servermetadata", "servermetadata", "an entry name and keys", false, true),
+        ("servermetadataexists", "servermetadata", "entry names", false, false),
+    ];
+    const COMPARISON_TAGS: &[&str] =
+        &[":is", ":contains", ":matches", ":regex", ":value", ":count", ":comparator"];
+    let required = script.required_capabilities();
+
+    for test in script.all_tests() {
+        let Some(&(name, capability, expected, mailbox, keys)) =
+            SIGNATURES.iter().find(|(name, ..)| *name == test.name)
+        else {
+            continue;
+        };
+        let mut problems = Vec::new();
+        if !required.iter().any(|r| r == capability) {
+            problems.push((
+                test.name_range,
+                "missing-require",
+                DiagnosticSeverity::WARNING,
+                format!("'{}' requires the \"{}\" extension", name, capability),
+            ));
+        }
+
+        for argument in &test.arguments {
+            if let Some(tag) = argument.tag()
+                && !(keys && COMPARISON_TAGS.contains(&tag))
+            {
+                problems.push((
+                    argument.range(),
+                    "invalid-metadata",
+                    DiagnosticSeverity::ERROR,
+                    format!("'{}' is not an argument of '{}'", tag, name),
+                ));
+            }
+        }
+
+        let positional = test.positional_arguments();
+        if positional.len() != usize::from(mailbox) + 1 + usize::from(keys) {
+            problems.push((
+                test.range,
+                "invalid-metadata",
+                DiagnosticSeverity::ERROR,
+                format!("'{}' expects {}", name, expected),
+            ));
+        } else {
+            let entries = positional[usize::from(mailbox)];
+            // Only the exists tests take a list of entries
+            let singles = [
+                mailbox.then_some(("mailbox", positional[0])),
+                keys.then_some(("entry name", entries)),
+            ];
+            for (what, argument) in singles.into_iter().flatten() {
+                if !matches!(argument, Argument::String(_)) {
+                    problems.push((
+                        argument.range(),
+                        "invalid-metadata",
+                        DiagnosticSeverity::ERROR,
+                        format!("The {} of '{}' must be a single string", what, name),
+                    ));
+                }
+            }
+            for entry in entries.strings() {
+                let value = entry.decoded();
+                if value.contains("${") {
+                    continue;
+                }
+                if let Some(problem) = sieve::metadata_entry_problem(&value) {
+                    problems.push((
+                        entry.range,
+                        "invalid-metadata",
+                        DiagnosticSeverity::ERROR,
+                        format!("'{}' is not a metadata entry name: {}", entry.value, problem),
+                    ));
+                }
+            }
+        }
+
+        for (range, code, severity, message) in problems {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5490").unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Check that every `size` test has one comparison and a numeric limit
+/// Malformed number literals themselves are reported by the parser
+fn check_size_tests(sink: &mut Sink, script: &Script) {
+    trace!("Checking size tests");
+    for test in script.all_tests() {
+        if test.name != "size" {
+            continue;
+        }
+        let comparisons = test
+            .arguments
+            .iter()
+            .filter(|a| matches!(a.tag(), Some(":over" | ":under")))
+            .count();
+        let limit = test.arguments.iter().find(|a| !matches!(a, Argument::Tag { .. }));
+
+        let problem = if comparisons != 1 {
+            Some((test.name_range, "'size' needs exactly one of ':over' or ':under'"))
+        } else {
+            match limit {
+                Some(Argument::Number { .. }) => None,
+                Some(other) => Some((
+                    other.range(),
+                    "'size' expects a number such as 10M, not a string",
+                )),
+                None => Some((test.range, "'size' expects a limit such as 10M")),
+            }
+        };
+
+        if let Some((range, message)) = problem {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid-size".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-5.9")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message: message.to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Check the header names of `header`, `exists` and `address` tests
+/// Names must be valid RFC 5322 field names, and `address` only parses address headers.
+/// A trailing colon as in "Subject:" gets a quick fix that removes it.
+fn check_header_names(sink: &mut Sink, script: &Script) {
+    trace!("Checking header names");
+    for test in script.all_tests() {
+        if !matches!(test.name.as_str(), "header" | "exists" | "address") {
+            continue;
+        }
+        let Some(names) = test.positional_arguments().first().copied() else {
+            continue;
+        };
+        for name in names.strings() {
+            // Variables are expanded at runtime, so the final name is unknown
+            let value = name.decoded();
+            if value.contains("${") {
+                continue;
+            }
+
+            let (code, severity, href, message, data) = if !sieve::is_header_name(&value) {
+                let trimmed = value.trim().trim_end_matches(':');
+                let data = sieve::is_header_name(trimmed).then(|| {
+                    serde_json::json!({
+                        "title": format!("Replace with \"{}\"", trimmed),
+                        "replacement": refactor::quote_string(trimmed),
+                    })
+                });
+                (
+                    "invalid-header-name",
+                    DiagnosticSeverity::ERROR,
+                    "https://datatracker.ietf.org/doc/html/rfc5322#section-2.2",
+                    format!(
+                        "Invalid header name '{}': field names cannot contain spaces, colons or control characters",
+                        value
+                    ),
+                    data,
+                )
+            } else if test.name == "address" && !sieve::is_address_header(&value) {
+                (
+                    "non-address-header",
+                    DiagnosticSeverity::WARNING,
+                    "https://datatracker.ietf.org/doc/html/rfc5228#section-5.1",
+                    format!(
+                        "'{}' does not contain addresses; use 'header' to test its content",
+                        value
+                    ),
+                    None,
+                )
+            } else {
+                continue;
+            };
+
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: name.range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(href).unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data,
+            });
+        }
+    }
+}
+
+/// Validate the arguments of `addheader` and `deleteheader` (RFC 5293)
+fn check_editheader(sink: &mut Sink, script: &Script) {
+    trace!("Checking editheader actions");
+    for command in script.all_commands() {
+        let (allowed_tags, section): (&[&str], &str) = match command.name.as_str() {
+            "addheader" => (&[":last"], "5"),
+            "deleteheader" => (
+                &[
+                    ":index", ":last", ":comparator", ":is", ":contains", ":matches", ":regex",
+                    ":value", ":count",
+                ],
+                "6",
+            ),
+            _ => continue,
+        };
+
+        let mut problems = Vec::new();
+        let mut positional = Vec::new();
+        let mut arguments = command.arguments.iter().peekable();
+        while let Some(argument) = arguments.next() {
+            let Some(tag) = argument.tag() else {
+                positional.push(argument);
+                continue;
+            };
+            if !allowed_tags.contains(&tag) {
+                problems.push((
+                    argument.range(),
+                    format!("'{}' does not accept '{}'", command.name, tag),
+                ));
+                // Skip the tag's value so it is not taken for the field name
+                if sieve::TAGS_WITH_VALUE.contains(&tag) {
+                    arguments.next();
+                }
+                continue;
+            }
+            match tag {
+                ":index" => match arguments.next_if(|a| matches!(a, Argument::Number { .. })) {
+                    Some(Argument::Number { raw, range }) => {
+                        if parser::number_value(raw) == Some(0) {
+                            problems.push((*range, "':index' counts from 1".to_string()));
+                        }
+                    }
+                    _ => problems.push((
+                        argument.range(),
+                        "':index' expects a field number".to_string(),
+                    )),
+                },
+                ":comparator" | ":value" | ":count" => {
+                    arguments.next();
+                }
+                _ => {}
+            }
+        }
+        if command.name == "deleteheader"
+            && let Some(last) = command.arguments.iter().find(|a| a.tag() == Some(":last"))
+            && !command.arguments.iter().any(|a| a.tag() == Some(":index"))
+        {
+            problems.push((last.range(), "':last' requires ':index'".to_string()));
+        }
+
+        let field = match (command.name.as_str(), positional.as_slice()) {
+            ("addheader", [Argument::String(field), Argument::String(_)])
+            | ("deleteheader", [Argument::String(field)] | [Argument::String(field), _]) => {
+                Some(field)
+            }
+            ("addheader", _) => {
+                problems.push((
+                    command.name_range,
+                    "'addheader' expects a field name and a value".to_string(),
+                ));
+                None
+            }
+            _ => {
+                problems.push((
+                    command.name_range,
+                    "'deleteheader' expects a field name and optional value patterns"
+                        .to_string(),
+                ));
+                None
+            }
+        };
+        if let Some(field) = field
+            && !field.value.contains("${")
+            && !sieve::is_header_name(&field.value)
+        {
+            problems.push((
+                field.range,
+                format!(
+                    "Invalid header name '{}': field names cannot contain spaces, colons or control characters",
+                    field.value
+                ),
+            ));
+        }
+
+        for (range, message) in problems {
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid-editheader".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(&format!(
+                        "https://datatracker.ietf.org/doc/html/rfc5293#section-{}",
+                        section
+                    ))
+                    .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Check `foreverypart` loops, `break` and the MIME tags of tests (RFC 5703)
+fn check_mime(sink: &mut Sink, script: &Script) {
+    trace!("Checking MIME tests and foreverypart loops");
+    let mut problems = Vec::new();
+    check_loops(&script.commands, &mut Vec::new(), &mut problems);
+
+    for test in script.all_tests() {
+        let mime_tags: Vec<&Argument> = test
+            .arguments
+            .iter()
+            .filter(|a| a.tag().is_some_and(|tag| MIME_TAGS.contains(&tag)))
+            .collect();
+        let allowed: &[&str] = match test.name.as_str() {
+            "header" => MIME_TAGS,
+            "address" | "exists" => &[":mime", ":anychild"],
+            _ => &[],
+        };
+        let has_mime = mime_tags.iter().any(|a| a.tag() == Some(":mime"));
+        let mut options = Vec::new();
+        for argument in mime_tags {
+            let tag = argument.tag().unwrap_or_default();
+            if !allowed.contains(&tag) {
+                problems.push((
+                    argument.range(),
+                    format!("'{}' does not accept '{}'", test.name, tag),
+                    "4",
+                ));
+            } else if tag != ":mime" && !has_mime {
+                problems.push((argument.range(), format!("'{}' requires ':mime'", tag), "4.1"));
+            } else if tag != ":mime" && tag != ":anychild" {
+                options.push(argument);
+            }
+        }
+        if let [_, extra, ..] = options.as_slice() {
+            problems.push((
+                extra.range(),
+                "Use only one of ':type', ':subtype', ':contenttype' and ':param'".to_string(),
+                "4.1",
+            ));
+        }
+    }
+
+    for (range, message, section) in problems {
+        warn!("{}", message);
+        let code = if section == "3.2" { "invalid-break" } else { "invalid-mime" };
+        sink.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse(&format!(
+                    "https://datatracker.ietf.org/doc/html/rfc5703#section-{}",
+                    section
+                ))
+                .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+}
+
+/// Check a script against the Sieve implementation of the selected server dialect
+/// Capabilities, standard features and other vendors' extensions the server lacks are
+/// errors; its own vendor features are valid once their capability is required.
+/// Requires are left to the capabilities the server advertises when the client sent them.
+fn check_profile(
+    sink: &mut Sink,
+    script: &Script,
+    profile: &Profile,
+    check_requires: bool,
+) {
+    trace!("Checking against the {} profile", profile.name);
+    let required = script.required_capabilities();
+    let mut problems = Vec::new();
+
+    for command in script.all_commands() {
+        if command.name != "require" || !check_requires {
+            continue;
+        }
+        for capability in command.arguments.iter().flat_map(|a| a.strings()) {
+            if profile.supports_extension(&capability.value) || is_unknown_extension(capability)
+            {
+                continue;
+            }
+            let suggestion =
+                sieve::closest_match(&capability.value, profile.extensions.iter().copied());
+            let message = match suggestion {
+                Some(suggestion) => format!(
+                    "{} does not support the \"{}\" extension, did you mean \"{}\"?",
+                    profile.description, capability.value, suggestion
+                ),
+                None => format!(
+                    "{} does not support the \"{}\" extension",
+                    profile.description, capability.value
+                ),
+            };
+            problems.push((
+                capability.range,
+                message,
+                DiagnosticSeverity::ERROR,
+                suggestion.map(replacement_fix),
+            ));
+        }
+    }
+
+    let items = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (&c.name, c.name_range, &c.arguments))
+        .chain(
+            script
+                .all_tests()
+                .into_iter()
+                .map(|t| (&t.name, t.name_range, &t.arguments)),
+        );
+    for (name, name_range, arguments) in items {
+        let features = std::iter::once((name.as_str(), name_range)).chain(
+            arguments
+                .iter()
+                .filter_map(|a| a.tag().map(|tag| (tag, a.range()))),
+        );
+        for (feature, range) in features {
+            if profile.is_unsupported(feature) {
+                problems.push((
+                    range,
+                    format!("{} does not support '{}'", profile.description, feature),
+                    DiagnosticSeverity::ERROR,
+                    None,
+                ));
+            } else if let Some((vendor, capability)) = profile::vendor_of(feature) {
+                if vendor != profile {
+                    problems.push((
+                        range,
+                        format!(
+                            "'{}' is a {} extension that {} does not support",
+                            feature, vendor.description, profile.description
+                        ),
+                        DiagnosticSeverity::ERROR,
+                        None,
+                    ));
+                } else if !required.iter().any(|r| r == capability) {
+                    problems.push((
+                        range,
+                        format!("'{}' requires the \"{}\" extension", feature, capability),
+                        DiagnosticSeverity::WARNING,
+                        None,
+                    ));
+                }
+            }
+        }
+    }
+
+    for (range, message, severity, data) in problems {
+        warn!("{}", message);
+        let code = if severity == DiagnosticSeverity::ERROR {
+            "unsupported-feature"
+        } else {
+            "missing-require"
+        };
+        sink.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse(profile.documentation).unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        });
+    }
+}
+
+/// Check the envelope parts of `envelope` tests
+/// Parts beyond "from" and "to" need their extension required, and supported by the server
+/// dialect. Parts built from variables are left alone.
+fn check_envelope_parts(
+    sink: &mut Sink,
+    script: &Script,
+    profile: Option<&Profile>,
+) {
+    trace!("Checking envelope parts");
+    let required = script.required_capabilities();
+    for test in script.all_tests() {
+        if test.name != "envelope" {
+            continue;
+        }
+        let Some(parts) = test.positional_arguments().first().map(|a| a.strings()) else {
+            continue;
+        };
+        for part in parts {
+            let value = part.decoded();
+            if value.contains("${") {
+                continue;
+            }
+            let (code, message, severity, section) = match sieve::envelope_part(&value) {
+                None => (
+                    "unknown-envelope-part",
+                    format!("Unknown envelope part '{}'", value),
+                    DiagnosticSeverity::WARNING,
+                    "rfc5228#section-5.4",
+                ),
+                Some(EnvelopePart {
+                    capability: Some(capability),
+                    ..
+                }) => {
+                    if profile.is_some_and(|p| !p.supports_extension(capability)) {
+                        (
+                            "unsupported-feature",
+                            format!(
+                                "{} does not support the envelope part '{}'",
+                                profile.map_or("", |p| p.description),
+                                value
+                            ),
+                            DiagnosticSeverity::ERROR,
+                            "rfc6009#section-3",
+                        )
+                    } else if !required.iter().any(|r| r == capability) {
+                        (
+                            "missing-require",
+                            format!(
+                                "Envelope part '{}' requires the \"{}\" extension",
+                                value, capability
+                            ),
+                            DiagnosticSeverity::ERROR,
+                            "rfc6009#section-3",
+                        )
+                    } else {
+                        continue;
+                    }
+                }
+                Some(_) => continue,
+            };
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: part.range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(&format!(
+                        "https://datatracker.ietf.org/doc/html/{}",
+                        section
+                    ))
+                    .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Check requires against the extensions the server advertises (RFC 5804 section 1.7)
+fn check_advertised_capabilities(
+    sink: &mut Sink,
+    script: &Script,
+    advertised: &[String],
+) {
+    trace!("Checking requires against {} advertised capabilities", advertised.len());
+    for command in script.all_commands() {
+        if command.name != "require" {
+            continue;
+        }
+        for capability in command.arguments.iter().flat_map(|a| a.strings()) {
+            if advertised
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(&capability.value))
+                || is_unknown_extension(capability)
+            {
+                continue;
+            }
+            let suggestion =
+                sieve::closest_match(&capability.value, advertised.iter().map(String::as_str));
+            let message = match suggestion {
+                Some(suggestion) => format!(
+                    "The server does not advertise the \"{}\" extension, did you mean \"{}\"?",
+                    capability.value, suggestion
+                ),
+                None => format!(
+                    "The server does not advertise the \"{}\" extension",
+                    capability.value
+                ),
+            };
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: capability.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("unsupported-feature".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(
+                        "https://datatracker.ietf.org/doc/html/rfc5804#section-1.7",
+                    )
+                    .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: suggestion.map(replacement_fix),
+            });
+        }
+    }
+}
+
+/// Whether a required capability is no extension at all, e.g. a typo
+/// Vendor extensions are only known for some servers, so unknown ones are not judged here.
+fn is_unknown_extension(capability: &parser::StringLiteral) -> bool {
+    !sieve::is_known_extension(&capability.value) && !capability.value.starts_with("vnd.")
+}
+
+/// Quick fix data replacing a misspelled capability with the suggested one
+fn replacement_fix(suggestion: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": format!("Replace with \"{}\"", suggestion),
+        "replacement": refactor::quote_string(suggestion),
+    })
+}
+
+lazy_static! {
+    /// Names of commands and tests that are no typo, lowercase
+    static ref KNOWN_COMMANDS: BTreeSet<&'static str> = command_candidates()
+        .into_iter()
+        .chain(sieve::OTHER_COMMANDS.iter().copied())
+        .chain(dialect::FAMILIES.iter().flat_map(|family| family.members.iter().copied()))
+        .chain(profile::PROFILES.iter().flat_map(|profile| {
+            profile
+                .vendor_features
+                .iter()
+                .map(|(feature, _)| *feature)
+                .chain(profile.unsupported.iter().copied())
+        }))
+        .filter(|name| !name.contains(':'))
+        .collect();
+
+    /// Tags that are no typo, lowercase
+    static ref KNOWN_TAGS: BTreeSet<&'static str> = tag_candidates()
+        .into_iter()
+        .chain(sieve::OTHER_TAGS.iter().copied())
+        .chain(dialect::FAMILIES.iter().flat_map(|family| {
+            family
+                .variants
+                .iter()
+                .flat_map(|variant| variant.features.iter().copied())
+        }))
+        .chain(profile::PROFILES.iter().flat_map(|profile| {
+            profile.vendor_features.iter().map(|(feature, _)| *feature)
+        }))
+        .filter_map(|feature| feature.find(':').map(|at| &feature[at..]))
+        .collect();
+}
+
+/// Commands and tests suggested for a misspelled one
+fn command_candidates() -> Vec<&'static str> {
+    sieve::CONTROL_COMMANDS
+        .iter()
+        .chain(SIEVE_ACTIONS.iter())
+        .chain(sieve::SIEVE_TESTS.iter())
+        .copied()
+        .collect()
+}
+
+/// Tags suggested for a misspelled one
+fn tag_candidates() -> Vec<&'static str> {
+    let mut tags: Vec<&'static str> = sieve::SIEVE_TAGS.to_vec();
+    for tag in sieve::TAGS_WITH_VALUE {
+        if !tags.contains(tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Flag commands, tests and tags that look like a misspelled known one
+/// Names that resemble none are left alone: servers may know extensions this server does not.
+/// Each report offers the correction as a quick fix.
+fn check_unknown_identifiers(sink: &mut Sink, script: &Script) {
+    trace!("Checking for misspelled commands and tags");
+    let commands = command_candidates();
+    let tags = tag_candidates();
+    let mut problems = Vec::new();
+
+    let items = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (&c.name, c.name_range, &c.arguments))
+        .chain(
+            script
+                .all_tests()
+                .into_iter()
+                .map(|t| (&t.name, t.name_range, &t.arguments)),
+        );
+    for (name, name_range, arguments) in items {
+        let lowercase = name.to_lowercase();
+        if !KNOWN_COMMANDS.contains(lowercase.as_str())
+            && let Some(suggestion) = sieve::closest_match(name, commands.iter().copied())
+        {
+            problems.push((
+                name_range,
+                "unknown-command",
+                format!("Unknown command '{}', did you mean '{}'?", name, suggestion),
+                suggestion,
+            ));
+        }
+        for argument in arguments {
+            let Some(tag) = argument.tag() else {
+                continue;
+            };
+            let lowercase = tag.to_lowercase();
+            if !KNOWN_TAGS.contains(lowercase.as_str())
+                && let Some(suggestion) = sieve::closest_match(tag, tags.iter().copied())
+            {
+                problems.push((
+                    argument.range(),
+                    "unknown-tag",
+                    format!("Unknown tag '{}', did you mean '{}'?", tag, suggestion),
+                    suggestion,
+                ));
+            }
+        }
+    }
+
+    for (range, code, message, suggestion) in problems {
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-2.9")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: Some(serde_json::json!({
+                "title": format!("Change to '{}'", suggestion),
+                "replacement": suggestion,
+            })),
+        });
+    }
+}
+
+/// Flag required capabilities that name no extension, suggesting the closest one
+/// Suggestions come from the advertised capabilities or the server dialect when known.
+fn check_unknown_extensions(
+    sink: &mut Sink,
+    script: &Script,
+    profile: Option<&Profile>,
+    advertised: Option<&[String]>,
+) {
+    trace!("Checking for unknown extensions");
+    let candidates: Vec<String> = match (advertised, profile) {
+        (Some(advertised), _) => advertised.to_vec(),
+        (None, Some(profile)) => profile.extensions.iter().map(|e| e.to_string()).collect(),
+        (None, None) => sieve::known_extensions(),
+    };
+    for command in script.all_commands() {
+        if command.name != "require" {
+            continue;
+        }
+        for capability in command.arguments.iter().flat_map(|a| a.strings()) {
+            let is_advertised = advertised.is_some_and(|advertised| {
+                advertised
+                    .iter()
+                    .any(|a| a.eq_ignore_ascii_case(&capability.value))
+            });
+            if !is_unknown_extension(capability) || is_advertised {
+                continue;
+            }
+            let suggestion =
+                sieve::closest_match(&capability.value, candidates.iter().map(String::as_str));
+            let message = match suggestion {
+                Some(suggestion) => format!(
+                    "Unknown extension '{}', did you mean '{}'?",
+                    capability.value, suggestion
+                ),
+                None => format!("Unknown extension '{}'", capability.value),
+            };
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: capability.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("unknown-extension".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.2")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: suggestion.map(replacement_fix),
+            });
+        }
+    }
+}
+
+/// Flag tagged arguments that follow positional ones, e.g. `header "subject" :contains "x"`
+/// Many servers accept them anywhere, strict ones reject the script; strict mode reports
+/// an error. The fix moves every tag, with its value, in front of the positional arguments.
+fn check_argument_order(
+    sink: &mut Sink,
+    text: &str,
+    script: &Script,
+    strict: bool,
+) {
+    trace!("Checking argument order");
+    let commands = script.all_commands();
+    let tests = script.all_tests();
+    let argument_lists = commands
+        .iter()
+        .map(|c| (c.name.as_str(), c.arguments.as_slice()))
+        .chain(tests.iter().map(|t| (t.name.as_str(), t.arguments.as_slice())));
+
+    for (name, arguments) in argument_lists {
+        let (Some(first), Some(last)) = (arguments.first(), arguments.last()) else {
+            continue;
+        };
+        // Tags with the value they consume, and positional arguments
+        let mut tags: Vec<(&str, Range)> = Vec::new();
+        let mut positional: Vec<Range> = Vec::new();
+        let mut misplaced = None;
+        let mut iter = arguments.iter();
+        while let Some(argument) = iter.next() {
+            let mut range = argument.range();
+            match argument.tag() {
+                Some(tag) => {
+                    if sieve::TAGS_WITH_VALUE.contains(&tag)
+                        && let Some(value) = iter.next()
+                    {
+                        range.end = value.range().end;
+                    }
+                    if !positional.is_empty() && misplaced.is_none() {
+                        misplaced = Some(tag);
+                    }
+                    tags.push((tag, range));
+                }
+                None => positional.push(range),
+            }
+        }
+        let Some(tag) = misplaced else {
+            continue;
+        };
+
+        let range = Range {
+            start: first.range().start,
+            end: last.range().end,
+        };
+        let message = format!(
+            "Tagged argument '{}' must come before the positional arguments of '{}'",
+            tag, name
+        );
+        warn!("{}", message);
+        let multiline = arguments.iter().any(|argument| {
+            argument.strings().iter().any(|s| s.multiline)
+        });
+        let data = (range.start.line == range.end.line && !multiline).then(|| {
+            let reordered: Vec<&str> = tags
+                .iter()
+                .map(|(_, range)| *range)
+                .chain(positional.iter().copied())
+                .map(|range| refactor::slice(text, range))
+                .collect();
+            serde_json::json!({
+                "title": "Move tagged arguments first",
+                "replacement": reordered.join(" "),
+            })
+        });
+        sink.push(Diagnostic {
+            range,
+            severity: Some(if strict {
+                DiagnosticSeverity::ERROR
+            } else {
+                DiagnosticSeverity::WARNING
+            }),
+            code: Some(NumberOrString::String("argument-order".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-2.6")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        });
+    }
+}
+
+/// Suggest simpler forms of double negations, negated constants and `anyof`/`allof` lists
+/// of negated tests
+fn check_negations(sink: &mut Sink, text: &str, script: &Script) {
+    trace!("Checking negations");
+    let tests = script.all_tests();
+    let negated: Vec<Range> = tests
+        .iter()
+        .filter(|t| t.name == "not")
+        .filter_map(|t| t.tests.first().map(|inner| inner.range))
+        .collect();
+
+    for test in tests {
+        // Inner links of a `not not not` chain are covered by the outermost one
+        if negated.contains(&test.range) && test.name == "not" {
+            continue;
+        }
+        let Some(negation) = refactor::redundant_negation(test) else {
+            continue;
+        };
+        let (message, title) = match negation {
+            Negation::Double => (
+                "Double negation: 'not not' cancels out".to_string(),
+                "Remove double negation".to_string(),
+            ),
+            Negation::Constant(value) => (
+                format!("'not {}' is always {}", !value, value),
+                format!("Replace with \"{}\"", value),
+            ),
+            Negation::DeMorgan(combinator) => (
+                format!(
+                    "'{}' of negated tests is simpler as 'not {}'",
+                    test.name, combinator
+                ),
+                "Apply De Morgan's law".to_string(),
+            ),
+        };
+        debug!("{}", message);
+        let data = refactor::simplify_negation(text, test).map(|edit| {
+            serde_json::json!({
+                "title": title,
+                "replacement": edit.new_text,
+            })
+        });
+        sink.push(Diagnostic {
+            range: test.range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("redundant-negation".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-5.8")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        });
+    }
+}
+
+/// Suggest simpler forms of the `keep; stop;` and `fileinto "INBOX";` idioms
+/// A `keep` before `stop` is only redundant while nothing can have cancelled the implicit
+/// keep, so any earlier action that might is enough to leave it alone. Simplifications
+/// relying on a command the server dialect lacks are not offered.
+fn check_keep_idioms(
+    sink: &mut Sink,
+    script: &Script,
+    profile: Option<&Profile>,
+) {
+    trace!("Checking keep idioms");
+    let supports = |command: &str| !profile.is_some_and(|p| p.is_unsupported(command));
+    let plain = |command: &Command, name: &str| {
+        command.name == name && command.arguments.is_empty() && command.block.is_none()
+    };
+    let mut problems = Vec::new();
+
+    let commands = script.all_commands();
+    let blocks = commands.iter().filter_map(|c| c.block.as_ref());
+    let lists = std::iter::once(script.commands.as_slice())
+        .chain(blocks.map(|block| block.commands.as_slice()));
+    for list in lists {
+        for pair in list.windows(2) {
+            let [keep, stop] = pair else {
+                continue;
+            };
+            if !plain(keep, "keep") || !plain(stop, "stop") || !supports("stop") {
+                continue;
+            }
+            let cancelled = commands
+                .iter()
+                .take_while(|c| c.range.start < keep.range.start)
+                .any(|c| may_cancel_implicit_keep(c));
+            if cancelled {
+                continue;
+            }
+            problems.push((
+                Range {
+                    start: keep.range.start,
+                    end: stop.range.start,
+                },
+                "redundant-keep",
+                "'keep' before 'stop' is redundant: the implicit keep already files the message into the inbox"
+                    .to_string(),
+                serde_json::json!({
+                    "title": "Remove redundant 'keep'",
+                    "replacement": "",
+                }),
+            ));
+        }
+    }
+
+    for command in &commands {
+        if command.name != "fileinto" || !supports("keep") {
+            continue;
+        }
+        if let [Argument::String(mailbox)] = command.arguments.as_slice()
+            && mailbox.value.eq_ignore_ascii_case("INBOX")
+            && command.terminated
+        {
+            problems.push((
+                command.range,
+                "inbox-fileinto",
+                format!(
+                    "'fileinto \"{}\"' files the message into the inbox, which is what 'keep' does",
+                    mailbox.value
+                ),
+                serde_json::json!({
+                    "title": "Replace with \"keep;\"",
+                    "replacement": "keep;",
+                }),
+            ));
+        }
+    }
+
+    for (range, code, message, data) in problems {
+        debug!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-2.10.2")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: Some(data),
+        });
+    }
+}
+
+/// Check `notify` actions and notification tests of the enotify extension (RFC 5435)
+/// Skipped when the draft notify dialect is configured, whose syntax differs.
+fn check_notify(
+    sink: &mut Sink,
+    script: &Script,
+    dialects: &BTreeMap<String, String>,
+) {
+    trace!("Checking notifications");
+    if dialect::family_of("notify").is_some_and(|f| f.active(dialects).capability != "enotify") {
+        return;
+    }
+    let literal = |s: &parser::StringLiteral| !s.value.contains("${");
+    let mut problems = Vec::new();
+
+    for command in script.all_commands() {
+        if command.name != "notify" {
+            continue;
+        }
+        let mut methods = Vec::new();
+        let mut arguments = command.arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let Some(tag) = argument.tag() else {
+                methods.push(argument);
+                continue;
+            };
+            let value = match tag {
+                ":from" | ":importance" | ":options" | ":message" | ":method" | ":id" => {
+                    arguments.next()
+                }
+                _ => continue,
+            };
+            match (tag, value) {
+                (":from", Some(Argument::String(from)))
+                    if literal(from) && !sieve::is_email_address(&from.value) =>
+                {
+                    problems.push((
+                        from.range,
+                        format!("'{}' is not a valid email address", from.value),
+                        DiagnosticSeverity::ERROR,
+                    ));
+                }
+                (":importance", Some(Argument::String(importance)))
+                    if !notify::IMPORTANCE_LEVELS.contains(&importance.value.as_str()) =>
+                {
+                    problems.push((
+                        importance.range,
+                        "':importance' must be \"1\" (high), \"2\" (normal) or \"3\" (low)"
+                            .to_string(),
+                        DiagnosticSeverity::ERROR,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        match methods.as_slice() {
+            [Argument::String(method)] => {
+                if literal(method)
+                    && let Err(message) = notify::check_method(&method.value)
+                {
+                    problems.push((method.range, message, DiagnosticSeverity::ERROR));
+                }
+            }
+            _ => problems.push((
+                command.name_range,
+                "'notify' expects a single notification method URI".to_string(),
+                DiagnosticSeverity::ERROR,
+            )),
+        }
+    }
+
+    for test in script.all_tests() {
+        if test.name != "notify_method_capability" {
+            continue;
+        }
+        if let [_, Argument::String(capability), ..] = test.positional_arguments().as_slice()
+            && literal(capability)
+            && !capability.value.eq_ignore_ascii_case(notify::ONLINE_CAPABILITY)
+        {
+            problems.push((
+                capability.range,
+                format!(
+                    "Unknown notification capability '{}'; RFC 5435 only defines \"online\"",
+                    capability.value
+                ),
+                DiagnosticSeverity::WARNING,
+            ));
+        }
+    }
+
+    for (range, message, severity) in problems {
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String("invalid-notify".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5435#section-3")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+}
+
+/// Check `set` commands and `${...}` references of the variables extension (RFC 5229)
+/// Only runs when "variables" is required; without it `${...}` is literal text.
+fn check_variables(sink: &mut Sink, text: &str, script: &Script) {
+    trace!("Checking variables");
+    if !script.required_capabilities().contains(&"variables".to_string()) {
+        return;
+    }
+    let mut report = |range: Range,
+                      severity: DiagnosticSeverity,
+                      code: &str,
+                      section: &str,
+                      message: String| {
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse(&format!(
+                    "https://datatracker.ietf.org/doc/html/rfc5229#section-{}",
+                    section
+                ))
+                .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    };
+
+    // Names assigned anywhere count, since `set` may run in any branch or an included script
+    let mut assigned = BTreeSet::new();
+    for command in script.all_commands() {
+        match command.name.as_str() {
+            "set" => assigned.extend(
+                variables::set_variable(command).map(|name| name.value.to_lowercase()),
+            ),
+            "global" => assigned.extend(
+                command
+                    .arguments
+                    .iter()
+                    .flat_map(|a| a.strings())
+                    .map(|name| name.value.to_lowercase()),
+            ),
+            _ => {}
+        }
+    }
+
+    for command in script.all_commands() {
+        if command.name != "set" {
+            continue;
+        }
+        let mut precedences: Vec<(u8, &str)> = Vec::new();
+        let mut positional = Vec::new();
+        for argument in &command.arguments {
+            let Some(tag) = argument.tag() else {
+                positional.push(argument);
+                continue;
+            };
+            match variables::SET_MODIFIERS.iter().find(|(name, _)| *name == tag) {
+                None => report(
+                    argument.range(),
+                    DiagnosticSeverity::ERROR,
+                    "invalid-variable",
+                    "4.1",
+                    format!("Unknown modifier '{}' for 'set'", tag),
+                ),
+                Some((_, precedence)) => {
+                    if let Some((_, other)) =
+                        precedences.iter().find(|(p, _)| p == precedence)
+                    {
+                        report(
+                            argument.range(),
+                            DiagnosticSeverity::ERROR,
+                            "invalid-variable",
+                            "4.1",
+                            format!("'{}' cannot be combined with '{}'", tag, other),
+                        );
+                    }
+                    precedences.push((*precedence, tag));
+                }
+            }
+        }
+
+        match positional.as_slice() {
+            [Argument::String(name), Argument::String(_)] => {
+                if !variables::is_variable_name(&name.value) {
+                    report(
+                        name.range,
+                        DiagnosticSeverity::ERROR,
+                        "invalid-variable",
+                        "3",
+                        format!(
+                            "Invalid variable name '{}': use letters, digits and '_', not starting with a digit",
+                            name.value
+                        ),
+                    );
+                }
+            }
+            _ => report(
+                command.name_range,
+                DiagnosticSeverity::ERROR,
+                "invalid-variable",
+                "4",
+                "'set' expects a variable name and a value string".to_string(),
+            ),
+        }
+    }
+
+    let argument_lists = script
+        .all_commands()
+        .into_iter()
+        .filter(|c| c.name != "require")
+        .map(|c| &c.arguments)
+        .chain(script.all_tests().into_iter().map(|t| &t.arguments));
+    for arguments in argument_lists {
+        for string in arguments.iter().flat_map(|a| a.strings()) {
+            for reference in variables::references(&string.value) {
+                let range = string.value_range(text, reference.start, reference.end);
+                if reference.match_index().is_some() {
+                    if variables::match_source_before(script, range.start).is_none() {
+                        report(
+                            range,
+                            DiagnosticSeverity::WARNING,
+                            "match-variable",
+                            "3.2",
+                            format!(
+                                "'${{{}}}' is only set by a preceding ':matches' or ':regex' test",
+                                reference.name
+                            ),
+                        );
+                    }
+                } else if !reference.name.contains('.')
+                    && !assigned.contains(&reference.name.to_lowercase())
+                {
+                    report(
+                        range,
+                        DiagnosticSeverity::WARNING,
+                        "unset-variable",
+                        "3",
+                        format!("Variable '{}' is never set", reference.name),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Validate the parameters of `vacation` actions (RFC 5230)
+/// Also warns about a vacation next to `redirect`, which can start a mail loop when the
+/// redirect target replies to the vacation response.
+fn check_vacation(sink: &mut Sink, uri: &Url, script: &Script) {
+    trace!("Checking vacation actions");
+    const VALUE_TAGS: &[&str] =
+        &[":days", ":seconds", ":subject", ":from", ":addresses", ":handle"];
+
+    let mut report = |range: Range,
+                      severity: DiagnosticSeverity,
+                      code: &str,
+                      message: String,
+                      related_information: Option<Vec<DiagnosticRelatedInformation>>| {
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5230#section-4")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information,
+            tags: None,
+            data: None,
+        });
+    };
+
+    for command in script.all_commands() {
+        if command.name != "vacation" {
+            continue;
+        }
+        let mut reason = None;
+        let mut arguments = command.arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let Some(tag) = argument.tag() else {
+                reason = Some(argument);
+                continue;
+            };
+            let value = if VALUE_TAGS.contains(&tag) {
+                arguments.next()
+            } else {
+                None
+            };
+            match (tag, value) {
+                (":days", Some(Argument::Number { raw, range }))
+                    if parser::number_value(raw) == Some(0) =>
+                {
+                    report(
+                        *range,
+                        DiagnosticSeverity::ERROR,
+                        "invalid-vacation",
+                        "':days' must be at least 1".to_string(),
+                        None,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        match reason {
+            Some(Argument::String(_)) => {}
+            Some(other) => report(
+                other.range(),
+                DiagnosticSeverity::ERROR,
+                "invalid-vacation",
+                "The reason of 'vacation' must be a single string".to_string(),
+                None,
+            ),
+            None => report(
+                command.name_range,
+                DiagnosticSeverity::ERROR,
+                "invalid-vacation",
+                "'vacation' requires a reason string".to_string(),
+                None,
+            ),
+        }
+
+        let mime = command.arguments.iter().find(|a| a.tag() == Some(":mime"));
+        let subject = command.arguments.iter().find(|a| a.tag() == Some(":subject"));
+        if let (Some(_), Some(subject)) = (mime, subject) {
+            report(
+                subject.range(),
+                DiagnosticSeverity::WARNING,
+                "invalid-vacation",
+                "Do not combine ':subject' with ':mime'; a MIME reason carries its own headers"
+                    .to_string(),
+                None,
+            );
+        }
+    }
+
+    // Vacation responses and redirects within the same rule can bounce between two mailboxes
+    let blocks = std::iter::once(&script.commands).chain(
+        script
+            .all_commands()
+            .into_iter()
+            .filter_map(|c| c.block.as_ref().map(|b| &b.commands)),
+    );
+    for commands in blocks {
+        let redirect = commands.iter().find(|c| c.name == "redirect");
+        let vacation = commands.iter().find(|c| c.name == "vacation");
+        if let (Some(redirect), Some(vacation)) = (redirect, vacation) {
+            report(
+                vacation.range,
+                DiagnosticSeverity::WARNING,
+                "vacation-loop",
+                "'vacation' in a rule that also redirects can cause a mail loop".to_string(),
+                Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: redirect.range,
+                    },
+                    message: "Redirect in the same rule".to_string(),
+                }]),
+            );
+        }
+    }
+
+}
+
+/// Suggest `ereject` for `reject` where the server supports it (RFC 5429 section 2.1)
+/// `reject` may mail a rejection to a forged sender, while `ereject` refuses the message
+/// during the SMTP transaction where it can. Without a profile or advertised capabilities
+/// support is unknown and nothing is reported. The fix also updates the require statements.
+fn check_prefer_ereject(
+    sink: &mut Sink,
+    text: &str,
+    script: &Script,
+    profile: Option<&Profile>,
+    advertised: Option<&[String]>,
+) {
+    trace!("Checking for reject where ereject is available");
+    let supported = match (advertised, profile) {
+        (Some(advertised), _) => advertised.iter().any(|c| c.eq_ignore_ascii_case("ereject")),
+        (None, Some(profile)) => profile.extensions.contains(&"ereject"),
+        (None, None) => false,
+    };
+    if !supported {
+        return;
+    }
+
+    let rejects: Vec<&Command> = script
+        .all_commands()
+        .into_iter()
+        .filter(|c| c.name == "reject")
+        .collect();
+    // Other rejects still need "reject" until they are switched as well
+    let keep = rejects.len() > 1;
+    for command in &rejects {
+        let message = "Prefer 'ereject', which refuses the message during delivery instead of \
+                       mailing a rejection to a possibly forged sender"
+            .to_string();
+        debug!("{}", message);
+        let edits: Vec<TextEdit> =
+            requires::switch_requirement(text, script, "reject", "ereject", keep)
+                .into_iter()
+                .collect();
+        sink.push(Diagnostic {
+            range: command.name_range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String("prefer-ereject".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5429#section-2.1")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: Some(serde_json::json!({
+                "title": "Replace 'reject' with 'ereject'",
+                "replacement": "ereject",
+                "edits": edits,
+            })),
+        });
+    }
+}
+
+/// Check the addresses of `redirect` and of the `:addresses` of `vacation` as addr-specs
+/// Errors point at the offending characters inside the string literal.
+fn check_address_syntax(sink: &mut Sink, text: &str, script: &Script) {
+    trace!("Checking address syntax");
+    for command in script.all_commands() {
+        let (addresses, href) = match command.name.as_str() {
+            "redirect" => (
+                command.positional_arguments().first().map(|a| a.strings()).unwrap_or_default(),
+                "https://datatracker.ietf.org/doc/html/rfc5228#section-4.2",
+            ),
+            "vacation" => {
+                let mut addresses = Vec::new();
+                let mut arguments = command.arguments.iter();
+                while let Some(argument) = arguments.next() {
+                    if argument.tag() == Some(":addresses")
+                        && let Some(value) = arguments.next()
+                    {
+                        addresses.extend(value.strings());
+                    }
+                }
+                (addresses, "https://datatracker.ietf.org/doc/html/rfc5230#section-4.5")
+            }
+            _ => continue,
+        };
+        for address in addresses {
+            // Variables are expanded before the address is used
+            if address.value.contains("${") {
+                continue;
+            }
+            let Err(error) = sieve::check_address(&address.value) else {
+                continue;
+            };
+            let range = if error.start == error.end {
+                address.range
+            } else {
+                address.value_range(text, error.start, error.end)
+            };
+            warn!("{}", error.message);
+            sink.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid-address".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(href).unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message: error.message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Flag redirects that can loop back or lose mail
+/// A redirect to one of the user's own addresses, from `own_addresses` or the `:addresses` of
+/// `vacation`, likely comes back to the same script. A redirect without `:copy` followed by
+/// `discard` leaves no copy in the mailbox, so the message is gone if the redirect fails.
+fn check_redirects(sink: &mut Sink, uri: &Url, script: &Script, own_addresses: &[String]) {
+    trace!("Checking redirects");
+    let commands = script.all_commands();
+
+    let mut own: Vec<(String, Option<Range>)> = own_addresses
+        .iter()
+        .map(|address| (address.trim().to_lowercase(), None))
+        .collect();
+    for vacation in commands.iter().filter(|c| c.name == "vacation") {
+        let mut arguments = vacation.arguments.iter();
+        while let Some(argument) = arguments.next() {
+            if argument.tag() == Some(":addresses")
+                && let Some(addresses) = arguments.next()
+            {
+                for address in addresses.strings() {
+                    own.push((address.decoded().trim().to_lowercase(), Some(address.range)));
+                }
+            }
+        }
+    }
+
+    for redirect in commands.iter().filter(|c| c.name == "redirect") {
+        let targets = redirect
+            .positional_arguments()
+            .first()
+            .map(|a| a.strings())
+            .unwrap_or_default();
+        for target in targets {
+            let value = target.decoded().trim().to_lowercase();
+            if value.contains("${") {
+                continue;
+            }
+            let Some((_, source)) = own.iter().find(|(address, _)| *address == value) else {
+                continue;
+            };
+            let message = format!(
+                "Redirecting to '{}', one of your own addresses, can cause a mail loop",
+                target.value
+            );
+            warn!("{}", message);
+            sink.push(Diagnostic {
+                range: target.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("redirect-loop".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-4.2")
+                        .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message,
+                related_information: source.map(|range| {
+                    vec![DiagnosticRelatedInformation {
+                        location: Location {
+                            uri: uri.clone(),
+                            range,
+                        },
+                        message: "Address of the vacation response".to_string(),
+                    }]
+                }),
+                tags: None,
+                data: None,
+            });
+        }
+
+        if redirect.arguments.iter().any(|a| a.tag() == Some(":copy")) {
+            continue;
+        }
+        let Some(discard) = commands
+            .iter()
+            .find(|c| c.name == "discard" && c.range.start > redirect.range.end)
+        else {
+            continue;
+        };
+        let message = "'redirect' without ':copy' followed by 'discard' keeps no copy of the \
+                       message; it is lost if the redirect fails";
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range: redirect.name_range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("redirect-without-copy".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc3894#section-3")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message: message.to_string(),
+            related_information: Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: uri.clone(),
+                    range: discard.range,
+                },
+                message: "Later 'discard'".to_string(),
+            }]),
+            tags: None,
+            data: Some(serde_json::json!({
+                "title": "Redirect a copy with ':copy'",
+                "replacement": "redirect :copy",
+            })),
+        });
+    }
+}
+
+/// Flag match types that do not fit the wildcards in their keys
+/// `:matches` without any wildcard behaves like `:is`, and a `*` under `:is` or `:contains`
+/// is compared literally. Runs like "***SPAM***" are taken as literal text on purpose.
+fn check_wildcards(sink: &mut Sink, script: &Script) {
+    trace!("Checking wildcard usage");
+    const KEYED_TESTS: &[&str] = &[
+        "header", "address", "envelope", "string", "body", "date", "currentdate", "environment",
+    ];
+
+    for test in script.all_tests() {
+        if !KEYED_TESTS.contains(&test.name.as_str()) {
+            continue;
+        }
+        let match_type = test.arguments.iter().find(|a| {
+            matches!(
+                a.tag(),
+                Some(":is" | ":contains" | ":matches" | ":regex" | ":value" | ":count" | ":list")
+            )
+        });
+        let Some(keys) = test.arguments.last().map(|a| a.strings()) else {
+            continue;
+        };
+        // Variables are expanded at runtime, so the final keys are unknown
+        if keys.is_empty() || keys.iter().any(|k| k.value.contains("${")) {
+            continue;
+        }
+
+        let (range, message, data) = match match_type.and_then(|m| m.tag()) {
+            Some(":matches") if keys.iter().all(|k| !has_wildcard(&k.value, false)) => (
+                match_type.map(|m| m.range()).unwrap_or(test.range),
+                "':matches' without '*' or '?' compares exactly; use ':is'".to_string(),
+                Some(serde_json::json!({
+                    "title": "Replace with \":is\"",
+                    "replacement": ":is",
+                })),
+            ),
+            tag @ (None | Some(":is" | ":contains")) => {
+                let Some(key) = keys.iter().find(|k| has_wildcard(&k.value, true)) else {
+                    continue;
+                };
+                let tag = tag.unwrap_or(":is");
+                let message = format!(
+                    "'*' in \"{}\" is compared literally by '{}'; use ':matches' for wildcards",
+                    key.value, tag
+                );
+                match match_type {
+                    Some(explicit) => (
+                        explicit.range(),
+                        message,
+                        Some(serde_json::json!({
+                            "title": "Replace with \":matches\"",
+                            "replacement": ":matches",
+                        })),
+                    ),
+                    None => (key.range, message, None),
+                }
+            }
+            _ => continue,
+        };
+
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("match-type".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-2.7.1")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        });
+    }
+}
+
+/// Check `fileinto` targets against the configured separator and namespace prefix
+/// Each diagnostic carries a quick fix converting the path to the server's conventions.
+fn check_mailbox_paths(
+    sink: &mut Sink,
+    script: &Script,
+    convention: &MailboxConvention,
+) {
+    trace!("Checking mailbox paths");
+    for command in script.all_commands() {
+        if command.name != "fileinto" {
+            continue;
+        }
+        let Some(Argument::String(mailbox)) = command
+            .arguments
+            .iter()
+            .find(|a| !matches!(a, Argument::Tag { .. }))
+        else {
+            continue;
+        };
+        // Variables are expanded at runtime, so the final path is unknown
+        let value = mailbox.decoded();
+        if value.contains("${") {
+            continue;
+        }
+        let Some(problem) = convention.check(&value) else {
+            continue;
+        };
+
+        warn!("{}", problem.message);
+        sink.push(Diagnostic {
+            range: mailbox.range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("mailbox-path".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc3501#section-5.1")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message: problem.message,
+            related_information: None,
+            tags: None,
+            data: Some(serde_json::json!({
+                "title": format!("Replace with \"{}\"", problem.converted),
+                "replacement": refactor::quote_string(&problem.converted),
+            })),
+        });
+    }
+}
+
+/// Check the key patterns of `:regex` matches against the POSIX ERE grammar
+/// Errors point at the offending characters inside the string literal.
+fn check_regex_patterns(sink: &mut Sink, text: &str, script: &Script) {
+    trace!("Checking regex patterns");
+    let argument_lists = script
+        .all_commands()
+        .into_iter()
+        .map(|c| &c.arguments)
+        .chain(script.all_tests().into_iter().map(|t| &t.arguments));
+    for arguments in argument_lists {
+        if !arguments.iter().any(|a| a.tag() == Some(":regex")) {
+            continue;
+        }
+        let Some(keys) = arguments.last() else {
+            continue;
+        };
+        for key in keys.strings() {
+            // Variables are expanded before the pattern is compiled
+            if key.value.contains("${") {
+                continue;
+            }
+            let Err(error) = posix::check(&key.value) else {
+                continue;
+            };
+
+            warn!("{}", error.message);
+            sink.push(Diagnostic {
+                range: key.value_range(text, error.start, error.end),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid-regex".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse(
+                        "https://datatracker.ietf.org/doc/html/draft-ietf-sieve-regex-01#section-3",
+                    )
+                    .unwrap(),
+                }),
+                source: Some("sieve-lsp".to_string()),
+                message: error.message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+/// Flag required capabilities the script never uses
+/// Each gets a quick fix that removes it from its require statement.
+fn check_unused_requires(
+    sink: &mut Sink,
+    text: &str,
+    script: &Script,
+    dialects: &BTreeMap<String, String>,
+) {
+    trace!("Checking for unused requires");
+    for (command, capability) in requires::unused_requires(script, dialects) {
+        let message = format!("Extension '{}' is required but never used", capability.value);
+        debug!("{}", message);
+        let edit = requires::remove_requirement(text, command, capability);
+        sink.push(Diagnostic {
+            range: capability.range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-require".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.2")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(serde_json::json!({
+                "title": format!("Remove unused require of \"{}\"", capability.value),
+                "replacement": edit.new_text,
+                "range": edit.range,
+            })),
+        });
+    }
+}
+
+/// Flag capabilities required more than once, or next to one that already provides them
+/// Each gets a quick fix that removes it from its require statement.
+fn check_duplicate_requires(sink: &mut Sink, uri: &Url, text: &str, script: &Script) {
+    trace!("Checking for duplicate requires");
+    let capabilities: Vec<(&Command, &parser::StringLiteral)> = script
+        .all_commands()
+        .into_iter()
+        .filter(|c| c.name == "require")
+        .flat_map(|command| {
+            command
+                .arguments
+                .iter()
+                .flat_map(|a| a.strings())
+                .map(move |capability| (command, capability))
+        })
+        .collect();
+
+    for (idx, (command, capability)) in capabilities.iter().enumerate() {
+        let value = capability.value.as_str();
+        let earlier = capabilities[..idx]
+            .iter()
+            .find(|(_, other)| other.value.eq_ignore_ascii_case(value));
+        let provider = capabilities
+            .iter()
+            .find(|(_, other)| requires::implies(&other.value, value));
+        let (message, title, related, related_message) = match (earlier, provider) {
+            (Some((_, first)), _) => (
+                format!("Extension '{}' is already required", value),
+                format!("Remove duplicate require of \"{}\"", value),
+                first,
+                "First required here".to_string(),
+            ),
+            (None, Some((_, provider))) => (
+                format!("Extension '{}' is already provided by '{}'", value, provider.value),
+                format!("Remove redundant require of \"{}\"", value),
+                provider,
+                format!("'{}' is required here", provider.value),
+            ),
+            (None, None) => continue,
+        };
+        debug!("{}", message);
+        let edit = requires::remove_requirement(text, command, capability);
+        sink.push(Diagnostic {
+            range: capability.range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("duplicate-require".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.2")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: uri.clone(),
+                    range: related.range,
+                },
+                message: related_message,
+            }]),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(serde_json::json!({
+                "title": title,
+                "replacement": edit.new_text,
+                "range": edit.range,
+            })),
+        });
+    }
+}
+
+/// Flag capabilities and features of a dialect variant other than the configured one
+/// Requiring the other variant's capability gets a quick fix to the configured capability.
+fn check_dialects(
+    sink: &mut Sink,
+    script: &Script,
+    dialects: &BTreeMap<String, String>,
+) {
+    trace!("Checking extension dialects");
+    let mut problems = Vec::new();
+
+    for command in script.all_commands() {
+        if command.name != "require" {
+            continue;
+        }
+        for capability in command.arguments.iter().flat_map(|a| a.strings()) {
+            let Some(family) = dialect::family_of(&capability.value) else {
+                continue;
+            };
+            let active = family.active(dialects);
+            if capability.value == active.capability {
+                continue;
+            }
+            let Some(variant) = family
+                .variants
+                .iter()
+                .find(|variant| variant.capability == capability.value)
+            else {
+                continue;
+            };
+            problems.push((
+                capability.range,
+                format!(
+                    "\"{}\" is the capability of {}, but the server implements {}",
+                    capability.value, variant.description, active.description
+                ),
+                Some(serde_json::json!({
+                    "title": format!("Replace with \"{}\"", active.capability),
+                    "replacement": refactor::quote_string(active.capability),
+                })),
+            ));
+        }
+    }
+
+    // Features are commands or tests, optionally narrowed to one of their tags
+    let items = script
+        .all_commands()
+        .into_iter()
+        .map(|c| (&c.name, c.name_range, &c.arguments))
+        .chain(
+            script
+                .all_tests()
+                .into_iter()
+                .map(|t| (&t.name, t.name_range, &t.arguments)),
+        );
+    for (name, name_range, arguments) in items {
+        let features = std::iter::once((name.clone(), name_range)).chain(
+            arguments
+                .iter()
+                .filter_map(|a| a.tag().map(|tag| (format!("{} {}", name, tag), a.range()))),
+        );
+        for (feature, range) in features {
+            let Some((family, variant)) = dialect::variant_of_feature(&feature) else {
+                continue;
+            };
+            let active = family.active(dialects);
+            if variant == active {
+                continue;
+            }
+            problems.push((
+                range,
+                format!(
+                    "'{}' is only defined by {}, but the server implements {}",
+                    feature, variant.description, active.description
+                ),
+                None,
+            ));
+        }
+    }
+
+    for (range, message, data) in problems {
+        warn!("{}", message);
+        sink.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("dialect-mismatch".to_string())),
+            code_description: Some(CodeDescription {
+                href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.2")
+                    .unwrap(),
+            }),
+            source: Some("sieve-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        });
+    }
+}
+
+/// Build an "unreachable code" diagnostic that editors render as faded text
+fn unreachable_diagnostic(command: &Command, message: String) -> Diagnostic {
+    warn!("{}", message);
+    Diagnostic {
+        range: command.range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("unreachable-code".to_string())),
+        code_description: Some(CodeDescription {
+            href: Url::parse("https://datatracker.ietf.org/doc/html/rfc5228#section-3.3")
+                .unwrap(),
+        }),
+        source: Some("sieve-lsp".to_string()),
+        message,
+        related_information: None,
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        data: None,
+    }
+}
+
+/// Tags the mime extension adds to the header, address and exists tests
+const MIME_TAGS: &[&str] = &[":mime", ":anychild", ":type", ":subtype", ":contenttype", ":param"];
+
+/// Whether a command can cancel the implicit keep (RFC 5228 section 2.10.2)
+/// Included scripts are unknown here, so `include` counts as well.
+fn may_cancel_implicit_keep(command: &Command) -> bool {
+    let copy = command.arguments.iter().any(|a| a.tag() == Some(":copy"));
+    match command.name.as_str() {
+        "fileinto" | "redirect" | "pipe" => !copy,
+        "discard" | "reject" | "ereject" | "include" => true,
+        _ => false,
+    }
+}
+
+/// The `:name` of a `foreverypart` loop or `break`
+fn loop_name(command: &Command) -> Option<&parser::StringLiteral> {
+    let idx = command.arguments.iter().position(|a| a.tag() == Some(":name"))?;
+    match command.arguments.get(idx + 1)? {
+        Argument::String(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// Report `break` commands outside of the loop they refer to
+/// `loops` holds the names of the enclosing `foreverypart` loops, innermost last.
+fn check_loops<'a>(
+    commands: &'a [Command],
+    loops: &mut Vec<Option<&'a str>>,
+    problems: &mut Vec<(Range, String, &'static str)>,
+) {
+    for command in commands {
+        if command.name == "break" {
+            match loop_name(command) {
+                _ if loops.is_empty() => problems.push((
+                    command.name_range,
+                    "'break' is only allowed inside 'foreverypart'".to_string(),
+                    "3.2",
+                )),
+                Some(name) if !loops.contains(&Some(name.value.as_str())) => problems.push((
+                    name.range,
+                    format!("No enclosing 'foreverypart' loop is named '{}'", name.value),
+                    "3.2",
+                )),
+                _ => {}
+            }
+        }
+        let Some(block) = &command.block else {
+            continue;
+        };
+        if command.name == "foreverypart" {
+            loops.push(loop_name(command).map(|name| name.value.as_str()));
+            check_loops(&block.commands, loops, problems);
+            loops.pop();
+        } else {
+            check_loops(&block.commands, loops, problems);
+        }
+    }
+}
+
+/// Whether a key contains an unescaped wildcard
+/// With `star_only`, only isolated `*` count, since runs like "***" are usually literal text.
+fn has_wildcard(key: &str, star_only: bool) -> bool {
+    let chars: Vec<char> = key.chars().collect();
+    let mut idx = 0;
+    while idx < chars.len() {
+        match chars[idx] {
+            '\\' => idx += 1,
+            '?' if !star_only => return true,
+            '*' => {
+                let run = chars[idx..].iter().take_while(|&&c| c == '*').count();
+                if !star_only || run == 1 {
+                    return true;
+                }
+                idx += run - 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    false
+}
@@ -1,5 +1,6 @@
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::BTreeMap;
 
 // ================================================================================================
 // SIEVE LANGUAGE DEFINITIONS
@@ -26,11 +27,23 @@ lazy_static! {
         "currentdate", // Test current date/time (useful for time-based rules)
         "date",        // Test date values from headers (RFC 5260)
         "environment", // Access server environment info (RFC 5183)
+        "ihave",       // Test if extensions are available at runtime (RFC 5463)
         "mailbox",     // Test mailbox properties
         "mailboxexists", // Test if mailbox exists before filing
+        "metadata",    // Test a metadata entry of a mailbox (RFC 5490)
+        "metadataexists", // Test if metadata entries of a mailbox exist (RFC 5490)
+        "notify_method_capability", // Query a capability of a notification method (RFC 5435)
         "regex",       // Regular expression matching (draft standard)
+        "servermetadata", // Test a metadata entry of the server (RFC 5490)
+        "servermetadataexists", // Test if metadata entries of the server exist (RFC 5490)
         "spamtest",    // Interface with spam detection systems (RFC 5235)
+        "string",      // Compare strings after variable expansion (RFC 5229)
+        "valid_notify_method", // Test if notification URIs are supported (RFC 5435)
         "virustest",   // Interface with virus detection systems (RFC 5235)
+
+        // Proton Mail specific extensions
+        "expiration",  // Compare the expiration time of the message (Proton-specific)
+        "hasexpiration", // Test if the message has an expiration time (Proton-specific)
     ];
 }
 
@@ -45,18 +58,36 @@ lazy_static! {
         "reject",      // Reject message with error response to sender
         "stop",        // Stop processing this script (but continue with others)
 
+        // Foreverypart extension (RFC 5703)
+        "break",       // Leave the enclosing foreverypart loop
+
         // IMAP flags extension (RFC 5232) - for IMAP flag manipulation
         "addflag",     // Add IMAP flags to message (e.g., \Seen, \Flagged)
         "removeflag",  // Remove IMAP flags from message
         "setflag",     // Set IMAP flags (replaces existing flags)
 
+        // Variables extension (RFC 5229)
+        "set",         // Assign a value to a variable
+
+        // Editheader extension (RFC 5293)
+        "addheader",   // Add a header field to the message
+        "deleteheader", // Remove header fields from the message
+
         // Additional common actions
         "vacation",    // Send auto-reply message (RFC 5230)
         "notify",      // Send notification to external system
         "denotify",    // Cancel previous notification
+        "error",       // Fail the script with a message (RFC 5463)
 
         // Proton Mail specific extensions
         "expire",      // Set message expiration time (Proton-specific)
+        "unexpire",    // Remove the expiration time again (Proton-specific)
+
+        // Dovecot Pigeonhole specific extensions
+        "debug_log",   // Write a message to the Sieve log (vnd.dovecot.debug)
+        "execute",     // Run an external program, also usable as a test (vnd.dovecot.execute)
+        "filter",      // Replace the message with a program's output, also a test (vnd.dovecot.filter)
+        "pipe",        // Pass the message to an external program (vnd.dovecot.pipe)
     ];
 }
 
@@ -81,11 +112,16 @@ lazy_static! {
         ":localpart",  // Local part of address (before @)
         ":domain",     // Domain part of address (after @)
         ":all",        // Entire address
+        ":user",       // Local part without the detail (RFC 5233 subaddress)
+        ":detail",     // Detail after the separator in the local part (RFC 5233 subaddress)
 
         // Size comparison tags
         ":over",       // Size greater than specified value
         ":under",      // Size less than specified value
 
+        // Spam test tags (RFC 5235 spamtestplus)
+        ":percent",    // Spam score as a percentage from 0 to 100
+
         // Action modifier tags
         ":copy",       // Copy message instead of moving it
         ":create",     // Create mailbox if it doesn't exist
@@ -97,48 +133,725 @@ lazy_static! {
         // Advanced tags for various extensions
         ":flags",      // Specify IMAP flags
         ":importance", // Message importance level
+        ":from",       // Sender of a notification or vacation response
+        ":options",    // Method-specific notification options
+        ":message",    // Text of a notification
         ":mime",       // MIME-related operations
         ":anychild",   // Match any child MIME part
         ":type",       // MIME content type
         ":subtype",    // MIME content subtype
         ":contenttype", // Full MIME content type
         ":param",      // MIME parameter
+        ":name",       // Name of a foreverypart loop, for break
+
+        // Header field positions (RFC 5260 index, RFC 5293 editheader)
+        ":index",      // Select the n-th occurrence of a header field
+        ":last",       // Count occurrences from the end / add at the end
+
+        // Modifiers of the set command (RFC 5229)
+        ":lower",      // Convert to lower case
+        ":upper",      // Convert to upper case
+        ":lowerfirst", // Lower-case the first character
+        ":upperfirst", // Upper-case the first character
+        ":quotewildcard", // Escape *, ? and \\ for use in :matches patterns
+        ":length",     // Replace the value with its length
+        ":eval",       // Evaluate the value as an expression (Proton-specific)
+
+        // Program execution (Dovecot Pigeonhole)
+        ":try",        // Ignore failures of the program
+        ":input",      // Feed a string to the program instead of the message
+        ":pipe",       // Feed the message to the program
+        ":output",     // Store the program's output in a variable
     ];
 }
 
 lazy_static! {
     /// Sieve extensions that can be loaded with 'require' statements
     /// Each extension adds new functionality to the base Sieve language
-    pub static ref SIEVE_EXTENSIONS: HashMap<&'static str, &'static str> = {
-        let mut map = HashMap::new();
+    /// Kept in a BTreeMap so every listing derived from it is alphabetically ordered
+    pub static ref SIEVE_EXTENSIONS: BTreeMap<&'static str, &'static str> = {
+        let mut map = BTreeMap::new();
 
         // RFC standardized extensions
         map.insert("body", "Message body testing (RFC 5173)");
+        map.insert("comparator-i;ascii-numeric", "Numeric comparator i;ascii-numeric (RFC 4790)");
+        map.insert("comparator-i;unicode-casemap", "Unicode case-insensitive comparator (RFC 5051)");
         map.insert("copy", "Copy messages instead of moving (RFC 3894)");
         map.insert("date", "Date/time operations (RFC 5260)");
         map.insert("editheader", "Modify message headers (RFC 5293)");
         map.insert("encoded-character", "Encoded character support (RFC 5228)");
+        map.insert("enotify", "Notifications via URI methods (RFC 5435)");
         map.insert("envelope", "SMTP envelope testing (RFC 5228)");
+        map.insert("envelope-auth", "Authenticated sender in envelope tests");
+        map.insert("envelope-deliverby", "Delivery deadline in envelope tests (RFC 6009)");
+        map.insert("envelope-dsn", "Delivery status notification parameters in envelope tests (RFC 6009)");
         map.insert("environment", "Access to server environment (RFC 5183)");
         map.insert("ereject", "Enhanced reject with reason (RFC 5429)");
+        map.insert("extlists", "Externally stored address lists (RFC 6134)");
         map.insert("fileinto", "File messages into folders (RFC 5228)");
         map.insert("foreverypart", "Iterate over MIME parts (RFC 5703)");
+        map.insert("ihave", "Test for extensions at runtime (RFC 5463)");
         map.insert("imap4flags", "IMAP flag manipulation (RFC 5232)");
+        map.insert("imapflags", "IMAP flag manipulation (draft, superseded by imap4flags)");
         map.insert("include", "Include other scripts (RFC 6609)");
         map.insert("index", "Positional testing of headers (RFC 5260)");
         map.insert("mailbox", "Mailbox metadata access (RFC 5490)");
         map.insert("mboxmetadata", "Mailbox metadata operations (RFC 5490)");
         map.insert("mime", "MIME structure operations (RFC 5703)");
+        map.insert("notify", "Notifications (draft, superseded by enotify)");
         map.insert("regex", "Regular expression support (draft)");
         map.insert("reject", "Reject messages with errors (RFC 5228)");
         map.insert("relational", "Numeric comparisons (RFC 5231)");
         map.insert("servermetadata", "Server metadata access (RFC 5490)");
         map.insert("spamtest", "Spam testing interface (RFC 5235)");
+        map.insert("spamtestplus", "Spam scores as percentages with :percent (RFC 5235)");
         map.insert("subaddress", "Sub-addressing support (RFC 5233)");
         map.insert("vacation", "Auto-reply functionality (RFC 5230)");
         map.insert("variables", "Variable support (RFC 5229)");
         map.insert("virustest", "Virus testing interface (RFC 5235)");
 
+        // Vendor extensions
+        map.insert("vnd.dovecot.debug", "Logging from scripts (Dovecot Pigeonhole)");
+        map.insert("vnd.dovecot.execute", "Running external programs (Dovecot Pigeonhole)");
+        map.insert("vnd.dovecot.filter", "Filtering messages through programs (Dovecot Pigeonhole)");
+        map.insert("vnd.dovecot.pipe", "Piping messages to programs (Dovecot Pigeonhole)");
+        map.insert("vnd.proton.eval", "Expressions in variable assignments (Proton Mail)");
+        map.insert("vnd.proton.expire", "Message expiration (Proton Mail)");
+
+        map
+    };
+}
+
+lazy_static! {
+    /// Comparators that can be selected with `:comparator` (RFC 5228 section 2.7.3)
+    /// Keyed by the lowercase comparator name
+    pub static ref SIEVE_COMPARATORS: BTreeMap<&'static str, &'static str> = {
+        let mut map = BTreeMap::new();
+
+        map.insert("i;octet", "Byte-wise comparison, case-sensitive (RFC 4790)");
+        map.insert("i;ascii-casemap", "ASCII case-insensitive comparison, the default (RFC 4790)");
+        map.insert("i;ascii-numeric", "Compares strings as unsigned decimal numbers (RFC 4790)");
+        map.insert("i;unicode-casemap", "Unicode case-insensitive comparison (RFC 5051)");
+
+        map
+    };
+}
+
+lazy_static! {
+    /// Relations accepted by the `:value` and `:count` match types (RFC 5231 section 4)
+    pub static ref RELATIONAL_OPERATORS: BTreeMap<&'static str, &'static str> = {
+        let mut map = BTreeMap::new();
+
+        map.insert("gt", "Greater than");
+        map.insert("ge", "Greater than or equal to");
+        map.insert("lt", "Less than");
+        map.insert("le", "Less than or equal to");
+        map.insert("eq", "Equal to");
+        map.insert("ne", "Not equal to");
+
         map
     };
 }
+
+lazy_static! {
+    /// Offsets accepted by `:zone` (RFC 5260 section 4.1), with the zones that use them
+    pub static ref TIME_ZONES: BTreeMap<&'static str, &'static str> = {
+        let mut map = BTreeMap::new();
+
+        map.insert("-1200", "Baker Island");
+        map.insert("-1100", "Samoa Standard Time (SST)");
+        map.insert("-1000", "Hawaii (HST)");
+        map.insert("-0930", "Marquesas Islands");
+        map.insert("-0900", "Alaska Standard Time (AKST)");
+        map.insert("-0800", "Pacific Standard Time (PST), Alaska Daylight Time (AKDT)");
+        map.insert("-0700", "Mountain Standard Time (MST), Pacific Daylight Time (PDT)");
+        map.insert("-0600", "Central Standard Time (CST), Mountain Daylight Time (MDT)");
+        map.insert("-0500", "Eastern Standard Time (EST), Central Daylight Time (CDT)");
+        map.insert("-0400", "Atlantic Standard Time (AST), Eastern Daylight Time (EDT)");
+        map.insert("-0330", "Newfoundland Standard Time (NST)");
+        map.insert("-0300", "Argentina, Brazil, Atlantic Daylight Time (ADT)");
+        map.insert("-0230", "Newfoundland Daylight Time (NDT)");
+        map.insert("-0200", "South Georgia");
+        map.insert("-0100", "Azores, Cape Verde");
+        map.insert("+0000", "Coordinated Universal Time (UTC), Greenwich Mean Time (GMT)");
+        map.insert("+0100", "Central European Time (CET), British Summer Time");
+        map.insert("+0200", "Eastern European Time (EET), Central European Summer Time (CEST)");
+        map.insert("+0300", "Moscow (MSK), Eastern European Summer Time (EEST)");
+        map.insert("+0330", "Iran");
+        map.insert("+0400", "Gulf Standard Time");
+        map.insert("+0430", "Afghanistan");
+        map.insert("+0500", "Pakistan");
+        map.insert("+0530", "India");
+        map.insert("+0545", "Nepal");
+        map.insert("+0600", "Bangladesh");
+        map.insert("+0630", "Myanmar");
+        map.insert("+0700", "Indochina");
+        map.insert("+0800", "China, Singapore, Western Australia (AWST)");
+        map.insert("+0845", "Eucla");
+        map.insert("+0900", "Japan (JST), Korea (KST)");
+        map.insert("+0930", "Central Australia (ACST)");
+        map.insert("+1000", "Eastern Australia (AEST)");
+        map.insert("+1030", "Lord Howe Island, Central Australia Daylight Time (ACDT)");
+        map.insert("+1100", "Eastern Australia Daylight Time (AEDT)");
+        map.insert("+1200", "New Zealand Standard Time (NZST)");
+        map.insert("+1245", "Chatham Islands");
+        map.insert("+1300", "New Zealand Daylight Time (NZDT), Tonga");
+        map.insert("+1400", "Line Islands");
+
+        map
+    };
+}
+
+/// Zone names mapped to the offset `:zone` expects instead
+/// The US names are the obsolete zones of RFC 5322 section 4.3, the rest are unambiguous
+/// abbreviations that users commonly type.
+pub const NAMED_ZONES: &[(&str, &str)] = &[
+    ("UT", "+0000"),
+    ("UTC", "+0000"),
+    ("GMT", "+0000"),
+    ("EST", "-0500"),
+    ("EDT", "-0400"),
+    ("CST", "-0600"),
+    ("CDT", "-0500"),
+    ("MST", "-0700"),
+    ("MDT", "-0600"),
+    ("PST", "-0800"),
+    ("PDT", "-0700"),
+    ("CET", "+0100"),
+    ("CEST", "+0200"),
+    ("EET", "+0200"),
+    ("EEST", "+0300"),
+    ("JST", "+0900"),
+    ("AEST", "+1000"),
+    ("AEDT", "+1100"),
+    ("NZST", "+1200"),
+    ("NZDT", "+1300"),
+];
+
+/// Whether `value` is a `:zone` offset, `+hhmm` or `-hhmm` (RFC 5260 section 4.1)
+/// Hours beyond 14 and minutes beyond 59 are rejected since no zone uses them.
+pub fn is_zone_offset(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    if bytes.len() != 5 || !matches!(bytes[0], b'+' | b'-') {
+        return false;
+    }
+    if !bytes[1..].iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    let hours: u32 = value[1..3].parse().unwrap_or(99);
+    let minutes: u32 = value[3..5].parse().unwrap_or(99);
+    hours <= 14 && minutes < 60
+}
+
+/// The offset of a zone name from `NAMED_ZONES`, ignoring case
+pub fn named_zone_offset(name: &str) -> Option<&'static str> {
+    NAMED_ZONES
+        .iter()
+        .find(|(zone, _)| zone.eq_ignore_ascii_case(name))
+        .map(|(_, offset)| *offset)
+}
+
+/// Comparators every implementation provides without a `require`
+pub const BUILTIN_COMPARATORS: &[&str] = &["i;octet", "i;ascii-casemap"];
+
+/// A part of the SMTP envelope the `envelope` test can examine
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnvelopePart {
+    pub name: &'static str,
+    /// Capability enabling the part; `None` for those of the base specification
+    pub capability: Option<&'static str>,
+    pub description: &'static str,
+}
+
+/// Every known envelope part
+pub const ENVELOPE_PARTS: &[EnvelopePart] = &[
+    EnvelopePart {
+        name: "from",
+        capability: None,
+        description: "Return path of the message (SMTP MAIL FROM)",
+    },
+    EnvelopePart {
+        name: "to",
+        capability: None,
+        description: "Recipient the message is delivered to (SMTP RCPT TO)",
+    },
+    EnvelopePart {
+        name: "auth",
+        capability: Some("envelope-auth"),
+        description: "Identity the sender authenticated as (SMTP AUTH parameter)",
+    },
+    EnvelopePart {
+        name: "notify",
+        capability: Some("envelope-dsn"),
+        description: "When the sender wants delivery status notifications (SMTP NOTIFY parameter)",
+    },
+    EnvelopePart {
+        name: "orcpt",
+        capability: Some("envelope-dsn"),
+        description: "Original recipient before forwarding (SMTP ORCPT parameter)",
+    },
+    EnvelopePart {
+        name: "ret",
+        capability: Some("envelope-dsn"),
+        description: "Whether notifications return the full message or headers (SMTP RET parameter)",
+    },
+    EnvelopePart {
+        name: "envid",
+        capability: Some("envelope-dsn"),
+        description: "Identifier the sender gave the transaction (SMTP ENVID parameter)",
+    },
+    EnvelopePart {
+        name: "bytimeabsolute",
+        capability: Some("envelope-deliverby"),
+        description: "Deadline for delivery as a date-time (SMTP BY parameter)",
+    },
+    EnvelopePart {
+        name: "bytimerelative",
+        capability: Some("envelope-deliverby"),
+        description: "Seconds left until the delivery deadline (SMTP BY parameter)",
+    },
+    EnvelopePart {
+        name: "bymode",
+        capability: Some("envelope-deliverby"),
+        description: "What happens once the deadline passes: \"notify\" or \"return\"",
+    },
+    EnvelopePart {
+        name: "bytrace",
+        capability: Some("envelope-deliverby"),
+        description: "Whether the delivery deadline is traced: \"true\" or \"false\"",
+    },
+];
+
+/// The envelope part of a name, which is case-insensitive
+pub fn envelope_part(name: &str) -> Option<&'static EnvelopePart> {
+    ENVELOPE_PARTS
+        .iter()
+        .find(|part| part.name.eq_ignore_ascii_case(name))
+}
+
+/// The capability string a comparator has to be required with, if any
+pub fn comparator_requirement(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    (!BUILTIN_COMPARATORS.contains(&name.as_str())).then(|| format!("comparator-{}", name))
+}
+
+/// Usual values of headers set by mail software, in the case they are written with
+/// Case-sensitive comparisons with these values only match when the case is the same.
+pub const TYPICAL_HEADER_VALUES: &[(&str, &[&str])] = &[
+    ("x-spam-flag", &["YES", "NO"]),
+    ("x-spam-status", &["Yes", "No"]),
+    ("x-virus-status", &["Clean", "Infected"]),
+    ("precedence", &["bulk", "list", "junk"]),
+    ("auto-submitted", &["no", "auto-generated", "auto-replied", "auto-notified"]),
+    ("x-priority", &["1 (Highest)", "2 (High)", "3 (Normal)", "4 (Low)", "5 (Lowest)"]),
+    (
+        "content-type",
+        &["text/plain", "text/html", "multipart/mixed", "multipart/alternative"],
+    ),
+];
+
+/// Usual values of a header, matched case-insensitively by name
+pub fn typical_header_values(name: &str) -> &'static [&'static str] {
+    TYPICAL_HEADER_VALUES
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map_or(&[], |(_, values)| values)
+}
+
+/// Control commands (RFC 5228 section 3) and those of the include extension (RFC 6609)
+pub const CONTROL_COMMANDS: &[&str] = &[
+    "require", "if", "elsif", "else", "stop", "foreverypart", "include", "return", "global",
+];
+
+/// Commands and tests of extensions without completions, so they are not taken for typos
+pub const OTHER_COMMANDS: &[&str] = &[
+    "ereject", "duplicate", "hasflag", "replace", "enclose", "extracttext",
+    "convert", "specialuse_exists", "valid_ext_list",
+];
+
+/// Tags of extensions without completions, so they are not taken for typos
+pub const OTHER_TAGS: &[&str] = &[
+    ":raw", ":content", ":text", ":list", ":quoteregex",
+    ":encodeurl", ":header", ":uniqueid", ":global", ":personal", ":once", ":optional",
+];
+
+/// Whether a capability is a known extension, including the comparators
+pub fn is_known_extension(capability: &str) -> bool {
+    SIEVE_EXTENSIONS.contains_key(capability)
+        || capability
+            .strip_prefix("comparator-")
+            .is_some_and(|comparator| SIEVE_COMPARATORS.contains_key(comparator))
+}
+
+/// Every known extension capability, including the comparators
+pub fn known_extensions() -> Vec<String> {
+    SIEVE_EXTENSIONS
+        .keys()
+        .map(|capability| capability.to_string())
+        .chain(SIEVE_COMPARATORS.keys().map(|c| format!("comparator-{}", c)))
+        .collect()
+}
+
+/// Edit distance between two words, counting a swap of adjacent characters as one edit
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between the first i characters of a and the first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The candidate a misspelled word most likely stands for
+/// Case is ignored; candidates further away than a few edits are no likely typo.
+pub fn closest_match<'a>(
+    word: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    let limit = (word.chars().count() / 4).clamp(1, 3);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&word, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Tags that consume the argument following them, such as `:comparator "i;octet"`
+pub const TAGS_WITH_VALUE: &[&str] = &[
+    ":comparator", ":value", ":count", ":index", ":param", ":name", ":input", ":output",
+    ":days", ":seconds", ":subject", ":from", ":addresses", ":handle", ":flags", ":importance",
+    ":options", ":message", ":method", ":id", ":zone", ":fcc", ":specialuse", ":mailboxid",
+];
+
+/// Headers whose content is an address list (RFC 5322 section 3.6)
+/// The `address` test is only meaningful for these, plus custom `X-` headers
+pub const ADDRESS_HEADERS: &[&str] = &[
+    "from",
+    "sender",
+    "reply-to",
+    "to",
+    "cc",
+    "bcc",
+    "resent-from",
+    "resent-sender",
+    "resent-to",
+    "resent-cc",
+    "resent-bcc",
+    "return-path",
+    "delivered-to",
+    "envelope-to",
+    "errors-to",
+    "disposition-notification-to",
+    "mail-followup-to",
+    "mail-reply-to",
+];
+
+/// Header fields offered when completing the header names of `header`, `exists` and `address`
+/// Standard fields of RFC 5322 and its companions first, then ones common providers add
+pub const COMMON_HEADERS: &[(&str, &str)] = &[
+    ("From", "Author of the message"),
+    ("Sender", "Mailbox that actually sent the message, when not the author"),
+    ("Reply-To", "Where replies should be sent"),
+    ("To", "Primary recipients"),
+    ("Cc", "Secondary recipients"),
+    ("Bcc", "Blind carbon copy recipients, usually removed before delivery"),
+    ("Subject", "Topic of the message"),
+    ("Date", "When the message was written"),
+    ("Message-ID", "Unique identifier of the message"),
+    ("In-Reply-To", "Message ID of the message this one replies to"),
+    ("References", "Message IDs of the thread this message belongs to"),
+    ("Comments", "Free-form comments on the message"),
+    ("Keywords", "Comma-separated keywords describing the message"),
+    ("Return-Path", "Envelope sender recorded by the final delivery"),
+    ("Received", "Trace of a server that handled the message, one per hop"),
+    ("Resent-From", "Who resent the message"),
+    ("Resent-To", "Recipients of a resent message"),
+    ("Delivered-To", "Mailbox the message was delivered to"),
+    ("X-Original-To", "Recipient address before aliases were expanded"),
+    ("Errors-To", "Where delivery errors should be sent"),
+    ("Mail-Followup-To", "Where follow-ups to a mailing list message should go"),
+    ("Disposition-Notification-To", "Where read receipts should be sent (RFC 8098)"),
+    ("MIME-Version", "MIME version of the message (RFC 2045)"),
+    ("Content-Type", "Media type of the message body (RFC 2045)"),
+    ("List-Id", "Identifier of the mailing list the message came from (RFC 2919)"),
+    ("List-Unsubscribe", "How to leave the mailing list (RFC 2369)"),
+    ("List-Post", "Address for posting to the mailing list (RFC 2369)"),
+    ("Precedence", "Bulk, list or junk mail marker used by mailing lists"),
+    ("Auto-Submitted", "Marks automatically generated messages such as auto-replies (RFC 3834)"),
+    ("Importance", "Importance set by the sender: low, normal or high"),
+    ("X-Priority", "Priority set by the sender, 1 (highest) to 5 (lowest)"),
+    ("User-Agent", "Mail program that composed the message"),
+    ("X-Mailer", "Mail program that composed the message"),
+    ("Authentication-Results", "Results of SPF, DKIM and DMARC checks (RFC 8601)"),
+    ("Received-SPF", "Result of the SPF check of the sending host (RFC 7208)"),
+    ("DKIM-Signature", "Cryptographic signature of the sending domain (RFC 6376)"),
+    ("X-Spam-Status", "Verdict and tests of the spam filter, e.g. \"Yes, score=7.1\""),
+    ("X-Spam-Flag", "\"YES\" when the spam filter considers the message spam"),
+    ("X-Spam-Score", "Numeric score of the spam filter"),
+    ("X-Spam-Level", "Spam score as a row of asterisks"),
+];
+
+/// IMAP system flags scripts can set (RFC 3501 section 2.3.2); `\Recent` is server-managed
+pub const SYSTEM_FLAGS: &[(&str, &str)] = &[
+    ("\\Seen", "Message has been read"),
+    ("\\Answered", "Message has been answered"),
+    ("\\Flagged", "Message is flagged for urgent or special attention"),
+    ("\\Deleted", "Message is marked for removal by a later expunge"),
+    ("\\Draft", "Message is a draft that has not been sent"),
+];
+
+/// Date parts extracted by `date` and `currentdate` (RFC 5260 section 4.2)
+pub const DATE_PARTS: &[(&str, &str)] = &[
+    ("year", "Four-digit year, e.g. 2024"),
+    ("month", "Two-digit month, 01 to 12"),
+    ("day", "Two-digit day of the month, 01 to 31"),
+    ("date", "Date as yyyy-mm-dd"),
+    ("julian", "Days since 1858-11-17 (Modified Julian Day)"),
+    ("hour", "Two-digit hour, 00 to 23"),
+    ("minute", "Two-digit minute, 00 to 59"),
+    ("second", "Two-digit second, 00 to 60"),
+    ("time", "Time as hh:mm:ss"),
+    ("iso8601", "Date and time in ISO 8601 format"),
+    ("std11", "Date and time in RFC 2822 format"),
+    ("zone", "Time zone offset as +hhmm or -hhmm"),
+    ("weekday", "Day of the week, 0 (Sunday) to 6"),
+];
+
+/// Results of `spamtest` without `:percent` (RFC 5235 section 3.2)
+pub const SPAMTEST_SCORES: &[(&str, &str)] = &[
+    ("0", "Not tested for spam"),
+    ("1", "Tested and definitely not spam"),
+    ("2", "Tested, spam likelihood 2 of 10"),
+    ("3", "Tested, spam likelihood 3 of 10"),
+    ("4", "Tested, spam likelihood 4 of 10"),
+    ("5", "Tested, spam likelihood 5 of 10"),
+    ("6", "Tested, spam likelihood 6 of 10"),
+    ("7", "Tested, spam likelihood 7 of 10"),
+    ("8", "Tested, spam likelihood 8 of 10"),
+    ("9", "Tested, spam likelihood 9 of 10"),
+    ("10", "Tested and definitely spam"),
+];
+
+/// Results of `virustest` (RFC 5235 section 3.3)
+pub const VIRUSTEST_SCORES: &[(&str, &str)] = &[
+    ("0", "Not tested for viruses"),
+    ("1", "Tested and contains no known virus"),
+    ("2", "Tested and contained a known virus that was replaced with harmless content"),
+    ("3", "Tested and contained a known virus that was cured and is now harmless"),
+    ("4", "Tested and possibly contains a known virus"),
+    ("5", "Tested and definitely contains a known virus"),
+];
+
+/// Highest result of a `spamtest` or `virustest`; `spamtest :percent` goes up to 100
+pub fn max_score(test: &str, percent: bool) -> Option<u32> {
+    match (test, percent) {
+        ("spamtest", true) => Some(100),
+        ("spamtest", false) => Some(10),
+        ("virustest", _) => Some(5),
+        _ => None,
+    }
+}
+
+/// Description of a header field from `COMMON_HEADERS`, case-insensitively
+pub fn common_header(name: &str) -> Option<&'static str> {
+    COMMON_HEADERS
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, description)| *description)
+}
+
+/// Whether a string is a valid header field name: printable US-ASCII except ':' (RFC 5322 section 2.2)
+pub fn is_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| (33..=126).contains(&b) && b != b':')
+}
+
+/// Metadata entries defined for mailboxes and the server (RFC 5464 section 3.2, RFC 6154)
+pub const METADATA_ENTRIES: &[(&str, &str)] = &[
+    ("/private/comment", "The user's comment on the mailbox"),
+    ("/shared/comment", "Comment on the mailbox or server, visible to every user"),
+    ("/shared/admin", "Contact address of the server's administrator"),
+    ("/private/specialuse", "Special use of the mailbox, such as \\Sent or \\Junk"),
+];
+
+/// Why a string is not a metadata entry name, `None` when it is one (RFC 5464 section 3.2)
+/// Entry names are case-insensitive paths under `/private/` or `/shared/`.
+pub fn metadata_entry_problem(name: &str) -> Option<&'static str> {
+    let lower = name.to_lowercase();
+    if !["/private/", "/shared/"].iter().any(|p| lower.len() > p.len() && lower.starts_with(p)) {
+        Some("entry names start with \"/private/\" or \"/shared/\"")
+    } else if name.contains(['*', '%']) {
+        Some("entry names cannot contain '*' or '%'")
+    } else if name.contains("//") || name.ends_with('/') {
+        Some("entry names cannot contain empty components")
+    } else if !name.bytes().all(|b| (33..=126).contains(&b)) {
+        Some("entry names are printable US-ASCII without spaces")
+    } else {
+        None
+    }
+}
+
+/// Whether the `address` test can parse the given header
+pub fn is_address_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ADDRESS_HEADERS.contains(&name.as_str()) || name.starts_with("x-")
+}
+
+lazy_static! {
+    static ref EMAIL_ADDRESS: Regex =
+        Regex::new(r#"^[^\s@<>(),;:"]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)*$"#).unwrap();
+}
+
+/// Whether a string is a plain email address such as `user@example.com`
+pub fn is_email_address(value: &str) -> bool {
+    EMAIL_ADDRESS.is_match(value)
+}
+
+/// A syntax error in an address, with the offending characters
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressError {
+    pub message: String,
+    /// Character offsets into the address, end exclusive
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Characters an unquoted local part cannot contain
+const ADDRESS_SPECIALS: &[char] = &['(', ')', '<', '>', '[', ']', ':', ';', '\\', ',', '"'];
+
+/// Check an address against the addr-spec grammar of RFC 5322
+/// Reports the first of: spaces, a missing or repeated '@', an empty local part or domain,
+/// characters outside the grammar and misplaced dots. Quoted local parts and domain literals
+/// are accepted as they are.
+pub fn check_address(value: &str) -> Result<(), AddressError> {
+    let chars: Vec<char> = value.chars().collect();
+    let error = |message: &str, start: usize, end: usize| {
+        Err(AddressError {
+            message: message.to_string(),
+            start,
+            end,
+        })
+    };
+    if chars.is_empty() {
+        return error("The address is empty", 0, 0);
+    }
+
+    // A quoted local part may contain anything but an unescaped quote
+    let mut quoted_end = 0;
+    if chars[0] == '"' {
+        let mut idx = 1;
+        loop {
+            match chars.get(idx) {
+                None => return error("Unterminated quoted local part", 0, chars.len()),
+                Some('\\') => idx += 2,
+                Some('"') => break,
+                Some(_) => idx += 1,
+            }
+        }
+        quoted_end = idx + 1;
+    }
+
+    if let Some(start) = (quoted_end..chars.len()).find(|&idx| chars[idx].is_whitespace()) {
+        let end = start + chars[start..].iter().take_while(|c| c.is_whitespace()).count();
+        return error("Addresses cannot contain spaces", start, end);
+    }
+    let mut signs = (quoted_end..chars.len()).filter(|&idx| chars[idx] == '@');
+    let Some(at) = signs.next() else {
+        return error("The address is missing the '@' before its domain", 0, chars.len());
+    };
+    if let Some(second) = signs.next() {
+        return error("Addresses contain a single '@'", second, second + 1);
+    }
+    if at == 0 {
+        return error("The address is missing the local part before '@'", at, at + 1);
+    }
+    if at + 1 == chars.len() {
+        return error("The address is missing the domain after '@'", at, at + 1);
+    }
+
+    if quoted_end > 0 {
+        if at != quoted_end {
+            return error("A quoted local part must be followed by '@'", quoted_end, at);
+        }
+    } else {
+        if let Some(idx) =
+            (0..at).find(|&idx| chars[idx].is_control() || ADDRESS_SPECIALS.contains(&chars[idx]))
+        {
+            return Err(AddressError {
+                message: format!("'{}' is not allowed in an address", chars[idx]),
+                start: idx,
+                end: idx + 1,
+            });
+        }
+        if let Some(problem) = misplaced_dots(&chars, 0, at, false) {
+            return Err(problem);
+        }
+    }
+
+    // Domain literals such as `[192.0.2.1]` are not looked into
+    let domain_start = at + 1;
+    if chars[domain_start] == '[' && chars.last() == Some(&']') {
+        return Ok(());
+    }
+    if let Some(idx) = (domain_start..chars.len())
+        .find(|&idx| !(chars[idx].is_alphanumeric() || matches!(chars[idx], '-' | '.')))
+    {
+        return Err(AddressError {
+            message: format!("'{}' is not allowed in a domain", chars[idx]),
+            start: idx,
+            end: idx + 1,
+        });
+    }
+    misplaced_dots(&chars, domain_start, chars.len(), true).map_or(Ok(()), Err)
+}
+
+/// Leading, trailing or consecutive dots in the local part or domain at `chars[start..end]`
+fn misplaced_dots(chars: &[char], start: usize, end: usize, domain: bool) -> Option<AddressError> {
+    let (edge, consecutive) = if domain {
+        (
+            "The domain cannot start or end with a dot",
+            "The domain contains consecutive dots",
+        )
+    } else {
+        (
+            "The local part cannot start or end with a dot",
+            "The local part contains consecutive dots",
+        )
+    };
+    let problem = |message: &str, start: usize, end: usize| {
+        Some(AddressError {
+            message: message.to_string(),
+            start,
+            end,
+        })
+    };
+    if let Some(idx) = (start..end - 1).find(|&idx| chars[idx] == '.' && chars[idx + 1] == '.') {
+        let run = chars[idx..end].iter().take_while(|&&c| c == '.').count();
+        return problem(consecutive, idx, idx + run);
+    }
+    if chars[start] == '.' {
+        return problem(edge, start, start + 1);
+    }
+    if chars[end - 1] == '.' {
+        return problem(edge, end - 1, end);
+    }
+    None
+}
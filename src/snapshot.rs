@@ -0,0 +1,355 @@
+// ================================================================================================
+// FROZEN STATE
+// ================================================================================================
+//
+// Large workspaces are expensive to index: every script is read and parsed to follow its
+// includes. On shutdown the server freezes what it knows into the workspace
+// (`.sieve-lsp/state.json`), and the next session for the same workspace thaws it, so only
+// scripts modified in between have to be parsed again. The snapshot also remembers the open
+// documents with their external linter findings, which are expensive to reproduce because the
+// linter only runs on save.
+//
+// The snapshot is only written on a clean shutdown. Each indexed script is therefore also
+// cached on its own in `.sieve-lsp/cache`, keyed by the hash of its content, as soon as it is
+// parsed: after a crash, a checkout that only touched modification times or a branch switch
+// back, the index is rebuilt from the cache instead of parsing every script again.
+
+use crate::include;
+use crate::outline::{self, FlatSymbol};
+use crate::parser;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+use tower_lsp::lsp_types::{Diagnostic, PositionEncodingKind, Url};
+use tracing::debug;
+
+/// Location of the frozen state relative to the workspace root
+pub const STATE_FILE: &str = ".sieve-lsp/state.json";
+
+/// Version of the snapshot layout; snapshots of other versions are ignored
+pub const STATE_FORMAT: u32 = 2;
+
+/// Location of the per-script index cache relative to the workspace root
+pub const CACHE_DIR: &str = ".sieve-lsp/cache";
+
+/// What the index remembers about one script
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedScript {
+    /// Modification time in nanoseconds since the Unix epoch
+    pub modified: u64,
+    pub size: u64,
+    /// Hash of the text, see [`content_hash`]
+    pub content_hash: u64,
+    /// Script names of its include commands, resolved when the graph is walked
+    pub includes: Vec<String>,
+    /// Outline of the script, in character columns
+    pub symbols: Vec<FlatSymbol>,
+    /// Whether the text is plain ASCII, so character columns are client columns too
+    pub ascii: bool,
+}
+
+/// Include structure of all scripts in a workspace
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceIndex {
+    pub scripts: BTreeMap<PathBuf, IndexedScript>,
+}
+
+/// Modification time and size of a file, `None` when it cannot be read
+fn fingerprint(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((modified.as_nanos() as u64, metadata.len()))
+}
+
+fn index_script(path: &Path) -> Option<IndexedScript> {
+    let (modified, size) = fingerprint(path)?;
+    let text = std::fs::read_to_string(path).ok()?;
+    Some(index_text(modified, size, &text))
+}
+
+fn index_text(modified: u64, size: u64, text: &str) -> IndexedScript {
+    let script = parser::parse(text);
+    IndexedScript {
+        modified,
+        size,
+        content_hash: content_hash(text),
+        includes: include::includes_of(&script)
+            .into_iter()
+            .map(|include| include.name)
+            .collect(),
+        symbols: outline::flatten(outline::document_symbols(text, &script)),
+        ascii: text.is_ascii(),
+    }
+}
+
+/// Index a script through the cache
+/// Returns the script and whether it had to be parsed.
+fn index_cached(path: &Path, cache: &IndexCache) -> Option<(IndexedScript, bool)> {
+    let (modified, size) = fingerprint(path)?;
+    let text = std::fs::read_to_string(path).ok()?;
+    if let Some(cached) = cache.load(content_hash(&text)) {
+        return Some((IndexedScript { modified, size, ..cached }, false));
+    }
+    let indexed = index_text(modified, size, &text);
+    if let Err(err) = cache.store(&indexed) {
+        debug!("Failed to cache the index of {}: {}", path.display(), err);
+    }
+    Some((indexed, true))
+}
+
+/// Indexed scripts stored by content hash, shared by all scripts with the same text
+pub struct IndexCache {
+    dir: PathBuf,
+}
+
+/// A cache entry; entries of other layouts are ignored like snapshots
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    format: u32,
+    script: IndexedScript,
+}
+
+impl IndexCache {
+    /// The cache of a workspace
+    pub fn new(root: &Path) -> Self {
+        IndexCache {
+            dir: root.join(CACHE_DIR),
+        }
+    }
+
+    fn path(&self, hash: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", hash))
+    }
+
+    /// The indexed script with this content hash, if it is cached
+    pub fn load(&self, hash: u64) -> Option<IndexedScript> {
+        let content = std::fs::read_to_string(self.path(hash)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&content).ok()?;
+        (entry.format == STATE_FORMAT && entry.script.content_hash == hash).then_some(entry.script)
+    }
+
+    /// Cache an indexed script under its content hash
+    pub fn store(&self, script: &IndexedScript) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            format: STATE_FORMAT,
+            script: script.clone(),
+        };
+        std::fs::write(self.path(script.content_hash), serde_json::to_string(&entry)?)
+    }
+
+    /// Remove the entries of contents no script has anymore
+    /// Returns the number of removed entries.
+    pub fn prune(&self, keep: &BTreeSet<u64>) -> usize {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let hash = path
+                    .file_stem()
+                    .and_then(|stem| u64::from_str_radix(&stem.to_string_lossy(), 16).ok());
+                hash.is_none_or(|hash| !keep.contains(&hash))
+            })
+            .filter(|path| std::fs::remove_file(path).is_ok())
+            .count()
+    }
+}
+
+impl WorkspaceIndex {
+    /// Bring the index up to date with the scripts on disk
+    /// Returns the number of scripts that had to be parsed.
+    pub fn refresh(&mut self, root: &Path) -> usize {
+        self.refresh_with(root, None, &|_, _| {})
+    }
+
+    /// Bring the index up to date, taking unchanged contents from the cache
+    /// Cache entries of contents no longer in the workspace are removed. `progress` is called
+    /// from the scanning threads with the number of scripts indexed so far and the number to
+    /// index. Returns the number of scripts that had to be parsed.
+    pub fn refresh_cached(
+        &mut self,
+        root: &Path,
+        cache: &IndexCache,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> usize {
+        let parsed = self.refresh_with(root, Some(cache), progress);
+        let hashes = self.scripts.values().map(|s| s.content_hash).collect();
+        let pruned = cache.prune(&hashes);
+        if pruned > 0 {
+            debug!("Pruned {} stale index cache entries", pruned);
+        }
+        parsed
+    }
+
+    /// Scripts changed since they were indexed are read and parsed in parallel
+    fn refresh_with(
+        &mut self,
+        root: &Path,
+        cache: Option<&IndexCache>,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> usize {
+        let scripts = include::workspace_scripts(root);
+        let present: BTreeSet<&PathBuf> = scripts.iter().collect();
+        self.scripts.retain(|path, _| present.contains(path));
+
+        let stale: Vec<&PathBuf> = scripts
+            .iter()
+            .filter(|path| {
+                !self.scripts.get(*path).is_some_and(|indexed| {
+                    fingerprint(path) == Some((indexed.modified, indexed.size))
+                })
+            })
+            .collect();
+        let done = AtomicUsize::new(0);
+        let results: Vec<(&PathBuf, Option<(IndexedScript, bool)>)> = stale
+            .par_iter()
+            .map(|path| {
+                let indexed = match cache {
+                    Some(cache) => index_cached(path, cache),
+                    None => index_script(path).map(|indexed| (indexed, true)),
+                };
+                progress(done.fetch_add(1, Ordering::Relaxed) + 1, stale.len());
+                (*path, indexed)
+            })
+            .collect();
+
+        let mut parsed = 0;
+        for (path, indexed) in results {
+            match indexed {
+                Some((indexed, fresh)) => {
+                    parsed += usize::from(fresh);
+                    self.scripts.insert(path.clone(), indexed);
+                }
+                None => {
+                    parsed += 1;
+                    self.scripts.remove(path);
+                }
+            }
+        }
+        parsed
+    }
+
+    /// Drop a script changed outside the editor, so the next refresh reads it again
+    pub fn invalidate(&mut self, path: &Path) {
+        let canonical = path.canonicalize().ok();
+        self.scripts.retain(|indexed, _| {
+            indexed != path && (canonical.is_none() || indexed.canonicalize().ok() != canonical)
+        });
+    }
+
+    /// Include names of a script, from the index or read from disk for scripts outside of it
+    fn includes(&self, path: &Path) -> Vec<String> {
+        match self.scripts.get(path) {
+            Some(indexed) => indexed.includes.clone(),
+            None => index_script(path).map(|s| s.includes).unwrap_or_default(),
+        }
+    }
+
+    /// Scripts reachable from the entry points through includes (canonical paths)
+    pub fn reachable(&self, entry_points: &[PathBuf]) -> BTreeSet<PathBuf> {
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<PathBuf> = entry_points.to_vec();
+        while let Some(path) = pending.pop() {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if !reached.insert(canonical) {
+                continue;
+            }
+            for name in self.includes(&path) {
+                if let Some(target) = include::resolve_include(&path, &name) {
+                    pending.push(target);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Indexed scripts that no entry point uses, directly or through includes
+    pub fn orphans(&self, entry_points: &[PathBuf]) -> Vec<PathBuf> {
+        let reached = self.reachable(entry_points);
+        self.scripts
+            .keys()
+            .filter(|path| {
+                let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                !reached.contains(&canonical)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// An open document at the time of the snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentState {
+    pub uri: Url,
+    pub version: i32,
+    /// Hash of the text, see [`content_hash`]
+    pub content_hash: u64,
+    /// Findings of the external linter, in client positions
+    #[serde(default)]
+    pub external_diagnostics: Vec<Diagnostic>,
+}
+
+/// Session parameters negotiated with the client
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionCapabilities {
+    pub position_encoding: PositionEncodingKind,
+}
+
+/// Everything persisted between sessions of a workspace
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrozenState {
+    pub format: u32,
+    /// Workspace the state belongs to
+    pub root: PathBuf,
+    pub capabilities: SessionCapabilities,
+    #[serde(default)]
+    pub documents: Vec<DocumentState>,
+    #[serde(default)]
+    pub index: WorkspaceIndex,
+}
+
+impl FrozenState {
+    /// Read the state frozen in a workspace
+    /// Returns `None` when there is none, or it is unreadable, of another format or was
+    /// written for a different workspace (for example a copied directory).
+    pub fn load(root: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(root.join(STATE_FILE)).ok()?;
+        let state: Self = serde_json::from_str(&content).ok()?;
+        (state.format == STATE_FORMAT && state.root == root).then_some(state)
+    }
+
+    /// Write the state into its workspace
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.root.join(STATE_FILE);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// The frozen state of a document, if its text is unchanged
+    pub fn document(&self, uri: &Url, text: &str) -> Option<&DocumentState> {
+        let hash = content_hash(text);
+        self.documents
+            .iter()
+            .find(|document| &document.uri == uri && document.content_hash == hash)
+    }
+}
+
+/// FNV-1a hash of a text, stable across builds unlike the standard library hasher
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
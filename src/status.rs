@@ -0,0 +1,58 @@
+// ================================================================================================
+// SERVER STATUS
+// ================================================================================================
+//
+// Figures for a status-bar item and for diagnosing slow validation: open documents, the size
+// of the workspace index, how long the last validation of each document took and the memory
+// the server holds. Clients ask for them with `sieve/statistics`, or receive them every
+// `status_interval` seconds as a `sieve/status` notification.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+use tower_lsp::lsp_types::notification::Notification;
+
+/// Custom request returning the current [`ServerStatus`]
+pub const STATISTICS_METHOD: &str = "sieve/statistics";
+
+/// Notification the server sends periodically, carrying a [`ServerStatus`]
+pub enum StatusNotification {}
+
+impl Notification for StatusNotification {
+    type Params = ServerStatus;
+    const METHOD: &'static str = "sieve/status";
+}
+
+/// Statistics of a running server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub version: String,
+    pub uptime_secs: u64,
+    pub open_documents: usize,
+    /// Workspace scripts in the index
+    pub indexed_scripts: usize,
+    /// Last validation of each open document, slowest first
+    pub validations: Vec<ValidationTiming>,
+    /// Resident memory in bytes, where the platform reports it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resident_memory: Option<u64>,
+    /// Internal errors retained for `sieve/lastError`
+    pub internal_errors: usize,
+}
+
+/// How long the last validation of a document took
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationTiming {
+    pub uri: Url,
+    pub duration_ms: f64,
+}
+
+/// Resident memory of the server process in bytes
+/// Read from `/proc`, so only available on Linux.
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
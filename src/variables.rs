@@ -0,0 +1,355 @@
+// ================================================================================================
+// VARIABLES (RFC 5229)
+// ================================================================================================
+//
+// Helpers for `${...}` variable references, including the numbered match variables `${0}` to
+// `${9}` that are filled by the most recent `:matches` or `:regex` test.
+
+use crate::parser::{self, Argument, Command, Script, StringLiteral, Test};
+use tower_lsp::lsp_types::Position;
+
+/// Modifiers of the `set` command with their precedence (RFC 5229 section 4.1)
+/// Two modifiers of the same precedence cannot be combined.
+pub const SET_MODIFIERS: &[(&str, u8)] = &[
+    (":lower", 40),
+    (":upper", 40),
+    (":lowerfirst", 30),
+    (":upperfirst", 30),
+    (":quotewildcard", 20),
+    (":quoteregex", 20),
+    (":encodeurl", 15),
+    (":length", 10),
+];
+
+/// A `${...}` reference inside a string value
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableReference {
+    /// The name between the braces, e.g. `subject` or `1`
+    pub name: String,
+    /// Character offsets of the whole reference within the value, end exclusive
+    pub start: usize,
+    pub end: usize,
+}
+
+impl VariableReference {
+    /// The index of a numbered match variable such as `${1}`
+    pub fn match_index(&self) -> Option<usize> {
+        self.name
+            .chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| self.name.parse().ok())
+            .flatten()
+    }
+}
+
+/// Whether a name can be assigned with `set`: a letter or underscore followed by letters,
+/// digits and underscores
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// All variable references in a string value
+/// Namespaced names like `${env.user}` are included; anything else in braces is literal text.
+pub fn references(value: &str) -> Vec<VariableReference> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut found = Vec::new();
+    let mut idx = 0;
+    while idx + 1 < chars.len() {
+        if chars[idx] != '$' || chars[idx + 1] != '{' {
+            idx += 1;
+            continue;
+        }
+        let Some(close) = (idx + 2..chars.len()).find(|&i| chars[i] == '}') else {
+            break;
+        };
+        let name: String = chars[idx + 2..close].iter().collect();
+        let numbered = !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+        if numbered || (!name.is_empty() && name.split('.').all(is_variable_name)) {
+            found.push(VariableReference {
+                name,
+                start: idx,
+                end: close + 1,
+            });
+            idx = close + 1;
+        } else {
+            idx += 2;
+        }
+    }
+    found
+}
+
+/// The variable name string of a `set` command (the first of its two positional strings)
+pub fn set_variable(command: &Command) -> Option<&StringLiteral> {
+    command.arguments.iter().find_map(|a| match a {
+        Argument::String(s) => Some(s),
+        _ => None,
+    })
+}
+
+/// Find the `${name}` reference surrounding a character position in a line
+/// Returns the variable name and the character range of the whole reference
+pub fn variable_reference_at(line: &str, character: usize) -> Option<(String, usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    let cursor = character.min(chars.len());
+
+    // Walk back to the nearest "${" that is not closed before the cursor
+    let mut start = None;
+    let mut idx = cursor;
+    while idx > 0 {
+        idx -= 1;
+        if chars[idx] == '}' && idx + 1 < cursor {
+            return None;
+        }
+        if chars[idx] == '$' && chars.get(idx + 1) == Some(&'{') {
+            start = Some(idx);
+            break;
+        }
+    }
+    let start = start?;
+    let end = (start + 2..chars.len()).find(|&i| chars[i] == '}')?;
+    if cursor > end {
+        return None;
+    }
+    let name: String = chars[start + 2..end].iter().collect();
+    Some((name.trim().to_string(), start, end + 1))
+}
+
+/// A `:matches` or `:regex` test that populates the numbered match variables
+#[derive(Debug, Clone)]
+pub struct MatchSource<'a> {
+    pub test: &'a Test,
+    /// Either `:matches` or `:regex`
+    pub match_type: &'static str,
+    /// The key list of the test (the patterns)
+    pub patterns: Vec<&'a StringLiteral>,
+}
+
+/// The last `:matches`/`:regex` test that precedes a position in the script
+pub fn match_source_before(script: &Script, position: Position) -> Option<MatchSource<'_>> {
+    script
+        .all_tests()
+        .into_iter()
+        .filter(|t| t.range.end <= position)
+        .filter_map(|test| {
+            let match_type = if test.arguments.iter().any(|a| a.tag() == Some(":matches")) {
+                ":matches"
+            } else if test.arguments.iter().any(|a| a.tag() == Some(":regex")) {
+                ":regex"
+            } else {
+                return None;
+            };
+            let patterns = test.arguments.last()?.strings();
+            Some(MatchSource {
+                test,
+                match_type,
+                patterns,
+            })
+        })
+        .next_back()
+}
+
+/// Locate the wildcard (`*`/`?`) or capture group that fills match variable `index`
+/// Returns the character offset within the pattern and the matched construct
+pub fn match_group_position(pattern: &str, index: usize, regex: bool) -> Option<(usize, String)> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut count = 0;
+    let mut in_bracket = false;
+    let mut idx = 0;
+    while idx < chars.len() {
+        let c = chars[idx];
+        if c == '\\' {
+            idx += 2;
+            continue;
+        }
+        let opens_group = if regex {
+            match c {
+                '[' => in_bracket = true,
+                ']' => in_bracket = false,
+                _ => {}
+            }
+            !in_bracket && c == '('
+        } else {
+            c == '*' || c == '?'
+        };
+        if opens_group {
+            count += 1;
+            if count == index {
+                let construct = if regex {
+                    // Show the group up to its matching parenthesis
+                    let mut depth = 0;
+                    let mut end = idx;
+                    for (j, &g) in chars.iter().enumerate().skip(idx) {
+                        match g {
+                            '(' => depth += 1,
+                            ')' => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    end = j;
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    chars[idx..=end.max(idx)].iter().collect()
+                } else {
+                    c.to_string()
+                };
+                return Some((idx, construct));
+            }
+        }
+        idx += 1;
+    }
+    None
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (1, 11) | (2, 12) | (3, 13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Markdown hover text for a numbered match variable at `position`
+pub fn match_variable_hover(script: &Script, position: Position, index: usize) -> String {
+    let mut doc = format!("**${{{}}}** - match variable (RFC 5229)\n\n", index);
+    let Some(source) = match_source_before(script, position) else {
+        doc.push_str(&format!(
+            "No preceding `:matches` or `:regex` test defines `${{{}}}`; it expands to an empty string.",
+            index
+        ));
+        return doc;
+    };
+
+    doc.push_str(&format!(
+        "Set by `{} {}` on line {}\n\n",
+        source.test.name,
+        source.match_type,
+        source.test.range.start.line + 1
+    ));
+
+    if index == 0 {
+        doc.push_str("`${0}` holds the entire matched value.");
+        return doc;
+    }
+
+    let regex = source.match_type == ":regex";
+    let unit = if regex { "capture group" } else { "wildcard" };
+    for pattern in &source.patterns {
+        match match_group_position(&pattern.value, index, regex) {
+            Some((offset, construct)) => doc.push_str(&format!(
+                "- `\"{}\"`: {} {} `{}` at offset {}\n",
+                pattern.value,
+                ordinal(index),
+                unit,
+                construct,
+                offset
+            )),
+            None => doc.push_str(&format!(
+                "- `\"{}\"`: has no {} {}; `${{{}}}` will be empty\n",
+                pattern.value,
+                ordinal(index),
+                unit,
+                index
+            )),
+        }
+    }
+    doc
+}
+
+/// Markdown hover text for a named variable reference at `position`
+/// Shows the `set` statement that last assigns the variable before the reference, with its
+/// modifiers in the order they are applied.
+pub fn named_variable_hover(text: &str, script: &Script, position: Position, name: &str) -> String {
+    let mut doc = format!("**${{{}}}** - variable (RFC 5229)\n\n", name);
+    let lookup = match name.split_once('.') {
+        Some((namespace, local)) if namespace.eq_ignore_ascii_case("global") => local,
+        Some((namespace, _)) => {
+            doc.push_str(&format!(
+                "Variable of the `{}` namespace, provided by the server rather than `set`",
+                namespace
+            ));
+            return doc;
+        }
+        None => name,
+    };
+
+    let (before, after): (Vec<&Command>, Vec<&Command>) = script
+        .all_commands()
+        .into_iter()
+        .filter(|command| command.name == "set")
+        .filter(|command| {
+            set_variable(command).is_some_and(|v| v.value.eq_ignore_ascii_case(lookup))
+        })
+        .partition(|command| command.range.end <= position);
+
+    let Some(last) = before.last() else {
+        let global = script
+            .all_commands()
+            .into_iter()
+            .filter(|command| command.name == "global")
+            .find(|command| {
+                command
+                    .arguments
+                    .iter()
+                    .flat_map(|a| a.strings())
+                    .any(|s| s.value.eq_ignore_ascii_case(lookup))
+            });
+        match (after.first(), global) {
+            (_, Some(command)) => doc.push_str(&format!(
+                "Declared `global` on line {}; its value comes from the including script",
+                command.range.start.line + 1
+            )),
+            (Some(command), None) => doc.push_str(&format!(
+                "Only set after this reference, on line {}; `${{{}}}` is empty here",
+                command.range.start.line + 1,
+                name
+            )),
+            (None, None) => doc.push_str(&format!(
+                "No `set` statement defines `${{{}}}`; it expands to an empty string.",
+                name
+            )),
+        }
+        return doc;
+    };
+
+    let start = parser::offset_at(text, last.range.start);
+    let end = parser::offset_at(text, last.range.end);
+    doc.push_str(&format!(
+        "Set on line {}\n\n```sieve\n{}\n```\n",
+        last.range.start.line + 1,
+        text[start..end].trim_end()
+    ));
+
+    // Higher precedence modifiers are applied first (RFC 5229 section 4.1)
+    let mut modifiers: Vec<(&str, u8)> = last
+        .arguments
+        .iter()
+        .filter_map(|argument| argument.tag())
+        .filter_map(|tag| SET_MODIFIERS.iter().find(|(m, _)| m.eq_ignore_ascii_case(tag)))
+        .copied()
+        .collect();
+    modifiers.sort_by_key(|(_, precedence)| std::cmp::Reverse(*precedence));
+    if !modifiers.is_empty() {
+        let order: Vec<String> = modifiers.iter().map(|(m, _)| format!("`{}`", m)).collect();
+        doc.push_str(&format!("\nModifiers applied in order: {}\n", order.join(", ")));
+    }
+
+    let others: Vec<String> = before[..before.len() - 1]
+        .iter()
+        .chain(&after)
+        .map(|command| (command.range.start.line + 1).to_string())
+        .collect();
+    if !others.is_empty() {
+        doc.push_str(&format!("\nAlso set on line {}\n", others.join(", ")));
+    }
+    doc
+}
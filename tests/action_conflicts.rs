@@ -0,0 +1,35 @@
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn test_reject_and_fileinto_conflict() {
+    let text = "require [\"fileinto\", \"reject\"];\nfileinto \"Junk\";\nreject \"go away\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let conflicts = with_code(&diagnostics, "conflicting-actions");
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].range.start.line, 2);
+
+    let related = conflicts[0].related_information.as_ref().unwrap();
+    assert_eq!(related.len(), 2);
+    assert_eq!(related[0].location.range.start.line, 1);
+    assert_eq!(related[1].location.range.start.line, 2);
+}
+
+#[tokio::test]
+async fn test_multiple_vacations_and_keep_after_discard() {
+    let text = "vacation \"away\";\nvacation \"still away\";\ndiscard;\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    let lines: Vec<u32> = with_code(&diagnostics, "conflicting-actions")
+        .iter()
+        .map(|d| d.range.start.line)
+        .collect();
+    assert_eq!(lines, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn test_actions_in_separate_branches_do_not_conflict() {
+    let text = "if true {\n  vacation \"a\";\n} else {\n  vacation \"b\";\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "conflicting-actions").is_empty());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::*;
+use sieve_language_server::sieve::check_address;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_check_address() {
+    assert!(check_address("user@example.com").is_ok());
+    assert!(check_address("first.last+tag@mail.example.co.uk").is_ok());
+    assert!(check_address("\"john doe\"@example.com").is_ok());
+    assert!(check_address("user@[192.0.2.1]").is_ok());
+
+    let problem = |value: &str| {
+        let error = check_address(value).unwrap_err();
+        (error.message, error.start, error.end)
+    };
+    assert_eq!(problem(""), ("The address is empty".to_string(), 0, 0));
+    assert_eq!(
+        problem("john  doe@example.com"),
+        ("Addresses cannot contain spaces".to_string(), 4, 6)
+    );
+    assert_eq!(
+        problem("example.com"),
+        ("The address is missing the '@' before its domain".to_string(), 0, 11)
+    );
+    assert_eq!(problem("a@b@example.com"), ("Addresses contain a single '@'".to_string(), 3, 4));
+    assert_eq!(
+        problem("@example.com"),
+        ("The address is missing the local part before '@'".to_string(), 0, 1)
+    );
+    assert_eq!(
+        problem("user@"),
+        ("The address is missing the domain after '@'".to_string(), 4, 5)
+    );
+    assert_eq!(
+        problem("<user@example.com>"),
+        ("'<' is not allowed in an address".to_string(), 0, 1)
+    );
+    assert_eq!(
+        problem("user@example_mail.com"),
+        ("'_' is not allowed in a domain".to_string(), 12, 13)
+    );
+    assert_eq!(
+        problem("user@example...com"),
+        ("The domain contains consecutive dots".to_string(), 12, 15)
+    );
+    assert_eq!(
+        problem("user@example.com."),
+        ("The domain cannot start or end with a dot".to_string(), 16, 17)
+    );
+    assert_eq!(
+        problem(".user@example.com"),
+        ("The local part cannot start or end with a dot".to_string(), 0, 1)
+    );
+    assert_eq!(
+        problem("\"john\"doe@example.com"),
+        ("A quoted local part must be followed by '@'".to_string(), 6, 9)
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_redirect_targets() {
+    let text = "redirect \"user@example..com\";\n\
+                redirect \"user example.com\";\n\
+                redirect \"${target}\";\n\
+                redirect \"user@example.com\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-address");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(found[0].message, "The domain contains consecutive dots");
+    assert_eq!(found[0].range, Range::new(Position::new(0, 22), Position::new(0, 24)));
+    assert_eq!(found[1].message, "Addresses cannot contain spaces");
+    assert_eq!(found[1].range, Range::new(Position::new(1, 14), Position::new(1, 15)));
+}
+
+#[tokio::test]
+async fn test_invalid_vacation_addresses() {
+    let text = "require \"vacation\";\n\
+                vacation :addresses [\"me@example.com\", \"me.example.com\", \"\"] \"Away\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-address");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "The address is missing the '@' before its domain");
+    assert_eq!(found[0].range, Range::new(Position::new(1, 40), Position::new(1, 54)));
+    assert_eq!(found[1].message, "The address is empty");
+    assert_eq!(found[1].range, Range::new(Position::new(1, 57), Position::new(1, 59)));
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn replacement(diagnostic: &Diagnostic) -> &str {
+    diagnostic.data.as_ref().unwrap()["replacement"].as_str().unwrap()
+}
+
+#[tokio::test]
+async fn test_display_name_in_address_key() {
+    let diagnostics =
+        diagnostics_for("if address :is \"from\" \"John <j@x.com>\" {\n    keep;\n}\n").await;
+    let found = with_code(&diagnostics, "address-value");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(0, 22));
+    assert_eq!(replacement(found[0]), "\"j@x.com\"");
+}
+
+#[tokio::test]
+async fn test_full_address_with_address_part() {
+    let text = "if address :domain \"from\" [\"j@x.com\", \"y.org\"] {\n    keep;\n}\nif address :localpart :is \"to\" \"<me@x.com>\" {\n    keep;\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "address-value");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(replacement(found[0]), "\"x.com\"");
+    assert!(found[0].message.contains(":domain"));
+    assert_eq!(replacement(found[1]), "\"me\"");
+}
+
+#[tokio::test]
+async fn test_plain_addresses_and_other_tests_are_fine() {
+    let text = "require \"variables\";\nif address :all \"from\" \"j@x.com\" { keep; }\nif address :domain \"from\" \"${d}@x\" { keep; }\nif header :contains \"from\" \"John <j@x.com>\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "address-value").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_quick_fix_replaces_key() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if address :domain \"from\" \"j@x.com\" {\n    keep;\n}\n";
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), text.to_string(), 1),
+    );
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let diagnostic = with_code(&diagnostics, "address-value")[0].clone();
+
+    let actions = server
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: diagnostic.range,
+            context: CodeActionContext {
+                diagnostics: vec![diagnostic.clone()],
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(actions.len(), 1);
+    let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+        panic!("expected a code action");
+    };
+    assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+    assert_eq!(edits[0].range, diagnostic.range);
+    assert_eq!(edits[0].new_text, "\"x.com\"");
+}
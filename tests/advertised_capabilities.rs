@@ -0,0 +1,72 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::profile::parse_capabilities;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_parse_capabilities() {
+    let list = [
+        "fileinto reject envelope".to_string(),
+        "\"vacation\"".to_string(),
+        "FileInto".to_string(),
+    ];
+    assert_eq!(parse_capabilities(&list), ["fileinto", "reject", "envelope", "vacation"]);
+}
+
+#[tokio::test]
+async fn test_requires_limited_to_advertised_set() {
+    let text = "require [\"fileinto\", \"Vacation\", \"editheader\", \"vnd.example.thing\"];\nkeep;\n";
+    let settings = json!({ "capabilities": ["fileinto", "vacation", "vnd.example.thing"] });
+    let (service, uri) = server_with(settings, text).await;
+    let diagnostics = service.inner().validate_document(&uri).await.unwrap();
+    let messages: Vec<&str> = with_code(&diagnostics, "unsupported-feature")
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(messages, ["The server does not advertise the \"editheader\" extension"]);
+
+    // Without a list every known extension is accepted
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_advertised_list_takes_precedence_over_profile() {
+    let text = "require \"reject\";\nkeep;\n";
+    let settings = json!({ "server_dialect": "proton", "capabilities": ["reject", "fileinto"] });
+    let (service, uri) = server_with(settings, text).await;
+    let diagnostics = service.inner().validate_document(&uri).await.unwrap();
+    let requires: Vec<_> = with_code(&diagnostics, "unsupported-feature")
+        .into_iter()
+        .filter(|d| d.range.start.line == 0)
+        .collect();
+    assert!(requires.is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_extension_completions_follow_advertised_set() {
+    let (service, uri) =
+        server_with(json!({ "capabilities": ["fileinto vnd.example.thing"] }), "").await;
+    let server = service.inner();
+    let mut extensions = Vec::new();
+    for item in server.get_completions(&uri, Position::new(0, 0)).await {
+        if item.kind != Some(CompletionItemKind::MODULE) {
+            continue;
+        }
+        let item = server.resolve_completion(item).await;
+        let Some(Documentation::MarkupContent(doc)) = item.documentation else {
+            panic!("missing documentation");
+        };
+        let description = doc.value.lines().nth(2).unwrap().to_string();
+        extensions.push((item.label, description));
+    }
+    assert_eq!(
+        extensions,
+        [
+            ("\"fileinto\"".to_string(), "File messages into folders (RFC 5228)".to_string()),
+            ("\"vnd.example.thing\"".to_string(), "Extension advertised by the server".to_string()),
+        ]
+    );
+}
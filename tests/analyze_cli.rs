@@ -0,0 +1,88 @@
+use sieve_language_server::cli::analyze_text;
+use sieve_language_server::outline::*;
+use sieve_language_server::parser::parse;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tower_lsp::lsp_types::*;
+
+const SCRIPT: &str = "require [\"fileinto\",\n         \"variables\"];\n# Sort mail\n# by sender\nif address :is \"from\" \"boss@example.com\" {\n    set \"who\" \"boss\";\n    fileinto \"Boss\";\n}\nkeep\n";
+
+#[test]
+fn test_document_symbols() {
+    let symbols = document_symbols(SCRIPT, &parse(SCRIPT));
+    let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["require", "if address :is \"from\" \"boss@example.com\"", "keep"]
+    );
+    assert_eq!(symbols[0].detail.as_deref(), Some("fileinto, variables"));
+
+    let children = symbols[1].children.as_ref().unwrap();
+    assert_eq!(children[0].name, "who");
+    assert_eq!(children[0].kind, SymbolKind::VARIABLE);
+    assert_eq!(children[1].name, "fileinto");
+    assert_eq!(children[1].detail.as_deref(), Some("Boss"));
+}
+
+#[test]
+fn test_folding_ranges() {
+    let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> = folding_ranges(SCRIPT, &parse(SCRIPT))
+        .into_iter()
+        .map(|r| (r.start_line, r.end_line, r.kind))
+        .collect();
+    assert_eq!(
+        ranges,
+        [
+            (0, 1, Some(FoldingRangeKind::Imports)),
+            (2, 3, Some(FoldingRangeKind::Comment)),
+            (4, 6, None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_analyze_text_reports_everything() {
+    let report = analyze_text(SCRIPT).await;
+    assert_eq!(report.position_encoding, PositionEncodingKind::UTF16);
+    assert!(!report.diagnostics.is_empty(), "missing semicolon after keep");
+    assert_eq!(report.symbols.len(), 3);
+    assert_eq!(report.folding_ranges.len(), 3);
+    assert_eq!(report.metrics.rules, 1);
+    assert_eq!(report.metrics.fileinto_targets.get("Boss"), Some(&1));
+}
+
+#[test]
+fn test_analyze_stdin_binary() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["analyze", "--stdin", "--format", "json"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(SCRIPT.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["positionEncoding"], "utf-16");
+    assert_eq!(json["symbols"][0]["name"], "require");
+    assert!(json["diagnostics"].is_array());
+    assert!(json["foldingRanges"].is_array());
+}
+
+#[test]
+fn test_analyze_rejects_unknown_format() {
+    let status = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["analyze", "--stdin", "--format", "xml"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+}
@@ -0,0 +1,90 @@
+use serde_json::json;
+use sieve_language_server::anonymize::anonymize;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::lsp::COMMAND_ANONYMIZE_SCRIPT;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn anonymized(text: &str) -> String {
+    anonymize(text, &parse(text))
+}
+
+#[test]
+fn test_personal_content_is_replaced() {
+    let text = "require [\"fileinto\", \"copy\"];\n\
+                # Mails from my landlord\n\
+                if address :is \"from\" \"Jane.Doe@corp.example\" {\n\
+                \x20   fileinto :copy \"Private/Housing\";\n\
+                } elsif header :contains \"subject\" \"Rent due\" {\n\
+                \x20   redirect \"jane.doe@corp.example\";\n\
+                \x20   fileinto \"Private\";\n\
+                }\n";
+    let result = anonymized(text);
+
+    assert_eq!(
+        result,
+        "require [\"fileinto\", \"copy\"];\n\
+         # comment\n\
+         if address :is \"from\" \"user1@example.com\" {\n\
+         \x20   fileinto :copy \"Folder1/Folder2\";\n\
+         } elsif header :contains \"subject\" \"word1 word2\" {\n\
+         \x20   redirect \"user1@example.com\";\n\
+         \x20   fileinto \"Folder1\";\n\
+         }\n"
+    );
+    assert!(parse(&result).errors.is_empty());
+}
+
+#[test]
+fn test_structure_is_preserved() {
+    let text = "require [\"variables\", \"imap4flags\", \"relational\"];\n\
+                if header :matches \"X-Spam-Level\" \"*secret*\" {\n\
+                \x20   set \"level\" \"${1} stars\";\n\
+                \x20   addflag \"\\\\Seen\";\n\
+                }\n\
+                if header :value \"ge\" :comparator \"i;ascii-numeric\" \"X-Score\" \"10\" { stop; }\n\
+                /* first\n   second */\n\
+                fileinto \"INBOX.Archive\";\n";
+    let result = anonymized(text);
+
+    assert!(result.contains("\"*word1*\""));
+    assert!(result.contains("set \"level\" \"${1} word2\""));
+    assert!(result.contains("addflag \"\\\\Seen\""));
+    assert!(result.contains(":value \"ge\" :comparator \"i;ascii-numeric\" \"X-Score\" \"10\""));
+    assert!(result.contains("/* comment\n */"));
+    assert!(result.contains("fileinto \"INBOX.Folder1\""));
+    assert_eq!(result.lines().count(), text.lines().count());
+}
+
+#[test]
+fn test_multiline_strings_keep_their_lines() {
+    let text = "require \"vacation\";\nvacation :subject \"Away\" text:\nHi there,\n..signed\n.\n;\n";
+    let result = anonymized(text);
+
+    assert_eq!(
+        result,
+        "require \"vacation\";\nvacation :subject \"word1\" text:\nword2 word3,\n..word4\n.\n;\n"
+    );
+}
+
+#[tokio::test]
+async fn test_anonymize_command() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///tmp/anonymize.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), "redirect \"boss@work.example\";\n".to_string(), 1),
+    );
+
+    let result = server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_ANONYMIZE_SCRIPT.to_string(),
+            arguments: vec![json!(uri.to_string())],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(result, Some(json!("redirect \"user1@example.com\";\n")));
+}
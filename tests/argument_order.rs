@@ -0,0 +1,67 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[tokio::test]
+async fn test_tag_after_positional_argument() {
+    let text = "if header \"subject\" :contains \"x\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "argument-order");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        found[0].message,
+        "Tagged argument ':contains' must come before the positional arguments of 'header'"
+    );
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::WARNING));
+    assert_eq!(found[0].range, Range::new(Position::new(0, 10), Position::new(0, 33)));
+    assert_eq!(
+        found[0].data.as_ref().unwrap()["replacement"],
+        ":contains \"subject\" \"x\""
+    );
+}
+
+#[tokio::test]
+async fn test_tags_keep_their_values() {
+    let text = "if header \"to\" :comparator \"i;octet\" :is \"boss@example.com\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "argument-order");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        found[0].data.as_ref().unwrap()["replacement"],
+        ":comparator \"i;octet\" :is \"to\" \"boss@example.com\""
+    );
+
+    // Values of tags are not positional arguments
+    let text = "require \"vacation\";\nvacation :subject \"Away\" :days 7 \"Back soon\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "argument-order").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_commands_are_checked_too() {
+    let text = "require [\"fileinto\", \"copy\"];\nfileinto \"Archive\" :copy;\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "argument-order");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], ":copy \"Archive\"");
+}
+
+#[tokio::test]
+async fn test_strict_mode_reports_errors() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "strict_mode": true })).unwrap();
+    let uri = Url::parse("file:///strict.sieve").unwrap();
+    let text = "if header \"subject\" :contains \"x\" { stop; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let found = with_code(&diagnostics, "argument-order");
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::{blank_comments, parse};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require [\"fileinto\"];\n\
+                      /* Disabled for now:\n\
+                      if header :contains \"subject\" \"spam\" {\n\
+                      \x20   fileinto \"Junk\"\n\
+                      \x20   vacation \"away\";\n\
+                      }\n\
+                      */\n\
+                      fileinto \"Archive\"; /* trailing { */ # and \"hash\"\n";
+
+#[test]
+fn test_blank_comments_keeps_code_and_columns() {
+    let script = parse(SCRIPT);
+    let code = blank_comments(SCRIPT, &script.comments);
+    let lines: Vec<&str> = code.lines().collect();
+    assert_eq!(lines.len(), SCRIPT.lines().count());
+    assert_eq!(lines[0], "require [\"fileinto\"];");
+    assert!(lines[1..7].iter().all(|line| line.trim().is_empty()), "{:?}", lines);
+    assert_eq!(lines[7].trim_end(), "fileinto \"Archive\";");
+    assert_eq!(lines[7].chars().count(), SCRIPT.lines().nth(7).unwrap().chars().count());
+}
+
+#[test]
+fn test_comment_at() {
+    let script = parse(SCRIPT);
+    assert!(script.comment_at(Position::new(3, 6)).is_some_and(|c| c.bracket));
+    assert!(script.comment_at(Position::new(7, 0)).is_none());
+    // Typing at the end of a hash comment is still inside it
+    let end = SCRIPT.lines().nth(7).unwrap().chars().count() as u32;
+    assert!(script.comment_at(Position::new(7, end)).is_some_and(|c| !c.bracket));
+    // Right after `*/` is code again
+    assert!(script.comment_at(Position::new(7, 36)).is_none());
+}
+
+#[tokio::test]
+async fn test_commented_out_rules_are_not_validated() {
+    let diagnostics = diagnostics_for(SCRIPT).await;
+    assert!(with_code(&diagnostics, "missing-semicolon").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(
+        diagnostics
+            .iter()
+            .all(|d| d.severity != Some(DiagnosticSeverity::ERROR)),
+        "{:?}",
+        diagnostics
+    );
+}
+
+#[tokio::test]
+async fn test_no_hover_or_completion_in_comments() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///comments.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+
+    assert!(server.get_completions(&uri, Position::new(3, 8)).await.is_empty());
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(4, 6),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(hover, None);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+
+fn bracket_errors(text: &str) -> Vec<(Position, String)> {
+    parse(text)
+        .errors
+        .into_iter()
+        .filter(|e| e.code == "unmatched-bracket")
+        .map(|e| (e.range.start, e.message))
+        .collect()
+}
+
+#[test]
+fn test_balanced_brackets() {
+    let text = "if anyof (true, false) {\n  if true {\n    keep;\n  }\n}\n";
+    assert!(bracket_errors(text).is_empty());
+}
+
+#[test]
+fn test_missing_inner_brace_points_at_inner_opener() {
+    let text = "if true {\n  if false {\n    keep;\n}\n";
+    let errors = bracket_errors(text);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, Position::new(1, 11));
+    assert!(errors[0].1.contains("Unclosed '{'"));
+}
+
+#[test]
+fn test_extra_and_mismatched_closers() {
+    assert_eq!(bracket_errors("keep;\n}\n")[0].0, Position::new(1, 0));
+    let errors = bracket_errors("if anyof (true {\n  keep;\n}\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, Position::new(0, 9));
+}
+
+#[tokio::test]
+async fn test_bracket_diagnostic_links_the_pair() {
+    let diagnostics = diagnostics_for("if true {\n  if false {\n    keep;\n}\n").await;
+    let errors = with_code(&diagnostics, "unmatched-bracket");
+    assert_eq!(errors.len(), 1);
+    let related = errors[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start, Position::new(3, 0));
+}
@@ -0,0 +1,178 @@
+use sieve_language_server::cli::{check_files, format_diagnostic};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tower_lsp::lsp_types::*;
+
+const CLEAN: &str = "require \"fileinto\";\nif header :contains \"subject\" \"x\" { fileinto \"X\"; }\n";
+const BROKEN: &str = "if header :contains \"subject\" \"x\" {\n    keep\n}\n";
+
+#[test]
+fn test_format_diagnostic() {
+    let diagnostic = Diagnostic {
+        range: Range::new(Position::new(2, 4), Position::new(2, 12)),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String("match-type".to_string())),
+        message: "Something odd".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        format_diagnostic("filter.sieve", &diagnostic),
+        "filter.sieve:3:5: warning[match-type]: Something odd"
+    );
+}
+
+#[tokio::test]
+async fn test_check_files_uses_character_columns() {
+    let files = vec![
+        ("clean.sieve".to_string(), CLEAN.to_string()),
+        (
+            "emoji.sieve".to_string(),
+            "if anyof (header :is \"😀\" \"x\", size :over 10Q) { stop; }\n".to_string(),
+        ),
+    ];
+    let results = check_files(&files, &Default::default()).await;
+    assert_eq!(results[0].0, "clean.sieve");
+    assert!(results[0].1.is_empty(), "{:?}", results[0].1);
+    let invalid = results[1]
+        .1
+        .iter()
+        .find(|d| d.code == Some(NumberOrString::String("invalid-number".to_string())))
+        .expect("invalid number");
+    assert_eq!(invalid.range.start, Position::new(0, 41));
+}
+
+fn run(args: &[&str], stdin: Option<&str>) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    if let Some(text) = stdin {
+        input.write_all(text.as_bytes()).unwrap();
+    }
+    drop(input);
+    let output = child.wait_with_output().unwrap();
+    (output.status.code(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_check_binary_exit_codes() {
+    let dir = std::env::temp_dir().join(format!("sieve-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let clean = dir.join("clean.sieve");
+    let broken = dir.join("broken.sieve");
+    std::fs::write(&clean, CLEAN).unwrap();
+    std::fs::write(&broken, BROKEN).unwrap();
+    let clean = clean.to_str().unwrap();
+    let broken = broken.to_str().unwrap();
+
+    let (code, output) = run(&["check", clean], None);
+    assert_eq!(code, Some(0), "{}", output);
+    assert!(output.ends_with("0 error(s), 0 warning(s) in 1 file(s)\n"), "{}", output);
+
+    let (code, output) = run(&["check", clean, broken], None);
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(
+        output.contains(&format!("{}:2:8: error[missing-semicolon]: ", broken)),
+        "{}",
+        output
+    );
+    assert!(output.contains("in 2 file(s)"), "{}", output);
+
+    let missing = dir.join("missing.sieve");
+    let (code, output) = run(&["check", missing.to_str().unwrap()], None);
+    assert_eq!(code, Some(1));
+    assert!(output.contains("cannot read file"), "{}", output);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_stdin_and_usage() {
+    let (code, output) = run(&["check", "--stdin"], Some(BROKEN));
+    assert_eq!(code, Some(1));
+    assert!(output.starts_with("<stdin>:2:8: error[missing-semicolon]"), "{}", output);
+
+    // Warnings only fail the run when asked to
+    let warning = "if header :matches \"subject\" \"invoice\" { stop; }\n";
+    assert_eq!(run(&["check", "--stdin"], Some(warning)).0, Some(0));
+    assert_eq!(run(&["check", "--deny-warnings", "--stdin"], Some(warning)).0, Some(1));
+
+    assert_eq!(run(&["check"], None).0, Some(2));
+    assert_eq!(run(&["check", "--stdin", "a.sieve"], None).0, Some(2));
+    assert_eq!(run(&["check", "--format", "xml"], None).0, Some(2));
+}
+
+#[test]
+fn test_check_json_format() {
+    let (code, output) = run(&["check", "--format", "json", "--stdin"], Some(BROKEN));
+    assert_eq!(code, Some(1));
+    let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(report["errors"], 1);
+    assert_eq!(report["files"][0]["path"], "<stdin>");
+    let diagnostic = &report["files"][0]["diagnostics"][0];
+    assert_eq!(diagnostic["code"], "missing-semicolon");
+    assert_eq!(diagnostic["range"]["start"]["line"], 1);
+}
+
+#[test]
+fn test_check_sarif_format() {
+    let dir = std::env::temp_dir().join(format!("sieve-sarif-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let broken = dir.join("broken.sieve");
+    std::fs::write(&broken, BROKEN).unwrap();
+    let missing = dir.join("missing.sieve");
+
+    let (code, output) = run(
+        &[
+            "check",
+            "--format",
+            "sarif",
+            broken.to_str().unwrap(),
+            missing.to_str().unwrap(),
+        ],
+        None,
+    );
+    assert_eq!(code, Some(1));
+    let sarif: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let sarif_run = &sarif["runs"][0];
+    assert_eq!(sarif_run["tool"]["driver"]["name"], "sieve-lsp");
+    assert_eq!(sarif_run["columnKind"], "unicodeCodePoints");
+    assert_eq!(sarif_run["tool"]["driver"]["rules"][0]["id"], "missing-semicolon");
+
+    let result = &sarif_run["results"][0];
+    assert_eq!(result["ruleId"], "missing-semicolon");
+    assert_eq!(result["ruleIndex"], 0);
+    assert_eq!(result["level"], "error");
+    let location = &result["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], broken.to_str().unwrap());
+    assert_eq!(location["region"]["startLine"], 2);
+    assert_eq!(location["region"]["startColumn"], 8);
+
+    let notification = &sarif_run["invocations"][0]["toolExecutionNotifications"][0];
+    assert_eq!(notification["level"], "error");
+
+    assert_eq!(run(&["check", "--format", "xml", "--stdin"], None).0, Some(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_check_findings_are_not_logged() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["check", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(BROKEN.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!output.stdout.is_empty());
+    // The diagnostics on stdout are not repeated as log lines on stderr
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
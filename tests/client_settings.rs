@@ -0,0 +1,71 @@
+use serde_json::{Value, json};
+use sieve_language_server::config;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+async fn change(server: &SieveLanguageServer, settings: Value) -> Value {
+    server
+        .did_change_configuration(DidChangeConfigurationParams { settings })
+        .await;
+    serde_json::to_value(&*server.settings.read().await).unwrap()
+}
+
+#[test]
+fn test_settings_section() {
+    assert_eq!(
+        config::settings_section(json!({ "sieve": { "strict_mode": true }, "editor": {} })),
+        json!({ "strict_mode": true })
+    );
+    assert_eq!(
+        config::settings_section(json!({ "strict_mode": true })),
+        json!({ "strict_mode": true })
+    );
+    // A setting called like the section is not mistaken for it
+    assert_eq!(config::settings_section(json!({ "sieve": 1 })), json!({ "sieve": 1 }));
+}
+
+#[tokio::test]
+async fn test_nested_settings_are_applied() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let settings = change(server, json!({ "sieve": { "max_errors": 5, "unknownKey": 1 } })).await;
+    assert_eq!(settings["max_errors"], 5);
+    assert!(settings.get("unknownKey").is_none());
+}
+
+#[tokio::test]
+async fn test_partial_updates_keep_other_settings() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    change(
+        server,
+        json!({ "sieve": { "strict_mode": true, "requires": { "autoManage": true } } }),
+    )
+    .await;
+
+    let settings = change(server, json!({ "sieve": { "max_errors": 7 } })).await;
+    assert_eq!(settings["max_errors"], 7);
+    assert_eq!(settings["strict_mode"], true);
+    assert_eq!(settings["requires"]["autoManage"], true);
+
+    // An invalid update changes nothing, not even its valid keys
+    let settings = change(server, json!({ "sieve": { "max_errors": 9, "debounce_ms": "x" } })).await;
+    assert_eq!(settings["max_errors"], 7);
+
+    // `null` restores the default
+    let settings = change(server, json!({ "sieve": { "strict_mode": null } })).await;
+    assert_eq!(settings["strict_mode"], false);
+    assert_eq!(settings["max_errors"], 7);
+}
+
+#[tokio::test]
+async fn test_change_notifications_without_settings() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    change(server, json!({ "sieve": { "max_errors": 3 } })).await;
+    // The client does not support pulling settings: the current ones stay in effect
+    let settings = change(server, Value::Null).await;
+    assert_eq!(settings["max_errors"], 3);
+    assert!(server.errors.read().await.is_empty());
+}
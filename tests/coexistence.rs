@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::coexistence::*;
+use sieve_language_server::external::*;
+use tower_lsp::lsp_types::*;
+
+const SCRIPT: &str = "require [\"variables\", \"vacation\"];\n\
+                      if header :contains \"Bad Name\" \"x\" {\n\
+                      \x20   fileinto \"Spam\";\n\
+                      }\n\
+                      if size :over 10Q { stop; keep; }\n\
+                      set \"a\" \"${undefined}\";\n";
+
+/// Validate the script while the given tools are active
+async fn diagnostics_with(active_tools: &[&str], coexistence: serde_json::Value) -> Vec<Diagnostic> {
+    let (service, uri) = server_with(json!({ "coexistence": coexistence }), SCRIPT).await;
+    let server = service.inner();
+    *server.active_tools.write().await = active_tools.iter().map(|t| t.to_string()).collect();
+    server.validate_document(&uri).await.unwrap()
+}
+
+fn categories(diagnostics: &[Diagnostic]) -> Vec<DiagnosticCategory> {
+    let mut categories: Vec<DiagnosticCategory> = diagnostics
+        .iter()
+        .filter_map(|d| match &d.code {
+            Some(NumberOrString::String(code)) => category_of(code),
+            _ => None,
+        })
+        .collect();
+    categories.sort();
+    categories.dedup();
+    categories
+}
+
+#[tokio::test]
+async fn test_all_diagnostics_are_namespaced() {
+    let diagnostics = diagnostics_for(SCRIPT).await;
+    assert!(!diagnostics.is_empty());
+    for diagnostic in &diagnostics {
+        assert_eq!(diagnostic.source.as_deref(), Some(DIAGNOSTIC_SOURCE));
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            panic!("diagnostic without code: {:?}", diagnostic);
+        };
+        assert!(category_of(code).is_some(), "unregistered code {}", code);
+    }
+
+    let linter: ExternalLinterSettings =
+        serde_json::from_value(json!({ "command": "sievec" })).unwrap();
+    let external = parse_linter_output("x.sieve: line 1: error: unknown command.", &linter);
+    assert_eq!(
+        external[0].code,
+        Some(NumberOrString::String(EXTERNAL_LINTER_CODE.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_categories_are_suppressed_while_another_tool_is_active() {
+    let settings = json!({ "suppress": ["syntax", "arguments"] });
+    let alone = diagnostics_with(&[], settings.clone()).await;
+    let together = diagnostics_with(&["proton-checker"], settings).await;
+
+    assert!(categories(&alone).contains(&DiagnosticCategory::Arguments));
+    assert!(categories(&alone).contains(&DiagnosticCategory::Syntax));
+    assert_eq!(
+        categories(&together),
+        categories(&alone)
+            .into_iter()
+            .filter(|c| !matches!(c, DiagnosticCategory::Syntax | DiagnosticCategory::Arguments))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_suppression_can_be_limited_to_named_tools() {
+    let settings = json!({ "suppress": ["logic"], "tools": ["sievec"] });
+    let other = diagnostics_with(&["proton-checker"], settings.clone()).await;
+    let named = diagnostics_with(&["SieveC"], settings).await;
+
+    assert!(categories(&other).contains(&DiagnosticCategory::Logic));
+    assert!(!categories(&named).contains(&DiagnosticCategory::Logic));
+}
+
+#[test]
+fn test_active_tools_from_initialization_options() {
+    let options = json!({ "activeSieveTools": ["sievec", 3, "proton"] });
+    assert_eq!(active_tools(Some(&options)), ["sievec", "proton"]);
+    assert!(active_tools(Some(&json!({ "other": true }))).is_empty());
+    assert!(active_tools(None).is_empty());
+}
@@ -0,0 +1,73 @@
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use sieve_language_server::datastructures::*;
+use std::sync::{Arc, Mutex};
+use tower::{Service, ServiceExt};
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+/// Methods and parameters of the messages the server sent to the client, in order
+pub type ClientMessages = Arc<Mutex<Vec<(String, Value)>>>;
+
+/// A server initialized with the given `initialize` parameters
+/// Its client records every message and answers requests with `null`.
+pub async fn initialized_server(
+    params: Value,
+) -> (LspService<SieveLanguageServer>, ClientMessages) {
+    let (mut service, socket) = LspService::new(SieveLanguageServer::new);
+    let messages = ClientMessages::default();
+    let received = messages.clone();
+    let (mut requests, mut responses) = socket.split();
+    tokio::spawn(async move {
+        while let Some(request) = requests.next().await {
+            let params = request.params().cloned().unwrap_or(Value::Null);
+            received.lock().unwrap().push((request.method().to_string(), params));
+            if let Some(id) = request.id() {
+                let _ = responses.send(Response::from_ok(id.clone(), Value::Null)).await;
+            }
+        }
+    });
+    let initialize = Request::build("initialize").id(0).params(params).finish();
+    service.ready().await.unwrap().call(initialize).await.unwrap();
+    (service, messages)
+}
+
+/// The parameters of the recorded messages with the given method
+pub fn sent(messages: &ClientMessages, method: &str) -> Vec<Value> {
+    let messages = messages.lock().unwrap();
+    messages.iter().filter(|(m, _)| m == method).map(|(_, p)| p.clone()).collect()
+}
+
+/// A server with the given settings and one open document, `file:///test.sieve`
+pub async fn server_with(settings: Value, text: &str) -> (LspService<SieveLanguageServer>, Url) {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    *service.inner().settings.write().await = serde_json::from_value(settings).unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    service
+        .inner()
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    (service, uri)
+}
+
+/// Run the full validation pipeline over a script using the given settings
+pub async fn diagnostics_with_settings(text: &str, settings: Value) -> Vec<Diagnostic> {
+    let (service, uri) = server_with(settings, text).await;
+    service.inner().validate_document(&uri).await.unwrap()
+}
+
+/// Run the full validation pipeline over a script using default settings
+pub async fn diagnostics_for(text: &str) -> Vec<Diagnostic> {
+    diagnostics_with_settings(text, json!({})).await
+}
+
+/// Keep only the diagnostics carrying the given rule code
+pub fn with_code<'a>(diagnostics: &'a [Diagnostic], code: &str) -> Vec<&'a Diagnostic> {
+    diagnostics
+        .iter()
+        .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+        .collect()
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::sieve::comparator_requirement;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[tokio::test]
+async fn test_known_comparators_are_accepted() {
+    let text = "require \"comparator-i;ascii-numeric\";\nif header :comparator \"i;ascii-numeric\" :is \"x-priority\" \"1\" { keep; }\nif header :comparator \"i;octet\" :is \"subject\" \"A\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unknown-comparator").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_unknown_comparator() {
+    let text = "if header :comparator \"i;ascii-casemp\" :is \"subject\" \"A\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "unknown-comparator");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(0, 22));
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+}
+
+#[tokio::test]
+async fn test_numeric_comparator_must_be_required() {
+    let text = "if header :comparator \"i;ascii-numeric\" :is \"x-priority\" \"1\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "missing-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert!(found[0].message.contains("comparator-i;ascii-numeric"));
+}
+
+#[test]
+fn test_comparator_requirement() {
+    assert_eq!(comparator_requirement("i;octet"), None);
+    assert_eq!(comparator_requirement("I;ASCII-CASEMAP"), None);
+    assert_eq!(
+        comparator_requirement("i;unicode-casemap").as_deref(),
+        Some("comparator-i;unicode-casemap")
+    );
+}
+
+#[tokio::test]
+async fn test_comparator_completions() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if header :comparator \"i; :is \"subject\" \"A\" { keep; }\n";
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), text.to_string(), 1),
+    );
+
+    let labels: Vec<String> = server
+        .get_completions(&uri, Position::new(0, 25))
+        .await
+        .into_iter()
+        .map(|c| c.label)
+        .collect();
+    assert_eq!(
+        labels,
+        ["i;ascii-casemap", "i;ascii-numeric", "i;octet", "i;unicode-casemap"]
+    );
+
+    // Outside the comparator argument the regular completions are offered
+    let completions = server.get_completions(&uri, Position::new(0, 3)).await;
+    assert!(completions.iter().any(|c| c.label == "header"));
+}
+
+#[tokio::test]
+async fn test_octet_keys_differing_by_case_from_usual_values() {
+    let text = "if header :comparator \"i;octet\" :is \"X-Spam-Flag\" \"yes\" { discard; }\n\
+                if header :comparator \"i;octet\" :contains \"precedence\" \"Bulk\" { keep; }\n\
+                if header :comparator \"i;octet\" :is \"x-spam-flag\" [\"YES\", \"maybe\"] { keep; }\n\
+                if header :is \"x-spam-flag\" \"yes\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "octet-case-mismatch");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(0, 50));
+    assert_eq!(
+        found[0].message,
+        "\"yes\" never matches the usual \"YES\" of 'X-Spam-Flag': \"i;octet\" is case-sensitive"
+    );
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], "\"YES\"");
+    assert_eq!(found[1].range.start.line, 1);
+    assert_eq!(found[1].data.as_ref().unwrap()["replacement"], "\"bulk\"");
+}
+
+#[tokio::test]
+async fn test_tokens_compared_ignoring_case() {
+    let text = "if header :is \"x-auth-token\" \"q8ZtR2vLx9KdPw4N\" { keep; }\n\
+                if header :is \"subject\" \"ThisIsMyNewsletter1\" { keep; }\n\
+                if header :comparator \"i;octet\" :is \"x-auth-token\" \"q8ZtR2vLx9KdPw4N\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "case-sensitive-key");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::INFORMATION));
+    let data = found[0].data.as_ref().unwrap();
+    assert_eq!(data["replacement"], " :comparator \"i;octet\"");
+    assert_eq!(data["range"]["start"]["character"], 9);
+
+    // Only with semantic analysis
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(serde_json::json!({ "semantic_analysis": false })).unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    assert!(with_code(&diagnostics, "case-sensitive-key").is_empty(), "{:?}", diagnostics);
+}
@@ -0,0 +1,87 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::documentation::{FEATURES, completion_markdown, extension_markdown};
+use sieve_language_server::sieve::{SIEVE_ACTIONS, SIEVE_TAGS, SIEVE_TESTS};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn markdown(item: &CompletionItem) -> &str {
+    match &item.documentation {
+        Some(Documentation::MarkupContent(content)) => &content.value,
+        other => panic!("expected Markdown documentation, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_features_are_completions() {
+    for feature in FEATURES {
+        assert!(
+            [&*SIEVE_TESTS, &*SIEVE_ACTIONS, &*SIEVE_TAGS]
+                .iter()
+                .any(|names| names.contains(&feature.name)),
+            "{} is never completed",
+            feature.name
+        );
+    }
+}
+
+#[test]
+fn test_markdown_has_example_and_link() {
+    assert_eq!(
+        completion_markdown("fileinto", "Sieve action", "Files the message"),
+        "**fileinto** - Sieve action\n\nFiles the message\n\n\
+         ```sieve\nfileinto \"Archive\";\n```\n\n\
+         [RFC 5228 section 4.1](https://datatracker.ietf.org/doc/html/rfc5228#section-4.1)"
+    );
+    // Vendor features have an example but no specification
+    assert!(!completion_markdown("expire", "Sieve action", "x").contains("RFC"));
+    assert_eq!(
+        extension_markdown("body", "Message body testing (RFC 5173)"),
+        "**body** - Sieve extension\n\nMessage body testing (RFC 5173)\n\n\
+         ```sieve\nrequire \"body\";\n```\n\n\
+         [RFC 5173](https://datatracker.ietf.org/doc/html/rfc5173)"
+    );
+}
+
+#[tokio::test]
+async fn test_completions_are_resolved_lazily() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///resolve.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), String::new(), 1));
+    let response = server
+        .completion(CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(0, 0),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        })
+        .await
+        .unwrap();
+    let Some(CompletionResponse::Array(items)) = response else {
+        panic!("expected a completion list");
+    };
+    assert!(items.iter().all(|item| item.documentation.is_none()));
+
+    let header = items.iter().find(|item| item.label == "header").unwrap().clone();
+    assert_eq!(header.data, Some(json!({ "kind": "Sieve test", "name": "header" })));
+    let resolved = server.completion_resolve(header).await.unwrap();
+    let doc = markdown(&resolved);
+    assert!(doc.starts_with("**header** - Sieve test\n\nTests the contents"), "{}", doc);
+    assert!(doc.contains("```sieve\nif header :contains"), "{}", doc);
+    assert!(doc.ends_with("rfc5228#section-5.7)"), "{}", doc);
+
+    // Control keywords keep their structured documentation
+    let item = items.iter().find(|item| item.label == "if").unwrap().clone();
+    let resolved = server.completion_resolve(item).await.unwrap();
+    assert!(markdown(&resolved).starts_with("**if** - control structure"));
+
+    // Items the server did not produce are left alone
+    let foreign = CompletionItem::new_simple("x".to_string(), "y".to_string());
+    assert_eq!(server.completion_resolve(foreign.clone()).await.unwrap(), foreign);
+}
@@ -0,0 +1,188 @@
+use serde_json::json;
+use sieve_language_server::builder::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::lsp::COMMAND_NEW_RULE;
+use sieve_language_server::parser::parse;
+use sieve_language_server::refactor::apply_edits;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn params(field: &str, operator: &str, values: &[&str]) -> BuildConditionParams {
+    BuildConditionParams {
+        field: field.to_string(),
+        operator: operator.to_string(),
+        values: values.iter().map(|v| v.to_string()).collect(),
+        negate: false,
+    }
+}
+
+fn build(field: &str, operator: &str, values: &[&str]) -> (String, Vec<String>) {
+    let result = build_condition(&params(field, operator, values)).unwrap();
+    (result.source, result.extensions)
+}
+
+#[test]
+fn test_header_and_address_conditions() {
+    assert_eq!(
+        build("Subject", "contains", &["invoice", "say \"hi\""]),
+        (
+            "header :contains \"Subject\" [\"invoice\", \"say \\\"hi\\\"\"]".to_string(),
+            vec![]
+        )
+    );
+    assert_eq!(
+        build("From", "is", &["boss@example.com"]),
+        ("address :is \"From\" \"boss@example.com\"".to_string(), vec![])
+    );
+    assert_eq!(
+        build("X-Spam-Flag", "exists", &[]),
+        ("exists \"X-Spam-Flag\"".to_string(), vec![])
+    );
+}
+
+#[test]
+fn test_conditions_report_needed_extensions() {
+    assert_eq!(
+        build("X-Spam-Score", "ge", &["5"]),
+        (
+            "header :value \"ge\" :comparator \"i;ascii-numeric\" \"X-Spam-Score\" \"5\"".to_string(),
+            vec!["comparator-i;ascii-numeric".to_string(), "relational".to_string()]
+        )
+    );
+    assert_eq!(
+        build("body", "regex", &["order [0-9]+"]),
+        (
+            "body :regex \"order [0-9]+\"".to_string(),
+            vec!["body".to_string(), "regex".to_string()]
+        )
+    );
+    assert_eq!(
+        build("envelope-to", "matches", &["*+lists@example.com"]),
+        (
+            "envelope :matches \"to\" \"*+lists@example.com\"".to_string(),
+            vec!["envelope".to_string()]
+        )
+    );
+}
+
+#[test]
+fn test_size_and_negation() {
+    let mut negated = params("size", "over", &["10M"]);
+    negated.negate = true;
+    let result = build_condition(&negated).unwrap();
+    assert_eq!(result.source, "not size :over 10M");
+
+    // The generated source parses cleanly
+    let script = parse(&format!("if {} {{ keep; }}\n", result.source));
+    assert!(script.errors.is_empty(), "{:?}", script.errors);
+}
+
+#[test]
+fn test_invalid_input() {
+    let error = |p: BuildConditionParams| build_condition(&p).unwrap_err();
+    assert_eq!(error(params("Subject:", "is", &["x"])), "'Subject:' is not a valid header name");
+    assert_eq!(error(params("Subject", "like", &["x"])), "Unknown operator 'like'");
+    assert_eq!(error(params("Subject", "is", &[])), "Operator 'is' needs at least one value");
+    assert_eq!(error(params("size", "over", &["10MB"])), "'10MB' is not a size such as 100K or 10M");
+    assert_eq!(error(params("body", "exists", &[])), "Field 'body' does not support 'exists'");
+}
+
+#[tokio::test]
+async fn test_custom_request_translates_errors() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.localizer.write().await = sieve_language_server::i18n::Localizer::new(Some("de"));
+
+    let error = server
+        .build_condition(params("Subject", "like", &["x"]))
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "Unbekannter Operator 'like'");
+}
+
+fn rule(header: &str, match_type: &str, value: &str, action: &str) -> NewRuleParams {
+    serde_json::from_value(json!({
+        "textDocument": { "uri": "file:///rules.sieve" },
+        "header": header,
+        "match": match_type,
+        "value": value,
+        "action": action,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_new_rule_adds_requires() {
+    let mut params = rule("Subject", "contains", "digest", "fileinto");
+    params.folder = Some("News".to_string());
+    params.stop = true;
+
+    let text = "require \"vacation\";\nkeep;\n";
+    let edits = new_rule_edits(text, &parse(text), &params, "  ").unwrap();
+    let expected = "require [\"vacation\", \"fileinto\"];\nkeep;\n\n\
+                    if header :contains \"Subject\" \"digest\" {\n  fileinto \"News\";\n  stop;\n}\n";
+    let result = apply_edits(text, &edits);
+    assert_eq!(result, expected);
+    assert!(parse(&result).errors.is_empty());
+
+    // Nothing to require, and an empty script gets the rule alone
+    let params = rule("size", "over", "1M", "discard");
+    let edits = new_rule_edits("", &parse(""), &params, "    ").unwrap();
+    assert_eq!(apply_edits("", &edits), "if size :over 1M {\n    discard;\n}\n");
+
+    // Required capabilities are not repeated, and a missing final newline is added
+    let text = "require [\"fileinto\", \"regex\"];\nkeep;";
+    let mut params = rule("X-Tag", "regex", "^a", "fileinto");
+    params.folder = Some("A".to_string());
+    let edits = new_rule_edits(text, &parse(text), &params, "    ").unwrap();
+    assert_eq!(edits.len(), 1);
+    let result = apply_edits(text, &edits);
+    assert!(result.starts_with("require [\"fileinto\", \"regex\"];\nkeep;\n\nif "), "{}", result);
+}
+
+#[test]
+fn test_new_rule_invalid_actions() {
+    let error = |p: NewRuleParams| build_rule(&p, "    ").unwrap_err();
+    assert_eq!(error(rule("Subject", "is", "x", "fileinto")), "Action 'fileinto' needs a folder");
+    assert_eq!(error(rule("Subject", "is", "x", "redirect")), "Action 'redirect' needs an address");
+    assert_eq!(error(rule("Subject", "is", "x", "archive")), "Unknown action 'archive'");
+    let mut params = rule("Subject", "is", "x", "redirect");
+    params.address = Some("boss@example..com".to_string());
+    assert_eq!(error(params), "The domain contains consecutive dots");
+}
+
+#[tokio::test]
+async fn test_new_rule_command_returns_workspace_edit() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///rules.sieve").unwrap();
+    let text = "require \"fileinto\"; # 😀\nkeep;\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let execute = |arguments| {
+        server.execute_command(ExecuteCommandParams {
+            command: COMMAND_NEW_RULE.to_string(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    let mut params = rule("From", "is", "boss@example.com", "redirect");
+    params.address = Some("me@example.org".to_string());
+    let params = serde_json::to_value(params).unwrap();
+    let result = execute(vec![params]).await.unwrap().unwrap();
+    let edit: WorkspaceEdit = serde_json::from_value(result).unwrap();
+    let edits = &edit.changes.unwrap()[&uri];
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].range.start, Position::new(2, 0));
+    assert_eq!(
+        edits[0].new_text,
+        "\nif address :is \"From\" \"boss@example.com\" {\n    redirect \"me@example.org\";\n}\n"
+    );
+
+    *server.localizer.write().await = sieve_language_server::i18n::Localizer::new(Some("de"));
+    let params = serde_json::to_value(rule("Subject", "is", "x", "fileinto")).unwrap();
+    let error = execute(vec![params]).await.unwrap_err();
+    assert_eq!(error.message, "Die Aktion 'fileinto' benötigt einen Ordner");
+}
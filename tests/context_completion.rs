@@ -0,0 +1,73 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+/// Labels of the completions at a position
+async fn labels_at(text: &str, position: Position) -> Vec<String> {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    server
+        .get_completions(&uri, position)
+        .await
+        .into_iter()
+        .map(|c| c.label)
+        .collect()
+}
+
+fn has(labels: &[String], label: &str) -> bool {
+    labels.iter().any(|l| l == label)
+}
+
+#[test]
+fn test_context_at_nested_blocks() {
+    let script = parse(
+        "require \"foreverypart\";\nforeverypart {\n    if true { keep; }\n    \n}\n",
+    );
+    let context = script.context_at(Position::new(3, 4));
+    let enclosing: Vec<&str> = context.enclosing.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(enclosing, ["foreverypart"]);
+    assert_eq!(context.previous.map(|c| c.name.as_str()), Some("if"));
+
+    let context = script.context_at(Position::new(5, 0));
+    assert!(context.enclosing.is_empty());
+    assert_eq!(context.previous.map(|c| c.name.as_str()), Some("foreverypart"));
+}
+
+#[tokio::test]
+async fn test_elsif_else_only_after_if_block() {
+    let text = "if true {\n    keep;\n}\n\n";
+    let labels = labels_at(text, Position::new(3, 0)).await;
+    assert!(has(&labels, "elsif") && has(&labels, "else"), "{:?}", labels);
+    assert!(has(&labels, "if"));
+
+    let text = "if true { keep; } elsif false { discard; }\nel";
+    let labels = labels_at(text, Position::new(1, 2)).await;
+    assert!(has(&labels, "elsif") && has(&labels, "else"), "{:?}", labels);
+
+    let text = "keep;\n\n";
+    let labels = labels_at(text, Position::new(1, 0)).await;
+    assert!(!has(&labels, "elsif") && !has(&labels, "else"), "{:?}", labels);
+    assert!(has(&labels, "if"));
+
+    let text = "if true { keep; } else { discard; }\n\n";
+    let labels = labels_at(text, Position::new(1, 0)).await;
+    assert!(!has(&labels, "elsif") && !has(&labels, "else"), "{:?}", labels);
+}
+
+#[tokio::test]
+async fn test_break_only_inside_foreverypart() {
+    let text = "require \"foreverypart\";\nforeverypart {\n    \n}\n";
+    assert!(has(&labels_at(text, Position::new(2, 4)).await, "break"));
+    assert!(!has(&labels_at(text, Position::new(4, 0)).await, "break"));
+
+    // While typing, the loop is not closed yet
+    let text = "require \"foreverypart\";\nforeverypart {\n    if true {\n        br";
+    assert!(has(&labels_at(text, Position::new(3, 10)).await, "break"));
+
+    assert!(!has(&labels_at("\n", Position::new(0, 0)).await, "break"));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{ClientMessages, initialized_server, sent};
+use serde_json::{Value, json};
+use sieve_language_server::datastructures::*;
+use std::time::Duration;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const PUBLISH: &str = "textDocument/publishDiagnostics";
+
+/// An initialized server with the given debounce delay and an open, already published document
+async fn open_with_debounce(
+    debounce_ms: u64,
+) -> (LspService<SieveLanguageServer>, ClientMessages, Url) {
+    let (service, messages) = initialized_server(json!({ "capabilities": {} })).await;
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "debounce_ms": debounce_ms })).unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "sieve".into(), 1, String::new()),
+        })
+        .await;
+    (service, messages, uri)
+}
+
+/// Append a line to the document as the given version
+async fn type_line(server: &SieveLanguageServer, uri: &Url, version: i32) {
+    let end = Position::new(version as u32 - 1, 0);
+    server
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(end, end)),
+                range_length: None,
+                text: "keep\n".to_string(),
+            }],
+        })
+        .await;
+}
+
+/// Versions of the published diagnostics after the debounce delay has passed
+async fn published_versions(messages: &ClientMessages) -> Vec<Value> {
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    sent(messages, PUBLISH).into_iter().map(|p| p["version"].clone()).collect()
+}
+
+#[tokio::test]
+async fn test_rapid_changes_publish_once() {
+    let (service, messages, uri) = open_with_debounce(100).await;
+    for version in 2..=6 {
+        type_line(service.inner(), &uri, version).await;
+    }
+    assert_eq!(published_versions(&messages).await, [json!(1), json!(6)]);
+
+    // Every change was applied; only the last one was validated
+    let document = service.inner().document_map.get(&uri).unwrap().get_text();
+    assert_eq!(document, "keep\n".repeat(5));
+}
+
+#[tokio::test]
+async fn test_a_new_change_aborts_the_pending_validation() {
+    let (service, _messages, uri) = open_with_debounce(60_000).await;
+    let server = service.inner();
+    type_line(server, &uri, 2).await;
+    let first = server.pending_validations.get(&uri).unwrap().abort_handle();
+    assert!(!first.is_finished());
+
+    type_line(server, &uri, 3).await;
+    tokio::task::yield_now().await;
+    assert!(first.is_finished());
+    let second = server.pending_validations.get(&uri).unwrap().abort_handle();
+    assert!(!second.is_finished());
+    second.abort();
+}
+
+#[tokio::test]
+async fn test_stale_versions_are_not_published() {
+    let (service, messages, uri) = open_with_debounce(100).await;
+    let server = service.inner();
+    type_line(server, &uri, 2).await;
+    // A newer version arrives before the delay has passed, without scheduling a validation
+    server.document_map.get_mut(&uri).unwrap().version = 3;
+    assert_eq!(published_versions(&messages).await, [json!(1)]);
+}
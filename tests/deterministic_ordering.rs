@@ -0,0 +1,145 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[tokio::test]
+async fn test_completions_are_stably_ordered() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+
+    let first = server.get_completions(&uri, Position::new(0, 0)).await;
+    let second = server.get_completions(&uri, Position::new(0, 0)).await;
+    assert_eq!(first, second);
+
+    let keys: Vec<String> = first.iter().map(|c| c.sort_text.clone().unwrap()).collect();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+}
+
+#[tokio::test]
+async fn test_diagnostics_are_in_document_order() {
+    let text = "fileinto \"a\";\nbody :contains \"x\";\nkeep\n";
+    let diagnostics = diagnostics_for(text).await;
+    let positions: Vec<(u32, u32)> = diagnostics
+        .iter()
+        .map(|d| (d.range.start.line, d.range.start.character))
+        .collect();
+    let mut sorted = positions.clone();
+    sorted.sort();
+    assert_eq!(positions, sorted);
+    assert!(!diagnostics.is_empty());
+}
+
+#[tokio::test]
+async fn test_workspace_symbols_are_ordered_by_document() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    for name in ["m", "z", "b", "y", "a", "q", "c", "x"] {
+        let uri = Url::parse(&format!("file:///{}.sieve", name)).unwrap();
+        let text = "require \"fileinto\";\nfileinto \"Spam\";\nif true { fileinto \"Ham\"; }\n";
+        server
+            .document_map
+            .insert(uri.clone(), SieveDocument::new(uri, text.to_string(), 1));
+    }
+
+    let symbols = server.workspace_symbols("").await;
+    assert_eq!(symbols, server.workspace_symbols("").await);
+    let keys: Vec<(String, Position, String)> = symbols
+        .iter()
+        .map(|s| (s.location.uri.to_string(), s.location.range.start, s.name.clone()))
+        .collect();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+    assert_eq!(keys.first().unwrap().0, "file:///a.sieve");
+    assert!(keys.len() > 8, "{:?}", keys);
+}
+
+/// The code actions for a document, given its diagnostics in the order the client sends them
+async fn code_actions(
+    server: &SieveLanguageServer,
+    uri: &Url,
+    diagnostics: Vec<Diagnostic>,
+) -> Vec<CodeActionOrCommand> {
+    server
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::default(),
+            context: CodeActionContext {
+                diagnostics,
+                only: Some(vec![CodeActionKind::SOURCE]),
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_code_actions_do_not_depend_on_the_order_of_diagnostics() {
+    let text = "if address :domain \"from\" \"j@x.com\" { keep; }\n\
+                if address :domain \"to\" \"k@y.com\" { keep; }\n";
+    let (service, uri) = server_with(serde_json::json!({}), text).await;
+    let server = service.inner();
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let mut reversed = diagnostics.clone();
+    reversed.reverse();
+
+    let actions = code_actions(server, &uri, diagnostics).await;
+    assert_eq!(actions, code_actions(server, &uri, reversed).await);
+    let keys: Vec<(String, Option<Position>)> = actions
+        .iter()
+        .map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) => (
+                action.kind.as_ref().unwrap().as_str().to_string(),
+                action.diagnostics.as_ref().map(|d| d[0].range.start),
+            ),
+            CodeActionOrCommand::Command(command) => panic!("{:?}", command),
+        })
+        .collect();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+    assert!(keys.iter().filter(|(kind, _)| kind == "quickfix").count() > 1, "{:?}", keys);
+    assert!(keys.iter().any(|(kind, _)| kind == "source.fixAll"), "{:?}", keys);
+}
+
+#[tokio::test]
+async fn test_document_symbols_are_in_document_order() {
+    let text = "require \"fileinto\";\n\
+                if header :is \"x\" \"y\" {\n    fileinto \"B\";\n    fileinto \"A\";\n}\n\
+                elsif true { discard; }\n";
+    let (service, uri) = server_with(serde_json::json!({}), text).await;
+    let response = service
+        .inner()
+        .document_symbol(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    let Some(DocumentSymbolResponse::Nested(symbols)) = response else {
+        panic!("{:?}", response);
+    };
+
+    fn assert_sorted(symbols: &[DocumentSymbol]) {
+        let starts: Vec<Position> = symbols.iter().map(|s| s.range.start).collect();
+        let mut sorted = starts.clone();
+        sorted.sort();
+        assert_eq!(starts, sorted);
+        for symbol in symbols {
+            assert_sorted(symbol.children.as_deref().unwrap_or_default());
+        }
+    }
+    assert_sorted(&symbols);
+    assert!(symbols.iter().any(|s| s.children.as_ref().is_some_and(|c| c.len() == 2)));
+}
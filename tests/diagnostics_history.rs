@@ -0,0 +1,58 @@
+use sieve_language_server::history::*;
+use tower_lsp::lsp_types::*;
+
+fn diagnostic(code: &str, message: &str, line: u32) -> Diagnostic {
+    Diagnostic {
+        range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+        code: Some(NumberOrString::String(code.to_string())),
+        message: message.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_report_against_first_snapshot_and_deploy() {
+    let uri = "file:///rules.sieve";
+    let mut history = DiagnosticsHistory::default();
+
+    history.record(uri, &[diagnostic("missing-semicolon", "a", 1)]);
+    history.record(
+        uri,
+        &[
+            diagnostic("missing-semicolon", "a", 4),
+            diagnostic("invalid-syntax", "b", 2),
+        ],
+    );
+
+    // Line changes alone do not make an issue "new"
+    let report = history.report();
+    assert_eq!(report.baseline, "first-snapshot");
+    assert_eq!(report.files[0].trend, vec![1, 2]);
+    assert_eq!(report.files[0].new_issues.len(), 1);
+    assert_eq!(report.files[0].new_issues[0].code, "invalid-syntax");
+    assert!(report.files[0].fixed_issues.is_empty());
+
+    history.mark_deployed();
+    history.record(uri, &[diagnostic("invalid-syntax", "b", 2)]);
+
+    let report = history.report();
+    assert_eq!(report.baseline, "deploy");
+    assert!(report.files[0].new_issues.is_empty());
+    assert_eq!(report.files[0].fixed_issues[0].code, "missing-semicolon");
+}
+
+#[test]
+fn test_history_is_rolling_and_persisted() {
+    let uri = "file:///rules.sieve";
+    let mut history = DiagnosticsHistory::default();
+    for _ in 0..MAX_SNAPSHOTS_PER_FILE + 5 {
+        history.record(uri, &[]);
+    }
+    assert_eq!(history.files[uri].len(), MAX_SNAPSHOTS_PER_FILE);
+
+    let root = std::env::temp_dir().join(format!("sieve-history-{}", std::process::id()));
+    history.save(&root).unwrap();
+    let loaded = DiagnosticsHistory::load(&root);
+    assert_eq!(loaded.files[uri].len(), MAX_SNAPSHOTS_PER_FILE);
+    std::fs::remove_dir_all(root).unwrap();
+}
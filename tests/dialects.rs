@@ -0,0 +1,103 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::dialect::{self, FAMILIES};
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[test]
+fn test_active_variant_selection() {
+    let notify = dialect::family_of("enotify").unwrap();
+    assert_eq!(notify, dialect::family_of("denotify").unwrap());
+    assert_eq!(notify.active(&BTreeMap::new()).capability, "enotify");
+
+    let mut dialects = BTreeMap::new();
+    dialects.insert("notify".to_string(), "Draft".to_string());
+    assert_eq!(notify.active(&dialects).capability, "notify");
+
+    // Unknown variants fall back to the default
+    dialects.insert("notify".to_string(), "rfc9999".to_string());
+    assert_eq!(notify.active(&dialects).capability, "enotify");
+
+    for family in FAMILIES {
+        assert!(!family.variants.is_empty(), "{}", family.name);
+    }
+}
+
+#[tokio::test]
+async fn test_rfc_dialect_is_default() {
+    let text = "require [\"enotify\", \"imap4flags\", \"fileinto\"];\n\
+                if hasflag \"\\\\Seen\" { fileinto :flags \"\\\\Seen\" \"Read\"; }\n\
+                notify :importance \"1\" \"mailto:alice@example.com\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "dialect-mismatch").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_draft_capability_under_rfc_dialect() {
+    let text = "require [\"notify\", \"imapflags\"];\naddflag \"\\\\Flagged\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let mismatches = with_code(&diagnostics, "dialect-mismatch");
+    assert_eq!(mismatches.len(), 2, "{:?}", diagnostics);
+    assert_eq!(
+        mismatches[0].data.as_ref().unwrap()["replacement"],
+        "\"enotify\""
+    );
+    assert_eq!(
+        mismatches[1].data.as_ref().unwrap()["replacement"],
+        "\"imap4flags\""
+    );
+
+    // The draft capability still counts as requiring the family
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_draft_dialect_flags_rfc_features() {
+    let text = "require [\"notify\", \"imapflags\"];\n\
+                notify :method \"mailto:alice@example.com\" :low;\n\
+                notify :importance \"1\" \"mailto:bob@example.com\";\n\
+                mark;\n";
+    let dialects = serde_json::json!({ "dialects": { "notify": "draft", "imapflags": "draft" } });
+    let diagnostics = diagnostics_with_settings(text, dialects).await;
+    let mismatches = with_code(&diagnostics, "dialect-mismatch");
+    assert_eq!(mismatches.len(), 1, "{:?}", diagnostics);
+    assert_eq!(mismatches[0].range.start.line, 2);
+    assert!(mismatches[0].message.contains("'notify :importance'"));
+}
+
+#[tokio::test]
+async fn test_hover_names_active_dialect() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(serde_json::json!({ "dialects": { "imapflags": "draft" } }))
+            .unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), "setflag \"\\\\Seen\";\n".to_string(), 1),
+    );
+
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(0, 2),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let HoverContents::Scalar(MarkedString::String(text)) = hover.contents else {
+        panic!("unexpected hover {:?}", hover.contents);
+    };
+    assert!(
+        text.ends_with("Active dialect: draft-melnikov-sieve-imapflags-03 (imapflags)"),
+        "{}",
+        text
+    );
+}
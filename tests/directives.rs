@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use sieve_language_server::directives::{self, Scope, Suppression};
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_directives_are_parsed() {
+    let text = "# sieve-lsp: dialect=Proton strict=true capabilities=fileinto,envelope\n\
+                # sieve-lsp-disable missing-require\n\
+                keep; # sieve-lsp-disable-line\n\
+                # sieve-lsp-disable-next-line unreachable-code, redundant-keep\n\
+                keep;\n";
+    let script = parse(text);
+    let found = directives::parse(text, &script);
+    assert!(found.problems.is_empty(), "{:?}", found.problems);
+    assert_eq!(found.settings["server_dialect"], "proton");
+    assert_eq!(found.settings["strict_mode"], true);
+    assert_eq!(found.settings["capabilities"], serde_json::json!(["fileinto", "envelope"]));
+    assert_eq!(
+        found.suppressions,
+        vec![
+            Suppression {
+                scope: Scope::File,
+                codes: vec!["missing-require".to_string()],
+            },
+            Suppression {
+                scope: Scope::Line(2),
+                codes: vec![],
+            },
+            Suppression {
+                scope: Scope::Line(4),
+                codes: vec!["unreachable-code".to_string(), "redundant-keep".to_string()],
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_directives_are_reported() {
+    let text = "# sieve-lsp: dialect=exchange strict verbose=1\n\
+                keep;\n\
+                # sieve-lsp: strict=true\n\
+                # sieve-lsp-disable-next-line no-such-code\n\
+                # sieve-lsp-enable\n\
+                # sieve-lsp is not a directive\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found: Vec<(Range, &str)> = with_code(&diagnostics, "invalid-directive")
+        .iter()
+        .map(|d| (d.range, d.message.as_str()))
+        .collect();
+    let range =
+        |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+    assert_eq!(
+        found,
+        vec![
+            (range(0, 13, 29), "Invalid value 'exchange' for setting 'dialect'"),
+            (range(0, 30, 36), "Expected 'name=value' in directive, found 'strict'"),
+            (range(0, 37, 46), "Unknown setting 'verbose' in directive"),
+            (range(2, 0, 24), "Settings directives only apply before the first statement"),
+            (range(3, 30, 42), "Unknown diagnostic code 'no-such-code'"),
+            (range(4, 2, 18), "Unknown directive 'sieve-lsp-enable'"),
+        ]
+    );
+    assert_eq!(
+        with_code(&diagnostics, "invalid-directive")[0].severity,
+        Some(DiagnosticSeverity::WARNING)
+    );
+}
+
+#[tokio::test]
+async fn test_suppressions() {
+    let text = "stop;\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(with_code(&diagnostics, "unreachable-code").len(), 1, "{:?}", diagnostics);
+
+    let text = "stop;\nkeep; # sieve-lsp-disable-line unreachable-code\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unreachable-code").is_empty(), "{:?}", diagnostics);
+
+    // Other codes on the line are still reported
+    let text = "stop;\n# sieve-lsp-disable-next-line missing-require\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(with_code(&diagnostics, "unreachable-code").len(), 1, "{:?}", diagnostics);
+
+    let text = "# sieve-lsp-disable missing-require\nfileinto \"Archive\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+
+    // A directive naming only unknown codes suppresses nothing
+    let text = "stop;\n# sieve-lsp-disable-next-line no-such-code\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(with_code(&diagnostics, "unreachable-code").len(), 1, "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_settings_overrides() {
+    let text = "require \"vacation\";\nvacation \"Away\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+
+    let text = format!("# sieve-lsp: dialect=gmail-forwarding\n{}", text);
+    let diagnostics = diagnostics_for(&text).await;
+    assert!(!with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+}
@@ -0,0 +1,87 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::highlight::highlights;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn found(text: &str, line: u32, character: u32) -> Vec<(Range, DocumentHighlightKind)> {
+    highlights(text, &parse(text), Position::new(line, character))
+        .into_iter()
+        .map(|h| (h.range, h.kind.unwrap()))
+        .collect()
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+const CHAIN: &str =
+    "if true {\n  keep;\n} elsif false {\n  discard;\n} else {\n  stop;\n}\nkeep;\n";
+
+#[test]
+fn test_if_chain_keywords_and_braces() {
+    let text = DocumentHighlightKind::TEXT;
+    let expected = vec![
+        (range(0, 0, 2), text),
+        (range(0, 8, 9), text),
+        (range(2, 0, 1), text),
+        (range(2, 2, 7), text),
+        (range(2, 14, 15), text),
+        (range(4, 0, 1), text),
+        (range(4, 2, 6), text),
+        (range(4, 7, 8), text),
+        (range(6, 0, 1), text),
+    ];
+    assert_eq!(found(CHAIN, 0, 1), expected);
+    // Any keyword of the chain highlights the whole chain
+    assert_eq!(found(CHAIN, 4, 3), expected);
+}
+
+#[test]
+fn test_block_braces() {
+    let text = DocumentHighlightKind::TEXT;
+    assert_eq!(found(CHAIN, 3, 0), vec![]);
+    assert_eq!(found(CHAIN, 2, 14), vec![(range(2, 14, 15), text), (range(4, 0, 1), text)]);
+}
+
+#[test]
+fn test_variable_usages() {
+    let text = "set \"Folder\" \"Spam\";\n\
+                if header :contains \"subject\" \"${folder}\" {\n  \
+                fileinto \"INBOX.${folder}\";\n  set \"other\" \"${folder}.${other}\";\n}\n";
+    let expected = vec![
+        (range(0, 5, 11), DocumentHighlightKind::WRITE),
+        (range(1, 31, 40), DocumentHighlightKind::READ),
+        (range(2, 18, 27), DocumentHighlightKind::READ),
+        (range(3, 15, 24), DocumentHighlightKind::READ),
+    ];
+    // From the assignment and from a reference alike
+    assert_eq!(found(text, 0, 7), expected);
+    assert_eq!(found(text, 2, 22), expected);
+    // Plain text in a string highlights nothing
+    assert_eq!(found(text, 2, 13), vec![]);
+}
+
+#[tokio::test]
+async fn test_document_highlight_request() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///highlight.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), CHAIN.to_string(), 1));
+    let highlights = server
+        .document_highlight(DocumentHighlightParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(2, 4),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(highlights.len(), 9);
+    assert_eq!(highlights[0].range, range(0, 0, 2));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::*;
+use tower_lsp::lsp_types::*;
+
+#[tokio::test]
+async fn test_duplicates_within_and_across_requires() {
+    let text = "require [\"fileinto\", \"fileinto\"];\n\
+                require \"FileInto\";\n\
+                fileinto \"A\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "duplicate-require");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "Extension 'fileinto' is already required");
+    assert_eq!(found[0].range.start, Position::new(0, 21));
+    let related = found[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start, Position::new(0, 9));
+    assert_eq!(found[1].message, "Extension 'FileInto' is already required");
+
+    // Within a list the fix rewrites the statement, a lone duplicate statement goes away
+    let data = found[0].data.as_ref().unwrap();
+    assert_eq!(data["replacement"], "require \"fileinto\";");
+    let data = found[1].data.as_ref().unwrap();
+    assert_eq!(data["replacement"], "");
+    assert_eq!(data["range"]["start"]["line"], 1);
+    assert_eq!(data["range"]["end"]["line"], 2);
+}
+
+#[tokio::test]
+async fn test_capability_provided_by_another() {
+    let text = "require [\"spamtest\", \"spamtestplus\", \"relational\"];\n\
+                if spamtest :percent :value \"gt\" \"50\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "duplicate-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "Extension 'spamtest' is already provided by 'spamtestplus'");
+    assert_eq!(found[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+    let data = found[0].data.as_ref().unwrap();
+    assert_eq!(data["title"], "Remove redundant require of \"spamtest\"");
+    assert_eq!(data["replacement"], "require [\"spamtestplus\", \"relational\"];");
+}
+
+#[tokio::test]
+async fn test_distinct_requires_are_fine() {
+    let text = "require [\"fileinto\", \"reject\", \"ereject\"];\n\
+                fileinto \"A\";\nif size :over 1M { ereject \"No\"; } else { reject \"No\"; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "duplicate-require").is_empty(), "{:?}", diagnostics);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::requires::used_capabilities;
+use std::collections::BTreeMap;
+
+#[tokio::test]
+async fn test_valid_editheader_passes() {
+    let text = "require \"editheader\";\n\
+                addheader \"X-Filtered\" \"yes\";\n\
+                addheader :last \"X-Trace\" \"sieve\";\n\
+                deleteheader :index 1 :last \"Received\";\n\
+                deleteheader :contains \"X-Spam\" [\"yes\", \"maybe\"];\n";
+    let diagnostics = diagnostics_for(text).await;
+    for code in ["invalid-editheader", "invalid-syntax", "missing-require"] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+
+    let used: Vec<String> = used_capabilities(&parse(text), &BTreeMap::new())
+        .into_iter()
+        .collect();
+    assert_eq!(used, ["editheader"]);
+}
+
+#[tokio::test]
+async fn test_editheader_arguments() {
+    let text = "require \"editheader\";\n\
+                addheader :index 1 \"X-A\" \"b\";\n\
+                addheader \"X-Only-Name\";\n\
+                deleteheader :last \"X-B\";\n\
+                deleteheader :index 0 \"X-C\";\n\
+                deleteheader \"Bad Name\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-editheader")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (1, "'addheader' does not accept ':index'"),
+            (2, "'addheader' expects a field name and a value"),
+            (3, "':last' requires ':index'"),
+            (4, "':index' counts from 1"),
+            (
+                5,
+                "Invalid header name 'Bad Name': field names cannot contain spaces, colons or control characters"
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_editheader_requires_extension() {
+    let diagnostics = diagnostics_for("addheader \"X-A\" \"b\";\n").await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 1, "{:?}", diagnostics);
+    assert!(missing[0].message.contains("'editheader'"));
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::{EncodedValue, decode_encoded_characters, encoded_characters};
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_decoding() {
+    assert_eq!(decode_encoded_characters("${hex: 53 75 62}ject"), "Subject");
+    assert_eq!(decode_encoded_characters("${UNICODE:48 0069}!"), "Hi!");
+    assert_eq!(decode_encoded_characters("caf${hex:c3 a9}"), "café");
+    assert_eq!(decode_encoded_characters("${unicode:1F600}"), "😀");
+    // Variables and unknown namespaces are left alone
+    assert_eq!(decode_encoded_characters("${name} ${env:x}"), "${name} ${env:x}");
+}
+
+#[test]
+fn test_malformed_and_invalid_sequences() {
+    let sequences = encoded_characters("a${hex:4G}b${hex:}c${unicode:D800}${hex:ff}");
+    let values: Vec<&EncodedValue> = sequences.iter().map(|s| &s.value).collect();
+    assert_eq!(
+        values,
+        vec![
+            &EncodedValue::Malformed("'4G' is not a hexadecimal octet".to_string()),
+            &EncodedValue::Malformed("no value is encoded".to_string()),
+            &EncodedValue::Invalid("U+D800 is not a Unicode character".to_string()),
+            &EncodedValue::Invalid("the octets are not valid UTF-8".to_string()),
+        ]
+    );
+    assert_eq!((sequences[0].start, sequences[0].end), (1, 10));
+    // Malformed sequences stay in the value as they are
+    assert_eq!(decode_encoded_characters("a${hex:4G}b"), "a${hex:4G}b");
+}
+
+#[tokio::test]
+async fn test_encoded_characters_need_the_extension() {
+    let text = "if header :is \"${hex:53 75 62 6a 65 63 74}\" \"x\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 1, "{:?}", diagnostics);
+    assert_eq!(missing[0].range.start, Position::new(0, 15));
+    assert_eq!(missing[0].range.end, Position::new(0, 42));
+}
+
+#[tokio::test]
+async fn test_strings_are_analyzed_decoded() {
+    // "Subject:" with an encoded colon is still an invalid header name
+    let text = "require \"encoded-character\";\n\
+                if header :is \"Sub${hex:6a 65 63 74}\" \"x\" { discard; }\n\
+                if header :is \"Subject${hex:3a}\" \"x\" { discard; }\n\
+                if header :is \"${unicode:110000}\" \"x\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unused-require").is_empty(), "{:?}", diagnostics);
+
+    let names = with_code(&diagnostics, "invalid-header-name");
+    assert_eq!(names.len(), 1, "{:?}", diagnostics);
+    assert_eq!(names[0].range.start.line, 2);
+    assert!(names[0].message.contains("'Subject:'"), "{}", names[0].message);
+
+    let invalid = with_code(&diagnostics, "invalid-encoding");
+    assert_eq!(invalid.len(), 1, "{:?}", diagnostics);
+    assert_eq!(invalid[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert!(invalid[0].message.contains("U+110000"), "{}", invalid[0].message);
+}
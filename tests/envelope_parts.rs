@@ -0,0 +1,131 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::requires;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[tokio::test]
+async fn test_base_parts_need_no_extension() {
+    let text = "require \"envelope\";\n\
+                if envelope :all [\"from\", \"TO\"] \"a@example.com\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unknown-envelope-part").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_extension_parts_need_their_require() {
+    let text = "require \"envelope\";\nif envelope \"auth\" \"a@example.com\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "missing-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        found[0].message,
+        "Envelope part 'auth' requires the \"envelope-auth\" extension"
+    );
+    assert_eq!(found[0].range, Range::new(Position::new(1, 12), Position::new(1, 18)));
+
+    let text = "require [\"envelope\", \"envelope-dsn\"];\n\
+                if envelope :is \"notify\" \"never\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_unknown_parts_are_flagged() {
+    let text = "require \"envelope\";\nif envelope \"sender\" \"a@example.com\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "unknown-envelope-part");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "Unknown envelope part 'sender'");
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::WARNING));
+
+    // Parts built from variables are only known at runtime
+    let text = "require [\"envelope\", \"variables\"];\nset \"part\" \"to\";\n\
+                if envelope \"${part}\" \"a@example.com\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unknown-envelope-part").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_parts_are_checked_against_the_dialect() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "server_dialect": "fastmail" })).unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "require [\"envelope\", \"envelope-dsn\"];\n\
+                if envelope \"orcpt\" \"a@example.com\" { stop; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let found = with_code(&diagnostics, "unsupported-feature");
+    assert!(
+        found
+            .iter()
+            .any(|d| d.message == "Fastmail does not support the envelope part 'orcpt'"),
+        "{:?}",
+        diagnostics
+    );
+}
+
+#[test]
+fn test_parts_are_counted_as_used_capabilities() {
+    let script = parse(
+        "require [\"envelope\", \"envelope-deliverby\"];\n\
+         if envelope \"bymode\" \"notify\" { stop; }\n",
+    );
+    let used = requires::used_capabilities(&script, &Default::default());
+    assert!(used.iter().any(|c| c == "envelope-deliverby"), "{:?}", used);
+}
+
+#[tokio::test]
+async fn test_part_completions_follow_the_dialect() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if envelope :all [\"to\", \"";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let position = Position::new(0, text.len() as u32);
+
+    let labels =
+        |items: Vec<CompletionItem>| items.into_iter().map(|i| i.label).collect::<Vec<_>>();
+    let all = labels(server.get_completions(&uri, position).await);
+    assert!(all.contains(&"auth".to_string()), "{:?}", all);
+    assert!(all.contains(&"from".to_string()), "{:?}", all);
+    assert!(!all.contains(&"fileinto".to_string()), "{:?}", all);
+
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "server_dialect": "fastmail" })).unwrap();
+    let limited = labels(server.get_completions(&uri, position).await);
+    assert_eq!(limited, vec!["from", "to"]);
+}
+
+#[tokio::test]
+async fn test_part_hover() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let text = "require [\"envelope\", \"envelope-auth\"];\n\
+                if envelope \"auth\" \"a@example.com\" { stop; }\n";
+    let script = parse(text);
+    let (doc, range) = server
+        .get_envelope_part_documentation(&script, Position::new(1, 14))
+        .expect("hover");
+    assert!(doc.starts_with("**auth**"), "{}", doc);
+    assert!(doc.contains("Requires the \"envelope-auth\" extension"), "{}", doc);
+    assert_eq!(range, Range::new(Position::new(1, 12), Position::new(1, 18)));
+
+    // Keys of the test are not parts
+    assert!(
+        server
+            .get_envelope_part_documentation(&script, Position::new(1, 22))
+            .is_none()
+    );
+}
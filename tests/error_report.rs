@@ -0,0 +1,90 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::errors::{ErrorKind, ErrorLog, InternalError, MAX_ERRORS};
+use sieve_language_server::lsp::COMMAND_COPY_ERROR_REPORT;
+use sieve_language_server::snapshot::content_hash;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[test]
+fn test_error_log_keeps_the_most_recent_errors() {
+    let mut log = ErrorLog::default();
+    assert!(log.last().is_none());
+    for i in 0..MAX_ERRORS + 5 {
+        log.record(InternalError::new(ErrorKind::Io, format!("error {}", i)));
+    }
+    assert_eq!(log.len(), MAX_ERRORS);
+    assert_eq!(log.errors().next().unwrap().message, "error 5");
+    assert_eq!(log.last().unwrap().message, format!("error {}", MAX_ERRORS + 4));
+}
+
+#[tokio::test]
+async fn test_invalid_settings_are_recorded() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    assert_eq!(server.last_error().await.unwrap(), None);
+
+    let settings = json!({ "max_errors": "many" });
+    server
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: settings.clone(),
+        })
+        .await;
+
+    let error = server.last_error().await.unwrap().expect("error recorded");
+    assert_eq!(error.kind, ErrorKind::Parse);
+    assert!(error.message.starts_with("Invalid settings"), "{}", error.message);
+    assert_eq!(error.input_hash, Some(content_hash(&settings.to_string())));
+    let current = serde_json::to_value(&*server.settings.read().await).unwrap();
+    assert_eq!(current["max_errors"], 100);
+
+    let value = serde_json::to_value(&error).unwrap();
+    assert_eq!(value["kind"], "parse");
+    assert!(value.get("inputHash").is_some());
+    assert!(value.get("uri").is_none());
+}
+
+#[tokio::test]
+async fn test_error_report_anonymizes_the_failing_script() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///tmp/crash.sieve").unwrap();
+    let text = "redirect \"boss@work.example\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+
+    let execute = || {
+        server.execute_command(ExecuteCommandParams {
+            command: COMMAND_COPY_ERROR_REPORT.to_string(),
+            arguments: Vec::new(),
+            work_done_progress_params: Default::default(),
+        })
+    };
+    assert_eq!(execute().await.unwrap(), None);
+
+    server
+        .record_error(
+            InternalError::new(ErrorKind::Panic, "index out of bounds")
+                .with_uri(&uri)
+                .with_input_hash(content_hash(text)),
+        )
+        .await;
+    let report = execute().await.unwrap().unwrap();
+    let report = report.as_str().unwrap();
+
+    assert!(report.contains(&format!("- Server version: {}", env!("CARGO_PKG_VERSION"))));
+    assert!(report.contains("- Kind: panic"));
+    assert!(report.contains("- Message: index out of bounds"));
+    assert!(report.contains("\"max_errors\": 100"));
+    assert!(report.contains("redirect \"user1@example.com\";"));
+    assert!(!report.contains("boss@work.example"));
+    assert!(!report.contains("may no longer reproduce"));
+
+    // Edits after the failure are pointed out
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), "keep;\n".to_string(), 2));
+    let report = execute().await.unwrap().unwrap();
+    assert!(report.as_str().unwrap().contains("may no longer reproduce"));
+}
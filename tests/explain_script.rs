@@ -0,0 +1,131 @@
+use serde_json::{Value, json};
+use sieve_language_server::datastructures::*;
+use sieve_language_server::explain::{Condition, explain, normalize};
+use sieve_language_server::lsp::COMMAND_EXPLAIN_SCRIPT;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require [\"fileinto\", \"imap4flags\"];\n\
+                      if header :contains \"Subject\" \"digest\" {\n\
+                      \x20   fileinto :flags \"\\\\Seen\" \"News\";\n\
+                      \x20   stop;\n\
+                      } elsif size :over 1M {\n\
+                      \x20   if address :domain \"From\" \"example.com\" { keep; }\n\
+                      \x20   discard;\n\
+                      } else {\n\
+                      \x20   keep;\n\
+                      }\n";
+
+#[test]
+fn test_rules_carry_their_full_condition() {
+    let explanation = explain(&parse(SCRIPT));
+    assert_eq!(explanation.requires, ["fileinto", "imap4flags"]);
+    let conditions: Vec<Value> = explanation
+        .rules
+        .iter()
+        .map(|rule| serde_json::to_value(&rule.condition).unwrap())
+        .collect();
+    let digest = json!({ "test": {
+        "name": "header",
+        "matchType": ":contains",
+        "comparator": "i;ascii-casemap",
+        "arguments": ["subject", "digest"],
+    }});
+    let large = json!({ "test": {
+        "name": "size",
+        "tags": { ":over": true },
+        "arguments": [1048576],
+    }});
+    let from = json!({ "test": {
+        "name": "address",
+        "matchType": ":is",
+        "comparator": "i;ascii-casemap",
+        "addressPart": ":domain",
+        "arguments": ["from", "example.com"],
+    }});
+    assert_eq!(
+        conditions,
+        [
+            digest.clone(),
+            json!({ "allOf": [{ "not": digest }, large, from] }),
+            json!({ "allOf": [{ "not": digest }, large] }),
+            json!({ "allOf": [{ "not": digest }, { "not": large }] }),
+        ]
+    );
+
+    let actions: Vec<Vec<&str>> = explanation
+        .rules
+        .iter()
+        .map(|rule| rule.actions.iter().map(|a| a.command.as_str()).collect())
+        .collect();
+    assert_eq!(actions, [vec!["fileinto", "stop"], vec!["keep"], vec!["discard"], vec!["keep"]]);
+    let fileinto = &explanation.rules[0].actions[0];
+    assert_eq!(fileinto.tags[":flags"], json!("\\Seen"));
+    assert_eq!(fileinto.arguments, [json!("News")]);
+    // A rule starts at its branch
+    assert_eq!(explanation.rules[0].range.start, Position::new(1, 0));
+    assert_eq!(explanation.rules[3].range.start, Position::new(7, 2));
+}
+
+#[test]
+fn test_normalize_folds_lists_and_negations() {
+    let script = parse(
+        "if allof (true, not not exists \"X-Spam\", allof (size :under 10K, anyof (false))) \
+         { keep; }\n",
+    );
+    let condition = normalize(&script.commands[0].tests[0]);
+    assert_eq!(condition, Condition::Constant(false));
+
+    let script =
+        parse("if anyof (anyof (exists \"A\", exists \"b\"), not true, header \"c\" \"d\") {}");
+    let condition = serde_json::to_value(normalize(&script.commands[0].tests[0])).unwrap();
+    assert_eq!(
+        condition,
+        json!({ "anyOf": [
+            { "test": { "name": "exists", "arguments": ["a"] } },
+            { "test": { "name": "exists", "arguments": ["b"] } },
+            { "test": {
+                "name": "header",
+                "matchType": ":is",
+                "comparator": "i;ascii-casemap",
+                "arguments": ["c", "d"],
+            }},
+        ]})
+    );
+}
+
+#[tokio::test]
+async fn test_explain_command_uses_client_positions() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///explain.sieve").unwrap();
+    let text = "if exists \"😀\" { keep; } discard;\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+
+    let result = server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_EXPLAIN_SCRIPT.to_string(),
+            arguments: vec![json!(uri)],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let rules = result["rules"].as_array().unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[1]["condition"], json!({ "constant": true }));
+    assert_eq!(rules[1]["actions"][0]["command"], "discard");
+    assert_eq!(rules[1]["range"]["start"], json!({ "line": 0, "character": 25 }));
+
+    let missing = server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_EXPLAIN_SCRIPT.to_string(),
+            arguments: vec![json!("file:///nowhere/missing.sieve")],
+            work_done_progress_params: Default::default(),
+        })
+        .await;
+    assert!(missing.is_err());
+}
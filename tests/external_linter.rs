@@ -0,0 +1,34 @@
+use sieve_language_server::external::*;
+use tower_lsp::lsp_types::*;
+
+fn settings(json: serde_json::Value) -> ExternalLinterSettings {
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn test_default_patterns_parse_sievec_output() {
+    let linter = settings(serde_json::json!({ "command": "sievec" }));
+    assert_eq!(linter.args, vec!["-c", FILE_PLACEHOLDER]);
+
+    let output = "rules.sieve: line 3: error: unknown command 'fileinot'.\nrules.sieve: line 7: warning: deprecated.\nrules.sieve: error: validation failed.\n";
+    let diagnostics = parse_linter_output(output, &linter);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].range.start, Position::new(2, 0));
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(diagnostics[0].message, "unknown command 'fileinot'.");
+    assert_eq!(diagnostics[0].source.as_deref(), Some("sieve-external"));
+    assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::WARNING));
+}
+
+#[test]
+fn test_custom_pattern_with_column() {
+    let linter = settings(serde_json::json!({
+        "command": "provider-check",
+        "patterns": [r"^(?P<line>\d+):(?P<column>\d+) (?P<message>.+)$"],
+        "source": "provider"
+    }));
+    let diagnostics = parse_linter_output("4:10 bad folder\nnoise\n", &linter);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range.start, Position::new(3, 9));
+    assert_eq!(diagnostics[0].source.as_deref(), Some("provider"));
+}
@@ -0,0 +1,114 @@
+use sieve_language_server::fixtures::{self, Expectation, FixtureResult};
+use std::path::Path;
+use std::process::Command;
+
+const FILTER: &str = "require \"fileinto\";\n\
+                      if header :contains \"subject\" \"digest\" { fileinto \"News\"; stop; }\n";
+
+fn write(dir: &Path, name: &str, content: &str) {
+    std::fs::write(dir.join(name), content).unwrap();
+}
+
+#[test]
+fn test_discover_pairs_messages_with_scripts() {
+    let dir = std::env::temp_dir().join(format!("sieve-fixtures-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    for name in ["a.sieve", "a.b.sieve", "nested/c.sieve"] {
+        write(&dir, name, "keep;\n");
+    }
+    for name in ["a.eml", "a.x.eml", "a.b.eml", "a.b.y.eml", "ab.eml", "nested/c.eml"] {
+        write(&dir, name, "Subject: x\n\n");
+    }
+
+    let found: Vec<(String, String)> = fixtures::discover(&dir)
+        .into_iter()
+        .map(|fixture| {
+            let name = |path: &Path| path.strip_prefix(&dir).unwrap().display().to_string();
+            assert_eq!(fixture.expected, fixture.message.with_extension("expected"));
+            (name(&fixture.script), name(&fixture.message))
+        })
+        .collect();
+    let expected = [
+        ("a.b.sieve", "a.b.eml"),
+        ("a.b.sieve", "a.b.y.eml"),
+        ("a.sieve", "a.eml"),
+        ("a.sieve", "a.x.eml"),
+        ("nested/c.sieve", "nested/c.eml"),
+    ];
+    let expected: Vec<(String, String)> =
+        expected.iter().map(|(s, m)| (s.to_string(), m.to_string())).collect();
+    assert_eq!(found, expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_run_compares_actions() {
+    let dir = std::env::temp_dir().join(format!("sieve-fixture-run-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write(&dir, "filter.sieve", FILTER);
+    write(&dir, "filter.eml", "Subject: Weekly digest\n\nx\n");
+    write(&dir, "filter.expected", "actions:\n  - fileinto \"News\"\nimplicit_keep: false\n");
+    write(&dir, "filter.personal.eml", "Subject: Hello\n\ny\n");
+    write(&dir, "filter.personal.expected", "actions: [fileinto \"News\"]\n");
+    write(&dir, "filter.other.eml", "Subject: Hello\n\ny\n");
+
+    let results: Vec<FixtureResult> = fixtures::discover(&dir).iter().map(fixtures::run).collect();
+    assert_eq!(results[0], FixtureResult::Passed);
+    let FixtureResult::Error(error) = &results[1] else {
+        panic!("expected a missing expectation, got {:?}", results[1]);
+    };
+    assert!(error.contains("filter.other.expected"), "{}", error);
+    assert_eq!(
+        results[2],
+        FixtureResult::Failed {
+            expected: Expectation {
+                actions: vec!["fileinto \"News\"".to_string()],
+                implicit_keep: None,
+            },
+            actual: Expectation {
+                actions: vec![],
+                implicit_keep: Some(true),
+            },
+        }
+    );
+
+    // Only the given fields are compared
+    write(&dir, "filter.personal.expected", "implicit_keep: true\n");
+    assert_eq!(fixtures::run(&fixtures::discover(&dir)[2]), FixtureResult::Passed);
+    write(&dir, "filter.personal.expected", "actions: []\nkeep: true\n");
+    let FixtureResult::Error(error) = fixtures::run(&fixtures::discover(&dir)[2]) else {
+        panic!("expected an invalid expectation");
+    };
+    assert!(error.contains("unknown field"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    let dir = std::env::temp_dir().join(format!("sieve-fixture-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let run = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+            .args(["test", dir.to_str().unwrap()])
+            .output()
+            .unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+
+    // Nothing to test fails the run
+    assert_eq!(run().0, Some(1));
+
+    write(&dir, "filter.sieve", FILTER);
+    write(&dir, "filter.eml", "Subject: Weekly digest\n\nx\n");
+    write(&dir, "filter.expected", "actions:\n  - fileinto \"News\"\n");
+    let (code, output) = run();
+    assert_eq!(code, Some(0), "{}", output);
+    assert!(output.ends_with("1 passed, 0 failed\n"), "{}", output);
+
+    write(&dir, "filter.expected", "actions:\n  - keep\n");
+    let (code, output) = run();
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(output.contains("FAILED  "), "{}", output);
+    assert!(output.contains("  actual:\n    actions:\n    - fileinto \"News\""), "{}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
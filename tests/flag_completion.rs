@@ -0,0 +1,77 @@
+mod common;
+
+use common::server_with;
+use serde_json::json;
+use tower_lsp::LanguageServer;
+use tower_lsp::lsp_types::*;
+
+async fn flag_completions(text: &str, position: Position) -> Vec<CompletionItem> {
+    let (service, uri) = server_with(json!({ "keyword_flags": ["$Label1", "Junk"] }), text).await;
+    service
+        .inner()
+        .get_completions(&uri, position)
+        .await
+        .into_iter()
+        .filter(|item| item.kind == Some(CompletionItemKind::CONSTANT))
+        .collect()
+}
+
+fn edit(item: &CompletionItem) -> (Range, &str) {
+    let Some(CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+        panic!("expected a text edit");
+    };
+    (edit.range, &edit.new_text)
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+#[tokio::test]
+async fn test_system_flags_are_escaped() {
+    let text = "require \"imap4flags\";\naddflag \"";
+    let items = flag_completions(text, Position::new(1, 9)).await;
+    let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+    assert_eq!(
+        labels,
+        vec!["$Label1", "Junk", "\\Answered", "\\Deleted", "\\Draft", "\\Flagged", "\\Seen"]
+    );
+    let seen = items.iter().find(|item| item.label == "\\Seen").unwrap();
+    assert_eq!(edit(seen), (range(1, 9, 9), "\\\\Seen"));
+    assert_eq!(seen.filter_text.as_deref(), Some("\\\\Seen"));
+}
+
+#[tokio::test]
+async fn test_flag_after_other_flags_is_replaced() {
+    // Only the flag being typed is replaced, a lone backslash included
+    let text = "setflag \"\\\\Seen \\";
+    let items = flag_completions(text, Position::new(0, 17)).await;
+    let flagged = items.iter().find(|item| item.label == "\\Flagged").unwrap();
+    assert_eq!(edit(flagged), (range(0, 16, 17), "\\\\Flagged"));
+
+    // Variable names, lists and :flags arguments
+    let text = "removeflag \"flags\" [\"\\\\Seen\", \"\\\\Fl";
+    let items = flag_completions(text, Position::new(0, 36)).await;
+    assert_eq!(edit(&items[0]), (range(0, 32, 36), "$Label1"));
+    let text = "fileinto :flags \"";
+    assert_eq!(flag_completions(text, Position::new(0, 17)).await.len(), 7);
+}
+
+#[tokio::test]
+async fn test_other_strings_get_no_flags() {
+    let text = "addflag \"\\\\Seen\";\nfileinto \"";
+    assert!(flag_completions(text, Position::new(1, 10)).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_system_flags_are_documented() {
+    let (service, uri) = server_with(json!({}), "addflag \"").await;
+    let server = service.inner();
+    let items = server.get_completions(&uri, Position::new(0, 9)).await;
+    let item = items.into_iter().find(|item| item.label == "\\Deleted").unwrap();
+    let resolved = server.completion_resolve(item).await.unwrap();
+    let Some(Documentation::MarkupContent(doc)) = resolved.documentation else {
+        panic!("missing documentation");
+    };
+    assert!(doc.value.contains("marked for removal"), "{}", doc.value);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::mailbox::{MailboxConvention, parse_folder_list};
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+async fn folder_completions(
+    settings: serde_json::Value,
+    text: &str,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let (service, uri) = server_with(settings, text).await;
+    service
+        .inner()
+        .get_completions(&uri, position)
+        .await
+        .into_iter()
+        .filter(|item| item.kind == Some(CompletionItemKind::FOLDER))
+        .collect()
+}
+
+fn labels(items: &[CompletionItem]) -> Vec<&str> {
+    items.iter().map(|item| item.label.as_str()).collect()
+}
+
+#[test]
+fn test_folder_list_and_hierarchy() {
+    assert_eq!(
+        parse_folder_list("# exported from the server\nINBOX\n\n  Lists/Rust  \nArchive\n"),
+        vec!["INBOX", "Lists/Rust", "Archive"]
+    );
+    let convention = MailboxConvention {
+        separator: Some('/'),
+        ..Default::default()
+    };
+    let folders = convention.folder_hierarchy(["Lists/Rust/Announce", "Lists/Go", "/abs"]);
+    assert_eq!(
+        folders.into_iter().collect::<Vec<_>>(),
+        vec!["/abs", "Lists", "Lists/Go", "Lists/Rust", "Lists/Rust/Announce"]
+    );
+}
+
+#[tokio::test]
+async fn test_fileinto_completes_configured_folders() {
+    let settings = json!({ "mailbox": { "separator": "/", "folders": ["Lists/Rust", "Archive"] } });
+    let text = "require [\"fileinto\", \"copy\"];\nfileinto :copy \"Lists/R\";\n";
+    let items = folder_completions(settings.clone(), text, Position::new(1, 22)).await;
+    assert_eq!(labels(&items), vec!["Archive", "Lists", "Lists/Rust"]);
+    // The typed path, separator included, is replaced
+    assert_eq!(
+        items[2].text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit::new(
+            Range::new(Position::new(1, 16), Position::new(1, 22)),
+            "Lists/Rust".to_string(),
+        )))
+    );
+
+    // Only the mailbox argument of fileinto gets folders
+    let text = "if header :is \"subject\" \"Li\" { fileinto \"A\"; }\n";
+    assert!(folder_completions(settings.clone(), text, Position::new(0, 27)).await.is_empty());
+    let text = "fileinto :flags \"\\\\Seen\" \"";
+    let items = folder_completions(settings, text, Position::new(0, 26)).await;
+    assert_eq!(labels(&items), vec!["Archive", "Lists", "Lists/Rust"]);
+}
+
+#[tokio::test]
+async fn test_folders_file_in_workspace() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let root = std::env::temp_dir().join(format!("sieve-folders-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("folders.txt"), "INBOX.Receipts\nINBOX.Travel\n").unwrap();
+    *server.workspace_root.write().await = Some(root.clone());
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "mailbox": { "folders": ["Archive"] } })).unwrap();
+
+    let folders: Vec<String> = server.folder_names().await.into_iter().collect();
+    assert_eq!(folders, vec!["Archive", "INBOX.Receipts", "INBOX.Travel"]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,139 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::format::{self, FormatOptions};
+use std::process::Command;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const MESSY: &str = "\n\nrequire [\"fileinto\",\n\"variables\"];   \n\
+                     if anyof (header :contains \"subject\" \"x\",\nexists \"list-id\")\n{\n\
+                     fileinto \"A\";\n    }\nelsif header :is \"from\"\n\"a@b\" {\n  # note\n\n\n\
+                     if true { keep; }\n     set \"x\" text:\n  body  \n.\n;\n}\n\n\n";
+
+const TIDY: &str = "require [\"fileinto\",\n    \"variables\"];\n\
+                    if anyof (header :contains \"subject\" \"x\",\n    exists \"list-id\")\n{\n    \
+                    fileinto \"A\";\n}\nelsif header :is \"from\"\n    \"a@b\" {\n    # note\n\n    \
+                    if true { keep; }\n    set \"x\" text:\n  body  \n.\n    ;\n}\n";
+
+/// Apply edits that do not overlap, as an editor would
+fn apply(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|e| (e.range.start.line, e.range.start.character));
+    let mut result = text.to_string();
+    for edit in edits.iter().rev() {
+        let offset = |p: Position| {
+            let line_start: usize =
+                text.split_inclusive('\n').take(p.line as usize).map(str::len).sum();
+            let line = text[line_start..].split('\n').next().unwrap_or("");
+            line_start
+                + line
+                    .char_indices()
+                    .nth(p.character as usize)
+                    .map_or(line.len(), |(i, _)| i)
+        };
+        let (start, end) = (offset(edit.range.start), offset(edit.range.end));
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+#[test]
+fn test_layout_is_normalized() {
+    let options = FormatOptions::default();
+    assert_eq!(format::format(MESSY, &options), TIDY);
+    assert_eq!(format::format(TIDY, &options), TIDY, "formatting is idempotent");
+    assert!(format::edits(TIDY, &options).is_empty());
+}
+
+#[test]
+fn test_protected_text_is_kept() {
+    let options = FormatOptions::default();
+    let text = "if true {\n/* keep\n      this */\nset \"a\" \"two  \n  lines\";\n}";
+    assert_eq!(
+        format::format(text, &options),
+        "if true {\n    /* keep\n      this */\n    set \"a\" \"two  \n  lines\";\n}\n"
+    );
+
+    // Line endings are preserved
+    assert_eq!(
+        format::format("if true {\r\nkeep;\r\n}\r\n", &options),
+        "if true {\r\n    keep;\r\n}\r\n"
+    );
+}
+
+#[test]
+fn test_edits_match_formatted_text() {
+    let options = FormatOptions {
+        indent: "\t".to_string(),
+        ..FormatOptions::default()
+    };
+    let expected = format::format(MESSY, &options);
+    assert!(expected.contains("\n\tfileinto \"A\";\n"), "{}", expected);
+    assert_eq!(apply(MESSY, &format::edits(MESSY, &options)), expected);
+    let unterminated = "keep;";
+    assert_eq!(apply(unterminated, &format::edits(unterminated, &options)), "keep;\n");
+}
+
+#[tokio::test]
+async fn test_formatting_request() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if true {\n  # größe\nkeep;\n}\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let edits = server
+        .formatting(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options: FormattingOptions {
+                tab_size: 2,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        edits,
+        vec![TextEdit {
+            range: Range::new(Position::new(2, 0), Position::new(2, 5)),
+            new_text: "  keep;".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_fmt_check_and_write() {
+    let dir = std::env::temp_dir().join(format!("sieve-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let messy = dir.join("messy.sieve");
+    let tidy = dir.join("tidy.sieve");
+    std::fs::write(&messy, MESSY).unwrap();
+    std::fs::write(&tidy, TIDY).unwrap();
+    let fmt = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+            .arg("fmt")
+            .args(args)
+            .output()
+            .unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+    let (messy_path, tidy_path) = (messy.to_str().unwrap(), tidy.to_str().unwrap());
+
+    let (code, stdout) = fmt(&["--check", messy_path, tidy_path]);
+    assert_eq!(code, Some(1));
+    assert_eq!(stdout, format!("Would reformat: {}\n", messy_path));
+    assert_eq!(std::fs::read_to_string(&messy).unwrap(), MESSY, "--check never writes");
+
+    let (code, stdout) = fmt(&["--write", messy_path, tidy_path]);
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, format!("Reformatted: {}\n", messy_path));
+    assert_eq!(std::fs::read_to_string(&messy).unwrap(), TIDY);
+    assert_eq!(fmt(&["--check", messy_path]).0, Some(0));
+
+    assert_eq!(fmt(&["--check"]).0, Some(2));
+    assert_eq!(fmt(&["--check", "--write", messy_path]).0, Some(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
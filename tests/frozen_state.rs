@@ -0,0 +1,234 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::snapshot::*;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sieve-state-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join(".sieve-lsp")).unwrap();
+    fs::write(dir.join("main.sieve"), "require \"include\";\ninclude \"spam\";\n").unwrap();
+    fs::write(dir.join("spam.sieve"), "discard;\n").unwrap();
+    fs::write(dir.join("old.sieve"), "keep;\n").unwrap();
+    fs::write(dir.join(".sieve-lsp/manifest.json"), r#"{ "entryPoints": ["main.sieve"] }"#)
+        .unwrap();
+    dir
+}
+
+#[test]
+fn test_index_only_parses_changed_scripts() {
+    let dir = workspace("index");
+    let mut index = WorkspaceIndex::default();
+    assert_eq!(index.refresh(&dir), 3);
+    assert_eq!(index.refresh(&dir), 0);
+
+    fs::write(dir.join("old.sieve"), "require \"include\";\ninclude \"spam\";\n").unwrap();
+    fs::remove_file(dir.join("spam.sieve")).unwrap();
+    let parsed = index.refresh(&dir);
+    let scripts: Vec<PathBuf> = index.scripts.keys().cloned().collect();
+    let old = &index.scripts[&dir.join("old.sieve")];
+    let includes = old.includes.clone();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(parsed, 1);
+    assert_eq!(scripts, [dir.join("main.sieve"), dir.join("old.sieve")]);
+    assert_eq!(includes, ["spam"]);
+}
+
+#[test]
+fn test_invalidated_scripts_are_parsed_again() {
+    let dir = workspace("invalidate");
+    let mut index = WorkspaceIndex::default();
+    index.refresh(&dir);
+    index.invalidate(&dir.join("spam.sieve"));
+    let remaining = index.scripts.len();
+    let parsed = index.refresh(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(remaining, 2);
+    assert_eq!(parsed, 1);
+}
+
+#[test]
+fn test_index_orphans_match_include_graph() {
+    let dir = workspace("orphans");
+    let mut index = WorkspaceIndex::default();
+    index.refresh(&dir);
+    let orphans = index.orphans(&[dir.join("main.sieve")]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(orphans, [dir.join("old.sieve")]);
+}
+
+#[tokio::test]
+async fn test_state_survives_restart() {
+    let dir = workspace("restart");
+    let uri = Url::from_file_path(dir.join("main.sieve")).unwrap();
+    let text = fs::read_to_string(dir.join("main.sieve")).unwrap();
+    let external = Diagnostic {
+        message: "checked by sievec".to_string(),
+        source: Some("sievec".to_string()),
+        ..Default::default()
+    };
+
+    {
+        let (service, _socket) = LspService::new(SieveLanguageServer::new);
+        let server = service.inner();
+        *server.workspace_root.write().await = Some(dir.clone());
+        server.refresh_orphaned_scripts().await;
+        server
+            .document_map
+            .insert(uri.clone(), SieveDocument::new(uri.clone(), text.clone(), 7));
+        server
+            .external_diagnostics
+            .insert(uri.clone(), vec![external.clone()]);
+        server.freeze_state().await;
+    }
+
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.workspace_root.write().await = Some(dir.clone());
+    assert!(server.restore_state().await);
+    let indexed = server.workspace_index.read().await.scripts.len();
+    let parsed = server.workspace_index.write().await.refresh(&dir);
+
+    // A changed document does not get the old findings back
+    let other = Url::from_file_path(dir.join("spam.sieve")).unwrap();
+    server.restore_document(&other, "keep;\n").await;
+    server.restore_document(&uri, &text).await;
+    let restored = server.external_diagnostics.get(&uri).map(|d| d.clone());
+    let state = FrozenState::load(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(indexed, 3);
+    assert_eq!(parsed, 0);
+    assert!(server.external_diagnostics.get(&other).is_none());
+    assert_eq!(restored, Some(vec![external]));
+    assert_eq!(state.documents[0].version, 7);
+    assert_eq!(state.capabilities.position_encoding, PositionEncodingKind::UTF16);
+}
+
+#[tokio::test]
+async fn test_state_of_other_workspace_is_ignored() {
+    let dir = workspace("moved");
+    let state = FrozenState {
+        format: STATE_FORMAT,
+        root: dir.join("elsewhere"),
+        capabilities: SessionCapabilities {
+            position_encoding: PositionEncodingKind::UTF16,
+        },
+        documents: Vec::new(),
+        index: WorkspaceIndex::default(),
+    };
+    fs::write(dir.join(STATE_FILE), serde_json::to_string(&state).unwrap()).unwrap();
+    let loaded = FrozenState::load(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(loaded.is_none());
+}
+
+#[test]
+fn test_cache_spares_parsing_unchanged_contents() {
+    let dir = workspace("cache");
+    let cache = IndexCache::new(&dir);
+    let mut index = WorkspaceIndex::default();
+    assert_eq!(index.refresh_cached(&dir, &cache, &|_, _| {}), 3);
+
+    // A new session without a frozen state, and a copy of a known script
+    fs::copy(dir.join("spam.sieve"), dir.join("spam2.sieve")).unwrap();
+    let mut thawed = WorkspaceIndex::default();
+    let parsed = thawed.refresh_cached(&dir, &cache, &|_, _| {});
+    let copy = thawed.scripts[&dir.join("spam2.sieve")].clone();
+
+    // Entries of contents that are gone are pruned
+    fs::remove_file(dir.join("old.sieve")).unwrap();
+    thawed.refresh_cached(&dir, &cache, &|_, _| {});
+    let hash = content_hash("keep;\n");
+    let pruned = cache.load(hash).is_none();
+    let kept = cache.load(content_hash("discard;\n")).is_some();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(parsed, 0);
+    assert_eq!(copy.symbols.len(), 1);
+    assert_eq!(copy.symbols[0].name, "discard");
+    assert!(pruned);
+    assert!(kept);
+}
+
+#[tokio::test]
+async fn test_workspace_symbols_come_from_the_index() {
+    let dir = workspace("symbols");
+    fs::write(dir.join("spam.sieve"), "# Spär\nif true { fileinto \"Spam\"; }\n").unwrap();
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.workspace_root.write().await = Some(dir.clone());
+    server.refresh_orphaned_scripts().await;
+
+    // Open documents are outlined from their current text
+    let uri = Url::from_file_path(dir.join("main.sieve")).unwrap();
+    let text = "require \"fileinto\";\nfileinto \"Spam/Old\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let symbols = server
+        .symbol(WorkspaceSymbolParams {
+            query: "spam".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let found: Vec<(&str, &str, Option<&str>)> = symbols
+        .iter()
+        .map(|s| {
+            let file = s.location.uri.path_segments().unwrap().next_back().unwrap();
+            (file, s.name.as_str(), s.container_name.as_deref())
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("main.sieve", "fileinto Spam/Old", None),
+            ("spam.sieve", "fileinto Spam", Some("if true")),
+        ]
+    );
+    assert_eq!(symbols[1].location.range.start, Position::new(1, 10));
+}
+
+#[tokio::test]
+async fn test_large_workspaces_are_scanned_in_parallel() {
+    let dir = workspace("parallel");
+    for n in 0..INDEX_PROGRESS_THRESHOLD {
+        let text = format!("if size :over {}K {{ discard; }}\n", n);
+        fs::write(dir.join(format!("filter{:02}.sieve", n)), text).unwrap();
+    }
+    let total = INDEX_PROGRESS_THRESHOLD + 3;
+    let reports = Mutex::new(Vec::new());
+    let mut index = WorkspaceIndex::default();
+    let parsed = index.refresh_cached(&dir, &IndexCache::new(&dir), &|done, total| {
+        reports.lock().unwrap().push((done, total));
+    });
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort();
+
+    // The server shows no progress to a client that is not initialized, but still scans
+    fs::write(dir.join("new.sieve"), "stop;\n").unwrap();
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.work_done_progress.write().await = true;
+    *server.workspace_index.write().await = index;
+    let rescanned = server.refresh_index(&dir).await;
+    let indexed = server.workspace_index.read().await.scripts.len();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(parsed, total);
+    assert_eq!(reports, (1..=total).map(|done| (done, total)).collect::<Vec<_>>());
+    assert_eq!(rescanned, 1);
+    assert_eq!(indexed, total + 1);
+}
@@ -0,0 +1,157 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::gmail::import;
+use sieve_language_server::lsp::COMMAND_IMPORT_GMAIL_FILTERS;
+use sieve_language_server::parser::parse;
+use std::process::Command;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// An export with one filter per line of properties
+fn export(filters: &[&[(&str, &str)]]) -> String {
+    let mut xml = String::from(
+        "<?xml version='1.0' encoding='UTF-8'?>\
+         <feed xmlns='http://www.w3.org/2005/Atom' \
+         xmlns:apps='http://schemas.google.com/apps/2006'>\
+         <title>Mail Filters</title>",
+    );
+    for properties in filters {
+        xml.push_str("<entry><category term='filter'></category><title>Mail Filter</title>");
+        for (name, value) in *properties {
+            xml.push_str(&format!("<apps:property name='{}' value='{}'/>", name, value));
+        }
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+#[test]
+fn test_filters_become_rules() {
+    let xml = export(&[
+        &[
+            ("from", "news@example.com OR digest@example.com"),
+            ("label", "News"),
+            ("shouldMarkAsRead", "true"),
+            ("sizeOperator", "s_sl"),
+            ("sizeUnit", "s_smb"),
+        ],
+        &[("subject", "invoice"), ("hasTheWord", "urgent &quot;please pay&quot;")],
+        &[
+            ("hasTheWord", "list:dev.lists.example.org"),
+            ("label", "Dev"),
+            ("shouldArchive", "true"),
+        ],
+        &[("to", "{me@example.com me@example.net}"), ("forwardTo", "backup@example.org")],
+    ]);
+    let result = import(&xml).unwrap();
+    assert_eq!(
+        result.text,
+        "require [\"copy\", \"fileinto\", \"imap4flags\"];\n\
+         \n\
+         # Gmail filter 1\n\
+         if address :contains \"from\" [\"news@example.com\", \"digest@example.com\"] {\n\
+         \x20   addflag \"\\\\Seen\";\n\
+         \x20   fileinto :copy \"News\";\n\
+         }\n\
+         \n\
+         # Gmail filter 2\n\
+         # No action of the filter can be expressed in Sieve\n\
+         \n\
+         # Gmail filter 3\n\
+         if header :contains \"list-id\" \"dev.lists.example.org\" {\n\
+         \x20   fileinto \"Dev\";\n\
+         }\n\
+         \n\
+         # Gmail filter 4\n\
+         if address :contains [\"to\", \"cc\"] [\"me@example.com\", \"me@example.net\"] {\n\
+         \x20   redirect :copy \"backup@example.org\";\n\
+         }\n"
+    );
+    assert_eq!(result.warnings, ["Filter 2: No action of the filter can be expressed in Sieve"]);
+    assert!(parse(&result.text).errors.is_empty(), "{:?}", parse(&result.text).errors);
+}
+
+#[test]
+fn test_unrepresentable_filters_are_flagged() {
+    let xml = export(&[
+        &[("hasTheWord", "from:boss is:unread"), ("label", "Boss")],
+        &[
+            ("subject", "offer"),
+            ("hasTheWord", "sale discount"),
+            ("doesNotHaveTheWord", "unsubscribe"),
+            ("hasAttachment", "true"),
+            ("size", "2"),
+            ("sizeOperator", "s_ss"),
+            ("sizeUnit", "s_skb"),
+            ("shouldTrash", "true"),
+            ("shouldNeverSpam", "true"),
+        ],
+        &[("from", "x@example.com"), ("shouldArchive", "true")],
+    ]);
+    let result = import(&xml).unwrap();
+    assert_eq!(
+        result.warnings,
+        [
+            "Filter 1 was not converted: the search \"from:boss is:unread\" uses operators \
+             Sieve cannot express",
+            "Filter 2: 'Has attachment' is approximated by a multipart/mixed Content-Type",
+            "Filter 2: 'Never send it to Spam' has no Sieve equivalent",
+            "Filter 3: Archiving without a label files the message into \"Archive\"",
+        ]
+    );
+    assert!(result.text.contains(
+        "# Not converted: the search \"from:boss is:unread\" uses operators Sieve cannot express\n"
+    ));
+    assert!(result.text.contains(
+        "if allof (header :contains \"subject\" \"offer\", \
+         allof (body :text :contains \"sale\", body :text :contains \"discount\"), \
+         not body :text :contains \"unsubscribe\", \
+         header :contains \"content-type\" \"multipart/mixed\", size :under 2K) {\n\
+         \x20   fileinto \"Trash\";\n}\n"
+    ));
+    assert!(parse(&result.text).errors.is_empty(), "{:?}", parse(&result.text).errors);
+}
+
+#[test]
+fn test_invalid_files_are_rejected() {
+    assert!(import("<feed>").unwrap_err().starts_with("Invalid filter XML: "));
+    assert_eq!(import(&export(&[])).unwrap_err(), "The file contains no Gmail filters");
+}
+
+#[tokio::test]
+async fn test_import_command_and_cli() {
+    let path = std::env::temp_dir().join(format!("mailFilters-{}.xml", std::process::id()));
+    std::fs::write(&path, export(&[&[("from", "a@example.com"), ("shouldStar", "true")]]))
+        .unwrap();
+
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let result = server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_IMPORT_GMAIL_FILTERS.to_string(),
+            arguments: vec![json!(Url::from_file_path(&path).unwrap())],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let text = result["text"].as_str().unwrap();
+    assert!(text.starts_with("require [\"imap4flags\"];\n"), "{}", text);
+    assert!(text.contains("addflag \"\\\\Flagged\";"), "{}", text);
+    assert_eq!(result["warnings"], json!([]));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["import-gmail", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), text);
+    std::fs::remove_file(&path).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["import-gmail"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::server_with;
+use serde_json::json;
+use sieve_language_server::sieve::{COMMON_HEADERS, common_header, is_header_name};
+use tower_lsp::lsp_types::*;
+
+async fn header_completions(text: &str, position: Position) -> Vec<CompletionItem> {
+    let (service, uri) = server_with(json!({}), text).await;
+    service
+        .inner()
+        .get_completions(&uri, position)
+        .await
+        .into_iter()
+        .filter(|item| item.kind == Some(CompletionItemKind::FIELD))
+        .collect()
+}
+
+fn has(items: &[CompletionItem], label: &str) -> bool {
+    items.iter().any(|item| item.label == label)
+}
+
+#[test]
+fn test_common_headers_are_valid_names() {
+    for (name, _) in COMMON_HEADERS {
+        assert!(is_header_name(name), "{}", name);
+    }
+    assert_eq!(common_header("list-id"), common_header("List-Id"));
+    assert_eq!(common_header("X-Unknown"), None);
+}
+
+#[tokio::test]
+async fn test_header_names_are_completed() {
+    let text = "if header :contains [\"From\", \"X-Sp\"] \"x\" { discard; }\n";
+    let items = header_completions(text, Position::new(0, 34)).await;
+    assert!(has(&items, "X-Spam-Status") && has(&items, "Authentication-Results"));
+    let spam = items.iter().find(|item| item.label == "X-Spam-Flag").unwrap();
+    assert_eq!(
+        spam.text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit::new(
+            Range::new(Position::new(0, 30), Position::new(0, 34)),
+            "X-Spam-Flag".to_string(),
+        )))
+    );
+
+    let items = header_completions("if exists \"", Position::new(0, 11)).await;
+    assert!(has(&items, "List-Id"));
+    let text =
+        "require \"relational\";\nif header :count \"ge\" :comparator \"i;ascii-numeric\" \"";
+    assert!(has(&header_completions(text, Position::new(1, 54)).await, "Received"));
+}
+
+#[tokio::test]
+async fn test_address_offers_only_address_headers() {
+    let items = header_completions("if address :domain :is \"", Position::new(0, 24)).await;
+    assert!(has(&items, "From") && has(&items, "Reply-To") && has(&items, "Delivered-To"));
+    assert!(!has(&items, "Subject") && !has(&items, "X-Spam-Status"));
+}
+
+#[tokio::test]
+async fn test_keys_and_other_strings_get_no_headers() {
+    // The key list of a header test is message content
+    let text = "if header :is \"subject\" \"Re";
+    assert!(header_completions(text, Position::new(0, 27)).await.is_empty());
+    let text = "if envelope :all \"fr";
+    assert!(header_completions(text, Position::new(0, 20)).await.is_empty());
+}
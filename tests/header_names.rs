@@ -0,0 +1,58 @@
+mod common;
+
+use common::*;
+use sieve_language_server::sieve::{is_address_header, is_header_name};
+
+#[test]
+fn test_header_name_syntax() {
+    assert!(is_header_name("Subject"));
+    assert!(is_header_name("X-Spam-Flag"));
+    assert!(!is_header_name(""));
+    assert!(!is_header_name("Subject:"));
+    assert!(!is_header_name("X Spam"));
+    assert!(!is_header_name("X-Spam\t"));
+    assert!(!is_header_name("Betreff-ü"));
+}
+
+#[test]
+fn test_address_headers() {
+    assert!(is_address_header("From"));
+    assert!(is_address_header("resent-to"));
+    assert!(is_address_header("X-Original-To"));
+    assert!(!is_address_header("Subject"));
+}
+
+#[tokio::test]
+async fn test_valid_header_names_pass() {
+    let text = "require \"relational\";\n\
+                if header :comparator \"i;octet\" :contains \"Subject\" \"x\" { discard; }\n\
+                if exists [\"X-Spam-Flag\", \"List-Id\"] { discard; }\n\
+                if address :count \"gt\" [\"To\", \"Cc\"] \"5\" { discard; }\n\
+                if header \"${name}\" \"x\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-header-name").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "non-address-header").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_invalid_header_names() {
+    let text = "if header :is \"Subject:\" \"x\" { discard; }\nif exists \"X Spam\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let invalid = with_code(&diagnostics, "invalid-header-name");
+    assert_eq!(invalid.len(), 2, "{:?}", diagnostics);
+
+    // The stray colon comes with a quick fix, the space does not
+    assert_eq!(invalid[0].range.start.character, 14);
+    let data = invalid[0].data.as_ref().unwrap();
+    assert_eq!(data["replacement"], "\"Subject\"");
+    assert!(invalid[1].data.is_none());
+}
+
+#[tokio::test]
+async fn test_address_on_non_address_header() {
+    let text = "if address :domain [\"From\", \"Subject\"] \"example.com\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let warnings = with_code(&diagnostics, "non-address-header");
+    assert_eq!(warnings.len(), 1, "{:?}", diagnostics);
+    assert!(warnings[0].message.contains("'Subject'"));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::requires::*;
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+
+#[tokio::test]
+async fn test_guarded_extensions_need_no_require() {
+    let text = "require \"ihave\";\n\
+                if ihave [\"regex\", \"fileinto\"] {\n  \
+                if header :regex \"subject\" \"^[0-9]+$\" { fileinto \"Numbers\"; }\n\
+                } else {\n  \
+                error \"regex is not available\";\n\
+                }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unknown-command").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "invalid-ihave").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_usage_outside_the_guard_needs_require() {
+    let text = "require \"ihave\";\n\
+                if ihave \"fileinto\" { fileinto \"A\"; }\n\
+                fileinto \"B\";\n\
+                if allof (ihave \"regex\", header :regex \"to\" \"x\") { keep; }\n\
+                elsif header :regex \"from\" \"y\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 2, "{:?}", diagnostics);
+    assert_eq!(missing[0].range.start.line, 2);
+    assert!(missing[0].message.contains("fileinto"), "{}", missing[0].message);
+    assert_eq!(missing[1].range.start.line, 4);
+    assert!(missing[1].message.contains("regex"), "{}", missing[1].message);
+}
+
+#[tokio::test]
+async fn test_ihave_and_error_need_require_and_arguments() {
+    let text = "if ihave :all { error; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 2, "{:?}", diagnostics);
+    assert_eq!(missing[0].message, "'ihave' requires the \"ihave\" extension");
+    assert_eq!(missing[1].message, "'error' requires the \"ihave\" extension");
+    let invalid = with_code(&diagnostics, "invalid-ihave");
+    assert_eq!(invalid.len(), 2, "{:?}", diagnostics);
+    assert_eq!(invalid[0].message, "'ihave' expects a list of capabilities");
+    assert_eq!(invalid[0].range.start, Position::new(0, 9));
+    assert_eq!(invalid[1].message, "'error' expects a message");
+    assert_eq!(invalid[1].range.start, Position::new(0, 16));
+}
+
+#[tokio::test]
+async fn test_code_after_error_is_unreachable() {
+    let text = "require [\"ihave\", \"fileinto\"];\nerror \"broken\";\nfileinto \"Never\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let unreachable = with_code(&diagnostics, "unreachable-code");
+    assert_eq!(unreachable.len(), 1, "{:?}", diagnostics);
+    assert_eq!(unreachable[0].message, "Unreachable code: statement follows 'error'");
+    assert_eq!(unreachable[0].range.start.line, 2);
+}
+
+#[test]
+fn test_guarded_usage_is_not_a_used_capability() {
+    let text = "require [\"ihave\", \"regex\"];\n\
+                if ihave \"regex\" { if header :regex \"to\" \"x\" { keep; } }\n";
+    let script = parse(text);
+    let used: Vec<String> = used_capabilities(&script, &BTreeMap::new()).into_iter().collect();
+    assert_eq!(used, ["ihave"]);
+    let guards = ihave_guards(&script);
+    assert_eq!(guards.len(), 1);
+    assert_eq!(guards[0].capabilities, ["regex"]);
+    assert!(is_guarded(&guards, "regex", Position::new(1, 25)));
+    assert!(!is_guarded(&guards, "fileinto", Position::new(1, 25)));
+    let unused: Vec<&str> = unused_requires(&script, &BTreeMap::new())
+        .into_iter()
+        .map(|(_, capability)| capability.value.as_str())
+        .collect();
+    assert_eq!(unused, ["regex"]);
+}
@@ -0,0 +1,45 @@
+use sieve_language_server::include::*;
+use std::fs;
+
+#[test]
+fn test_flatten_inlines_includes_and_merges_requires() {
+    let dir = std::env::temp_dir().join(format!("sieve-include-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("main.sieve"),
+        "require [\"include\", \"variables\"];\nset \"folder\" \"Main\";\ninclude \"spam\";\ninclude :once \"spam\";\nfileinto \"${folder}\";\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("spam.sieve"),
+        "require [\"fileinto\", \"variables\"];\nset \"folder\" \"Junk\";\nif header :contains \"x-spam\" \"yes\" {\n  fileinto \"${folder}\";\n}\n",
+    )
+    .unwrap();
+
+    let flattened = flatten(&dir.join("main.sieve")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(flattened.requires, vec!["fileinto", "variables"]);
+    assert!(flattened.warnings.is_empty());
+    let text = flattened.text;
+    assert!(text.starts_with("require [\"fileinto\", \"variables\"];\n"));
+    assert!(!text.lines().any(|l| l.trim_start().starts_with("include")));
+    // The included script's local variable is renamed, the entry's is kept
+    assert!(text.contains("set \"folder_spam\" \"Junk\";"));
+    assert!(text.contains("fileinto \"${folder_spam}\";"));
+    assert!(text.contains("fileinto \"${folder}\";"));
+    // :once prevents inlining the same script twice
+    assert_eq!(text.matches("\"Junk\"").count(), 1);
+}
+
+#[test]
+fn test_include_cycles_are_reported() {
+    let dir = std::env::temp_dir().join(format!("sieve-include-cycle-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.sieve"), "require \"include\";\ninclude \"b\";\n").unwrap();
+    fs::write(dir.join("b.sieve"), "require \"include\";\ninclude \"a\";\n").unwrap();
+
+    let result = flatten(&dir.join("a.sieve"));
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(result.unwrap_err().contains("cycle"));
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{diagnostics_for, diagnostics_with_settings, server_with, with_code};
+use serde_json::json;
+use tower_lsp::lsp_types::*;
+
+const SCRIPT: &str = "require [\"fileinto\"];\n# comment\n\
+                      if header :contains \"subject\" \"a\" {\n  fileinto \"A\"\n}\n\
+                      keep; stop;\n/* bracket\n comment */\n\
+                      if body :contains \"x\" {\n  discard;\n}\nvacation \"away\";\n";
+
+fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(Range::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1),
+        )),
+        range_length: None,
+        text: text.to_string(),
+    }
+}
+
+/// Validate after every change and compare with validating the text from scratch
+async fn assert_matches_full_validation(changes: &[TextDocumentContentChangeEvent]) {
+    let (service, uri) = server_with(json!({}), SCRIPT).await;
+    let server = service.inner();
+    assert_eq!(server.validate_document(&uri).await.unwrap(), diagnostics_for(SCRIPT).await);
+    for change in changes {
+        server.document_map.get_mut(&uri).unwrap().apply_change(change);
+        let text = server.document_map.get(&uri).unwrap().get_text();
+        assert_eq!(
+            server.validate_document(&uri).await.unwrap(),
+            diagnostics_for(&text).await,
+            "after change {:?}",
+            change
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_edits_inside_a_line() {
+    assert_matches_full_validation(&[
+        edit((3, 15), (3, 15), ";"),
+        edit((3, 15), (3, 16), ""),
+        edit((11, 0), (11, 8), "notify"),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn test_edits_that_change_line_counts() {
+    assert_matches_full_validation(&[
+        edit((5, 11), (5, 11), "\nkeep\n"),
+        edit((0, 0), (2, 0), ""),
+        edit((0, 0), (0, 0), "redirect \"a@example.com\"\n\n"),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn test_edits_that_affect_the_rest_of_the_file() {
+    assert_matches_full_validation(&[
+        // Opening a comment or a text: block changes how the lines below are read
+        edit((1, 0), (1, 0), "/* "),
+        edit((1, 0), (1, 3), ""),
+        edit((5, 0), (5, 11), "reject text:"),
+        edit((5, 0), (5, 12), "keep; stop;"),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn test_only_edited_lines_are_checked_again() {
+    let script = format!("{}expire \"day\" \"7\";\n", SCRIPT);
+    let (service, uri) = server_with(json!({}), &script).await;
+    let server = service.inner();
+    let unchecked = || server.document_map.get(&uri).unwrap().unchecked_lines();
+    assert_eq!(unchecked(), 13);
+    server.validate_document(&uri).await.unwrap();
+    assert_eq!(unchecked(), 0);
+
+    let change = |change| server.document_map.get_mut(&uri).unwrap().apply_change(&change);
+    change(edit((3, 15), (3, 15), ";"));
+    assert_eq!(unchecked(), 1);
+    change(edit((5, 11), (5, 11), "\nkeep;\nstop;"));
+    assert_eq!(unchecked(), 4);
+    server.validate_document(&uri).await.unwrap();
+    assert_eq!(unchecked(), 0);
+
+    // Lines checked with other settings are checked again
+    let settings = json!({ "proton_extensions": false });
+    *server.settings.write().await = serde_json::from_value(settings.clone()).unwrap();
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    assert_eq!(with_code(&diagnostics, "proton-extension-disabled").len(), 1);
+    let text = server.document_map.get(&uri).unwrap().get_text();
+    assert_eq!(diagnostics, diagnostics_with_settings(&text, settings).await);
+}
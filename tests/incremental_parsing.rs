@@ -0,0 +1,76 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+
+const SCRIPT: &str = "require [\"fileinto\"];\n# comment\nif header :contains \"subject\" \"a\" {\n  fileinto \"A\";\n}\nkeep; stop;\n/* bracket\n comment */\nif true {\n  discard;\n}\n";
+
+fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(Range::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1),
+        )),
+        range_length: None,
+        text: text.to_string(),
+    }
+}
+
+fn assert_matches_full_parse(changes: &[TextDocumentContentChangeEvent]) {
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let mut document = SieveDocument::new(uri, SCRIPT.to_string(), 1);
+    for change in changes {
+        document.apply_change(change);
+        assert_eq!(
+            document.script(),
+            &parse(&document.get_text()),
+            "after change {:?}",
+            change
+        );
+    }
+}
+
+#[test]
+fn test_edits_inside_a_statement() {
+    assert_matches_full_parse(&[
+        edit((3, 13), (3, 14), "B"),
+        edit((3, 2), (3, 2), "keep;\n  "),
+        edit((0, 0), (1, 0), ""),
+    ]);
+}
+
+#[test]
+fn test_edits_that_change_line_counts() {
+    assert_matches_full_parse(&[
+        edit((5, 5), (5, 5), "\n\n"),
+        edit((2, 0), (5, 0), ""),
+        edit((0, 0), (0, 0), "keep;\nkeep;\n"),
+    ]);
+}
+
+#[test]
+fn test_edits_that_affect_the_rest_of_the_file() {
+    assert_matches_full_parse(&[
+        // Opening a string, a comment or a block changes everything below
+        edit((5, 0), (5, 0), "fileinto \"x;\n"),
+        edit((5, 10), (5, 10), "\""),
+        edit((1, 0), (1, 0), "/* "),
+        edit((1, 0), (1, 3), ""),
+        edit((8, 9), (8, 10), ""),
+        edit((8, 9), (8, 9), "{"),
+        // Dropping a terminator joins two statements
+        edit((5, 4), (5, 5), ""),
+    ]);
+}
+
+#[test]
+fn test_reversed_ranges_replace_the_same_text() {
+    assert_matches_full_parse(&[edit((5, 5), (2, 0), ""), edit((1, 9), (0, 3), "uire")]);
+
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let mut forward = SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1);
+    let mut reversed = SieveDocument::new(uri, SCRIPT.to_string(), 1);
+    forward.apply_change(&edit((2, 0), (5, 5), "discard;"));
+    reversed.apply_change(&edit((5, 5), (2, 0), "discard;"));
+    assert_eq!(reversed.get_text(), forward.get_text());
+    assert_eq!(reversed.script(), forward.script());
+}
@@ -0,0 +1,213 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::interpreter::*;
+use sieve_language_server::lsp::COMMAND_TEST_MESSAGE;
+use sieve_language_server::message::Message;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const MESSAGE: &str = "Return-Path: <bounce@lists.example.org>\r\n\
+                       From: \"Doe, Jane\" <jane+news@example.com>\r\n\
+                       To: me@example.net, other@example.net\r\n\
+                       Subject: [list] Weekly digest\r\n\
+                       List-Id: <weekly.lists.example.org>\r\n\
+                       \r\n\
+                       Hello there,\r\nthis is the digest.\r\n";
+
+fn simulate(script: &str) -> Outcome {
+    let message = Message::parse(MESSAGE);
+    run(&parse(script), &message, &Envelope::from_message(&message))
+}
+
+#[test]
+fn test_envelope_from_headers() {
+    let envelope = Envelope::from_message(&Message::parse(MESSAGE));
+    assert_eq!(envelope.from.as_deref(), Some("bounce@lists.example.org"));
+    assert_eq!(envelope.to.as_deref(), Some("me@example.net"));
+    assert_eq!(
+        addresses("\"Doe, Jane\" <jane@example.com>, bob@example.com"),
+        vec!["jane@example.com", "bob@example.com"]
+    );
+}
+
+#[test]
+fn test_first_matching_branch_runs() {
+    let outcome = simulate(
+        "require \"fileinto\";\n\
+         if header :contains \"subject\" \"invoice\" { fileinto \"Bills\"; }\n\
+         elsif exists \"list-id\" { fileinto \"Lists\"; }\n\
+         elsif true { discard; }\n\
+         else { discard; }\n",
+    );
+    let rules: Vec<(&str, bool)> =
+        outcome.rules.iter().map(|r| (r.test.as_str(), r.matched)).collect();
+    assert_eq!(rules, vec![("header", false), ("exists", true)]);
+    assert_eq!(outcome.actions.len(), 1);
+    assert_eq!(outcome.actions[0].command, "fileinto");
+    assert_eq!(outcome.actions[0].arguments, vec!["Lists"]);
+    assert_eq!(outcome.actions[0].range.start.line, 2);
+    assert!(!outcome.implicit_keep);
+    assert!(!outcome.stopped);
+}
+
+#[test]
+fn test_address_parts_and_match_variables() {
+    let outcome = simulate(
+        "require [\"fileinto\", \"variables\", \"subaddress\"];\n\
+         if address :detail \"from\" \"news\" { set \"kind\" \"newsletter\"; }\n\
+         if address :domain :is \"from\" \"EXAMPLE.com\" {\n\
+         if header :matches \"subject\" \"[*] *\" { fileinto \"Lists/${1}\"; stop; }\n\
+         }\n\
+         discard;\n",
+    );
+    assert!(outcome.rules.iter().all(|r| r.matched), "{:?}", outcome.rules);
+    assert_eq!(outcome.variables.get("kind").map(String::as_str), Some("newsletter"));
+    assert_eq!(outcome.actions[0].arguments, vec!["Lists/list"]);
+    assert!(outcome.stopped);
+    assert_eq!(outcome.actions.len(), 1, "discard is never reached");
+}
+
+#[test]
+fn test_copy_keeps_the_implicit_keep() {
+    let outcome = simulate(
+        "require [\"copy\", \"fileinto\", \"imap4flags\"];\n\
+         addflag \"\\\\Seen\";\n\
+         fileinto :copy \"Archive\";\n\
+         redirect :copy \"backup@example.net\";\n",
+    );
+    assert!(outcome.implicit_keep);
+    // Flags are not actions of their own but stored with the message
+    assert_eq!(outcome.actions.len(), 2);
+    assert_eq!(outcome.actions[0].tags, vec![":copy"]);
+    assert_eq!(outcome.actions[0].flags, vec!["\\Seen"]);
+    assert!(outcome.actions[1].flags.is_empty());
+}
+
+#[test]
+fn test_relational_and_size_tests() {
+    let outcome = simulate(
+        "require \"relational\";\n\
+         if header :count \"ge\" :comparator \"i;ascii-numeric\" \"to\" \"1\" { keep; }\n\
+         if address :count \"eq\" \"to\" \"2\" { keep; }\n\
+         if size :over 10K { discard; }\n\
+         if body :contains \"DIGEST\" { keep; }\n",
+    );
+    let matched: Vec<bool> = outcome.rules.iter().map(|r| r.matched).collect();
+    assert_eq!(matched, vec![true, true, false, true]);
+}
+
+#[test]
+fn test_unsupported_tests_are_reported() {
+    let outcome = simulate(
+        "require \"date\";\n\
+         if currentdate :value \"ge\" \"hour\" \"09\" { discard; }\n",
+    );
+    assert!(!outcome.rules[0].matched);
+    assert_eq!(outcome.unsupported.len(), 1);
+    assert_eq!(outcome.unsupported[0].name, "currentdate");
+    assert!(outcome.implicit_keep);
+}
+
+#[tokio::test]
+async fn test_message_command() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let dir = std::env::temp_dir().join(format!("sieve-interpreter-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("filter.sieve");
+    let uri = Url::from_file_path(&path).unwrap();
+    let text = "if envelope :domain \"to\" \"example.net\" { discard; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+
+    let execute = |arguments: Vec<serde_json::Value>| {
+        server.execute_command(ExecuteCommandParams {
+            command: COMMAND_TEST_MESSAGE.to_string(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    // No message given and no sidecar file yet
+    assert!(execute(vec![json!(uri)]).await.is_err());
+
+    std::fs::write(dir.join("filter.eml"), MESSAGE).unwrap();
+    let outcome = execute(vec![json!(uri)]).await.unwrap().unwrap();
+    assert_eq!(outcome["rules"][0]["matched"], true);
+    assert_eq!(outcome["actions"][0]["command"], "discard");
+    assert_eq!(outcome["implicitKeep"], false);
+
+    // An explicit message and envelope take precedence
+    let outcome = execute(vec![
+        json!(uri),
+        json!(MESSAGE),
+        json!({ "from": "a@example.com", "to": "me@elsewhere.example" }),
+    ])
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(outcome["rules"][0]["matched"], false);
+    assert_eq!(outcome["implicitKeep"], true);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_trace_lists_tests_and_actions() {
+    let outcome = simulate(
+        "require [\"fileinto\", \"variables\", \"date\"];\n\
+         if anyof (header :contains \"subject\" \"invoice\", not exists \"list-id\") {\n\
+         discard;\n\
+         } else {\n\
+         set \"folder\" \"Lists\";\n\
+         fileinto \"${folder}\";\n\
+         }\n\
+         if currentdate :is \"weekday\" \"0\" { stop; }\n",
+    );
+    let text = trace_text(&outcome, "filter.sieve");
+    let expected = "Trace of filter.sieve\n\
+                    \n   \
+                    2  if anyof -> false\n   \
+                    2    header :contains \"subject\" \"invoice\" -> false\n   \
+                    2    not -> false\n   \
+                    2      exists \"list-id\" -> true\n   \
+                    4  else\n   \
+                    5    set folder = \"Lists\"\n   \
+                    6    fileinto \"Lists\"\n   \
+                    8  if currentdate :is \"weekday\" \"0\" -> false (not simulated)\n\
+                    \n\
+                    The implicit keep was cancelled\n\
+                    1 test(s) could not be simulated and count as false\n";
+    assert_eq!(text, expected);
+}
+
+#[tokio::test]
+async fn test_trace_message_request() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///tmp/rules/filter.sieve").unwrap();
+    let text = "if true { stop; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+
+    let trace = |message: &str| {
+        server.trace_message(TraceMessageParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            message: Some(message.to_string()),
+            envelope: None,
+        })
+    };
+    let result = trace(MESSAGE).await.unwrap();
+    assert_eq!(result.uri.scheme(), "sieve-trace");
+    assert!(result.uri.path().ends_with("/rules/filter.sieve.trace"), "{}", result.uri);
+    assert!(result.text.starts_with("Trace of filter.sieve\n"), "{}", result.text);
+    assert!(result.text.contains("Execution ended at 'stop'"), "{}", result.text);
+    assert!(result.outcome.stopped);
+
+    // Every message gets its own virtual document
+    let other = trace("Subject: other\r\n\r\n").await.unwrap();
+    assert_ne!(result.uri, other.uri);
+}
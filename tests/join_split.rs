@@ -0,0 +1,46 @@
+use sieve_language_server::parser::parse;
+use sieve_language_server::refactor::*;
+use tower_lsp::lsp_types::*;
+
+fn apply(text: &str, edit: &TextEdit) -> String {
+    let start = sieve_language_server::parser::offset_at(text, edit.range.start);
+    let end = sieve_language_server::parser::offset_at(text, edit.range.end);
+    format!("{}{}{}", &text[..start], edit.new_text, &text[end..])
+}
+
+#[test]
+fn test_join_wrapped_statement() {
+    let text = "if header :contains\n    [\"from\",\n     \"sender\"]\n    \"boss\"\n{\n  keep;\n}\n";
+    let edit = join_statement(text, &parse(text), Position::new(1, 4)).unwrap();
+    assert_eq!(
+        apply(text, &edit),
+        "if header :contains [\"from\", \"sender\"] \"boss\" {\n  keep;\n}\n"
+    );
+}
+
+#[test]
+fn test_join_keeps_comments_and_single_lines_untouched() {
+    let text = "fileinto # where\n  \"Junk\";\nkeep;\n";
+    assert!(join_statement(text, &parse(text), Position::new(0, 2)).is_none());
+    assert!(join_statement(text, &parse(text), Position::new(2, 1)).is_none());
+}
+
+#[test]
+fn test_split_test_list() {
+    let text = "  if anyof (header :is \"a\" \"b\", exists \"c\") {\n    stop;\n  }\n";
+    let edit = split_statement(text, &parse(text), Position::new(0, 4)).unwrap();
+    assert_eq!(
+        apply(text, &edit),
+        "  if anyof (\n      header :is \"a\" \"b\",\n      exists \"c\"\n  ) {\n    stop;\n  }\n"
+    );
+}
+
+#[test]
+fn test_split_arguments() {
+    let text = "if header :contains \"subject\" [\"a\", \"b\"] {\n  stop;\n}\n";
+    let edit = split_statement(text, &parse(text), Position::new(0, 0)).unwrap();
+    assert_eq!(
+        apply(text, &edit),
+        "if header :contains \"subject\"\n    [\"a\", \"b\"] {\n  stop;\n}\n"
+    );
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[tokio::test]
+async fn test_redundant_keep_before_stop() {
+    let text = "if header :is \"x-vip\" \"yes\" {\n    keep;\n    stop;\n}\nfileinto \"Other\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redundant-keep");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(
+        found[0].range,
+        Range::new(Position::new(1, 4), Position::new(2, 4))
+    );
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], "");
+}
+
+#[tokio::test]
+async fn test_keep_is_needed_after_cancelling_actions() {
+    let text = "require [\"fileinto\", \"copy\"];\n\
+                fileinto \"Archive\";\n\
+                if true { keep; stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "redundant-keep").is_empty(), "{:?}", diagnostics);
+
+    let text = "require [\"fileinto\", \"copy\"];\n\
+                fileinto :copy \"Archive\";\n\
+                if true { keep; stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(with_code(&diagnostics, "redundant-keep").len(), 1, "{:?}", diagnostics);
+
+    // Keep with arguments does more than the implicit keep
+    let text = "require \"imap4flags\";\nkeep :flags \"\\\\Seen\";\nstop;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "redundant-keep").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_fileinto_inbox() {
+    let text = "require \"fileinto\";\nif true { fileinto \"INBOX\"; }\nfileinto \"INBOX.Lists\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "inbox-fileinto");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        found[0].range,
+        Range::new(Position::new(1, 10), Position::new(1, 27))
+    );
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], "keep;");
+}
+
+#[tokio::test]
+async fn test_profile_without_stop_gets_no_stop_idiom() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "server_dialect": "gmail-forwarding" })).unwrap();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if true { keep; stop; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    assert!(with_code(&diagnostics, "redundant-keep").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_fix_all_applies_every_fix() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "require \"fileinto\";\nif true {\n    keep;\n    stop;\n}\nfileinto \"inbox\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+
+    let request = |only: Option<Vec<CodeActionKind>>| CodeActionParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        range: Range::default(),
+        context: CodeActionContext {
+            diagnostics: Vec::new(),
+            only,
+            trigger_kind: None,
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+
+    let actions = server.code_action(request(None)).await.unwrap().unwrap();
+    assert!(actions.is_empty());
+
+    let actions = server
+        .code_action(request(Some(vec![CodeActionKind::SOURCE])))
+        .await
+        .unwrap()
+        .unwrap();
+    let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+        panic!("expected the fix-all action, got {:?}", actions);
+    };
+    assert_eq!(action.kind, Some(CodeActionKind::SOURCE_FIX_ALL));
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+    let replacements: Vec<(u32, &str)> = edits
+        .iter()
+        .map(|e| (e.range.start.line, e.new_text.as_str()))
+        .collect();
+    assert_eq!(replacements, [(2, ""), (5, "keep;")]);
+}
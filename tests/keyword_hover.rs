@@ -0,0 +1,82 @@
+mod common;
+
+use sieve_language_server::datastructures::*;
+use sieve_language_server::documentation::{self, CONTROL_KEYWORDS};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// Markdown of the hover at a position, if any
+async fn hover_at(text: &str, position: Position) -> Option<String> {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()?;
+    match hover.contents {
+        HoverContents::Markup(markup) => Some(markup.value),
+        other => panic!("unexpected hover {:?}", other),
+    }
+}
+
+const SCRIPT: &str = "require \"fileinto\";\n\
+                      if header :is \"x-spam\" \"yes\" {\n\
+                      \x20   fileinto \"Junk\";\n\
+                      \x20   stop;\n\
+                      } elsif size :over 1M {\n\
+                      \x20   discard;\n\
+                      } else {\n\
+                      \x20   keep;\n\
+                      }\n";
+
+#[test]
+fn test_every_keyword_has_notes() {
+    for doc in CONTROL_KEYWORDS {
+        assert!(!doc.notes.is_empty(), "{}", doc.keyword);
+        assert_eq!(documentation::keyword(doc.keyword), Some(doc));
+    }
+    assert_eq!(documentation::keyword("fileinto"), None);
+}
+
+#[tokio::test]
+async fn test_control_keyword_hovers() {
+    let doc = hover_at(SCRIPT, Position::new(1, 1)).await.unwrap();
+    assert!(doc.starts_with("**if** - control structure (RFC 5228)"), "{}", doc);
+    assert!(doc.contains("Only the first branch of an 'if'/'elsif'/'else' chain"), "{}", doc);
+
+    let doc = hover_at(SCRIPT, Position::new(4, 4)).await.unwrap();
+    assert!(doc.starts_with("**elsif**"), "{}", doc);
+    let doc = hover_at(SCRIPT, Position::new(6, 3)).await.unwrap();
+    assert!(doc.starts_with("**else**"), "{}", doc);
+    let doc = hover_at(SCRIPT, Position::new(0, 3)).await.unwrap();
+    assert!(doc.ends_with("(https://datatracker.ietf.org/doc/html/rfc5228#section-3.2)"), "{}", doc);
+
+    let doc = hover_at(SCRIPT, Position::new(3, 5)).await.unwrap();
+    assert!(doc.contains("the implicit keep files the message into the inbox"), "{}", doc);
+}
+
+#[tokio::test]
+async fn test_brace_hovers_name_their_command() {
+    let opening = hover_at(SCRIPT, Position::new(1, 29)).await.unwrap();
+    assert!(opening.starts_with("Block of 'if'\n\n**{ }**"), "{}", opening);
+
+    let closing = hover_at(SCRIPT, Position::new(4, 0)).await.unwrap();
+    assert!(closing.starts_with("Block of 'if'"), "{}", closing);
+    let elsif = hover_at(SCRIPT, Position::new(4, 22)).await.unwrap();
+    assert!(elsif.starts_with("Block of 'elsif'"), "{}", elsif);
+
+    // Braces inside strings are not blocks
+    let text = "if header :is \"subject\" \"{x}\" { keep; }\n";
+    let string = hover_at(text, Position::new(0, 25)).await.unwrap();
+    assert!(string.starts_with("**\"{x}\"** - match key"), "{}", string);
+}
@@ -0,0 +1,107 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::{offset_at, parse};
+use sieve_language_server::refactor::shorten_line;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn apply(text: &str, edit: &TextEdit) -> String {
+    let start = offset_at(text, edit.range.start);
+    let end = offset_at(text, edit.range.end);
+    format!("{}{}{}", &text[..start], edit.new_text, &text[end..])
+}
+
+const LONG_KEYS: &str = "  if header :contains \"subject\" [\"newsletter\", \"digest\", \"weekly update\"] {\n\
+                         \x20   stop;\n  }\n";
+
+#[test]
+fn test_split_string_list_across_lines() {
+    let edits = shorten_line(LONG_KEYS, &parse(LONG_KEYS), 0, 40);
+    let (title, edit) = &edits[0];
+    assert_eq!(*title, "Split string list across lines");
+    assert_eq!(
+        apply(LONG_KEYS, edit),
+        "  if header :contains \"subject\" [\n      \"newsletter\",\n      \"digest\",\n      \
+         \"weekly update\"\n  ] {\n    stop;\n  }\n"
+    );
+}
+
+#[test]
+fn test_key_list_becomes_anyof() {
+    let edits = shorten_line(LONG_KEYS, &parse(LONG_KEYS), 0, 40);
+    assert_eq!(edits.len(), 2);
+    let (title, edit) = &edits[1];
+    assert_eq!(*title, "Convert to 'anyof' with one key per test");
+    assert_eq!(
+        apply(LONG_KEYS, edit),
+        "  if anyof (\n      header :contains \"subject\" \"newsletter\",\n      \
+         header :contains \"subject\" \"digest\",\n      \
+         header :contains \"subject\" \"weekly update\"\n  ) {\n    stop;\n  }\n"
+    );
+}
+
+#[test]
+fn test_only_key_lists_of_any_key_tests_become_anyof() {
+    // Every header must exist, so splitting the list into an anyof would change the meaning
+    let text = "if exists [\"list-id\", \"list-unsubscribe\", \"precedence\"] { stop; }\n";
+    let titles: Vec<&str> = shorten_line(text, &parse(text), 0, 40)
+        .into_iter()
+        .map(|(title, _)| title)
+        .collect();
+    assert_eq!(titles, ["Split string list across lines"]);
+
+    // The header names are not the key list either
+    let text = "if header :is [\"from\", \"sender\", \"reply-to\"] \"boss@example.com\" { stop; }\n";
+    let titles: Vec<&str> = shorten_line(text, &parse(text), 0, 40)
+        .into_iter()
+        .map(|(title, _)| title)
+        .collect();
+    assert_eq!(titles, ["Split string list across lines"]);
+}
+
+#[test]
+fn test_short_lines_are_left_alone() {
+    assert!(shorten_line(LONG_KEYS, &parse(LONG_KEYS), 0, 100).is_empty());
+    assert!(shorten_line(LONG_KEYS, &parse(LONG_KEYS), 0, 0).is_empty());
+    let text = "fileinto \"Some/Very/Long/Mailbox/Name/That/Goes/On\";\n";
+    assert!(shorten_line(text, &parse(text), 0, 20).is_empty());
+}
+
+#[tokio::test]
+async fn test_code_action_follows_setting() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///long.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), LONG_KEYS.to_string(), 1));
+
+    let titles = || async {
+        let actions = server
+            .code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(0, 10), Position::new(0, 10)),
+                context: CodeActionContext::default(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap_or_default();
+        actions
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => {
+                    assert_eq!(action.kind, Some(CodeActionKind::REFACTOR_REWRITE));
+                    Some(action.title)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(titles().await.is_empty());
+
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "max_line_length": 60 })).unwrap();
+    assert_eq!(titles().await.len(), 2);
+}
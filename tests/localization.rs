@@ -0,0 +1,60 @@
+mod common;
+
+use common::*;
+use sieve_language_server::i18n::*;
+
+#[test]
+fn test_fallback_chain() {
+    let german = Localizer::new(Some("de-AT"));
+    assert_eq!(
+        german.translate("Unknown comparator 'i;foo'"),
+        "Unbekannter Vergleicher 'i;foo'"
+    );
+    assert_eq!(
+        Localizer::new(Some("de_DE")).translate("Trailing ',' in string list"),
+        "Überzähliges ',' am Ende der Liste"
+    );
+
+    // Unknown locales and untranslated messages stay English
+    assert_eq!(
+        Localizer::new(Some("fr")).translate("Unknown comparator 'x'"),
+        "Unknown comparator 'x'"
+    );
+    assert_eq!(Localizer::new(None).translate("Tests the size of the message in bytes"), "Tests the size of the message in bytes");
+    assert_eq!(german.translate("something else"), "something else");
+}
+
+#[test]
+fn test_placeholders_can_be_reordered() {
+    let catalog =
+        Catalog::from_json(r#"{ "Unclosed '{0}': missing '{1}'": "'{1}' fehlt für '{0}'" }"#)
+            .unwrap();
+    assert_eq!(
+        catalog.translate("Unclosed '{': missing '}'").as_deref(),
+        Some("'}' fehlt für '{'")
+    );
+}
+
+#[test]
+fn test_catalogs_cover_the_same_templates() {
+    let english = catalog("en").unwrap().templates();
+    let german = catalog("de").unwrap().templates();
+    assert_eq!(english, german);
+}
+
+#[tokio::test]
+async fn test_diagnostics_are_translated() {
+    let text = "if header :comparator \"i;nope\" :is [\"a\",] \"b\" {\n    stop;\n    keep;\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(!diagnostics.is_empty());
+
+    let german = Localizer::new(Some("de"));
+    for diagnostic in &diagnostics {
+        assert_ne!(
+            german.translate(&diagnostic.message),
+            diagnostic.message,
+            "no German translation for {:?}",
+            diagnostic.message
+        );
+    }
+}
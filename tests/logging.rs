@@ -0,0 +1,119 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::logging::*;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+use tracing::{debug, info, warn, Level};
+use tracing_subscriber::layer::SubscriberExt;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_server_options() {
+    assert!(is_server_invocation(&[]));
+    assert!(is_server_invocation(&args(&["--log-level", "debug"])));
+    assert!(is_server_invocation(&args(&["--stdio"])));
+    assert!(!is_server_invocation(&args(&["check", "--stdin"])));
+
+    let options = parse_options(&args(&["--log-file", "/tmp/sieve.log", "--log-level", "DEBUG"]));
+    assert_eq!(
+        options,
+        Ok(LogOptions {
+            file: Some(PathBuf::from("/tmp/sieve.log")),
+            level: Level::DEBUG,
+            ignored: vec![],
+        })
+    );
+    assert_eq!(parse_options(&[]), Ok(LogOptions::default()));
+    assert!(parse_options(&args(&["--log-level", "loud"])).is_err());
+    assert!(parse_options(&args(&["--log-file"])).is_err());
+    // Editor clients start servers with --stdio, and some add flags of their own
+    assert_eq!(parse_options(&args(&["--stdio"])), Ok(LogOptions::default()));
+    let options = parse_options(&args(&["--stdio", "--clientProcessId=42", "--log-level", "warn"]));
+    assert_eq!(
+        options,
+        Ok(LogOptions {
+            file: None,
+            level: Level::WARN,
+            ignored: args(&["--clientProcessId=42"]),
+        })
+    );
+    assert!(parse_options(&args(&["--stdio", "extra"])).is_err());
+}
+
+#[test]
+fn test_command_line_log_level() {
+    let mut arguments = args(&["check", "--log-level", "debug", "--stdin"]);
+    assert_eq!(take_log_level(&mut arguments), Ok(Some(Level::DEBUG)));
+    assert_eq!(arguments, args(&["check", "--stdin"]));
+    assert_eq!(take_log_level(&mut arguments), Ok(None));
+    assert!(take_log_level(&mut args(&["check", "--log-level"])).is_err());
+}
+
+#[test]
+fn test_relay_follows_the_trace_level() {
+    let level = TraceLevel::default();
+    let (relay, mut receiver) = TraceRelay::new(level.clone());
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(relay));
+    let emit = || {
+        info!(target: "sieve_language_server::lsp", uri = "file:///a.sieve", "Document saved");
+        debug!(target: "sieve_language_server::lsp", "Debounced");
+        warn!(target: "tower_lsp::transport", "Not ours");
+    };
+    let mut received = || std::iter::from_fn(|| receiver.try_recv().ok()).collect::<Vec<_>>();
+
+    emit();
+    assert!(received().is_empty());
+
+    level.set(TraceValue::Messages);
+    emit();
+    let messages = received();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].message, "Document saved");
+    assert_eq!(messages[0].verbose, None);
+
+    level.set(TraceValue::Verbose);
+    emit();
+    let messages = received();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(
+        messages[0].verbose.as_deref(),
+        Some("INFO sieve_language_server::lsp uri=file:///a.sieve")
+    );
+    assert_eq!(messages[1].message, "Debounced");
+}
+
+#[tokio::test]
+async fn test_set_trace_changes_the_level() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    assert_eq!(server.trace.get(), TraceValue::Off);
+    server
+        .set_trace(SetTraceParams {
+            value: TraceValue::Verbose,
+        })
+        .await;
+    assert_eq!(server.trace.get(), TraceValue::Verbose);
+}
+
+#[test]
+fn test_binary_serves_with_stdio_flag() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["--stdio", "--clientProcessId=1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+    let mut stdin = child.stdin.take().unwrap();
+    write!(stdin, "Content-Length: {}\r\n\r\n{}", request.len(), request).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\"capabilities\""), "{}", stdout);
+}
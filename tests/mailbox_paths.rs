@@ -0,0 +1,64 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::mailbox::MailboxConvention;
+
+fn convention(separator: char, prefix: &str) -> MailboxConvention {
+    MailboxConvention {
+        separator: Some(separator),
+        namespace_prefix: Some(prefix.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_conversion_between_conventions() {
+    let dovecot = convention('/', "");
+    let courier = convention('.', "INBOX.");
+    assert_eq!(courier.convert("Lists/Rust"), "INBOX.Lists.Rust");
+    assert_eq!(courier.convert("Archive"), "INBOX.Archive");
+    assert_eq!(dovecot.convert("INBOX.Lists.Rust"), "INBOX/Lists/Rust");
+    assert_eq!(dovecot.convert("Lists.Rust"), "Lists/Rust");
+}
+
+#[test]
+fn test_paths_following_conventions_pass() {
+    let courier = convention('.', "INBOX.");
+    assert_eq!(courier.check("INBOX.Lists.Rust"), None);
+    assert_eq!(courier.check("inbox"), None);
+    assert_eq!(convention('/', "").check("Lists/Rust"), None);
+    assert_eq!(MailboxConvention::default().check("Lists/Rust"), None);
+}
+
+#[tokio::test]
+async fn test_wrong_separator_and_missing_prefix() {
+    let text = "require \"fileinto\";\n\
+                fileinto \"Lists/Rust\";\n\
+                fileinto \"Archive\";\n\
+                fileinto \"INBOX.Spam\";\n\
+                fileinto \"${folder}\";\n";
+    let mailbox = json!({ "mailbox": { "separator": ".", "namespacePrefix": "INBOX." } });
+    let all = diagnostics_with_settings(text, mailbox).await;
+    let diagnostics = with_code(&all, "mailbox-path");
+    assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0].message,
+        "Mailbox 'Lists/Rust' uses '/' as separator; the server separates folders with '.'"
+    );
+    assert_eq!(
+        diagnostics[0].data.as_ref().unwrap()["replacement"],
+        "\"INBOX.Lists.Rust\""
+    );
+    assert_eq!(
+        diagnostics[1].message,
+        "Mailbox 'Archive' is missing the namespace prefix 'INBOX.'"
+    );
+}
+
+#[tokio::test]
+async fn test_unconfigured_server_is_not_checked() {
+    let text = "require \"fileinto\";\nfileinto \"Lists/Rust\";\nfileinto \"Lists.Go\";\n";
+    let all = diagnostics_with_settings(text, json!({ "mailbox": {} })).await;
+    assert!(with_code(&all, "mailbox-path").is_empty());
+}
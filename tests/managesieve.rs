@@ -0,0 +1,420 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::managesieve::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const GREETING: &str = "\"IMPLEMENTATION\" \"Fake Sieve\"\r\n\
+                        \"SIEVE\" \"fileinto vacation\"\r\n\
+                        \"SASL\" \"PLAIN\"\r\n\
+                        \"VERSION\" \"1.0\"\r\n\
+                        OK \"ready\"\r\n";
+
+/// Scripts of the fake server, with the name of the active one
+#[derive(Default)]
+struct Store {
+    scripts: BTreeMap<String, String>,
+    active: Option<String>,
+    logins: Vec<String>,
+    /// Behave like servers predating RFC 5804, without VERSION and CHECKSCRIPT
+    legacy: bool,
+    /// Commands received, with their first argument
+    commands: Vec<String>,
+}
+
+/// Response of the fake server to a submitted script
+fn verdict(content: &str) -> String {
+    if content.contains("fileinot") {
+        let message = "line 2: error: unknown command 'fileinot' (author error).\r\nerror: failed.";
+        format!("NO {{{}}}\r\n{}\r\n", message.len(), message)
+    } else if content.contains("discard") {
+        "OK (WARNINGS) \"line 1: warning: mail is discarded silently\"\r\n".to_string()
+    } else {
+        "OK\r\n".to_string()
+    }
+}
+
+/// Serve ManageSieve on a local port, answering from a shared script store
+async fn fake_server(store: Arc<Mutex<Store>>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = socket.into_split();
+                let mut reader = BufReader::new(read);
+                let legacy = store.lock().unwrap().legacy;
+                let greeting = if legacy {
+                    GREETING.replace("\"VERSION\" \"1.0\"\r\n", "")
+                } else {
+                    GREETING.to_string()
+                };
+                write.write_all(greeting.as_bytes()).await.unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).await.unwrap() > 0 {
+                    let request = line.trim_end().to_string();
+                    line.clear();
+                    let (command, rest) = request.split_once(' ').unwrap_or((&request, ""));
+                    let argument = rest.split('"').nth(1).unwrap_or_default().to_string();
+                    if command != "AUTHENTICATE" {
+                        let logged = format!("{} {}", command, argument);
+                        store.lock().unwrap().commands.push(logged.trim_end().to_string());
+                    }
+                    let response = match command {
+                        "AUTHENTICATE" => {
+                            let credentials = rest.rsplit('"').nth(1).unwrap().to_string();
+                            store.lock().unwrap().logins.push(credentials);
+                            "OK\r\n".to_string()
+                        }
+                        "LISTSCRIPTS" => {
+                            let store = store.lock().unwrap();
+                            let mut response = String::new();
+                            for name in store.scripts.keys() {
+                                let active = store.active.as_ref() == Some(name);
+                                response.push_str(&format!(
+                                    "\"{}\"{}\r\n",
+                                    name,
+                                    if active { " ACTIVE" } else { "" }
+                                ));
+                            }
+                            response + "OK\r\n"
+                        }
+                        "GETSCRIPT" => match store.lock().unwrap().scripts.get(&argument) {
+                            Some(content) => {
+                                format!("{{{}}}\r\n{}\r\nOK\r\n", content.len(), content)
+                            }
+                            None => "NO (NONEXISTENT) \"There is no such script\"\r\n".to_string(),
+                        },
+                        "PUTSCRIPT" | "CHECKSCRIPT" => {
+                            let length: usize = rest
+                                .rsplit('{')
+                                .next()
+                                .unwrap()
+                                .trim_end_matches("+}")
+                                .parse()
+                                .unwrap();
+                            let mut content = vec![0; length + 2];
+                            reader.read_exact(&mut content).await.unwrap();
+                            content.truncate(length);
+                            let content = String::from_utf8(content).unwrap();
+                            let response = verdict(&content);
+                            if command == "CHECKSCRIPT" && legacy {
+                                "NO \"Unknown command\"\r\n".to_string()
+                            } else {
+                                if command == "PUTSCRIPT" && response.starts_with("OK") {
+                                    store.lock().unwrap().scripts.insert(argument, content);
+                                }
+                                response
+                            }
+                        }
+                        "SETACTIVE" => {
+                            store.lock().unwrap().active =
+                                (!argument.is_empty()).then_some(argument);
+                            "OK\r\n".to_string()
+                        }
+                        "DELETESCRIPT" => {
+                            let mut store = store.lock().unwrap();
+                            if store.active.as_ref() == Some(&argument) {
+                                "NO (ACTIVE) \"You may not delete an active script\"\r\n"
+                                    .to_string()
+                            } else {
+                                store.scripts.remove(&argument);
+                                "OK\r\n".to_string()
+                            }
+                        }
+                        "LOGOUT" => {
+                            write.write_all(b"OK \"Logout completed\"\r\n").await.unwrap();
+                            break;
+                        }
+                        _ => "NO \"Unknown command\"\r\n".to_string(),
+                    };
+                    write.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+    port
+}
+
+fn settings(port: u16) -> ManageSieveSettings {
+    serde_json::from_value(json!({
+        "host": "127.0.0.1",
+        "port": port,
+        "username": "alice",
+        "password": "secret",
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_session_round_trip() {
+    let store = Arc::new(Mutex::new(Store::default()));
+    let port = fake_server(store.clone()).await;
+
+    let mut client = connect(&settings(port)).await.unwrap();
+    assert_eq!(
+        client.capabilities(),
+        &SieveCapabilities {
+            implementation: Some("Fake Sieve".to_string()),
+            sieve: vec!["fileinto".to_string(), "vacation".to_string()],
+            sasl: vec!["PLAIN".to_string()],
+            starttls: false,
+            version: Some("1.0".to_string()),
+        }
+    );
+    assert_eq!(store.lock().unwrap().logins, ["AGFsaWNlAHNlY3JldA=="]);
+
+    client.put_script("main", "keep;\r\n").await.unwrap();
+    client.put_script("spam", "discard;\r\n").await.unwrap();
+    client.set_active("main").await.unwrap();
+    assert_eq!(
+        client.list_scripts().await.unwrap(),
+        [
+            RemoteScript {
+                name: "main".to_string(),
+                active: true
+            },
+            RemoteScript {
+                name: "spam".to_string(),
+                active: false
+            },
+        ]
+    );
+    assert_eq!(client.get_script("spam").await.unwrap(), "discard;\r\n");
+
+    assert_eq!(
+        client.delete_script("main").await,
+        Err(ManageSieveError::Refused {
+            code: Some("ACTIVE".to_string()),
+            message: "You may not delete an active script".to_string(),
+        })
+    );
+    client.delete_script("spam").await.unwrap();
+    assert_eq!(
+        client.put_script("bad", "fileinot \"x\";").await,
+        Err(ManageSieveError::Refused {
+            code: None,
+            message: "line 2: error: unknown command 'fileinot' (author error).\r\nerror: failed."
+                .to_string(),
+        })
+    );
+    client.logout().await.unwrap();
+    assert_eq!(store.lock().unwrap().scripts.len(), 1);
+}
+
+#[test]
+fn test_password_stays_out_of_logs() {
+    let settings = settings(DEFAULT_PORT);
+    let output = format!("{:?}", settings);
+    assert!(!output.contains("secret"), "{}", output);
+    assert!(serde_json::to_value(&settings).unwrap().get("password").is_none());
+}
+
+#[tokio::test]
+async fn test_commands_use_configured_server() {
+    let store = Arc::new(Mutex::new(Store::default()));
+    store
+        .lock()
+        .unwrap()
+        .scripts
+        .insert("main".to_string(), "keep;\n".to_string());
+    let port = fake_server(store.clone()).await;
+
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let execute = |command: &str, arguments: Vec<serde_json::Value>| {
+        server.execute_command(ExecuteCommandParams {
+            command: command.to_string(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    let error = execute("sieve.listRemoteScripts", Vec::new()).await.unwrap_err();
+    assert_eq!(error.message, "No ManageSieve server is configured");
+
+    *server.settings.write().await = serde_json::from_value(json!({
+        "managesieve": { "host": "127.0.0.1", "port": port, "username": "alice" }
+    }))
+    .unwrap();
+
+    let listed = execute("sieve.listRemoteScripts", Vec::new()).await.unwrap();
+    assert_eq!(listed, Some(json!([{ "name": "main", "active": false }])));
+    assert_eq!(
+        *server.remote_capabilities.read().await,
+        Some(vec!["fileinto".to_string(), "vacation".to_string()])
+    );
+
+    let uri = Url::parse("file:///vacation.sieve").unwrap();
+    let text = "require \"vacation\";\nvacation \"Away\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    execute("sieve.uploadScript", vec![json!("away"), json!(uri.to_string())])
+        .await
+        .unwrap();
+    execute("sieve.activateScript", vec![json!("away")]).await.unwrap();
+    let downloaded = execute("sieve.downloadScript", vec![json!("away")]).await.unwrap();
+    assert_eq!(downloaded, Some(json!(text)));
+    assert_eq!(store.lock().unwrap().active.as_deref(), Some("away"));
+
+    execute("sieve.deleteScript", vec![json!("main")]).await.unwrap();
+    let error = execute("sieve.deleteScript", vec![json!("away")]).await.unwrap_err();
+    assert_eq!(error.message, "Server refused: You may not delete an active script (ACTIVE)");
+}
+
+#[tokio::test]
+async fn test_remote_capabilities_drive_validation() {
+    let store = Arc::new(Mutex::new(Store::default()));
+    let port = fake_server(store).await;
+
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///notify.sieve").unwrap();
+    let text = "require [\"fileinto\", \"enotify\"];\nfileinto \"Archive\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let unsupported = || async {
+        server
+            .validate_document(&uri)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unsupported-feature".to_string())))
+            .map(|d| d.message)
+            .collect::<Vec<_>>()
+    };
+    assert!(unsupported().await.is_empty());
+
+    server
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: json!({ "managesieve": { "host": "127.0.0.1", "port": port } }),
+        })
+        .await;
+    for _ in 0..50 {
+        if server.remote_capabilities.read().await.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        unsupported().await,
+        ["The server does not advertise the \"enotify\" extension"]
+    );
+}
+
+#[test]
+fn test_check_messages_become_diagnostics() {
+    let text = "require \"fileinto\";\nfileinot \"Junk\";\n";
+    let report = CheckReport {
+        valid: false,
+        message: "line 2: error: unknown command 'fileinot' (author error).\r\nerror: failed."
+            .to_string(),
+    };
+    let diagnostics = check_diagnostics(&report, text);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "unknown command 'fileinot' (author error).");
+    assert_eq!(diagnostics[0].range, Range::new(Position::new(1, 0), Position::new(1, 16)));
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(diagnostics[0].source.as_deref(), Some("managesieve"));
+
+    // Cyrus style, with a column
+    let report = CheckReport {
+        valid: false,
+        message: "script errors:\nline 2 column 5: syntax error".to_string(),
+    };
+    let diagnostics = check_diagnostics(&report, text);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "syntax error");
+    assert_eq!(diagnostics[0].range.start, Position::new(1, 4));
+
+    // Without any position the whole message lands on the first line
+    let report = CheckReport {
+        valid: false,
+        message: "Script too complex".to_string(),
+    };
+    let diagnostics = check_diagnostics(&report, text);
+    assert_eq!(diagnostics[0].message, "Script too complex");
+    assert_eq!(diagnostics[0].range.start, Position::new(0, 0));
+}
+
+#[tokio::test]
+async fn test_check_remote_publishes_server_errors() {
+    for legacy in [false, true] {
+        let store = Arc::new(Mutex::new(Store {
+            legacy,
+            ..Store::default()
+        }));
+        // A script of the user that happens to carry the prefix of the temporary names
+        let own = (CHECK_SCRIPT_NAME.to_string(), "keep;\n".to_string());
+        store.lock().unwrap().scripts.extend([own.clone()]);
+        let port = fake_server(store.clone()).await;
+
+        let (service, _socket) = LspService::new(SieveLanguageServer::new);
+        let server = service.inner();
+        *server.settings.write().await = serde_json::from_value(json!({
+            "managesieve": { "host": "127.0.0.1", "port": port }
+        }))
+        .unwrap();
+        let uri = Url::parse("file:///check.sieve").unwrap();
+        let check = |text: &str| {
+            server
+                .document_map
+                .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+            server.execute_command(ExecuteCommandParams {
+                command: "sieve.checkRemote".to_string(),
+                arguments: vec![json!(uri.to_string())],
+                work_done_progress_params: Default::default(),
+            })
+        };
+
+        let result = check("require \"fileinto\";\nfileinot \"Junk\";\n").await.unwrap();
+        let diagnostics: Vec<Diagnostic> = serde_json::from_value(result.unwrap()).unwrap();
+        assert_eq!(diagnostics.len(), 1, "legacy: {}", legacy);
+        assert_eq!(diagnostics[0].range.start.line, 1);
+        assert_eq!(server.remote_diagnostics.get(&uri).unwrap().0, 1);
+
+        let result = check("discard;\n").await.unwrap();
+        let diagnostics: Vec<Diagnostic> = serde_json::from_value(result.unwrap()).unwrap();
+        assert_eq!(diagnostics[0].message, "mail is discarded silently");
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+
+        let result = check("keep;\n").await.unwrap();
+        assert_eq!(result, Some(json!([])));
+        // Nothing was left behind by the dry run, and the user's script was not touched
+        let store = store.lock().unwrap();
+        assert_eq!(store.scripts, BTreeMap::from([own]));
+        let puts: Vec<&String> =
+            store.commands.iter().filter(|c| c.starts_with("PUTSCRIPT")).collect();
+        if legacy {
+            assert_eq!(puts.len(), 3);
+            for put in puts {
+                let name = put.trim_start_matches("PUTSCRIPT ");
+                assert!(name.starts_with(&format!("{}-", CHECK_SCRIPT_NAME)), "{}", name);
+                // The name was confirmed to be free first
+                let index = store.commands.iter().position(|c| c == put).unwrap();
+                assert_eq!(store.commands[index - 1], "LISTSCRIPTS");
+            }
+        } else {
+            assert!(puts.is_empty());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_literals_are_refused() {
+    let (client, mut server) = tokio::io::duplex(1024);
+    server.write_all(b"{1099511627776}\r\n").await.unwrap();
+    let result = ManageSieveClient::new(client, Duration::from_secs(5)).await;
+    let Err(ManageSieveError::Protocol(message)) = result else {
+        panic!("expected a protocol error");
+    };
+    assert!(message.contains("exceeds the limit"), "{}", message);
+}
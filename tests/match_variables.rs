@@ -0,0 +1,75 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::variables::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require [\"variables\", \"regex\", \"fileinto\"];\nif header :matches \"subject\" \"[*] * from ?\" {\n    fileinto \"${1}/${3}\";\n}\nif address :regex \"from\" \"^([a-z]+)@(example\\\\.(com|org))$\" {\n    fileinto \"${2}\";\n}\n";
+
+#[test]
+fn test_variable_reference_at() {
+    let line = "    fileinto \"${1}/${3}\";";
+    assert_eq!(variable_reference_at(line, 16), Some(("1".to_string(), 14, 18)));
+    assert_eq!(variable_reference_at(line, 20), Some(("3".to_string(), 19, 23)));
+    assert_eq!(variable_reference_at(line, 5), None);
+}
+
+#[test]
+fn test_wildcard_and_group_positions() {
+    assert_eq!(match_group_position("[*] * from ?", 1, false), Some((1, "*".to_string())));
+    assert_eq!(match_group_position("[*] * from ?", 3, false), Some((11, "?".to_string())));
+    assert_eq!(match_group_position("a\\*b*", 1, false), Some((4, "*".to_string())));
+    assert_eq!(
+        match_group_position("^([a-z]+)@(example\\.(com|org))$", 2, true),
+        Some((10, "(example\\.(com|org))".to_string()))
+    );
+    assert_eq!(match_group_position("[(]x", 1, true), None);
+}
+
+#[test]
+fn test_hover_names_the_source_test() {
+    let script = parse(SCRIPT);
+    let doc = match_variable_hover(&script, Position::new(2, 16), 3);
+    assert!(doc.contains("header :matches"), "{}", doc);
+    assert!(doc.contains("line 2"), "{}", doc);
+    assert!(doc.contains("3rd wildcard `?` at offset 11"), "{}", doc);
+
+    let doc = match_variable_hover(&script, Position::new(5, 16), 2);
+    assert!(doc.contains("address :regex"), "{}", doc);
+    assert!(doc.contains("2nd capture group"), "{}", doc);
+}
+
+#[test]
+fn test_hover_without_match_test() {
+    let script = parse("require \"variables\";\nset \"x\" \"${1}\";\n");
+    let doc = match_variable_hover(&script, Position::new(1, 12), 1);
+    assert!(doc.contains("No preceding"), "{}", doc);
+}
+
+#[tokio::test]
+async fn test_server_hover_on_match_variable() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1),
+    );
+
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(2, 16),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .expect("hover for ${1}");
+    let HoverContents::Markup(content) = hover.contents else {
+        panic!("expected markdown hover");
+    };
+    assert!(content.value.contains("1st wildcard"), "{}", content.value);
+    assert_eq!(hover.range.unwrap().start, Position::new(2, 14));
+}
@@ -0,0 +1,149 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::interpreter::run_corpus;
+use sieve_language_server::lsp::COMMAND_RUN_CORPUS;
+use sieve_language_server::message::*;
+use sieve_language_server::parser::parse;
+use std::process::Command;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[test]
+fn test_parse_message_headers_and_body() {
+    let raw = "From: Alice <alice@example.com>\r\nSubject: Hello\r\n  world\r\nX-Test: 1\r\n\r\nBody line\r\n";
+    let message = Message::parse(raw);
+    assert_eq!(message.header("subject"), vec!["Hello world"]);
+    assert_eq!(message.header("FROM"), vec!["Alice <alice@example.com>"]);
+    assert_eq!(message.body, "Body line\r\n");
+    assert_eq!(message.size, raw.len());
+}
+
+#[test]
+fn test_parse_mbox_splits_messages() {
+    let mbox = "From alice@example.com Mon Jan  1 00:00:00 2024\nSubject: one\n\n>From the start\n\nFrom bob@example.com Mon Jan  1 00:00:00 2024\nSubject: two\n\nhi\n";
+    let messages = parse_mbox(mbox, "inbox.mbox");
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].envelope_from.as_deref(), Some("alice@example.com"));
+    assert!(messages[0].body.starts_with("From the start"));
+    assert_eq!(messages[1].header("subject"), vec!["two"]);
+    assert_eq!(messages[1].origin, "inbox.mbox#1");
+}
+
+#[test]
+fn test_load_maildir() {
+    let root = std::env::temp_dir().join(format!("sieve-maildir-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("cur")).unwrap();
+    std::fs::create_dir_all(root.join("new")).unwrap();
+    std::fs::write(root.join("cur/1"), "Subject: a\n\nx\n").unwrap();
+    std::fs::write(root.join("new/2"), "Subject: b\n\ny\n").unwrap();
+
+    let messages = load_corpus(&root).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].header("subject"), vec!["a"]);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+const CORPUS_SCRIPT: &str = "require [\"fileinto\", \"copy\"];\n\
+                             if header :contains \"list-id\" \"dev\" {\n\
+                             \x20   fileinto \"Lists\";\n\
+                             \x20   if header :contains \"subject\" \"release\" {\n\
+                             \x20       fileinto :copy \"Releases\";\n\
+                             \x20   }\n\
+                             } elsif address :is \"from\" \"boss@example.com\" {\n\
+                             \x20   fileinto \"Work\";\n\
+                             }\n";
+
+const CORPUS_MBOX: &str = "From a@example.com Mon Jan  1 00:00:00 2024\n\
+                           List-Id: <dev.example.org>\nSubject: release 1.0\n\nx\n\n\
+                           From a@example.com Mon Jan  1 00:00:00 2024\n\
+                           List-Id: <dev.example.org>\nSubject: question\n\nx\n\n\
+                           From boss@example.com Mon Jan  1 00:00:00 2024\n\
+                           From: boss@example.com\nSubject: meeting\n\nx\n\n\
+                           From c@example.com Mon Jan  1 00:00:00 2024\n\
+                           From: c@example.com\nSubject: hello\n\nx\n";
+
+#[test]
+fn test_run_corpus_counts_matches_per_rule() {
+    let messages = parse_mbox(CORPUS_MBOX, "corpus.mbox");
+    let report = run_corpus(&parse(CORPUS_SCRIPT), &messages);
+    assert_eq!(report.messages, 4);
+    assert_eq!(report.kept, 1);
+    let rules: Vec<_> = report
+        .rules
+        .iter()
+        .map(|rule| {
+            let destinations: Vec<_> =
+                rule.destinations.iter().map(|(m, n)| (m.as_str(), *n)).collect();
+            (rule.range.start.line, rule.command.as_str(), rule.matched, destinations)
+        })
+        .collect();
+    assert_eq!(
+        rules,
+        [
+            (1, "if", 2, vec![("Lists", 2), ("Releases", 1)]),
+            (3, "if", 1, vec![("Releases", 1)]),
+            (6, "elsif", 1, vec![("Work", 1)]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_corpus_command_and_cli() {
+    let dir = std::env::temp_dir().join(format!("sieve-corpus-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("filter.sieve");
+    let mbox = dir.join("corpus.mbox");
+    std::fs::write(&script, CORPUS_SCRIPT).unwrap();
+    std::fs::write(&mbox, CORPUS_MBOX).unwrap();
+
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let settings = json!({ "sample_messages": mbox.display().to_string() });
+    *server.settings.write().await = serde_json::from_value(settings).unwrap();
+    let uri = Url::from_file_path(&script).unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), CORPUS_SCRIPT.to_string(), 1));
+    let report = server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_RUN_CORPUS.to_string(),
+            arguments: vec![json!(uri)],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(report["messages"], 4);
+    assert_eq!(report["rules"][2]["destinations"], json!({ "Work": 1 }));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["corpus", script.to_str().unwrap(), mbox.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "4 messages, 1 kept in the inbox\n\
+         line 2: if header matched 2\n\
+         \x20 fileinto \"Lists\": 2\n\
+         \x20 fileinto \"Releases\": 1\n\
+         line 4: if header matched 1\n\
+         \x20 fileinto \"Releases\": 1\n\
+         line 7: elsif address matched 1\n\
+         \x20 fileinto \"Work\": 1\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["corpus", "--format", "json", script.to_str().unwrap(), mbox.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["rules"][0]["matched"], 2);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+        .args(["corpus", script.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
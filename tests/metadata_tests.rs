@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::roles::{StringRole, role_markdown, string_at};
+use sieve_language_server::sieve::metadata_entry_problem;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_entry_names() {
+    assert_eq!(metadata_entry_problem("/private/comment"), None);
+    assert_eq!(metadata_entry_problem("/Shared/vendor/acme/x"), None);
+    assert!(metadata_entry_problem("/comment").is_some());
+    assert!(metadata_entry_problem("/shared/").is_some());
+    assert!(metadata_entry_problem("/shared/a//b").is_some());
+    assert!(metadata_entry_problem("/private/*").is_some());
+    assert!(metadata_entry_problem("/private/my comment").is_some());
+}
+
+#[tokio::test]
+async fn test_valid_metadata_tests() {
+    let text = "require [\"mboxmetadata\", \"servermetadata\"];\n\
+                if metadata :contains \"INBOX\" \"/private/comment\" \"important\" { keep; }\n\
+                if metadataexists \"Lists\" [\"/shared/comment\", \"/private/comment\"] { keep; }\n\
+                if servermetadata :matches \"/shared/admin\" \"mailto:*\" { keep; }\n\
+                if servermetadataexists \"/shared/admin\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-metadata").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unused-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unknown-command").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_metadata_requires_its_extension() {
+    let text = "require \"mboxmetadata\";\n\
+                if servermetadataexists \"/shared/admin\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        missing[0].message,
+        "'servermetadataexists' requires the \"servermetadata\" extension"
+    );
+    assert_eq!(missing[0].range.start, Position::new(1, 3));
+    assert_eq!(with_code(&diagnostics, "unused-require").len(), 1, "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_invalid_metadata_arguments() {
+    let text = "require [\"mboxmetadata\", \"servermetadata\"];\n\
+                if metadata \"INBOX\" \"/private/comment\" { keep; }\n\
+                if metadata [\"INBOX\", \"Sent\"] [\"/private/comment\"] \"x\" { keep; }\n\
+                if metadataexists :is \"INBOX\" \"/comment\" { keep; }\n\
+                if servermetadataexists [\"/shared/admin\", \"/shared/a*\"] { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-metadata")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (1, "'metadata' expects a mailbox, an entry name and keys"),
+            (2, "The mailbox of 'metadata' must be a single string"),
+            (2, "The entry name of 'metadata' must be a single string"),
+            (3, "':is' is not an argument of 'metadataexists'"),
+            (
+                3,
+                "'/comment' is not a metadata entry name: entry names start with \"/private/\" \
+                 or \"/shared/\""
+            ),
+            (4, "'/shared/a*' is not a metadata entry name: entry names cannot contain '*' or '%'"),
+        ]
+    );
+}
+
+#[test]
+fn test_entry_hover() {
+    let text = "if metadata \"INBOX\" \"/private/comment\" \"x\" { keep; }";
+    let script = parse(text);
+    let (string, role) = string_at(&script, Position::new(0, 25)).unwrap();
+    assert_eq!(role, StringRole::MetadataEntry);
+    let doc = role_markdown(&string.value, &role);
+    assert!(doc.starts_with("**\"/private/comment\"** - metadata entry"), "{}", doc);
+    assert!(doc.contains("only visible to the user"), "{}", doc);
+
+    let (_, role) = string_at(&script, Position::new(0, 14)).unwrap();
+    assert_eq!(role, StringRole::Mailbox);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::requires::used_capabilities;
+use std::collections::BTreeMap;
+
+#[tokio::test]
+async fn test_valid_foreverypart_passes() {
+    let text = "require [\"foreverypart\", \"mime\", \"fileinto\", \"vacation\"];\n\
+                foreverypart :name \"parts\" {\n\
+                \x20   if header :mime :type \"Content-Type\" \"image\" {\n\
+                \x20       fileinto \"Images\";\n\
+                \x20       break :name \"parts\";\n\
+                \x20   }\n\
+                \x20   foreverypart {\n\
+                \x20       if header :mime :anychild :param [\"filename\"] \"Content-Disposition\" \"*.exe\" { break; }\n\
+                \x20   }\n\
+                }\n\
+                if exists :mime :anychild \"Content-Language\" { keep; }\n\
+                vacation :mime \"Content-Type: text/plain\\r\\n\\r\\nAway\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    for code in [
+        "invalid-break",
+        "invalid-mime",
+        "invalid-syntax",
+        "missing-semicolon",
+        "missing-require",
+        "unused-require",
+    ] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+
+    let used: Vec<String> = used_capabilities(&parse(text), &BTreeMap::new())
+        .into_iter()
+        .collect();
+    assert_eq!(used, ["fileinto", "foreverypart", "mime", "vacation"]);
+}
+
+#[tokio::test]
+async fn test_break_outside_its_loop() {
+    let text = "require [\"foreverypart\"];\n\
+                foreverypart :name \"outer\" {\n\
+                \x20   foreverypart { break :name \"inner\"; }\n\
+                }\n\
+                break;\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-break")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (2, "No enclosing 'foreverypart' loop is named 'inner'"),
+            (4, "'break' is only allowed inside 'foreverypart'"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_mime_tags_need_matching_tests() {
+    let text = "require [\"mime\"];\n\
+                if address :mime :type \"From\" \"x\" { keep; }\n\
+                if header :anychild \"Subject\" \"x\" { keep; }\n\
+                if header :mime :type :subtype \"Content-Type\" \"x\" { keep; }\n\
+                if size :mime :over 1K { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-mime")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (1, "'address' does not accept ':type'"),
+            (2, "':anychild' requires ':mime'"),
+            (3, "Use only one of ':type', ':subtype', ':contenttype' and ':param'"),
+            (4, "'size' does not accept ':mime'"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_statements_after_break_are_unreachable() {
+    let text = "require [\"foreverypart\"];\n\
+                foreverypart {\n\
+                \x20   break;\n\
+                \x20   keep;\n\
+                }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let unreachable: Vec<&str> = with_code(&diagnostics, "unreachable-code")
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(unreachable, ["Unreachable code: statement follows 'break'"]);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::*;
+use tower_lsp::lsp_types::*;
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+#[tokio::test]
+async fn test_missing_require_is_reported_at_first_usage() {
+    let text = "require \"envelope\";\n\
+                if true {\n    fileinto \"Archive\";\n}\nfileinto \"Other\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "missing-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range, range(2, 4, 12));
+    assert!(found[0].message.contains("'fileinto'"));
+
+    let related = found[0].related_information.clone().unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].location.range, range(0, 0, 19));
+    assert_eq!(related[0].message, "Extensions are required here");
+}
+
+#[tokio::test]
+async fn test_missing_require_without_require_statement() {
+    let text = "if header :contains \"subject\" \"x\" {\n  addflag \"\\\\Seen\";\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "missing-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range, range(1, 2, 9));
+    assert_eq!(found[0].related_information, None);
+}
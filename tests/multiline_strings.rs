@@ -0,0 +1,28 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+const VACATION: &str = "require \"vacation\";\nvacation :days 7 text:\nI am away; keep calm.\nreject this line as a statement\n.\n;\nkeep;\n";
+
+#[tokio::test]
+async fn test_text_block_content_is_not_validated() {
+    let diagnostics = diagnostics_for(VACATION).await;
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_no_completions_inside_text_block() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), VACATION.to_string(), 1),
+    );
+
+    assert!(server.get_completions(&uri, Position::new(2, 3)).await.is_empty());
+    assert!(!server.get_completions(&uri, Position::new(6, 0)).await.is_empty());
+}
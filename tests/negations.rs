@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::refactor::*;
+use tower_lsp::lsp_types::*;
+
+fn replacement(diagnostic: &Diagnostic) -> Option<&str> {
+    diagnostic.data.as_ref()?["replacement"].as_str()
+}
+
+#[tokio::test]
+async fn test_double_negation() {
+    let text = "if not not exists \"x\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redundant-negation");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "Double negation: 'not not' cancels out");
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(found[0].range, Range::new(Position::new(0, 3), Position::new(0, 21)));
+    assert_eq!(replacement(found[0]), Some("exists \"x\""));
+
+    // Longer chains are reported once and keep an odd negation
+    let text = "if not not not exists \"x\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redundant-negation");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(replacement(found[0]), Some("not exists \"x\""));
+}
+
+#[tokio::test]
+async fn test_negated_constants() {
+    let text = "if not true { stop; }\nif anyof (not false, size :over 1M) { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redundant-negation");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "'not true' is always false");
+    assert_eq!(replacement(found[0]), Some("false"));
+    assert_eq!(found[1].message, "'not false' is always true");
+    assert_eq!(found[1].data.as_ref().unwrap()["title"], "Replace with \"true\"");
+}
+
+#[tokio::test]
+async fn test_de_morgan_keeps_formatting() {
+    let text = "if anyof (not header :is \"from\" \"a@example.com\",\n\
+                          # only during the week\n\
+                          not   exists \"x-weekend\") { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redundant-negation");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "'anyof' of negated tests is simpler as 'not allof'");
+    assert_eq!(
+        replacement(found[0]),
+        Some(
+            "not allof (header :is \"from\" \"a@example.com\",\n\
+             # only during the week\n\
+             exists \"x-weekend\")"
+        )
+    );
+
+    // Mixed lists are left alone
+    let text = "if allof (not exists \"a\", exists \"b\") { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "redundant-negation").is_empty());
+}
+
+#[test]
+fn test_comments_inside_negations_block_the_rewrite() {
+    let text = "if not # keep this\n not exists \"x\" { stop; }\n";
+    let script = parse(text);
+    let test = &script.commands[0].tests[0];
+    assert_eq!(redundant_negation(test), Some(Negation::Double));
+    assert_eq!(simplify_negation(text, test), None);
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::notify::check_method;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[test]
+fn test_method_uris() {
+    assert!(check_method("mailto:alice@example.com").is_ok());
+    assert!(check_method("mailto:alice@example.com,bob@example.org?subject=Mail%20arrived").is_ok());
+    assert!(check_method("mailto:?to=alice%40example.com").is_ok());
+    assert!(check_method("xmpp:alice@example.com").is_ok());
+
+    assert_eq!(
+        check_method("alice@example.com").unwrap_err(),
+        "'alice@example.com' is not a notification URI: it needs a scheme such as 'mailto:'"
+    );
+    assert_eq!(
+        check_method("mailto:").unwrap_err(),
+        "The notification URI 'mailto:' is empty after its scheme"
+    );
+    assert_eq!(
+        check_method("mailto:?subject=hi").unwrap_err(),
+        "The mailto URI has no recipient"
+    );
+    assert_eq!(
+        check_method("mailto:alice").unwrap_err(),
+        "'alice' is not a valid email address"
+    );
+    assert_eq!(
+        check_method("mailto:alice@example.com?subject").unwrap_err(),
+        "Header field 'subject' of the mailto URI has no value"
+    );
+    assert_eq!(
+        check_method("mailto:alice@example.com?subject=100%+done").unwrap_err(),
+        "Invalid percent-encoding in '100%+done'"
+    );
+}
+
+#[tokio::test]
+async fn test_valid_notify_passes() {
+    let text = "require [\"enotify\", \"variables\"];\n\
+                if valid_notify_method \"mailto:\" {\n\
+                \x20   notify :from \"sieve@example.com\" :importance \"1\" :message \"New mail\" \"mailto:alice@example.com\";\n\
+                \x20   notify :options [\"x\"] \"mailto:bob@example.com\";\n\
+                }\n\
+                if notify_method_capability \"xmpp:bob@example.com\" \"Online\" \"yes\" {\n\
+                \x20   notify \"mailto:${owner}\";\n\
+                }\n";
+    let diagnostics = diagnostics_for(text).await;
+    for code in ["invalid-notify", "invalid-syntax", "missing-require", "dialect-mismatch"] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+}
+
+#[tokio::test]
+async fn test_notify_arguments() {
+    let text = "require \"enotify\";\n\
+                notify :from \"nobody\" \"mailto:alice@example.com\";\n\
+                notify :importance \"high\" \"mailto:alice@example.com\";\n\
+                notify \"alice@example.com\";\n\
+                notify :message \"hi\";\n\
+                if notify_method_capability \"mailto:\" \"busy\" \"yes\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-notify")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (1, "'nobody' is not a valid email address"),
+            (2, "':importance' must be \"1\" (high), \"2\" (normal) or \"3\" (low)"),
+            (
+                3,
+                "'alice@example.com' is not a notification URI: it needs a scheme such as 'mailto:'"
+            ),
+            (4, "'notify' expects a single notification method URI"),
+            (5, "Unknown notification capability 'busy'; RFC 5435 only defines \"online\""),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_draft_notify_is_not_checked_as_enotify() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "dialects": { "notify": "draft" } })).unwrap();
+    let uri = Url::parse("file:///draft.sieve").unwrap();
+    let text = "require \"notify\";\nnotify :method \"mailto:alice@example.com\" :low;\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+
+    assert!(with_code(&diagnostics, "invalid-notify").is_empty(), "{:?}", diagnostics);
+}
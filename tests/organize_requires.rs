@@ -0,0 +1,118 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::lsp::{SOURCE_MERGE_REQUIRES, SOURCE_SPLIT_REQUIRES};
+use sieve_language_server::parser::{offset_at, parse};
+use sieve_language_server::requires::{RequireLayout, organize_requires, tidy_requires};
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// Apply non-overlapping edits back to front
+fn apply(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|e| (e.range.start.line, e.range.start.character));
+    let mut result = text.to_string();
+    for edit in edits.iter().rev() {
+        let start = offset_at(&result, edit.range.start);
+        let end = offset_at(&result, edit.range.end);
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+fn organize(text: &str, layout: RequireLayout) -> String {
+    apply(text, &organize_requires(text, &parse(text), layout))
+}
+
+#[test]
+fn test_merge_requires() {
+    let text = "require \"vacation\";\n# filing\nrequire [\"fileinto\", \"body\"];\n\
+                require \"fileinto\";\nkeep;\n";
+    assert_eq!(
+        organize(text, RequireLayout::Merged),
+        "require [\"body\", \"fileinto\", \"vacation\"];\n# filing\nkeep;\n"
+    );
+    // A single statement is left as it is
+    let text = "require [\"fileinto\", \"body\"];\nkeep;\n";
+    assert!(organize_requires(text, &parse(text), RequireLayout::Merged).is_empty());
+}
+
+#[test]
+fn test_split_requires() {
+    let text = "require [\"fileinto\", \"body\"];\nrequire \"vacation\";\nkeep;\n";
+    assert_eq!(
+        organize(text, RequireLayout::Split),
+        "require \"fileinto\";\nrequire \"body\";\nrequire \"vacation\";\nkeep;\n"
+    );
+    let text = "require \"vacation\";\nkeep;\n";
+    assert!(organize_requires(text, &parse(text), RequireLayout::Split).is_empty());
+}
+
+#[test]
+fn test_tidy_requires() {
+    let tidy = |text: &str| apply(text, &tidy_requires(text, &parse(text), &BTreeMap::new()));
+    let text = "require [\"vacation\", \"fileinto\"];\nrequire [\"body\", \"fileinto\"];\n\
+                require \"vnd.example.custom\";\nfileinto \"A\";\nvacation \"Away\";\n";
+    assert_eq!(
+        tidy(text),
+        "require [\"fileinto\", \"vacation\", \"vnd.example.custom\"];\n\
+         fileinto \"A\";\nvacation \"Away\";\n"
+    );
+    // Requires that are all unused go away
+    assert_eq!(tidy("require \"body\";\nkeep;\n"), "keep;\n");
+
+    // Organized requires need no edits
+    let text = "require [\"fileinto\", \"vacation\"];\nfileinto \"A\";\nvacation \"Away\";\n";
+    assert!(tidy_requires(text, &parse(text), &BTreeMap::new()).is_empty());
+
+    // Usage is not trusted in scripts with syntax errors
+    let text = "require [\"body\", \"body\"];\nif true {\n";
+    assert_eq!(tidy(text), "require \"body\";\nif true {\n");
+}
+
+#[tokio::test]
+async fn test_organize_actions_are_offered_on_request() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///requires.sieve").unwrap();
+    let text = "require \"fileinto\";\nrequire \"body\";\nfileinto \"A\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let titles = |only: Option<Vec<CodeActionKind>>| {
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::default(),
+            context: CodeActionContext {
+                only,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        async {
+            server
+                .code_action(params)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => Some(action.title),
+                    CodeActionOrCommand::Command(_) => None,
+                })
+                .collect::<Vec<String>>()
+        }
+    };
+
+    assert!(titles(None).await.is_empty());
+    assert_eq!(
+        titles(Some(vec![CodeActionKind::SOURCE_ORGANIZE_IMPORTS])).await,
+        vec!["Organize requires"]
+    );
+    assert_eq!(
+        titles(Some(vec![CodeActionKind::new(SOURCE_MERGE_REQUIRES)])).await,
+        vec!["Merge require statements into one list"]
+    );
+    // Nothing to split yet
+    assert!(titles(Some(vec![CodeActionKind::new(SOURCE_SPLIT_REQUIRES)])).await.is_empty());
+}
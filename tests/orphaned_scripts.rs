@@ -0,0 +1,101 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::include::*;
+use sieve_language_server::manifest::*;
+use std::fs;
+use std::path::Path;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn workspace(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("sieve-orphans-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::create_dir_all(dir.join(".sieve-lsp")).unwrap();
+    fs::write(dir.join("main.sieve"), "require \"include\";\ninclude \"lib/spam\";\n").unwrap();
+    fs::write(dir.join("lib/spam.sieve"), "require \"include\";\ninclude \"helpers\";\n").unwrap();
+    fs::write(dir.join("lib/helpers.sieve"), "keep;\n").unwrap();
+    fs::write(dir.join("lib/old.sieve"), "require \"include\";\ninclude \"older\";\n").unwrap();
+    fs::write(dir.join("lib/older.sieve"), "discard;\n").unwrap();
+    fs::write(dir.join(MANIFEST_FILE), r#"{ "entryPoints": ["main.sieve"] }"#).unwrap();
+    dir
+}
+
+fn names(paths: &[std::path::PathBuf], root: &Path) -> Vec<String> {
+    paths
+        .iter()
+        .map(|p| p.strip_prefix(root).unwrap().display().to_string())
+        .collect()
+}
+
+#[test]
+fn test_orphans_are_scripts_unreachable_from_entry_points() {
+    let dir = workspace("graph");
+    let manifest = DeploymentManifest::load(&dir).unwrap();
+    let orphans = orphaned_scripts(&dir, &manifest.entry_paths(&dir));
+    let found = names(&orphans, &dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    // old.sieve includes older.sieve, but nothing deployed includes old.sieve
+    assert_eq!(found, ["lib/old.sieve", "lib/older.sieve"]);
+}
+
+#[tokio::test]
+async fn test_orphaned_script_diagnostic() {
+    let dir = workspace("server");
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.workspace_root.write().await = Some(dir.clone());
+    assert!(server.refresh_orphaned_scripts().await);
+
+    let mut codes = Vec::new();
+    for file in ["lib/old.sieve", "lib/helpers.sieve"] {
+        let uri = Url::from_file_path(dir.join(file)).unwrap();
+        let text = fs::read_to_string(dir.join(file)).unwrap();
+        server
+            .document_map
+            .insert(uri.clone(), SieveDocument::new(uri.clone(), text, 1));
+        let diagnostics = server.validate_document(&uri).await.unwrap();
+        codes.push(
+            diagnostics
+                .iter()
+                .any(|d| d.code == Some(NumberOrString::String("unused-script".to_string()))),
+        );
+    }
+
+    // Without a manifest nothing is flagged
+    fs::remove_file(dir.join(MANIFEST_FILE)).unwrap();
+    assert!(server.refresh_orphaned_scripts().await);
+    assert!(server.orphaned_scripts.read().await.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(codes, [true, false]);
+}
+
+#[tokio::test]
+async fn test_external_edits_update_orphans() {
+    let dir = workspace("watched");
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.workspace_root.write().await = Some(dir.clone());
+    server.refresh_orphaned_scripts().await;
+    let old = dir.join("lib/old.sieve").canonicalize().unwrap();
+    assert!(server.orphaned_scripts.read().await.contains(&old));
+
+    // A checkout makes the entry point include old.sieve as well
+    fs::write(
+        dir.join("main.sieve"),
+        "require \"include\";\ninclude \"lib/spam\";\ninclude \"lib/old\";\n",
+    )
+    .unwrap();
+    server
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: Url::from_file_path(dir.join("main.sieve")).unwrap(),
+                typ: FileChangeType::CHANGED,
+            }],
+        })
+        .await;
+    let orphans = server.orphaned_scripts.read().await.clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(orphans.is_empty(), "{:?}", orphans);
+}
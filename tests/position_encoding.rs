@@ -0,0 +1,79 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::encoding::PositionEncoding;
+use tower_lsp::lsp_types::*;
+
+const LINE: &str = "# 📬 inbox rule é\n";
+
+fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(Range::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1),
+        )),
+        range_length: None,
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn test_column_conversion() {
+    // "📬" is one character, two UTF-16 code units and four UTF-8 bytes
+    assert_eq!(PositionEncoding::Utf16.to_char_column(LINE, 4), 3);
+    assert_eq!(PositionEncoding::Utf16.from_char_column(LINE, 3), 4);
+    assert_eq!(PositionEncoding::Utf8.to_char_column(LINE, 6), 3);
+    assert_eq!(PositionEncoding::Utf8.from_char_column(LINE, 3), 6);
+    assert_eq!(PositionEncoding::Utf32.to_char_column(LINE, 3), 3);
+
+    // Columns inside a surrogate pair snap to the character, past the end clamp
+    assert_eq!(PositionEncoding::Utf16.to_char_column(LINE, 3), 2);
+    assert_eq!(PositionEncoding::Utf16.to_char_column(LINE, 100), 16);
+    assert_eq!(PositionEncoding::Utf16.from_char_column(LINE, 100), 17);
+}
+
+#[test]
+fn test_negotiation() {
+    let mut capabilities = ClientCapabilities::default();
+    assert_eq!(PositionEncoding::negotiate(&capabilities), PositionEncoding::Utf16);
+
+    capabilities.general = Some(GeneralClientCapabilities {
+        position_encodings: Some(vec![PositionEncodingKind::UTF16, PositionEncodingKind::UTF8]),
+        ..Default::default()
+    });
+    assert_eq!(PositionEncoding::negotiate(&capabilities), PositionEncoding::Utf8);
+}
+
+#[test]
+fn test_utf16_change_after_emoji() {
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = format!("{}keep;\n", LINE);
+    let mut document = SieveDocument::new(uri, text, 1);
+
+    // Replace "inbox" (UTF-16 columns 5..10) with "spam"
+    document.apply_change(&change((0, 5), (0, 10), "spam"));
+    assert_eq!(document.get_text(), "# 📬 spam rule é\nkeep;\n");
+}
+
+#[test]
+fn test_utf8_change_and_ranges() {
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let mut document =
+        SieveDocument::new(uri, format!("{}keep;\n", LINE), 1).with_encoding(PositionEncoding::Utf8);
+
+    // "é" starts at byte 18 and is two bytes wide
+    document.apply_change(&change((0, 18), (0, 20), "e"));
+    assert_eq!(document.get_text(), "# 📬 inbox rule e\nkeep;\n");
+
+    let range = Range::new(Position::new(0, 3), Position::new(0, 4));
+    assert_eq!(
+        document.to_client_range(range),
+        Range::new(Position::new(0, 6), Position::new(0, 7))
+    );
+}
+
+#[test]
+fn test_out_of_range_change_does_not_panic() {
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let mut document = SieveDocument::new(uri, "keep;\n".to_string(), 1);
+    document.apply_change(&change((0, 40), (7, 3), "\nstop;\n"));
+    assert_eq!(document.get_text(), "keep;\nstop;\n");
+}
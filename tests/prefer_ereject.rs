@@ -0,0 +1,85 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::parser::parse;
+use sieve_language_server::requires::switch_requirement;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LanguageServer;
+
+#[tokio::test]
+async fn test_reject_suggests_ereject_where_supported() {
+    let text = "require \"reject\";\nif size :over 10M { reject \"Too large\"; }\n";
+    let (service, uri) = server_with(json!({ "server_dialect": "dovecot" }), text).await;
+    let diagnostics = service.inner().validate_document(&uri).await.unwrap();
+    let found = with_code(&diagnostics, "prefer-ereject");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::INFORMATION));
+    assert_eq!(found[0].range.start, Position::new(1, 20));
+
+    // Unknown or missing support gets no suggestion
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "prefer-ereject").is_empty(), "{:?}", diagnostics);
+    let settings = json!({ "capabilities": ["reject", "fileinto"] });
+    let (service, uri) = server_with(settings, text).await;
+    let diagnostics = service.inner().validate_document(&uri).await.unwrap();
+    assert!(with_code(&diagnostics, "prefer-ereject").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_quick_fix_swaps_command_and_require() {
+    let text = "require [\"fileinto\", \"reject\"];\nreject \"No\";\n";
+    let settings = json!({ "capabilities": ["fileinto", "reject", "ereject"] });
+    let (service, uri) = server_with(settings, text).await;
+    let server = service.inner();
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let diagnostic = with_code(&diagnostics, "prefer-ereject")[0].clone();
+
+    let actions = server
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: diagnostic.range,
+            context: CodeActionContext {
+                diagnostics: vec![diagnostic.clone()],
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+        panic!("expected a code action");
+    };
+    assert_eq!(action.title, "Replace 'reject' with 'ereject'");
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+    assert_eq!(edits.len(), 2);
+    assert_eq!(edits[0].range, diagnostic.range);
+    assert_eq!(edits[0].new_text, "ereject");
+    assert_eq!(edits[1].range.start, Position::new(0, 0));
+    assert_eq!(edits[1].new_text, "require [\"fileinto\", \"ereject\"];");
+}
+
+#[test]
+fn test_switch_requirement() {
+    let text = "require \"reject\";\nreject \"a\";\nreject \"b\";\n";
+    let script = parse(text);
+    let edit = switch_requirement(text, &script, "reject", "ereject", true).unwrap();
+    assert_eq!(edit.new_text, "require [\"reject\", \"ereject\"];");
+    let edit = switch_requirement(text, &script, "reject", "ereject", false).unwrap();
+    assert_eq!(edit.new_text, "require \"ereject\";");
+
+    // Already required: only the old capability goes
+    let text = "require [\"ereject\", \"reject\"];\nreject \"a\";\n";
+    let script = parse(text);
+    let edit = switch_requirement(text, &script, "reject", "ereject", false).unwrap();
+    assert_eq!(edit.new_text, "require \"ereject\";");
+    assert!(switch_requirement(text, &script, "reject", "ereject", true).is_none());
+
+    // Without requires one is added at the top
+    let text = "reject \"a\";\n";
+    let edit = switch_requirement(text, &parse(text), "reject", "ereject", false).unwrap();
+    assert_eq!(edit.new_text, "require \"ereject\";\n");
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::profile::{self, DOVECOT, PROFILES, PROTON};
+use sieve_language_server::sieve::SIEVE_EXTENSIONS;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[test]
+fn test_profile_lookup() {
+    assert_eq!(profile::find("Proton"), Some(&PROTON));
+    assert_eq!(profile::find("gmail"), None);
+    assert_eq!(profile::find(profile::GENERIC), None);
+    assert!(PROTON.supports_extension("vnd.proton.expire"));
+    assert!(!PROTON.supports_extension("reject"));
+    assert!(PROTON.is_unsupported("reject"));
+    assert_eq!(profile::vendor_capability("hasexpiration"), Some("vnd.proton.expire"));
+    assert_eq!(profile::vendor_capability("fileinto"), None);
+}
+
+#[tokio::test]
+async fn test_proton_script_passes() {
+    let text = "require [\"fileinto\", \"variables\", \"vnd.proton.expire\", \"vnd.proton.eval\"];\n\
+                if hasexpiration { unexpire; }\n\
+                if header :contains \"subject\" \"newsletter\" { expire \"day\" \"7\"; }\n\
+                set :eval \"count\" \"1 + 1\";\n\
+                fileinto \"Archive\";\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "proton" })).await;
+    for code in ["unsupported-feature", "missing-require", "invalid-syntax"] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+}
+
+#[tokio::test]
+async fn test_unsupported_features_are_errors() {
+    let text = "require [\"reject\", \"editheader\"];\n\
+                if size :over 1M { reject \"too big\"; }\n\
+                redirect \"alice@example.com\";\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "proton" })).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "unsupported-feature")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (0, "Proton Mail does not support the \"reject\" extension"),
+            (0, "Proton Mail does not support the \"editheader\" extension"),
+            (1, "Proton Mail does not support 'reject'"),
+            (2, "Proton Mail does not support 'redirect'"),
+        ]
+    );
+    assert!(with_code(&diagnostics, "unsupported-feature")
+        .iter()
+        .all(|d| d.severity == Some(DiagnosticSeverity::ERROR)));
+}
+
+#[tokio::test]
+async fn test_vendor_features_need_their_require() {
+    let text = "if hasexpiration { unexpire; }\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "proton" })).await;
+    let messages: Vec<&str> = with_code(&diagnostics, "missing-require")
+        .iter()
+        .filter(|d| d.range.start.line == 0 && d.range.start.character > 0)
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "'hasexpiration' requires the \"vnd.proton.expire\" extension",
+            "'unexpire' requires the \"vnd.proton.expire\" extension",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_without_profile_standard_features_are_valid() {
+    let text = "require \"reject\";\nreject \"no\";\nredirect \"alice@example.com\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_completions_follow_profile() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "server_dialect": "proton" })).unwrap();
+    let uri = Url::parse("file:///proton.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), String::new(), 1));
+
+    let labels: Vec<String> = server
+        .get_completions(&uri, Position::new(0, 0))
+        .await
+        .into_iter()
+        .map(|c| c.label)
+        .collect();
+    assert!(labels.contains(&"unexpire".to_string()));
+    assert!(labels.contains(&"\"vnd.proton.expire\"".to_string()));
+    assert!(!labels.contains(&"reject".to_string()));
+    assert!(!labels.contains(&"redirect".to_string()));
+    assert!(!labels.contains(&"\"editheader\"".to_string()));
+}
+
+#[test]
+fn test_profiles_only_name_known_extensions() {
+    for profile in PROFILES {
+        for capability in profile.extensions {
+            assert!(
+                SIEVE_EXTENSIONS.contains_key(capability),
+                "{}: {}",
+                profile.name,
+                capability
+            );
+        }
+        for (feature, capability) in profile.vendor_features {
+            assert!(profile.supports_extension(capability), "{}: {}", profile.name, feature);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dovecot_script_passes() {
+    let text = "require [\"vnd.dovecot.pipe\", \"vnd.dovecot.execute\", \"vnd.dovecot.debug\", \"variables\", \"copy\"];\n\
+                if execute :output \"score\" \"spamcheck\" { debug_log \"score ${score}\"; }\n\
+                pipe :copy :try \"sa-learn\" [\"--spam\"];\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "dovecot" })).await;
+    for code in ["unsupported-feature", "missing-require", "invalid-syntax"] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+    assert_eq!(DOVECOT.vendor_capability("filter"), Some("vnd.dovecot.filter"));
+}
+
+#[tokio::test]
+async fn test_other_vendors_extensions_are_errors() {
+    let text = "require \"vnd.dovecot.pipe\";\npipe \"sa-learn\";\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "proton" })).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "unsupported-feature")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (0, "Proton Mail does not support the \"vnd.dovecot.pipe\" extension"),
+            (1, "'pipe' is a Dovecot Pigeonhole extension that Proton Mail does not support"),
+        ]
+    );
+
+    // The generic dialect accepts every vendor
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "generic" })).await;
+    assert!(with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+}
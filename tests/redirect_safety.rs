@@ -0,0 +1,60 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+#[tokio::test]
+async fn test_redirect_to_vacation_address() {
+    let text = "require \"vacation\";\n\
+                vacation :addresses [\"me@example.com\", \"alias@example.com\"] \"Away\";\n\
+                if header :contains \"to\" \"alias\" { redirect \"Alias@Example.com\"; }\n\
+                redirect \"other@example.org\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redirect-loop");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(2, 44));
+    assert!(found[0].message.contains("'Alias@Example.com'"), "{}", found[0].message);
+    let related = found[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start, Position::new(1, 39));
+}
+
+#[tokio::test]
+async fn test_redirect_to_configured_own_address() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "own_addresses": ["me@example.com"] })).unwrap();
+    let uri = Url::parse("file:///own.sieve").unwrap();
+    let text = "redirect \"me@example.com\";\nredirect \"${target}\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let found = with_code(&diagnostics, "redirect-loop");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start.line, 0);
+    assert!(found[0].related_information.is_none());
+}
+
+#[tokio::test]
+async fn test_redirect_without_copy_before_discard() {
+    let text = "require \"copy\";\n\
+                redirect \"a@example.com\";\n\
+                redirect :copy \"b@example.com\";\n\
+                discard;\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "redirect-without-copy");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(1, 0));
+    assert_eq!(found[0].range.end, Position::new(1, 8));
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], "redirect :copy");
+    let related = found[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start.line, 3);
+
+    // Without a later discard the redirect is fine
+    let diagnostics = diagnostics_for("discard;\nredirect \"a@example.com\";\n").await;
+    assert!(with_code(&diagnostics, "redirect-without-copy").is_empty(), "{:?}", diagnostics);
+}
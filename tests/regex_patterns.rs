@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::posix::check;
+use tower_lsp::lsp_types::*;
+
+fn error_at(pattern: &str) -> (String, usize, usize) {
+    let error = check(pattern).unwrap_err();
+    (error.message, error.start, error.end)
+}
+
+#[test]
+fn test_valid_patterns() {
+    for pattern in [
+        "^(re|fwd): .*$",
+        "[[:alpha:]]+[0-9]{2,4}",
+        "[]a-]",
+        "[^]]",
+        "a{3}b{1,}",
+        "\\(literal\\)",
+        "x{",
+    ] {
+        assert_eq!(check(pattern), Ok(()), "{}", pattern);
+    }
+}
+
+#[test]
+fn test_invalid_patterns() {
+    assert_eq!(error_at("(a|b"), ("Unmatched '(' in regular expression".into(), 0, 1));
+    assert_eq!(error_at("a)b"), ("Unmatched ')' in regular expression".into(), 1, 2));
+    assert_eq!(
+        error_at("x[abc"),
+        ("Unterminated bracket expression: missing ']'".into(), 1, 2)
+    );
+    assert_eq!(
+        error_at("[[:alfa:]]"),
+        ("Unknown character class '[:alfa:]'".into(), 1, 9)
+    );
+    assert_eq!(
+        error_at("[z-a]"),
+        ("Invalid range 'z-a': the end sorts before the start".into(), 1, 4)
+    );
+    assert_eq!(error_at("*a"), ("Nothing to repeat before '*'".into(), 0, 1));
+    assert_eq!(error_at("(+a)"), ("Nothing to repeat before '+'".into(), 1, 2));
+    assert_eq!(error_at("a{3,1}"), ("Invalid repetition bound '{3,1}'".into(), 1, 6));
+    assert_eq!(
+        error_at("a{300}"),
+        ("Repetition bound exceeds the limit of 255".into(), 1, 6)
+    );
+    assert_eq!(
+        error_at("abc\\"),
+        ("Pattern ends with an incomplete escape '\\'".into(), 3, 4)
+    );
+}
+
+#[test]
+fn test_value_range_skips_escapes() {
+    let text = "if header :regex \"subject\" \"a\\\\.(b\" { keep; }\n";
+    let script = parse(text);
+    let key = script.all_tests()[0].arguments.last().unwrap().strings()[0].clone();
+    assert_eq!(key.value, "a\\.(b");
+    // The '(' is the fourth value character but the fifth source character of the literal
+    let range = key.value_range(text, 3, 4);
+    assert_eq!(range, Range::new(Position::new(0, 32), Position::new(0, 33)));
+}
+
+#[tokio::test]
+async fn test_regex_diagnostics_point_into_the_string() {
+    let text = "require \"regex\";\n\
+                if header :regex \"subject\" [\"ok.*\", \"(urgent\"] { keep; }\n\
+                if body :text :regex \"[[:digit:]]+\" { keep; }\n\
+                if header :contains \"subject\" \"(not a regex\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let invalid = with_code(&diagnostics, "invalid-regex");
+    assert_eq!(invalid.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        invalid[0].range,
+        Range::new(Position::new(1, 37), Position::new(1, 38))
+    );
+}
+
+#[tokio::test]
+async fn test_multiline_pattern_positions() {
+    let text = "require [\"regex\", \"body\"];\n\
+                if body :regex text:\n..start\nfoo[bar\n.\n{ keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let invalid = with_code(&diagnostics, "invalid-regex");
+    assert_eq!(invalid.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        invalid[0].range,
+        Range::new(Position::new(3, 3), Position::new(3, 4))
+    );
+}
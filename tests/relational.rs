@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::LspService;
+
+#[tokio::test]
+async fn test_valid_relations() {
+    let text = "require [\"relational\", \"comparator-i;ascii-numeric\"];\nif header :value \"ge\" :comparator \"i;ascii-numeric\" \"x-spam-score\" \"5\" { discard; }\nif address :count \"EQ\" \"to\" \"1\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-relation").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_invalid_relation() {
+    let text = "require \"relational\";\nif header :value \">=\" \"x-spam-score\" \"5\" { discard; }\nif header :count :is \"to\" \"1\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-relation");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(1, 17));
+    assert!(found[0].message.contains("'>='"));
+    assert_eq!(found[1].range.start, Position::new(2, 10));
+}
+
+#[tokio::test]
+async fn test_relational_must_be_required() {
+    let text = "if header :count \"gt\" \"to\" \"3\" { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "missing-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start, Position::new(0, 10));
+    assert!(found[0].message.contains("relational"));
+}
+
+#[tokio::test]
+async fn test_relation_completions() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if header :value \"\n";
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), text.to_string(), 1),
+    );
+
+    let labels: Vec<String> = server
+        .get_completions(&uri, Position::new(0, 18))
+        .await
+        .into_iter()
+        .map(|c| c.label)
+        .collect();
+    assert_eq!(labels, ["eq", "ge", "gt", "le", "lt", "ne"]);
+}
@@ -0,0 +1,99 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::{offset_at, parse};
+use sieve_language_server::requires::*;
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// Apply non-overlapping edits back to front
+fn apply(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|e| (e.range.start.line, e.range.start.character));
+    let mut result = text.to_string();
+    for edit in edits.iter().rev() {
+        let start = offset_at(&result, edit.range.start);
+        let end = offset_at(&result, edit.range.end);
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+fn sync(text: &str) -> String {
+    apply(text, &sync_requires(text, &parse(text), &BTreeMap::new()))
+}
+
+#[test]
+fn test_used_capabilities() {
+    let text = "if address :user :comparator \"i;ascii-numeric\" \"to\" \"1\" {\n  \
+                fileinto :copy \"${1}\";\n  addflag \"\\\\Seen\";\n}\n";
+    let used: Vec<String> = used_capabilities(&parse(text), &BTreeMap::new())
+        .into_iter()
+        .collect();
+    assert_eq!(
+        used,
+        [
+            "comparator-i;ascii-numeric",
+            "copy",
+            "fileinto",
+            "imap4flags",
+            "subaddress",
+            "variables"
+        ]
+    );
+}
+
+#[test]
+fn test_adds_missing_to_last_require() {
+    let text = "require \"fileinto\";\nif body :contains \"x\" { fileinto \"Spam\"; }\n";
+    assert_eq!(
+        sync(text),
+        "require [\"fileinto\", \"body\"];\nif body :contains \"x\" { fileinto \"Spam\"; }\n"
+    );
+}
+
+#[test]
+fn test_inserts_require_before_first_command() {
+    let text = "# Spam rules\nif header :contains \"subject\" \"x\" { fileinto \"Spam\"; }\n";
+    assert_eq!(
+        sync(text),
+        "# Spam rules\nrequire \"fileinto\";\nif header :contains \"subject\" \"x\" { fileinto \"Spam\"; }\n"
+    );
+}
+
+#[test]
+fn test_removes_unused_requires() {
+    let text = "require \"vacation\";\nrequire [\"fileinto\", \"copy\", \"x-custom\"];\nfileinto \"Archive\";\n";
+    // Capabilities whose usage cannot be detected are kept
+    assert_eq!(sync(text), "require [\"fileinto\", \"x-custom\"];\nfileinto \"Archive\";\n");
+}
+
+#[test]
+fn test_in_sync_and_broken_scripts_are_untouched() {
+    let text = "require [\"imapflags\", \"fileinto\"];\nsetflag \"\\\\Seen\";\nfileinto \"A\";\n";
+    assert!(sync_requires(text, &parse(text), &BTreeMap::new()).is_empty());
+
+    let broken = "require \"vacation\";\nif header :is \"x\" {\n";
+    assert!(sync_requires(broken, &parse(broken), &BTreeMap::new()).is_empty());
+}
+
+#[tokio::test]
+async fn test_will_save_wait_until_is_opt_in() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), "require \"vacation\";\nkeep;\n".to_string(), 1),
+    );
+    let params = || WillSaveTextDocumentParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        reason: TextDocumentSaveReason::MANUAL,
+    };
+
+    assert_eq!(server.will_save_wait_until(params()).await.unwrap(), None);
+
+    *server.settings.write().await =
+        serde_json::from_value(serde_json::json!({ "requires": { "autoManage": true } })).unwrap();
+    let edits = server.will_save_wait_until(params()).await.unwrap().unwrap();
+    assert_eq!(apply("require \"vacation\";\nkeep;\n", &edits), "keep;\n");
+}
@@ -0,0 +1,106 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::interpreter::*;
+use sieve_language_server::lsp::COMMAND_TEST_RULE;
+use sieve_language_server::message::Message;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require \"fileinto\";\n\
+                      if header :contains \"subject\" \"digest\" {\n  fileinto \"Lists\";\n}\n\
+                      if exists \"x-spam\" {\n  if true { stop; }\n}\n\
+                      if size :over 1K { discard; }\n";
+
+const MBOX: &str = "From a@example.com Mon Jan  1 00:00:00 2024\nSubject: Weekly digest\n\nx\n\
+                    From b@example.com Mon Jan  1 00:00:00 2024\nSubject: Hi\nX-Spam: yes\n\ny\n";
+
+#[test]
+fn test_rule_against_messages() {
+    let script = parse(SCRIPT);
+    let messages = sieve_language_server::message::parse_mbox(MBOX, "inbox");
+    let results = test_rule(&script, &script.commands[1], &messages);
+    let matched: Vec<Option<bool>> = results.iter().map(|r| r.matched).collect();
+    assert_eq!(matched, vec![Some(true), Some(false)]);
+    assert_eq!(results[0].origin, "inbox#0");
+    assert_eq!(rule_test_summary(&results), "Rule matches 1 of 2 sample messages");
+
+    // The second message stops before the last rule
+    let results = test_rule(&script, &script.commands[3], &messages);
+    let matched: Vec<Option<bool>> = results.iter().map(|r| r.matched).collect();
+    assert_eq!(matched, vec![Some(false), None]);
+    assert_eq!(
+        rule_test_summary(&results),
+        "Rule matches 0 of 2 sample messages, 1 end before reaching it"
+    );
+
+    let single = test_rule(&script, &script.commands[1], &[Message::parse("Subject: digest\n\n")]);
+    assert_eq!(rule_test_summary(&single), "Rule matches the sample message");
+}
+
+#[tokio::test]
+async fn test_lenses_above_top_level_rules() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///lens.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+    let lenses = server
+        .code_lens(CodeLensParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let lines: Vec<u32> = lenses.iter().map(|lens| lens.range.start.line).collect();
+    assert_eq!(lines, vec![1, 4, 7]);
+    let command = lenses[1].command.as_ref().unwrap();
+    assert_eq!(command.title, "▶ Test rule");
+    assert_eq!(command.command, COMMAND_TEST_RULE);
+    assert_eq!(command.arguments, Some(vec![json!(uri), json!(4)]));
+}
+
+#[tokio::test]
+async fn test_rule_command_uses_configured_messages() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let dir = std::env::temp_dir().join(format!("sieve-rule-lens-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let uri = Url::from_file_path(dir.join("filter.sieve")).unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+    let execute = |line: u32| {
+        server.execute_command(ExecuteCommandParams {
+            command: COMMAND_TEST_RULE.to_string(),
+            arguments: vec![json!(uri), json!(line)],
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    // Neither configured messages nor a sidecar file
+    assert!(execute(1).await.is_err());
+
+    std::fs::write(dir.join("filter.eml"), "Subject: The digest\n\nbody\n").unwrap();
+    let results = execute(1).await.unwrap().unwrap();
+    assert_eq!(results[0]["matched"], true);
+    assert!(results[0]["origin"].as_str().unwrap().ends_with("filter.eml"));
+
+    std::fs::write(dir.join("samples.mbox"), MBOX).unwrap();
+    let path = dir.join("samples.mbox").display().to_string();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "sample_messages": path })).unwrap();
+    let results = execute(4).await.unwrap().unwrap();
+    assert_eq!(results, json!([
+        { "origin": format!("{}#0", path), "matched": false },
+        { "origin": format!("{}#1", path), "matched": true },
+    ]));
+
+    // Only lines starting a top-level rule can be tested
+    assert!(execute(5).await.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::rules::{self, RULES, RuleContext, Sink};
+use std::collections::BTreeSet;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_rule_ids_are_unique() {
+    let ids: BTreeSet<&str> = RULES.iter().map(|rule| rule.id()).collect();
+    assert_eq!(ids.len(), RULES.len());
+    assert_eq!(rules::find("redundant-negation").map(|rule| rule.id()), Some("redundant-negation"));
+    assert!(rules::find("no-such-rule").is_none());
+}
+
+#[test]
+fn test_rules_run_in_isolation() {
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "if not not true { stop; keep; }\n";
+    let script = parse(text);
+    let context = RuleContext::new(&uri, text, &script);
+
+    let mut sink = Sink::default();
+    rules::find("unreachable-code").unwrap().check(&context, &mut sink);
+    let diagnostics = sink.into_diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0].code,
+        Some(NumberOrString::String("unreachable-code".to_string()))
+    );
+
+    let all = rules::run(&context, |_| true);
+    assert!(all.len() > diagnostics.len(), "{:?}", all);
+    let none = rules::run(&context, |_| false);
+    assert!(none.is_empty());
+}
+
+#[tokio::test]
+async fn test_rules_can_be_disabled_in_settings() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "stop;\nkeep;\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let unreachable = |diagnostics: &[Diagnostic]| {
+        diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unreachable-code".to_string())))
+            .count()
+    };
+    assert_eq!(unreachable(&server.validate_document(&uri).await.unwrap()), 1);
+
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "rules": { "unreachable-code": false } })).unwrap();
+    assert_eq!(unreachable(&server.validate_document(&uri).await.unwrap()), 0);
+}
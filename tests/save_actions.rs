@@ -0,0 +1,122 @@
+use serde_json::json;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::deep;
+use sieve_language_server::message::parse_mbox;
+use sieve_language_server::parser::parse;
+use sieve_language_server::refactor::{apply_edits, changed_lines};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const MBOX: &str = "From a@example.com Mon Jan  1 00:00:00 2024\nSubject: Weekly digest\n\nx\n\
+                    From b@example.com Mon Jan  1 00:00:00 2024\nSubject: Hi\n\ny\n";
+
+#[test]
+fn test_changed_lines() {
+    let old = "require \"fileinto\";\nif true {\nkeep;\n}\n";
+    let new = "require \"fileinto\";\nif true {\n    keep;\n}\n";
+    let edit = changed_lines(old, new).unwrap();
+    assert_eq!(edit.range.start, Position::new(2, 0));
+    assert_eq!(edit.range.end, Position::new(3, 0));
+    assert_eq!(edit.new_text, "    keep;\n");
+    assert_eq!(apply_edits(old, &[edit]), new);
+
+    // A last line without a newline is replaced up to its end
+    let edit = changed_lines("keep;", "keep;\n").unwrap();
+    assert_eq!(edit.range.end, Position::new(0, 5));
+    assert_eq!(apply_edits("keep;", &[edit]), "keep;\n");
+    assert!(changed_lines("keep;\n", "keep;\n").is_none());
+}
+
+#[tokio::test]
+async fn test_will_save_combines_requires_and_formatting() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    let text = "require \"vacation\";\nrequire \"fileinto\";\nif true {\nfileinto \"A\";\n}\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let params = || WillSaveTextDocumentParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        reason: TextDocumentSaveReason::MANUAL,
+    };
+
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "format": { "onSave": true } })).unwrap();
+    let edits = server.will_save_wait_until(params()).await.unwrap().unwrap();
+    assert_eq!(
+        apply_edits(text, &edits),
+        "require \"vacation\";\nrequire \"fileinto\";\nif true {\n    fileinto \"A\";\n}\n"
+    );
+
+    *server.settings.write().await = serde_json::from_value(json!({
+        "requires": { "organizeOnSave": true },
+        "format": { "onSave": true, "tabSize": 2 },
+    }))
+    .unwrap();
+    let edits = server.will_save_wait_until(params()).await.unwrap().unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(
+        apply_edits(text, &edits),
+        "require \"fileinto\";\nif true {\n  fileinto \"A\";\n}\n"
+    );
+}
+
+#[test]
+fn test_deep_analysis_runs_the_samples() {
+    let script = parse(
+        "require [\"fileinto\", \"variables\"];\n\
+         if header :matches \"subject\" \"Weekly *\" { fileinto \"${2}\"; }\n\
+         if header :contains \"subject\" \"invoice\" { keep; }\n\
+         if currentdate :is \"year\" \"2024\" { keep; }\n",
+    );
+    let messages = parse_mbox(MBOX, "samples");
+    let diagnostics = deep::analyze(&script, &messages);
+    let found: Vec<(&str, u32)> = diagnostics
+        .iter()
+        .map(|d| match &d.code {
+            Some(NumberOrString::String(code)) => (code.as_str(), d.range.start.line),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(found, [("unmatched-rule", 2), ("empty-destination", 1)]);
+    assert_eq!(diagnostics[1].message, "Mailbox name expands to nothing for 1 sample message(s)");
+}
+
+#[tokio::test]
+async fn test_deep_analysis_on_save() {
+    let dir = std::env::temp_dir().join(format!("sieve-deep-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("samples.mbox"), MBOX).unwrap();
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::from_file_path(dir.join("filter.sieve")).unwrap();
+    let text = "if header :contains \"subject\" \"invoice\" { keep; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 3));
+    let save = || DidSaveTextDocumentParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        text: None,
+    };
+
+    // Nothing runs without configured sample messages
+    server.did_save(save()).await;
+    assert!(server.deep_diagnostics.get(&uri).is_none());
+
+    let path = dir.join("samples.mbox").display().to_string();
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "sample_messages": path })).unwrap();
+    server.did_save(save()).await;
+    let (version, diagnostics) = server.deep_diagnostics.get(&uri).unwrap().clone();
+    assert_eq!(version, 3);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "No sample message matches this rule");
+
+    *server.settings.write().await =
+        serde_json::from_value(json!({ "sample_messages": path, "deep_analysis": false }))
+            .unwrap();
+    server.did_save(save()).await;
+    assert!(server.deep_diagnostics.get(&uri).is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
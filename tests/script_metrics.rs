@@ -0,0 +1,70 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::metrics::{MetricsParams, metrics};
+use sieve_language_server::parser::parse;
+use std::collections::BTreeMap;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+const SCRIPT: &str = "require [\"fileinto\", \"variables\", \"imap4flags\"];\n\
+                      # Lists\n\
+                      if anyof (header :contains \"list-id\" \"rust\",\n\
+                      \x20         header :contains \"list-id\" \"go\") {\n\
+                      \x20   if not exists \"x-important\" {\n\
+                      \x20       fileinto :flags \"\\\\Seen\" \"Lists\";\n\
+                      \x20   }\n\
+                      } elsif address :is \"from\" \"boss@example.com\" {\n\
+                      \x20   set \"who\" \"boss\";\n\
+                      \x20   redirect \"me@example.org\";\n\
+                      }\n\
+                      if size :over 10M { fileinto \"Lists\"; }\n\
+                      keep;\n";
+
+#[test]
+fn test_script_metrics() {
+    let metrics = metrics(SCRIPT, &parse(SCRIPT), &BTreeMap::new());
+    assert_eq!(metrics.lines, 13);
+    assert_eq!(metrics.comments, 1);
+    assert_eq!(metrics.rules, 2);
+    assert_eq!(metrics.tests, 5);
+    // fileinto twice, set, redirect and keep
+    assert_eq!(metrics.actions, 5);
+    assert_eq!(metrics.max_nesting_depth, 2);
+    // 1 + if, nested if, elsif, second if + one extra anyof operand
+    assert_eq!(metrics.complexity, 6);
+    assert_eq!(metrics.required_extensions, ["fileinto", "imap4flags", "variables"]);
+    assert_eq!(metrics.used_extensions, ["fileinto", "imap4flags", "variables"]);
+    assert_eq!(metrics.variables, ["who"]);
+    // The flag given to :flags is not a target
+    assert_eq!(metrics.fileinto_targets, BTreeMap::from([("Lists".to_string(), 2)]));
+    assert_eq!(metrics.redirect_targets, BTreeMap::from([("me@example.org".to_string(), 1)]));
+}
+
+#[test]
+fn test_empty_script() {
+    let metrics = metrics("", &parse(""), &BTreeMap::new());
+    assert_eq!(metrics.complexity, 1);
+    assert_eq!(metrics.max_nesting_depth, 0);
+    assert!(metrics.fileinto_targets.is_empty());
+}
+
+#[tokio::test]
+async fn test_metrics_request() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///metrics.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+
+    let params = |uri: &Url| MetricsParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+    };
+    let metrics = server.metrics(params(&uri)).await.unwrap();
+    assert_eq!(metrics.rules, 2);
+    let json = serde_json::to_value(&metrics).unwrap();
+    assert_eq!(json["maxNestingDepth"], 2);
+    assert_eq!(json["fileintoTargets"]["Lists"], 2);
+
+    let closed = Url::parse("file:///closed.sieve").unwrap();
+    assert!(server.metrics(params(&closed)).await.is_err());
+}
@@ -0,0 +1,45 @@
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[tokio::test]
+async fn test_statistics_report_documents_and_validations() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let small = Url::parse("file:///small.sieve").unwrap();
+    let large = Url::parse("file:///large.sieve").unwrap();
+    let rules = "if header :contains \"subject\" \"x\" { discard; }\n".repeat(2_000);
+    for (uri, text) in [(&small, "keep;\n".to_string()), (&large, rules)] {
+        server
+            .document_map
+            .insert(uri.clone(), SieveDocument::new(uri.clone(), text, 1));
+    }
+
+    let status = server.statistics().await.unwrap();
+    assert_eq!(status.open_documents, 2);
+    assert_eq!(status.indexed_scripts, 0);
+    assert!(status.validations.is_empty());
+    assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+
+    server.validate_guarded(&small).await;
+    server.validate_guarded(&large).await;
+    let status = server.statistics().await.unwrap();
+    let validated: Vec<&Url> = status.validations.iter().map(|v| &v.uri).collect();
+    assert_eq!(validated, [&large, &small]);
+
+    server
+        .did_close(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: large.clone() },
+        })
+        .await;
+    let status = server.statistics().await.unwrap();
+    assert_eq!(status.open_documents, 1);
+    assert_eq!(status.validations.len(), 1);
+    if cfg!(target_os = "linux") {
+        assert!(status.resident_memory.is_some_and(|bytes| bytes > 0));
+    }
+
+    let json = serde_json::to_value(&status).unwrap();
+    assert_eq!(json["openDocuments"], 1);
+    assert!(json["validations"][0]["durationMs"].is_number());
+}
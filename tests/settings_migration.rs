@@ -0,0 +1,229 @@
+mod common;
+
+use common::{diagnostics_with_settings, initialized_server, sent, with_code};
+use serde_json::{Value, json};
+use sieve_language_server::datastructures::*;
+use sieve_language_server::lsp::COMMAND_MIGRATE_SETTINGS;
+use sieve_language_server::migration::{migrate, rewrite};
+use std::path::{Path, PathBuf};
+use tower_lsp::LanguageServer;
+use tower_lsp::lsp_types::*;
+
+fn temp_workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sieve-migrate-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn migrate_command(server: &SieveLanguageServer) -> tower_lsp::jsonrpc::Result<Value> {
+    server
+        .execute_command(ExecuteCommandParams {
+            command: COMMAND_MIGRATE_SETTINGS.to_string(),
+            arguments: vec![],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .map(Option::unwrap)
+}
+
+#[test]
+fn test_proton_extensions_stay_a_setting() {
+    for value in [true, false] {
+        let migration = migrate(&json!({ "proton_extensions": value }));
+        assert_eq!(migration.settings, json!({ "proton_extensions": value }));
+        assert!(migration.changes.is_empty());
+
+        let migration = migrate(&json!({ "protonExtensions": value }));
+        assert_eq!(migration.settings, json!({ "proton_extensions": value }));
+        assert_eq!(migration.changes, ["`protonExtensions` is now `proton_extensions`"]);
+    }
+}
+
+#[tokio::test]
+async fn test_an_explicit_default_validates_like_an_omitted_key() {
+    let script = "require [\"body\", \"vnd.dovecot.debug\"];\n\
+                  if body :contains \"invoice\" {\n    debug_log \"invoice\";\n}\n";
+    let omitted = diagnostics_with_settings(script, json!({})).await;
+    assert!(with_code(&omitted, "unsupported-feature").is_empty(), "{:?}", omitted);
+    for settings in [json!({ "proton_extensions": true }), json!({ "protonExtensions": true })] {
+        assert_eq!(diagnostics_with_settings(script, settings).await, omitted);
+    }
+}
+
+#[test]
+fn test_camel_case_keys_are_renamed() {
+    let settings = json!({ "strictMode": true, "maxErrors": 5, "serverDialect": "sieve" });
+    let migration = migrate(&settings);
+    assert_eq!(
+        migration.settings,
+        json!({ "strict_mode": true, "max_errors": 5, "server_dialect": "sieve" })
+    );
+    assert_eq!(
+        migration.changes,
+        [
+            "`maxErrors` is now `max_errors`",
+            "`serverDialect` is now `server_dialect`",
+            "`strictMode` is now `strict_mode`",
+        ]
+    );
+}
+
+#[test]
+fn test_flat_keys_move_into_their_section() {
+    let moved = [
+        ("auto_manage_requires", json!(true), "requires", "autoManage"),
+        ("organizeRequiresOnSave", json!(true), "requires", "organizeOnSave"),
+        ("mailbox_separator", json!("."), "mailbox", "separator"),
+        ("namespace_prefix", json!("INBOX."), "mailbox", "namespacePrefix"),
+        ("mailbox_folders", json!(["Archive"]), "mailbox", "folders"),
+    ];
+    for (legacy, value, section, key) in moved {
+        let migration = migrate(&json!({ legacy: value.clone() }));
+        assert_eq!(migration.settings, json!({ section: { key: value } }), "{}", legacy);
+        assert_eq!(migration.changes, [format!("`{}` is now `{}.{}`", legacy, section, key)]);
+    }
+
+    let migration = migrate(&json!({
+        "mailbox_separator": ".",
+        "mailbox": { "separator": "/", "folders": ["Spam"] }
+    }));
+    assert_eq!(
+        migration.settings,
+        json!({ "mailbox": { "separator": "/", "folders": ["Spam"] } })
+    );
+    assert_eq!(
+        migration.changes,
+        ["`mailbox_separator` is dropped, `mailbox.separator` is already set"]
+    );
+}
+
+#[test]
+fn test_current_and_unknown_keys_are_left_alone() {
+    let settings = json!({
+        "strict_mode": true,
+        "strictMode": false,
+        "requires": { "autoManage": true },
+        "futureSetting": 1
+    });
+    let migration = migrate(&settings);
+    assert_eq!(
+        migration.settings,
+        json!({ "strict_mode": true, "requires": { "autoManage": true }, "futureSetting": 1 })
+    );
+    assert_eq!(migration.changes, ["`strictMode` is dropped, `strict_mode` is already set"]);
+
+    let current = json!({ "server_dialect": "dovecot", "format": { "tabSize": 2 } });
+    assert_eq!(migrate(&current).settings, current);
+    assert!(migrate(&current).changes.is_empty());
+}
+
+#[test]
+fn test_toml_files_keep_their_comments() {
+    let content = "# Filters of the family server\n\
+                   strictMode = true # until the family upgrades\n\
+                   mailbox_separator = \".\"\n\
+                   \n\
+                   [format]\n\
+                   # Two spaces everywhere\n\
+                   tabSize = 2\n";
+    let (rewritten, changes) = rewrite(Path::new("sieve-lsp.toml"), content).unwrap().unwrap();
+    assert_eq!(
+        rewritten,
+        "# Filters of the family server\n\
+         strict_mode = true # until the family upgrades\n\
+         \n\
+         [format]\n\
+         # Two spaces everywhere\n\
+         tabSize = 2\n\
+         \n\
+         [mailbox]\n\
+         separator = \".\"\n"
+    );
+    assert_eq!(
+        changes,
+        [
+            "`mailbox_separator` is now `mailbox.separator`",
+            "`strictMode` is now `strict_mode`",
+        ]
+    );
+    assert_eq!(rewrite(Path::new("sieve-lsp.toml"), &rewritten).unwrap(), None);
+    assert!(rewrite(Path::new("sieve-lsp.toml"), "strictMode = ").is_err());
+}
+
+#[test]
+fn test_json_files_are_rewritten() {
+    let content = "{ \"strictMode\": true, \"auto_manage_requires\": false }";
+    let (rewritten, changes) = rewrite(Path::new(".sieverc"), content).unwrap().unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&rewritten).unwrap(),
+        json!({ "strict_mode": true, "requires": { "autoManage": false } })
+    );
+    assert!(rewritten.ends_with("}\n"));
+    assert_eq!(changes.len(), 2);
+}
+
+#[test]
+fn test_loaded_settings_are_migrated() {
+    let settings = SieveSettings::layered(
+        &json!({ "strictMode": true, "protonExtensions": false }),
+        Some(&json!({ "mailbox_separator": "." })),
+    )
+    .unwrap();
+    let settings = serde_json::to_value(settings).unwrap();
+    assert_eq!(settings["strict_mode"], true);
+    assert_eq!(settings["proton_extensions"], false);
+    assert_eq!(settings["server_dialect"], "generic");
+    assert_eq!(settings["mailbox"]["separator"], ".");
+}
+
+#[tokio::test]
+async fn test_migrate_command_rewrites_the_workspace_configuration() {
+    let root = temp_workspace("command");
+    let path = root.join("sieve-lsp.toml");
+    std::fs::write(&path, "strictMode = true\n").unwrap();
+    let root_uri = Url::from_file_path(&root).unwrap();
+    let (service, messages) = initialized_server(json!({
+        "capabilities": {},
+        "rootUri": root_uri
+    }))
+    .await;
+    let server = service.inner();
+    assert_eq!(serde_json::to_value(&*server.settings.read().await).unwrap()["strict_mode"], true);
+
+    server.initialized(InitializedParams {}).await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let notices = sent(&messages, "window/showMessage");
+    assert_eq!(
+        notices.last().unwrap()["message"],
+        "The workspace configuration uses settings of an earlier version; \
+         the \"Migrate settings\" command updates them"
+    );
+
+    let result = migrate_command(server).await.unwrap();
+    assert_eq!(result["path"], json!(path));
+    assert_eq!(result["changes"], json!(["`strictMode` is now `strict_mode`"]));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "strict_mode = true\n");
+    assert_eq!(*server.workspace_config.read().await, Some(json!({ "strict_mode": true })));
+
+    let result = migrate_command(server).await.unwrap();
+    assert_eq!(result, json!({ "path": null, "changes": [] }));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let summaries: Vec<Value> = sent(&messages, "window/showMessage")
+        .into_iter()
+        .map(|notice| notice["message"].clone())
+        .collect();
+    assert_eq!(
+        summaries[summaries.len() - 2..],
+        [
+            json!(format!(
+                "Migrated 1 setting(s) in {}: `strictMode` is now `strict_mode`",
+                path.display()
+            )),
+            json!("The workspace configuration is up to date"),
+        ]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(migrate_command(server).await.is_err());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::cli::check_files;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+
+const TEXT: &str = "fileinto \"Archive\";\nstop;\nkeep;\n";
+
+fn severity_of(diagnostics: &[Diagnostic], code: &str) -> Vec<Option<DiagnosticSeverity>> {
+    diagnostics
+        .iter()
+        .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+        .map(|d| d.severity)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_codes_can_be_raised_lowered_and_disabled() {
+    let defaults = diagnostics_with_settings(TEXT, json!({})).await;
+    assert_eq!(
+        severity_of(&defaults, "missing-require"),
+        vec![Some(DiagnosticSeverity::WARNING)]
+    );
+    assert!(!severity_of(&defaults, "unreachable-code").is_empty());
+
+    let diagnostics = diagnostics_with_settings(TEXT, json!({
+        "severity": { "missing-require": "error", "unreachable-code": "off" }
+    }))
+    .await;
+    assert_eq!(
+        severity_of(&diagnostics, "missing-require"),
+        vec![Some(DiagnosticSeverity::ERROR)]
+    );
+    assert!(severity_of(&diagnostics, "unreachable-code").is_empty(), "{:?}", diagnostics);
+
+    let diagnostics =
+        diagnostics_with_settings(TEXT, json!({ "severity": { "missing-require": "information" } }))
+        .await;
+    assert_eq!(
+        severity_of(&diagnostics, "missing-require"),
+        vec![Some(DiagnosticSeverity::INFORMATION)]
+    );
+}
+
+#[test]
+fn test_unknown_severities_are_rejected() {
+    let settings = json!({ "severity": { "missing-require": "fatal" } });
+    let settings = serde_json::from_value::<SieveSettings>(settings);
+    assert!(settings.is_err());
+}
+
+#[tokio::test]
+async fn test_check_exit_status_follows_overrides() {
+    let settings: SieveSettings =
+        serde_json::from_value(json!({ "severity": { "missing-require": "error" } })).unwrap();
+    let files = vec![("filter.sieve".to_string(), TEXT.to_string())];
+    let results = check_files(&files, &settings).await;
+    assert!(
+        results[0]
+            .1
+            .iter()
+            .any(|d| d.severity == Some(DiagnosticSeverity::ERROR)),
+        "{:?}",
+        results
+    );
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::*;
+use sieve_language_server::implication::implies;
+use sieve_language_server::parser::{Test, parse};
+use tower_lsp::lsp_types::*;
+
+/// The test of the first `if` in `text`
+fn test_of(text: &str) -> Test {
+    let script = parse(&format!("if {} {{ keep; }}", text));
+    script.commands[0].tests[0].clone()
+}
+
+#[test]
+fn test_implication() {
+    let cases = [
+        (r#"header :is "from" "a@corp.com""#, r#"header :contains "From" "CORP.com""#, true),
+        (r#"header :contains "from" "x.corp.com""#, r#"header :contains "from" "corp.com""#, true),
+        (r#"header :contains "from" "corp.com""#, r#"header :contains "from" "x.corp.com""#, false),
+        (r#"header :is "from" "a@corp.com""#, r#"header :matches "from" "*@corp.com""#, true),
+        (r#"header :contains "from" "corp""#, r#"header :matches "from" "*corp*""#, true),
+        (r#"header :is "to" "a@corp.com""#, r#"header :contains "from" "corp.com""#, false),
+        (r#"header :is ["from"] ["a", "b"]"#, r#"header :is ["from", "to"] ["a", "b", "c"]"#, true),
+        (r#"header :is "from" ["a", "d"]"#, r#"header :is "from" ["a", "b"]"#, false),
+        (r#"header :comparator "i;octet" :is "from" "A""#, r#"header :is "from" "a""#, false),
+        (r#"address :domain "from" "corp.com""#, r#"address :all "from" "corp.com""#, false),
+        (r#"address :is "from" "a@corp.com""#, r#"address :all :contains "from" "corp""#, true),
+        (r#"header :is "from" "${me}""#, r#"header :is "from" "${me}""#, false),
+        (r#"size :over 2M"#, r#"size :over 1M"#, true),
+        (r#"size :under 2M"#, r#"size :under 1M"#, false),
+        (r#"exists ["from", "to"]"#, r#"exists "to""#, true),
+        (r#"allof (exists "x", size :over 1K)"#, r#"exists "x""#, true),
+        (r#"exists "x""#, r#"anyof (size :over 1K, exists "x")"#, true),
+        (r#"exists "x""#, r#"allof (size :over 1K, exists "x")"#, false),
+        (r#"exists "x""#, "true", true),
+        (r#"not exists "x""#, r#"not exists "x""#, false),
+    ];
+    for (specific, general, expected) in cases {
+        assert_eq!(
+            implies(&test_of(specific), &test_of(general)),
+            expected,
+            "{} => {}",
+            specific,
+            general
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_rule_after_stopping_superset_is_shadowed() {
+    let text = "require \"fileinto\";\n\
+                if header :contains \"from\" \"@corp.com\" { fileinto \"Corp\"; stop; }\n\
+                if header :is \"from\" \"boss@corp.com\" { fileinto \"Boss\"; }\n\
+                if header :contains \"from\" \"@other.com\" { fileinto \"Other\"; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let shadowed = with_code(&diagnostics, "shadowed-rule");
+    assert_eq!(shadowed.len(), 1, "{:?}", diagnostics);
+    assert_eq!(shadowed[0].range.start, Position::new(2, 0));
+    assert_eq!(shadowed[0].severity, Some(DiagnosticSeverity::WARNING));
+    assert!(shadowed[0].message.contains("line 2"), "{}", shadowed[0].message);
+    let related = shadowed[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start, Position::new(1, 0));
+}
+
+#[tokio::test]
+async fn test_rule_after_non_stopping_superset_is_reachable() {
+    let text = "require \"fileinto\";\n\
+                if header :contains \"from\" \"@corp.com\" { fileinto \"Corp\"; }\n\
+                if header :is \"from\" \"boss@corp.com\" { fileinto \"Boss\"; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "shadowed-rule").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_elsif_implied_by_earlier_branch() {
+    let text = "require \"fileinto\";\n\
+                if size :over 1M { fileinto \"Big\"; }\n\
+                elsif size :over 5M { fileinto \"Huge\"; }\n\
+                elsif size :under 1K { fileinto \"Small\"; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let shadowed = with_code(&diagnostics, "shadowed-rule");
+    assert_eq!(shadowed.len(), 1, "{:?}", diagnostics);
+    assert_eq!(shadowed[0].range.start.line, 2);
+    assert!(shadowed[0].message.starts_with("Branch is never taken"), "{}", shadowed[0].message);
+}
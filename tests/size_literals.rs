@@ -0,0 +1,81 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::number_value;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[test]
+fn test_number_values() {
+    assert_eq!(number_value("100"), Some(100));
+    assert_eq!(number_value("10K"), Some(10_240));
+    assert_eq!(number_value("10M"), Some(10_485_760));
+    assert_eq!(number_value("2g"), Some(2_147_483_648));
+    assert_eq!(number_value("10MB"), None);
+    assert_eq!(number_value("99999999999999999999"), None);
+    assert_eq!(number_value("18014398509481984G"), None);
+}
+
+#[tokio::test]
+async fn test_valid_sizes_pass() {
+    let text = "if size :over 10M { discard; }\nif size :under 500 { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-number").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "invalid-size").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_malformed_number_literals() {
+    let text = "if size :over 10MB { discard; }\nif size :over 1.5M { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-number");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(
+        found[0].range,
+        Range::new(Position::new(0, 14), Position::new(0, 18))
+    );
+    assert!(found[1].message.contains("'1.5M'"));
+}
+
+#[tokio::test]
+async fn test_size_test_shape() {
+    let text = "if size :over \"10M\" { discard; }\nif size 10M { discard; }\nif size :over :under 1 { discard; }\nif size :over { discard; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found: Vec<u32> = with_code(&diagnostics, "invalid-size")
+        .iter()
+        .map(|d| d.range.start.line)
+        .collect();
+    assert_eq!(found, [0, 1, 2, 3], "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_hover_shows_byte_count() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server.document_map.insert(
+        uri.clone(),
+        SieveDocument::new(uri.clone(), "if size :over 10M { discard; }\n".to_string(), 1),
+    );
+
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(0, 15),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        hover.contents,
+        HoverContents::Scalar(MarkedString::String("10M = 10,485,760 bytes".to_string()))
+    );
+    assert_eq!(
+        hover.range,
+        Some(Range::new(Position::new(0, 14), Position::new(0, 17)))
+    );
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+const NUMERIC: &str = "require [\"spamtest\", \"virustest\", \"relational\", \
+                       \"comparator-i;ascii-numeric\"];\n";
+
+#[tokio::test]
+async fn test_numeric_comparisons_pass() {
+    let text = format!(
+        "{}if spamtest :value \"ge\" :comparator \"i;ascii-numeric\" \"6\" {{ discard; }}\n\
+         if virustest :value \"eq\" :comparator \"i;ascii-numeric\" [\"4\", \"5\"] {{ discard; }}\n",
+        NUMERIC
+    );
+    let diagnostics = diagnostics_for(&text).await;
+    assert!(with_code(&diagnostics, "spamtest-comparison").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "invalid-spamtest").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_text_comparisons_are_flagged() {
+    let text = format!(
+        "{}if spamtest \"10\" {{ discard; }}\n\
+         if spamtest :value \"ge\" \"6\" {{ discard; }}\n\
+         if virustest :count \"gt\" :comparator \"i;octet\" \"0\" {{ discard; }}\n",
+        NUMERIC
+    );
+    let diagnostics = diagnostics_for(&text).await;
+    let found = with_code(&diagnostics, "spamtest-comparison");
+    assert_eq!(found.len(), 3, "{:?}", diagnostics);
+
+    assert_eq!(found[0].range.start, Position::new(1, 3));
+    let fix = found[0].data.as_ref().unwrap();
+    assert_eq!(fix["replacement"], "spamtest :value \"eq\" :comparator \"i;ascii-numeric\"");
+
+    // The comparator is inserted after the relation
+    let fix = found[1].data.as_ref().unwrap();
+    assert_eq!(fix["replacement"], " :comparator \"i;ascii-numeric\"");
+    assert_eq!(fix["range"]["start"], serde_json::json!({ "line": 2, "character": 23 }));
+
+    assert!(found[2].message.contains("'i;octet'"), "{}", found[2].message);
+    assert_eq!(found[2].data.as_ref().unwrap()["replacement"], "\"i;ascii-numeric\"");
+}
+
+#[tokio::test]
+async fn test_values_out_of_range() {
+    let text = "require [\"spamtestplus\", \"virustest\", \"relational\", \
+                \"comparator-i;ascii-numeric\"];\n\
+                if spamtest :value \"ge\" :comparator \"i;ascii-numeric\" \"11\" { discard; }\n\
+                if spamtest :percent :value \"ge\" :comparator \"i;ascii-numeric\" \"80\" { stop; }\n\
+                if virustest :value \"ge\" :comparator \"i;ascii-numeric\" \"6\" { discard; }\n\
+                if spamtest :value \"ge\" :comparator \"i;ascii-numeric\" \"high\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let lines: Vec<u32> = with_code(&diagnostics, "invalid-spamtest")
+        .iter()
+        .map(|d| d.range.start.line)
+        .collect();
+    assert_eq!(lines, [1, 3, 4], "{:?}", diagnostics);
+    // spamtestplus provides spamtest as well
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unused-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_percent_requires_spamtestplus() {
+    let text = format!(
+        "{}if spamtest :percent :value \"ge\" :comparator \"i;ascii-numeric\" \"80\" {{ stop; }}\n\
+         if virustest :percent :value \"ge\" :comparator \"i;ascii-numeric\" \"4\" {{ stop; }}\n",
+        NUMERIC
+    );
+    let diagnostics = diagnostics_for(&text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 1, "{:?}", diagnostics);
+    assert!(missing[0].message.contains("spamtestplus"), "{}", missing[0].message);
+    let invalid = with_code(&diagnostics, "invalid-spamtest");
+    assert_eq!(invalid.len(), 1, "{:?}", diagnostics);
+    assert_eq!(invalid[0].message, "':percent' only applies to 'spamtest'");
+}
+
+async fn completions(text: &str) -> Vec<CompletionItem> {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///spam.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let line = text.lines().count() as u32 - 1;
+    let column = text.lines().last().unwrap().chars().count() as u32;
+    server.get_completions(&uri, Position::new(line, column)).await
+}
+
+#[tokio::test]
+async fn test_argument_pattern_completions() {
+    let items = completions("if spamtest ").await;
+    let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(
+        labels,
+        [
+            ":value \"eq\" :comparator \"i;ascii-numeric\" \"0\"",
+            ":value \"ge\" :comparator \"i;ascii-numeric\" \"5\"",
+            ":percent :value \"ge\" :comparator \"i;ascii-numeric\" \"50\"",
+        ]
+    );
+
+    let items = completions("if virustest ").await;
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|i| !i.label.contains(":percent")));
+}
+
+#[tokio::test]
+async fn test_score_completions() {
+    let items = completions("if spamtest :value \"ge\" :comparator \"i;ascii-numeric\" \"").await;
+    let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+    assert_eq!(labels, ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10"]);
+    assert_eq!(items[10].detail.as_deref(), Some("Spam score: Tested and definitely spam"));
+
+    let items = completions("if spamtest :percent :value \"ge\" :comparator \"i;ascii-numeric\" \"")
+        .await;
+    assert_eq!(items.len(), 11);
+    assert_eq!(items[10].label, "100");
+
+    let items = completions("if virustest :value \"eq\" :comparator \"i;ascii-numeric\" \"").await;
+    assert_eq!(items.len(), 6);
+}
@@ -0,0 +1,88 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::roles::{StringRole, role_markdown, string_at};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require [\"fileinto\", \"imap4flags\"];\n\
+                      if header :comparator \"i;octet\" :matches \"Subject\" \"*sale*\" {\n\
+                      \x20   fileinto \"Promotions\";\n\
+                      \x20   addflag \"\\\\Seen $label\";\n\
+                      }\n\
+                      redirect \"boss@example.com\";\n";
+
+fn role_at(line: u32, character: u32) -> Option<(String, StringRole)> {
+    let script = parse(SCRIPT);
+    string_at(&script, Position::new(line, character))
+        .map(|(string, role)| (string.value.clone(), role))
+}
+
+#[test]
+fn test_roles_follow_argument_positions() {
+    assert_eq!(role_at(0, 12), Some(("fileinto".to_string(), StringRole::Extension)));
+    assert_eq!(role_at(1, 23), Some(("i;octet".to_string(), StringRole::Comparator)));
+    assert_eq!(role_at(1, 45), Some(("Subject".to_string(), StringRole::HeaderName)));
+    assert_eq!(
+        role_at(1, 56),
+        Some((
+            "*sale*".to_string(),
+            StringRole::MatchKey {
+                match_type: ":matches".to_string(),
+                comparator: "i;octet".to_string(),
+            }
+        ))
+    );
+    assert_eq!(role_at(2, 16), Some(("Promotions".to_string(), StringRole::Mailbox)));
+    assert_eq!(role_at(3, 14), Some(("\\Seen $label".to_string(), StringRole::Flag)));
+    assert_eq!(role_at(5, 12), Some(("boss@example.com".to_string(), StringRole::Address)));
+    // Keywords are not strings
+    assert_eq!(role_at(1, 4), None);
+}
+
+#[test]
+fn test_role_markdown() {
+    let key = StringRole::MatchKey {
+        match_type: ":is".to_string(),
+        comparator: "i;ascii-casemap".to_string(),
+    };
+    assert_eq!(
+        role_markdown("yes", &key),
+        "**\"yes\"** - match key\n\nThe tested value must equal this key\n\n\
+         Match type `:is`, comparator `i;ascii-casemap`\n"
+    );
+    assert!(
+        role_markdown("a@b.org", &StringRole::Address).contains("Local part `a`, domain `b.org`")
+    );
+    let flags = role_markdown("\\Seen $label", &StringRole::Flag);
+    assert!(flags.contains("- `\\Seen`: Message has been read\n- `$label`: IMAP keyword"));
+    assert!(role_markdown("weekday", &StringRole::DatePart).contains("0 (Sunday) to 6"));
+    assert!(role_markdown("name", &StringRole::VariableName).contains("`${name}`"));
+}
+
+#[tokio::test]
+async fn test_extension_string_hover() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///roles.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(0, 12),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let HoverContents::Markup(markup) = hover.contents else {
+        panic!("unexpected hover {:?}", hover.contents);
+    };
+    // The extension is documented rather than the action of the same name
+    assert!(markup.value.starts_with("**fileinto** - Sieve extension"), "{}", markup.value);
+    assert!(markup.value.contains("require \"fileinto\";"), "{}", markup.value);
+    assert_eq!(hover.range, Some(Range::new(Position::new(0, 9), Position::new(0, 19))));
+}
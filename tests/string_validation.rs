@@ -0,0 +1,66 @@
+mod common;
+
+use common::*;
+use sieve_language_server::parser::parse;
+use tower_lsp::lsp_types::*;
+
+fn error_codes(text: &str) -> Vec<&'static str> {
+    parse(text).errors.iter().map(|e| e.code).collect()
+}
+
+#[test]
+fn test_unterminated_quoted_string() {
+    let script = parse("fileinto \"Junk;\nkeep;\n");
+    assert_eq!(script.errors.len(), 1);
+    assert_eq!(script.errors[0].code, "unterminated-string");
+    assert_eq!(script.errors[0].range.start, Position::new(0, 9));
+}
+
+#[test]
+fn test_escape_sequences() {
+    let script = parse("fileinto \"a\\\"b\\\\c\\d\";\n");
+    assert_eq!(script.errors.len(), 1);
+    assert_eq!(script.errors[0].code, "invalid-escape");
+    assert_eq!(script.errors[0].severity, DiagnosticSeverity::WARNING);
+    assert_eq!(script.errors[0].range.start, Position::new(0, 17));
+}
+
+#[test]
+fn test_malformed_string_lists() {
+    assert!(error_codes("require [\"a\", \"b\"];").is_empty());
+    assert_eq!(
+        error_codes("require [\"a\" \"b\"];"),
+        vec!["malformed-string-list"]
+    );
+    assert_eq!(
+        error_codes("require [\"a\", ];"),
+        vec!["malformed-string-list"]
+    );
+    assert_eq!(error_codes("require [];"), vec!["malformed-string-list"]);
+    assert_eq!(
+        error_codes("require [\"a\";"),
+        vec!["malformed-string-list"]
+    );
+}
+
+#[test]
+fn test_multiline_strings() {
+    let ok = "vacation text:\nHello\n..dot stuffed\n.\n;\n";
+    let script = parse(ok);
+    assert!(script.errors.is_empty());
+    assert_eq!(
+        script.commands[0].arguments[0].strings()[0].value,
+        "Hello\n.dot stuffed\n"
+    );
+
+    assert_eq!(
+        error_codes("vacation text:\nHello\n"),
+        vec!["unterminated-multiline"]
+    );
+}
+
+#[tokio::test]
+async fn test_string_errors_are_reported_as_diagnostics() {
+    let diagnostics = diagnostics_for("require [\"fileinto\",];\n").await;
+    assert_eq!(with_code(&diagnostics, "malformed-string-list").len(), 1);
+}
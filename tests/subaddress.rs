@@ -0,0 +1,62 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+#[tokio::test]
+async fn test_subaddress_parts_on_address_tests() {
+    let text = "require [\"subaddress\", \"envelope\", \"fileinto\"];\n\
+                if envelope :user \"to\" \"ken\" { fileinto \"Personal\"; }\n\
+                if address :detail \"to\" \"lists\" { fileinto \"Lists\"; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-address-part").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "missing-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unused-require").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unknown-tag").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_subaddress_needs_require() {
+    let text = "if address :detail \"to\" \"lists\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let missing = with_code(&diagnostics, "missing-require");
+    assert_eq!(missing.len(), 1, "{:?}", diagnostics);
+    assert_eq!(missing[0].message, "':detail' requires the \"subaddress\" extension");
+    assert_eq!(missing[0].range.start, Position::new(0, 11));
+}
+
+#[tokio::test]
+async fn test_subaddress_parts_elsewhere() {
+    let text = "require \"subaddress\";\n\
+                if header :user \"to\" \"ken\" { keep; }\n\
+                if address :user \"to\" \"ken\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-address-part");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(found[0].range.start, Position::new(1, 10));
+    assert_eq!(found[0].message, "':user' only applies to the 'address' and 'envelope' tests");
+}
+
+#[tokio::test]
+async fn test_subaddress_completion_and_docs() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///subaddress.sieve").unwrap();
+    let text = "if address :";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let items = server.get_completions(&uri, Position::new(0, 12)).await;
+    for tag in [":user", ":detail"] {
+        let item = items.iter().find(|i| i.label == tag).expect(tag).clone();
+        let resolved = server.resolve_completion(item).await;
+        let Some(Documentation::MarkupContent(doc)) = resolved.documentation else {
+            panic!("expected documentation for {}", tag);
+        };
+        assert!(doc.value.contains("'subaddress'"), "{}", doc.value);
+        assert!(doc.value.contains("RFC 5233"), "{}", doc.value);
+    }
+}
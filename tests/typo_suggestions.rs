@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+#[tokio::test]
+async fn test_misspelled_command_and_tag() {
+    let text =
+        "require \"fileinto\";\nif header :contians \"subject\" \"x\" {\n  fileinfo \"A\";\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+
+    let commands = with_code(&diagnostics, "unknown-command");
+    assert_eq!(commands.len(), 1, "{:?}", diagnostics);
+    assert_eq!(commands[0].message, "Unknown command 'fileinfo', did you mean 'fileinto'?");
+    assert_eq!(commands[0].range, Range::new(Position::new(2, 2), Position::new(2, 10)));
+
+    let tags = with_code(&diagnostics, "unknown-tag");
+    assert_eq!(tags.len(), 1, "{:?}", diagnostics);
+    assert_eq!(tags[0].message, "Unknown tag ':contians', did you mean ':contains'?");
+    assert_eq!(tags[0].range, Range::new(Position::new(1, 10), Position::new(1, 19)));
+}
+
+#[tokio::test]
+async fn test_known_and_unrelated_names_are_accepted() {
+    // Identifiers are case-insensitive, and extensions without completions are no typos
+    let text = "require [\"ereject\", \"variables\", \"subaddress\"];\n\
+                if address :user \"to\" \"me\" { Keep; }\n\
+                set :upperfirst \"a\" \"b\";\n\
+                ereject \"no\";\n\
+                frobnicate :wibble;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unknown-command").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "unknown-tag").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_code_action_applies_the_correction() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///typo.sieve").unwrap();
+    let text = "if size :ovr 100K { discard; }\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let typos = with_code(&diagnostics, "unknown-tag");
+    assert_eq!(typos.len(), 1, "{:?}", diagnostics);
+
+    let response = server
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: typos[0].range,
+            context: CodeActionContext {
+                diagnostics: typos.into_iter().cloned().collect(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let CodeActionOrCommand::CodeAction(action) = &response[0] else {
+        panic!("expected a code action");
+    };
+    assert_eq!(action.title, "Change to ':over'");
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+    assert_eq!(
+        edits,
+        &vec![TextEdit {
+            range: Range::new(Position::new(0, 8), Position::new(0, 12)),
+            new_text: ":over".to_string(),
+        }]
+    );
+}
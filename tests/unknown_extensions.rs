@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use sieve_language_server::sieve::{closest_match, edit_distance};
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("fileinto", "fileinto"), 0);
+    assert_eq!(edit_distance("fileinot", "fileinto"), 1);
+    assert_eq!(edit_distance("vacaton", "vacation"), 1);
+    assert_eq!(edit_distance("body", "copy"), 2);
+    assert_eq!(closest_match("FileInto", ["fileinto", "envelope"]), Some("fileinto"));
+    assert_eq!(closest_match("archive", ["fileinto", "envelope"]), None);
+}
+
+#[tokio::test]
+async fn test_misspelled_extension_gets_a_suggestion() {
+    let text = "require [\"fileinot\", \"no-such-thing\", \"vnd.example.custom\"];\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "unknown-extension");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "Unknown extension 'fileinot', did you mean 'fileinto'?");
+    assert_eq!(found[0].range, Range::new(Position::new(0, 9), Position::new(0, 19)));
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(
+        found[0].data,
+        Some(json!({ "title": "Replace with \"fileinto\"", "replacement": "\"fileinto\"" }))
+    );
+    assert_eq!(found[1].message, "Unknown extension 'no-such-thing'");
+    assert_eq!(found[1].data, None);
+}
+
+#[tokio::test]
+async fn test_suggestions_follow_the_server_dialect() {
+    let text = "require [\"imapflags\", \"filenito\"];\n";
+    let diagnostics = diagnostics_with_settings(text, json!({ "server_dialect": "proton" })).await;
+    let unsupported = with_code(&diagnostics, "unsupported-feature");
+    assert_eq!(unsupported.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        unsupported[0].message,
+        "Proton Mail does not support the \"imapflags\" extension, did you mean \"imap4flags\"?"
+    );
+    assert!(unsupported[0].data.is_some());
+    // A capability that is no extension at all is only reported once
+    let unknown = with_code(&diagnostics, "unknown-extension");
+    assert_eq!(unknown.len(), 1, "{:?}", diagnostics);
+    assert_eq!(unknown[0].message, "Unknown extension 'filenito', did you mean 'fileinto'?");
+}
+
+#[tokio::test]
+async fn test_advertised_capabilities_are_known() {
+    let settings = json!({ "capabilities": ["fileinto", "x-custom"] });
+    let text = "require [\"x-custom\", \"fileint\"];\n";
+    let diagnostics = diagnostics_with_settings(text, settings).await;
+    let unknown = with_code(&diagnostics, "unknown-extension");
+    assert_eq!(unknown.len(), 1, "{:?}", diagnostics);
+    assert_eq!(unknown[0].message, "Unknown extension 'fileint', did you mean 'fileinto'?");
+    assert!(with_code(&diagnostics, "unsupported-feature").is_empty(), "{:?}", diagnostics);
+}
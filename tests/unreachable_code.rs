@@ -0,0 +1,41 @@
+mod common;
+
+use common::*;
+use tower_lsp::lsp_types::*;
+
+fn unreachable_lines(diagnostics: &[Diagnostic]) -> Vec<u32> {
+    with_code(diagnostics, "unreachable-code")
+        .into_iter()
+        .map(|d| {
+            assert_eq!(d.tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+            d.range.start.line
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_statements_after_stop() {
+    let diagnostics = diagnostics_for("keep;\nstop;\nkeep;\n").await;
+    assert_eq!(unreachable_lines(&diagnostics), vec![2]);
+}
+
+#[tokio::test]
+async fn test_actions_after_discard_in_block() {
+    let text = "if header :contains \"subject\" \"spam\" {\n  discard;\n  keep;\n}\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(unreachable_lines(&diagnostics), vec![2]);
+}
+
+#[tokio::test]
+async fn test_branches_after_if_true() {
+    let text = "if true {\n  keep;\n} elsif false {\n  discard;\n} else {\n  discard;\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert_eq!(unreachable_lines(&diagnostics), vec![2, 4]);
+}
+
+#[tokio::test]
+async fn test_reachable_code_is_not_flagged() {
+    let text = "if header :is \"from\" \"a@b.c\" {\n  stop;\n}\nkeep;\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(unreachable_lines(&diagnostics).is_empty());
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::{offset_at, parse};
+use sieve_language_server::requires::*;
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// The script after removing its first unused capability
+fn remove_first_unused(text: &str) -> String {
+    let script = parse(text);
+    let unused = unused_requires(&script, &BTreeMap::new());
+    let (command, capability) = unused[0];
+    let edit = remove_requirement(text, command, capability);
+    let mut result = text.to_string();
+    let start = offset_at(text, edit.range.start);
+    let end = offset_at(text, edit.range.end);
+    result.replace_range(start..end, &edit.new_text);
+    result
+}
+
+#[tokio::test]
+async fn test_unused_requires_are_flagged() {
+    let text = "require [\"fileinto\", \"body\", \"vnd.acme.custom\"];\nfileinto \"Archive\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "unused-require");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range, Range::new(Position::new(0, 21), Position::new(0, 27)));
+    assert_eq!(found[0].message, "Extension 'body' is required but never used");
+    assert_eq!(found[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+    assert_eq!(found[0].severity, Some(DiagnosticSeverity::HINT));
+
+    // Usage cannot be trusted in a script with syntax errors
+    let text = "require \"body\";\nif header :contains \"subject\" {\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unused-require").is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn test_removal_reflows_the_require() {
+    assert_eq!(
+        remove_first_unused("require [\"fileinto\", \"body\"];\nfileinto \"A\";\n"),
+        "require \"fileinto\";\nfileinto \"A\";\n"
+    );
+    assert_eq!(
+        remove_first_unused("require [\"body\", \"fileinto\", \"copy\"];\nfileinto :copy \"A\";\n"),
+        "require [\"fileinto\", \"copy\"];\nfileinto :copy \"A\";\n"
+    );
+    assert_eq!(
+        remove_first_unused("require \"fileinto\";\nrequire \"body\";\nfileinto \"A\";\n"),
+        "require \"fileinto\";\nfileinto \"A\";\n"
+    );
+}
+
+#[tokio::test]
+async fn test_quick_fix_removes_the_entry() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///unused.sieve").unwrap();
+    let text = "require [\"fileinto\", \"body\"];\nfileinto \"Archive\";\n";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let unused = with_code(&diagnostics, "unused-require");
+    assert_eq!(unused.len(), 1, "{:?}", diagnostics);
+
+    let response = server
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: unused[0].range,
+            context: CodeActionContext {
+                diagnostics: unused.into_iter().cloned().collect(),
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let CodeActionOrCommand::CodeAction(action) = &response[0] else {
+        panic!("expected a code action");
+    };
+    assert_eq!(action.title, "Remove unused require of \"body\"");
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+    assert_eq!(
+        edits,
+        &vec![TextEdit {
+            range: Range::new(Position::new(0, 0), Position::new(0, 29)),
+            new_text: "require \"fileinto\";".to_string(),
+        }]
+    );
+}
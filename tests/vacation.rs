@@ -0,0 +1,54 @@
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn test_valid_vacation_passes() {
+    let text = "require \"vacation\";\n\
+                vacation :days 7 :subject \"Away\" :addresses [\"me@example.com\", \"${alias}\"] \"Back soon\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "invalid-vacation").is_empty(), "{:?}", diagnostics);
+    assert!(with_code(&diagnostics, "vacation-loop").is_empty(), "{:?}", diagnostics);
+}
+
+#[tokio::test]
+async fn test_vacation_parameters() {
+    let text = "require \"vacation\";\n\
+                vacation :days 0 :addresses [\"me@example.com\", \"not an address\"] \"Away\";\n\
+                vacation :mime :subject \"Away\" text:\nContent-Type: text/plain\n\nAway\n.\n;\n\
+                vacation :days 3 [\"Away\", \"Back\"];\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<(u32, &str)> = with_code(&diagnostics, "invalid-vacation")
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (1, "':days' must be at least 1"),
+            (2, "Do not combine ':subject' with ':mime'; a MIME reason carries its own headers"),
+            (8, "The reason of 'vacation' must be a single string"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_missing_reason() {
+    let text = "require \"vacation\";\nvacation :subject \"Away\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "invalid-vacation");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].message, "'vacation' requires a reason string");
+}
+
+#[tokio::test]
+async fn test_vacation_next_to_redirect() {
+    let text = "require \"vacation\";\n\
+                if header :contains \"to\" \"team\" {\n  redirect \"team@example.com\";\n  vacation \"Away\";\n}\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "vacation-loop");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start.line, 3);
+    let related = found[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start.line, 2);
+}
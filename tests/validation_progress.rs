@@ -0,0 +1,156 @@
+mod common;
+
+use common::{ClientMessages, initialized_server, sent};
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use sieve_language_server::datastructures::*;
+use std::time::Duration;
+use tower::{Service, ServiceExt};
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+/// A script long enough to show progress, missing the semicolon on its last line
+fn huge_script() -> String {
+    let mut text = "require \"fileinto\";\n".to_string();
+    for n in 0..VALIDATION_PROGRESS_THRESHOLD {
+        text.push_str(&format!("if header :is \"x-id\" \"{}\" {{ fileinto \"Id\"; }}\n", n));
+    }
+    text.push_str("keep\n");
+    text
+}
+
+/// Validate a script in an initialized server and return the `$/progress` values sent
+async fn progress_for(params: Value, text: String) -> Vec<Value> {
+    let (service, messages) = initialized_server(params).await;
+    let server = service.inner();
+    let uri = Url::parse("file:///generated.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text, 1));
+    server.validate_document(&uri).await.unwrap();
+    // The client records messages in its own task
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    sent(&messages, "$/progress").into_iter().map(|p| p["value"].clone()).collect()
+}
+
+fn with_progress() -> Value {
+    json!({ "capabilities": { "window": { "workDoneProgress": true } } })
+}
+
+#[tokio::test]
+async fn test_huge_documents_validate_with_progress_enabled() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    *server.work_done_progress.write().await = true;
+
+    let uri = Url::parse("file:///generated.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), huge_script(), 1));
+
+    // Without an initialized client no progress is shown, but validation completes
+    let diagnostics = server.validate_document(&uri).await.unwrap();
+    let last = VALIDATION_PROGRESS_THRESHOLD as u32 + 1;
+    assert!(
+        diagnostics.iter().any(|d| d.range.start.line == last
+            && d.code == Some(NumberOrString::String("missing-semicolon".to_string()))),
+        "{:?}",
+        diagnostics.iter().take(5).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_huge_documents_report_progress() {
+    let progress = progress_for(with_progress(), huge_script()).await;
+    let kinds: Vec<&str> = progress.iter().map(|p| p["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds.first(), Some(&"begin"), "{:?}", progress);
+    assert_eq!(kinds.last(), Some(&"end"), "{:?}", progress);
+    assert!(kinds[1..kinds.len() - 1].iter().all(|kind| *kind == "report"), "{:?}", kinds);
+    assert!(kinds.len() > 2, "{:?}", kinds);
+    assert_eq!(progress[0]["title"], "Validating filters");
+    assert_eq!(progress[kinds.len() - 2]["message"], "Running lint rules");
+    let lines = VALIDATION_PROGRESS_THRESHOLD + 2;
+    let suffix = format!("/{} lines", lines);
+    assert!(
+        progress.iter().any(|p| p["message"].as_str().is_some_and(|m| m.ends_with(&suffix))),
+        "{:?}",
+        progress
+    );
+}
+
+#[tokio::test]
+async fn test_progress_is_translated() {
+    let mut params = with_progress();
+    params["locale"] = json!("de");
+    let progress = progress_for(params, huge_script()).await;
+    assert_eq!(progress[0]["title"], "Filter werden geprüft");
+    assert!(progress.iter().any(|p| p["message"] == "Lint-Regeln werden ausgeführt"));
+}
+
+#[tokio::test]
+async fn test_progress_is_skipped_for_small_documents() {
+    let progress = progress_for(with_progress(), "keep;\n".to_string()).await;
+    assert_eq!(progress, Vec::<Value>::new());
+}
+
+#[tokio::test]
+async fn test_progress_is_skipped_without_client_support() {
+    let progress = progress_for(json!({ "capabilities": {} }), huge_script()).await;
+    assert_eq!(progress, Vec::<Value>::new());
+}
+
+/// An initialized server whose client edits `uri` before it answers the progress request,
+/// so every validation showing progress is overtaken by a newer version
+async fn server_edited_during_validation(
+    uri: &Url,
+) -> (LspService<SieveLanguageServer>, ClientMessages) {
+    let (mut service, socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner().clone();
+    let edited = uri.clone();
+    let messages = ClientMessages::default();
+    let received = messages.clone();
+    let (mut requests, mut responses) = socket.split();
+    tokio::spawn(async move {
+        while let Some(request) = requests.next().await {
+            let params = request.params().cloned().unwrap_or(Value::Null);
+            received.lock().unwrap().push((request.method().to_string(), params));
+            if request.method() == "window/workDoneProgress/create"
+                && let Some(mut document) = server.document_map.get_mut(&edited)
+            {
+                document.version += 1;
+            }
+            if let Some(id) = request.id() {
+                let _ = responses.send(Response::from_ok(id.clone(), Value::Null)).await;
+            }
+        }
+    });
+    let initialize = Request::build("initialize").id(0).params(with_progress()).finish();
+    service.ready().await.unwrap().call(initialize).await.unwrap();
+    (service, messages)
+}
+
+#[tokio::test]
+async fn test_abandoned_validations_report_nothing() {
+    let uri = Url::parse("file:///generated.sieve").unwrap();
+    let (service, messages) = server_edited_during_validation(&uri).await;
+    let server = service.inner();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), huge_script(), 1));
+
+    assert_eq!(server.validate_document(&uri).await, None);
+    assert_eq!(server.validate_guarded(&uri).await, None);
+
+    server
+        .did_save(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            text: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(server.history.read().await.files.is_empty(), "no snapshot of the saved version");
+    assert_eq!(sent(&messages, "textDocument/publishDiagnostics"), Vec::<Value>::new());
+    let progress: Vec<Value> = sent(&messages, "$/progress");
+    assert_eq!(progress.last().unwrap()["value"]["kind"], "end", "progress still ends");
+}
@@ -0,0 +1,68 @@
+use sieve_language_server::datastructures::*;
+use sieve_language_server::parser::parse;
+use sieve_language_server::variables::named_variable_hover;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+const SCRIPT: &str = "require [\"variables\", \"fileinto\"];\n\
+                      set \"folder\" \"Archive\";\n\
+                      if header :matches \"list-id\" \"*<*>\" {\n\
+                      \x20   set :length :lower \"folder\" \"${2}\";\n\
+                      }\n\
+                      fileinto \"${folder}\";\n\
+                      set \"folder\" \"INBOX\";\n";
+
+#[test]
+fn test_hover_shows_the_last_set_before_the_reference() {
+    let script = parse(SCRIPT);
+    let doc = named_variable_hover(SCRIPT, &script, Position::new(5, 12), "folder");
+    assert!(doc.starts_with("**${folder}** - variable (RFC 5229)"), "{}", doc);
+    assert!(
+        doc.contains("Set on line 4\n\n```sieve\nset :length :lower \"folder\" \"${2}\";\n```"),
+        "{}",
+        doc
+    );
+    // :lower has the higher precedence and runs first
+    assert!(doc.contains("Modifiers applied in order: `:lower`, `:length`"), "{}", doc);
+    assert!(doc.contains("Also set on line 2, 7"), "{}", doc);
+}
+
+#[test]
+fn test_hover_without_a_preceding_set() {
+    let text = "require \"variables\";\nfileinto \"${box}\";\nset \"box\" \"x\";\n";
+    let script = parse(text);
+    let doc = named_variable_hover(text, &script, Position::new(1, 12), "box");
+    assert!(doc.contains("Only set after this reference, on line 3"), "{}", doc);
+
+    let doc = named_variable_hover(text, &script, Position::new(1, 12), "other");
+    assert!(doc.contains("No `set` statement defines `${other}`"), "{}", doc);
+
+    let doc = named_variable_hover(text, &script, Position::new(1, 12), "env.user");
+    assert!(doc.contains("`env` namespace"), "{}", doc);
+}
+
+#[tokio::test]
+async fn test_server_hover_on_named_variable() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///vars.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), SCRIPT.to_string(), 1));
+    let hover = server
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(5, 14),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .expect("hover for ${folder}");
+    let HoverContents::Markup(content) = hover.contents else {
+        panic!("expected markdown hover");
+    };
+    assert!(content.value.contains("Set on line 4"), "{}", content.value);
+    assert_eq!(hover.range.unwrap().start, Position::new(5, 10));
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::*;
+use sieve_language_server::variables::{is_variable_name, references};
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_reference_extraction() {
+    let found: Vec<(String, usize, usize)> = references("Re: ${subject} ${1} ${env.user} ${hex:41} $x")
+        .into_iter()
+        .map(|r| (r.name, r.start, r.end))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("subject".to_string(), 4, 14),
+            ("1".to_string(), 15, 19),
+            ("env.user".to_string(), 20, 31)
+        ]
+    );
+    assert!(is_variable_name("_folder2"));
+    assert!(!is_variable_name("2folder"));
+    assert!(!is_variable_name("my-folder"));
+}
+
+#[tokio::test]
+async fn test_valid_variables_pass() {
+    let text = "require [\"variables\", \"fileinto\"];\n\
+                set :lower :upperfirst \"folder\" \"Lists\";\n\
+                if header :matches \"list-id\" \"*<*>\" {\n  fileinto \"${folder}/${2}\";\n}\n\
+                if string :is \"${folder}\" \"lists\" { stop; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    for code in ["invalid-variable", "unset-variable", "match-variable", "invalid-syntax"] {
+        assert!(with_code(&diagnostics, code).is_empty(), "{}: {:?}", code, diagnostics);
+    }
+}
+
+#[tokio::test]
+async fn test_set_validation() {
+    let text = "require \"variables\";\n\
+                set :lower :upper :trim \"a\" \"b\";\n\
+                set \"2nd\" \"x\";\n\
+                set \"only_name\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    let messages: Vec<&str> = with_code(&diagnostics, "invalid-variable")
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "':upper' cannot be combined with ':lower'",
+            "Unknown modifier ':trim' for 'set'",
+            "Invalid variable name '2nd': use letters, digits and '_', not starting with a digit",
+            "'set' expects a variable name and a value string",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_unset_and_match_variables() {
+    let text = "require [\"variables\", \"fileinto\"];\n\
+                fileinto \"${1}\";\n\
+                fileinto \"Lists/${nmae}\";\n";
+    let diagnostics = diagnostics_for(text).await;
+
+    let matches = with_code(&diagnostics, "match-variable");
+    assert_eq!(matches.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        matches[0].range,
+        Range::new(Position::new(1, 10), Position::new(1, 14))
+    );
+
+    let unset = with_code(&diagnostics, "unset-variable");
+    assert_eq!(unset.len(), 1, "{:?}", diagnostics);
+    assert_eq!(unset[0].message, "Variable 'nmae' is never set");
+    assert_eq!(
+        unset[0].range,
+        Range::new(Position::new(2, 16), Position::new(2, 23))
+    );
+}
+
+#[tokio::test]
+async fn test_references_are_literal_without_require() {
+    let text = "require \"fileinto\";\nfileinto \"${folder}\";\n";
+    let diagnostics = diagnostics_for(text).await;
+    assert!(with_code(&diagnostics, "unset-variable").is_empty(), "{:?}", diagnostics);
+}
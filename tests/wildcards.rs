@@ -0,0 +1,45 @@
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn test_wildcards_with_matching_match_types_pass() {
+    let text = "if header :matches \"subject\" [\"*urgent*\", \"re: ?\"] { keep; }\n\
+                if header :contains \"subject\" \"***SPAM***\" { discard; }\n\
+                if header :matches \"subject\" \"\\\\*\" { keep; }\n\
+                if header :is \"subject\" \"hello\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "match-type");
+    // The escaped star in line 3 is no wildcard
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start.line, 2);
+}
+
+#[tokio::test]
+async fn test_matches_without_wildcards() {
+    let text = "if header :matches \"subject\" [\"invoice\", \"receipt\"] { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "match-type");
+    assert_eq!(found.len(), 1, "{:?}", diagnostics);
+    assert_eq!(found[0].range.start.character, 10);
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], ":is");
+}
+
+#[tokio::test]
+async fn test_star_under_is_and_contains() {
+    let text = "if header :contains \"subject\" \"order*\" { keep; }\n\
+                if address \"from\" \"*@example.com\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let found = with_code(&diagnostics, "match-type");
+    assert_eq!(found.len(), 2, "{:?}", diagnostics);
+
+    // An explicit match type gets a quick fix on the tag
+    assert_eq!(found[0].range.start.character, 10);
+    assert_eq!(found[0].data.as_ref().unwrap()["replacement"], ":matches");
+
+    // The implicit :is has no tag to replace, so the key is flagged
+    assert_eq!(found[1].range.start.line, 1);
+    assert_eq!(found[1].range.start.character, 18);
+    assert!(found[1].data.is_none());
+    assert!(found[1].message.contains("':is'"));
+}
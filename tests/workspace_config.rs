@@ -0,0 +1,140 @@
+use serde_json::json;
+use sieve_language_server::config;
+use sieve_language_server::datastructures::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+fn temp_workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sieve-config-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn current_settings(server: &SieveLanguageServer) -> serde_json::Value {
+    serde_json::to_value(&*server.settings.read().await).unwrap()
+}
+
+#[test]
+fn test_config_files_are_parsed() {
+    let toml = "strict_mode = true\ncapabilities = [\"fileinto\"]\n\n[format]\ntabSize = 2\n";
+    assert_eq!(
+        config::parse(Path::new("sieve-lsp.toml"), toml).unwrap(),
+        json!({ "strict_mode": true, "capabilities": ["fileinto"], "format": { "tabSize": 2 } })
+    );
+    assert_eq!(
+        config::parse(Path::new(".sieverc"), "{ \"server_dialect\": \"dovecot\" }").unwrap(),
+        json!({ "server_dialect": "dovecot" })
+    );
+    assert!(config::parse(Path::new(".sieverc"), "[1, 2]").is_err());
+    assert!(config::parse(Path::new("sieve-lsp.toml"), "strict_mode = ").is_err());
+
+    let mut settings = json!({ "strict_mode": false, "requires": { "autoManage": true } });
+    config::overlay(&mut settings, &json!({ "strict_mode": true, "requires": {} }));
+    assert_eq!(settings, json!({ "strict_mode": true, "requires": { "autoManage": true } }));
+}
+
+#[tokio::test]
+async fn test_workspace_config_overrides_the_editor() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let root = temp_workspace("override");
+    std::fs::write(
+        root.join("sieve-lsp.toml"),
+        "server_dialect = \"dovecot\"\n[format]\ninsertSpaces = false\n",
+    )
+    .unwrap();
+    *server.workspace_root.write().await = Some(root.clone());
+    assert!(server.reload_workspace_config().await);
+
+    server
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: json!({ "server_dialect": "proton", "strict_mode": true }),
+        })
+        .await;
+    let settings = current_settings(server).await;
+    assert_eq!(settings["server_dialect"], "dovecot");
+    assert_eq!(settings["strict_mode"], true, "keys the file does not set come from the editor");
+    assert_eq!(settings["format"]["insertSpaces"], false);
+
+    // Editing the file applies it without restarting
+    std::fs::write(root.join("sieve-lsp.toml"), "server_dialect = \"cyrus\"\n").unwrap();
+    server
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: Url::from_file_path(root.join("sieve-lsp.toml")).unwrap(),
+                typ: FileChangeType::CHANGED,
+            }],
+        })
+        .await;
+    let settings = current_settings(server).await;
+    assert_eq!(settings["server_dialect"], "cyrus");
+    assert_eq!(settings["strict_mode"], true);
+    assert_eq!(settings["format"]["insertSpaces"], serde_json::Value::Null);
+
+    // A broken file keeps the settings in effect
+    std::fs::write(root.join("sieve-lsp.toml"), "server_dialect = [").unwrap();
+    assert!(!server.reload_workspace_config().await);
+    assert_eq!(current_settings(server).await["server_dialect"], "cyrus");
+    let error = server.errors.read().await.last().cloned().unwrap();
+    assert!(error.message.starts_with("Invalid configuration in "), "{}", error.message);
+
+    std::fs::remove_file(root.join("sieve-lsp.toml")).unwrap();
+    assert!(server.reload_workspace_config().await);
+    assert_eq!(current_settings(server).await["server_dialect"], "proton");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_formatting_follows_the_workspace_config() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let root = temp_workspace("format");
+    std::fs::write(root.join(".sieverc"), "{ \"format\": { \"tabSize\": 2 } }").unwrap();
+    *server.workspace_root.write().await = Some(root.clone());
+    assert!(server.reload_workspace_config().await);
+
+    let uri = Url::parse("file:///test.sieve").unwrap();
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), "if true {\nkeep;\n}\n".into(), 1));
+    let edits = server
+        .formatting(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options: FormattingOptions {
+                tab_size: 8,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].new_text, "  keep;");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_cli_uses_the_config_of_the_current_directory() {
+    let root = temp_workspace("cli");
+    std::fs::write(root.join("sieve-lsp.toml"), "[format]\ntabSize = 2\n").unwrap();
+    std::fs::write(root.join("filter.sieve"), "if true {\n  keep;\n}\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_sieve-lsp"))
+            .args(args)
+            .current_dir(&root)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+    assert_eq!(run(&["fmt", "--check", "filter.sieve"]), Some(0));
+
+    std::fs::write(root.join("sieve-lsp.toml"), "[format]\ntabSize = \"two\"\n").unwrap();
+    assert_eq!(run(&["fmt", "--check", "filter.sieve"]), Some(2));
+    assert_eq!(run(&["check", "filter.sieve"]), Some(2));
+    std::fs::remove_dir_all(&root).unwrap();
+}
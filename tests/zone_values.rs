@@ -0,0 +1,69 @@
+mod common;
+
+use common::*;
+use sieve_language_server::datastructures::*;
+use sieve_language_server::sieve::{TIME_ZONES, is_zone_offset, named_zone_offset};
+use tower_lsp::LspService;
+use tower_lsp::lsp_types::*;
+
+#[test]
+fn test_zone_offset_syntax() {
+    assert!(is_zone_offset("+0000"));
+    assert!(is_zone_offset("-0500"));
+    assert!(is_zone_offset("+0545"));
+    assert!(is_zone_offset("+1400"));
+    assert!(!is_zone_offset("0500"));
+    assert!(!is_zone_offset("+05:00"));
+    assert!(!is_zone_offset("+0575"));
+    assert!(!is_zone_offset("-1900"));
+    assert!(!is_zone_offset("EST"));
+    assert!(TIME_ZONES.keys().all(|offset| is_zone_offset(offset)));
+
+    assert_eq!(named_zone_offset("est"), Some("-0500"));
+    assert_eq!(named_zone_offset("CEST"), Some("+0200"));
+    assert_eq!(named_zone_offset("Europe/Berlin"), None);
+}
+
+#[tokio::test]
+async fn test_zone_completions() {
+    let (service, _socket) = LspService::new(SieveLanguageServer::new);
+    let server = service.inner();
+    let uri = Url::parse("file:///zone.sieve").unwrap();
+    let text = "require \"date\";\nif date :zone \"";
+    server
+        .document_map
+        .insert(uri.clone(), SieveDocument::new(uri.clone(), text.to_string(), 1));
+    let items = server.get_completions(&uri, Position::new(1, 15)).await;
+
+    // Offsets come first, then names that insert their offset
+    assert_eq!(items[0].label, "+0000");
+    assert_eq!(items.len(), TIME_ZONES.len() + 20);
+    let est = items.iter().find(|item| item.label == "EST").unwrap();
+    assert_eq!(est.insert_text.as_deref(), Some("-0500"));
+    assert_eq!(est.detail.as_deref(), Some("Time zone: -0500"));
+
+    let resolved = server.resolve_completion(est.clone()).await;
+    let Some(Documentation::MarkupContent(content)) = resolved.documentation else {
+        panic!("expected documentation");
+    };
+    assert!(content.value.contains("Eastern Standard Time"), "{}", content.value);
+}
+
+#[tokio::test]
+async fn test_zone_diagnostics() {
+    let text = "require [\"date\", \"variables\"];\n\
+                if date :zone \"+0100\" \"date\" \"2024-01-01\" { keep; }\n\
+                if currentdate :zone \"${tz}\" \"hour\" \"12\" { keep; }\n\
+                if date :zone \"EST\" \"date\" \"2024-01-01\" { keep; }\n\
+                if currentdate :zone \"+25:00\" \"hour\" \"12\" { keep; }\n";
+    let diagnostics = diagnostics_for(text).await;
+    let invalid = with_code(&diagnostics, "invalid-zone");
+    assert_eq!(invalid.len(), 2, "{:?}", diagnostics);
+
+    // Named zones come with a quick fix to the offset
+    assert_eq!(invalid[0].range.start, Position::new(3, 14));
+    let data = invalid[0].data.as_ref().unwrap();
+    assert_eq!(data["replacement"], "\"-0500\"");
+    assert_eq!(invalid[1].range.start.line, 4);
+    assert!(invalid[1].data.is_none());
+}